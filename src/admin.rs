@@ -1,32 +1,87 @@
-use std::fs::create_dir_all;
-use std::process::Command;
+use std::env;
+use std::fs::{File, create_dir_all, remove_file};
+use std::io::{self, BufRead, BufReader, Write};
+use std::process::{Command, Stdio};
 
 use clap::ArgMatches;
-use rusoto_core::ChainProvider;
 
-use aws::credentials_provider;
-use encryption::Encryptor;
-use error::KawsResult;
-use pki::{CertificateAuthority, CertificateSigningRequest};
+use approval::Approvals;
+use aws::{Access, cluster_credentials_provider};
+use dependencies::ensure_ssh_keygen;
+use error::{KawsError, KawsResult};
+use files::entries;
+use message::Message;
+use metadata::ClusterMetadata;
+use paths::from_invocation;
+use pki::{
+    Certificate,
+    CertificateAuthority,
+    CertificateSigningRequest,
+    DEFAULT_BACKDATE,
+    DEFAULT_CERT_EXPIRY,
+    KeyAlgorithm,
+    PrivateKey,
+    Provenance,
+};
+use pki_manifest::PkiManifest;
+use pki_profiles::PkiProfiles;
 use process::execute_child_process;
+use provider::{CloudProvider, cloud_provider};
+use ssh::SshCertificateAuthority;
+use units::parse_duration;
+
+/// The user kaws's servers allow SSH logins for.
+const SSH_USER: &str = "core";
 
 pub struct Admin<'a> {
     admin: &'a str,
-    aws_credentials_provider: ChainProvider,
+    aws_credentials_path: Option<String>,
+    aws_credentials_profile: Option<&'a str>,
+    backdate: &'a str,
+    cert_expiry: Option<String>,
+    cfssl_auth_key_file: Option<String>,
     cluster: &'a str,
     groups: Option<Vec<&'a str>>,
+    key_algo: &'a str,
+    key_size: Option<&'a str>,
+    output: Option<String>,
+    passphrase: bool,
+    passphrase_file: Option<String>,
+    password_file: Option<String>,
+    public_key: Option<String>,
+    repository: String,
+    subject_fields: Option<&'a str>,
+    ttl: &'a str,
 }
 
 impl<'a> Admin<'a> {
     pub fn new(matches: &'a ArgMatches) -> Self {
         Admin {
             admin: matches.value_of("name").expect("clap should have required name"),
-            aws_credentials_provider: credentials_provider(
-                matches.value_of("aws-credentials-path"),
-                matches.value_of("aws-credentials-profile"),
-            ),
+            aws_credentials_path: matches.value_of("aws-credentials-path").map(from_invocation),
+            aws_credentials_profile: matches.value_of("aws-credentials-profile"),
+            backdate: matches.value_of("backdate").unwrap_or(DEFAULT_BACKDATE),
+            cert_expiry: match matches.value_of("days") {
+                Some(days) => Some(format!(
+                    "{}h",
+                    days.parse::<u64>().expect("clap should have validated days") * 24,
+                )),
+                None => matches.value_of("cert-expiry").map(str::to_owned),
+            },
+            cfssl_auth_key_file: matches.value_of("cfssl-auth-key-file").map(from_invocation),
             cluster: matches.value_of("cluster").expect("clap should have required cluster"),
             groups: matches.values_of("group").map(|values| values.collect()),
+            key_algo: matches.value_of("key-algo").unwrap_or("rsa"),
+            key_size: matches.value_of("key-size"),
+            output: matches.value_of("output").map(from_invocation),
+            passphrase: matches.is_present("passphrase") || matches.is_present("passphrase-file"),
+            passphrase_file: matches.value_of("passphrase-file").map(from_invocation),
+            password_file: matches.value_of("password-file").map(from_invocation),
+            public_key: matches.value_of("public-key").map(from_invocation),
+            repository: matches.value_of("repository")
+                .map_or_else(|| ".".to_owned(), from_invocation),
+            subject_fields: matches.value_of("subject-fields"),
+            ttl: matches.value_of("ttl").unwrap_or("8h"),
         }
     }
 
@@ -35,7 +90,21 @@ impl<'a> Admin<'a> {
             create_dir_all(format!("clusters/{}", self.cluster))?;
         });
 
-        let (csr, key) = CertificateSigningRequest::generate(self.admin, self.groups.as_ref())?;
+        let metadata = self.metadata()?;
+
+        // Fall back to the groups declared for this IAM user at `cluster init` time.
+        let groups = self.groups.clone().or_else(|| {
+            metadata.groups_for(self.admin).map(|groups| groups.iter().map(String::as_str).collect())
+        });
+
+        metadata.ensure_groups_bound(groups.as_deref().unwrap_or_default())?;
+
+        let (csr, key) = CertificateSigningRequest::generate(
+            self.admin,
+            groups.as_ref(),
+            &metadata.subject_fields(self.subject_fields)?,
+            KeyAlgorithm::new(self.key_algo, self.key_size)?,
+        )?;
 
         let csr_path = format!(
             "clusters/{}/{}-csr.pem",
@@ -50,18 +119,91 @@ impl<'a> Admin<'a> {
         );
 
         csr.write_to_file(&csr_path)?;
-        key.write_to_file_unencrypted(&key_path)?;
 
-        Ok(Some(format!(
-            "Certificate signing request created! Commit changes to Git and ask an\n\
-            administrator to generate your client certificate."
-        )))
+        if self.passphrase {
+            let passphrase = new_secret(
+                self.passphrase_file.as_deref(),
+                "Passphrase for the private key",
+                "passphrase",
+            )?;
+
+            key.write_to_file_with_passphrase(&key_path, &passphrase)?;
+        } else {
+            key.write_to_file_unencrypted(&key_path)?;
+        }
+
+        Ok(Some(Message::AdminCreated {
+            admin: self.admin.to_owned(),
+            cluster: self.cluster.to_owned(),
+        }))
+    }
+
+    /// Prints the administrator's client certificate and decrypted private key for kubectl, as
+    /// the credential plugin `kaws admin install` configures for keys with a passphrase.
+    pub fn credential(&mut self) -> KawsResult {
+        let cert_path = format!("{}/clusters/{}/{}.pem", self.repository, self.cluster, self.admin);
+        let cert = Certificate::from_file(&cert_path)?;
+        let key = self.key(&format!(
+            "{}/clusters/{}/{}-key.pem",
+            self.repository,
+            self.cluster,
+            self.admin,
+        ))?;
+
+        println!("{}", json!({
+            "apiVersion": "client.authentication.k8s.io/v1beta1",
+            "kind": "ExecCredential",
+            "status": {
+                "clientCertificateData": String::from_utf8_lossy(cert.as_bytes()),
+                "clientKeyData": String::from_utf8_lossy(key.as_bytes()),
+            },
+        }));
+
+        // Nothing else is printed on success, since kubectl reads the credential from stdout.
+        Ok(None)
+    }
+
+    pub fn export(&mut self) -> KawsResult {
+        let cert = Certificate::from_file(
+            &format!("clusters/{}/{}.pem", self.cluster, self.admin),
+        )?;
+        let key = self.key(&format!("clusters/{}/{}-key.pem", self.cluster, self.admin))?;
+        let chain = Certificate::from_file(
+            &format!("clusters/{}/k8s-ca.pem", self.cluster),
+        )?.unbundle()?;
+
+        let password = self.password()?;
+        let path = match self.output {
+            Some(ref path) => path.to_owned(),
+            None => format!("clusters/{}/{}.p12", self.cluster, self.admin),
+        };
+
+        let pkcs12 = cert.to_pkcs12(
+            &key,
+            &chain,
+            &format!("kaws-{}-{}", self.cluster, self.admin),
+            &password,
+        )?;
+
+        log_wrap!(format!("Writing {}", path), {
+            File::create(&path)?.write_all(&pkcs12)?;
+        });
+
+        Ok(Some(Message::AdminExported {
+            admin: self.admin.to_owned(),
+            cluster: self.cluster.to_owned(),
+            path,
+        }))
     }
 
     pub fn install(&mut self) -> KawsResult {
         let domain = self.domain()?.expect(
             "Terraform should have had a value for the domain output"
         );
+        let metadata = self.metadata()?;
+        let key_encrypted = !metadata.iam_authenticator && PrivateKey::from_file_unencrypted(
+            &format!("clusters/{}/{}-key.pem", self.cluster, self.admin),
+        )?.is_encrypted();
 
         log_wrap!("Configuring kubectl", {
             // set cluster
@@ -75,14 +217,45 @@ impl<'a> Admin<'a> {
             ])?;
 
             // set credentials
-            execute_child_process("kubectl", &[
-                "config",
-                "set-credentials",
-                &format!("kaws-{}-{}", self.cluster, self.admin),
-                &format!("--client-certificate=clusters/{}/{}.pem", self.cluster, self.admin),
-                &format!("--client-key=clusters/{}/{}-key.pem", self.cluster, self.admin),
-                "--embed-certs=true",
-            ])?;
+            if metadata.iam_authenticator {
+                execute_child_process("kubectl", &[
+                    "config",
+                    "set-credentials",
+                    &format!("kaws-{}-{}", self.cluster, self.admin),
+                    "--exec-api-version=client.authentication.k8s.io/v1beta1",
+                    "--exec-command=aws-iam-authenticator",
+                    "--exec-arg=token",
+                    "--exec-arg=-i",
+                    &format!("--exec-arg=kaws-{}", self.cluster),
+                ])?;
+            } else if key_encrypted {
+                // kubectl can't read a key with a passphrase, so kaws decrypts it whenever kubectl
+                // needs it, from wherever kubectl is run.
+                let repository = env::current_dir()?;
+
+                execute_child_process("kubectl", &[
+                    "config",
+                    "set-credentials",
+                    &format!("kaws-{}-{}", self.cluster, self.admin),
+                    "--exec-api-version=client.authentication.k8s.io/v1beta1",
+                    "--exec-command=kaws",
+                    "--exec-arg=admin",
+                    "--exec-arg=credential",
+                    &format!("--exec-arg={}", self.cluster),
+                    &format!("--exec-arg={}", self.admin),
+                    "--exec-arg=--repository",
+                    &format!("--exec-arg={}", repository.display()),
+                ])?;
+            } else {
+                execute_child_process("kubectl", &[
+                    "config",
+                    "set-credentials",
+                    &format!("kaws-{}-{}", self.cluster, self.admin),
+                    &format!("--client-certificate=clusters/{}/{}.pem", self.cluster, self.admin),
+                    &format!("--client-key=clusters/{}/{}-key.pem", self.cluster, self.admin),
+                    "--embed-certs=true",
+                ])?;
+            }
 
             // set context
             execute_child_process("kubectl", &[
@@ -94,65 +267,276 @@ impl<'a> Admin<'a> {
             ])?;
         });
 
-        Ok(Some(format!(
-            "Admin credentials for user \"{admin}\" installed for cluster \"{cluster}\"!\n\
-            To activate these settings as the current context, run:\n\n\
-            kubectl config use-context kaws-{cluster}\n\n\
-            If the kubectl configuration file is ever removed or changed accidentally,\n\
-            just run this command again to regenerate or reconfigure it.",
-            admin = self.admin,
-            cluster = self.cluster,
-        )))
+        Ok(Some(Message::AdminInstalled {
+            admin: self.admin.to_owned(),
+            cluster: self.cluster.to_owned(),
+        }))
     }
 
     pub fn sign(&mut self) -> KawsResult {
-        let region = self.region()?.expect(
-            "Terraform should have had a value for the region output"
-        );
-
+        let metadata = self.metadata()?;
         let admin_csr_path = format!("clusters/{}/{}-csr.pem", self.cluster, self.admin);
         let admin_cert_path = format!("clusters/{}/{}.pem", self.cluster, self.admin);
         let ca_cert_path = format!("clusters/{}/k8s-ca.pem", self.cluster);
-        let encrypted_ca_key_path = format!("clusters/{}/k8s-ca-key-encrypted.base64", self.cluster);
 
-        let mut encryptor = Encryptor::new(
-            self.aws_credentials_provider.clone(),
-            region.parse()?,
-            None,
+        let csr = CertificateSigningRequest::from_file(&admin_csr_path)?;
+
+        // Whatever is in the repository is checked, since anyone who can push to it can replace
+        // the request, and a cfssl signer may honor more of it than kaws does.
+        if let Some(reason) = csr.admin_policy_violation(self.admin)? {
+            return Err(KawsError::new(Message::CertificateSigningRequestRejected {
+                admin: self.admin.to_owned(),
+                reason,
+            }));
+        }
+
+        // The request may predate a group's removal, or have been made by hand.
+        metadata.ensure_groups_bound(&csr.groups()?)?;
+
+        let approvals = Approvals::new(self.cluster, "admin-sign", self.admin);
+
+        approvals.ensure_sufficient(&metadata)?;
+
+        let cert = match metadata.cfssl_signer {
+            Some(ref signer) => {
+                if self.cert_expiry.is_some() {
+                    eprintln!(
+                        "warning: the cfssl signer's profile decides how long the certificate is \
+                        valid for, so --cert-expiry and --days are ignored"
+                    );
+                }
+
+                let auth_key = match self.cfssl_auth_key_file {
+                    Some(ref path) => Some(first_line(path)?),
+                    None => None,
+                };
+                let cert = signer.sign(
+                    &csr,
+                    &Certificate::from_file(&ca_cert_path)?,
+                    auth_key.as_deref(),
+                )?;
+
+                cert.write_to_file(&admin_cert_path)?;
+
+                cert
+            }
+            None => self.sign_locally(&metadata, &csr, &ca_cert_path, &admin_cert_path)?,
+        };
+        // The administrator's key never leaves their machine, so no KMS key is involved.
+        PkiManifest::record(self.cluster, &admin_cert_path, &cert, None)?;
+
+        approvals.clear()?;
+        self.remove_serial_files()?;
+
+        Ok(Some(Message::AdminSigned {
+            admin: self.admin.to_owned(),
+            cluster: self.cluster.to_owned(),
+        }))
+    }
+
+    pub fn ssh_cert(&mut self) -> KawsResult {
+        ensure_ssh_keygen()?;
+
+        let region = self.region()?.expect(
+            "Terraform should have had a value for the region output"
         );
 
-        let ca = CertificateAuthority::from_files(
+        let public_key_path = match self.public_key {
+            Some(ref path) => path.to_owned(),
+            None => format!(
+                "{}/.ssh/id_ed25519.pub",
+                env::var("HOME").unwrap_or_else(|_| ".".to_owned()),
+            ),
+        };
+
+        let mut encryptor = self.cloud_provider()?.encryptor(&region, None)?
+            .with_contexts_required(self.metadata()?.encryption_contexts_required);
+
+        let ca = SshCertificateAuthority::from_files(
             &mut encryptor,
-            &ca_cert_path,
-            &encrypted_ca_key_path,
+            &format!("clusters/{}/ssh-user-ca.pub", self.cluster),
+            &format!("clusters/{}/ssh-user-ca-key-encrypted.base64", self.cluster),
         )?;
-        let csr = CertificateSigningRequest::from_file(&admin_csr_path)?;
 
-        let cert = ca.sign(&csr)?;
+        let cert_path = ca.sign_user_key(
+            &public_key_path,
+            &format!("kaws-{}-{}", self.cluster, self.admin),
+            &[SSH_USER],
+            parse_duration(self.backdate).expect("clap should have validated backdate"),
+            parse_duration(self.ttl).expect("clap should have validated ttl"),
+        )?;
 
-        cert.write_to_file(&admin_cert_path)?;
+        Ok(Some(Message::SshCertificateSigned {
+            admin: self.admin.to_owned(),
+            cert_path,
+            cluster: self.cluster.to_owned(),
+            ttl: self.ttl.to_owned(),
+        }))
+    }
 
-        Ok(Some(format!(
-            "Client certificate for administrator \"{}\" created for cluster \"{}\"!\n\
-            Commit changes to Git and ask the administrator to run `kaws admin install`.",
-            self.admin,
+    fn cloud_provider(&self) -> Result<Box<dyn CloudProvider>, KawsError> {
+        Ok(cloud_provider(self.metadata()?.cloud_provider, cluster_credentials_provider(
             self.cluster,
-        )))
+            Access::ReadWrite,
+            self.aws_credentials_path.as_deref(),
+            self.aws_credentials_profile,
+        )?))
+    }
+
+    // The administrator's private key at `path`, decrypted with the passphrase from
+    // --passphrase-file or a prompt if it has one.
+    fn key(&self, path: &str) -> Result<PrivateKey, KawsError> {
+        let key = PrivateKey::from_file_unencrypted(path)?;
+
+        if !key.is_encrypted() {
+            return Ok(key);
+        }
+
+        let passphrase = match self.passphrase_file {
+            Some(ref passphrase_file) => first_line(passphrase_file)?,
+            None => prompt_hidden(&format!("Passphrase for {}: ", path))?,
+        };
+
+        key.decrypt(path, &passphrase)
+    }
+
+    // The password protecting an exported archive.
+    fn password(&self) -> Result<String, KawsError> {
+        new_secret(self.password_file.as_deref(), "Password for the exported file", "password")
+    }
+
+    // Versions of kaws that signed with `openssl x509 -CAcreateserial` left a serial number file
+    // next to the CA certificate. kaws now picks random serial numbers, so clean up any that are
+    // left over.
+    fn remove_serial_files(&self) -> KawsResult {
+        for entry in entries(&format!("clusters/{}", self.cluster))? {
+            if entry.name.ends_with(".srl") {
+                log_wrap!(format!("Removing stray serial number file {}", entry.path), {
+                    remove_file(&entry.path)?;
+                });
+            }
+        }
+
+        Ok(None)
+    }
+
+    // Issues the administrator's certificate with the k8s CA's key, decrypted with KMS or kept
+    // outside kaws, and writes it to `cert_path`.
+    fn sign_locally(
+        &self,
+        metadata: &ClusterMetadata,
+        csr: &CertificateSigningRequest,
+        ca_cert_path: &str,
+        cert_path: &str,
+    ) -> Result<Certificate, KawsError> {
+        let profiles = PkiProfiles::from_file(self.cluster)?;
+        let profile = profiles.for_certificate("admin");
+        let expiry = metadata.certificate_expiry_for(
+            "admin",
+            self.cert_expiry.as_deref(),
+            profile.expiry.as_deref().unwrap_or(DEFAULT_CERT_EXPIRY),
+            true,
+        )?;
+        let region = self.region()?.expect(
+            "Terraform should have had a value for the region output"
+        );
+
+        let mut encryptor = self.cloud_provider()?.encryptor(&region, None)?
+            .with_contexts_required(metadata.encryption_contexts_required);
+
+        let ca = CertificateAuthority::from_files(
+            &mut encryptor,
+            ca_cert_path,
+            &format!("clusters/{}/k8s-ca-key-encrypted.base64", self.cluster),
+            metadata.external_ca_key("k8s"),
+            self.backdate,
+        )?.with_provenance(Provenance::new(self.cluster));
+
+        let cert = ca.sign(csr, expiry, Some(&profile))?;
+
+        ca.write_issued(&cert, cert_path)?;
+
+        Ok(cert)
     }
 
-    fn domain(&self) -> KawsResult {
+    fn metadata(&self) -> Result<ClusterMetadata, KawsError> {
+        ClusterMetadata::from_file(&format!("clusters/{}/metadata.json", self.cluster))
+    }
+
+    fn domain(&self) -> Result<Option<String>, KawsError> {
         self.output("domain")
     }
 
-    fn region(&self) -> KawsResult {
+    fn region(&self) -> Result<Option<String>, KawsError> {
         self.output("region")
     }
 
-    fn output(&self, output_name: &str) -> KawsResult {
+    fn output(&self, output_name: &str) -> Result<Option<String>, KawsError> {
         let output = Command::new("kaws")
-            .args(&["cluster", "output", self.cluster, output_name])
+            .args(["cluster", "output", self.cluster, output_name])
             .output()?;
 
-        Ok(Some(String::from_utf8_lossy(&output.stdout).trim_right().to_string()))
+        Ok(Some(String::from_utf8_lossy(&output.stdout).trim_end().to_string()))
+    }
+}
+
+// A new password or passphrase, read from the first line of `path`, or else entered twice at a
+// prompt with echoing turned off.
+fn new_secret(path: Option<&str>, prompt: &str, noun: &str) -> Result<String, KawsError> {
+    let secret = match path {
+        Some(path) => first_line(path)?,
+        None => {
+            let secret = prompt_hidden(&format!("{}: ", prompt))?;
+
+            if prompt_hidden(&format!("Confirm the {}: ", noun))? != secret {
+                return Err(KawsError::new(Message::PasswordMismatch));
+            }
+
+            secret
+        }
+    };
+
+    if secret.is_empty() {
+        return Err(KawsError::new(Message::PasswordEmpty));
     }
+
+    Ok(secret)
+}
+
+fn first_line(path: &str) -> Result<String, KawsError> {
+    let mut line = String::new();
+
+    BufReader::new(File::open(path)?).read_line(&mut line)?;
+
+    Ok(line.trim_end_matches(&['\r', '\n'][..]).to_owned())
+}
+
+// Reads a line from stdin without echoing it, if stdin is a terminal that `stty` can configure.
+// The prompt goes to stderr, so it doesn't mix with output read by another program, as kubectl
+// reads `kaws admin credential`'s.
+fn prompt_hidden(prompt: &str) -> Result<String, KawsError> {
+    eprint!("{}", prompt);
+    io::stderr().flush()?;
+
+    let hidden = stty("-echo");
+    let mut line = String::new();
+    let result = io::stdin().read_line(&mut line);
+
+    if hidden {
+        stty("echo");
+        eprintln!();
+    }
+
+    result?;
+
+    Ok(line.trim_end_matches(&['\r', '\n'][..]).to_owned())
+}
+
+fn stty(setting: &str) -> bool {
+    Command::new("stty")
+        .arg(setting)
+        .stdin(Stdio::inherit())
+        .stderr(Stdio::null())
+        .status()
+        .is_ok_and(|status| status.success())
 }
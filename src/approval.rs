@@ -0,0 +1,285 @@
+use std::env;
+use std::fs::{copy, create_dir_all, remove_dir_all, File};
+use std::io::{Read, Write};
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+use clap::ArgMatches;
+use openssl::sha::sha256;
+
+use dependencies::ensure_ssh_keygen;
+use encryption::{ENCRYPTED_SUFFIX, encrypted_file_paths};
+use error::{KawsError, KawsResult};
+use message::Message;
+use metadata::ClusterMetadata;
+use paths::from_invocation;
+use process::execute_child_process;
+use runtime::temp_dir;
+
+/// The namespace approvals are signed in, so that no other signature made with an operator's SSH
+/// key can be passed off as an approval, and the other way around.
+const NAMESPACE: &str = "kaws-approval";
+
+/// The commands that decrypt a CA's private key to sign with it, by their leading subcommands, with
+/// the operation approvers approve for each, which the audit expects to decrypt one. Three are
+/// exempt from approvals: `admin ssh-cert` signs SSH certificates that expire within hours,
+/// `kms-regions add` only encrypts the keys again for another region, and `pki verify` only checks
+/// them against their certificates. `kaws secret cat` needs approvals to print a CA's key, but
+/// signs nothing, so the audit reports it.
+pub const SIGNING_COMMANDS: [(&str, Option<&str>); 12] = [
+    ("admin sign", Some("admin-sign")),
+    ("admin ssh-cert", None),
+    ("cluster generate-pki", Some("generate-pki")),
+    ("cluster kms-regions add", None),
+    ("cluster pki import-ca", Some("import-ca")),
+    ("cluster pki refresh-endpoints", Some("refresh-endpoints")),
+    ("cluster pki revoke", Some("revoke")),
+    ("cluster pki revoke-node", Some("revoke-node")),
+    ("cluster pki verify", None),
+    ("cluster reencrypt", Some("reencrypt")),
+    ("cluster renew-pki", Some("renew-pki")),
+    ("cluster rotate-pki", Some("rotate-pki")),
+];
+
+/// The approvals recorded for one run of an operation that uses a CA's private key, such as
+/// signing an administrator's certificate.
+///
+/// Each approval is an SSH signature, made with `ssh-keygen -Y sign`, of a statement naming the
+/// cluster, the operation, and a digest of the files it acts on. An approval is therefore only
+/// good for the files it was given for, e.g. the one certificate signing request.
+pub struct Approvals<'a> {
+    cluster: &'a str,
+    operation: &'a str,
+    target: &'a str,
+}
+
+pub struct Approve<'a> {
+    approver: &'a str,
+    cluster: &'a str,
+    key: Option<String>,
+    operation: &'a str,
+    target: &'a str,
+}
+
+impl<'a> Approvals<'a> {
+    pub fn new(cluster: &'a str, operation: &'a str, target: &'a str) -> Self {
+        Approvals {
+            cluster,
+            operation,
+            target,
+        }
+    }
+
+    /// Checks that at least as many approvers as the cluster's metadata requires have approved
+    /// the operation. Clusters that require no approvals always pass.
+    pub fn ensure_sufficient(&self, metadata: &ClusterMetadata) -> Result<(), KawsError> {
+        self.ensure_at_least(metadata, metadata.required_approvals)
+    }
+
+    /// Checks that at least `required` of the approvers in the cluster's metadata have approved
+    /// the operation, for callers that require approvals whatever the metadata says.
+    pub fn ensure_at_least(&self, metadata: &ClusterMetadata, required: usize)
+    -> Result<(), KawsError> {
+        if required == 0 {
+            return Ok(());
+        }
+
+        ensure_ssh_keygen()?;
+
+        let statement = self.statement()?;
+        let mut approvals = 0;
+
+        for (approver, public_key) in &metadata.approvers {
+            let signature_path = self.signature_path(approver);
+
+            if Path::new(&signature_path).exists() &&
+                verify(&statement, &signature_path, approver, public_key)? {
+                approvals += 1;
+            }
+        }
+
+        if approvals < required {
+            return Err(KawsError::new(Message::ApprovalsMissing {
+                approvals,
+                cluster: self.cluster.to_owned(),
+                operation: self.description(),
+                required,
+            }));
+        }
+
+        Ok(())
+    }
+
+    /// Removes the approvals once the operation has run, so they can't be used to run it again.
+    pub fn clear(&self) -> KawsResult {
+        if Path::new(&self.directory()).exists() {
+            log_wrap!("Removing used approvals", {
+                remove_dir_all(self.directory())?;
+            });
+        }
+
+        Ok(None)
+    }
+
+    // Private
+
+    fn description(&self) -> String {
+        format!("{} {}", self.operation, self.target)
+    }
+
+    fn directory(&self) -> String {
+        format!("clusters/{}/approvals/{}-{}", self.cluster, self.operation, self.target)
+    }
+
+    fn signature_path(&self, approver: &str) -> String {
+        format!("{}/{}.sig", self.directory(), approver)
+    }
+
+    // What an approver signs. The digest covers the administrator's CSR when signing a client
+    // certificate, the cluster's spec when applying it, the encrypted files when encrypting them
+    // again, the secret when printing it, and otherwise the CA certificates the cluster has, since
+    // generating, importing, and rotating CAs change them.
+    fn statement(&self) -> Result<String, KawsError> {
+        let paths = match self.operation {
+            "admin-sign" => vec![format!("clusters/{}/{}-csr.pem", self.cluster, self.target)],
+            "apply-spec" => vec![format!("clusters/{}/{}.json", self.cluster, self.target)],
+            "reencrypt" => encrypted_file_paths(self.cluster)?,
+            "secret-cat" => vec![
+                format!("clusters/{}/{}{}", self.cluster, self.target, ENCRYPTED_SUFFIX),
+            ],
+            _ => ["etcd", "etcd-peer", "k8s"].iter()
+                .map(|ca| format!("clusters/{}/{}-ca.pem", self.cluster, ca))
+                .filter(|path| Path::new(path).exists())
+                .collect(),
+        };
+
+        let mut contents = vec![];
+
+        for path in paths {
+            File::open(path)?.read_to_end(&mut contents)?;
+        }
+
+        let digest = sha256(&contents).iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect::<String>();
+
+        Ok(format!(
+            "kaws approval\ncluster: {}\noperation: {}\ntarget: {}\nsha256: {}\n",
+            self.cluster,
+            self.operation,
+            self.target,
+            digest,
+        ))
+    }
+}
+
+impl<'a> Approve<'a> {
+    pub fn new(matches: &'a ArgMatches) -> Self {
+        Approve {
+            approver: matches.value_of("approver").expect("clap should have required approver"),
+            cluster: matches.value_of("cluster").expect("clap should have required cluster"),
+            key: matches.value_of("key").map(from_invocation),
+            operation: matches.value_of("operation").expect("clap should have required operation"),
+            target: matches.value_of("target").expect("clap should have required target"),
+        }
+    }
+
+    pub fn approve(&self) -> KawsResult {
+        ensure_ssh_keygen()?;
+
+        let metadata = ClusterMetadata::from_file(&format!("clusters/{}/metadata.json", self.cluster))?;
+        let public_key = metadata.approvers.get(self.approver).ok_or_else(|| {
+            KawsError::new(Message::ApproverUnknown {
+                approver: self.approver.to_owned(),
+                cluster: self.cluster.to_owned(),
+            })
+        })?;
+
+        let approvals = Approvals::new(self.cluster, self.operation, self.target);
+        let statement = approvals.statement()?;
+
+        let key_path = match self.key {
+            Some(ref path) => path.to_owned(),
+            None => format!(
+                "{}/.ssh/id_ed25519",
+                env::var("HOME").unwrap_or_else(|_| ".".to_owned()),
+            ),
+        };
+
+        let tempdir = temp_dir()?;
+        let statement_path = tempdir.path().join("statement");
+
+        File::create(&statement_path)?.write_all(statement.as_bytes())?;
+
+        log_wrap!("Signing the approval", {
+            execute_child_process("ssh-keygen", &[
+                "-q",
+                "-Y",
+                "sign",
+                "-f",
+                &key_path,
+                "-n",
+                NAMESPACE,
+                &statement_path.to_string_lossy(),
+            ])?;
+        });
+
+        let temporary_signature_path = tempdir.path().join("statement.sig");
+
+        // Catch a key that doesn't match the one recorded for the approver now, rather than when
+        // the approval is needed.
+        if !verify(
+            &statement,
+            &temporary_signature_path.to_string_lossy(),
+            self.approver,
+            public_key,
+        )? {
+            return Err(KawsError::new(Message::ApprovalKeyMismatch {
+                approver: self.approver.to_owned(),
+                cluster: self.cluster.to_owned(),
+                key_path,
+            }));
+        }
+
+        let signature_path = approvals.signature_path(self.approver);
+
+        create_dir_all(approvals.directory())?;
+        copy(temporary_signature_path, &signature_path)?;
+
+        Ok(Some(Message::ApprovalRecorded {
+            approver: self.approver.to_owned(),
+            operation: approvals.description(),
+            path: signature_path,
+        }))
+    }
+}
+
+// Whether the signature at `signature_path` is `approver`'s approval of `statement`.
+fn verify(statement: &str, signature_path: &str, approver: &str, public_key: &str)
+-> Result<bool, KawsError> {
+    let tempdir = temp_dir()?;
+    let allowed_signers_path = tempdir.path().join("allowed_signers");
+
+    writeln!(
+        File::create(&allowed_signers_path)?,
+        "{} namespaces=\"{}\" {}",
+        approver,
+        NAMESPACE,
+        public_key.trim(),
+    )?;
+
+    let mut child = Command::new("ssh-keygen")
+        .arg("-Y")
+        .arg("verify")
+        .arg("-f")
+        .arg(&allowed_signers_path)
+        .args(["-I", approver, "-n", NAMESPACE, "-s", signature_path])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()?;
+
+    child.stdin.take().expect("stdin should have been piped").write_all(statement.as_bytes())?;
+
+    Ok(child.wait()?.success())
+}
@@ -0,0 +1,351 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::env;
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, ErrorKind, Write};
+use std::sync::OnceLock;
+
+use chrono::{DateTime, Duration, Utc};
+use clap::ArgMatches;
+use serde_json::{Value, from_str, to_string};
+
+use approval::SIGNING_COMMANDS;
+use aws::{Access, cloudtrail_events, cluster_credentials_provider};
+use encryption::Encryptor;
+use error::{KawsError, KawsResult};
+use message::Message;
+use paths::from_invocation;
+use pki_manifest::PkiManifest;
+use render::print_rows;
+use tfvars::Tfvars;
+use units::parse_duration;
+
+/// The IAM roles of a cluster's servers, which decrypt its secrets when they boot, without
+/// `-CLUSTER`.
+const SERVER_ROLES: [&str; 4] = ["kaws-bastion", "kaws-etcd", "kaws-k8s-master", "kaws-k8s-node"];
+
+static COMMAND: OnceLock<String> = OnceLock::new();
+
+/// One decryption of one of a cluster's encrypted files, as recorded in
+/// clusters/CLUSTER/kms-audit.log, one JSON object per line.
+#[derive(Deserialize, Serialize)]
+pub struct Decryption {
+    pub time: DateTime<Utc>,
+    /// The name of the decrypted file in the cluster's directory.
+    pub file: String,
+    /// The kaws subcommands that decrypted it, e.g. "admin sign".
+    pub command: String,
+    /// The local user who ran kaws.
+    pub user: String,
+    /// The ID KMS gave the Decrypt request, which CloudTrail records too.
+    pub request_id: Option<String>,
+}
+
+pub struct Audit<'a> {
+    aws_credentials_path: Option<String>,
+    aws_credentials_profile: Option<&'a str>,
+    cloudtrail: bool,
+    cluster: &'a str,
+    kms_key: Option<&'a str>,
+    since: Duration,
+}
+
+/// Records the command kaws is running, e.g. "cluster generate-pki all", for the audit log.
+pub fn set_command(command: &str) {
+    let _ = COMMAND.set(command.to_owned());
+}
+
+/// Appends a decryption of the file at `path` to its cluster's audit log. Files outside a
+/// cluster's directory aren't recorded. A log that can't be written is only warned about, so
+/// auditing can't stop a command from working.
+pub fn record_decryption(path: &str, request_id: Option<String>) {
+    let mut parts = path.trim_start_matches("./").splitn(3, '/');
+
+    let (cluster, file) = match (parts.next(), parts.next(), parts.next()) {
+        (Some("clusters"), Some(cluster), Some(file)) if !file.contains('/') => (cluster, file),
+        _ => return,
+    };
+
+    let decryption = Decryption {
+        time: Utc::now(),
+        file: file.to_owned(),
+        command: COMMAND.get().cloned().unwrap_or_default(),
+        user: env::var("USER").or_else(|_| env::var("USERNAME")).unwrap_or_default(),
+        request_id,
+    };
+
+    let result = to_string(&decryption).map_err(KawsError::from).and_then(|line| {
+        let mut log = OpenOptions::new().create(true).append(true).open(log_path(cluster))?;
+
+        writeln!(log, "{}", line)?;
+
+        Ok(())
+    });
+
+    if let Err(error) = result {
+        eprintln!("warning: failed to record decryption in the audit log: {}", error);
+    }
+}
+
+impl<'a> Audit<'a> {
+    pub fn new(matches: &'a ArgMatches) -> Self {
+        Audit {
+            aws_credentials_path: matches.value_of("aws-credentials-path").map(from_invocation),
+            aws_credentials_profile: matches.value_of("aws-credentials-profile"),
+            cloudtrail: matches.is_present("cloudtrail"),
+            cluster: matches.value_of("cluster").expect("clap should have required cluster"),
+            kms_key: matches.value_of("kms-key"),
+            since: parse_duration(matches.value_of("since").unwrap_or("720h"))
+                .expect("clap should have validated since"),
+        }
+    }
+
+    /// Lists how often each of the cluster's encrypted files was decrypted, by whom, and with
+    /// which commands. With `--cloudtrail`, also lists the AWS principals behind the decryptions
+    /// and the decryptions with the cluster's key that kaws didn't record.
+    pub fn usage(&self) -> KawsResult {
+        let decryptions = self.decryptions()?;
+        let events = self.events()?;
+        let mut usage: BTreeMap<&str, Vec<&Decryption>> = BTreeMap::new();
+
+        for decryption in &decryptions {
+            usage.entry(&decryption.file).or_default().push(decryption);
+        }
+
+        let mut header = vec!["FILE", "DECRYPTIONS", "USERS", "COMMANDS", "LAST"];
+
+        if events.is_some() {
+            header.push("AWS PRINCIPALS");
+        }
+
+        let mut rows = vec![header.iter().map(|column| column.to_string()).collect()];
+
+        for (file, decryptions) in &usage {
+            let users: BTreeSet<&str> = decryptions.iter().map(|d| d.user.as_str()).collect();
+            let commands: BTreeSet<&str> = decryptions.iter().map(|d| d.command.as_str()).collect();
+            let last = decryptions.iter().map(|decryption| decryption.time).max()
+                .expect("every file in the usage has a decryption");
+            let mut row = vec![
+                file.to_string(),
+                decryptions.len().to_string(),
+                join(&users),
+                join(&commands),
+                last.format("%Y-%m-%d %H:%M").to_string(),
+            ];
+
+            if let Some(ref events) = events {
+                let principals: BTreeSet<&str> = decryptions.iter()
+                    .filter_map(|decryption| events.get(decryption.request_id.as_ref()?))
+                    .map(principal)
+                    .collect();
+
+                row.push(join(&principals));
+            }
+
+            rows.push(row);
+        }
+
+        print_rows(&rows)?;
+
+        if let Some(ref events) = events {
+            let recorded = recorded_request_ids(&decryptions);
+            let mut unrecorded: BTreeMap<&str, (usize, &str)> = BTreeMap::new();
+
+            for (request_id, event) in events {
+                if recorded.contains(request_id.as_str()) {
+                    continue;
+                }
+
+                let entry = unrecorded.entry(principal(event)).or_insert((0, ""));
+
+                entry.0 += 1;
+                entry.1 = entry.1.max(event["eventTime"].as_str().unwrap_or(""));
+            }
+
+            if !unrecorded.is_empty() {
+                let mut rows = vec![vec![
+                    "AWS PRINCIPAL NOT RECORDED BY KAWS".to_owned(),
+                    "DECRYPTIONS".to_owned(),
+                    "LAST".to_owned(),
+                ]];
+
+                for (principal, (count, last)) in unrecorded {
+                    rows.push(vec![principal.to_owned(), count.to_string(), last.to_owned()]);
+                }
+
+                println!();
+                print_rows(&rows)?;
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Lists decryptions that don't fit how kaws uses the cluster's secrets: a CA's key decrypted
+    /// by a command that doesn't sign anything, and with `--cloudtrail`, the cluster's key used
+    /// to decrypt by anyone but kaws and the cluster's servers.
+    pub fn anomalies(&self) -> KawsResult {
+        let decryptions = self.decryptions()?;
+        let events = self.events()?;
+        let mut rows = vec![vec!["TIME".to_owned(), "WHAT".to_owned(), "WHY".to_owned()]];
+
+        for decryption in &decryptions {
+            if decryption.file.ends_with("-ca-key-encrypted.base64") &&
+                !SIGNING_COMMANDS.iter()
+                    .any(|&(command, _)| decryption.command.starts_with(command)) {
+                rows.push(vec![
+                    decryption.time.format("%Y-%m-%d %H:%M").to_string(),
+                    format!("{} decrypted by {}", decryption.file, decryption.user),
+                    format!("a CA's key was decrypted by `kaws {}`, which signs nothing", decryption.command),
+                ]);
+            }
+        }
+
+        if let Some(ref events) = events {
+            let recorded = recorded_request_ids(&decryptions);
+            let server_roles: Vec<String> = SERVER_ROLES.iter()
+                .map(|role| format!("assumed-role/{}-{}/", role, self.cluster))
+                .collect();
+
+            for (request_id, event) in events {
+                let principal = principal(event);
+
+                if recorded.contains(request_id.as_str()) ||
+                    server_roles.iter().any(|role| principal.contains(role.as_str())) {
+                    continue;
+                }
+
+                rows.push(vec![
+                    event["eventTime"].as_str().unwrap_or("-").to_owned(),
+                    format!("the cluster's KMS key used by {}", principal),
+                    "a decryption kaws didn't record, by someone other than the cluster's servers"
+                        .to_owned(),
+                ]);
+            }
+        }
+
+        let anomalies = rows.len() - 1;
+
+        if anomalies == 0 {
+            return Ok(Some(Message::AuditAnomaliesNotFound {
+                cluster: self.cluster.to_owned(),
+            }));
+        }
+
+        print_rows(&rows)?;
+
+        Err(KawsError::new(Message::AuditAnomaliesFound {
+            anomalies,
+            cluster: self.cluster.to_owned(),
+        }))
+    }
+
+    // Private
+
+    // The decryptions recorded since `--since`, oldest first. Lines that can't be parsed, e.g.
+    // from a merge conflict resolved by hand, are skipped.
+    fn decryptions(&self) -> Result<Vec<Decryption>, KawsError> {
+        let file = match File::open(log_path(self.cluster)) {
+            Ok(file) => file,
+            Err(ref error) if error.kind() == ErrorKind::NotFound => return Ok(vec![]),
+            Err(error) => return Err(error.into()),
+        };
+        let start = Utc::now() - self.since;
+        let mut decryptions = vec![];
+
+        for line in BufReader::new(file).lines() {
+            if let Ok(decryption) = from_str::<Decryption>(&line?) {
+                if decryption.time >= start {
+                    decryptions.push(decryption);
+                }
+            }
+        }
+
+        decryptions.sort_by_key(|decryption| decryption.time);
+
+        Ok(decryptions)
+    }
+
+    // With `--cloudtrail`, the Decrypt events for the cluster's KMS keys since `--since`, by
+    // request ID.
+    fn events(&self) -> Result<Option<BTreeMap<String, Value>>, KawsError> {
+        if !self.cloudtrail {
+            return Ok(None);
+        }
+
+        let mut key_ids = self.kms_key_ids()?;
+        let tfvars = Tfvars::from_file(&format!("clusters/{}/terraform.tfvars", self.cluster))?;
+        let region = tfvars.get("kaws_region").ok_or_else(|| {
+            KawsError::new(Message::TfvarsUnreadable {
+                cluster: self.cluster.to_owned(),
+                error: "kaws_region is not set".to_owned(),
+            })
+        })?;
+        let provider = cluster_credentials_provider(
+            self.cluster,
+            Access::ReadOnly,
+            self.aws_credentials_path.as_deref(),
+            self.aws_credentials_profile,
+        )?;
+        let mut events = BTreeMap::new();
+
+        // CloudTrail names keys by ARN, so an alias is looked up as the key it points to now.
+        let alias = self.kms_key.filter(|key| key.starts_with("alias/") || key.contains(":alias/"));
+
+        if let Some(alias) = alias {
+            key_ids.insert(Encryptor::new(provider.clone(), region.parse()?, None).key_arn(alias)?);
+        }
+
+        for event in cloudtrail_events(&provider, &region.parse()?, "Decrypt", Utc::now() - self.since)? {
+            // Decrypt requests name no key, since the ciphertext does, so the key is only in the
+            // resources of the event.
+            let uses_key = event["resources"].as_array().into_iter().flatten().any(|resource| {
+                resource["ARN"].as_str().is_some_and(|arn| {
+                    key_ids.iter().any(|key_id| arn.ends_with(&format!("key/{}", key_id)) || arn == key_id)
+                })
+            });
+
+            if let (true, Some(request_id)) = (uses_key, event["requestID"].as_str()) {
+                events.insert(request_id.to_owned(), event.clone());
+            }
+        }
+
+        Ok(Some(events))
+    }
+
+    // The KMS key given with `--kms-key`, or else each key the cluster's PKI manifest records
+    // private keys being encrypted with.
+    fn kms_key_ids(&self) -> Result<BTreeSet<String>, KawsError> {
+        if let Some(kms_key) = self.kms_key {
+            return Ok(vec![kms_key.to_owned()].into_iter().collect());
+        }
+
+        let key_ids: BTreeSet<String> = PkiManifest::from_file(self.cluster)?.certificates.into_iter()
+            .filter_map(|entry| entry.kms_key_id)
+            .collect();
+
+        if key_ids.is_empty() {
+            return Err(KawsError::new(Message::AuditKmsKeyUnknown {
+                cluster: self.cluster.to_owned(),
+            }));
+        }
+
+        Ok(key_ids)
+    }
+}
+
+fn log_path(cluster: &str) -> String {
+    format!("clusters/{}/kms-audit.log", cluster)
+}
+
+fn recorded_request_ids(decryptions: &[Decryption]) -> BTreeSet<&str> {
+    decryptions.iter().filter_map(|decryption| decryption.request_id.as_deref()).collect()
+}
+
+// The ARN of the IAM user or role session that made a CloudTrail event's request.
+fn principal(event: &Value) -> &str {
+    event["userIdentity"]["arn"].as_str().unwrap_or("unknown")
+}
+
+fn join(values: &BTreeSet<&str>) -> String {
+    values.iter().cloned().collect::<Vec<_>>().join(", ")
+}
@@ -1,4 +1,230 @@
-use rusoto_core::{ChainProvider, ProfileProvider};
+use std::cell::RefCell;
+use std::env;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::OnceLock;
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Utc};
+use rusoto_core::{DispatchSignedRequest, HttpClient, HttpDispatchError, Region};
+use rusoto_core::credential::{AwsCredentials, ChainProvider, ProfileProvider, ProvideAwsCredentials};
+use rusoto_core::param::{Params, ServiceParams};
+use rusoto_core::request::{DispatchSignedRequestFuture, HttpResponse};
+use rusoto_core::signature::SignedRequest;
+use serde_json::{Value, from_slice, from_str};
+use tokio::runtime::{Builder, Runtime};
+use xml::reader::{EventReader, XmlEvent};
+
+use error::KawsError;
+use message::Message;
+use metadata::ClusterMetadata;
+
+const EC2_API_VERSION: &str = "2016-11-15";
+
+static RUNTIME: OnceLock<Runtime> = OnceLock::new();
+
+thread_local! {
+    // The request ID of the last AWS API call that got a response on this thread, which the
+    // decryption audit log records so its entries can be matched with CloudTrail's.
+    static LAST_REQUEST_ID: RefCell<Option<String>> = const { RefCell::new(None) };
+}
+
+/// The kind of access to AWS a command needs, which picks the credentials profile recorded for it
+/// in the cluster's metadata.
+#[derive(Clone, Copy)]
+pub enum Access {
+    /// Only reads AWS resources, e.g. `kaws cluster plan`.
+    ReadOnly,
+    /// Changes AWS resources or uses KMS, e.g. `kaws cluster apply` or `kaws admin sign`.
+    ReadWrite,
+}
+
+/// An HTTP dispatcher for AWS clients that traces every API call.
+///
+/// Each attempt is logged at the trace level under the `kaws::aws` target, which `--trace-aws`
+/// enables. Only the service, operation, status, request ID, and duration are recorded. Request
+/// and response bodies and headers are never logged, since they carry credentials, signatures,
+/// and plaintext key material.
+pub struct TracingHttpClient {
+    inner: HttpClient,
+}
+
+struct TracedDispatch {
+    future: DispatchSignedRequestFuture,
+    operation: String,
+    service: String,
+    started_at: Instant,
+}
+
+impl TracingHttpClient {
+    pub fn new() -> Self {
+        TracingHttpClient {
+            inner: HttpClient::new().expect("failed to create HTTP client with TLS"),
+        }
+    }
+}
+
+impl DispatchSignedRequest for TracingHttpClient {
+    fn dispatch(&self, request: SignedRequest, timeout: Option<Duration>)
+    -> DispatchSignedRequestFuture {
+        let service = request.service.clone();
+        let operation = operation_name(&request);
+
+        Box::pin(TracedDispatch {
+            future: self.inner.dispatch(request, timeout),
+            operation,
+            service,
+            started_at: Instant::now(),
+        })
+    }
+}
+
+impl Future for TracedDispatch {
+    type Output = Result<HttpResponse, HttpDispatchError>;
+
+    fn poll(mut self: Pin<&mut Self>, context: &mut Context) -> Poll<Self::Output> {
+        let result = match self.future.as_mut().poll(context) {
+            Poll::Ready(result) => result,
+            Poll::Pending => return Poll::Pending,
+        };
+
+        let elapsed = self.started_at.elapsed();
+
+        match result {
+            Ok(ref response) => {
+                let request_id = response.headers.get("x-amzn-requestid")
+                    .or_else(|| response.headers.get("x-amz-request-id"))
+                    .map(|request_id| request_id.as_str());
+
+                LAST_REQUEST_ID.with(|last| *last.borrow_mut() = request_id.map(str::to_owned));
+
+                let request_id = request_id.unwrap_or("-");
+
+                trace!(
+                    "{}:{} status={} request_id={} duration={}ms",
+                    self.service,
+                    self.operation,
+                    response.status.as_u16(),
+                    request_id,
+                    elapsed.as_millis(),
+                );
+            }
+            Err(ref error) => {
+                trace!(
+                    "{}:{} failed after {}ms: {}",
+                    self.service,
+                    self.operation,
+                    elapsed.as_millis(),
+                    error,
+                );
+            }
+        }
+
+        Poll::Ready(result)
+    }
+}
+
+/// Runs a future to completion on the shared Tokio runtime.
+///
+/// The AWS SDK is asynchronous, but the CLI is not. This is the single point where the two meet,
+/// so command implementations can call AWS APIs as if they were blocking.
+pub fn block_on<F: Future>(future: F) -> F::Output {
+    RUNTIME.get_or_init(|| {
+        Builder::new_multi_thread()
+            .enable_all()
+            .build()
+            .expect("Failed to create Tokio runtime.")
+    }).block_on(future)
+}
+
+/// The current time according to AWS.
+///
+/// Read from the Date header of an unauthenticated request to KMS, so no credentials are needed.
+/// The header only has one second precision.
+pub fn server_time(region: &Region) -> Result<DateTime<Utc>, KawsError> {
+    let request = SignedRequest::new("GET", "kms", region, "/");
+    let response = block_on(
+        TracingHttpClient::new().dispatch(request, Some(Duration::from_secs(10)))
+    )?;
+
+    match response.headers.get("date") {
+        Some(date) => Ok(DateTime::parse_from_rfc2822(date)?.with_timezone(&Utc)),
+        None => Err(KawsError::new(Message::AwsDateMissing)),
+    }
+}
+
+/// The events CloudTrail recorded for API calls named `event_name`, e.g. "Decrypt", since `start`,
+/// newest first. Each is the parsed JSON of the event's full CloudTrail record.
+///
+/// CloudTrail has no client in the AWS SDK kaws uses, so the requests are built by hand, like the
+/// Price List's. Only the last 90 days of management events can be looked up.
+pub fn cloudtrail_events(
+    provider: &ChainProvider,
+    region: &Region,
+    event_name: &str,
+    start: DateTime<Utc>,
+) -> Result<Vec<Value>, KawsError> {
+    let credentials = credentials(provider)?;
+    let mut events = vec![];
+    let mut next_token: Option<String> = None;
+
+    loop {
+        let mut body = json!({
+            "LookupAttributes": [{
+                "AttributeKey": "EventName",
+                "AttributeValue": event_name,
+            }],
+            "StartTime": start.timestamp(),
+            "MaxResults": 50,
+        });
+
+        if let Some(ref next_token) = next_token {
+            body["NextToken"] = json!(next_token);
+        }
+
+        let mut request = SignedRequest::new("POST", "cloudtrail", region, "/");
+
+        request.set_content_type("application/x-amz-json-1.1".to_owned());
+        request.add_header(
+            "x-amz-target",
+            "com.amazonaws.cloudtrail.v20131101.CloudTrail_20131101.LookupEvents",
+        );
+        request.set_payload(Some(body.to_string()));
+        request.sign(&credentials);
+
+        let mut response = block_on(
+            TracingHttpClient::new().dispatch(request, Some(Duration::from_secs(30)))
+        )?;
+        let body: Value = from_slice(&block_on(response.buffer())?.body)?;
+
+        if !response.status.is_success() {
+            let error = body["message"].as_str()
+                .or_else(|| body["Message"].as_str())
+                .unwrap_or_else(|| response.status.as_str());
+
+            return Err(KawsError::new(Message::CloudTrailError {
+                error: format!("{}: {}", body["__type"].as_str().unwrap_or("Error"), error),
+            }));
+        }
+
+        // Like the Price List's products, each event's record is a JSON document in a string.
+        for event in body["Events"].as_array().into_iter().flatten() {
+            if let Some(record) = event["CloudTrailEvent"].as_str() {
+                events.push(from_str(record)?);
+            }
+        }
+
+        match body["NextToken"].as_str() {
+            Some(token) => next_token = Some(token.to_owned()),
+            None => return Ok(events),
+        }
+    }
+}
+
+pub fn credentials(provider: &ChainProvider) -> Result<AwsCredentials, KawsError> {
+    Ok(block_on(provider.credentials())?)
+}
 
 pub fn credentials_provider(path: Option<&str>, profile: Option<&str>) -> ChainProvider {
     let mut profile_provider = ProfileProvider::new().expect(
@@ -15,3 +241,390 @@ pub fn credentials_provider(path: Option<&str>, profile: Option<&str>) -> ChainP
 
     ChainProvider::with_profile_provider(profile_provider)
 }
+
+/// The credentials for a command on `cluster` that needs `access`.
+///
+/// A profile given on the command line or with `AWS_PROFILE` wins. Otherwise the profile recorded
+/// in the cluster's metadata for the access is used, falling back from a missing read-only profile
+/// to the read-write one, and then to the default profile. Credentials in the environment are
+/// still used before any profile.
+pub fn cluster_credentials_provider(
+    cluster: &str,
+    access: Access,
+    path: Option<&str>,
+    profile: Option<&str>,
+) -> Result<ChainProvider, KawsError> {
+    if profile.is_some() || env::var_os("AWS_PROFILE").is_some() {
+        return Ok(credentials_provider(path, profile));
+    }
+
+    let metadata = ClusterMetadata::from_file(&format!("clusters/{}/metadata.json", cluster))?;
+    let aws_profiles = metadata.aws_profiles;
+    let recorded = match access {
+        Access::ReadOnly => aws_profiles.read_only.or(aws_profiles.read_write),
+        Access::ReadWrite => aws_profiles.read_write,
+    };
+
+    Ok(credentials_provider(path, recorded.as_deref()))
+}
+
+/// Calls an EC2 API action, returning the XML body of a successful response.
+///
+/// EC2 has no client in the AWS SDK kaws uses, so the signed query request is built by hand.
+pub fn ec2_query(
+    provider: &ChainProvider,
+    region: &Region,
+    action: &str,
+    parameters: &[(&str, &str)],
+) -> Result<Vec<u8>, KawsError> {
+    let mut params = Params::new();
+
+    params.put("Action", action);
+    params.put("Version", EC2_API_VERSION);
+
+    for &(key, value) in parameters {
+        params.put(key, value);
+    }
+
+    let mut request = SignedRequest::new("GET", "ec2", region, "/");
+
+    request.set_params(params);
+    request.sign(&credentials(provider)?);
+
+    let mut response = block_on(
+        TracingHttpClient::new().dispatch(request, Some(Duration::from_secs(30)))
+    )?;
+    let body = block_on(response.buffer())?.body.to_vec();
+
+    if response.status.is_success() {
+        return Ok(body);
+    }
+
+    let code = xml_element_text(&body, "Code")?.unwrap_or_else(|| response.status.to_string());
+    let error = match xml_element_text(&body, "Message")? {
+        Some(message) => format!("{}: {}", code, message),
+        None => code,
+    };
+
+    Err(KawsError::new(Message::Ec2Error { error }))
+}
+
+/// The request ID of the last AWS API call made on this thread that got a response, if AWS gave
+/// it one.
+pub fn last_request_id() -> Option<String> {
+    LAST_REQUEST_ID.with(|last| last.borrow().clone())
+}
+
+/// The contents of the object `key` in `bucket`, or `None` if there is no such object.
+pub fn s3_get_object(provider: &ChainProvider, region: &Region, bucket: &str, key: &str)
+-> Result<Option<Vec<u8>>, KawsError> {
+    let request = SignedRequest::new("GET", "s3", region, &format!("/{}/{}", bucket, key));
+
+    match s3_request(provider, request) {
+        Ok(body) => Ok(Some(body)),
+        Err(ref error) if matches!(*error.message(), Message::S3ObjectNotFound { .. }) => Ok(None),
+        Err(error) => Err(error),
+    }
+}
+
+/// Whether versioning is enabled for `bucket`, so it keeps the objects that were overwritten.
+pub fn s3_is_versioned(provider: &ChainProvider, region: &Region, bucket: &str)
+-> Result<bool, KawsError> {
+    let mut request = SignedRequest::new("GET", "s3", region, &format!("/{}", bucket));
+    let mut params = Params::new();
+
+    params.put_key("versioning");
+    request.set_params(params);
+
+    let body = s3_request(provider, request)?;
+
+    Ok(xml_element_text(&body, "Status")?.as_deref() == Some("Enabled"))
+}
+
+/// The keys of the objects in `bucket` that start with `prefix`.
+pub fn s3_list_objects(provider: &ChainProvider, region: &Region, bucket: &str, prefix: &str)
+-> Result<Vec<String>, KawsError> {
+    let mut keys = vec![];
+    let mut continuation_token = None;
+
+    loop {
+        let mut request = SignedRequest::new("GET", "s3", region, &format!("/{}", bucket));
+        let mut params = Params::new();
+
+        params.put("list-type", "2");
+        params.put("prefix", prefix);
+
+        if let Some(ref token) = continuation_token {
+            params.put("continuation-token", token);
+        }
+
+        request.set_params(params);
+
+        let body = s3_request(provider, request)?;
+
+        keys.extend(xml_element_texts(&body, "Key")?);
+
+        match xml_element_text(&body, "NextContinuationToken")? {
+            Some(token) => continuation_token = Some(token),
+            None => return Ok(keys),
+        }
+    }
+}
+
+/// Stores `contents` as the object `key` in `bucket`, encrypted with the KMS key `kms_key_id` if
+/// it's given, or the way the bucket encrypts objects by default.
+///
+/// Like EC2, S3 has no client in the AWS SDK kaws uses, so the signed request is built by hand.
+pub fn s3_put_object(
+    provider: &ChainProvider,
+    region: &Region,
+    bucket: &str,
+    key: &str,
+    contents: &[u8],
+    kms_key_id: Option<&str>,
+) -> Result<(), KawsError> {
+    let mut request = SignedRequest::new("PUT", "s3", region, &format!("/{}/{}", bucket, key));
+
+    if let Some(kms_key_id) = kms_key_id {
+        request.add_header("x-amz-server-side-encryption", "aws:kms");
+        request.add_header("x-amz-server-side-encryption-aws-kms-key-id", kms_key_id);
+    }
+
+    request.set_payload(Some(contents.to_vec()));
+    s3_request(provider, request)?;
+
+    Ok(())
+}
+
+/// Calls a Secrets Manager API action, returning the JSON body of a successful response.
+///
+/// Like SSM, Secrets Manager has no client in the AWS SDK kaws uses. A secret that doesn't exist
+/// fails with `Message::SecretsManagerSecretNotFound`.
+pub fn secrets_manager_call(provider: &ChainProvider, region: &Region, action: &str, body: Value)
+-> Result<Value, KawsError> {
+    let mut request = SignedRequest::new("POST", "secretsmanager", region, "/");
+
+    request.set_content_type("application/x-amz-json-1.1".to_owned());
+    request.add_header("x-amz-target", &format!("secretsmanager.{}", action));
+    request.set_payload(Some(body.to_string()));
+    request.sign(&credentials(provider)?);
+
+    let mut response = block_on(
+        TracingHttpClient::new().dispatch(request, Some(Duration::from_secs(30)))
+    )?;
+    let response_body = block_on(response.buffer())?.body;
+    let response_body: Value = if response_body.is_empty() {
+        json!({})
+    } else {
+        from_slice(&response_body)?
+    };
+
+    if response.status.is_success() {
+        return Ok(response_body);
+    }
+
+    let error_type = response_body["__type"].as_str().unwrap_or("Error");
+
+    if error_type.ends_with("ResourceNotFoundException") {
+        return Err(KawsError::new(Message::SecretsManagerSecretNotFound {
+            name: body["SecretId"].as_str().unwrap_or_default().to_owned(),
+        }));
+    }
+
+    let error = response_body["message"].as_str()
+        .or_else(|| response_body["Message"].as_str())
+        .unwrap_or_else(|| response.status.as_str());
+
+    Err(KawsError::new(Message::SecretsManagerError {
+        error: format!("{}: {}", error_type, error),
+    }))
+}
+
+/// Calls an SSM API action, returning the JSON body of a successful response.
+///
+/// Like CloudTrail, SSM has no client in the AWS SDK kaws uses, so the requests are built by hand.
+/// A parameter that doesn't exist fails with `Message::SsmParameterNotFound`.
+pub fn ssm_call(provider: &ChainProvider, region: &Region, action: &str, body: Value)
+-> Result<Value, KawsError> {
+    let mut request = SignedRequest::new("POST", "ssm", region, "/");
+
+    request.set_content_type("application/x-amz-json-1.1".to_owned());
+    request.add_header("x-amz-target", &format!("AmazonSSM.{}", action));
+    request.set_payload(Some(body.to_string()));
+    request.sign(&credentials(provider)?);
+
+    let mut response = block_on(
+        TracingHttpClient::new().dispatch(request, Some(Duration::from_secs(30)))
+    )?;
+    let response_body = block_on(response.buffer())?.body;
+    let response_body: Value = if response_body.is_empty() {
+        json!({})
+    } else {
+        from_slice(&response_body)?
+    };
+
+    if response.status.is_success() {
+        return Ok(response_body);
+    }
+
+    let error_type = response_body["__type"].as_str().unwrap_or("Error");
+
+    if error_type.ends_with("ParameterNotFound") {
+        return Err(KawsError::new(Message::SsmParameterNotFound {
+            name: body["Name"].as_str().unwrap_or_default().to_owned(),
+        }));
+    }
+
+    let error = response_body["message"].as_str()
+        .or_else(|| response_body["Message"].as_str())
+        .unwrap_or_else(|| response.status.as_str());
+
+    Err(KawsError::new(Message::SsmError {
+        error: format!("{}: {}", error_type, error),
+    }))
+}
+
+/// Looks up products in the AWS Price List, returning each product's description and terms.
+///
+/// Like EC2, the Price List has no client in the AWS SDK kaws uses. It is only served from
+/// us-east-1, whatever region the products are in.
+pub fn pricing_products(
+    provider: &ChainProvider,
+    service_code: &str,
+    filters: &[(&str, &str)],
+) -> Result<Vec<Value>, KawsError> {
+    let body = json!({
+        "ServiceCode": service_code,
+        "Filters": filters.iter().map(|&(field, value)| json!({
+            "Type": "TERM_MATCH",
+            "Field": field,
+            "Value": value,
+        })).collect::<Vec<_>>(),
+        "FormatVersion": "aws_v1",
+    });
+
+    let mut request = SignedRequest::new("POST", "pricing", &Region::UsEast1, "/");
+
+    request.set_endpoint_prefix("api.pricing".to_owned());
+    request.set_content_type("application/x-amz-json-1.1".to_owned());
+    request.add_header("x-amz-target", "AWSPriceListService.GetProducts");
+    request.set_payload(Some(body.to_string()));
+    request.sign(&credentials(provider)?);
+
+    let mut response = block_on(
+        TracingHttpClient::new().dispatch(request, Some(Duration::from_secs(30)))
+    )?;
+    let body: Value = from_slice(&block_on(response.buffer())?.body)?;
+
+    if !response.status.is_success() {
+        let error = body["message"].as_str()
+            .or_else(|| body["Message"].as_str())
+            .unwrap_or_else(|| response.status.as_str());
+
+        return Err(KawsError::new(Message::PricingError {
+            error: format!("{}: {}", body["__type"].as_str().unwrap_or("Error"), error),
+        }));
+    }
+
+    let mut products = vec![];
+
+    // Each product is itself a JSON document, encoded as a string.
+    for product in body["PriceList"].as_array().into_iter().flatten() {
+        if let Some(product) = product.as_str() {
+            products.push(from_str(product)?);
+        }
+    }
+
+    Ok(products)
+}
+
+/// The text of the first element named `name` in an XML document, if there is one.
+///
+/// An element that is present but empty gives an empty string.
+pub fn xml_element_text(document: &[u8], name: &str) -> Result<Option<String>, KawsError> {
+    let mut inside = false;
+
+    for event in EventReader::new(document) {
+        match event? {
+            XmlEvent::StartElement { name: element, .. } => inside = element.local_name == name,
+            XmlEvent::Characters(text) if inside => return Ok(Some(text)),
+            XmlEvent::EndElement { .. } if inside => return Ok(Some(String::new())),
+            _ => {}
+        }
+    }
+
+    Ok(None)
+}
+
+/// The text of every element named `name` in an XML document, in order.
+pub fn xml_element_texts(document: &[u8], name: &str) -> Result<Vec<String>, KawsError> {
+    let mut inside = false;
+    let mut texts = vec![];
+
+    for event in EventReader::new(document) {
+        match event? {
+            XmlEvent::StartElement { name: element, .. } => inside = element.local_name == name,
+            XmlEvent::Characters(text) if inside => {
+                texts.push(text);
+                inside = false;
+            }
+            XmlEvent::EndElement { .. } if inside => {
+                texts.push(String::new());
+                inside = false;
+            }
+            _ => {}
+        }
+    }
+
+    Ok(texts)
+}
+
+// Private
+
+// Signs and sends an S3 request, returning the body of a successful response. An object that
+// doesn't exist fails with `Message::S3ObjectNotFound`.
+fn s3_request(provider: &ChainProvider, mut request: SignedRequest) -> Result<Vec<u8>, KawsError> {
+    let path = request.path.clone();
+
+    request.sign(&credentials(provider)?);
+
+    let mut response = block_on(
+        TracingHttpClient::new().dispatch(request, Some(Duration::from_secs(30)))
+    )?;
+    let body = block_on(response.buffer())?.body.to_vec();
+
+    if response.status.is_success() {
+        return Ok(body);
+    }
+
+    let code = xml_element_text(&body, "Code")?.unwrap_or_else(|| response.status.to_string());
+
+    if code == "NoSuchKey" {
+        return Err(KawsError::new(Message::S3ObjectNotFound {
+            path: path.trim_start_matches('/').to_owned(),
+        }));
+    }
+
+    let error = match xml_element_text(&body, "Message")? {
+        Some(message) => format!("{}: {}", code, message),
+        None => code,
+    };
+
+    Err(KawsError::new(Message::S3Error { error }))
+}
+
+fn operation_name(request: &SignedRequest) -> String {
+    // JSON protocol services (e.g. KMS) name the operation in a header, while query protocol
+    // services (e.g. EC2) pass it as the "Action" parameter.
+    if let Some(target) = request.headers.get("x-amz-target").and_then(|values| values.first()) {
+        let target = String::from_utf8_lossy(target);
+
+        return target.rsplit('.').next().unwrap_or(&target).to_owned();
+    }
+
+    if let Some(Some(action)) = request.params.get("Action") {
+        return action.clone();
+    }
+
+    format!("{} {}", request.method, request.path)
+}
@@ -0,0 +1,143 @@
+use std::collections::BTreeMap;
+use std::env;
+use std::fs::{File, Metadata, create_dir_all, metadata, rename, symlink_metadata};
+use std::io::{self, ErrorKind, Write};
+use std::path::Path;
+use std::process;
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use serde_json::{from_reader, to_string};
+
+use error::KawsError;
+use files::entries;
+
+/// The directory read-only commands cache what they parse from the repository in, so they don't
+/// parse it again until the files it came from change.
+pub const CACHE_DIR: &str = ".kaws/cache";
+
+/// The size and modification time of each file a cached value was derived from, keyed by path.
+pub type Inputs = BTreeMap<String, FileStamp>;
+
+#[derive(Deserialize, PartialEq, Serialize)]
+pub struct FileStamp {
+    len: u64,
+    modified: DateTime<Utc>,
+}
+
+#[derive(Deserialize, Serialize)]
+struct CacheEntry<T> {
+    inputs: Inputs,
+    value: T,
+}
+
+/// The value cached as `name`, unless it is missing, unreadable, or was derived from files that
+/// have changed since.
+pub fn read<T: DeserializeOwned>(name: &str, inputs: &Inputs) -> Option<T> {
+    let file = File::open(cache_path(name)).ok()?;
+    let entry: CacheEntry<T> = from_reader(file).ok()?;
+
+    if entry.inputs == *inputs {
+        debug!("Using cached {}", name);
+
+        Some(entry.value)
+    } else {
+        None
+    }
+}
+
+/// Caches `value` as `name`. The cache only saves time, so failing to write it is logged rather
+/// than returned.
+pub fn write<T: Serialize>(name: &str, inputs: Inputs, value: T) {
+    if let Err(error) = try_write(name, CacheEntry { inputs, value }) {
+        debug!("Not caching {}: {}", name, error);
+    }
+}
+
+/// Stamps for every file in `dir`, which change whenever a file is added, removed, or rewritten.
+pub fn directory_inputs(dir: &str) -> Result<Inputs, KawsError> {
+    let mut inputs = BTreeMap::new();
+
+    for entry in entries(dir)? {
+        inputs.insert(entry.path.clone(), FileStamp::new(&entry.metadata()?)?);
+    }
+
+    Ok(inputs)
+}
+
+/// Stamps for each of `paths` that exists.
+pub fn file_inputs(paths: &[&str]) -> Result<Inputs, KawsError> {
+    let mut inputs = BTreeMap::new();
+
+    for path in paths {
+        match metadata(path) {
+            Ok(metadata) => {
+                inputs.insert(path.to_string(), FileStamp::new(&metadata)?);
+            }
+            Err(ref error) if error.kind() == ErrorKind::NotFound => {}
+            Err(error) => return Err(error.into()),
+        }
+    }
+
+    Ok(inputs)
+}
+
+/// The path `program` runs from, as found on the PATH.
+pub fn program_path(program: &str) -> Option<String> {
+    env::split_paths(&env::var_os("PATH")?)
+        .map(|dir| dir.join(program))
+        .find(|path| path.is_file())
+        .and_then(|path| path.to_str().map(String::from))
+}
+
+impl FileStamp {
+    fn new(metadata: &Metadata) -> Result<Self, KawsError> {
+        Ok(FileStamp {
+            len: metadata.len(),
+            modified: DateTime::from(metadata.modified()?),
+        })
+    }
+}
+
+fn cache_path(name: &str) -> String {
+    format!("{}/{}.json", CACHE_DIR, name)
+}
+
+// Writes the entry to a temporary file first, so a command reading the cache at the same time
+// never sees half of it. The file is named after the process, so two commands caching the same
+// value at once don't write to the same one. Like Terraform's working directories, the cache is
+// only written through real directories, since a symlink could point it anywhere.
+fn try_write<T: Serialize>(name: &str, entry: CacheEntry<T>) -> Result<(), KawsError> {
+    let path = cache_path(name);
+    let dir = Path::new(&path).parent().and_then(Path::to_str).unwrap_or(CACHE_DIR).to_owned();
+    let mut parent = String::new();
+
+    for component in dir.split('/') {
+        if !parent.is_empty() {
+            parent.push('/');
+        }
+
+        parent.push_str(component);
+
+        match symlink_metadata(&parent) {
+            Ok(metadata) => if !metadata.is_dir() {
+                let error = io::Error::other(format!("{} is not a directory", parent));
+
+                return Err(error.into());
+            },
+            Err(ref error) if error.kind() == ErrorKind::NotFound => break,
+            Err(error) => return Err(error.into()),
+        }
+    }
+
+    create_dir_all(&dir)?;
+
+    let temporary_path = format!("{}.{}.tmp", path, process::id());
+    let mut file = File::create(&temporary_path)?;
+
+    writeln!(file, "{}", to_string(&entry)?)?;
+    rename(&temporary_path, &path)?;
+
+    Ok(())
+}
@@ -0,0 +1,147 @@
+use std::time::Duration;
+
+use openssl::hash::MessageDigest;
+use openssl::pkey::PKey;
+use openssl::sign::Signer;
+use openssl::x509::X509;
+use rusoto_core::{DispatchSignedRequest, HttpClient, Region};
+use rusoto_core::signature::SignedRequest;
+use rustc_serialize::base64::{STANDARD, ToBase64};
+use rustc_serialize::hex::FromHex;
+use serde_json::{Value, from_slice, to_vec};
+
+use aws::block_on;
+use error::KawsError;
+use message::Message;
+use pki::{Certificate, CertificateSigningRequest};
+
+/// A `cfssl serve` API that signs certificates with a CA whose key is kept on the signing host, as
+/// recorded under `cfssl_signer` in a cluster's metadata.
+#[derive(Deserialize, Serialize)]
+pub struct CfsslSigner {
+    /// The API's base URL, e.g. "https://signer.example.com:8888".
+    pub url: String,
+    /// The signing profile to request, defaults to the signer's default profile.
+    #[serde(default)]
+    pub profile: Option<String>,
+    /// The label of the signer to use, for a server with more than one.
+    #[serde(default)]
+    pub label: Option<String>,
+}
+
+impl CfsslSigner {
+    /// Sends `csr` to the signer and returns the certificate it issues, once it's checked to be
+    /// for the request's key and to chain to `ca`, the CA certificate in the cluster's directory.
+    ///
+    /// With `auth_key`, the hex-encoded key of the signer's auth provider, the request goes to the
+    /// authsign endpoint with an HMAC-SHA256 token, as `cfssl serve` requires for a signing
+    /// profile with an auth key. Without it, the request goes to the unauthenticated sign endpoint.
+    pub fn sign(
+        &self,
+        csr: &CertificateSigningRequest,
+        ca: &Certificate,
+        auth_key: Option<&str>,
+    ) -> Result<Certificate, KawsError> {
+        let mut request = json!({
+            "certificate_request": String::from_utf8_lossy(csr.as_bytes()),
+        });
+
+        if let Some(ref profile) = self.profile {
+            request["profile"] = json!(profile);
+        }
+
+        if let Some(ref label) = self.label {
+            request["label"] = json!(label);
+        }
+
+        let request = to_vec(&request)?;
+        let (path, payload) = match auth_key {
+            Some(auth_key) => {
+                let key = auth_key.trim().from_hex()
+                    .map_err(|_| self.failure("the auth key is not hex-encoded".to_owned()))?;
+                let hmac_key = PKey::hmac(&key)?;
+                let mut signer = Signer::new(MessageDigest::sha256(), &hmac_key)?;
+
+                signer.update(&request)?;
+
+                ("/api/v1/cfssl/authsign", to_vec(&json!({
+                    "token": signer.sign_to_vec()?.to_base64(STANDARD),
+                    "request": request.to_base64(STANDARD),
+                }))?)
+            }
+            None => ("/api/v1/cfssl/sign", request),
+        };
+
+        // As for the Pushgateway, a custom endpoint keeps the URL's scheme and port, and the
+        // request is never signed for AWS.
+        let region = Region::Custom {
+            name: "cfssl".to_owned(),
+            endpoint: self.url.trim_end_matches('/').to_owned(),
+        };
+        let mut http_request = SignedRequest::new("POST", "cfssl", &region, path);
+
+        http_request.set_content_type("application/json".to_owned());
+        http_request.set_payload(Some(payload));
+
+        debug!("Sending the certificate signing request to {}{}", self.url, path);
+
+        let client = HttpClient::new().expect("failed to create HTTP client with TLS");
+        let mut response = block_on(client.dispatch(http_request, Some(Duration::from_secs(30))))?;
+        let status = response.status.as_u16();
+        let body = block_on(response.buffer())?.body;
+        let reply: Value = match from_slice(&body) {
+            Ok(reply) => reply,
+            Err(_) => return Err(self.failure(format!(
+                "HTTP {}: {}",
+                status,
+                String::from_utf8_lossy(&body).trim(),
+            ))),
+        };
+
+        // cfssl reports each problem with a code and message, e.g. an invalid token.
+        if reply["success"] != Value::Bool(true) {
+            let errors: Vec<String> = reply["errors"].as_array().into_iter().flatten()
+                .map(|error| format!(
+                    "{} (code {})",
+                    error["message"].as_str().unwrap_or("unknown error"),
+                    error["code"],
+                ))
+                .collect();
+
+            return Err(self.failure(if errors.is_empty() {
+                format!("HTTP {}", status)
+            } else {
+                errors.join(", ")
+            }));
+        }
+
+        let cert: Certificate = match reply["result"]["certificate"].as_str() {
+            Some(pem) => X509::from_pem(pem.as_bytes())?.into(),
+            None => return Err(self.failure("the response has no certificate".to_owned())),
+        };
+
+        if cert.public_key()?.as_bytes() != csr.public_key()?.as_bytes() {
+            return Err(self.failure(
+                "it returned a certificate for a different key than the request's".to_owned(),
+            ));
+        }
+
+        if let Some(reason) = cert.verify_chain(ca)? {
+            return Err(self.failure(format!(
+                "it returned a certificate that isn't issued by the cluster's CA: {}",
+                reason,
+            )));
+        }
+
+        Ok(cert)
+    }
+
+    // Private
+
+    fn failure(&self, error: String) -> KawsError {
+        KawsError::new(Message::CfsslSigningFailed {
+            error,
+            url: self.url.clone(),
+        })
+    }
+}
@@ -0,0 +1,114 @@
+use std::fs::{Metadata, remove_dir_all, remove_file, symlink_metadata};
+use std::io::ErrorKind;
+
+use clap::ArgMatches;
+
+use cache::CACHE_DIR;
+use error::{KawsError, KawsResult};
+use files::entries_if_present;
+use message::Message;
+use module::EMBEDDED_MODULE_DIR;
+use runtime::stale_invocation_dirs;
+use terraform::{DATA_DIR_ROOT, LEGACY_DATA_DIR, data_dir};
+
+pub struct Clean<'a> {
+    cluster: Option<&'a str>,
+}
+
+impl<'a> Clean<'a> {
+    pub fn new(matches: &'a ArgMatches) -> Self {
+        Clean {
+            cluster: matches.value_of("cluster"),
+        }
+    }
+
+    pub fn clean(&self) -> KawsResult {
+        let mut count = 0;
+
+        // Never descend through a symlink, which could lead outside the repository. If .kaws or
+        // .kaws/terraform is anything but a real directory, removing it is all there is to do.
+        let mut parents_are_directories = true;
+
+        for path in &[".kaws", DATA_DIR_ROOT] {
+            if let Some(metadata) = metadata(path)? {
+                if !metadata.is_dir() {
+                    remove(path, &metadata)?;
+                    count += 1;
+                    parents_are_directories = false;
+
+                    break;
+                }
+            }
+        }
+
+        if parents_are_directories {
+            let mut paths = vec![];
+
+            match self.cluster {
+                Some(cluster) => {
+                    paths.push(data_dir(cluster));
+                    paths.push(format!("{}/clusters/{}.json", CACHE_DIR, cluster));
+                    paths.push(format!("{}/outputs/{}", CACHE_DIR, cluster));
+                }
+                None => for entry in entries_if_present(DATA_DIR_ROOT)? {
+                    paths.push(entry.path);
+                },
+            }
+
+            for path in paths {
+                if let Some(metadata) = metadata(&path)? {
+                    remove(&path, &metadata)?;
+                    count += 1;
+                }
+            }
+        }
+
+        if self.cluster.is_none() {
+            if parents_are_directories {
+                for path in &[EMBEDDED_MODULE_DIR, CACHE_DIR] {
+                    if let Some(metadata) = metadata(path)? {
+                        remove(path, &metadata)?;
+                        count += 1;
+                    }
+                }
+            }
+
+            if let Some(metadata) = metadata(LEGACY_DATA_DIR)? {
+                remove(LEGACY_DATA_DIR, &metadata)?;
+                count += 1;
+            }
+
+            // Invocations that were killed before they could remove their own scratch files.
+            for path in stale_invocation_dirs()? {
+                if let Some(metadata) = metadata(&path)? {
+                    remove(&path, &metadata)?;
+                    count += 1;
+                }
+            }
+        }
+
+        Ok(Some(Message::WorkingDirectoriesCleaned { count }))
+    }
+}
+
+// The metadata of `path` itself rather than what it links to, or `None` if there is nothing there.
+fn metadata(path: &str) -> Result<Option<Metadata>, KawsError> {
+    match symlink_metadata(path) {
+        Ok(metadata) => Ok(Some(metadata)),
+        Err(ref error) if error.kind() == ErrorKind::NotFound => Ok(None),
+        Err(error) => Err(error.into()),
+    }
+}
+
+// Removes a directory and its contents, or just the link if `path` is a symlink.
+fn remove(path: &str, metadata: &Metadata) -> Result<(), KawsError> {
+    log_wrap!(format!("Removing {}", path), {
+        if metadata.is_dir() {
+            remove_dir_all(path)?;
+        } else {
+            remove_file(path)?;
+        }
+    });
+
+    Ok(())
+}
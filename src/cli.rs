@@ -1,9 +1,14 @@
 use std::cmp::Ordering;
+use std::net::IpAddr;
 
 use bitstring::BitString;
 use cidr::Ipv4Cidr;
 use clap::{App, AppSettings, Arg, SubCommand};
 
+use pki::SubjectFields;
+use preset::NODE_PLACEMENTS;
+use units::{parse_duration, parse_volume_size};
+
 pub fn app<'a, 'b>() -> App<'a, 'b> {
     App::new("kaws")
         .version(env!("CARGO_PKG_VERSION"))
@@ -11,9 +16,83 @@ pub fn app<'a, 'b>() -> App<'a, 'b> {
         .after_help("\nStart by creating a new repository with the `init` command.")
         .setting(AppSettings::GlobalVersion)
         .setting(AppSettings::SubcommandRequiredElseHelp)
+        .arg(
+            Arg::with_name("columns")
+                .long("columns")
+                .global(true)
+                .takes_value(true)
+                .value_name("COLUMNS")
+                .help("The columns of tables to print, in order, separated by commas, e.g. \"cluster,version\"; defaults to all but the ones only --wide prints")
+        )
+        .arg(
+            Arg::with_name("jobs")
+                .short("j")
+                .long("jobs")
+                .global(true)
+                .takes_value(true)
+                .value_name("N")
+                .validator(validate_jobs)
+                .help("The most things kaws does at once, such as KMS requests and clusters' statuses; defaults to 16")
+        )
+        .arg(
+            Arg::with_name("no-pager")
+                .long("no-pager")
+                .global(true)
+                .help("Prints tables directly instead of through $KAWS_PAGER, $PAGER, or less when stdout is a terminal")
+        )
+        .arg(
+            Arg::with_name("porcelain")
+                .long("porcelain")
+                .global(true)
+                .help("Prints the final success or error message as a line of JSON with a stable code")
+        )
+        .arg(
+            Arg::with_name("pushgateway")
+                .long("pushgateway")
+                .global(true)
+                .takes_value(true)
+                .value_name("URL")
+                .help("Pushes metrics about the command to the Prometheus Pushgateway at this URL when it finishes")
+        )
+        .arg(
+            Arg::with_name("repo")
+                .long("repo")
+                .global(true)
+                .takes_value(true)
+                .value_name("PATH")
+                .help("The kaws repository to work on, defaults to the current directory or the nearest one above it that is a repository")
+        )
+        .arg(
+            Arg::with_name("sort-by")
+                .long("sort-by")
+                .global(true)
+                .takes_value(true)
+                .value_name("COLUMN")
+                .help("The column to sort the rows of tables by, e.g. \"expires\"; defaults to each table's own order")
+        )
+        .arg(
+            Arg::with_name("trace-aws")
+                .long("trace-aws")
+                .global(true)
+                .help("Logs every AWS API call made by kaws, with request IDs and timings")
+        )
+        .arg(
+            Arg::with_name("wide")
+                .long("wide")
+                .global(true)
+                .help("Prints every column of tables, including long ones such as certificates' SANs")
+        )
         .subcommand(admin())
+        .subcommand(audit())
+        .subcommand(clean())
         .subcommand(cluster())
+        .subcommand(doctor())
+        .subcommand(fleet())
+        .subcommand(image())
         .subcommand(init())
+        .subcommand(reconcile())
+        .subcommand(secret())
+        .subcommand(status())
 }
 
 fn admin<'a, 'b>() -> App<'a, 'b> {
@@ -21,8 +100,12 @@ fn admin<'a, 'b>() -> App<'a, 'b> {
         .about("Commands for managing cluster administrators")
         .setting(AppSettings::SubcommandRequiredElseHelp)
         .subcommand(admin_create())
+        .subcommand(admin_credential())
+        .subcommand(admin_export())
+        .subcommand(admin_groups())
         .subcommand(admin_install())
         .subcommand(admin_sign())
+        .subcommand(admin_ssh_cert())
 }
 
 fn admin_create<'a, 'b>() -> App<'a, 'b> {
@@ -47,16 +130,232 @@ fn admin_create<'a, 'b>() -> App<'a, 'b> {
                 .takes_value(true)
                 .multiple(true)
                 .number_of_values(1)
-            .help("A Kubernetes groups this user belongs to; this option can be specified more than once")
+            .help("A Kubernetes groups this user belongs to, which must be bound to a cluster role, defaults to the groups recorded for the user in the cluster's metadata; this option can be specified more than once")
+        )
+        .arg(
+            Arg::with_name("key-algo")
+                .long("key-algo")
+                .takes_value(true)
+                .possible_values(&["ecdsa", "rsa"])
+                .help("Algorithm of the new private keys, defaults to \"rsa\"")
+        )
+        .arg(
+            Arg::with_name("key-size")
+                .long("key-size")
+                .takes_value(true)
+                .possible_values(&["256", "384", "2048", "3072", "4096"])
+                .help("Size in bits of the new private keys: 256 (P-256) or 384 (P-384) for ecdsa, and 2048, 3072, or 4096 for rsa; defaults to 256 for ecdsa and 2048 for rsa")
+        )
+        .arg(
+            Arg::with_name("passphrase")
+                .long("passphrase")
+                .help("Encrypts the private key with a passphrase, which is prompted for")
+        )
+        .arg(
+            Arg::with_name("passphrase-file")
+                .long("passphrase-file")
+                .takes_value(true)
+                .value_name("PATH")
+                .help("A file whose first line is the passphrase to encrypt the private key with; implies --passphrase")
+        )
+        .arg(
+            Arg::with_name("subject-fields")
+                .long("subject-fields")
+                .takes_value(true)
+                .value_name("FIELDS")
+                .validator(validate_subject_fields)
+                .help("Fields added to the subject of the certificate signing request, e.g. \"C=US,ST=California,OU=Platform\"; each defaults to the certificate_subject in the cluster's metadata, and O is left out, since the admin's groups are the organizations")
         )
         .after_help(
             "\nCreates the following files:\n\n\
-            * clusters/CLUSTER/NAME-key.pem: The admin's unencrypted private key\n\
+            * clusters/CLUSTER/NAME-key.pem: The admin's private key, unencrypted unless a passphrase is given\n\
             * clusters/CLUSTER/NAME-csr.pem: The admin's certificate signing request\n\n\
             Generated files are only valid for the specified cluster. The private key should not be checked into Git."
         )
 }
 
+fn admin_credential<'a, 'b>() -> App<'a, 'b> {
+    SubCommand::with_name("credential")
+        .about("Prints an administrator's certificate and decrypted private key for kubectl, which runs it as a credential plugin")
+        .arg(
+            Arg::with_name("cluster")
+                .index(1)
+                .required(true)
+                .help("The cluster the credentials are for")
+        )
+        .arg(
+            Arg::with_name("name")
+                .index(2)
+                .required(true)
+                .help("The name of the administrator whose credentials are being printed")
+        )
+        .arg(
+            Arg::with_name("passphrase-file")
+                .long("passphrase-file")
+                .takes_value(true)
+                .value_name("PATH")
+                .help("A file whose first line is the private key's passphrase; without it, the passphrase is prompted for")
+        )
+        .arg(
+            Arg::with_name("repository")
+                .long("repository")
+                .takes_value(true)
+                .value_name("PATH")
+                .help("The kaws repository holding the credentials, defaults to the current directory")
+        )
+        .after_help(
+            "\n`kaws admin install` configures kubectl to run this command when an administrator's \
+            private key is encrypted with a passphrase, since kubectl can't decrypt it itself. The \
+            credentials are printed as a client.authentication.k8s.io/v1beta1 ExecCredential."
+        )
+}
+
+fn admin_export<'a, 'b>() -> App<'a, 'b> {
+    SubCommand::with_name("export")
+        .about("Packages an administrator's credentials into a single file for browsers and other clients")
+        .arg(
+            Arg::with_name("cluster")
+                .index(1)
+                .required(true)
+                .help("The cluster the credentials are for")
+        )
+        .arg(
+            Arg::with_name("name")
+                .index(2)
+                .required(true)
+                .help("The name of the administrator whose credentials are being exported")
+        )
+        .arg(
+            Arg::with_name("format")
+                .long("format")
+                .takes_value(true)
+                .possible_values(&["p12"])
+                .help("The format of the exported file, defaults to \"p12\" (a password-protected PKCS #12 archive)")
+        )
+        .arg(
+            Arg::with_name("output")
+                .short("o")
+                .long("output")
+                .takes_value(true)
+                .value_name("PATH")
+                .help("Where to write the exported file, defaults to clusters/CLUSTER/NAME.p12")
+        )
+        .arg(
+            Arg::with_name("password-file")
+                .long("password-file")
+                .takes_value(true)
+                .value_name("PATH")
+                .help("A file whose first line is the password to protect the exported file with; without it, the password is prompted for")
+        )
+        .arg(
+            Arg::with_name("passphrase-file")
+                .long("passphrase-file")
+                .takes_value(true)
+                .value_name("PATH")
+                .help("A file whose first line is the private key's passphrase, if it has one; without it, the passphrase is prompted for")
+        )
+        .after_help(
+            "\nThe following files are expected by this command:\n\n\
+            * clusters/CLUSTER/k8s-ca.pem: The k8s CA certificate, and any CAs above it\n\
+            * clusters/CLUSTER/NAME.pem: The admin's client certificate\n\
+            * clusters/CLUSTER/NAME-key.pem: The admin's private key\n\n\
+            The exported file contains the private key, so it should not be checked into Git."
+        )
+}
+
+fn admin_groups<'a, 'b>() -> App<'a, 'b> {
+    SubCommand::with_name("groups")
+        .about("Manages the Kubernetes groups administrators belong to and the cluster roles they are bound to")
+        .setting(AppSettings::SubcommandRequiredElseHelp)
+        .subcommand(admin_groups_add())
+        .subcommand(admin_groups_list())
+        .subcommand(admin_groups_remove())
+}
+
+fn admin_groups_add<'a, 'b>() -> App<'a, 'b> {
+    SubCommand::with_name("add")
+        .about("Binds a group to a cluster role and adds administrators to it")
+        .arg(
+            Arg::with_name("cluster")
+                .index(1)
+                .required(true)
+                .help("The cluster the group belongs to")
+        )
+        .arg(
+            Arg::with_name("group")
+                .index(2)
+                .required(true)
+                .help("The Kubernetes group")
+        )
+        .arg(
+            Arg::with_name("member")
+                .short("m")
+                .long("member")
+                .takes_value(true)
+                .value_name("NAME")
+                .multiple(true)
+                .number_of_values(1)
+                .help("An administrator or IAM user to add to the group; this option can be specified more than once")
+        )
+        .arg(
+            Arg::with_name("role")
+                .long("role")
+                .takes_value(true)
+                .value_name("ROLE")
+                .help("The cluster role to bind the group to, required unless the group is already bound")
+        )
+        .after_help(
+            "\nUpdates the following files:\n\n\
+            * clusters/CLUSTER/metadata.json: The cluster's groups, their roles, and their members\n\
+            * clusters/CLUSTER/rbac.yml: A ClusterRoleBinding for each group\n\
+            * clusters/CLUSTER/terraform.tfvars: The aws-iam-authenticator configuration, if the cluster uses it"
+        )
+}
+
+fn admin_groups_list<'a, 'b>() -> App<'a, 'b> {
+    SubCommand::with_name("list")
+        .about("Lists a cluster's groups, their cluster roles, and their members")
+        .arg(
+            Arg::with_name("cluster")
+                .index(1)
+                .required(true)
+                .help("The cluster whose groups to list")
+        )
+}
+
+fn admin_groups_remove<'a, 'b>() -> App<'a, 'b> {
+    SubCommand::with_name("remove")
+        .about("Removes administrators from a group, or the group itself")
+        .arg(
+            Arg::with_name("cluster")
+                .index(1)
+                .required(true)
+                .help("The cluster the group belongs to")
+        )
+        .arg(
+            Arg::with_name("group")
+                .index(2)
+                .required(true)
+                .help("The Kubernetes group")
+        )
+        .arg(
+            Arg::with_name("member")
+                .short("m")
+                .long("member")
+                .takes_value(true)
+                .value_name("NAME")
+                .multiple(true)
+                .number_of_values(1)
+                .help("An administrator or IAM user to remove from the group, instead of removing the group and its binding; this option can be specified more than once")
+        )
+        .after_help(
+            "\nUpdates the following files:\n\n\
+            * clusters/CLUSTER/metadata.json: The cluster's groups, their roles, and their members\n\
+            * clusters/CLUSTER/rbac.yml: A ClusterRoleBinding for each group\n\
+            * clusters/CLUSTER/terraform.tfvars: The aws-iam-authenticator configuration, if the cluster uses it"
+        )
+}
+
 fn admin_install<'a, 'b>() -> App<'a, 'b> {
     SubCommand::with_name("install")
         .about("Configures kubectl for a new cluster and administrator")
@@ -76,7 +375,11 @@ fn admin_install<'a, 'b>() -> App<'a, 'b> {
             "\nThe following files are expected by this command:\n\n\
             * clusters/CLUSTER/k8s-ca.pem: The k8s CA certificate\n\
             * clusters/CLUSTER/NAME.pem: The admin's client certificate\n\
-            * clusters/CLUSTER/NAME-key.pem: The admin's unencrypted private key"
+            * clusters/CLUSTER/NAME-key.pem: The admin's private key\n\n\
+            If the private key is encrypted with a passphrase, kubectl is configured to run `kaws admin credential`, \
+            which prompts for the passphrase, instead of reading the key itself.\n\
+            If the cluster was created with --iam-authenticator, the client certificate and key are not needed.\n\
+            kubectl is configured to get tokens from `aws-iam-authenticator` instead, which must be in your PATH."
         )
 }
 
@@ -95,11 +398,220 @@ fn admin_sign<'a, 'b>() -> App<'a, 'b> {
                 .required(true)
                 .help("The new administrator's name")
         )
+        .arg(
+            Arg::with_name("backdate")
+                .long("backdate")
+                .takes_value(true)
+                .value_name("DURATION")
+                .validator(validate_duration)
+                .help("How long before now new certificates are valid from, to tolerate clock skew, e.g. \"90s\"; defaults to 5m")
+        )
+        .arg(
+            Arg::with_name("cert-expiry")
+                .long("cert-expiry")
+                .takes_value(true)
+                .value_name("DURATION")
+                .validator(validate_duration)
+                .help("How long new certificates are valid for, e.g. \"30d\"; defaults to the cluster's metadata, or 8760h")
+        )
+        .arg(
+            Arg::with_name("cfssl-auth-key-file")
+                .long("cfssl-auth-key-file")
+                .takes_value(true)
+                .value_name("PATH")
+                .help("A file whose first line is the hex-encoded auth key of the cluster's cfssl signer, which authenticates the request to it; without it, the signer's unauthenticated endpoint is used")
+        )
+        .arg(
+            Arg::with_name("days")
+                .long("days")
+                .takes_value(true)
+                .value_name("DAYS")
+                .conflicts_with("cert-expiry")
+                .validator(|days| {
+                    match days.parse::<u32>() {
+                        Ok(days) if days > 0 => Ok(()),
+                        _ => Err("Value must be a whole number of days greater than zero".to_string()),
+                    }
+                })
+                .help("How many days new certificates are valid for, as an alternative to --cert-expiry")
+        )
         .after_help(
             "\nThe following files are expected by this command:\n\n\
             * clusters/CLUSTER/k8s-ca.pem: The CA certificate\n\
             * clusters/CLUSTER/k8s-ca-key-encrypted.base64: The KMS-encrypted CA private key\n\
-            * clusters/CLUSTER/NAME-csr.pem: The requesting administrator's CSR"
+            * clusters/CLUSTER/NAME-csr.pem: The requesting administrator's CSR\n\n\
+            Without --cert-expiry or --days, the certificate is valid for the duration recorded for \
+            \"admin\" under certificate_expiry in clusters/CLUSTER/metadata.json, then in the \
+            repository's certificate-expiry.json, then for the expiry of the \"client\" signing \
+            profile, or for one year.\n\n\
+            If clusters/CLUSTER/metadata.json has a cfssl_signer, the request is sent to that \
+            `cfssl serve` API instead, and the CA's private key isn't needed."
+        )
+}
+
+fn admin_ssh_cert<'a, 'b>() -> App<'a, 'b> {
+    SubCommand::with_name("ssh-cert")
+        .about("Signs a short-lived SSH certificate that logs in to the cluster's servers")
+        .arg(
+            Arg::with_name("cluster")
+                .index(1)
+                .required(true)
+                .help("The name of the cluster the certificate will be valid for")
+        )
+        .arg(
+            Arg::with_name("name")
+                .index(2)
+                .required(true)
+                .help("The administrator's name, recorded as the certificate's key ID")
+        )
+        .arg(
+            Arg::with_name("backdate")
+                .long("backdate")
+                .takes_value(true)
+                .value_name("DURATION")
+                .validator(validate_duration)
+                .help("How long before now new certificates are valid from, to tolerate clock skew, e.g. \"90s\"; defaults to 5m")
+        )
+        .arg(
+            Arg::with_name("public-key")
+                .long("public-key")
+                .takes_value(true)
+                .value_name("PATH")
+                .help("The SSH public key to sign, defaults to ~/.ssh/id_ed25519.pub")
+        )
+        .arg(
+            Arg::with_name("ttl")
+                .long("ttl")
+                .takes_value(true)
+                .value_name("DURATION")
+                .validator(validate_duration)
+                .help("How long the certificate is valid for, e.g. \"30m\"; defaults to 8h")
+        )
+        .after_help(
+            "\nThe following files are expected by this command:\n\n\
+            * clusters/CLUSTER/ssh-user-ca.pub: The SSH user CA public key\n\
+            * clusters/CLUSTER/ssh-user-ca-key-encrypted.base64: The KMS-encrypted SSH user CA private key\n\n\
+            The certificate is written next to the public key, e.g. ~/.ssh/id_ed25519-cert.pub, where ssh \
+            finds it automatically."
+        )
+}
+
+fn audit<'a, 'b>() -> App<'a, 'b> {
+    SubCommand::with_name("audit")
+        .about("Commands for reviewing how a cluster's encrypted secrets are used")
+        .setting(AppSettings::SubcommandRequiredElseHelp)
+        .subcommand(audit_anomalies())
+        .subcommand(audit_usage())
+}
+
+fn audit_anomalies<'a, 'b>() -> App<'a, 'b> {
+    SubCommand::with_name("anomalies")
+        .about("Lists unusual decryptions of a cluster's secrets, such as a CA's key decrypted by a command that signs nothing")
+        .arg(
+            Arg::with_name("cluster")
+                .index(1)
+                .required(true)
+                .help("The cluster whose decryptions should be checked")
+        )
+        .arg(
+            Arg::with_name("since")
+                .long("since")
+                .takes_value(true)
+                .value_name("DURATION")
+                .help("How far back to look, e.g. \"7d\"; defaults to 720h")
+                .validator(validate_duration)
+        )
+        .arg(
+            Arg::with_name("cloudtrail")
+                .long("cloudtrail")
+                .help("Also looks up the cluster's KMS key's Decrypt events in CloudTrail")
+        )
+        .arg(
+            Arg::with_name("kms-key")
+                .short("k")
+                .long("kms-key")
+                .takes_value(true)
+                .requires("cloudtrail")
+                .help("KMS customer master key ID, ARN, or alias to look up in CloudTrail; defaults to the keys recorded in the cluster's PKI manifest")
+        )
+        .arg(
+            Arg::with_name("aws-credentials-path")
+                .long("aws-credentials-path")
+                .takes_value(true)
+                .help("Path to the AWS credentials file, defaults to ~/.aws/credentials")
+        )
+        .arg(
+            Arg::with_name("aws-credentials-profile")
+                .long("aws-credentials-profile")
+                .takes_value(true)
+                .help("Name of the AWS credentials profile to use, defaults to the cluster's recorded profile or \"default\"")
+        )
+}
+
+fn audit_usage<'a, 'b>() -> App<'a, 'b> {
+    SubCommand::with_name("usage")
+        .about("Lists how often each of a cluster's encrypted files was decrypted, by whom, and with which commands")
+        .arg(
+            Arg::with_name("cluster")
+                .index(1)
+                .required(true)
+                .help("The cluster whose decryptions should be listed")
+        )
+        .arg(
+            Arg::with_name("since")
+                .long("since")
+                .takes_value(true)
+                .value_name("DURATION")
+                .help("How far back to look, e.g. \"7d\"; defaults to 720h")
+                .validator(validate_duration)
+        )
+        .arg(
+            Arg::with_name("cloudtrail")
+                .long("cloudtrail")
+                .help("Also looks up the cluster's KMS key's Decrypt events in CloudTrail")
+        )
+        .arg(
+            Arg::with_name("kms-key")
+                .short("k")
+                .long("kms-key")
+                .takes_value(true)
+                .requires("cloudtrail")
+                .help("KMS customer master key ID, ARN, or alias to look up in CloudTrail; defaults to the keys recorded in the cluster's PKI manifest")
+        )
+        .arg(
+            Arg::with_name("aws-credentials-path")
+                .long("aws-credentials-path")
+                .takes_value(true)
+                .help("Path to the AWS credentials file, defaults to ~/.aws/credentials")
+        )
+        .arg(
+            Arg::with_name("aws-credentials-profile")
+                .long("aws-credentials-profile")
+                .takes_value(true)
+                .help("Name of the AWS credentials profile to use, defaults to the cluster's recorded profile or \"default\"")
+        )
+}
+
+fn clean<'a, 'b>() -> App<'a, 'b> {
+    SubCommand::with_name("clean")
+        .about("Removes the Terraform working directories and caches kaws keeps for each cluster")
+        .arg(
+            Arg::with_name("cluster")
+                .index(1)
+                .help("The cluster whose working directory should be removed, defaults to all clusters")
+        )
+        .after_help(
+            "\nkaws runs Terraform for each cluster in its own working directory under \
+            .kaws/terraform, which holds downloaded modules and providers. Removing them is always \
+            safe: the next command that runs Terraform for the cluster recreates its directory. \
+            Cluster state in clusters/CLUSTER is never touched.\n\n\
+            Also removes what read-only commands cached in .kaws/cache about the cluster, or about \
+            every cluster and the installed dependencies without one. The cache is rebuilt as \
+            needed, and is ignored whenever the files it was read from change anyway.\n\n\
+            Without a cluster, also removes the copy of the built-in Terraform module in \
+            .kaws/module and the .terraform directory that older versions of kaws shared between all \
+            clusters, and the scratch files of kaws invocations that were killed before they could \
+            remove them, from $XDG_RUNTIME_DIR/kaws, or $XDG_CACHE_HOME/kaws/run without it."
         )
 }
 
@@ -108,12 +620,29 @@ fn cluster<'a, 'b>() -> App<'a, 'b> {
         .about("Commands for managing a cluster's infrastructure")
         .setting(AppSettings::SubcommandRequiredElseHelp)
         .subcommand(cluster_apply())
+        .subcommand(cluster_apply_spec())
+        .subcommand(cluster_approve())
+        .subcommand(cluster_compare())
         .subcommand(cluster_destroy())
+        .subcommand(cluster_drift_cron())
+        .subcommand(cluster_feature_gates())
+        .subcommand(cluster_gc())
         .subcommand(cluster_generate_pki())
+        .subcommand(cluster_graph())
         .subcommand(cluster_init())
+        .subcommand(cluster_kms_regions())
         .subcommand(cluster_output())
+        .subcommand(cluster_pki())
         .subcommand(cluster_plan())
+        .subcommand(cluster_reencrypt())
         .subcommand(cluster_refresh())
+        .subcommand(cluster_renew_pki())
+        .subcommand(cluster_rollback())
+        .subcommand(cluster_rotate_pki())
+        .subcommand(cluster_rotate_service_account_key())
+        .subcommand(cluster_secret_store())
+        .subcommand(cluster_set())
+        .subcommand(cluster_tunnel())
 }
 
 fn cluster_apply<'a, 'b>() -> App<'a, 'b> {
@@ -136,7 +665,17 @@ fn cluster_apply<'a, 'b>() -> App<'a, 'b> {
             Arg::with_name("aws-credentials-profile")
                 .long("aws-credentials-profile")
                 .takes_value(true)
-                .help("Name of the AWS credentials profile to use, defaults to \"default\"")
+                .help("Name of the AWS credentials profile to use, defaults to the cluster's recorded profile or \"default\"")
+        )
+        .arg(
+            Arg::with_name("override-budget")
+                .long("override-budget")
+                .help("Skips checking the cluster's estimated monthly cost against the max_monthly_cost in its metadata")
+        )
+        .arg(
+            Arg::with_name("skip-ami-check")
+                .long("skip-ami-check")
+                .help("Skips checking with EC2 that the AMI supports the features the instance size requires")
         )
         .arg(
             Arg::with_name("terraform-args")
@@ -148,15 +687,15 @@ fn cluster_apply<'a, 'b>() -> App<'a, 'b> {
         .after_help("\nAny arguments following a literal -- will be passed directly as options to `terraform apply`.")
 }
 
-fn cluster_destroy<'a, 'b>() -> App<'a, 'b> {
-    SubCommand::with_name("destroy")
-        .about("Destroys resources defined by the Terraform plan for the target cluster")
+fn cluster_apply_spec<'a, 'b>() -> App<'a, 'b> {
+    SubCommand::with_name("apply-spec")
+        .about("Converges a cluster on the desired state described in a spec file")
         .setting(AppSettings::TrailingVarArg)
         .arg(
-            Arg::with_name("cluster")
+            Arg::with_name("spec")
                 .index(1)
                 .required(true)
-                .help("The cluster to destroy")
+                .help("Path to the spec file describing the cluster")
         )
         .arg(
             Arg::with_name("aws-credentials-path")
@@ -168,7 +707,144 @@ fn cluster_destroy<'a, 'b>() -> App<'a, 'b> {
             Arg::with_name("aws-credentials-profile")
                 .long("aws-credentials-profile")
                 .takes_value(true)
-                .help("Name of the AWS credentials profile to use, defaults to \"default\"")
+                .help("Name of the AWS credentials profile to use, defaults to the cluster's recorded profile or \"default\"")
+        )
+        .arg(
+            Arg::with_name("dry-run")
+                .long("dry-run")
+                .help("Shows how the cluster differs from the spec without changing anything")
+        )
+        .arg(
+            Arg::with_name("override-budget")
+                .long("override-budget")
+                .help("Skips checking the cluster's estimated monthly cost against the max_monthly_cost in its metadata")
+        )
+        .arg(
+            Arg::with_name("skip-ami-check")
+                .long("skip-ami-check")
+                .help("Skips checking with EC2 that the AMI supports the features the instance size requires")
+        )
+        .arg(
+            Arg::with_name("terraform-args")
+                .index(2)
+                .multiple(true)
+                .hidden(true)
+                .help("Additional arguments to be passed on to `terraform apply`")
+        )
+        .after_help(
+            "\nThe spec is a JSON document, which is also valid YAML, naming the cluster and any of the settings \
+            instance_size, kubernetes_version, masters, nodes, groups, and admins. Settings left out are left as \
+            they are.\n\n\
+            Any arguments following a literal -- will be passed directly as options to `terraform apply`."
+        )
+}
+
+fn cluster_approve<'a, 'b>() -> App<'a, 'b> {
+    SubCommand::with_name("approve")
+        .about("Approves an operation that uses a CA's private key, for clusters that require approvals")
+        .arg(
+            Arg::with_name("cluster")
+                .index(1)
+                .required(true)
+                .help("The cluster the operation will run on")
+        )
+        .arg(
+            Arg::with_name("operation")
+                .index(2)
+                .required(true)
+                .possible_values(&[
+                    "admin-sign",
+                    "apply-spec",
+                    "generate-pki",
+                    "import-ca",
+                    "reencrypt",
+                    "refresh-endpoints",
+                    "renew-pki",
+                    "revoke",
+                    "revoke-node",
+                    "rotate-pki",
+                    "secret-cat",
+                ])
+                .help("The operation to approve")
+        )
+        .arg(
+            Arg::with_name("target")
+                .index(3)
+                .required(true)
+                .help("What the operation acts on: the administrator's name for admin-sign, \"spec\" for apply-spec, the generate-pki subcommand, e.g. \"kubernetes\" or \"all\", the CA for import-ca, \"files\" for reencrypt, \"endpoints\" for refresh-endpoints, \"certificates\" for renew-pki, the serial number for revoke, the instance ID for revoke-node, the phase for rotate-pki, or the secret's name for secret-cat")
+        )
+        .arg(
+            Arg::with_name("approver")
+                .long("approver")
+                .takes_value(true)
+                .value_name("NAME")
+                .required(true)
+                .help("Your name, as listed under approvers in the cluster's metadata")
+        )
+        .arg(
+            Arg::with_name("key")
+                .long("key")
+                .takes_value(true)
+                .value_name("PATH")
+                .help("The SSH private key to sign the approval with, defaults to ~/.ssh/id_ed25519")
+        )
+        .after_help(
+            "\nCreates the following file:\n\n\
+            * clusters/CLUSTER/approvals/OPERATION-TARGET/NAME.sig: The approval\n\n\
+            The approval covers the administrator's certificate signing request, the cluster's spec, its encrypted\n\
+            files, the secret, or otherwise its CA certificates, as they are now. It stops counting if they change, and is removed once the operation runs."
+        )
+}
+
+fn cluster_compare<'a, 'b>() -> App<'a, 'b> {
+    SubCommand::with_name("compare")
+        .about("Displays the configuration settings that differ between two clusters")
+        .arg(
+            Arg::with_name("first")
+                .index(1)
+                .required(true)
+                .help("The cluster to compare against, e.g. \"staging\"")
+        )
+        .arg(
+            Arg::with_name("second")
+                .index(2)
+                .required(true)
+                .help("The cluster to compare, e.g. \"production\"")
+        )
+        .arg(
+            Arg::with_name("all")
+                .long("all")
+                .help("Also displays settings that match and settings that identify each cluster")
+        )
+        .after_help(
+            "\nSettings are read from each cluster's terraform.tfvars and metadata.json files. \
+            Settings that identify a cluster, such as its name, domain, and CIDR, are expected to \
+            differ and are not counted. The command fails if any other setting differs, so it can be \
+            used to check that two clusters match before promoting changes from one to the other."
+        )
+}
+
+fn cluster_destroy<'a, 'b>() -> App<'a, 'b> {
+    SubCommand::with_name("destroy")
+        .about("Destroys resources defined by the Terraform plan for the target cluster")
+        .setting(AppSettings::TrailingVarArg)
+        .arg(
+            Arg::with_name("cluster")
+                .index(1)
+                .required(true)
+                .help("The cluster to destroy")
+        )
+        .arg(
+            Arg::with_name("aws-credentials-path")
+                .long("aws-credentials-path")
+                .takes_value(true)
+                .help("Path to the AWS credentials file, defaults to ~/.aws/credentials")
+        )
+        .arg(
+            Arg::with_name("aws-credentials-profile")
+                .long("aws-credentials-profile")
+                .takes_value(true)
+                .help("Name of the AWS credentials profile to use, defaults to the cluster's recorded profile or \"default\"")
         )
         .arg(
             Arg::with_name("terraform-args")
@@ -180,6 +856,80 @@ fn cluster_destroy<'a, 'b>() -> App<'a, 'b> {
         .after_help("\nAny arguments following a literal -- will be passed directly as options to `terraform destroy`.")
 }
 
+fn cluster_drift_cron<'a, 'b>() -> App<'a, 'b> {
+    SubCommand::with_name("drift-cron")
+        .about("Generates a scheduled CI job that alerts when the target cluster drifts from its plan")
+        .arg(
+            Arg::with_name("cluster")
+                .index(1)
+                .required(true)
+                .help("The cluster to check for drift")
+        )
+        .arg(
+            Arg::with_name("ci")
+                .long("ci")
+                .takes_value(true)
+                .possible_values(&["github", "gitlab"])
+                .help("The CI service to generate a job for, defaults to \"github\"")
+        )
+        .arg(
+            Arg::with_name("schedule")
+                .long("schedule")
+                .takes_value(true)
+                .value_name("CRON")
+                .help("When to check for drift, as a cron expression in UTC, defaults to \"0 6 * * *\"")
+        )
+        .arg(
+            Arg::with_name("terraform-version")
+                .long("terraform-version")
+                .takes_value(true)
+                .value_name("VERSION")
+                .help("The version of Terraform the job installs, defaults to \"0.11.15\"")
+        )
+        .after_help(
+            "\nWrites a CI job definition that runs `kaws cluster plan` on a schedule and posts to a \
+            webhook when the plan has changes or fails, and clusters/CLUSTER/drift-iam-policy.json, \
+            the read-only IAM policy the job's AWS credentials need. The job reads the credentials and \
+            the webhook URL from CI secrets; see the comments at the top of the job definition."
+        )
+}
+
+fn cluster_graph<'a, 'b>() -> App<'a, 'b> {
+    SubCommand::with_name("graph")
+        .about("Draws a cluster's Terraform resources and PKI files, and how they depend on each other")
+        .arg(
+            Arg::with_name("cluster")
+                .index(1)
+                .required(true)
+                .help("The cluster to draw")
+        )
+        .arg(
+            Arg::with_name("format")
+                .short("f")
+                .long("format")
+                .takes_value(true)
+                .possible_values(&["dot", "svg"])
+                .help("The format of the graph: the DOT language, or SVG rendered by Graphviz; defaults to \"dot\"")
+        )
+        .arg(
+            Arg::with_name("output")
+                .short("o")
+                .long("output")
+                .takes_value(true)
+                .value_name("PATH")
+                .help("Where to write the graph, defaults to stdout")
+        )
+        .after_help(
+            "\nThe graph is Terraform's graph of the cluster's resources, plus a box holding the \
+            certificates, public keys, and encrypted files in clusters/CLUSTER, with edges from:\n\n\
+            * Each CA's certificate to the certificates it issued\n\
+            * Each encrypted key to its certificate\n\
+            * The KMS key to each encrypted file\n\
+            * Each file to the Terraform resources that read it, dashed if they only do with some settings\n\n\
+            Rendering SVG needs Graphviz's `dot`."
+        )
+}
+
 fn cluster_init<'a, 'b>() -> App<'a, 'b> {
     SubCommand::with_name("init")
         .about("Initializes all the configuration files for a new cluster")
@@ -205,6 +955,23 @@ fn cluster_init<'a, 'b>() -> App<'a, 'b> {
                 .required(true)
                 .help("EC2 AMI ID to use for all CoreOS instances, e.g. \"ami-1234\"")
         )
+        .arg(
+            Arg::with_name("aws-credentials-path")
+                .long("aws-credentials-path")
+                .takes_value(true)
+                .help("Path to the AWS credentials file, defaults to ~/.aws/credentials")
+        )
+        .arg(
+            Arg::with_name("aws-credentials-profile")
+                .long("aws-credentials-profile")
+                .takes_value(true)
+                .help("Name of the AWS credentials profile to use, defaults to \"default\"")
+        )
+        .arg(
+            Arg::with_name("skip-ami-check")
+                .long("skip-ami-check")
+                .help("Skips checking with EC2 that the AMI supports the features the instance size requires")
+        )
         .arg(
             Arg::with_name("availability-zone")
                 .long("availability-zone")
@@ -212,6 +979,25 @@ fn cluster_init<'a, 'b>() -> App<'a, 'b> {
                 .required(true)
                 .help("Availability Zone for etcd instances and EBS volumes, e.g. \"us-east-1a\"")
         )
+        .arg(
+            Arg::with_name("bastion")
+                .long("bastion")
+                .help("Makes the Kubernetes API private, reachable only through the bastion server with `kaws cluster tunnel`")
+        )
+        .arg(
+            Arg::with_name("bastion-ssh-cidr")
+                .long("bastion-ssh-cidr")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .help("IPv4 network range allowed to SSH to the bastion server, defaults to \"0.0.0.0/0\"; this option can be specified more than once")
+                .validator(|cidr| {
+                    match cidr.parse::<Ipv4Cidr>() {
+                        Ok(_) => Ok(()),
+                        Err(_) => Err("Invalid CIDR provided.".to_string()),
+                    }
+                })
+        )
         .arg(
             Arg::with_name("cidr")
                 .short("C")
@@ -219,33 +1005,14 @@ fn cluster_init<'a, 'b>() -> App<'a, 'b> {
                 .takes_value(true)
                 .required(true)
                 .help("IPv4 network range of the subnet where Kubernetes nodes will run, e.g. \"10.0.2.0/24\"")
-                .validator(|cidr| {
-                    let cidr: Ipv4Cidr = match cidr.parse() {
-                        Ok(cidr) => cidr,
-                        Err(_) => return Err("Invalid CIDR provided.".to_string()),
-                    };
-
-                    let vpc_cidr: Ipv4Cidr = "10.0.0.0/16".parse().unwrap();
-                    let elb_cidr: Ipv4Cidr = "10.0.0.0/24".parse().unwrap();
-                    let etcd_cidr: Ipv4Cidr = "10.0.1.0/24".parse().unwrap();
-
-                    match cidr.subset_cmp(&vpc_cidr) {
-                        Some(Ordering::Less) => {}
-                        _ => return Err("Provided CIDR must be a subset of 10.0.0.0/16.".to_string()),
-                    }
-
-                    match cidr.subset_cmp(&elb_cidr) {
-                        Some(_) => return Err("Provided CIDR cannot overlap with 10.0.0.0/24, which is used for ELBs.".to_string()),
-                        None => {}
-                    }
-
-                    match cidr.subset_cmp(&etcd_cidr) {
-                        Some(_) => return Err("Provided CIDR cannot overlap with 10.0.1.0/24, which is used for etcd.".to_string()),
-                        None => {}
-                    }
-
-                    Ok(())
-                })
+                .validator(validate_subnet_cidr)
+        )
+        .arg(
+            Arg::with_name("cloud-provider")
+                .long("cloud-provider")
+                .takes_value(true)
+                .possible_values(&["aws", "azure", "gcp"])
+                .help("The cloud the cluster runs in, defaults to \"aws\", the only one kaws can manage so far")
         )
         .arg(
             Arg::with_name("domain")
@@ -255,11 +1022,24 @@ fn cluster_init<'a, 'b>() -> App<'a, 'b> {
                 .required(true)
                 .help("The base domain name for the cluster, e.g. \"example.com\"")
         )
+        .arg(
+            Arg::with_name("etcd-member-certificates")
+                .long("etcd-member-certificates")
+                .help("Issues each etcd member server and peer certificates of its own, naming only its DNS name and IP address, instead of sharing them")
+        )
+        .arg(
+            Arg::with_name("etcd-volume-size")
+                .long("etcd-volume-size")
+                .takes_value(true)
+                .value_name("SIZE")
+                .validator(validate_volume_size)
+                .help("The size of each etcd member's EBS volume in whole gibibytes, e.g. \"20Gi\"; defaults to 10Gi")
+        )
         .arg(
             Arg::with_name("masters-max-size")
                 .long("masters-max-size")
                 .takes_value(true)
-                .required(true)
+                .required_unless("preset")
                 .help(
                     "The maximum number of EC2 instances the Kubernetes masters may autoscale to"
                 )
@@ -268,16 +1048,45 @@ fn cluster_init<'a, 'b>() -> App<'a, 'b> {
             Arg::with_name("masters-min-size")
                 .long("masters-min-size")
                 .takes_value(true)
-                .required(true)
+                .required_unless("preset")
                 .help(
                     "The minimum number of EC2 instances the Kubernetes masters may autoscale to"
                 )
         )
+        .arg(
+            Arg::with_name("max-monthly-cost")
+                .long("max-monthly-cost")
+                .takes_value(true)
+                .value_name("DOLLARS")
+                .help("Makes `kaws cluster plan` and `apply` refuse to run when the cluster's EC2 instances would cost more than this many US dollars per month")
+                .validator(|dollars| {
+                    match dollars.parse::<f64>() {
+                        Ok(dollars) if dollars > 0.0 => Ok(()),
+                        _ => Err("Value must be a positive number of dollars".to_string()),
+                    }
+                })
+        )
+        .arg(
+            Arg::with_name("node-placement")
+                .long("node-placement")
+                .takes_value(true)
+                .possible_values(&NODE_PLACEMENTS)
+                .help("Strategy of a placement group to launch Kubernetes nodes in")
+        )
+        .arg(
+            Arg::with_name("node-subnet")
+                .long("node-subnet")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .validator(validate_subnet_cidr)
+                .help("IPv4 network range of a subnet to run Kubernetes nodes in instead of the --cidr subnet, e.g. \"10.0.3.0/24\"; this option can be specified more than once")
+        )
         .arg(
             Arg::with_name("nodes-max-size")
                 .long("nodes-max-size")
                 .takes_value(true)
-                .required(true)
+                .required_unless("preset")
                 .help(
                     "The maximum number of EC2 instances the Kubernetes nodes may autoscale to"
                 )
@@ -286,11 +1095,18 @@ fn cluster_init<'a, 'b>() -> App<'a, 'b> {
             Arg::with_name("nodes-min-size")
                 .long("nodes-min-size")
                 .takes_value(true)
-                .required(true)
+                .required_unless("preset")
                 .help(
                     "The minimum number of EC2 instances the Kubernetes nodes may autoscale to"
                 )
         )
+        .arg(
+            Arg::with_name("preset")
+                .long("preset")
+                .takes_value(true)
+                .value_name("NAME")
+                .help("Fills in the instance size, autoscaling ranges, and hardening options not given from a preset: \"dev\", \"small-prod\", \"ha-prod\", or one in the repository's presets directory")
+        )
         .arg(
             Arg::with_name("region")
                 .short("r")
@@ -310,11 +1126,34 @@ fn cluster_init<'a, 'b>() -> App<'a, 'b> {
                 .help("An IAM user name who will have access to cluster PKI secrets, e.g. \"alice\"; this option can be specified more than once")
         )
         .arg(
-            Arg::with_name("size")
+            Arg::with_name("iam-authenticator")
+                .long("iam-authenticator")
+                .help("Runs aws-iam-authenticator on the masters so administrators can authenticate with their IAM credentials instead of client certificates")
+        )
+        .arg(
+            Arg::with_name("iam-user-group")
+                .long("iam-user-group")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .validator(validate_key_value)
+                .help("Adds an IAM user to a Kubernetes group, e.g. \"alice=ops\"; this option can be specified more than once")
+        )
+        .arg(
+            Arg::with_name("group-role")
+                .long("group-role")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .validator(validate_key_value)
+                .help("Binds a Kubernetes group to a cluster role, e.g. \"ops=edit\"; this option can be specified more than once")
+        )
+        .arg(
+            Arg::with_name("instance-size")
                 .short("s")
                 .long("instance-size")
                 .takes_value(true)
-                .required(true)
+                .required_unless("preset")
                 .help("EC2 instance size to use for all instances, e.g. \"m3.medium\"")
         )
         .arg(
@@ -323,9 +1162,22 @@ fn cluster_init<'a, 'b>() -> App<'a, 'b> {
                 .long("ssh-key")
                 .takes_value(true)
                 .multiple(true)
-                .required(true)
                 .number_of_values(1)
-                .help("SSH public key to add to ~/.ssh/authorized_keys on each server; this option can be specified more than once")
+                .help("SSH public key to add to ~/.ssh/authorized_keys on each server, in addition to SSH certificates; this option can be specified more than once")
+        )
+        .arg(
+            Arg::with_name("key-algo")
+                .long("key-algo")
+                .takes_value(true)
+                .possible_values(&["ecdsa", "rsa"])
+                .help("Algorithm of the private keys Terraform generates for the cluster's PKI, defaults to \"rsa\"")
+        )
+        .arg(
+            Arg::with_name("key-size")
+                .long("key-size")
+                .takes_value(true)
+                .possible_values(&["256", "384", "2048", "3072", "4096"])
+                .help("Size in bits of the private keys Terraform generates for the cluster's PKI: 256 (P-256) or 384 (P-384) for ecdsa, and 2048, 3072, or 4096 for rsa; defaults to 256 for ecdsa and 2048 for rsa")
         )
         .arg(
             Arg::with_name("k8s-version")
@@ -342,12 +1194,27 @@ fn cluster_init<'a, 'b>() -> App<'a, 'b> {
                     }
 
                     if version >= "1.7" {
-                        return Ok(());
+                        Ok(())
                     } else {
-                        return Err("This version of kaws supports only Kubernetes 1.7.0 or greater".to_string());
+                        Err("This version of kaws supports only Kubernetes 1.7.0 or greater".to_string())
                     }
                 })
         )
+        .arg(
+            Arg::with_name("scrub-credentials")
+                .long("scrub-credentials")
+                .help("Has servers remove the credentials they decrypted at boot when they shut down for good, e.g. when their instances are terminated, rather than only when they reboot")
+        )
+        .arg(
+            Arg::with_name("tls-bootstrapping")
+                .long("tls-bootstrapping")
+                .help("Has each kubelet request its own client certificate with a bootstrap token instead of sharing the nodes' certificate; requires Kubernetes 1.7 or later")
+        )
+        .arg(
+            Arg::with_name("wireguard")
+                .long("wireguard")
+                .help("Encrypts pod network traffic between servers with WireGuard; requires an AMI whose kernel includes WireGuard")
+        )
         .arg(
             Arg::with_name("zone-id")
                 .short("z")
@@ -358,186 +1225,2230 @@ fn cluster_init<'a, 'b>() -> App<'a, 'b> {
         )
 }
 
-fn cluster_generate_pki<'a, 'b>() -> App<'a, 'b> {
-    SubCommand::with_name("generate-pki")
-        .about("Generates public key infrastructure for a cluster")
+fn cluster_feature_gates<'a, 'b>() -> App<'a, 'b> {
+    SubCommand::with_name("feature-gates")
+        .about("Lists, enables, and disables a cluster's Kubernetes feature gates")
         .setting(AppSettings::SubcommandRequiredElseHelp)
-        .subcommand(cluster_generate_pki_all())
-        .subcommand(cluster_generate_pki_etcd())
-        .subcommand(cluster_generate_pki_etcd_peer())
-        .subcommand(cluster_generate_pki_kubernetes())
+        .subcommand(cluster_feature_gates_disable())
+        .subcommand(cluster_feature_gates_enable())
+        .subcommand(cluster_feature_gates_list())
 }
 
-fn cluster_generate_pki_all<'a, 'b>() -> App<'a, 'b> {
-    SubCommand::with_name("all")
-        .about("Generates all necessary public key infrastructure for a new cluster")
+fn cluster_feature_gates_disable<'a, 'b>() -> App<'a, 'b> {
+    SubCommand::with_name("disable")
+        .about("Disables a feature gate for a cluster's API server, controller manager, and kubelets")
         .arg(
             Arg::with_name("cluster")
                 .index(1)
                 .required(true)
-                .help("The cluster to generate PKI assets for")
-        )
-        .arg(
-            Arg::with_name("domain")
-                .short("d")
-                .long("domain")
-                .takes_value(true)
-                .required(true)
-                .help("The base domain name for the cluster, e.g. \"example.com\"")
+                .help("The cluster whose feature gate should be disabled")
         )
         .arg(
-            Arg::with_name("kms-key")
-                .short("k")
-                .long("kms-key")
-                .takes_value(true)
+            Arg::with_name("gate")
+                .index(2)
                 .required(true)
-                .help("KMS customer master key ID, e.g. \"12345678-1234-1234-1234-123456789012\"")
+                .help("The feature gate to disable, e.g. \"PodPriority\"")
         )
-        .arg(
-            Arg::with_name("region")
-                .short("r")
-                .long("region")
-                .takes_value(true)
-                .required(true)
-                .help("AWS Region where the KMS key lives, e.g. \"us-east-1\"")
+        .after_help(
+            "\nThe gate is set to false in the --feature-gates flag of every component in \
+            clusters/CLUSTER/metadata.json, even if it's off by default, and the cluster is marked \
+            as needing its masters and nodes replaced."
         )
 }
 
-fn cluster_generate_pki_etcd<'a, 'b>() -> App<'a, 'b> {
-    SubCommand::with_name("etcd")
-        .about("Generates public key infrastructure for etcd's client API")
+fn cluster_feature_gates_enable<'a, 'b>() -> App<'a, 'b> {
+    SubCommand::with_name("enable")
+        .about("Enables a feature gate for a cluster's API server, controller manager, and kubelets")
         .arg(
             Arg::with_name("cluster")
                 .index(1)
                 .required(true)
-                .help("The cluster to generate PKI assets for")
+                .help("The cluster whose feature gate should be enabled")
         )
         .arg(
-            Arg::with_name("subject")
+            Arg::with_name("gate")
                 .index(2)
                 .required(true)
-                .possible_values(&["ca", "client", "server"])
-                .help("The subject to generate PKI assets for")
+                .help("The feature gate to enable, e.g. \"PodPriority\"")
         )
-        .arg(
-            Arg::with_name("kms-key")
-                .short("k")
-                .long("kms-key")
-                .takes_value(true)
-                .required(true)
-                .help("KMS customer master key ID, e.g. \"12345678-1234-1234-1234-123456789012\"")
+        .after_help(
+            "\nThe gate is set to true in the --feature-gates flag of every component in \
+            clusters/CLUSTER/metadata.json, and the cluster is marked as needing its masters and \
+            nodes replaced."
         )
+}
+
+fn cluster_feature_gates_list<'a, 'b>() -> App<'a, 'b> {
+    SubCommand::with_name("list")
+        .about("Lists the feature gates of a cluster's version of Kubernetes and whether each is set")
         .arg(
-            Arg::with_name("region")
-                .short("r")
-                .long("region")
-                .takes_value(true)
+            Arg::with_name("cluster")
+                .index(1)
                 .required(true)
-                .help("AWS Region where the KMS key lives, e.g. \"us-east-1\"")
+                .help("The cluster whose feature gates should be listed")
         )
 }
 
-fn cluster_generate_pki_etcd_peer<'a, 'b>() -> App<'a, 'b> {
-    SubCommand::with_name("etcd-peer")
-        .about("Generates public key infrastructure for etcd's peer API")
+fn cluster_gc<'a, 'b>() -> App<'a, 'b> {
+    SubCommand::with_name("gc")
+        .about("Removes files a cluster no longer needs, such as expired or revoked administrators' credentials")
         .arg(
             Arg::with_name("cluster")
                 .index(1)
                 .required(true)
-                .help("The cluster to generate PKI assets for")
+                .help("The cluster whose files should be cleaned up")
         )
         .arg(
-            Arg::with_name("subject")
+            Arg::with_name("aws-credentials-path")
+                .long("aws-credentials-path")
+                .takes_value(true)
+                .help("Path to the AWS credentials file, defaults to ~/.aws/credentials")
+        )
+        .arg(
+            Arg::with_name("aws-credentials-profile")
+                .long("aws-credentials-profile")
+                .takes_value(true)
+                .help("Name of the AWS credentials profile to use, defaults to \"default\"")
+        )
+        .after_help(
+            "\nLists the files in clusters/CLUSTER that nothing uses any more, and removes them once \
+            confirmed:\n\n\
+            * Administrators' certificates, requests, keys, and exported credentials, once the certificate has expired or \
+            been revoked\n\
+            * etcd certificates and keys for the kind of etcd certificates the cluster doesn't use\n\
+            * Unencrypted CA keys, and unencrypted copies of encrypted keys\n\
+            * Serial number files left by older versions of kaws\n\n\
+            Revoked certificates are read from the Kubernetes CA's revocation list, if it has one, \
+            which is decrypted with KMS if an older version of kaws kept it encrypted."
+        )
+}
+
+fn cluster_generate_pki<'a, 'b>() -> App<'a, 'b> {
+    SubCommand::with_name("generate-pki")
+        .about("Generates public key infrastructure for a cluster")
+        .setting(AppSettings::SubcommandRequiredElseHelp)
+        .subcommand(cluster_generate_pki_all())
+        .subcommand(cluster_generate_pki_bootstrap_token())
+        .subcommand(cluster_generate_pki_etcd())
+        .subcommand(cluster_generate_pki_etcd_peer())
+        .subcommand(cluster_generate_pki_front_proxy())
+        .subcommand(cluster_pki_import_ca())
+        .subcommand(cluster_generate_pki_ingress())
+        .subcommand(cluster_generate_pki_kubernetes())
+        .subcommand(cluster_generate_pki_service_account())
+        .subcommand(cluster_generate_pki_ssh())
+        .subcommand(cluster_generate_pki_userdata_signing())
+}
+
+fn cluster_generate_pki_all<'a, 'b>() -> App<'a, 'b> {
+    SubCommand::with_name("all")
+        .about("Generates all necessary public key infrastructure for a new cluster")
+        .arg(
+            Arg::with_name("cluster")
+                .index(1)
+                .required(true)
+                .help("The cluster to generate PKI assets for")
+        )
+        .arg(
+            Arg::with_name("apiserver-san")
+                .long("apiserver-san")
+                .takes_value(true)
+                .value_name("SAN")
+                .multiple(true)
+                .number_of_values(1)
+                .validator(validate_san)
+                .help("An extra DNS name or IP address for the Kubernetes API server's certificate, in addition to those in the cluster's metadata; this option can be specified more than once")
+        )
+        .arg(
+            Arg::with_name("backdate")
+                .long("backdate")
+                .takes_value(true)
+                .value_name("DURATION")
+                .validator(validate_duration)
+                .help("How long before now new certificates are valid from, to tolerate clock skew, e.g. \"90s\"; defaults to 5m")
+        )
+        .arg(
+            Arg::with_name("ca-expiry")
+                .long("ca-expiry")
+                .takes_value(true)
+                .value_name("DURATION")
+                .validator(validate_duration)
+                .help("How long new CA certificates are valid for, e.g. \"3650d\"; defaults to the cluster's metadata, or 43800h")
+        )
+        .arg(
+            Arg::with_name("cert-expiry")
+                .long("cert-expiry")
+                .takes_value(true)
+                .value_name("DURATION")
+                .validator(validate_duration)
+                .help("How long new certificates are valid for, e.g. \"30d\"; defaults to the cluster's metadata, or 8760h")
+        )
+        .arg(
+            Arg::with_name("domain")
+                .short("d")
+                .long("domain")
+                .takes_value(true)
+                .required(true)
+                .help("The base domain name for the cluster, e.g. \"example.com\"")
+        )
+        .arg(
+            Arg::with_name("force")
+                .long("force")
+                .help("Replaces existing CAs and keys, after confirming, instead of refusing to")
+        )
+        .arg(
+            Arg::with_name("key-algo")
+                .long("key-algo")
+                .takes_value(true)
+                .possible_values(&["ecdsa", "rsa"])
+                .help("Algorithm of the new private keys, defaults to \"rsa\"")
+        )
+        .arg(
+            Arg::with_name("key-size")
+                .long("key-size")
+                .takes_value(true)
+                .possible_values(&["256", "384", "2048", "3072", "4096"])
+                .help("Size in bits of the new private keys: 256 (P-256) or 384 (P-384) for ecdsa, and 2048, 3072, or 4096 for rsa; defaults to 256 for ecdsa and 2048 for rsa")
+        )
+        .arg(
+            Arg::with_name("kms-key")
+                .short("k")
+                .long("kms-key")
+                .takes_value(true)
+                .required(true)
+                .help("KMS customer master key ID, ARN, or alias, e.g. \"12345678-1234-1234-1234-123456789012\" or \"alias/kaws-production\"")
+        )
+        .arg(
+            Arg::with_name("subject-fields")
+                .long("subject-fields")
+                .takes_value(true)
+                .value_name("FIELDS")
+                .validator(validate_subject_fields)
+                .help("Fields added to the subjects of new certificates, e.g. \"C=US,ST=California,L=San Francisco,O=Example Inc.,OU=Platform\"; each defaults to the certificate_subject in the cluster's metadata")
+        )
+        .arg(
+            Arg::with_name("region")
+                .short("r")
+                .long("region")
+                .takes_value(true)
+                .required(true)
+                .help("AWS Region where the KMS key lives, e.g. \"us-east-1\"")
+        )
+}
+
+fn cluster_generate_pki_bootstrap_token<'a, 'b>() -> App<'a, 'b> {
+    SubCommand::with_name("bootstrap-token")
+        .about("Generates the token kubelets request their own client certificates with")
+        .arg(
+            Arg::with_name("cluster")
+                .index(1)
+                .required(true)
+                .help("The cluster to generate PKI assets for")
+        )
+        .arg(
+            Arg::with_name("force")
+                .long("force")
+                .help("Replaces existing CAs and keys, after confirming, instead of refusing to")
+        )
+        .arg(
+            Arg::with_name("kms-key")
+                .short("k")
+                .long("kms-key")
+                .takes_value(true)
+                .required(true)
+                .help("KMS customer master key ID, ARN, or alias, e.g. \"12345678-1234-1234-1234-123456789012\" or \"alias/kaws-production\"")
+        )
+        .arg(
+            Arg::with_name("region")
+                .short("r")
+                .long("region")
+                .takes_value(true)
+                .required(true)
+                .help("AWS Region where the KMS key lives, e.g. \"us-east-1\"")
+        )
+        .after_help(
+            "\nCreates the following files:\n\n\
+            * clusters/CLUSTER/bootstrap-token-encrypted.base64: The KMS-encrypted token, used by clusters with TLS bootstrapping\n\n\
+            Running this command again with --force replaces the token. Kubelets that already have \
+            their own certificates keep working, but nodes launched before the next `kaws cluster apply` \
+            can't join the cluster."
+        )
+}
+
+fn cluster_generate_pki_etcd<'a, 'b>() -> App<'a, 'b> {
+    SubCommand::with_name("etcd")
+        .about("Generates public key infrastructure for etcd's client API")
+        .arg(
+            Arg::with_name("cluster")
+                .index(1)
+                .required(true)
+                .help("The cluster to generate PKI assets for")
+        )
+        .arg(
+            Arg::with_name("subject")
+                .index(2)
+                .required(true)
+                .possible_values(&["ca", "client", "server"])
+                .help("The subject to generate PKI assets for")
+        )
+        .arg(
+            Arg::with_name("backdate")
+                .long("backdate")
+                .takes_value(true)
+                .value_name("DURATION")
+                .validator(validate_duration)
+                .help("How long before now new certificates are valid from, to tolerate clock skew, e.g. \"90s\"; defaults to 5m")
+        )
+        .arg(
+            Arg::with_name("ca-expiry")
+                .long("ca-expiry")
+                .takes_value(true)
+                .value_name("DURATION")
+                .validator(validate_duration)
+                .help("How long new CA certificates are valid for, e.g. \"3650d\"; defaults to the cluster's metadata, or 43800h")
+        )
+        .arg(
+            Arg::with_name("cert-expiry")
+                .long("cert-expiry")
+                .takes_value(true)
+                .value_name("DURATION")
+                .validator(validate_duration)
+                .help("How long new certificates are valid for, e.g. \"30d\"; defaults to the cluster's metadata, or 8760h")
+        )
+        .arg(
+            Arg::with_name("force")
+                .long("force")
+                .help("Replaces existing CAs and keys, after confirming, instead of refusing to")
+        )
+        .arg(
+            Arg::with_name("intermediate-cert")
+                .long("intermediate-cert")
+                .takes_value(true)
+                .value_name("PATH")
+                .requires("root-cert")
+                .conflicts_with("intermediate-csr")
+                .help("With the \"ca\" subject, uses the certificate at this path, signed by an external root CA, as an intermediate CA instead of generating a self-signed one")
+        )
+        .arg(
+            Arg::with_name("intermediate-csr")
+                .long("intermediate-csr")
+                .help("With the \"ca\" subject, generates the CA's private key and a certificate signing request for an external root CA to sign, instead of a self-signed CA")
+        )
+        .arg(
+            Arg::with_name("intermediate-key")
+                .long("intermediate-key")
+                .takes_value(true)
+                .value_name("PATH")
+                .requires("intermediate-cert")
+                .help("The intermediate CA's unencrypted private key, if it wasn't generated with --intermediate-csr")
+        )
+        .arg(
+            Arg::with_name("key-algo")
+                .long("key-algo")
+                .takes_value(true)
+                .possible_values(&["ecdsa", "rsa"])
+                .help("Algorithm of the new private keys, defaults to \"rsa\"")
+        )
+        .arg(
+            Arg::with_name("key-size")
+                .long("key-size")
+                .takes_value(true)
+                .possible_values(&["256", "384", "2048", "3072", "4096"])
+                .help("Size in bits of the new private keys: 256 (P-256) or 384 (P-384) for ecdsa, and 2048, 3072, or 4096 for rsa; defaults to 256 for ecdsa and 2048 for rsa")
+        )
+        .arg(
+            Arg::with_name("kms-key")
+                .short("k")
+                .long("kms-key")
+                .takes_value(true)
+                .required(true)
+                .help("KMS customer master key ID, ARN, or alias, e.g. \"12345678-1234-1234-1234-123456789012\" or \"alias/kaws-production\"")
+        )
+        .arg(
+            Arg::with_name("region")
+                .short("r")
+                .long("region")
+                .takes_value(true)
+                .required(true)
+                .help("AWS Region where the KMS key lives, e.g. \"us-east-1\"")
+        )
+        .arg(
+            Arg::with_name("subject-fields")
+                .long("subject-fields")
+                .takes_value(true)
+                .value_name("FIELDS")
+                .validator(validate_subject_fields)
+                .help("Fields added to the subjects of new certificates, e.g. \"C=US,ST=California,L=San Francisco,O=Example Inc.,OU=Platform\"; each defaults to the certificate_subject in the cluster's metadata")
+        )
+        .arg(
+            Arg::with_name("root-cert")
+                .long("root-cert")
+                .takes_value(true)
+                .value_name("PATH")
+                .requires("intermediate-cert")
+                .help("The external root CA's certificate, followed by any CAs between it and the intermediate CA")
+        )
+}
+
+fn cluster_generate_pki_etcd_peer<'a, 'b>() -> App<'a, 'b> {
+    SubCommand::with_name("etcd-peer")
+        .about("Generates public key infrastructure for etcd's peer API")
+        .arg(
+            Arg::with_name("cluster")
+                .index(1)
+                .required(true)
+                .help("The cluster to generate PKI assets for")
+        )
+        .arg(
+            Arg::with_name("subject")
                 .index(2)
                 .required(true)
                 .possible_values(&["ca", "peer"])
                 .help("The subject to generate PKI assets for")
         )
+        .arg(
+            Arg::with_name("backdate")
+                .long("backdate")
+                .takes_value(true)
+                .value_name("DURATION")
+                .validator(validate_duration)
+                .help("How long before now new certificates are valid from, to tolerate clock skew, e.g. \"90s\"; defaults to 5m")
+        )
+        .arg(
+            Arg::with_name("ca-expiry")
+                .long("ca-expiry")
+                .takes_value(true)
+                .value_name("DURATION")
+                .validator(validate_duration)
+                .help("How long new CA certificates are valid for, e.g. \"3650d\"; defaults to the cluster's metadata, or 43800h")
+        )
+        .arg(
+            Arg::with_name("cert-expiry")
+                .long("cert-expiry")
+                .takes_value(true)
+                .value_name("DURATION")
+                .validator(validate_duration)
+                .help("How long new certificates are valid for, e.g. \"30d\"; defaults to the cluster's metadata, or 8760h")
+        )
+        .arg(
+            Arg::with_name("force")
+                .long("force")
+                .help("Replaces existing CAs and keys, after confirming, instead of refusing to")
+        )
+        .arg(
+            Arg::with_name("intermediate-cert")
+                .long("intermediate-cert")
+                .takes_value(true)
+                .value_name("PATH")
+                .requires("root-cert")
+                .conflicts_with("intermediate-csr")
+                .help("With the \"ca\" subject, uses the certificate at this path, signed by an external root CA, as an intermediate CA instead of generating a self-signed one")
+        )
+        .arg(
+            Arg::with_name("intermediate-csr")
+                .long("intermediate-csr")
+                .help("With the \"ca\" subject, generates the CA's private key and a certificate signing request for an external root CA to sign, instead of a self-signed CA")
+        )
+        .arg(
+            Arg::with_name("intermediate-key")
+                .long("intermediate-key")
+                .takes_value(true)
+                .value_name("PATH")
+                .requires("intermediate-cert")
+                .help("The intermediate CA's unencrypted private key, if it wasn't generated with --intermediate-csr")
+        )
+        .arg(
+            Arg::with_name("key-algo")
+                .long("key-algo")
+                .takes_value(true)
+                .possible_values(&["ecdsa", "rsa"])
+                .help("Algorithm of the new private keys, defaults to \"rsa\"")
+        )
+        .arg(
+            Arg::with_name("key-size")
+                .long("key-size")
+                .takes_value(true)
+                .possible_values(&["256", "384", "2048", "3072", "4096"])
+                .help("Size in bits of the new private keys: 256 (P-256) or 384 (P-384) for ecdsa, and 2048, 3072, or 4096 for rsa; defaults to 256 for ecdsa and 2048 for rsa")
+        )
+        .arg(
+            Arg::with_name("kms-key")
+                .short("k")
+                .long("kms-key")
+                .takes_value(true)
+                .required(true)
+                .help("KMS customer master key ID, ARN, or alias, e.g. \"12345678-1234-1234-1234-123456789012\" or \"alias/kaws-production\"")
+        )
+        .arg(
+            Arg::with_name("region")
+                .short("r")
+                .long("region")
+                .takes_value(true)
+                .required(true)
+                .help("AWS Region where the KMS key lives, e.g. \"us-east-1\"")
+        )
+        .arg(
+            Arg::with_name("subject-fields")
+                .long("subject-fields")
+                .takes_value(true)
+                .value_name("FIELDS")
+                .validator(validate_subject_fields)
+                .help("Fields added to the subjects of new certificates, e.g. \"C=US,ST=California,L=San Francisco,O=Example Inc.,OU=Platform\"; each defaults to the certificate_subject in the cluster's metadata")
+        )
+        .arg(
+            Arg::with_name("root-cert")
+                .long("root-cert")
+                .takes_value(true)
+                .value_name("PATH")
+                .requires("intermediate-cert")
+                .help("The external root CA's certificate, followed by any CAs between it and the intermediate CA")
+        )
+}
+
+fn cluster_generate_pki_front_proxy<'a, 'b>() -> App<'a, 'b> {
+    SubCommand::with_name("front-proxy")
+        .about("Generates public key infrastructure for the Kubernetes API aggregation layer")
+        .arg(
+            Arg::with_name("cluster")
+                .index(1)
+                .required(true)
+                .help("The cluster to generate PKI assets for")
+        )
+        .arg(
+            Arg::with_name("subject")
+                .index(2)
+                .required(true)
+                .possible_values(&["ca", "client"])
+                .help("The subject to generate PKI assets for")
+        )
+        .arg(
+            Arg::with_name("backdate")
+                .long("backdate")
+                .takes_value(true)
+                .value_name("DURATION")
+                .validator(validate_duration)
+                .help("How long before now new certificates are valid from, to tolerate clock skew, e.g. \"90s\"; defaults to 5m")
+        )
+        .arg(
+            Arg::with_name("ca-expiry")
+                .long("ca-expiry")
+                .takes_value(true)
+                .value_name("DURATION")
+                .validator(validate_duration)
+                .help("How long new CA certificates are valid for, e.g. \"3650d\"; defaults to the cluster's metadata, or 43800h")
+        )
+        .arg(
+            Arg::with_name("cert-expiry")
+                .long("cert-expiry")
+                .takes_value(true)
+                .value_name("DURATION")
+                .validator(validate_duration)
+                .help("How long new certificates are valid for, e.g. \"30d\"; defaults to the cluster's metadata, or 8760h")
+        )
+        .arg(
+            Arg::with_name("force")
+                .long("force")
+                .help("Replaces existing CAs and keys, after confirming, instead of refusing to")
+        )
+        .arg(
+            Arg::with_name("intermediate-cert")
+                .long("intermediate-cert")
+                .takes_value(true)
+                .value_name("PATH")
+                .requires("root-cert")
+                .conflicts_with("intermediate-csr")
+                .help("With the \"ca\" subject, uses the certificate at this path, signed by an external root CA, as an intermediate CA instead of generating a self-signed one")
+        )
+        .arg(
+            Arg::with_name("intermediate-csr")
+                .long("intermediate-csr")
+                .help("With the \"ca\" subject, generates the CA's private key and a certificate signing request for an external root CA to sign, instead of a self-signed CA")
+        )
+        .arg(
+            Arg::with_name("intermediate-key")
+                .long("intermediate-key")
+                .takes_value(true)
+                .value_name("PATH")
+                .requires("intermediate-cert")
+                .help("The intermediate CA's unencrypted private key, if it wasn't generated with --intermediate-csr")
+        )
+        .arg(
+            Arg::with_name("key-algo")
+                .long("key-algo")
+                .takes_value(true)
+                .possible_values(&["ecdsa", "rsa"])
+                .help("Algorithm of the new private keys, defaults to \"rsa\"")
+        )
+        .arg(
+            Arg::with_name("key-size")
+                .long("key-size")
+                .takes_value(true)
+                .possible_values(&["256", "384", "2048", "3072", "4096"])
+                .help("Size in bits of the new private keys: 256 (P-256) or 384 (P-384) for ecdsa, and 2048, 3072, or 4096 for rsa; defaults to 256 for ecdsa and 2048 for rsa")
+        )
+        .arg(
+            Arg::with_name("kms-key")
+                .short("k")
+                .long("kms-key")
+                .takes_value(true)
+                .required(true)
+                .help("KMS customer master key ID, ARN, or alias, e.g. \"12345678-1234-1234-1234-123456789012\" or \"alias/kaws-production\"")
+        )
+        .arg(
+            Arg::with_name("region")
+                .short("r")
+                .long("region")
+                .takes_value(true)
+                .required(true)
+                .help("AWS Region where the KMS key lives, e.g. \"us-east-1\"")
+        )
+        .arg(
+            Arg::with_name("subject-fields")
+                .long("subject-fields")
+                .takes_value(true)
+                .value_name("FIELDS")
+                .validator(validate_subject_fields)
+                .help("Fields added to the subjects of new certificates, e.g. \"C=US,ST=California,L=San Francisco,O=Example Inc.,OU=Platform\"; each defaults to the certificate_subject in the cluster's metadata")
+        )
+        .arg(
+            Arg::with_name("root-cert")
+                .long("root-cert")
+                .takes_value(true)
+                .value_name("PATH")
+                .requires("intermediate-cert")
+                .help("The external root CA's certificate, followed by any CAs between it and the intermediate CA")
+        )
+}
+
+fn cluster_generate_pki_ingress<'a, 'b>() -> App<'a, 'b> {
+    SubCommand::with_name("ingress")
+        .about("Generates a wildcard certificate for an ingress controller in the cluster")
+        .arg(
+            Arg::with_name("cluster")
+                .index(1)
+                .required(true)
+                .help("The cluster to generate PKI assets for")
+        )
+        .arg(
+            Arg::with_name("backdate")
+                .long("backdate")
+                .takes_value(true)
+                .value_name("DURATION")
+                .validator(validate_duration)
+                .help("How long before now new certificates are valid from, to tolerate clock skew, e.g. \"90s\"; defaults to 5m")
+        )
+        .arg(
+            Arg::with_name("cert-expiry")
+                .long("cert-expiry")
+                .takes_value(true)
+                .value_name("DURATION")
+                .validator(validate_duration)
+                .help("How long new certificates are valid for, e.g. \"30d\"; defaults to the cluster's metadata, or 8760h")
+        )
+        .arg(
+            Arg::with_name("domain")
+                .short("d")
+                .long("domain")
+                .takes_value(true)
+                .required_unless("pattern")
+                .help("The base domain name for the cluster, e.g. \"example.com\"")
+        )
+        .arg(
+            Arg::with_name("key-algo")
+                .long("key-algo")
+                .takes_value(true)
+                .possible_values(&["ecdsa", "rsa"])
+                .help("Algorithm of the new private keys, defaults to \"rsa\"")
+        )
+        .arg(
+            Arg::with_name("key-size")
+                .long("key-size")
+                .takes_value(true)
+                .possible_values(&["256", "384", "2048", "3072", "4096"])
+                .help("Size in bits of the new private keys: 256 (P-256) or 384 (P-384) for ecdsa, and 2048, 3072, or 4096 for rsa; defaults to 256 for ecdsa and 2048 for rsa")
+        )
+        .arg(
+            Arg::with_name("kms-key")
+                .short("k")
+                .long("kms-key")
+                .takes_value(true)
+                .required(true)
+                .help("KMS customer master key ID, ARN, or alias, e.g. \"12345678-1234-1234-1234-123456789012\" or \"alias/kaws-production\"")
+        )
+        .arg(
+            Arg::with_name("pattern")
+                .long("pattern")
+                .takes_value(true)
+                .value_name("NAME")
+                .validator(validate_ingress_pattern)
+                .help("The name the certificate is valid for, e.g. \"*.apps.example.com\"; defaults to *.apps.DOMAIN")
+        )
+        .arg(
+            Arg::with_name("region")
+                .short("r")
+                .long("region")
+                .takes_value(true)
+                .required(true)
+                .help("AWS Region where the KMS key lives, e.g. \"us-east-1\"")
+        )
+        .arg(
+            Arg::with_name("subject-fields")
+                .long("subject-fields")
+                .takes_value(true)
+                .value_name("FIELDS")
+                .validator(validate_subject_fields)
+                .help("Fields added to the subjects of new certificates, e.g. \"C=US,ST=California,L=San Francisco,O=Example Inc.,OU=Platform\"; each defaults to the certificate_subject in the cluster's metadata")
+        )
+        .after_help("\nThe certificate is issued by the cluster's Kubernetes CA, which must already exist, and written to clusters/CLUSTER/ingress.pem, with its private key KMS-encrypted in clusters/CLUSTER/ingress-key-encrypted.base64.")
+}
+
+fn cluster_generate_pki_kubernetes<'a, 'b>() -> App<'a, 'b> {
+    SubCommand::with_name("kubernetes")
+        .about("Generates public key infrastructure for Kubernetes")
+        .arg(
+            Arg::with_name("cluster")
+                .index(1)
+                .required(true)
+                .help("The cluster to generate PKI assets for")
+        )
+        .arg(
+            Arg::with_name("subject")
+                .index(2)
+                .required(true)
+                .possible_values(&["ca", "masters", "nodes"])
+                .help("The subject to generate PKI assets for")
+        )
+        .arg(
+            Arg::with_name("apiserver-san")
+                .long("apiserver-san")
+                .takes_value(true)
+                .value_name("SAN")
+                .multiple(true)
+                .number_of_values(1)
+                .validator(validate_san)
+                .help("An extra DNS name or IP address for the Kubernetes API server's certificate, in addition to those in the cluster's metadata; this option can be specified more than once")
+        )
+        .arg(
+            Arg::with_name("backdate")
+                .long("backdate")
+                .takes_value(true)
+                .value_name("DURATION")
+                .validator(validate_duration)
+                .help("How long before now new certificates are valid from, to tolerate clock skew, e.g. \"90s\"; defaults to 5m")
+        )
+        .arg(
+            Arg::with_name("ca-expiry")
+                .long("ca-expiry")
+                .takes_value(true)
+                .value_name("DURATION")
+                .validator(validate_duration)
+                .help("How long new CA certificates are valid for, e.g. \"3650d\"; defaults to the cluster's metadata, or 43800h")
+        )
+        .arg(
+            Arg::with_name("cert-expiry")
+                .long("cert-expiry")
+                .takes_value(true)
+                .value_name("DURATION")
+                .validator(validate_duration)
+                .help("How long new certificates are valid for, e.g. \"30d\"; defaults to the cluster's metadata, or 8760h")
+        )
+        .arg(
+            Arg::with_name("domain")
+                .short("d")
+                .long("domain")
+                .takes_value(true)
+                .required(true)
+                .help("The base domain name for the cluster, e.g. \"example.com\"")
+        )
+        .arg(
+            Arg::with_name("force")
+                .long("force")
+                .help("Replaces existing CAs and keys, after confirming, instead of refusing to")
+        )
+        .arg(
+            Arg::with_name("intermediate-cert")
+                .long("intermediate-cert")
+                .takes_value(true)
+                .value_name("PATH")
+                .requires("root-cert")
+                .conflicts_with("intermediate-csr")
+                .help("With the \"ca\" subject, uses the certificate at this path, signed by an external root CA, as an intermediate CA instead of generating a self-signed one")
+        )
+        .arg(
+            Arg::with_name("intermediate-csr")
+                .long("intermediate-csr")
+                .help("With the \"ca\" subject, generates the CA's private key and a certificate signing request for an external root CA to sign, instead of a self-signed CA")
+        )
+        .arg(
+            Arg::with_name("intermediate-key")
+                .long("intermediate-key")
+                .takes_value(true)
+                .value_name("PATH")
+                .requires("intermediate-cert")
+                .help("The intermediate CA's unencrypted private key, if it wasn't generated with --intermediate-csr")
+        )
+        .arg(
+            Arg::with_name("key-algo")
+                .long("key-algo")
+                .takes_value(true)
+                .possible_values(&["ecdsa", "rsa"])
+                .help("Algorithm of the new private keys, defaults to \"rsa\"")
+        )
+        .arg(
+            Arg::with_name("key-size")
+                .long("key-size")
+                .takes_value(true)
+                .possible_values(&["256", "384", "2048", "3072", "4096"])
+                .help("Size in bits of the new private keys: 256 (P-256) or 384 (P-384) for ecdsa, and 2048, 3072, or 4096 for rsa; defaults to 256 for ecdsa and 2048 for rsa")
+        )
+        .arg(
+            Arg::with_name("kms-key")
+                .short("k")
+                .long("kms-key")
+                .takes_value(true)
+                .required(true)
+                .help("KMS customer master key ID, ARN, or alias, e.g. \"12345678-1234-1234-1234-123456789012\" or \"alias/kaws-production\"")
+        )
+        .arg(
+            Arg::with_name("region")
+                .short("r")
+                .long("region")
+                .takes_value(true)
+                .required(true)
+                .help("AWS Region where the KMS key lives, e.g. \"us-east-1\"")
+        )
+        .arg(
+            Arg::with_name("subject-fields")
+                .long("subject-fields")
+                .takes_value(true)
+                .value_name("FIELDS")
+                .validator(validate_subject_fields)
+                .help("Fields added to the subjects of new certificates, e.g. \"C=US,ST=California,L=San Francisco,O=Example Inc.,OU=Platform\"; each defaults to the certificate_subject in the cluster's metadata")
+        )
+        .arg(
+            Arg::with_name("root-cert")
+                .long("root-cert")
+                .takes_value(true)
+                .value_name("PATH")
+                .requires("intermediate-cert")
+                .help("The external root CA's certificate, followed by any CAs between it and the intermediate CA")
+        )
+}
+
+fn cluster_generate_pki_service_account<'a, 'b>() -> App<'a, 'b> {
+    SubCommand::with_name("service-account")
+        .about("Generates the key pair service account tokens are signed and verified with")
+        .arg(
+            Arg::with_name("cluster")
+                .index(1)
+                .required(true)
+                .help("The cluster to generate PKI assets for")
+        )
+        .arg(
+            Arg::with_name("force")
+                .long("force")
+                .help("Replaces existing CAs and keys, after confirming, instead of refusing to")
+        )
+        .arg(
+            Arg::with_name("key-algo")
+                .long("key-algo")
+                .takes_value(true)
+                .possible_values(&["ecdsa", "rsa"])
+                .help("Algorithm of the new private key, defaults to \"rsa\"")
+        )
+        .arg(
+            Arg::with_name("key-size")
+                .long("key-size")
+                .takes_value(true)
+                .possible_values(&["256", "384", "2048", "3072", "4096"])
+                .help("Size in bits of the new private key: 256 (P-256) or 384 (P-384) for ecdsa, and 2048, 3072, or 4096 for rsa; defaults to 256 for ecdsa and 2048 for rsa")
+        )
+        .arg(
+            Arg::with_name("kms-key")
+                .short("k")
+                .long("kms-key")
+                .takes_value(true)
+                .required(true)
+                .help("KMS customer master key ID, ARN, or alias, e.g. \"12345678-1234-1234-1234-123456789012\" or \"alias/kaws-production\"")
+        )
+        .arg(
+            Arg::with_name("region")
+                .short("r")
+                .long("region")
+                .takes_value(true)
+                .required(true)
+                .help("AWS Region where the KMS key lives, e.g. \"us-east-1\"")
+        )
+        .after_help(
+            "\nCreates the following files:\n\n\
+            * clusters/CLUSTER/service-account.pub: The public keys the API server verifies tokens with\n\
+            * clusters/CLUSTER/service-account-key-encrypted.base64: The KMS-encrypted private key the controller manager signs tokens with\n\n\
+            Running this command again with --force replaces the key pair, invalidating every token \
+            signed with it. Use `kaws cluster rotate-service-account-key` to replace it without doing so."
+        )
+}
+
+fn cluster_generate_pki_ssh<'a, 'b>() -> App<'a, 'b> {
+    SubCommand::with_name("ssh")
+        .about("Generates SSH certificate authorities for logging in to servers")
+        .arg(
+            Arg::with_name("cluster")
+                .index(1)
+                .required(true)
+                .help("The cluster to generate PKI assets for")
+        )
+        .arg(
+            Arg::with_name("force")
+                .long("force")
+                .help("Replaces existing CAs and keys, after confirming, instead of refusing to")
+        )
+        .arg(
+            Arg::with_name("kms-key")
+                .short("k")
+                .long("kms-key")
+                .takes_value(true)
+                .required(true)
+                .help("KMS customer master key ID, ARN, or alias, e.g. \"12345678-1234-1234-1234-123456789012\" or \"alias/kaws-production\"")
+        )
+        .arg(
+            Arg::with_name("region")
+                .short("r")
+                .long("region")
+                .takes_value(true)
+                .required(true)
+                .help("AWS Region where the KMS key lives, e.g. \"us-east-1\"")
+        )
+        .after_help(
+            "\nCreates the following files:\n\n\
+            * clusters/CLUSTER/ssh-user-ca.pub: The CA servers trust to sign user certificates\n\
+            * clusters/CLUSTER/ssh-user-ca-key-encrypted.base64: Its KMS-encrypted private key\n\
+            * clusters/CLUSTER/ssh-host-ca.pub: The CA servers use to sign their own host keys at boot\n\
+            * clusters/CLUSTER/ssh-host-ca-key-encrypted.base64: Its KMS-encrypted private key\n\
+            * clusters/CLUSTER/ssh_known_hosts: A known hosts file that trusts the host CA\n\n\
+            Running this command again with --force replaces both CAs, invalidating every certificate \
+            they signed."
+        )
+}
+
+fn cluster_generate_pki_userdata_signing<'a, 'b>() -> App<'a, 'b> {
+    SubCommand::with_name("userdata-signing")
+        .about("Generates the key pair servers verify their cloud-configs with before running them")
+        .arg(
+            Arg::with_name("cluster")
+                .index(1)
+                .required(true)
+                .help("The cluster to generate PKI assets for")
+        )
+        .arg(
+            Arg::with_name("force")
+                .long("force")
+                .help("Replaces existing CAs and keys, after confirming, instead of refusing to")
+        )
+        .arg(
+            Arg::with_name("key-algo")
+                .long("key-algo")
+                .takes_value(true)
+                .possible_values(&["ecdsa", "rsa"])
+                .help("Algorithm of the new private key, defaults to \"rsa\"")
+        )
+        .arg(
+            Arg::with_name("key-size")
+                .long("key-size")
+                .takes_value(true)
+                .possible_values(&["256", "384", "2048", "3072", "4096"])
+                .help("Size in bits of the new private key: 256 (P-256) or 384 (P-384) for ecdsa, and 2048, 3072, or 4096 for rsa; defaults to 256 for ecdsa and 2048 for rsa")
+        )
+        .arg(
+            Arg::with_name("kms-key")
+                .short("k")
+                .long("kms-key")
+                .takes_value(true)
+                .required(true)
+                .help("KMS customer master key ID, ARN, or alias, e.g. \"12345678-1234-1234-1234-123456789012\" or \"alias/kaws-production\"")
+        )
+        .arg(
+            Arg::with_name("region")
+                .short("r")
+                .long("region")
+                .takes_value(true)
+                .required(true)
+                .help("AWS Region where the KMS key lives, e.g. \"us-east-1\"")
+        )
+        .after_help(
+            "\nCreates the following files:\n\n\
+            * clusters/CLUSTER/userdata-signing.pub: The public key servers verify their cloud-configs with\n\
+            * clusters/CLUSTER/userdata-signing-key-encrypted.base64: The KMS-encrypted private key kaws signs them with\n\n\
+            The public key is also written to terraform.tfvars as kaws_userdata_signing_public_key, \
+            which makes every server check the signature of the cloud-config it downloads from S3 \
+            before running it. After each successful `kaws cluster apply`, kaws signs the \
+            cloud-configs in the Terraform state and uploads the signatures next to them. Applying \
+            the cluster after running this command replaces its servers."
+        )
+}
+
+fn cluster_kms_regions<'a, 'b>() -> App<'a, 'b> {
+    SubCommand::with_name("kms-regions")
+        .about("Keeps copies of a cluster's encrypted files under KMS keys in other regions")
+        .setting(AppSettings::SubcommandRequiredElseHelp)
+        .subcommand(cluster_kms_regions_add())
+        .subcommand(cluster_kms_regions_list())
+        .subcommand(cluster_kms_regions_remove())
+}
+
+fn cluster_kms_regions_add<'a, 'b>() -> App<'a, 'b> {
+    SubCommand::with_name("add")
+        .about("Copies a cluster's encrypted files under a KMS key in another region")
+        .arg(
+            Arg::with_name("cluster")
+                .index(1)
+                .required(true)
+                .help("The cluster whose encrypted files should be copied")
+        )
+        .arg(
+            Arg::with_name("region")
+                .index(2)
+                .required(true)
+                .help("The other AWS Region, e.g. \"us-west-2\"")
+        )
+        .arg(
+            Arg::with_name("kms-key")
+                .short("k")
+                .long("kms-key")
+                .takes_value(true)
+                .required(true)
+                .help("KMS customer master key ID, ARN, or alias in the other region, e.g. \"mrk-1234abcd12ab34cd56ef1234567890ab\" or \"alias/kaws-production\"")
+        )
+        .arg(
+            Arg::with_name("aws-credentials-path")
+                .long("aws-credentials-path")
+                .takes_value(true)
+                .help("Path to the AWS credentials file, defaults to ~/.aws/credentials")
+        )
+        .arg(
+            Arg::with_name("aws-credentials-profile")
+                .long("aws-credentials-profile")
+                .takes_value(true)
+                .help("Name of the AWS credentials profile to use, defaults to the cluster's recorded profile or \"default\"")
+        )
+        .after_help(
+            "\nEach file in clusters/CLUSTER ending in -encrypted.base64 is decrypted in the \
+            cluster's own region and encrypted again under the given key, with the same encryption \
+            context, into clusters/CLUSTER/kms-regions/REGION. The key is recorded in \
+            clusters/CLUSTER/metadata.json, and every command that encrypts the cluster's files \
+            from then on keeps the copies current.\n\n\
+            When KMS in the cluster's region is unreachable, kaws decrypts the copies instead, so a \
+            copy of the repository can still be used from another region. A replica of a \
+            multi-Region key works as well as an unrelated key. Adding a region again replaces its \
+            copies."
+        )
+}
+
+fn cluster_kms_regions_list<'a, 'b>() -> App<'a, 'b> {
+    SubCommand::with_name("list")
+        .about("Lists the regions a cluster's encrypted files have copies in, and their KMS keys")
+        .arg(
+            Arg::with_name("cluster")
+                .index(1)
+                .required(true)
+                .help("The cluster whose regions should be listed")
+        )
+}
+
+fn cluster_kms_regions_remove<'a, 'b>() -> App<'a, 'b> {
+    SubCommand::with_name("remove")
+        .about("Removes the copies of a cluster's encrypted files in another region")
+        .arg(
+            Arg::with_name("cluster")
+                .index(1)
+                .required(true)
+                .help("The cluster whose copies should be removed")
+        )
+        .arg(
+            Arg::with_name("region")
+                .index(2)
+                .required(true)
+                .help("The region whose copies should be removed, e.g. \"us-west-2\"")
+        )
+        .after_help(
+            "\nThe KMS key itself is left alone, so copies in the Git history can still be \
+            decrypted with it until it's disabled or scheduled for deletion."
+        )
+}
+
+fn cluster_output<'a, 'b>() -> App<'a, 'b> {
+    SubCommand::with_name("output")
+        .about("Displays the Terraform outputs for the target cluster")
+        .arg(
+            Arg::with_name("cluster")
+                .index(1)
+                .required(true)
+                .help("The cluster whose plan should be displayed")
+        )
+        .arg(
+            Arg::with_name("output")
+                .index(2)
+                .help("The name of an individual output to display")
+        )
+}
+
+fn cluster_pki<'a, 'b>() -> App<'a, 'b> {
+    SubCommand::with_name("pki")
+        .about("Inspects, verifies, and exports a cluster's public key infrastructure, and refreshes and revokes certificates and nodes")
+        .setting(AppSettings::SubcommandRequiredElseHelp)
+        .subcommand(cluster_pki_export_bundle())
+        .subcommand(cluster_pki_import_ca())
+        .subcommand(cluster_pki_refresh_endpoints())
+        .subcommand(cluster_pki_revoke())
+        .subcommand(cluster_pki_revoke_node())
+        .subcommand(cluster_pki_status())
+        .subcommand(cluster_pki_verify())
+}
+
+fn cluster_pki_export_bundle<'a, 'b>() -> App<'a, 'b> {
+    SubCommand::with_name("export-bundle")
+        .about("Exports a cluster's CA certificates and other public PKI files, without any keys")
+        .arg(
+            Arg::with_name("cluster")
+                .index(1)
+                .required(true)
+                .help("The cluster whose public PKI files should be exported")
+        )
+        .arg(
+            Arg::with_name("output")
+                .short("o")
+                .long("output")
+                .takes_value(true)
+                .value_name("PATH")
+                .help("Where to write the bundle: a gzipped tarball if PATH ends in .tar.gz or .tgz, and a directory otherwise; defaults to CLUSTER-public-pki.tar.gz")
+        )
+        .after_help(
+            "\nThe bundle holds these files from clusters/CLUSTER:\n\n\
+            * Every certificate, including the CA certificates\n\
+            * The SSH CAs' and service account public keys\n\
+            * pki-manifest.json and pki-profiles.json, if they exist\n\n\
+            Private keys, encrypted or not, are never included."
+        )
+}
+
+fn cluster_pki_import_ca<'a, 'b>() -> App<'a, 'b> {
+    SubCommand::with_name("import-ca")
+        .about("Stores an existing CA for kaws to issue the cluster's certificates with")
+        .arg(
+            Arg::with_name("cluster")
+                .index(1)
+                .required(true)
+                .help("The cluster to import the CA for")
+        )
+        .arg(
+            Arg::with_name("ca")
+                .index(2)
+                .required(true)
+                .possible_values(&["etcd", "etcd-peer", "front-proxy", "k8s"])
+                .help("The CA to replace with the imported one")
+        )
+        .arg(
+            Arg::with_name("cert")
+                .long("cert")
+                .takes_value(true)
+                .value_name("PATH")
+                .required(true)
+                .help("The CA's certificate")
+        )
+        .arg(
+            Arg::with_name("force")
+                .long("force")
+                .help("Replaces the CA even if the cluster already has one, after confirming")
+        )
+        .arg(
+            Arg::with_name("key")
+                .long("key")
+                .takes_value(true)
+                .value_name("PATH")
+                .required(true)
+                .help("The CA's unencrypted private key, which is stored encrypted with KMS")
+        )
+        .arg(
+            Arg::with_name("kms-key")
+                .short("k")
+                .long("kms-key")
+                .takes_value(true)
+                .required(true)
+                .help("KMS customer master key ID, ARN, or alias, e.g. \"12345678-1234-1234-1234-123456789012\" or \"alias/kaws-production\"")
+        )
+        .arg(
+            Arg::with_name("region")
+                .short("r")
+                .long("region")
+                .takes_value(true)
+                .required(true)
+                .help("AWS Region where the KMS key lives, e.g. \"us-east-1\"")
+        )
+        .arg(
+            Arg::with_name("root-cert")
+                .long("root-cert")
+                .takes_value(true)
+                .value_name("PATH")
+                .help("The certificate of the root CA the imported CA chains to, followed by any CAs between them, if it isn't self-signed")
+        )
+        .after_help(
+            "\nCreates the following files:\n\n\
+            * clusters/CLUSTER/CA-ca.pem: The CA's certificate\n\
+            * clusters/CLUSTER/CA-ca-key-encrypted.base64: Its KMS-encrypted private key\n\
+            * clusters/CLUSTER/CA-root-ca.pem: The root CA, if --root-cert is given\n\n\
+            The CA must be a CA by its basic constraints, and its key usage, if it has one, must \
+            allow signing certificates. No certificates are issued. Issue them by running `kaws \
+            cluster generate-pki` with any subject but \"ca\", which would replace the imported CA \
+            with a new one.\n\n\
+            The same command is also `kaws cluster generate-pki import-ca`."
+        )
+}
+
+fn cluster_pki_refresh_endpoints<'a, 'b>() -> App<'a, 'b> {
+    SubCommand::with_name("refresh-endpoints")
+        .about("Reissues the server certificates whose endpoints in the Terraform outputs have changed")
+        .arg(
+            Arg::with_name("cluster")
+                .index(1)
+                .required(true)
+                .help("The cluster whose certificates should be refreshed")
+        )
+        .arg(
+            Arg::with_name("apply")
+                .long("apply")
+                .help("Runs `kaws cluster apply` afterwards, so the servers get the reissued certificates")
+        )
+        .arg(
+            Arg::with_name("aws-credentials-path")
+                .long("aws-credentials-path")
+                .takes_value(true)
+                .help("Path to the AWS credentials file, defaults to ~/.aws/credentials")
+        )
+        .arg(
+            Arg::with_name("aws-credentials-profile")
+                .long("aws-credentials-profile")
+                .takes_value(true)
+                .help("Name of the AWS credentials profile to use, defaults to \"default\"")
+        )
+        .arg(
+            Arg::with_name("backdate")
+                .long("backdate")
+                .takes_value(true)
+                .value_name("DURATION")
+                .validator(validate_duration)
+                .help("How long before now new certificates are valid from, to tolerate clock skew, e.g. \"90s\"; defaults to 5m")
+        )
+        .arg(
+            Arg::with_name("cert-expiry")
+                .long("cert-expiry")
+                .takes_value(true)
+                .value_name("DURATION")
+                .validator(validate_duration)
+                .help("How long new certificates are valid for, e.g. \"30d\"; defaults to the cluster's metadata, or 8760h")
+        )
+        .arg(
+            Arg::with_name("domain")
+                .short("d")
+                .long("domain")
+                .takes_value(true)
+                .required(true)
+                .help("The base domain name for the cluster, e.g. \"example.com\"")
+        )
+        .arg(
+            Arg::with_name("key-algo")
+                .long("key-algo")
+                .takes_value(true)
+                .possible_values(&["ecdsa", "rsa"])
+                .help("Algorithm of the new private keys, defaults to \"rsa\"")
+        )
+        .arg(
+            Arg::with_name("key-size")
+                .long("key-size")
+                .takes_value(true)
+                .possible_values(&["256", "384", "2048", "3072", "4096"])
+                .help("Size in bits of the new private keys: 256 (P-256) or 384 (P-384) for ecdsa, and 2048, 3072, or 4096 for rsa; defaults to 256 for ecdsa and 2048 for rsa")
+        )
+        .arg(
+            Arg::with_name("kms-key")
+                .short("k")
+                .long("kms-key")
+                .takes_value(true)
+                .required(true)
+                .help("KMS customer master key ID, ARN, or alias, e.g. \"12345678-1234-1234-1234-123456789012\" or \"alias/kaws-production\"")
+        )
+        .arg(
+            Arg::with_name("region")
+                .short("r")
+                .long("region")
+                .takes_value(true)
+                .required(true)
+                .help("AWS Region where the KMS key lives, e.g. \"us-east-1\"")
+        )
+        .arg(
+            Arg::with_name("subject-fields")
+                .long("subject-fields")
+                .takes_value(true)
+                .value_name("FIELDS")
+                .validator(validate_subject_fields)
+                .help("Fields added to the subjects of new certificates, e.g. \"C=US,ST=California,L=San Francisco,O=Example Inc.,OU=Platform\"; each defaults to the certificate_subject in the cluster's metadata")
+        )
+        .after_help(
+            "\nThe endpoints are read with `kaws cluster output` and compared with the ones recorded \
+            in the cluster's metadata the last time this command ran. Only the certificates whose \
+            SANs include a changed endpoint are reissued:\n\n\
+            * kubernetes_masters_elb_dns_name: k8s-master\n\n\
+            The cluster must have been applied at least once, so the outputs exist."
+        )
+}
+
+fn cluster_pki_revoke<'a, 'b>() -> App<'a, 'b> {
+    SubCommand::with_name("revoke")
+        .about("Adds a certificate to its CA's certificate revocation list, which the servers don't check")
+        .arg(
+            Arg::with_name("cluster")
+                .index(1)
+                .required(true)
+                .help("The cluster whose certificate should be revoked")
+        )
+        .arg(
+            Arg::with_name("serial-number")
+                .index(2)
+                .required(true)
+                .value_name("SERIAL")
+                .help("The decimal serial number of the certificate, as listed by `kaws cluster pki status`")
+                .validator(|serial_number| {
+                    if !serial_number.is_empty() && serial_number.chars().all(|c| c.is_ascii_digit()) {
+                        Ok(())
+                    } else {
+                        Err("Value must be a decimal serial number".to_string())
+                    }
+                })
+        )
+        .arg(
+            Arg::with_name("ca")
+                .long("ca")
+                .takes_value(true)
+                .possible_values(&["etcd", "etcd-peer", "k8s"])
+                .help("The CA that issued the certificate; defaults to the one recorded in the cluster's PKI manifest")
+        )
+        .arg(
+            Arg::with_name("crl-expiry")
+                .long("crl-expiry")
+                .takes_value(true)
+                .value_name("DURATION")
+                .validator(validate_duration)
+                .help("How long the new certificate revocation list is valid for, e.g. \"90d\"; defaults to 720h")
+        )
+        .arg(
+            Arg::with_name("kms-key")
+                .short("k")
+                .long("kms-key")
+                .takes_value(true)
+                .required(true)
+                .help("KMS customer master key ID, ARN, or alias, e.g. \"12345678-1234-1234-1234-123456789012\" or \"alias/kaws-production\"")
+        )
+        .arg(
+            Arg::with_name("region")
+                .short("r")
+                .long("region")
+                .takes_value(true)
+                .required(true)
+                .help("AWS Region where the KMS key lives, e.g. \"us-east-1\"")
+        )
+}
+
+fn cluster_pki_revoke_node<'a, 'b>() -> App<'a, 'b> {
+    SubCommand::with_name("revoke-node")
+        .about("Revokes a compromised node's certificate and replaces the node with a new instance")
+        .arg(
+            Arg::with_name("cluster")
+                .index(1)
+                .required(true)
+                .help("The cluster the node belongs to")
+        )
+        .arg(
+            Arg::with_name("instance-id")
+                .index(2)
+                .required(true)
+                .value_name("INSTANCE_ID")
+                .help("The ID of the node's EC2 instance, e.g. \"i-0123456789abcdef0\"")
+        )
+        .arg(
+            Arg::with_name("aws-credentials-path")
+                .long("aws-credentials-path")
+                .takes_value(true)
+                .help("Path to the AWS credentials file, defaults to ~/.aws/credentials")
+        )
+        .arg(
+            Arg::with_name("aws-credentials-profile")
+                .long("aws-credentials-profile")
+                .takes_value(true)
+                .help("Name of the AWS credentials profile to use, defaults to \"default\"")
+        )
+        .arg(
+            Arg::with_name("backdate")
+                .long("backdate")
+                .takes_value(true)
+                .value_name("DURATION")
+                .validator(validate_duration)
+                .help("How long before now new certificates are valid from, to tolerate clock skew, e.g. \"90s\"; defaults to 5m")
+        )
+        .arg(
+            Arg::with_name("cert-expiry")
+                .long("cert-expiry")
+                .takes_value(true)
+                .value_name("DURATION")
+                .validator(validate_duration)
+                .help("How long new certificates are valid for, e.g. \"30d\"; defaults to the cluster's metadata, or 8760h")
+        )
+        .arg(
+            Arg::with_name("crl-expiry")
+                .long("crl-expiry")
+                .takes_value(true)
+                .value_name("DURATION")
+                .validator(validate_duration)
+                .help("How long the new certificate revocation list is valid for, e.g. \"90d\"; defaults to 720h")
+        )
+        .arg(
+            Arg::with_name("key-algo")
+                .long("key-algo")
+                .takes_value(true)
+                .possible_values(&["ecdsa", "rsa"])
+                .help("Algorithm of the new private keys, defaults to \"rsa\"")
+        )
+        .arg(
+            Arg::with_name("key-size")
+                .long("key-size")
+                .takes_value(true)
+                .possible_values(&["256", "384", "2048", "3072", "4096"])
+                .help("Size in bits of the new private keys: 256 (P-256) or 384 (P-384) for ecdsa, and 2048, 3072, or 4096 for rsa; defaults to 256 for ecdsa and 2048 for rsa")
+        )
+        .arg(
+            Arg::with_name("kms-key")
+                .short("k")
+                .long("kms-key")
+                .takes_value(true)
+                .required(true)
+                .help("KMS customer master key ID, ARN, or alias, e.g. \"12345678-1234-1234-1234-123456789012\" or \"alias/kaws-production\"")
+        )
+        .arg(
+            Arg::with_name("region")
+                .short("r")
+                .long("region")
+                .takes_value(true)
+                .required(true)
+                .help("AWS Region where the KMS key lives, e.g. \"us-east-1\"")
+        )
+        .arg(
+            Arg::with_name("timeout")
+                .long("timeout")
+                .takes_value(true)
+                .value_name("DURATION")
+                .validator(validate_duration)
+                .help("How long to wait for a new node to join the cluster, e.g. \"30m\"; defaults to 15m")
+        )
+        .after_help(
+            "\nThe node's client certificate is added to the Kubernetes CA's certificate \
+            revocation list, the node is cordoned and deleted from the cluster, and its instance is \
+            terminated. The command then waits for the instance the autoscaling group launches in \
+            its place to join the cluster as a ready node.\n\n\
+            With TLS bootstrapping, the certificates the Kubernetes certificates API issued to the \
+            node are revoked. Otherwise every node shares one certificate, which is revoked and \
+            reissued, and the cluster is applied before the instance is terminated; the other \
+            nodes keep the revoked certificate until they are replaced.\n\n\
+            kubectl must be configured for the cluster, as by `kaws admin install`."
+        )
+}
+
+fn cluster_pki_status<'a, 'b>() -> App<'a, 'b> {
+    SubCommand::with_name("status")
+        .about("Lists each of a cluster's certificates with its serial number, subject, SANs, issuer, and expiry")
+        .arg(
+            Arg::with_name("cluster")
+                .index(1)
+                .required(true)
+                .help("The cluster whose certificates should be listed")
+        )
+        .arg(
+            Arg::with_name("expiry-warning-days")
+                .long("expiry-warning-days")
+                .takes_value(true)
+                .value_name("DAYS")
+                .help("Exits with an error if any certificate expires within this many days, defaults to 30")
+                .validator(|days| {
+                    match days.parse::<u32>() {
+                        Ok(_) => Ok(()),
+                        Err(_) => Err("Value must be a whole number of days".to_string()),
+                    }
+                })
+        )
+}
+
+fn cluster_pki_verify<'a, 'b>() -> App<'a, 'b> {
+    SubCommand::with_name("verify")
+        .about("Checks that a cluster's certificates, keys, and encrypted files are consistent with each other")
+        .after_help(
+            "\nRuns these checks and prints the outcome of each:\n\n\
+            * Each certificate chains to the CA that should have issued it\n\
+            * Each private key matches its certificate, or for the service account key, its public key\n\
+            * The API server and etcd certificates have the names they're reached at\n\
+            * Each encrypted file decrypts with KMS\n\n\
+            Run it before `kaws cluster apply` after merging or editing PKI files by hand. Exits with an \
+            error if any check fails."
+        )
+        .arg(
+            Arg::with_name("cluster")
+                .index(1)
+                .required(true)
+                .help("The cluster whose PKI should be verified")
+        )
+        .arg(
+            Arg::with_name("aws-credentials-path")
+                .long("aws-credentials-path")
+                .takes_value(true)
+                .help("Path to the AWS credentials file, defaults to ~/.aws/credentials")
+        )
+        .arg(
+            Arg::with_name("aws-credentials-profile")
+                .long("aws-credentials-profile")
+                .takes_value(true)
+                .help("Name of the AWS credentials profile to use, defaults to the cluster's recorded profile or \"default\"")
+        )
+}
+
+fn cluster_plan<'a, 'b>() -> App<'a, 'b> {
+    SubCommand::with_name("plan")
+        .about("Displays the Terraform plan for the target cluster")
+        .setting(AppSettings::TrailingVarArg)
+        .arg(
+            Arg::with_name("cluster")
+                .index(1)
+                .required(true)
+                .help("The cluster whose plan should be displayed")
+        )
+        .arg(
+            Arg::with_name("aws-credentials-path")
+                .long("aws-credentials-path")
+                .takes_value(true)
+                .help("Path to the AWS credentials file, defaults to ~/.aws/credentials")
+        )
+        .arg(
+            Arg::with_name("aws-credentials-profile")
+                .long("aws-credentials-profile")
+                .takes_value(true)
+                .help("Name of the AWS credentials profile to use, defaults to the cluster's recorded profile or \"default\"")
+        )
+        .arg(
+            Arg::with_name("override-budget")
+                .long("override-budget")
+                .help("Skips checking the cluster's estimated monthly cost against the max_monthly_cost in its metadata")
+        )
+        .arg(
+            Arg::with_name("terraform-args")
+                .index(2)
+                .multiple(true)
+                .hidden(true)
+                .help("Additional arguments to be passed on to `terraform plan`")
+        )
+        .after_help("\nAny arguments following a literal -- will be passed directly as options to `terraform plan`.")
+}
+
+fn cluster_reencrypt<'a, 'b>() -> App<'a, 'b> {
+    SubCommand::with_name("reencrypt")
+        .about("Encrypts every one of a cluster's encrypted files again with another KMS key")
+        .arg(
+            Arg::with_name("cluster")
+                .index(1)
+                .required(true)
+                .help("The cluster whose files should be encrypted again")
+        )
+        .arg(
+            Arg::with_name("aws-credentials-path")
+                .long("aws-credentials-path")
+                .takes_value(true)
+                .help("Path to the AWS credentials file, defaults to ~/.aws/credentials")
+        )
+        .arg(
+            Arg::with_name("aws-credentials-profile")
+                .long("aws-credentials-profile")
+                .takes_value(true)
+                .help("Name of the AWS credentials profile to use, defaults to the cluster's recorded profile or \"default\"")
+        )
+        .arg(
+            Arg::with_name("kms-key")
+                .short("k")
+                .long("kms-key")
+                .takes_value(true)
+                .required(true)
+                .help("The new KMS customer master key ID, ARN, or alias, e.g. \"12345678-1234-1234-1234-123456789012\" or \"alias/kaws-production\"")
+        )
+        .arg(
+            Arg::with_name("region")
+                .short("r")
+                .long("region")
+                .takes_value(true)
+                .required(true)
+                .help("AWS Region where the KMS keys live, e.g. \"us-east-1\"")
+        )
+        .after_help(
+            "\nEach file in clusters/CLUSTER ending in -encrypted.base64 is decrypted with the key \
+            it was encrypted with, and encrypted again with the new key, as are its copies in the \
+            cluster's other KMS regions. Nothing is written unless every file can be decrypted and \
+            encrypted again. The new key is recorded in clusters/CLUSTER/metadata.json, and for \
+            each current certificate, in clusters/CLUSTER/pki-manifest.json.\n\n\
+            The new key's policy must let the cluster's servers decrypt with it. Run \
+            `kaws cluster apply` afterwards so the servers get the files, and only disable the old \
+            key once they have."
+        )
+}
+
+fn cluster_refresh<'a, 'b>() -> App<'a, 'b> {
+    SubCommand::with_name("refresh")
+        .about("Refreshes the Terraform state for the target cluster")
+        .setting(AppSettings::TrailingVarArg)
+        .arg(
+            Arg::with_name("cluster")
+                .index(1)
+                .required(true)
+                .help("The cluster whose plan should be displayed")
+        )
+        .arg(
+            Arg::with_name("aws-credentials-path")
+                .long("aws-credentials-path")
+                .takes_value(true)
+                .help("Path to the AWS credentials file, defaults to ~/.aws/credentials")
+        )
+        .arg(
+            Arg::with_name("aws-credentials-profile")
+                .long("aws-credentials-profile")
+                .takes_value(true)
+                .help("Name of the AWS credentials profile to use, defaults to the cluster's recorded profile or \"default\"")
+        )
+        .arg(
+            Arg::with_name("terraform-args")
+                .index(2)
+                .multiple(true)
+                .hidden(true)
+                .help("Additional arguments to be passed on to `terraform refresh`")
+        )
+        .after_help("\nAny arguments following a literal -- will be passed directly as options to `terraform refresh`.")
+}
+
+fn cluster_renew_pki<'a, 'b>() -> App<'a, 'b> {
+    SubCommand::with_name("renew-pki")
+        .about("Reissues every certificate the cluster's servers use from its existing CAs")
+        .arg(
+            Arg::with_name("cluster")
+                .index(1)
+                .required(true)
+                .help("The cluster whose certificates should be renewed")
+        )
+        .arg(
+            Arg::with_name("apply")
+                .long("apply")
+                .help("Runs `kaws cluster apply` afterwards, so the servers get the reissued certificates")
+        )
+        .arg(
+            Arg::with_name("apiserver-san")
+                .long("apiserver-san")
+                .takes_value(true)
+                .value_name("SAN")
+                .multiple(true)
+                .number_of_values(1)
+                .validator(validate_san)
+                .help("An extra DNS name or IP address for the Kubernetes API server's certificate, in addition to those in the cluster's metadata; this option can be specified more than once")
+        )
+        .arg(
+            Arg::with_name("aws-credentials-path")
+                .long("aws-credentials-path")
+                .takes_value(true)
+                .help("Path to the AWS credentials file, defaults to ~/.aws/credentials")
+        )
+        .arg(
+            Arg::with_name("aws-credentials-profile")
+                .long("aws-credentials-profile")
+                .takes_value(true)
+                .help("Name of the AWS credentials profile to use, defaults to \"default\"")
+        )
+        .arg(
+            Arg::with_name("backdate")
+                .long("backdate")
+                .takes_value(true)
+                .value_name("DURATION")
+                .validator(validate_duration)
+                .help("How long before now new certificates are valid from, to tolerate clock skew, e.g. \"90s\"; defaults to 5m")
+        )
+        .arg(
+            Arg::with_name("cert-expiry")
+                .long("cert-expiry")
+                .takes_value(true)
+                .value_name("DURATION")
+                .validator(validate_duration)
+                .help("How long new certificates are valid for, e.g. \"30d\"; defaults to the cluster's metadata, or 8760h")
+        )
+        .arg(
+            Arg::with_name("domain")
+                .short("d")
+                .long("domain")
+                .takes_value(true)
+                .required(true)
+                .help("The base domain name for the cluster, e.g. \"example.com\"")
+        )
+        .arg(
+            Arg::with_name("key-algo")
+                .long("key-algo")
+                .takes_value(true)
+                .possible_values(&["ecdsa", "rsa"])
+                .help("Algorithm of the new private keys, defaults to \"rsa\"")
+        )
+        .arg(
+            Arg::with_name("key-size")
+                .long("key-size")
+                .takes_value(true)
+                .possible_values(&["256", "384", "2048", "3072", "4096"])
+                .help("Size in bits of the new private keys: 256 (P-256) or 384 (P-384) for ecdsa, and 2048, 3072, or 4096 for rsa; defaults to 256 for ecdsa and 2048 for rsa")
+        )
+        .arg(
+            Arg::with_name("kms-key")
+                .short("k")
+                .long("kms-key")
+                .takes_value(true)
+                .required(true)
+                .help("KMS customer master key ID, ARN, or alias, e.g. \"12345678-1234-1234-1234-123456789012\" or \"alias/kaws-production\"")
+        )
+        .arg(
+            Arg::with_name("region")
+                .short("r")
+                .long("region")
+                .takes_value(true)
+                .required(true)
+                .help("AWS Region where the KMS key lives, e.g. \"us-east-1\"")
+        )
+        .arg(
+            Arg::with_name("subject-fields")
+                .long("subject-fields")
+                .takes_value(true)
+                .value_name("FIELDS")
+                .validator(validate_subject_fields)
+                .help("Fields added to the subjects of new certificates, e.g. \"C=US,ST=California,L=San Francisco,O=Example Inc.,OU=Platform\"; each defaults to the certificate_subject in the cluster's metadata")
+        )
+        .after_help(
+            "\nThe etcd server, client, and peer certificates, the front proxy client certificate, \
+            and the Kubernetes master, controller manager, scheduler, and node certificates are \
+            reissued with new keys, which are encrypted with the given KMS key. The CAs, the \
+            service account key pair, the bootstrap token, and the SSH CAs are left as they are, \
+            and so are administrators' certificates, which `kaws admin sign` renews."
+        )
+}
+
+fn cluster_rollback<'a, 'b>() -> App<'a, 'b> {
+    SubCommand::with_name("rollback")
+        .about("Restores a configuration the target cluster was successfully applied with")
+        .setting(AppSettings::TrailingVarArg)
+        .arg(
+            Arg::with_name("cluster")
+                .index(1)
+                .required(true)
+                .help("The cluster to roll back")
+        )
+        .arg(
+            Arg::with_name("to")
+                .long("to")
+                .takes_value(true)
+                .value_name("TIMESTAMP")
+                .help("The snapshot to restore, as listed without this option")
+        )
+        .arg(
+            Arg::with_name("aws-credentials-path")
+                .long("aws-credentials-path")
+                .takes_value(true)
+                .help("Path to the AWS credentials file, defaults to ~/.aws/credentials")
+        )
+        .arg(
+            Arg::with_name("aws-credentials-profile")
+                .long("aws-credentials-profile")
+                .takes_value(true)
+                .help("Name of the AWS credentials profile to use, defaults to the cluster's recorded profile or \"default\"")
+        )
+        .arg(
+            Arg::with_name("override-budget")
+                .long("override-budget")
+                .help("Skips checking the cluster's estimated monthly cost against the max_monthly_cost in its metadata")
+        )
+        .arg(
+            Arg::with_name("terraform-args")
+                .index(2)
+                .multiple(true)
+                .hidden(true)
+                .help("Additional arguments to be passed on to `terraform plan`")
+        )
+        .after_help("\nA snapshot of terraform.tfvars and metadata.json is taken after each successful `kaws cluster apply`. Without --to, the cluster's snapshots are listed. With it, the snapshot's files are restored and the plan that reverts the cluster is displayed; run `kaws cluster apply` to carry it out.\n\nAny arguments following a literal -- will be passed directly as options to `terraform plan`.")
+}
+
+fn cluster_rotate_pki<'a, 'b>() -> App<'a, 'b> {
+    SubCommand::with_name("rotate-pki")
+        .about("Replaces a cluster's etcd and Kubernetes CAs in phases, without downtime")
+        .arg(
+            Arg::with_name("cluster")
+                .index(1)
+                .required(true)
+                .help("The cluster whose CAs should be replaced")
+        )
+        .arg(
+            Arg::with_name("phase")
+                .index(2)
+                .required(true)
+                .possible_values(&["start", "reissue", "finish"])
+                .help("The phase of the rotation to run")
+        )
+        .arg(
+            Arg::with_name("apiserver-san")
+                .long("apiserver-san")
+                .takes_value(true)
+                .value_name("SAN")
+                .multiple(true)
+                .number_of_values(1)
+                .validator(validate_san)
+                .help("An extra DNS name or IP address for the Kubernetes API server's certificate, in addition to those in the cluster's metadata; this option can be specified more than once")
+        )
+        .arg(
+            Arg::with_name("backdate")
+                .long("backdate")
+                .takes_value(true)
+                .value_name("DURATION")
+                .validator(validate_duration)
+                .help("How long before now new certificates are valid from, to tolerate clock skew, e.g. \"90s\"; defaults to 5m")
+        )
+        .arg(
+            Arg::with_name("ca-expiry")
+                .long("ca-expiry")
+                .takes_value(true)
+                .value_name("DURATION")
+                .validator(validate_duration)
+                .help("How long new CA certificates are valid for, e.g. \"3650d\"; defaults to the cluster's metadata, or 43800h")
+        )
+        .arg(
+            Arg::with_name("cert-expiry")
+                .long("cert-expiry")
+                .takes_value(true)
+                .value_name("DURATION")
+                .validator(validate_duration)
+                .help("How long new certificates are valid for, e.g. \"30d\"; defaults to the cluster's metadata, or 8760h")
+        )
+        .arg(
+            Arg::with_name("domain")
+                .short("d")
+                .long("domain")
+                .takes_value(true)
+                .required(true)
+                .help("The base domain name for the cluster, e.g. \"example.com\"")
+        )
+        .arg(
+            Arg::with_name("key-algo")
+                .long("key-algo")
+                .takes_value(true)
+                .possible_values(&["ecdsa", "rsa"])
+                .help("Algorithm of the new private keys, defaults to \"rsa\"")
+        )
+        .arg(
+            Arg::with_name("key-size")
+                .long("key-size")
+                .takes_value(true)
+                .possible_values(&["256", "384", "2048", "3072", "4096"])
+                .help("Size in bits of the new private keys: 256 (P-256) or 384 (P-384) for ecdsa, and 2048, 3072, or 4096 for rsa; defaults to 256 for ecdsa and 2048 for rsa")
+        )
+        .arg(
+            Arg::with_name("kms-key")
+                .short("k")
+                .long("kms-key")
+                .takes_value(true)
+                .required(true)
+                .help("KMS customer master key ID, ARN, or alias, e.g. \"12345678-1234-1234-1234-123456789012\" or \"alias/kaws-production\"")
+        )
+        .arg(
+            Arg::with_name("region")
+                .short("r")
+                .long("region")
+                .takes_value(true)
+                .required(true)
+                .help("AWS Region where the KMS key lives, e.g. \"us-east-1\"")
+        )
+        .arg(
+            Arg::with_name("subject-fields")
+                .long("subject-fields")
+                .takes_value(true)
+                .value_name("FIELDS")
+                .validator(validate_subject_fields)
+                .help("Fields added to the subjects of new certificates, e.g. \"C=US,ST=California,L=San Francisco,O=Example Inc.,OU=Platform\"; each defaults to the certificate_subject in the cluster's metadata")
+        )
+        .after_help(
+            "\nRun the phases in order, committing changes to Git and running `kaws cluster apply` \
+            after each one:\n\n\
+            * start: Generates new CAs and trusts them alongside the current ones\n\
+            * reissue: Reissues every certificate with the new CAs, which replace the current ones\n\
+            * finish: Stops trusting the old CAs\n\n\
+            Between \"reissue\" and \"finish\", sign every administrator's certificate again with \
+            `kaws admin sign`. The SSH CAs are not rotated; replace them with \
+            `kaws cluster generate-pki ssh`."
+        )
+}
+
+fn cluster_rotate_service_account_key<'a, 'b>() -> App<'a, 'b> {
+    SubCommand::with_name("rotate-service-account-key")
+        .about("Replaces a cluster's service account key pair in phases, without invalidating tokens")
+        .arg(
+            Arg::with_name("cluster")
+                .index(1)
+                .required(true)
+                .help("The cluster whose service account key pair should be replaced")
+        )
+        .arg(
+            Arg::with_name("phase")
+                .index(2)
+                .required(true)
+                .possible_values(&["start", "reissue", "finish"])
+                .help("The phase of the rotation to run")
+        )
+        .arg(
+            Arg::with_name("key-algo")
+                .long("key-algo")
+                .takes_value(true)
+                .possible_values(&["ecdsa", "rsa"])
+                .help("Algorithm of the new private key, defaults to \"rsa\"")
+        )
+        .arg(
+            Arg::with_name("key-size")
+                .long("key-size")
+                .takes_value(true)
+                .possible_values(&["256", "384", "2048", "3072", "4096"])
+                .help("Size in bits of the new private key: 256 (P-256) or 384 (P-384) for ecdsa, and 2048, 3072, or 4096 for rsa; defaults to 256 for ecdsa and 2048 for rsa")
+        )
+        .arg(
+            Arg::with_name("kms-key")
+                .short("k")
+                .long("kms-key")
+                .takes_value(true)
+                .required(true)
+                .help("KMS customer master key ID, ARN, or alias, e.g. \"12345678-1234-1234-1234-123456789012\" or \"alias/kaws-production\"")
+        )
+        .arg(
+            Arg::with_name("region")
+                .short("r")
+                .long("region")
+                .takes_value(true)
+                .required(true)
+                .help("AWS Region where the KMS key lives, e.g. \"us-east-1\"")
+        )
+        .after_help(
+            "\nRun the phases in order, committing changes to Git and running `kaws cluster apply` \
+            after each one:\n\n\
+            * start: Generates a new key pair and trusts its public key alongside the current one\n\
+            * reissue: Signs new tokens with the new key\n\
+            * finish: Stops trusting the old public key\n\n\
+            Between \"reissue\" and \"finish\", replace every token signed with the old key, e.g. \
+            by deleting service account token secrets so they are recreated, and restarting the \
+            pods that use them."
+        )
+}
+
+fn cluster_secret_store<'a, 'b>() -> App<'a, 'b> {
+    SubCommand::with_name("secret-store")
+        .about("Keeps a cluster's encrypted files in AWS for its servers to fetch, rather than in Git")
+        .setting(AppSettings::SubcommandRequiredElseHelp)
+        .subcommand(cluster_secret_store_disable())
+        .subcommand(cluster_secret_store_enable())
+        .subcommand(cluster_secret_store_pull())
+        .subcommand(cluster_secret_store_rotate())
+}
+
+fn cluster_secret_store_disable<'a, 'b>() -> App<'a, 'b> {
+    SubCommand::with_name("disable")
+        .about("Passes a cluster's encrypted files to its servers in their user data again")
+        .arg(
+            Arg::with_name("cluster")
+                .index(1)
+                .required(true)
+                .help("The cluster whose secret store should be disabled")
+        )
+        .arg(
+            Arg::with_name("aws-credentials-path")
+                .long("aws-credentials-path")
+                .takes_value(true)
+                .help("Path to the AWS credentials file, defaults to ~/.aws/credentials")
+        )
+        .arg(
+            Arg::with_name("aws-credentials-profile")
+                .long("aws-credentials-profile")
+                .takes_value(true)
+                .help("Name of the AWS credentials profile to use, defaults to the cluster's recorded profile or \"default\"")
+        )
+        .after_help(
+            "\nThe store's copies of the cluster's encrypted files are first written to \
+            clusters/CLUSTER, and .gitignore stops leaving them out of Git, so they can be \
+            committed again. The copies in the store itself are left alone."
+        )
+}
+
+fn cluster_secret_store_enable<'a, 'b>() -> App<'a, 'b> {
+    SubCommand::with_name("enable")
+        .about("Writes a cluster's encrypted files to a secret store that its servers fetch them from")
+        .arg(
+            Arg::with_name("cluster")
+                .index(1)
+                .required(true)
+                .help("The cluster whose encrypted files should be kept in the store")
+        )
+        .arg(
+            Arg::with_name("store")
+                .index(2)
+                .required(true)
+                .possible_values(&["s3", "secretsmanager", "ssm"])
+                .help("The secret store: \"s3\" for an S3 bucket, \"secretsmanager\" for Secrets Manager, or \"ssm\" for SSM Parameter Store")
+        )
+        .arg(
+            Arg::with_name("aws-credentials-path")
+                .long("aws-credentials-path")
+                .takes_value(true)
+                .help("Path to the AWS credentials file, defaults to ~/.aws/credentials")
+        )
+        .arg(
+            Arg::with_name("aws-credentials-profile")
+                .long("aws-credentials-profile")
+                .takes_value(true)
+                .help("Name of the AWS credentials profile to use, defaults to the cluster's recorded profile or \"default\"")
+        )
+        .arg(
+            Arg::with_name("bucket")
+                .long("bucket")
+                .takes_value(true)
+                .required_if("store", "s3")
+                .help("The versioned S3 bucket to keep the files in, in the cluster's region; required for \"s3\"")
+        )
         .arg(
             Arg::with_name("kms-key")
                 .short("k")
                 .long("kms-key")
                 .takes_value(true)
+                .required_if("store", "s3")
+                .help("KMS customer master key ID, ARN, or alias that S3 encrypts the files with, normally the cluster's own; required for \"s3\"")
+        )
+        .after_help(
+            "\nEach file in clusters/CLUSTER ending in -encrypted.base64 is written to the store as \
+            it is, still encrypted with KMS, and every command that encrypts the cluster's files \
+            from then on writes them there too. With \"s3\", each file is an object named \
+            CLUSTER/FILE in the bucket, which must have versioning enabled, encrypted by S3 with the \
+            KMS key. With \"secretsmanager\", each file is a secret \
+            named kaws/CLUSTER/FILE in the cluster's region, tagged with kaws-cluster and \
+            kaws-file. With \"ssm\", each file is a SecureString parameter named \
+            /kaws/CLUSTER/FILE in the cluster's region.\n\n\
+            The store is recorded in clusters/CLUSTER/metadata.json and terraform.tfvars. After \
+            the next `kaws cluster apply`, servers find a placeholder in their user data in place \
+            of each encrypted file, and fetch the file from the store at boot. The files are left \
+            out of Git by the cluster's .gitignore, and kept in clusters/CLUSTER for kaws and \
+            Terraform to read; `kaws cluster secret-store pull` writes them there in a fresh clone."
+        )
+}
+
+fn cluster_secret_store_pull<'a, 'b>() -> App<'a, 'b> {
+    SubCommand::with_name("pull")
+        .about("Writes a cluster's encrypted files from its secret store to its directory")
+        .arg(
+            Arg::with_name("cluster")
+                .index(1)
                 .required(true)
-                .help("KMS customer master key ID, e.g. \"12345678-1234-1234-1234-123456789012\"")
+                .help("The cluster whose encrypted files should be pulled")
         )
         .arg(
-            Arg::with_name("region")
-                .short("r")
-                .long("region")
+            Arg::with_name("aws-credentials-path")
+                .long("aws-credentials-path")
+                .takes_value(true)
+                .help("Path to the AWS credentials file, defaults to ~/.aws/credentials")
+        )
+        .arg(
+            Arg::with_name("aws-credentials-profile")
+                .long("aws-credentials-profile")
                 .takes_value(true)
+                .help("Name of the AWS credentials profile to use, defaults to the cluster's recorded profile or \"default\"")
+        )
+        .after_help(
+            "\nRun it after cloning the repository, which doesn't have the encrypted files, before \
+            any command that reads them, including `kaws cluster plan` and `kaws cluster apply`. \
+            Files in clusters/CLUSTER are replaced with the store's copies."
+        )
+}
+
+fn cluster_secret_store_rotate<'a, 'b>() -> App<'a, 'b> {
+    SubCommand::with_name("rotate")
+        .about("Writes a cluster's encrypted files to its secret store again, through the store's rotation")
+        .arg(
+            Arg::with_name("cluster")
+                .index(1)
                 .required(true)
-                .help("AWS Region where the KMS key lives, e.g. \"us-east-1\"")
+                .help("The cluster whose encrypted files should be rotated")
+        )
+        .arg(
+            Arg::with_name("aws-credentials-path")
+                .long("aws-credentials-path")
+                .takes_value(true)
+                .help("Path to the AWS credentials file, defaults to ~/.aws/credentials")
+        )
+        .arg(
+            Arg::with_name("aws-credentials-profile")
+                .long("aws-credentials-profile")
+                .takes_value(true)
+                .help("Name of the AWS credentials profile to use, defaults to the cluster's recorded profile or \"default\"")
+        )
+        .after_help(
+            "\nEach file in clusters/CLUSTER ending in -encrypted.base64 replaces the store's \
+            copy. With Secrets Manager, the file is staged as the secret's AWSPENDING version, \
+            read back, and only then made AWSCURRENT, the version servers fetch, as a rotation \
+            function would. The other stores keep the copy each file replaces as an earlier \
+            version."
         )
 }
 
-fn cluster_generate_pki_kubernetes<'a, 'b>() -> App<'a, 'b> {
-    SubCommand::with_name("kubernetes")
-        .about("Generates public key infrastructure for Kubernetes")
+fn cluster_set<'a, 'b>() -> App<'a, 'b> {
+    SubCommand::with_name("set")
+        .about("Changes settings of the target cluster and displays the resulting plan")
         .arg(
             Arg::with_name("cluster")
                 .index(1)
                 .required(true)
-                .help("The cluster to generate PKI assets for")
+                .help("The cluster whose settings should be changed")
         )
         .arg(
-            Arg::with_name("subject")
+            Arg::with_name("settings")
                 .index(2)
                 .required(true)
-                .possible_values(&["ca", "masters", "nodes"])
-                .help("The subject to generate PKI assets for")
+                .multiple(true)
+                .value_name("KEY=VALUE")
+                .validator(validate_key_value)
+                .help("A setting to change, e.g. \"nodes_max_size=5\" or \"certificate_expiry.k8s-node=30d\"")
         )
         .arg(
-            Arg::with_name("domain")
-                .short("d")
-                .long("domain")
+            Arg::with_name("aws-credentials-path")
+                .long("aws-credentials-path")
                 .takes_value(true)
-                .required(true)
-                .help("The base domain name for the cluster, e.g. \"example.com\"")
+                .help("Path to the AWS credentials file, defaults to ~/.aws/credentials")
         )
         .arg(
-            Arg::with_name("kms-key")
-                .short("k")
-                .long("kms-key")
+            Arg::with_name("aws-credentials-profile")
+                .long("aws-credentials-profile")
                 .takes_value(true)
+                .help("Name of the AWS credentials profile to use, defaults to the cluster's recorded profile or \"default\"")
+        )
+        .arg(
+            Arg::with_name("override-budget")
+                .long("override-budget")
+                .help("Skips checking the cluster's estimated monthly cost against the max_monthly_cost in its metadata")
+        )
+        .after_help("\nThe settings instance_size, kubernetes_version, masters_max_size, masters_min_size, nodes_max_size, and nodes_min_size change terraform.tfvars. Any other KEY is a field of the cluster's metadata.json, with dots between the names of nested fields, e.g. \"apiserver_flags.v=2\". VALUE is read as JSON, or as a string if it isn't JSON or the field holds a string, and \"null\" removes the field. Every setting is checked before any file is changed, and the plan that carries them out is displayed; run `kaws cluster apply` to apply it.")
+}
+
+fn cluster_tunnel<'a, 'b>() -> App<'a, 'b> {
+    SubCommand::with_name("tunnel")
+        .about("Opens an SSH tunnel through the bastion server to the target cluster's Kubernetes API")
+        .arg(
+            Arg::with_name("cluster")
+                .index(1)
                 .required(true)
-                .help("KMS customer master key ID, e.g. \"12345678-1234-1234-1234-123456789012\"")
+                .help("The cluster whose Kubernetes API should be tunneled to")
+        )
+        .arg(
+            Arg::with_name("close")
+                .long("close")
+                .help("Closes the tunnel and points kubectl back at the Kubernetes API's own address")
+        )
+        .arg(
+            Arg::with_name("port")
+                .short("p")
+                .long("port")
+                .takes_value(true)
+                .help("The local port to listen on, defaults to 6443")
+                .validator(|port| {
+                    match port.parse::<u16>() {
+                        Ok(port) if port > 0 => Ok(()),
+                        _ => Err("Value must be a port number".to_string()),
+                    }
+                })
+        )
+        .after_help(
+            "\nThe tunnel runs in the background until it is closed with --close. While it is open, \
+            kubectl's kaws-CLUSTER cluster points at the local end of the tunnel, so kubectl works as \
+            usual. This makes clusters created with `kaws cluster init --bastion`, whose Kubernetes \
+            API is not reachable from the Internet, usable from outside their VPC."
+        )
+}
+
+fn doctor<'a, 'b>() -> App<'a, 'b> {
+    SubCommand::with_name("doctor")
+        .about("Checks this machine for problems that would stop kaws from working")
+        .arg(
+            Arg::with_name("backdate")
+                .long("backdate")
+                .takes_value(true)
+                .value_name("DURATION")
+                .validator(validate_duration)
+                .help("The certificate backdate to check clock skew against, e.g. \"90s\"; defaults to 5m")
         )
         .arg(
             Arg::with_name("region")
                 .short("r")
                 .long("region")
                 .takes_value(true)
-                .required(true)
-                .help("AWS Region where the KMS key lives, e.g. \"us-east-1\"")
+                .help("AWS Region whose clock to compare against, defaults to \"us-east-1\"")
+        )
+        .after_help(
+            "\nChecks that kubectl and Terraform are installed, and that this machine's clock \
+            agrees with AWS. A clock that is ahead by more than the certificate backdate issues \
+            certificates that servers reject as not yet valid, and AWS rejects requests signed by a \
+            clock that is off by more than five minutes in either direction."
         )
 }
 
-fn cluster_output<'a, 'b>() -> App<'a, 'b> {
-    SubCommand::with_name("output")
-        .about("Displays the Terraform outputs for the target cluster")
+fn fleet<'a, 'b>() -> App<'a, 'b> {
+    SubCommand::with_name("fleet")
+        .about("Commands for viewing all the clusters in the repository at once")
+        .setting(AppSettings::SubcommandRequiredElseHelp)
+        .subcommand(fleet_status())
+}
+
+fn fleet_status<'a, 'b>() -> App<'a, 'b> {
+    SubCommand::with_name("status")
+        .about("Displays the version, size, certificate expiry, and last apply time of every cluster")
         .arg(
-            Arg::with_name("cluster")
-                .index(1)
-                .required(true)
-                .help("The cluster whose plan should be displayed")
+            Arg::with_name("json")
+                .long("json")
+                .help("Prints the status of each cluster as JSON instead of a table")
         )
         .arg(
-            Arg::with_name("output")
-                .index(2)
-                .help("The name of an individual output to display")
+            Arg::with_name("inventory")
+                .long("inventory")
+                .takes_value(true)
+                .value_name("PATH")
+                .help("Also writes the status of each cluster as JSON to the given file")
+        )
+        .after_help(
+            "\nAll information is read from the repository. Node counts are the desired capacities \
+            recorded in each cluster's Terraform state, and the last apply time is when that state \
+            file was last written."
         )
 }
 
-fn cluster_plan<'a, 'b>() -> App<'a, 'b> {
-    SubCommand::with_name("plan")
-        .about("Displays the Terraform plan for the target cluster")
-        .setting(AppSettings::TrailingVarArg)
+fn image<'a, 'b>() -> App<'a, 'b> {
+    SubCommand::with_name("image")
+        .about("Commands for building machine images for clusters")
+        .setting(AppSettings::SubcommandRequiredElseHelp)
+        .subcommand(image_build())
+}
+
+fn image_build<'a, 'b>() -> App<'a, 'b> {
+    SubCommand::with_name("build")
+        .about("Builds an AMI with the cluster's Kubernetes images pre-installed for its masters and nodes")
         .arg(
             Arg::with_name("cluster")
                 .index(1)
                 .required(true)
-                .help("The cluster whose plan should be displayed")
+                .help("The cluster to build the image for")
         )
         .arg(
             Arg::with_name("aws-credentials-path")
@@ -549,28 +3460,72 @@ fn cluster_plan<'a, 'b>() -> App<'a, 'b> {
             Arg::with_name("aws-credentials-profile")
                 .long("aws-credentials-profile")
                 .takes_value(true)
-                .help("Name of the AWS credentials profile to use, defaults to \"default\"")
+                .help("Name of the AWS credentials profile to use, defaults to the cluster's recorded profile or \"default\"")
         )
         .arg(
-            Arg::with_name("terraform-args")
-                .index(2)
-                .multiple(true)
-                .hidden(true)
-                .help("Additional arguments to be passed on to `terraform plan`")
+            Arg::with_name("instance-size")
+                .long("instance-size")
+                .takes_value(true)
+                .value_name("SIZE")
+                .help("The EC2 instance size to build the image on, defaults to the cluster's instance size")
+        )
+        .arg(
+            Arg::with_name("subnet")
+                .long("subnet")
+                .takes_value(true)
+                .value_name("SUBNET_ID")
+                .help("The subnet to launch the build instance in, defaults to the default VPC's")
+        )
+        .after_help(
+            "\nThe build instance is launched from the cluster's CoreOS AMI and needs internet \
+            access to fetch the images. It is terminated once the image is made, or if the build \
+            fails."
         )
-        .after_help("\nAny arguments following a literal -- will be passed directly as options to `terraform plan`.")
 }
 
-fn cluster_refresh<'a, 'b>() -> App<'a, 'b> {
-    SubCommand::with_name("refresh")
-        .about("Refreshes the Terraform state for the target cluster")
-        .setting(AppSettings::TrailingVarArg)
+fn init<'a, 'b>() -> App<'a, 'b> {
+    SubCommand::with_name("init")
+        .about("Initializes a new repository for managing Kubernetes clusters")
         .arg(
-            Arg::with_name("cluster")
+            Arg::with_name("name")
                 .index(1)
                 .required(true)
-                .help("The cluster whose plan should be displayed")
+                .help("The name of the repository to create, e.g. \"example-company-infrastructure\"")
+        )
+        .arg(
+            Arg::with_name("embedded-module")
+                .long("embedded-module")
+                .conflicts_with("terraform-source")
+                .help("Uses the Terraform module built into kaws instead of downloading it from GitHub")
+        )
+        .arg(
+            Arg::with_name("template-dir")
+                .long("template-dir")
+                .takes_value(true)
+                .value_name("DIR")
+                .help("Directory of custom templates to use instead of the built-in ones")
+        )
+        .arg(
+            Arg::with_name("terraform-source")
+                .short("t")
+                .long("terraform-source")
+                .takes_value(true)
+                .help("Custom source value for the Terraform module to use")
         )
+        .after_help(
+            "\nCustom templates are named after the file they generate with a .hbs extension, e.g. \
+            kaws.tf.hbs, and are copied into the new repository's templates directory, where later \
+            commands like `kaws cluster init` look for them.\n\n\
+            With --embedded-module, every command that runs Terraform first writes the module built \
+            into kaws to .kaws/module, so clusters can be managed without network access to GitHub. \
+            The module used is always the one built into the kaws binary that runs the command."
+        )
+}
+
+fn reconcile<'a, 'b>() -> App<'a, 'b> {
+    SubCommand::with_name("reconcile")
+        .about("Applies the spec of every cluster that has one, once or whenever the Git branch changes")
+        .setting(AppSettings::TrailingVarArg)
         .arg(
             Arg::with_name("aws-credentials-path")
                 .long("aws-credentials-path")
@@ -581,32 +3536,208 @@ fn cluster_refresh<'a, 'b>() -> App<'a, 'b> {
             Arg::with_name("aws-credentials-profile")
                 .long("aws-credentials-profile")
                 .takes_value(true)
-                .help("Name of the AWS credentials profile to use, defaults to \"default\"")
+                .help("Name of the AWS credentials profile to use, defaults to the cluster's recorded profile or \"default\"")
+        )
+        .arg(
+            Arg::with_name("branch")
+                .long("branch")
+                .takes_value(true)
+                .requires("watch")
+                .help("The branch to watch, defaults to \"main\"")
+        )
+        .arg(
+            Arg::with_name("interval")
+                .long("interval")
+                .takes_value(true)
+                .value_name("DURATION")
+                .validator(validate_duration)
+                .requires("watch")
+                .help("How often to pull the branch, e.g. \"30s\"; defaults to 1m")
+        )
+        .arg(
+            Arg::with_name("remote")
+                .long("remote")
+                .takes_value(true)
+                .requires("watch")
+                .help("The Git remote to pull from and push to, defaults to \"origin\"")
+        )
+        .arg(
+            Arg::with_name("require-approval")
+                .long("require-approval")
+                .help("Only applies a spec once approvers have approved it with `kaws cluster approve CLUSTER apply-spec spec`")
+        )
+        .arg(
+            Arg::with_name("watch")
+                .long("watch")
+                .help("Keeps pulling the branch and applying specs as they change, committing and pushing the results")
+        )
+        .arg(
+            Arg::with_name("webhook-url")
+                .long("webhook-url")
+                .takes_value(true)
+                .value_name("URL")
+                .help("Posts a message to this incoming webhook whenever a spec is applied, fails, or waits for approval")
         )
         .arg(
             Arg::with_name("terraform-args")
-                .index(2)
+                .index(1)
                 .multiple(true)
                 .hidden(true)
-                .help("Additional arguments to be passed on to `terraform refresh`")
+                .help("Additional arguments to be passed on to `terraform apply`")
+        )
+        .after_help(
+            "\nEach cluster's spec is read from clusters/CLUSTER/spec.json and applied with `kaws cluster apply-spec`, \
+            without Terraform's prompt. Run this command from the root of a clone of the repository.\n\n\
+            Any arguments following a literal -- will be passed directly as options to `terraform apply`."
         )
-        .after_help("\nAny arguments following a literal -- will be passed directly as options to `terraform refresh`.")
 }
 
-fn init<'a, 'b>() -> App<'a, 'b> {
-    SubCommand::with_name("init")
-        .about("Initializes a new repository for managing Kubernetes clusters")
+fn secret<'a, 'b>() -> App<'a, 'b> {
+    SubCommand::with_name("secret")
+        .about("Commands for reading a cluster's encrypted secrets")
+        .setting(AppSettings::SubcommandRequiredElseHelp)
+        .subcommand(secret_cat())
+}
+
+fn secret_cat<'a, 'b>() -> App<'a, 'b> {
+    SubCommand::with_name("cat")
+        .about("Decrypts one of a cluster's secrets and writes it to stdout, never to disk")
         .arg(
-            Arg::with_name("name")
+            Arg::with_name("cluster")
                 .index(1)
                 .required(true)
-                .help("The name of the repository to create, e.g. \"example-company-infrastructure\"")
+                .help("The cluster the secret belongs to")
         )
         .arg(
-            Arg::with_name("terraform-source")
-                .short("t")
-                .long("terraform-source")
+            Arg::with_name("name")
+                .index(2)
+                .required(true)
+                .help("The name of the secret, e.g. \"etcd-client-key\" for clusters/CLUSTER/etcd-client-key-encrypted.base64")
+        )
+        .arg(
+            Arg::with_name("exec")
+                .long("exec")
                 .takes_value(true)
-                .help("Custom source value for the Terraform module to use")
+                .value_name("CMD")
+                .help("Runs CMD with `sh -c`, with the secret on its standard input instead of stdout")
+        )
+        .arg(
+            Arg::with_name("aws-credentials-path")
+                .long("aws-credentials-path")
+                .takes_value(true)
+                .help("Path to the AWS credentials file, defaults to ~/.aws/credentials")
+        )
+        .arg(
+            Arg::with_name("aws-credentials-profile")
+                .long("aws-credentials-profile")
+                .takes_value(true)
+                .help("Name of the AWS credentials profile to use, defaults to the cluster's recorded profile or \"default\"")
+        )
+        .after_help(
+            "\nWith --exec, the secret reaches the command through a pipe on its standard input, so \
+            a command that wants a file can read /dev/stdin, e.g.:\n\n\
+            kaws secret cat CLUSTER etcd-client-key --exec \\\n    \
+            'etcdctl --cert clusters/CLUSTER/etcd-client.pem --key /dev/stdin ...'"
         )
 }
+
+fn status<'a, 'b>() -> App<'a, 'b> {
+    SubCommand::with_name("status")
+        .about("Summarizes the repository and lists anything that needs attention")
+        .arg(
+            Arg::with_name("expiry-warning-days")
+                .long("expiry-warning-days")
+                .takes_value(true)
+                .value_name("DAYS")
+                .help("Lists certificates that expire within this many days, defaults to 30")
+                .validator(|days| {
+                    match days.parse::<u32>() {
+                        Ok(_) => Ok(()),
+                        Err(_) => Err("Value must be a whole number of days".to_string()),
+                    }
+                })
+        )
+        .after_help(
+            "\nLists every cluster with its Kubernetes version and last apply time, and the installed \
+            versions of kaws's dependencies. Then lists expired and expiring certificates, \
+            certificate signing requests that haven't been signed, generated files with uncommitted \
+            changes, and missing dependencies."
+        )
+}
+
+fn validate_ingress_pattern(value: String) -> Result<(), String> {
+    let name = value.strip_prefix("*.").unwrap_or(&value);
+    let is_dns_name = !name.is_empty() && name.split('.').all(|label| {
+        !label.is_empty() && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+    });
+
+    if is_dns_name {
+        Ok(())
+    } else {
+        Err("Value must be a DNS name, optionally starting with \"*.\"".to_string())
+    }
+}
+
+fn validate_jobs(value: String) -> Result<(), String> {
+    match value.parse::<usize>() {
+        Ok(jobs) if jobs > 0 => Ok(()),
+        _ => Err("Value must be a whole number of at least 1, e.g. \"4\"".to_string()),
+    }
+}
+
+fn validate_key_value(value: String) -> Result<(), String> {
+    match value.find('=') {
+        Some(index) if index > 0 && index < value.len() - 1 => Ok(()),
+        _ => Err("Value must be in the form KEY=VALUE".to_string()),
+    }
+}
+
+fn validate_san(value: String) -> Result<(), String> {
+    let is_dns_name = !value.is_empty() && value.split('.').all(|label| {
+        !label.is_empty() && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+    });
+
+    if is_dns_name || value.parse::<IpAddr>().is_ok() {
+        Ok(())
+    } else {
+        Err("Value must be a DNS name or an IP address".to_string())
+    }
+}
+
+fn validate_subnet_cidr(value: String) -> Result<(), String> {
+    let cidr: Ipv4Cidr = match value.parse() {
+        Ok(cidr) => cidr,
+        Err(_) => return Err("Invalid CIDR provided.".to_string()),
+    };
+
+    let vpc_cidr: Ipv4Cidr = "10.0.0.0/16".parse().unwrap();
+    let elb_cidr: Ipv4Cidr = "10.0.0.0/24".parse().unwrap();
+    let etcd_cidr: Ipv4Cidr = "10.0.1.0/24".parse().unwrap();
+
+    match cidr.subset_cmp(&vpc_cidr) {
+        Some(Ordering::Less) => {}
+        _ => return Err("Provided CIDR must be a subset of 10.0.0.0/16.".to_string()),
+    }
+
+    if cidr.subset_cmp(&elb_cidr).is_some() {
+        return Err("Provided CIDR cannot overlap with 10.0.0.0/24, which is used for ELBs.".to_string());
+    }
+
+    if cidr.subset_cmp(&etcd_cidr).is_some() {
+        return Err("Provided CIDR cannot overlap with 10.0.1.0/24, which is used for etcd.".to_string());
+    }
+
+    Ok(())
+}
+
+fn validate_duration(value: String) -> Result<(), String> {
+    parse_duration(&value).map(|_| ())
+}
+
+fn validate_volume_size(value: String) -> Result<(), String> {
+    parse_volume_size(&value).map(|_| ())
+}
+
+fn validate_subject_fields(value: String) -> Result<(), String> {
+    SubjectFields::parse(&value).map(|_| ())
+}
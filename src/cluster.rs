@@ -1,13 +1,87 @@
-use std::fs::{create_dir_all, File};
-use std::io::Write;
-
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs::{create_dir_all, metadata, remove_file, rename, File};
+use std::io::{self, ErrorKind, Read, Write};
+use std::path::Path;
+use std::process::Command;
+use std::sync::OnceLock;
+
+use bitstring::BitString;
+use chrono::Duration;
+use cidr::Ipv4Cidr;
 use clap::ArgMatches;
-use rusoto_core::ChainProvider;
-
-use aws::credentials_provider;
-use encryption::Encryptor;
-use error::KawsResult;
-use pki::CertificateAuthority;
+use openssl::rand::rand_bytes;
+use openssl::sha::sha256;
+use rustc_serialize::base64::{STANDARD, ToBase64};
+use rusoto_core::credential::ChainProvider;
+
+use approval::Approvals;
+use aws::{Access, cluster_credentials_provider, credentials_provider};
+use dependencies::ensure_ssh_keygen;
+use encryption::{Encryptor, encrypted_file_paths, remove_encrypted_file};
+use error::{KawsError, KawsResult};
+use files::{entries, non_empty_names};
+use jobs::run_all;
+use message::Message;
+use metadata::{ClusterMetadata, ENDPOINT_OUTPUTS, IamUser, KmsKey};
+use node::Node;
+use paths::from_invocation;
+use pki::{
+    Certificate,
+    CertificateAuthority,
+    CertificateRevocationList,
+    CertificateSigningRequest,
+    DEFAULT_BACKDATE,
+    DEFAULT_CA_EXPIRY,
+    DEFAULT_CERT_EXPIRY,
+    DEFAULT_CRL_EXPIRY,
+    ExternalCaKey,
+    KeyAlgorithm,
+    PrivateKey,
+    Provenance,
+    PublicKey,
+    SigningProfile,
+};
+use pki_manifest::PkiManifest;
+use pki_profiles::PkiProfiles;
+use pki_verify::dependents;
+use preset::Preset;
+use provider::{CloudProviderName, cloud_provider};
+use ssh::SshCertificateAuthority;
+use template::Templates;
+use tfvars::{Tfvars, with_values};
+use units::{parse_duration, parse_volume_size};
+use userdata::userdata_signing_encrypted_key_path;
+
+/// The etcd members, by the suffix of their server names, and their private IP addresses.
+pub const ETCD_MEMBERS: [(&str, &str); 3] = [("01", "10.0.1.4"), ("02", "10.0.1.5"), ("03", "10.0.1.6")];
+
+/// The control plane components that authenticate to the Kubernetes API server with client
+/// certificates of their own, by the suffix of their files, and the users Kubernetes' default RBAC
+/// policy grants their permissions to.
+const K8S_COMPONENTS: [(&str, &str); 2] = [
+    ("controller-manager", "system:kube-controller-manager"),
+    ("scheduler", "system:kube-scheduler"),
+];
+
+/// The size of each etcd member's EBS volume in gibibytes, unless otherwise given.
+const DEFAULT_ETCD_VOLUME_GIBIBYTES: u64 = 10;
+
+/// How long `kaws cluster pki revoke-node` waits for a node to replace the revoked one, unless
+/// otherwise given.
+const DEFAULT_NODE_REPLACEMENT_TIMEOUT: &str = "15m";
+
+/// What Terraform prints when the output it was asked for isn't in the state, because the cluster
+/// hasn't been applied with it yet, rather than because the state couldn't be read.
+const OUTPUT_MISSING_ERRORS: [&str; 2] = ["could not be found", "no outputs defined yet"];
+
+/// The CAs `kaws cluster pki revoke` keeps revocation lists for, by the prefix of their files.
+const REVOCABLE_CAS: [&str; 3] = ["etcd", "etcd-peer", "k8s"];
+
+/// The CAs whose certificates `kaws cluster renew-pki` reissues, by the prefix of their files.
+const RENEWED_CAS: [&str; 4] = ["etcd", "etcd-peer", "front-proxy", "k8s"];
+
+/// The CAs `kaws cluster rotate-pki` replaces, by the prefix of their files.
+const ROTATED_CAS: [&str; 3] = ["etcd", "etcd-peer", "k8s"];
 
 pub struct Cluster<'a> {
     name: &'a str,
@@ -15,39 +89,135 @@ pub struct Cluster<'a> {
 }
 
 pub struct ExistingCluster<'a> {
-    aws_credentials_provider: ChainProvider,
+    apiserver_sans: Vec<&'a str>,
+    apply: bool,
+    // Whether the approvals for the whole run have been checked, so that its parts, such as each
+    // of `generate-pki all`'s, don't need their own.
+    approved: bool,
+    aws_credentials_path: Option<String>,
+    aws_credentials_profile: Option<&'a str>,
+    backdate: &'a str,
+    ca: Option<&'a str>,
+    ca_expiry: Option<&'a str>,
+    cert_expiry: Option<&'a str>,
     cluster: Cluster<'a>,
+    crl_expiry: &'a str,
     domain: Option<&'a str>,
+    force: bool,
+    import_cert: Option<String>,
+    import_key: Option<String>,
+    ingress_pattern: Option<&'a str>,
+    instance_id: Option<&'a str>,
+    intermediate_cert: Option<String>,
+    intermediate_csr: bool,
+    intermediate_key: Option<String>,
+    key_algo: &'a str,
+    key_size: Option<&'a str>,
+    kms_key_arn: OnceLock<String>,
     kms_master_key_id: &'a str,
+    replacement_confirmed: bool,
+    root_cert: Option<String>,
+    rotation_phase: Option<&'a str>,
+    serial_number: Option<&'a str>,
     subject: &'a str,
+    subject_fields: Option<&'a str>,
+    timeout: &'a str,
 }
 
 pub struct NewCluster<'a> {
     availability_zone: &'a str,
     aws_account_id: &'a str,
+    aws_credentials_provider: ChainProvider,
+    bastion: bool,
+    bastion_ssh_cidrs: Vec<&'a str>,
     cidr: &'a str,
+    cloud_provider: CloudProviderName,
     cluster: Cluster<'a>,
     coreos_ami: &'a str,
     domain: &'a str,
+    etcd_member_certificates: bool,
+    etcd_volume_size: u64,
+    group_roles: Vec<&'a str>,
+    iam_authenticator: bool,
+    iam_user_groups: Vec<&'a str>,
     iam_users: Vec<&'a str>,
-    instance_size: &'a str,
+    instance_size: String,
+    key_algo: String,
+    key_size: Option<String>,
     kubernetes_version: &'a str,
-    masters_max_size: &'a str,
-    masters_min_size: &'a str,
-    nodes_max_size: &'a str,
-    nodes_min_size: &'a str,
+    masters_max_size: String,
+    masters_min_size: String,
+    max_monthly_cost: Option<f64>,
+    node_placement: Option<String>,
+    node_subnet_cidrs: Vec<&'a str>,
+    nodes_max_size: String,
+    nodes_min_size: String,
+    scrub_credentials: bool,
+    skip_ami_check: bool,
     ssh_keys: Vec<&'a str>,
+    tls_bootstrapping: bool,
+    wireguard: bool,
     zone_id: &'a str,
 }
 
+// How far a CA is through `kaws cluster rotate-pki`.
+#[derive(Clone, Copy)]
+enum RotationStage {
+    // Only the current CA is trusted.
+    Idle,
+    // A new CA is trusted alongside the current one, which still issues certificates.
+    Started,
+    // The new CA issues certificates, and the old one is still trusted.
+    Reissued,
+}
+
 impl<'a> Cluster<'a> {
     pub fn new(name: &'a str, region: &'a str) -> Self {
         Cluster {
-            name: name,
-            region: region,
+            name,
+            region,
         }
     }
 
+    fn bootstrap_encrypted_token_path(&self) -> String {
+        format!("clusters/{}/bootstrap-token-encrypted.base64", self.name)
+    }
+
+    fn ca_cert_path(&self, ca: &str) -> String {
+        format!("clusters/{}/{}-ca.pem", self.name, ca)
+    }
+
+    fn ca_csr_path(&self, ca: &str) -> String {
+        format!("clusters/{}/{}-ca-csr.pem", self.name, ca)
+    }
+
+    fn encrypted_ca_key_path(&self, ca: &str) -> String {
+        format!("clusters/{}/{}-ca-key-encrypted.base64", self.name, ca)
+    }
+
+    // The key of a CA waiting for a root CA to sign its request, kept apart from the CA's own
+    // until the signed certificate is imported, so the CA in use keeps a key that matches it.
+    fn csr_encrypted_ca_key_path(&self, ca: &str) -> String {
+        format!("clusters/{}/{}-ca-csr-key-encrypted.base64", self.name, ca)
+    }
+
+    fn crl_path(&self, ca: &str) -> String {
+        format!("clusters/{}/{}-ca.crl", self.name, ca)
+    }
+
+    // Where older versions of kaws kept a CA's revocation list, encrypted with KMS.
+    fn legacy_encrypted_crl_path(&self, ca: &str) -> String {
+        format!("clusters/{}/{}-crl-encrypted.base64", self.name, ca)
+    }
+
+    fn next_ca_cert_path(&self, ca: &str) -> String {
+        format!("clusters/{}/{}-ca-next.pem", self.name, ca)
+    }
+
+    fn next_encrypted_ca_key_path(&self, ca: &str) -> String {
+        format!("clusters/{}/{}-ca-next-key-encrypted.base64", self.name, ca)
+    }
+
     fn etcd_ca_cert_path(&self) -> String {
         format!("clusters/{}/etcd-ca.pem", self.name)
     }
@@ -56,6 +226,22 @@ impl<'a> Cluster<'a> {
         format!("clusters/{}/etcd-ca-key-encrypted.base64", self.name)
     }
 
+    fn etcd_member_encrypted_server_key_path(&self, member: &str) -> String {
+        format!("clusters/{}/etcd-server-{}-key-encrypted.base64", self.name, member)
+    }
+
+    fn etcd_member_peer_cert_path(&self, member: &str) -> String {
+        format!("clusters/{}/etcd-peer-{}.pem", self.name, member)
+    }
+
+    fn etcd_member_peer_encrypted_key_path(&self, member: &str) -> String {
+        format!("clusters/{}/etcd-peer-{}-key-encrypted.base64", self.name, member)
+    }
+
+    fn etcd_member_server_cert_path(&self, member: &str) -> String {
+        format!("clusters/{}/etcd-server-{}.pem", self.name, member)
+    }
+
     fn etcd_server_cert_path(&self) -> String {
         format!("clusters/{}/etcd-server.pem", self.name)
     }
@@ -64,6 +250,14 @@ impl<'a> Cluster<'a> {
         format!("clusters/{}/etcd-server-key-encrypted.base64", self.name)
     }
 
+    fn etcd_apiserver_client_cert_path(&self) -> String {
+        format!("clusters/{}/etcd-apiserver-client.pem", self.name)
+    }
+
+    fn etcd_encrypted_apiserver_client_key_path(&self) -> String {
+        format!("clusters/{}/etcd-apiserver-client-key-encrypted.base64", self.name)
+    }
+
     fn etcd_client_cert_path(&self) -> String {
         format!("clusters/{}/etcd-client.pem", self.name)
     }
@@ -88,6 +282,30 @@ impl<'a> Cluster<'a> {
         format!("clusters/{}/etcd-peer-key-encrypted.base64", self.name)
     }
 
+    fn front_proxy_ca_cert_path(&self) -> String {
+        format!("clusters/{}/front-proxy-ca.pem", self.name)
+    }
+
+    fn front_proxy_encrypted_ca_key_path(&self) -> String {
+        format!("clusters/{}/front-proxy-ca-key-encrypted.base64", self.name)
+    }
+
+    fn front_proxy_client_cert_path(&self) -> String {
+        format!("clusters/{}/front-proxy-client.pem", self.name)
+    }
+
+    fn front_proxy_encrypted_client_key_path(&self) -> String {
+        format!("clusters/{}/front-proxy-client-key-encrypted.base64", self.name)
+    }
+
+    fn ingress_cert_path(&self) -> String {
+        format!("clusters/{}/ingress.pem", self.name)
+    }
+
+    fn ingress_encrypted_key_path(&self) -> String {
+        format!("clusters/{}/ingress-key-encrypted.base64", self.name)
+    }
+
     fn k8s_ca_cert_path(&self) -> String {
         format!("clusters/{}/k8s-ca.pem", self.name)
     }
@@ -96,6 +314,14 @@ impl<'a> Cluster<'a> {
         format!("clusters/{}/k8s-ca-key-encrypted.base64", self.name)
     }
 
+    fn k8s_component_cert_path(&self, component: &str) -> String {
+        format!("clusters/{}/k8s-{}.pem", self.name, component)
+    }
+
+    fn k8s_encrypted_component_key_path(&self, component: &str) -> String {
+        format!("clusters/{}/k8s-{}-key-encrypted.base64", self.name, component)
+    }
+
     fn k8s_encrypted_master_key_path(&self) -> String {
         format!("clusters/{}/k8s-master-key-encrypted.base64", self.name)
     }
@@ -112,6 +338,10 @@ impl<'a> Cluster<'a> {
         format!("clusters/{}/k8s-master.pem", self.name)
     }
 
+    fn metadata_path(&self) -> String {
+        format!("clusters/{}/metadata.json", self.name)
+    }
+
     fn name(&self) -> &str {
         self.name
     }
@@ -120,130 +350,1680 @@ impl<'a> Cluster<'a> {
         format!("clusters/{}/k8s-node.pem", self.name)
     }
 
+    fn rbac_path(&self) -> String {
+        format!("clusters/{}/rbac.yml", self.name)
+    }
+
     fn region(&self) -> &str {
         self.region
     }
 
+    fn root_ca_cert_path(&self, ca: &str) -> String {
+        format!("clusters/{}/{}-root-ca.pem", self.name, ca)
+    }
+
+    fn service_account_encrypted_key_path(&self) -> String {
+        format!("clusters/{}/service-account-key-encrypted.base64", self.name)
+    }
+
+    fn service_account_next_encrypted_key_path(&self) -> String {
+        format!("clusters/{}/service-account-next-key-encrypted.base64", self.name)
+    }
+
+    fn service_account_next_public_key_path(&self) -> String {
+        format!("clusters/{}/service-account-next.pub", self.name)
+    }
+
+    fn service_account_public_key_path(&self) -> String {
+        format!("clusters/{}/service-account.pub", self.name)
+    }
+
+    fn ssh_host_ca_public_key_path(&self) -> String {
+        format!("clusters/{}/ssh-host-ca.pub", self.name)
+    }
+
+    fn ssh_host_encrypted_ca_key_path(&self) -> String {
+        format!("clusters/{}/ssh-host-ca-key-encrypted.base64", self.name)
+    }
+
+    fn ssh_known_hosts_path(&self) -> String {
+        format!("clusters/{}/ssh_known_hosts", self.name)
+    }
+
+    fn ssh_user_ca_public_key_path(&self) -> String {
+        format!("clusters/{}/ssh-user-ca.pub", self.name)
+    }
+
+    fn ssh_user_encrypted_ca_key_path(&self) -> String {
+        format!("clusters/{}/ssh-user-ca-key-encrypted.base64", self.name)
+    }
+
     fn tfvars_path(&self) -> String {
         format!("clusters/{}/terraform.tfvars", self.name)
     }
+
+    fn userdata_signing_encrypted_key_path(&self) -> String {
+        userdata_signing_encrypted_key_path(self.name)
+    }
+
+    fn userdata_signing_public_key_path(&self) -> String {
+        format!("clusters/{}/userdata-signing.pub", self.name)
+    }
 }
 
 impl<'a> ExistingCluster<'a> {
     pub fn new(matches: &'a ArgMatches) -> Self {
         ExistingCluster {
-            aws_credentials_provider: credentials_provider(
-                matches.value_of("aws-credentials-path"),
-                matches.value_of("aws-credentials-profile"),
-            ),
+            apiserver_sans: matches
+                .values_of("apiserver-san")
+                .map(|values| values.collect())
+                .unwrap_or_default(),
+            apply: matches.is_present("apply"),
+            approved: false,
+            aws_credentials_path: matches.value_of("aws-credentials-path").map(from_invocation),
+            aws_credentials_profile: matches.value_of("aws-credentials-profile"),
+            backdate: matches.value_of("backdate").unwrap_or(DEFAULT_BACKDATE),
+            ca: matches.value_of("ca"),
+            ca_expiry: matches.value_of("ca-expiry"),
+            cert_expiry: matches.value_of("cert-expiry"),
             cluster: Cluster::new(
                 matches.value_of("cluster").expect("missing cluster name"),
                 matches.value_of("region").expect("missing region"),
             ),
+            crl_expiry: matches.value_of("crl-expiry").unwrap_or(DEFAULT_CRL_EXPIRY),
             domain: matches.value_of("domain"),
+            force: matches.is_present("force"),
+            import_cert: matches.value_of("cert").map(from_invocation),
+            import_key: matches.value_of("key").map(from_invocation),
+            ingress_pattern: matches.value_of("pattern"),
+            instance_id: matches.value_of("instance-id"),
+            intermediate_cert: matches.value_of("intermediate-cert").map(from_invocation),
+            intermediate_csr: matches.is_present("intermediate-csr"),
+            intermediate_key: matches.value_of("intermediate-key").map(from_invocation),
+            key_algo: matches.value_of("key-algo").unwrap_or("rsa"),
+            key_size: matches.value_of("key-size"),
+            kms_key_arn: OnceLock::new(),
             kms_master_key_id: matches.value_of("kms-key").expect("missing kms-key"),
+            replacement_confirmed: false,
+            root_cert: matches.value_of("root-cert").map(from_invocation),
+            rotation_phase: matches.value_of("phase"),
+            serial_number: matches.value_of("serial-number"),
             subject: matches.value_of("subject").unwrap_or("ca"),
+            subject_fields: matches.value_of("subject-fields"),
+            timeout: matches.value_of("timeout").unwrap_or(DEFAULT_NODE_REPLACEMENT_TIMEOUT),
         }
     }
 
     pub fn generate_pki_all(&mut self) -> KawsResult {
-        self.generate_etcd_pki()?;
-        self.generate_etcd_peer_pki()?;
-        self.generate_kubernetes_pki()?;
+        // Checked up front, so that nothing is replaced unless everything may be, and confirmed
+        // once rather than for each part.
+        let mut paths = vec![];
+
+        for ca in ["etcd", "etcd-peer", "front-proxy", "k8s"].iter() {
+            paths.extend(self.ca_paths(ca));
+        }
+
+        paths.extend(self.service_account_paths());
+        paths.push(self.cluster.bootstrap_encrypted_token_path());
+        paths.extend(self.ssh_ca_paths());
+
+        let metadata = ClusterMetadata::from_file(&self.cluster.metadata_path())?;
+        let approvals = Approvals::new(self.cluster.name, "generate-pki", "all");
+
+        metadata.check_tls_bootstrapping(self.tls_bootstrapping()?)?;
+        approvals.ensure_sufficient(&metadata)?;
+        self.approved = true;
+        self.ensure_replaceable(&paths)?;
+        self.replacement_confirmed = true;
+
+        // The parts don't depend on each other, so they're generated at the same time, up to
+        // `--jobs` at once, which mostly saves waiting on KMS. Each has its own encryptor, for
+        // the key resolved first.
+        self.resolve_kms_key()?;
+
+        let cluster = &*self;
+        let parts: [fn(&Self) -> KawsResult; 6] = [
+            Self::generate_etcd_pki,
+            Self::generate_etcd_peer_pki,
+            Self::generate_front_proxy_pki,
+            // The service account key before the Kubernetes PKI, so that a cluster whose tokens
+            // were signed with the masters' key keeps trusting the key being replaced.
+            |cluster| {
+                cluster.generate_service_account_pki()?;
+                cluster.generate_kubernetes_pki()
+            },
+            Self::generate_bootstrap_token,
+            Self::generate_ssh_pki,
+        ];
+        for result in run_all(parts.to_vec(), |part| part(cluster)) {
+            result?;
+        }
+
+        approvals.clear()?;
 
         Ok(None)
     }
 
+    /// Generates the token kubelets authenticate with to request their own client certificates,
+    /// for clusters that use TLS bootstrapping.
+    pub fn generate_bootstrap_token(&self) -> KawsResult {
+        self.with_approvals("generate-pki", "bootstrap-token", || {
+            self.ensure_replaceable(&[self.cluster.bootstrap_encrypted_token_path()])?;
+
+            let mut encryptor = self.encryptor()?;
+
+            log_wrap!("Generating the kubelet bootstrap token", {
+                let mut bytes = [0; 16];
+
+                rand_bytes(&mut bytes)?;
+
+                let token = bytes.iter().map(|byte| format!("{:02x}", byte)).collect::<String>();
+
+                encryptor.encrypt_and_write_file(
+                    token.as_bytes(),
+                    &self.cluster.bootstrap_encrypted_token_path(),
+                )?;
+            });
+
+            Ok(None)
+        })
+    }
+
     pub fn generate_etcd_pki(&self) -> KawsResult {
-        let mut encryptor = Encryptor::new(
-            self.aws_credentials_provider.clone(),
-            self.cluster.region().parse()?,
-            Some(self.kms_master_key_id),
-        );
+        self.with_approvals("generate-pki", "etcd", || {
+            let key_algorithm = KeyAlgorithm::new(self.key_algo, self.key_size)?;
+            let metadata = ClusterMetadata::from_file(&self.cluster.metadata_path())?;
+            let ca_expiry = self.ca_expiry(&metadata, "etcd-ca")?;
+            let server_expiry = self.cert_expiry(&metadata, "etcd-server")?;
+            let client_expiry = self.cert_expiry(&metadata, "etcd-client")?;
+            let apiserver_client_expiry = self.cert_expiry(&metadata, "etcd-apiserver-client")?;
+
+            let mut encryptor = self.encryptor()?;
+
+            let ca = if self.subject == "ca" {
+                match self.create_ca(&mut encryptor, "etcd", ca_expiry, key_algorithm)? {
+                    Some(ca) => ca,
+                    None => return Ok(Some(self.intermediate_ca_requested("etcd"))),
+                }
+            } else {
+                self.load_ca(&mut encryptor, "etcd", &metadata)?
+            };
+
+            if self.subject == "ca" {
+                return self.reissue_dependents(
+                    "etcd",
+                    &ca,
+                    &mut encryptor,
+                    &metadata,
+                    key_algorithm,
+                );
+            }
+
+            if self.subject == "server" {
+                self.issue_etcd_server_cert(&ca, &mut encryptor, server_expiry, key_algorithm)?;
+            }
+
+            if self.subject == "client" {
+                self.issue_etcd_client_cert(&ca, &mut encryptor, client_expiry, key_algorithm)?;
+                self.issue_etcd_apiserver_client_cert(
+                    &ca,
+                    &mut encryptor,
+                    apiserver_client_expiry,
+                    key_algorithm,
+                )?;
+            }
+
+            Ok(None)
+        })
+    }
+
+    pub fn generate_etcd_peer_pki(&self) -> KawsResult {
+        self.with_approvals("generate-pki", "etcd-peer", || {
+            let key_algorithm = KeyAlgorithm::new(self.key_algo, self.key_size)?;
+            let metadata = ClusterMetadata::from_file(&self.cluster.metadata_path())?;
+            let ca_expiry = self.ca_expiry(&metadata, "etcd-peer-ca")?;
+            let peer_expiry = self.cert_expiry(&metadata, "etcd-peer")?;
+
+            let mut encryptor = self.encryptor()?;
+
+            let ca = if self.subject == "ca" {
+                match self.create_ca(&mut encryptor, "etcd-peer", ca_expiry, key_algorithm)? {
+                    Some(ca) => ca,
+                    None => return Ok(Some(self.intermediate_ca_requested("etcd-peer"))),
+                }
+            } else {
+                self.load_ca(&mut encryptor, "etcd-peer", &metadata)?
+            };
+
+            if self.subject == "ca" {
+                return self.reissue_dependents(
+                    "etcd-peer",
+                    &ca,
+                    &mut encryptor,
+                    &metadata,
+                    key_algorithm,
+                );
+            }
+
+            self.issue_etcd_peer_cert(&ca, &mut encryptor, peer_expiry, key_algorithm)?;
+
+            Ok(None)
+        })
+    }
 
-        let ca = if self.subject == "ca" {
-            let ca = CertificateAuthority::generate(
-                &format!("kaws-etcd-ca-{}", self.cluster.name)
+    /// Generates the CA the Kubernetes API server's aggregation layer trusts for request headers,
+    /// and the client certificate the API server presents when proxying to aggregated API servers.
+    pub fn generate_front_proxy_pki(&self) -> KawsResult {
+        self.with_approvals("generate-pki", "front-proxy", || {
+            let key_algorithm = KeyAlgorithm::new(self.key_algo, self.key_size)?;
+            let metadata = ClusterMetadata::from_file(&self.cluster.metadata_path())?;
+            let ca_expiry = self.ca_expiry(&metadata, "front-proxy-ca")?;
+            let client_expiry = self.cert_expiry(&metadata, "front-proxy-client")?;
+
+            let mut encryptor = self.encryptor()?;
+
+            let ca = if self.subject == "ca" {
+                match self.create_ca(&mut encryptor, "front-proxy", ca_expiry, key_algorithm)? {
+                    Some(ca) => ca,
+                    None => return Ok(Some(self.intermediate_ca_requested("front-proxy"))),
+                }
+            } else {
+                self.load_ca(&mut encryptor, "front-proxy", &metadata)?
+            };
+
+            if self.subject == "ca" {
+                return self.reissue_dependents(
+                    "front-proxy",
+                    &ca,
+                    &mut encryptor,
+                    &metadata,
+                    key_algorithm,
+                );
+            }
+
+            self.issue_front_proxy_client_cert(&ca, &mut encryptor, client_expiry, key_algorithm)?;
+
+            Ok(None)
+        })
+    }
+
+    /// Issues a wildcard certificate from the Kubernetes CA for an ingress controller in the
+    /// cluster to terminate TLS with, for the name given with `--pattern`, or `*.apps.DOMAIN`.
+    pub fn generate_ingress_pki(&self) -> KawsResult {
+        self.with_approvals("generate-pki", "ingress", || {
+            let key_algorithm = KeyAlgorithm::new(self.key_algo, self.key_size)?;
+            let metadata = ClusterMetadata::from_file(&self.cluster.metadata_path())?;
+            let expiry = self.cert_expiry(&metadata, "ingress")?;
+            let pattern = match self.ingress_pattern {
+                Some(pattern) => pattern.to_owned(),
+                None => format!(
+                    "*.apps.{}",
+                    self.domain.expect("clap should have required domain"),
+                ),
+            };
+
+            let mut encryptor = self.encryptor()?;
+            let ca = self.load_ca(&mut encryptor, "k8s", &metadata)?;
+
+            self.issue_ingress_cert(&ca, &mut encryptor, &[&pattern], expiry, key_algorithm)?;
+
+            Ok(None)
+        })
+    }
+
+    pub fn generate_kubernetes_pki(&self) -> KawsResult {
+        self.with_approvals("generate-pki", "kubernetes", || {
+            let key_algorithm = KeyAlgorithm::new(self.key_algo, self.key_size)?;
+            let metadata = ClusterMetadata::from_file(&self.cluster.metadata_path())?;
+
+            metadata.check_tls_bootstrapping(self.tls_bootstrapping()?)?;
+
+            let ca_expiry = self.ca_expiry(&metadata, "k8s-ca")?;
+            let master_expiry = self.cert_expiry(&metadata, "k8s-master")?;
+            let node_expiry = self.cert_expiry(&metadata, "k8s-node")?;
+
+            let mut encryptor = self.encryptor()?;
+
+            let ca = if self.subject == "ca" {
+                match self.create_ca(&mut encryptor, "k8s", ca_expiry, key_algorithm)? {
+                    Some(ca) => ca,
+                    None => return Ok(Some(self.intermediate_ca_requested("k8s"))),
+                }
+            } else {
+                self.load_ca(&mut encryptor, "k8s", &metadata)?
+            };
+
+            if self.subject == "ca" {
+                return self.reissue_dependents(
+                    "k8s",
+                    &ca,
+                    &mut encryptor,
+                    &metadata,
+                    key_algorithm,
+                );
+            }
+
+            if self.subject == "masters" {
+                self.issue_k8s_master_cert(
+                    &ca,
+                    &mut encryptor,
+                    &metadata,
+                    master_expiry,
+                    key_algorithm,
+                )?;
+                self.issue_k8s_component_certs(&ca, &mut encryptor, &metadata, key_algorithm)?;
+            }
+
+            if self.subject == "nodes" {
+                self.issue_k8s_node_cert(&ca, &mut encryptor, node_expiry, key_algorithm)?;
+            }
+
+            Ok(None)
+        })
+    }
+
+    /// Generates the key pair the controller manager signs service account tokens with, and the
+    /// Kubernetes API server verifies them with.
+    ///
+    /// Clusters created before they had their own service account key signed tokens with the
+    /// masters' key, so the first key pair generated for such a cluster is trusted alongside the
+    /// masters' key, as if the "reissue" phase of `kaws cluster rotate-service-account-key` had
+    /// just run.
+    pub fn generate_service_account_pki(&self) -> KawsResult {
+        self.with_approvals("generate-pki", "service-account", || {
+            let key_algorithm = KeyAlgorithm::new(self.key_algo, self.key_size)?;
+            let public_key_path = self.cluster.service_account_public_key_path();
+
+            self.ensure_replaceable(&self.service_account_paths())?;
+
+            let previous = if PublicKey::from_file_if_present(&public_key_path)?.is_none() {
+                match Certificate::from_file_if_present(&self.cluster.k8s_master_cert_path())? {
+                    Some(cert) => Some(cert.public_key()?),
+                    None => None,
+                }
+            } else {
+                None
+            };
+
+            let mut encryptor = self.encryptor()?;
+
+            log_wrap!("Generating the service account key pair", {
+                let key = PrivateKey::generate(key_algorithm)?;
+                let public_key = key.public_key()?;
+
+                key.write_to_file(
+                    &mut encryptor,
+                    &self.cluster.service_account_encrypted_key_path(),
+                )?;
+
+                match previous {
+                    Some(previous) => PublicKey::bundle(&[&previous, &public_key]),
+                    None => public_key,
+                }.write_to_file(&public_key_path)?;
+            });
+
+            Ok(None)
+        })
+    }
+
+    /// Generates the key pair kaws signs the servers' cloud-configs with after each apply, and the
+    /// servers verify them with before running them.
+    ///
+    /// The public key goes in terraform.tfvars, where the built-in module embeds it in each
+    /// server's user data, so that changing it replaces the servers.
+    pub fn generate_userdata_signing_pki(&self) -> KawsResult {
+        self.with_approvals("generate-pki", "userdata-signing", || {
+            let key_algorithm = KeyAlgorithm::new(self.key_algo, self.key_size)?;
+            let paths = vec![
+                self.cluster.userdata_signing_public_key_path(),
+                self.cluster.userdata_signing_encrypted_key_path(),
+            ];
+
+            self.ensure_replaceable(&paths)?;
+
+            let mut encryptor = self.encryptor()?;
+            let tfvars_path = self.cluster.tfvars_path();
+            let mut contents = String::new();
+
+            File::open(&tfvars_path)?.read_to_string(&mut contents)?;
+
+            log_wrap!("Generating the userdata signing key pair", {
+                let key = PrivateKey::generate(key_algorithm)?;
+                let public_key = key.public_key()?;
+
+                key.write_to_file(
+                    &mut encryptor,
+                    &self.cluster.userdata_signing_encrypted_key_path(),
+                )?;
+                public_key.write_to_file(&self.cluster.userdata_signing_public_key_path())?;
+
+                let value = format!("\"{}\"", public_key.as_bytes().to_base64(STANDARD));
+
+                File::create(&tfvars_path)?.write_all(with_values(
+                    &contents,
+                    &[("kaws_userdata_signing_public_key", &value)],
+                ).as_bytes())?;
+            });
+
+            Ok(None)
+        })
+    }
+
+    /// Stores a CA generated outside kaws as the cluster's CA named `ca`, which later runs of
+    /// `kaws cluster generate-pki` then issue certificates with.
+    pub fn import_ca(&self) -> KawsResult {
+        let ca = self.ca.expect("clap should have required ca");
+
+        self.with_approvals("import-ca", ca, || {
+            let cert = Certificate::from_file(
+                self.import_cert.as_deref().expect("clap should have required cert"),
+            )?;
+            let key = PrivateKey::from_file_unencrypted(
+                self.import_key.as_deref().expect("clap should have required key"),
             )?;
+            let root = match self.root_cert {
+                Some(ref path) => Some(Certificate::from_file(path)?),
+                None => None,
+            };
+
+            if let Some(key) = ClusterMetadata::from_file(&self.cluster.metadata_path())?
+                .external_ca_key(ca) {
+                return Err(key.unsupported("import a CA with a key file"));
+            }
+
+            self.ensure_replaceable(&self.ca_paths(ca))?;
+
+            let mut encryptor = self.encryptor()?;
+            let stale;
+
+            log_wrap!(format!("Importing the {} CA", ca), {
+                let authority = CertificateAuthority::from_certificate(
+                    cert,
+                    key,
+                    root.as_ref(),
+                    self.backdate,
+                )?;
+
+                self.write_ca(&mut encryptor, ca, &authority, root.as_ref())?;
+
+                stale = self.stale_dependents(ca, &authority)?;
+            });
+
+            Ok(Some(Message::CaImported {
+                ca: ca.to_owned(),
+                cluster: self.cluster.name.to_owned(),
+                stale,
+            }))
+        })
+    }
 
-            ca.write_to_files(
-                &mut encryptor,
-                &self.cluster.etcd_ca_cert_path(),
-                &self.cluster.etcd_encrypted_ca_key_path(),
+    /// Reissues the server certificates whose endpoints, as named by the cluster's Terraform
+    /// outputs, changed since they were last refreshed, e.g. because the API server's load
+    /// balancer was recreated with a new DNS name. With `--apply`, the cluster is applied
+    /// afterwards so the servers get the new certificates.
+    pub fn refresh_endpoints(&self) -> KawsResult {
+        self.with_approvals("refresh-endpoints", "endpoints", || {
+            let key_algorithm = KeyAlgorithm::new(self.key_algo, self.key_size)?;
+            let mut metadata = ClusterMetadata::from_file(&self.cluster.metadata_path())?;
+            let mut certificates = vec![];
+
+            for &(output, certificate) in ENDPOINT_OUTPUTS.iter() {
+                let value = match self.terraform_output(output)? {
+                    Some(value) => value,
+                    None => continue,
+                };
+
+                if metadata.endpoints.get(output) == Some(&value) {
+                    continue;
+                }
+
+                println!(
+                    "{} changed from {} to {}",
+                    output,
+                    metadata.endpoints.get(output).map_or("nothing", String::as_str),
+                    value,
+                );
+
+                metadata.endpoints.insert(output.to_owned(), value);
+
+                if !certificates.contains(&certificate.to_owned()) {
+                    certificates.push(certificate.to_owned());
+                }
+            }
+
+            if certificates.is_empty() {
+                return Ok(Some(Message::EndpointsUnchanged {
+                    cluster: self.cluster.name.to_owned(),
+                }));
+            }
+
+            let mut encryptor = self.encryptor()?;
+
+            // Only the API server's certificate is issued for an endpoint Terraform creates.
+            if certificates.iter().any(|certificate| certificate == "k8s-master") {
+                let master_expiry = self.cert_expiry(&metadata, "k8s-master")?;
+
+                log_wrap!("Reissuing the Kubernetes master certificate", {
+                    let ca = self.load_ca(&mut encryptor, "k8s", &metadata)?;
+
+                    self.issue_k8s_master_cert(
+                        &ca,
+                        &mut encryptor,
+                        &metadata,
+                        master_expiry,
+                        key_algorithm,
+                    )?;
+                });
+            }
+
+            // Recorded only once the certificates have the new endpoints, so a failed run is
+            // retried in full.
+            metadata.write_to_file(&self.cluster.metadata_path())?;
+
+            if self.apply {
+                self.apply_cluster()?;
+            }
+
+            Ok(Some(Message::EndpointsRefreshed {
+                applied: self.apply,
+                certificates,
+                cluster: self.cluster.name.to_owned(),
+            }))
+        })
+    }
+
+    /// Encrypts every encrypted file in the cluster's directory again with the KMS key given on
+    /// the command line, whichever key it was encrypted with before, e.g. to move the cluster to a
+    /// new key or another account's. Every file is decrypted before any is encrypted, and
+    /// encrypted before any is written, so a key that can't be used leaves the cluster as it was.
+    /// The PKI manifest records the new key for each current certificate before the files are
+    /// written, and goes back to the old one with the metadata if they can't be. Like rotating the
+    /// PKI, it needs the approvals the cluster requires, since it decrypts the CAs' keys.
+    ///
+    /// Every file is encrypted with its encryption context, so afterwards the metadata records
+    /// that the cluster requires them, and files are no longer decrypted without one.
+    pub fn reencrypt(&self) -> KawsResult {
+        let metadata = ClusterMetadata::from_file(&self.cluster.metadata_path())?;
+        let approvals = Approvals::new(self.cluster.name, "reencrypt", "files");
+
+        approvals.ensure_sufficient(&metadata)?;
+
+        let provider = cloud_provider(metadata.cloud_provider, cluster_credentials_provider(
+            self.cluster.name,
+            Access::ReadWrite,
+            self.aws_credentials_path.as_deref(),
+            self.aws_credentials_profile,
+        )?);
+        let paths = encrypted_file_paths(self.cluster.name)?;
+        let mut plaintexts = vec![];
+
+        log_wrap!("Decrypting the cluster's encrypted files", {
+            let mut decryptor = provider.encryptor(self.cluster.region(), None)?
+                .with_contexts_required(metadata.encryption_contexts_required);
+
+            for path in &paths {
+                plaintexts.push(decryptor.decrypt_file(path)?);
+            }
+        });
+
+        let mut encryptor = self.encryptor()?;
+        let files: Vec<(&[u8], &str)> = plaintexts.iter()
+            .map(Vec::as_slice)
+            .zip(paths.iter().map(String::as_str))
+            .collect();
+
+        let previous_manifest = PkiManifest::from_file(self.cluster.name)?;
+        let mut manifest = PkiManifest::from_file(self.cluster.name)?;
+        let current: BTreeSet<(String, String)> = manifest.current()?.into_iter()
+            .map(|entry| (entry.path, entry.fingerprint))
+            .collect();
+
+        for entry in &mut manifest.certificates {
+            if entry.kms_key_id.is_some() &&
+                current.contains(&(entry.path.clone(), entry.fingerprint.clone())) {
+                entry.kms_key_id = Some(self.kms_key_arn().to_owned());
+            }
+        }
+
+        log_wrap!("Encrypting the cluster's files with the new KMS key", {
+            // The encryptor recorded the new key in the metadata, which has to be undone along
+            // with the manifest if no file ends up encrypted with it.
+            let result = manifest.write_to_file(self.cluster.name)
+                .and_then(|_| encryptor.encrypt_and_write_files(&files));
+
+            if let Err(error) = result {
+                let mut current = ClusterMetadata::from_file(&self.cluster.metadata_path())?;
+
+                current.kms_key = metadata.kms_key;
+                current.write_to_file(&self.cluster.metadata_path())?;
+                previous_manifest.write_to_file(self.cluster.name)?;
+
+                return Err(error);
+            }
+        });
+
+        if !metadata.encryption_contexts_required {
+            let mut current = ClusterMetadata::from_file(&self.cluster.metadata_path())?;
+
+            current.encryption_contexts_required = true;
+            current.write_to_file(&self.cluster.metadata_path())?;
+        }
+
+        approvals.clear()?;
+
+        Ok(Some(Message::ClusterReencrypted {
+            cluster: self.cluster.name.to_owned(),
+            files: files.len(),
+            kms_key: self.kms_key_arn().to_owned(),
+        }))
+    }
+
+    /// Reissues every certificate the cluster's servers use from its existing CAs, which are left
+    /// as they are, so certificates about to expire can be replaced without the cluster trusting
+    /// anything new. CAs the cluster doesn't have, such as a front proxy CA created by an older
+    /// version of kaws, are skipped.
+    pub fn renew_pki(&self) -> KawsResult {
+        self.with_approvals("renew-pki", "certificates", || {
+            let key_algorithm = KeyAlgorithm::new(self.key_algo, self.key_size)?;
+            let metadata = ClusterMetadata::from_file(&self.cluster.metadata_path())?;
+            let before = self.pki_file_digests()?;
+
+            let mut encryptor = self.encryptor()?;
+
+            for ca_name in RENEWED_CAS.iter() {
+                let ca_cert_path = self.cluster.ca_cert_path(ca_name);
+
+                if Certificate::from_file_if_present(&ca_cert_path)?.is_none() {
+                    continue;
+                }
+
+                log_wrap!(format!("Reissuing the certificates of the {} CA", ca_name), {
+                    let ca = self.load_ca(&mut encryptor, ca_name, &metadata)?;
+
+                    self.issue_leaf_certs(ca_name, &ca, &mut encryptor, &metadata, key_algorithm)?;
+                });
+            }
+
+            let after = self.pki_file_digests()?;
+            let files: Vec<String> = after.iter()
+                .filter(|&(name, digest)| before.get(name) != Some(digest))
+                .map(|(name, _)| format!("clusters/{}/{}", self.cluster.name, name))
+                .collect();
+
+            if self.apply {
+                self.apply_cluster()?;
+            }
+
+            Ok(Some(Message::PkiRenewed {
+                applied: self.apply,
+                cluster: self.cluster.name.to_owned(),
+                files,
+            }))
+        })
+    }
+
+    /// Adds a certificate to the revocation list of the CA that issued it, creating the list if
+    /// this is the CA's first revocation. The CA is looked up in the cluster's PKI manifest unless
+    /// it is given.
+    pub fn revoke_certificate(&self) -> KawsResult {
+        let serial_number = self.serial_number.expect("clap should have required serial-number");
+
+        self.with_approvals("revoke", serial_number, || {
+            let crl_expiry = parse_duration(self.crl_expiry)
+                .expect("clap should have validated crl-expiry");
+            let ca = match self.ca {
+                Some(ca) => ca,
+                None => self.issuing_ca(serial_number)?,
+            };
+            let crl_path = self.cluster.crl_path(ca);
+            let metadata = ClusterMetadata::from_file(&self.cluster.metadata_path())?;
+
+            let mut encryptor = self.encryptor()?;
+
+            log_wrap!(format!("Revoking certificate {} of the {} CA", serial_number, ca), {
+                let authority = CertificateAuthority::from_files(
+                    &mut encryptor,
+                    &self.cluster.ca_cert_path(ca),
+                    &self.cluster.encrypted_ca_key_path(ca),
+                    metadata.external_ca_key(ca),
+                    self.backdate,
+                )?;
+                let previous = self.read_crl(&mut encryptor, ca)?;
+
+                let crl = authority.revoke(previous.as_ref(), serial_number, crl_expiry)?;
+
+                self.write_crl(&crl, ca)?;
+            });
+
+            Ok(Some(Message::CertificateRevoked {
+                crl_path,
+                serial_number: serial_number.to_owned(),
+            }))
+        })
+    }
+
+    /// Responds to the compromise of a node: revokes its client certificate, removes it from the
+    /// cluster, and terminates its instance, then waits for the instance the autoscaling group
+    /// launches in its place to join the cluster.
+    ///
+    /// With TLS bootstrapping, the certificates the Kubernetes certificates API issued to the node
+    /// are revoked, and its replacement requests one of its own. Otherwise nodes share one
+    /// certificate, which is revoked and reissued, and the cluster applied before the instance is
+    /// terminated so its replacement gets the new one.
+    pub fn revoke_node(&self) -> KawsResult {
+        let instance_id = self.instance_id.expect("clap should have required instance-id");
+
+        self.with_approvals("revoke-node", instance_id, || {
+            let crl_expiry = parse_duration(self.crl_expiry)
+                .expect("clap should have validated crl-expiry");
+            let timeout = parse_duration(self.timeout).expect("clap should have validated timeout");
+            let key_algorithm = KeyAlgorithm::new(self.key_algo, self.key_size)?;
+            let metadata = ClusterMetadata::from_file(&self.cluster.metadata_path())?;
+            let provider = cluster_credentials_provider(
+                self.cluster.name,
+                Access::ReadWrite,
+                self.aws_credentials_path.as_deref(),
+                self.aws_credentials_profile,
             )?;
+            let node = Node::find(
+                self.cluster.name,
+                instance_id,
+                &provider,
+                self.cluster.region(),
+            )?;
+            let shared = !self.tls_bootstrapping()?;
+            let serial_numbers = if shared {
+                match Certificate::from_file_if_present(&self.cluster.k8s_node_cert_path())? {
+                    Some(cert) => vec![cert.info()?.serial_number],
+                    None => vec![],
+                }
+            } else {
+                node.client_certificate_serial_numbers()?
+            };
+            let mut encryptor = self.encryptor()?;
+
+            log_wrap!(format!("Revoking the client certificates of node {}", node.name), {
+                let ca = CertificateAuthority::from_files(
+                    &mut encryptor,
+                    &self.cluster.k8s_ca_cert_path(),
+                    &self.cluster.k8s_encrypted_ca_key_path(),
+                    metadata.external_ca_key("k8s"),
+                    self.backdate,
+                )?;
+                let mut crl = self.read_crl(&mut encryptor, "k8s")?;
+
+                for serial_number in &serial_numbers {
+                    crl = Some(ca.revoke(crl.as_ref(), serial_number, crl_expiry)?);
+                }
+
+                if let Some(crl) = crl {
+                    self.write_crl(&crl, "k8s")?;
+                }
+
+                if shared {
+                    let node_expiry = self.cert_expiry(&metadata, "k8s-node")?;
+
+                    self.issue_k8s_node_cert(&ca, &mut encryptor, node_expiry, key_algorithm)?;
+                }
+            });
+
+            if shared {
+                self.apply_cluster()?;
+            }
+
+            let previous = node.node_names()?;
+
+            log_wrap!(format!("Removing node {} from the cluster", node.name), {
+                node.remove()?;
+            });
+
+            log_wrap!(format!("Terminating instance {}", instance_id), {
+                node.terminate()?;
+            });
+
+            let replacement;
+
+            log_wrap!("Waiting for a replacement node to join the cluster", {
+                replacement = node.wait_for_replacement(&previous, timeout)?;
+            });
+
+            Ok(Some(Message::NodeRevoked {
+                cluster: self.cluster.name.to_owned(),
+                instance_id: instance_id.to_owned(),
+                node: node.name,
+                replacement,
+                serial_numbers,
+            }))
+        })
+    }
 
-            ca
-        } else {
-            CertificateAuthority::from_files(
-                &mut encryptor,
-                &self.cluster.etcd_ca_cert_path(),
-                &self.cluster.etcd_encrypted_ca_key_path(),
-            )?
-        };
+    /// Runs one phase of replacing the etcd, etcd peer, and Kubernetes CAs without downtime.
+    ///
+    /// "start" generates new CAs and adds them to the trusted CA bundles, "reissue" reissues every
+    /// certificate with the new CAs, and "finish" removes the old CAs from the bundles. The cluster
+    /// must be applied after each phase, so every server trusts both CAs while certificates change.
+    pub fn rotate_pki(&self) -> KawsResult {
+        let phase = self.rotation_phase.expect("clap should have required phase");
+        let key_algorithm = KeyAlgorithm::new(self.key_algo, self.key_size)?;
+        let metadata = ClusterMetadata::from_file(&self.cluster.metadata_path())?;
+
+        // Check every CA before changing any, so a phase run out of order changes nothing. Each
+        // step can be repeated safely, so a phase that failed partway can be run again.
+        let mut pending = vec![];
+
+        for ca in ROTATED_CAS.iter() {
+            if let Some(key) = metadata.external_ca_key(ca) {
+                return Err(key.unsupported("rotate the CA"));
+            }
+
+            let stage = self.rotation_stage(ca)?;
+
+            match (phase, stage) {
+                ("start", RotationStage::Idle) |
+                ("start", RotationStage::Started) |
+                ("reissue", RotationStage::Started) |
+                ("finish", RotationStage::Reissued) => pending.push(*ca),
+                ("reissue", RotationStage::Reissued) |
+                ("finish", RotationStage::Idle) => {}
+                _ => return Err(KawsError::new(Message::PkiRotationOutOfOrder {
+                    cluster: self.cluster.name.to_owned(),
+                    phase: phase.to_owned(),
+                    next_phase: stage.next_phase().to_owned(),
+                })),
+            }
+        }
 
-        if self.subject == "ca" || self.subject == "server" {
-            let (server_cert, server_key) = ca.generate_cert(
-                &format!("kaws-etcd-server-{}", self.cluster.name),
-                Some(&[
-                    "10.0.1.4",
-                    "10.0.1.5",
-                    "10.0.1.6",
-                ]),
-                None,
+        let approvals = Approvals::new(self.cluster.name, "rotate-pki", phase);
+
+        approvals.ensure_sufficient(&metadata)?;
+
+        let mut encryptor = self.encryptor()?;
+
+        for ca in pending {
+            match phase {
+                "start" => {
+                    let expiry = self.ca_expiry(&metadata, &format!("{}-ca", ca))?;
+
+                    log_wrap!(format!("Generating a new {} CA", ca), {
+                        self.start_ca_rotation(ca, &mut encryptor, expiry, key_algorithm)?;
+                    });
+                }
+                "reissue" => {
+                    log_wrap!(format!("Reissuing certificates with the new {} CA", ca), {
+                        self.reissue_with_next_ca(ca, &mut encryptor, &metadata, key_algorithm)?;
+                    });
+                }
+                _ => {
+                    log_wrap!(format!("Removing the old {} CA", ca), {
+                        self.finish_ca_rotation(ca)?;
+                    });
+                }
+            }
+        }
+
+        approvals.clear()?;
+
+        Ok(Some(Message::PkiRotationPhaseCompleted {
+            cluster: self.cluster.name.to_owned(),
+            phase: phase.to_owned(),
+        }))
+    }
+
+    /// Runs one phase of replacing the service account key pair without invalidating tokens.
+    ///
+    /// "start" generates a new key pair and trusts its public key alongside the current one,
+    /// "reissue" signs new tokens with the new key, and "finish" stops trusting the old key. The
+    /// cluster must be applied after each phase, and tokens signed with the old key replaced
+    /// before the last.
+    pub fn rotate_service_account_key(&self) -> KawsResult {
+        let phase = self.rotation_phase.expect("clap should have required phase");
+        let stage = self.service_account_rotation_stage()?;
+
+        match (phase, stage) {
+            ("start", RotationStage::Idle) | ("start", RotationStage::Started) => {
+                let key_algorithm = KeyAlgorithm::new(self.key_algo, self.key_size)?;
+
+                let mut encryptor = self.encryptor()?;
+
+                log_wrap!("Generating a new service account key pair", {
+                    self.start_service_account_key_rotation(&mut encryptor, key_algorithm)?;
+                });
+            }
+            ("reissue", RotationStage::Started) => {
+                let mut encryptor = self.encryptor()?;
+
+                log_wrap!("Signing tokens with the new service account key", {
+                    // The key is encrypted again rather than renamed, since its encryption context
+                    // names the file it's in. The next public key is removed last, since its
+                    // presence marks the phase as not done.
+                    let next_key_path = self.cluster.service_account_next_encrypted_key_path();
+
+                    if Path::new(&next_key_path).exists() {
+                        PrivateKey::from_file(&mut encryptor, &next_key_path)?.write_to_file(
+                            &mut encryptor,
+                            &self.cluster.service_account_encrypted_key_path(),
+                        )?;
+                        remove_encrypted_file(&next_key_path)?;
+                    }
+
+                    remove_file(self.cluster.service_account_next_public_key_path())?;
+                });
+            }
+            ("finish", RotationStage::Reissued) => {
+                log_wrap!("Removing the old service account public key", {
+                    let public_key_path = self.cluster.service_account_public_key_path();
+                    let mut public_keys = PublicKey::from_file(&public_key_path)?.unbundle()?;
+
+                    public_keys.split_off(public_keys.len() - 1)
+                        .remove(0)
+                        .write_to_file(&public_key_path)?;
+                });
+            }
+            ("reissue", RotationStage::Reissued) | ("finish", RotationStage::Idle) => {}
+            _ => return Err(KawsError::new(Message::PkiRotationOutOfOrder {
+                cluster: self.cluster.name.to_owned(),
+                phase: phase.to_owned(),
+                next_phase: stage.next_phase().to_owned(),
+            })),
+        }
+
+        Ok(Some(Message::ServiceAccountKeyRotationPhaseCompleted {
+            cluster: self.cluster.name.to_owned(),
+            phase: phase.to_owned(),
+        }))
+    }
+
+    pub fn generate_ssh_pki(&self) -> KawsResult {
+        self.with_approvals("generate-pki", "ssh", || {
+            ensure_ssh_keygen()?;
+            self.ensure_replaceable(&self.ssh_ca_paths())?;
+
+            let mut encryptor = self.encryptor()?;
+
+            let user_ca = SshCertificateAuthority::generate(
+                &format!("kaws-ssh-user-ca-{}", self.cluster.name),
             )?;
 
-            server_cert.write_to_file(&self.cluster.etcd_server_cert_path())?;
-            server_key.write_to_file(
+            user_ca.write_to_files(
                 &mut encryptor,
-                &self.cluster.etcd_encrypted_server_key_path(),
+                &self.cluster.ssh_user_ca_public_key_path(),
+                &self.cluster.ssh_user_encrypted_ca_key_path(),
             )?;
-        }
 
-        if self.subject == "ca" || self.subject == "client" {
-            let (client_cert, client_key) = ca.generate_cert(
-                &format!("kaws-etcd-client-{}", self.cluster.name),
-                None,
-                None,
+            let host_ca = SshCertificateAuthority::generate(
+                &format!("kaws-ssh-host-ca-{}", self.cluster.name),
             )?;
 
-            client_cert.write_to_file(&self.cluster.etcd_client_cert_path())?;
-            client_key.write_to_file(
+            host_ca.write_to_files(
                 &mut encryptor,
-                &self.cluster.etcd_encrypted_client_key_path(),
+                &self.cluster.ssh_host_ca_public_key_path(),
+                &self.cluster.ssh_host_encrypted_ca_key_path(),
             )?;
+
+            log_wrap!("Creating SSH known hosts file", {
+                let mut file = File::create(self.cluster.ssh_known_hosts_path())?;
+
+                writeln!(file, "@cert-authority * {}", host_ca.public_key())?;
+            });
+
+            Ok(None)
+        })
+    }
+
+    // Private
+
+    // Encrypts with the KMS key given on the command line, and with the keys of the cluster's other
+    // KMS regions into each file's regional copies, through the cloud provider recorded in the
+    // cluster's metadata.
+    fn encryptor(&self) -> Result<Encryptor<'_>, KawsError> {
+        self.resolve_kms_key()?;
+
+        let metadata = ClusterMetadata::from_file(&self.cluster.metadata_path())?;
+        let provider = cloud_provider(metadata.cloud_provider, cluster_credentials_provider(
+            self.cluster.name,
+            Access::ReadWrite,
+            self.aws_credentials_path.as_deref(),
+            self.aws_credentials_profile,
+        )?);
+
+        let regional_keys = metadata.kms_regions.iter()
+            .map(|(region, key)| (region.clone(), key.arn.clone()))
+            .collect();
+
+        let encryptor = provider.encryptor(self.cluster.region(), Some(self.kms_key_arn()))?
+            .with_contexts_required(metadata.encryption_contexts_required)
+            .with_regional_keys(&regional_keys)?;
+
+        Ok(match metadata.secret_store {
+            Some(ref store) => encryptor.with_secret_store(store),
+            None => encryptor,
+        })
+    }
+
+    // Resolves the KMS key given on the command line, which may be an alias, to the ARN of the key
+    // it names, and records both in the cluster's metadata. It's resolved once per run, so an
+    // alias pointed at another key midway doesn't split the run's files between keys.
+    fn resolve_kms_key(&self) -> KawsResult {
+        if self.kms_key_arn.get().is_some() {
+            return Ok(None);
         }
 
+        let mut metadata = ClusterMetadata::from_file(&self.cluster.metadata_path())?;
+        let provider = cloud_provider(metadata.cloud_provider, cluster_credentials_provider(
+            self.cluster.name,
+            Access::ReadWrite,
+            self.aws_credentials_path.as_deref(),
+            self.aws_credentials_profile,
+        )?);
+        let arn = provider.encryptor(self.cluster.region(), None)?
+            .key_arn(self.kms_master_key_id)?;
+
+        metadata.kms_key = Some(KmsKey {
+            arn: arn.clone(),
+            id: self.kms_master_key_id.to_owned(),
+        });
+        metadata.write_to_file(&self.cluster.metadata_path())?;
+
+        let _ = self.kms_key_arn.set(arn);
+
         Ok(None)
     }
 
-    pub fn generate_etcd_peer_pki(&self) -> KawsResult {
-        let mut encryptor = Encryptor::new(
-            self.aws_credentials_provider.clone(),
-            self.cluster.region().parse()?,
-            Some(self.kms_master_key_id),
+    // The ARN the KMS key given on the command line resolved to, or the key as given until the
+    // encryptor resolves it.
+    fn kms_key_arn(&self) -> &str {
+        self.kms_key_arn.get().map(String::as_str).unwrap_or(self.kms_master_key_id)
+    }
+
+    // Runs `run` once the approvals the cluster requires for `operation` on `target` have been
+    // given, and removes them once it succeeds, as for rotating the PKI.
+    fn with_approvals<F>(&self, operation: &str, target: &str, run: F) -> KawsResult
+    where
+        F: FnOnce() -> KawsResult,
+    {
+        if self.approved {
+            return run();
+        }
+
+        let approvals = Approvals::new(self.cluster.name, operation, target);
+
+        approvals.ensure_sufficient(&ClusterMetadata::from_file(&self.cluster.metadata_path())?)?;
+
+        let result = run()?;
+
+        approvals.clear()?;
+
+        Ok(result)
+    }
+
+    // Applies the cluster with `kaws cluster apply`, so its servers get new certificates.
+    fn apply_cluster(&self) -> KawsResult {
+        let mut args = vec!["cluster", "apply", self.cluster.name];
+
+        if let Some(ref path) = self.aws_credentials_path {
+            args.extend(["--aws-credentials-path", path]);
+        }
+
+        if let Some(profile) = self.aws_credentials_profile {
+            args.extend(["--aws-credentials-profile", profile]);
+        }
+
+        let status = Command::new("kaws").args(&args).status()?;
+
+        if !status.success() {
+            return Err(KawsError::new(Message::ChildProcessFailed {
+                command: format!("kaws {}", args.join(" ")),
+            }));
+        }
+
+        Ok(None)
+    }
+
+    // Refuses to overwrite any of `paths` that already hold a CA or key, unless `--force` was
+    // given and the user confirms by typing the cluster's name. The empty files `kaws cluster init`
+    // creates as placeholders don't count.
+    fn ensure_replaceable(&self, paths: &[String]) -> KawsResult {
+        if self.replacement_confirmed {
+            return Ok(None);
+        }
+
+        let mut existing = vec![];
+
+        for path in paths {
+            match metadata(path) {
+                Ok(metadata) => if metadata.len() > 0 {
+                    existing.push(path.clone());
+                },
+                Err(ref error) if error.kind() == ErrorKind::NotFound => {}
+                Err(error) => return Err(error.into()),
+            }
+        }
+
+        if existing.is_empty() {
+            return Ok(None);
+        }
+
+        if !self.force {
+            return Err(KawsError::new(Message::PkiAlreadyExists {
+                cluster: self.cluster.name.to_owned(),
+                paths: existing,
+            }));
+        }
+
+        println!("This will replace:");
+
+        for path in &existing {
+            println!("  {}", path);
+        }
+
+        print!(
+            "Every certificate or token issued with these will stop working. Type the cluster's \
+            name to continue: ",
         );
+        io::stdout().flush()?;
+
+        let mut answer = String::new();
+
+        io::stdin().read_line(&mut answer)?;
+
+        if answer.trim() != self.cluster.name {
+            return Err(KawsError::new(Message::PkiReplacementDeclined {
+                cluster: self.cluster.name.to_owned(),
+            }));
+        }
+
+        Ok(None)
+    }
 
-        let ca = if self.subject == "ca" {
-            let ca = CertificateAuthority::generate(
-                &format!("kaws-etcd-peer-ca-{}", self.cluster.name)
+    // The files creating the CA named `ca` writes. An intermediate CA whose key is already stored
+    // only has its certificate written, and a request for one only its key.
+    fn ca_paths(&self, ca: &str) -> Vec<String> {
+        if self.intermediate_csr {
+            vec![self.cluster.csr_encrypted_ca_key_path(ca)]
+        } else if self.intermediate_cert.is_some() && self.intermediate_key.is_none() {
+            vec![self.cluster.ca_cert_path(ca)]
+        } else {
+            vec![self.cluster.ca_cert_path(ca), self.cluster.encrypted_ca_key_path(ca)]
+        }
+    }
+
+    fn service_account_paths(&self) -> Vec<String> {
+        vec![
+            self.cluster.service_account_public_key_path(),
+            self.cluster.service_account_encrypted_key_path(),
+        ]
+    }
+
+    fn ssh_ca_paths(&self) -> Vec<String> {
+        vec![
+            self.cluster.ssh_user_ca_public_key_path(),
+            self.cluster.ssh_user_encrypted_ca_key_path(),
+            self.cluster.ssh_host_ca_public_key_path(),
+            self.cluster.ssh_host_encrypted_ca_key_path(),
+        ]
+    }
+
+    // Creates the CA whose files start with `ca`, self-signed unless it is an intermediate of an
+    // external root CA. With `--intermediate-csr`, only the key and a request for the root to
+    // sign are written, and there is no CA yet.
+    //
+    // A CA whose key is in KMS gets a self-signed certificate for that key. One whose key is on a
+    // PKCS #11 token is created on the token, and its certificate copied into the cluster's
+    // directory by hand.
+    fn create_ca(
+        &self,
+        encryptor: &mut Encryptor,
+        ca: &str,
+        expiry: Duration,
+        key_algorithm: KeyAlgorithm,
+    ) -> Result<Option<CertificateAuthority>, KawsError> {
+        let common_name = format!("kaws-{}-ca-{}", ca, self.cluster.name);
+
+        let metadata = ClusterMetadata::from_file(&self.cluster.metadata_path())?;
+        let external_key = metadata.external_ca_key(ca);
+        let subject_fields = metadata.subject_fields(self.subject_fields)?;
+
+        match external_key {
+            Some(key @ ExternalCaKey::Pkcs11(_)) => {
+                return Err(key.unsupported("generate a new CA"));
+            }
+            Some(key) if self.intermediate_csr || self.intermediate_cert.is_some() => {
+                return Err(key.unsupported("make the CA an intermediate"));
+            }
+            _ => {}
+        }
+
+        self.ensure_replaceable(&self.ca_paths(ca))?;
+
+        if let Some(ExternalCaKey::Kms(key_id)) = external_key {
+            let authority = CertificateAuthority::generate_in_kms(
+                &common_name,
+                &subject_fields,
+                self.backdate,
+                expiry,
+                encryptor.signer(key_id),
+                Provenance::new(self.cluster.name),
             )?;
 
-            ca.write_to_files(
-                &mut encryptor,
-                &self.cluster.etcd_peer_ca_cert_path(),
-                &self.cluster.etcd_peer_encrypted_ca_key_path(),
+            self.write_ca(encryptor, ca, &authority, None)?;
+
+            return Ok(Some(authority));
+        }
+
+        if self.intermediate_csr {
+            let (csr, key) = CertificateSigningRequest::generate_ca(
+                &common_name,
+                &subject_fields,
+                key_algorithm,
             )?;
 
-            ca
-        } else {
-            CertificateAuthority::from_files(
-                &mut encryptor,
-                &self.cluster.etcd_peer_ca_cert_path(),
-                &self.cluster.etcd_peer_encrypted_ca_key_path(),
-            )?
+            key.write_to_file(encryptor, &self.cluster.csr_encrypted_ca_key_path(ca))?;
+            csr.write_to_file(&self.cluster.ca_csr_path(ca))?;
+
+            return Ok(None);
+        }
+
+        let authority = match self.intermediate_cert {
+            Some(ref cert_path) => {
+                let root = Certificate::from_file(
+                    self.root_cert.as_deref().expect("clap should have required root-cert"),
+                )?;
+                let key = match self.intermediate_key {
+                    Some(ref key_path) => PrivateKey::from_file_unencrypted(key_path)?,
+                    None => PrivateKey::from_file(
+                        encryptor,
+                        &self.cluster.csr_encrypted_ca_key_path(ca),
+                    )?,
+                };
+
+                let authority = CertificateAuthority::from_certificate(
+                    Certificate::from_file(cert_path)?,
+                    key,
+                    Some(&root),
+                    self.backdate,
+                )?
+                    .with_subject_fields(subject_fields)
+                    .with_provenance(Provenance::new(self.cluster.name));
+
+                self.write_ca(encryptor, ca, &authority, Some(&root))?;
+
+                authority
+            }
+            None => {
+                let authority = CertificateAuthority::generate(
+                    &common_name,
+                    &subject_fields,
+                    self.backdate,
+                    expiry,
+                    key_algorithm,
+                    Provenance::new(self.cluster.name),
+                )?;
+
+                self.write_ca(encryptor, ca, &authority, None)?;
+
+                authority
+            }
         };
 
-        let (peer_cert, peer_key) = ca.generate_cert(
+        Ok(Some(authority))
+    }
+
+    // Writes the CA whose files start with `ca`, along with the root CA it chains to unless it is
+    // its own root, and removes the request for it to be signed, which has been dealt with, and the
+    // key kept for it, which the CA now has.
+    fn write_ca(
+        &self,
+        encryptor: &mut Encryptor,
+        ca: &str,
+        authority: &CertificateAuthority,
+        root: Option<&Certificate>,
+    ) -> KawsResult {
+        let csr_path = self.cluster.ca_csr_path(ca);
+        let csr_key_path = self.cluster.csr_encrypted_ca_key_path(ca);
+        let root_path = self.cluster.root_ca_cert_path(ca);
+
+        authority.write_to_files(
+            encryptor,
+            &self.cluster.ca_cert_path(ca),
+            &self.cluster.encrypted_ca_key_path(ca),
+        )?;
+        PkiManifest::record(
+            self.cluster.name,
+            &self.cluster.ca_cert_path(ca),
+            authority.certificate(),
+            Some(self.kms_key_arn()),
+        )?;
+
+        match root {
+            Some(root) => {
+                root.write_to_file(&root_path)?;
+                PkiManifest::record(self.cluster.name, &root_path, root, None)?;
+            }
+            None => if Path::new(&root_path).exists() {
+                remove_file(&root_path)?;
+            },
+        }
+
+        if Path::new(&csr_path).exists() {
+            remove_file(&csr_path)?;
+        }
+
+        if Path::new(&csr_key_path).exists() {
+            remove_encrypted_file(&csr_key_path)?;
+        }
+
+        Ok(None)
+    }
+
+    // The revocation list of the CA whose files start with `ca`, if it has revoked anything. A list
+    // older versions of kaws kept encrypted is read until it's written again.
+    fn read_crl(&self, encryptor: &mut Encryptor, ca: &str)
+    -> Result<Option<CertificateRevocationList>, KawsError> {
+        let crl = CertificateRevocationList::from_file_if_present(&self.cluster.crl_path(ca))?;
+
+        if crl.is_some() {
+            return Ok(crl);
+        }
+
+        let legacy_path = self.cluster.legacy_encrypted_crl_path(ca);
+
+        if Path::new(&legacy_path).exists() {
+            Ok(Some(CertificateRevocationList::from_encrypted_file(encryptor, &legacy_path)?))
+        } else {
+            Ok(None)
+        }
+    }
+
+    // Writes the revocation list of the CA whose files start with `ca`, unencrypted since it's
+    // public, and removes the encrypted one an older version of kaws kept.
+    fn write_crl(&self, crl: &CertificateRevocationList, ca: &str) -> KawsResult {
+        let legacy_path = self.cluster.legacy_encrypted_crl_path(ca);
+
+        crl.write_to_file(&self.cluster.crl_path(ca))?;
+
+        if Path::new(&legacy_path).exists() {
+            remove_encrypted_file(&legacy_path)?;
+        }
+
+        Ok(None)
+    }
+
+    // Writes a certificate `ca` issued to `path` and records it in the cluster's PKI manifest.
+    fn write_issued(&self, ca: &CertificateAuthority, cert: &Certificate, path: &str) -> KawsResult {
+        ca.write_issued(cert, path)?;
+
+        PkiManifest::record(self.cluster.name, path, cert, Some(self.kms_key_arn()))
+    }
+
+    // The CA that issued the certificate with serial number `serial_number`, found through the
+    // cluster's PKI manifest by matching the certificate's issuer with a CA's subject.
+    fn issuing_ca(&self, serial_number: &str) -> Result<&'static str, KawsError> {
+        let manifest = PkiManifest::from_file(self.cluster.name)?;
+        let not_found = || KawsError::new(Message::CertificateNotInManifest {
+            cluster: self.cluster.name.to_owned(),
+            serial_number: serial_number.to_owned(),
+        });
+        let entry = manifest.find_serial_number(serial_number).ok_or_else(not_found)?;
+
+        REVOCABLE_CAS.iter().find(|ca| {
+            let ca_cert_path = self.cluster.ca_cert_path(ca);
+
+            manifest.certificates.iter().any(|ca_entry| {
+                ca_entry.path == ca_cert_path && ca_entry.subject == entry.issuer
+            })
+        }).cloned().ok_or_else(not_found)
+    }
+
+    fn intermediate_ca_requested(&self, ca: &str) -> Message {
+        Message::IntermediateCaRequested {
+            cluster: self.cluster.name.to_owned(),
+            csr_path: self.cluster.ca_csr_path(ca),
+        }
+    }
+
+    // Loads the CA whose files start with `ca` to issue certificates with, adding the cluster's
+    // subject fields to them.
+    fn load_ca(&self, encryptor: &mut Encryptor, ca: &str, metadata: &ClusterMetadata)
+    -> Result<CertificateAuthority, KawsError> {
+        Ok(CertificateAuthority::from_files(
+            encryptor,
+            &self.cluster.ca_cert_path(ca),
+            &self.cluster.encrypted_ca_key_path(ca),
+            metadata.external_ca_key(ca),
+            self.backdate,
+        )?
+            .with_subject_fields(metadata.subject_fields(self.subject_fields)?)
+            .with_provenance(Provenance::new(self.cluster.name)))
+    }
+
+    // The SHA-256 digests of the certificates and encrypted keys in the cluster's directory, by
+    // file name, to tell which files reissuing certificates changed.
+    fn pki_file_digests(&self) -> Result<BTreeMap<String, [u8; 32]>, KawsError> {
+        let mut digests = BTreeMap::new();
+
+        for entry in entries(&format!("clusters/{}", self.cluster.name))? {
+            if entry.name.ends_with(".pem") || entry.name.ends_with("-encrypted.base64") {
+                let mut contents = vec![];
+
+                File::open(&entry.path)?.read_to_end(&mut contents)?;
+                digests.insert(entry.name, sha256(&contents));
+            }
+        }
+
+        Ok(digests)
+    }
+
+    fn ca_expiry(&self, metadata: &ClusterMetadata, name: &str) -> Result<Duration, KawsError> {
+        metadata.certificate_expiry_for(name, self.ca_expiry, DEFAULT_CA_EXPIRY, false)
+    }
+
+    // Certificates expire as given on the command line or recorded in the cluster's metadata,
+    // and otherwise as their signing profile says. None of the servers' certificates gets the
+    // short-lived default: they're baked into launch configurations and user data, and nothing
+    // on the servers renews them before the servers are replaced.
+    fn cert_expiry(&self, metadata: &ClusterMetadata, name: &str) -> Result<Duration, KawsError> {
+        let profile = self.signing_profile(name)?;
+        let default = profile.expiry.as_deref().unwrap_or(DEFAULT_CERT_EXPIRY);
+
+        metadata.certificate_expiry_for(name, self.cert_expiry, default, false)
+    }
+
+    // The value of one of the cluster's Terraform outputs, or nothing if the cluster hasn't been
+    // applied with it yet. Looked up through `kaws cluster output`, which caches it. Any other
+    // failure, e.g. to read the state, is an error, rather than taken for a missing output.
+    fn terraform_output(&self, name: &str) -> Result<Option<String>, KawsError> {
+        let output = Command::new("kaws")
+            .args(["cluster", "output", self.cluster.name, name])
+            .output()?;
+        let value = String::from_utf8_lossy(&output.stdout).trim().to_owned();
+
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr).into_owned();
+
+            if OUTPUT_MISSING_ERRORS.iter().any(|error| stderr.contains(error)) {
+                return Ok(None);
+            }
+
+            return Err(KawsError::with_std_streams(
+                Message::ChildProcessFailed {
+                    command: format!("kaws cluster output {} {}", self.cluster.name, name),
+                },
+                value,
+                stderr,
+            ));
+        }
+
+        if value.is_empty() {
+            return Ok(None);
+        }
+
+        Ok(Some(value))
+    }
+
+    // The signing profile for the certificates whose expiry is configured as `name`.
+    fn signing_profile(&self, name: &str) -> Result<SigningProfile, KawsError> {
+        Ok(PkiProfiles::from_file(self.cluster.name)?.for_certificate(name))
+    }
+
+    // Whether each etcd member gets server and peer certificates of its own, as recorded in the
+    // cluster's Terraform variables by `kaws cluster init --etcd-member-certificates`.
+    fn etcd_member_certificates(&self) -> Result<bool, KawsError> {
+        let tfvars = Tfvars::from_file(&self.cluster.tfvars_path())?;
+
+        Ok(tfvars.get("kaws_etcd_member_certificates") == Some("true"))
+    }
+
+    // Whether nodes request client certificates of their own through the Kubernetes certificates
+    // API, as recorded in the cluster's Terraform variables by `kaws cluster init
+    // --tls-bootstrapping`.
+    fn tls_bootstrapping(&self) -> Result<bool, KawsError> {
+        let tfvars = Tfvars::from_file(&self.cluster.tfvars_path())?;
+
+        Ok(tfvars.get("kaws_tls_bootstrapping") == Some("true"))
+    }
+
+    // The private DNS name EC2 gives the etcd member at `ip`.
+    fn etcd_member_dns_name(&self, ip: &str) -> String {
+        let host = format!("ip-{}", ip.replace('.', "-"));
+
+        match self.cluster.region() {
+            "us-east-1" => format!("{}.ec2.internal", host),
+            region => format!("{}.{}.compute.internal", host, region),
+        }
+    }
+
+    fn issue_etcd_server_cert(
+        &self,
+        ca: &CertificateAuthority,
+        encryptor: &mut Encryptor,
+        expiry: Duration,
+        key_algorithm: KeyAlgorithm,
+    ) -> KawsResult {
+        let profile = self.signing_profile("etcd-server")?;
+
+        if self.etcd_member_certificates()? {
+            let mut keys = vec![];
+
+            for &(member, ip) in ETCD_MEMBERS.iter() {
+                let (cert, key) = ca.generate_cert(
+                    &format!("kaws-etcd-server-{}-{}", self.cluster.name, member),
+                    Some(&[&self.etcd_member_dns_name(ip), ip]),
+                    None,
+                    expiry,
+                    key_algorithm,
+                    Some(&profile),
+                )?;
+
+                self.write_issued(ca, &cert, &self.cluster.etcd_member_server_cert_path(member))?;
+                keys.push((key, self.cluster.etcd_member_encrypted_server_key_path(member)));
+            }
+
+            // Encrypted together, so the members' keys cost one round of KMS requests.
+            let files: Vec<(&[u8], &str)> = keys.iter()
+                .map(|(key, path)| (key.as_bytes(), path.as_str()))
+                .collect();
+
+            return encryptor.encrypt_and_write_files(&files);
+        }
+
+        let (cert, key) = ca.generate_cert(
+            &format!("kaws-etcd-server-{}", self.cluster.name),
+            Some(&[
+                "10.0.1.4",
+                "10.0.1.5",
+                "10.0.1.6",
+            ]),
+            None,
+            expiry,
+            key_algorithm,
+            Some(&profile),
+        )?;
+
+        self.write_issued(ca, &cert, &self.cluster.etcd_server_cert_path())?;
+        key.write_to_file(encryptor, &self.cluster.etcd_encrypted_server_key_path())
+    }
+
+    // The API server gets a client certificate for etcd of its own, rather than sharing the one
+    // flannel and locksmith use on every server, so etcd can tell it apart from them.
+    fn issue_etcd_apiserver_client_cert(
+        &self,
+        ca: &CertificateAuthority,
+        encryptor: &mut Encryptor,
+        expiry: Duration,
+        key_algorithm: KeyAlgorithm,
+    ) -> KawsResult {
+        let profile = self.signing_profile("etcd-apiserver-client")?;
+
+        let (cert, key) = ca.generate_cert(
+            &format!("kaws-etcd-apiserver-client-{}", self.cluster.name),
+            None,
+            None,
+            expiry,
+            key_algorithm,
+            Some(&profile),
+        )?;
+
+        self.write_issued(ca, &cert, &self.cluster.etcd_apiserver_client_cert_path())?;
+        key.write_to_file(encryptor, &self.cluster.etcd_encrypted_apiserver_client_key_path())
+    }
+
+    fn issue_etcd_client_cert(
+        &self,
+        ca: &CertificateAuthority,
+        encryptor: &mut Encryptor,
+        expiry: Duration,
+        key_algorithm: KeyAlgorithm,
+    ) -> KawsResult {
+        let profile = self.signing_profile("etcd-client")?;
+
+        let (cert, key) = ca.generate_cert(
+            &format!("kaws-etcd-client-{}", self.cluster.name),
+            None,
+            None,
+            expiry,
+            key_algorithm,
+            Some(&profile),
+        )?;
+
+        self.write_issued(ca, &cert, &self.cluster.etcd_client_cert_path())?;
+        key.write_to_file(encryptor, &self.cluster.etcd_encrypted_client_key_path())
+    }
+
+    fn issue_etcd_peer_cert(
+        &self,
+        ca: &CertificateAuthority,
+        encryptor: &mut Encryptor,
+        expiry: Duration,
+        key_algorithm: KeyAlgorithm,
+    ) -> KawsResult {
+        let profile = self.signing_profile("etcd-peer")?;
+
+        if self.etcd_member_certificates()? {
+            let mut keys = vec![];
+
+            for &(member, ip) in ETCD_MEMBERS.iter() {
+                let (cert, key) = ca.generate_cert(
+                    &format!("kaws-etcd-peer-{}-{}", self.cluster.name, member),
+                    Some(&[&self.etcd_member_dns_name(ip), ip]),
+                    None,
+                    expiry,
+                    key_algorithm,
+                    Some(&profile),
+                )?;
+
+                self.write_issued(ca, &cert, &self.cluster.etcd_member_peer_cert_path(member))?;
+                keys.push((key, self.cluster.etcd_member_peer_encrypted_key_path(member)));
+            }
+
+            // Encrypted together, so the members' keys cost one round of KMS requests.
+            let files: Vec<(&[u8], &str)> = keys.iter()
+                .map(|(key, path)| (key.as_bytes(), path.as_str()))
+                .collect();
+
+            return encryptor.encrypt_and_write_files(&files);
+        }
+
+        let (cert, key) = ca.generate_cert(
             &format!("kaws-etcd-peer-{}", self.cluster.name),
             Some(&[
                 "10.0.1.4",
@@ -251,130 +2031,607 @@ impl<'a> ExistingCluster<'a> {
                 "10.0.1.6",
             ]),
             None,
+            expiry,
+            key_algorithm,
+            Some(&profile),
         )?;
 
-        peer_cert.write_to_file(&self.cluster.etcd_peer_cert_path())?;
-        peer_key.write_to_file(
-            &mut encryptor,
-            &self.cluster.etcd_peer_encrypted_key_path(),
+        self.write_issued(ca, &cert, &self.cluster.etcd_peer_cert_path())?;
+        key.write_to_file(encryptor, &self.cluster.etcd_peer_encrypted_key_path())
+    }
+
+    fn issue_front_proxy_client_cert(
+        &self,
+        ca: &CertificateAuthority,
+        encryptor: &mut Encryptor,
+        expiry: Duration,
+        key_algorithm: KeyAlgorithm,
+    ) -> KawsResult {
+        let profile = self.signing_profile("front-proxy-client")?;
+
+        // The API server only accepts request headers from a client with this name, as set by
+        // its --requestheader-allowed-names flag.
+        let (cert, key) = ca.generate_cert(
+            "front-proxy-client",
+            None,
+            None,
+            expiry,
+            key_algorithm,
+            Some(&profile),
         )?;
 
-        Ok(None)
+        self.write_issued(ca, &cert, &self.cluster.front_proxy_client_cert_path())?;
+        key.write_to_file(encryptor, &self.cluster.front_proxy_encrypted_client_key_path())
     }
 
-    pub fn generate_kubernetes_pki(&self) -> KawsResult {
-        let mut encryptor = Encryptor::new(
-            self.aws_credentials_provider.clone(),
-            self.cluster.region().parse()?,
-            Some(self.kms_master_key_id),
-        );
+    // The certificate is issued for the first of `names` and valid for all of them.
+    fn issue_ingress_cert(
+        &self,
+        ca: &CertificateAuthority,
+        encryptor: &mut Encryptor,
+        names: &[&str],
+        expiry: Duration,
+        key_algorithm: KeyAlgorithm,
+    ) -> KawsResult {
+        let profile = self.signing_profile("ingress")?;
+
+        let (cert, key) = ca.generate_cert(
+            names[0],
+            Some(names),
+            None,
+            expiry,
+            key_algorithm,
+            Some(&profile),
+        )?;
 
-        let ca = if self.subject == "ca" {
-            let ca = CertificateAuthority::generate(
-                &format!("kaws-k8s-ca-{}", self.cluster.name)
-            )?;
+        self.write_issued(ca, &cert, &self.cluster.ingress_cert_path())?;
+        key.write_to_file(encryptor, &self.cluster.ingress_encrypted_key_path())
+    }
 
-            ca.write_to_files(
-                &mut encryptor,
-                &self.cluster.k8s_ca_cert_path(),
-                &self.cluster.k8s_encrypted_ca_key_path(),
+    // Each component's certificate names the user RBAC already grants its permissions to, so the
+    // components need no bindings of their own, and each can be revoked or reissued alone.
+    fn issue_k8s_component_certs(
+        &self,
+        ca: &CertificateAuthority,
+        encryptor: &mut Encryptor,
+        metadata: &ClusterMetadata,
+        key_algorithm: KeyAlgorithm,
+    ) -> KawsResult {
+        let mut keys = vec![];
+
+        for &(component, user) in K8S_COMPONENTS.iter() {
+            let name = format!("k8s-{}", component);
+            let profile = self.signing_profile(&name)?;
+
+            // No groups, but Kubernetes would read an organization as one.
+            let (cert, key) = ca.generate_cert(
+                user,
+                None,
+                Some(&[]),
+                self.cert_expiry(metadata, &name)?,
+                key_algorithm,
+                Some(&profile),
             )?;
 
-            ca
+            self.write_issued(ca, &cert, &self.cluster.k8s_component_cert_path(component))?;
+            keys.push((key, self.cluster.k8s_encrypted_component_key_path(component)));
+        }
+
+        // Encrypted together, so the components' keys cost one round of KMS requests.
+        let files: Vec<(&[u8], &str)> = keys.iter()
+            .map(|(key, path)| (key.as_bytes(), path.as_str()))
+            .collect();
+
+        encryptor.encrypt_and_write_files(&files)
+    }
+
+    fn issue_k8s_master_cert(
+        &self,
+        ca: &CertificateAuthority,
+        encryptor: &mut Encryptor,
+        metadata: &ClusterMetadata,
+        expiry: Duration,
+        key_algorithm: KeyAlgorithm,
+    ) -> KawsResult {
+        let profile = self.signing_profile("k8s-master")?;
+
+        let domain_san = format!("kubernetes.{}", self.domain.expect("missing domain"));
+        let mut sans = vec![
+            "kubernetes",
+            "kubernetes.default",
+            "kubernetes.default.svc",
+            "kubernetes.default.svc.cluster.local",
+            &domain_san,
+            "10.3.0.1",
+            // The controller manager and scheduler reach the API server on the same master.
+            "127.0.0.1",
+        ];
+
+        // Extra names, e.g. an internal load balancer or a VPN address, recorded for the cluster
+        // so that reissuing the certificate keeps them, the endpoints Terraform created for it,
+        // and any given for this run.
+        let extra_sans = metadata.apiserver_sans.iter().map(String::as_str)
+            .chain(metadata.endpoint_sans("k8s-master"))
+            .chain(self.apiserver_sans.iter().cloned());
+
+        for san in extra_sans {
+            if !sans.contains(&san) {
+                sans.push(san);
+            }
+        }
+
+        let (cert, key) = ca.generate_cert(
+            &format!("kaws-k8s-master-{}", self.cluster.name),
+            Some(&sans),
+            None,
+            expiry,
+            key_algorithm,
+            Some(&profile),
+        )?;
+
+        self.write_issued(ca, &cert, &self.cluster.k8s_master_cert_path())?;
+        key.write_to_file(encryptor, &self.cluster.k8s_encrypted_master_key_path())
+    }
+
+    fn issue_k8s_node_cert(
+        &self,
+        ca: &CertificateAuthority,
+        encryptor: &mut Encryptor,
+        expiry: Duration,
+        key_algorithm: KeyAlgorithm,
+    ) -> KawsResult {
+        let profile = self.signing_profile("k8s-node")?;
+
+        let (cert, key) = ca.generate_cert(
+            &format!("kaws-k8s-node-{}", self.cluster.name),
+            None,
+            Some(&["system:nodes"]),
+            expiry,
+            key_algorithm,
+            Some(&profile),
+        )?;
+
+        self.write_issued(ca, &cert, &self.cluster.k8s_node_cert_path())?;
+        key.write_to_file(encryptor, &self.cluster.k8s_encrypted_node_key_path())
+    }
+
+    // The certificates trusted as the CA `ca`, the one that issues certificates first.
+    fn trusted_ca_certs(&self, ca: &str) -> Result<Vec<Certificate>, KawsError> {
+        let path = self.cluster.ca_cert_path(ca);
+        let certs = match Certificate::from_file_if_present(&path)? {
+            Some(bundle) => bundle.unbundle()?,
+            None => vec![],
+        };
+
+        if certs.is_empty() {
+            Err(KawsError::new(Message::CertificateAuthorityMissing { path }))
         } else {
-            CertificateAuthority::from_files(
-                &mut encryptor,
-                &self.cluster.k8s_ca_cert_path(),
-                &self.cluster.k8s_encrypted_ca_key_path(),
-            )?
+            Ok(certs)
+        }
+    }
+
+    fn rotation_stage(&self, ca: &str) -> Result<RotationStage, KawsError> {
+        let trusted_count = self.trusted_ca_certs(ca)?.len();
+
+        if Path::new(&self.cluster.next_ca_cert_path(ca)).exists() {
+            Ok(RotationStage::Started)
+        } else if trusted_count > 1 {
+            Ok(RotationStage::Reissued)
+        } else {
+            Ok(RotationStage::Idle)
+        }
+    }
+
+    fn service_account_rotation_stage(&self) -> Result<RotationStage, KawsError> {
+        let trusted_count = match PublicKey::from_file_if_present(
+            &self.cluster.service_account_public_key_path(),
+        )? {
+            Some(public_keys) => public_keys.unbundle()?.len(),
+            None => 0,
         };
 
-        if self.subject == "ca" || self.subject == "masters" {
-            let (master_cert, master_key) = ca.generate_cert(
-                &format!("kaws-k8s-master-{}", self.cluster.name),
-                Some(&[
-                    "kubernetes",
-                    "kubernetes.default",
-                    "kubernetes.default.svc",
-                    "kubernetes.default.svc.cluster.local",
-                    &format!("kubernetes.{}", self.domain.expect("missing domain")),
-                    "10.3.0.1",
-                ]),
-                None,
-            )?;
+        if Path::new(&self.cluster.service_account_next_public_key_path()).exists() {
+            Ok(RotationStage::Started)
+        } else if trusted_count > 1 {
+            Ok(RotationStage::Reissued)
+        } else {
+            Ok(RotationStage::Idle)
+        }
+    }
 
-            master_cert.write_to_file(&self.cluster.k8s_master_cert_path())?;
-            master_key.write_to_file(
-                &mut encryptor,
-                &self.cluster.k8s_encrypted_master_key_path(),
-            )?;
+    // Generates the new key pair, unless an earlier attempt already did, and trusts its public key
+    // after the current one, which still signs tokens until the "reissue" phase.
+    fn start_service_account_key_rotation(
+        &self,
+        encryptor: &mut Encryptor,
+        key_algorithm: KeyAlgorithm,
+    ) -> KawsResult {
+        let public_key_path = self.cluster.service_account_public_key_path();
+        let next_public_key_path = self.cluster.service_account_next_public_key_path();
+
+        if !Path::new(&next_public_key_path).exists() {
+            let next = PrivateKey::generate(key_algorithm)?;
+
+            // The private key is written first, since the public key's presence marks the phase
+            // as done.
+            next.write_to_file(encryptor, &self.cluster.service_account_next_encrypted_key_path())?;
+            next.public_key()?.write_to_file(&format!("{}.tmp", next_public_key_path))?;
+            rename(format!("{}.tmp", next_public_key_path), &next_public_key_path)?;
         }
 
-        if self.subject == "ca" || self.subject == "nodes" {
-            let (node_cert, node_key) = ca.generate_cert(
-                &format!("kaws-k8s-node-{}", self.cluster.name),
-                None,
-                Some(&["system:nodes"]),
+        let next = PublicKey::from_file(&next_public_key_path)?;
+        let current = match PublicKey::from_file_if_present(&public_key_path)? {
+            Some(public_keys) => public_keys.unbundle()?.into_iter().next(),
+            None => None,
+        };
+
+        match current {
+            Some(current) => PublicKey::bundle(&[&current, &next]),
+            None => next,
+        }.write_to_file(&public_key_path)
+    }
+
+    // Generates the new CA, unless an earlier attempt already did, and trusts it alongside the
+    // current CA, which stays first since it issues certificates until the "reissue" phase.
+    fn start_ca_rotation(
+        &self,
+        ca: &str,
+        encryptor: &mut Encryptor,
+        expiry: Duration,
+        key_algorithm: KeyAlgorithm,
+    ) -> KawsResult {
+        let current = self.trusted_ca_certs(ca)?.remove(0);
+        let next_cert_path = self.cluster.next_ca_cert_path(ca);
+
+        if !Path::new(&next_cert_path).exists() {
+            let metadata = ClusterMetadata::from_file(&self.cluster.metadata_path())?;
+            let next = CertificateAuthority::generate(
+                &format!("kaws-{}-ca-{}", ca, self.cluster.name),
+                &metadata.subject_fields(self.subject_fields)?,
+                self.backdate,
+                expiry,
+                key_algorithm,
+                Provenance::new(self.cluster.name),
             )?;
 
-            node_cert.write_to_file(&self.cluster.k8s_node_cert_path())?;
-            node_key.write_to_file(
-                &mut encryptor,
-                &self.cluster.k8s_encrypted_node_key_path(),
+            // The key is written first, since the certificate's presence marks the phase as done.
+            next.write_to_files(
+                encryptor,
+                &format!("{}.tmp", next_cert_path),
+                &self.cluster.next_encrypted_ca_key_path(ca),
             )?;
+            rename(format!("{}.tmp", next_cert_path), &next_cert_path)?;
+        }
+
+        let next = Certificate::from_file(&next_cert_path)?;
+        let bundle = Certificate::bundle(&[&current, &next]);
+
+        PkiManifest::record(self.cluster.name, &next_cert_path, &next, Some(self.kms_key_arn()))?;
+        bundle.write_to_file(&self.cluster.ca_cert_path(ca))?;
+
+        PkiManifest::record(
+            self.cluster.name,
+            &self.cluster.ca_cert_path(ca),
+            &bundle,
+            Some(self.kms_key_arn()),
+        )
+    }
+
+    // Issues every certificate the CA `ca_name` signs for the cluster's servers with `ca`.
+    fn issue_leaf_certs(
+        &self,
+        ca_name: &str,
+        ca: &CertificateAuthority,
+        encryptor: &mut Encryptor,
+        metadata: &ClusterMetadata,
+        key_algorithm: KeyAlgorithm,
+    ) -> KawsResult {
+        match ca_name {
+            "etcd" => {
+                let server_expiry = self.cert_expiry(metadata, "etcd-server")?;
+                let client_expiry = self.cert_expiry(metadata, "etcd-client")?;
+                let apiserver_client_expiry = self.cert_expiry(metadata, "etcd-apiserver-client")?;
+
+                self.issue_etcd_server_cert(ca, encryptor, server_expiry, key_algorithm)?;
+                self.issue_etcd_client_cert(ca, encryptor, client_expiry, key_algorithm)?;
+                self.issue_etcd_apiserver_client_cert(
+                    ca,
+                    encryptor,
+                    apiserver_client_expiry,
+                    key_algorithm,
+                )?;
+            }
+            "etcd-peer" => {
+                let peer_expiry = self.cert_expiry(metadata, "etcd-peer")?;
+
+                self.issue_etcd_peer_cert(ca, encryptor, peer_expiry, key_algorithm)?;
+            }
+            "front-proxy" => {
+                let client_expiry = self.cert_expiry(metadata, "front-proxy-client")?;
+
+                self.issue_front_proxy_client_cert(ca, encryptor, client_expiry, key_algorithm)?;
+            }
+            _ => {
+                let master_expiry = self.cert_expiry(metadata, "k8s-master")?;
+                let node_expiry = self.cert_expiry(metadata, "k8s-node")?;
+
+                self.issue_k8s_master_cert(ca, encryptor, metadata, master_expiry, key_algorithm)?;
+                self.issue_k8s_component_certs(ca, encryptor, metadata, key_algorithm)?;
+                self.issue_k8s_node_cert(ca, encryptor, node_expiry, key_algorithm)?;
+
+                // The ingress certificate is only there if it was generated, and is reissued for
+                // the names it was issued for.
+                let ingress_cert_path = self.cluster.ingress_cert_path();
+
+                if let Some(cert) = Certificate::from_file_if_present(&ingress_cert_path)? {
+                    let info = cert.info()?;
+                    let names: Vec<&str> = info.subject_alt_names.iter()
+                        .filter_map(|name| name.strip_prefix("DNS:"))
+                        .collect();
+
+                    if !names.is_empty() {
+                        let expiry = self.cert_expiry(metadata, "ingress")?;
+
+                        self.issue_ingress_cert(ca, encryptor, &names, expiry, key_algorithm)?;
+                    }
+                }
+            }
+        }
+
+        Ok(None)
+    }
+
+    // Issues every certificate that depends on the CA `ca_name`, which was just replaced, and
+    // warns about those kaws can't issue by itself, such as administrators' certificates.
+    fn reissue_dependents(
+        &self,
+        ca_name: &str,
+        ca: &CertificateAuthority,
+        encryptor: &mut Encryptor,
+        metadata: &ClusterMetadata,
+        key_algorithm: KeyAlgorithm,
+    ) -> KawsResult {
+        self.issue_leaf_certs(ca_name, ca, encryptor, metadata, key_algorithm)?;
+
+        for path in self.stale_dependents(ca_name, ca)? {
+            eprintln!(
+                "warning: {} was issued by the {} CA that was replaced, and must be issued again, \
+                e.g. with `kaws admin sign` for an administrator",
+                path,
+                ca_name,
+            );
+        }
+
+        Ok(None)
+    }
+
+    // The certificates in the cluster's directory that the CA `ca_name` should have issued, but
+    // that `ca` didn't, so they still depend on a CA it replaced.
+    fn stale_dependents(&self, ca_name: &str, ca: &CertificateAuthority)
+    -> Result<Vec<String>, KawsError> {
+        let names: BTreeSet<String> = non_empty_names(&format!("clusters/{}", self.cluster.name))?
+            .into_iter()
+            .collect();
+        let key_id = ca.certificate().info()?.subject_key_id;
+        let mut stale = vec![];
+
+        for name in dependents(ca_name, &names) {
+            let path = format!("clusters/{}/{}", self.cluster.name, name);
+
+            if Certificate::from_file(&path)?.info()?.authority_key_id != key_id {
+                stale.push(path);
+            }
+        }
+
+        Ok(stale)
+    }
+
+    // Issues every certificate of `ca` with the new CA, then makes the new CA current: its key
+    // replaces the old one, and it moves to the front of the bundle, with the old CA behind it.
+    fn reissue_with_next_ca(
+        &self,
+        ca: &str,
+        encryptor: &mut Encryptor,
+        metadata: &ClusterMetadata,
+        key_algorithm: KeyAlgorithm,
+    ) -> KawsResult {
+        let next = CertificateAuthority::from_files(
+            encryptor,
+            &self.cluster.next_ca_cert_path(ca),
+            &self.cluster.next_encrypted_ca_key_path(ca),
+            None,
+            self.backdate,
+        )?
+            .with_subject_fields(metadata.subject_fields(self.subject_fields)?)
+            .with_provenance(Provenance::new(self.cluster.name));
+
+        self.issue_leaf_certs(ca, &next, encryptor, metadata, key_algorithm)?;
+
+        // Finding the new CA in the bundle by its key identifier keeps the old CA trusted even if
+        // an earlier attempt already reordered the bundle.
+        let next_key_id = next.certificate().info()?.subject_key_id;
+        let mut bundle = vec![next.certificate()];
+        let trusted = self.trusted_ca_certs(ca)?;
+
+        for cert in &trusted {
+            if cert.info()?.subject_key_id != next_key_id {
+                bundle.push(cert);
+            }
         }
 
+        next.write_key_to_file(encryptor, &self.cluster.encrypted_ca_key_path(ca))?;
+        Certificate::bundle(&bundle).write_to_file(&self.cluster.ca_cert_path(ca))?;
+
+        remove_file(self.cluster.next_ca_cert_path(ca))?;
+        remove_encrypted_file(&self.cluster.next_encrypted_ca_key_path(ca))?;
+
         Ok(None)
     }
+
+    // Stops trusting the old CA, which follows the new one in the bundle.
+    fn finish_ca_rotation(&self, ca: &str) -> KawsResult {
+        self.trusted_ca_certs(ca)?.remove(0).write_to_file(&self.cluster.ca_cert_path(ca))
+    }
+}
+
+impl RotationStage {
+    // The phase that moves a CA on from this stage.
+    fn next_phase(self) -> &'static str {
+        match self {
+            RotationStage::Idle => "start",
+            RotationStage::Started => "reissue",
+            RotationStage::Reissued => "finish",
+        }
+    }
 }
 
 impl<'a> NewCluster<'a> {
-    pub fn new(matches: &'a ArgMatches) -> Self {
-        NewCluster {
+    /// Reads the options of `kaws cluster init`, filling in those not given from the preset named
+    /// by `--preset`, if any.
+    pub fn new(matches: &'a ArgMatches) -> Result<Self, KawsError> {
+        let preset_name = matches.value_of("preset");
+        let preset = match preset_name {
+            Some(name) => Preset::named(name)?,
+            None => Preset::default(),
+        };
+        // clap requires the options that have no default unless a preset is given, and a preset
+        // can leave them out too.
+        let required = |option: &str, preset_value: Option<String>| {
+            matches.value_of(option).map(String::from).or(preset_value).ok_or_else(|| {
+                KawsError::new(Message::PresetOptionMissing {
+                    option: option.to_owned(),
+                    preset: preset_name.unwrap_or_default().to_owned(),
+                })
+            })
+        };
+        let size = |size: Option<u32>| size.map(|size| size.to_string());
+
+        Ok(NewCluster {
             availability_zone: matches
                 .value_of("availability-zone")
                 .expect("missing availability-zone"),
             aws_account_id: matches.value_of("aws-account-id").expect("missing aws-account-id"),
+            aws_credentials_provider: credentials_provider(
+                matches.value_of("aws-credentials-path").map(from_invocation).as_deref(),
+                matches.value_of("aws-credentials-profile"),
+            ),
+            bastion: matches.is_present("bastion") || preset.bastion,
+            bastion_ssh_cidrs: matches
+                .values_of("bastion-ssh-cidr")
+                .map(|values| values.collect())
+                .unwrap_or_else(|| vec!["0.0.0.0/0"]),
             cidr: matches.value_of("cidr").expect("missing cidr"),
+            cloud_provider: CloudProviderName::from_name(
+                matches.value_of("cloud-provider").unwrap_or("aws"),
+            ).expect("clap should have validated cloud-provider"),
             cluster: Cluster::new(
                 matches.value_of("cluster").expect("missing cluster name"),
                 matches.value_of("region").expect("missing region"),
             ),
             coreos_ami: matches.value_of("ami").expect("missing ami"),
             domain: matches.value_of("domain").expect("missing domain"),
+            etcd_member_certificates: matches.is_present("etcd-member-certificates") ||
+                preset.etcd_member_certificates,
+            etcd_volume_size: matches.value_of("etcd-volume-size").map(String::from)
+                .or(preset.etcd_volume_size)
+                .map(|size| {
+                    parse_volume_size(&size).expect("etcd-volume-size should have been validated")
+                })
+                .unwrap_or(DEFAULT_ETCD_VOLUME_GIBIBYTES),
+            group_roles: matches
+                .values_of("group-role")
+                .map(|values| values.collect())
+                .unwrap_or_default(),
+            iam_authenticator: matches.is_present("iam-authenticator") || preset.iam_authenticator,
+            iam_user_groups: matches
+                .values_of("iam-user-group")
+                .map(|values| values.collect())
+                .unwrap_or_default(),
             iam_users: matches
                 .values_of("iam-user")
                 .expect("missing iam-users")
                 .collect(),
-            instance_size: matches.value_of("size").expect("missing instance size"),
+            instance_size: required("instance-size", preset.instance_size)?,
+            key_algo: matches.value_of("key-algo").map(String::from).or(preset.key_algo)
+                .unwrap_or_else(|| "rsa".to_owned()),
+            key_size: matches.value_of("key-size").map(String::from).or(size(preset.key_size)),
             kubernetes_version: matches.value_of("k8s-version").expect("missing k8s-version"),
-            masters_max_size: matches
-                .value_of("masters-max-size")
-                .expect("missing masters-max-size"),
-            masters_min_size: matches
-                .value_of("masters-min-size")
-                .expect("missing masters-min-size"),
-            nodes_max_size: matches
-                .value_of("nodes-max-size")
-                .expect("missing nodes-max-size"),
-            nodes_min_size: matches
-                .value_of("nodes-min-size")
-                .expect("missing nodes-min-size"),
-            ssh_keys: matches.values_of("ssh-key").expect("missing ssh-keys").collect(),
+            masters_max_size: required("masters-max-size", size(preset.masters_max_size))?,
+            masters_min_size: required("masters-min-size", size(preset.masters_min_size))?,
+            max_monthly_cost: matches.value_of("max-monthly-cost").map(|dollars| {
+                dollars.parse().expect("clap should have validated max-monthly-cost")
+            }).or(preset.max_monthly_cost),
+            node_placement: matches.value_of("node-placement").map(String::from)
+                .or(preset.node_placement),
+            node_subnet_cidrs: matches
+                .values_of("node-subnet")
+                .map(|values| values.collect())
+                .unwrap_or_default(),
+            nodes_max_size: required("nodes-max-size", size(preset.nodes_max_size))?,
+            nodes_min_size: required("nodes-min-size", size(preset.nodes_min_size))?,
+            scrub_credentials: matches.is_present("scrub-credentials") || preset.scrub_credentials,
+            skip_ami_check: matches.is_present("skip-ami-check"),
+            ssh_keys: matches
+                .values_of("ssh-key")
+                .map(|values| values.collect())
+                .unwrap_or_default(),
+            tls_bootstrapping: matches.is_present("tls-bootstrapping") || preset.tls_bootstrapping,
+            wireguard: matches.is_present("wireguard") || preset.wireguard,
             zone_id: matches.value_of("zone-id").expect("missing zone-id"),
-        }
+        })
     }
 
     pub fn init(&mut self) -> KawsResult {
+        let metadata = self.metadata()?;
+        let templates = Templates::for_repository()?;
+        let key_algorithm = KeyAlgorithm::new(&self.key_algo, self.key_size.as_deref())?;
+        let provider = cloud_provider(self.cloud_provider, self.aws_credentials_provider.clone());
+        // Checked before anything is written, so a cloud kaws can't manage leaves nothing behind.
+        let tfvars_template = provider.tfvars_template()?;
+
+        self.check_subnets()?;
+
+        if !self.skip_ami_check {
+            log_wrap!("Checking that the AMI supports the instance size", {
+                provider.ensure_image_supports_instance_size(
+                    self.cluster.region(),
+                    self.coreos_ami,
+                    &self.instance_size,
+                )?;
+            });
+        }
+
         self.create_directories()?;
-        self.create_gitignore()?;
-        self.create_tfvars()?;
+        self.create_gitignore(&templates)?;
+        self.create_tfvars(&templates, tfvars_template, &metadata, key_algorithm)?;
+        self.create_metadata(&templates, &metadata)?;
         self.create_pki_stubs()?;
 
-        Ok(Some(format!(
-            "Cluster \"{name}\" initialized! Commit clusters/{name} to Git.",
-            name = self.cluster.name,
-        )))
+        Ok(Some(Message::ClusterInitialized {
+            cluster: self.cluster.name.to_owned(),
+        }))
+    }
+
+    // The cluster's subnets share one VPC, so no two of them may overlap.
+    fn check_subnets(&self) -> KawsResult {
+        let mut subnets = vec![self.cidr];
+        subnets.extend(&self.node_subnet_cidrs);
+
+        for (index, first) in subnets.iter().enumerate() {
+            for second in &subnets[index + 1..] {
+                let first_cidr: Ipv4Cidr = first.parse().expect("clap should have validated cidr");
+                let second_cidr: Ipv4Cidr = second.parse().expect("clap should have validated cidr");
+
+                if first_cidr.subset_cmp(&second_cidr).is_some() {
+                    return Err(KawsError::new(Message::SubnetsOverlap {
+                        first: first.to_string(),
+                        second: second.to_string(),
+                    }));
+                }
+            }
+        }
+
+        Ok(None)
     }
 
     fn create_directories(&self) -> KawsResult {
@@ -385,62 +2642,115 @@ impl<'a> NewCluster<'a> {
         Ok(None)
     }
 
-    fn create_gitignore(&self) -> KawsResult {
+    fn create_gitignore(&self, templates: &Templates) -> KawsResult {
         log_wrap!("Creating .gitignore file", {
-            let mut file = File::create(&self.cluster.gitignore_path())?;
+            let mut file = File::create(self.cluster.gitignore_path())?;
 
-            write!(file, "*-key.pem")?;
+            write!(file, "{}", templates.render("cluster-gitignore", &json!({}))?)?;
         });
 
         Ok(None)
     }
 
-    fn create_tfvars(&self) -> KawsResult {
+    fn create_tfvars(
+        &self,
+        templates: &Templates,
+        template: &str,
+        metadata: &ClusterMetadata,
+        key_algorithm: KeyAlgorithm,
+    ) -> KawsResult {
+        let iam_authenticator_config = if metadata.iam_authenticator {
+            metadata.iam_authenticator_config(templates, self.cluster.name(), self.aws_account_id)?
+                .as_bytes()
+                .to_base64(STANDARD)
+        } else {
+            String::new()
+        };
+
         log_wrap!("Creating tfvars file", {
-            let mut file = File::create(&self.cluster.tfvars_path())?;
-
-            write!(
-                file,
-                "\
-kaws_account_id = \"{}\"
-kaws_availability_zone = \"{}\"
-kaws_cidr = \"{}\"
-kaws_cluster = \"{}\"
-kaws_coreos_ami = \"{}\"
-kaws_domain = \"{}\"
-kaws_iam_users = [{}]
-kaws_instance_size = \"{}\"
-kaws_masters_max_size = \"{}\"
-kaws_masters_min_size = \"{}\"
-kaws_nodes_max_size = \"{}\"
-kaws_nodes_min_size = \"{}\"
-kaws_propagating_vgws = []
-kaws_region = \"{}\"
-kaws_ssh_keys = [{}]
-kaws_version = \"{}\"
-kaws_zone_id = \"{}\"
-",
-                self.aws_account_id,
-                self.availability_zone,
-                self.cidr,
-                self.cluster.name(),
-                self.coreos_ami,
-                self.domain,
-                self.iam_users.iter().map(|iam_user| {
-                    format!("\"{}\"", iam_user)
-                }).collect::<Vec<String>>().join(", "),
-                self.instance_size,
-                self.masters_max_size,
-                self.masters_min_size,
-                self.nodes_max_size,
-                self.nodes_min_size,
-                self.cluster.region(),
-                self.ssh_keys.iter().map(|ssh_key| {
-                    format!("\"{}\"", ssh_key)
-                }).collect::<Vec<String>>().join(", "),
-                self.kubernetes_version,
-                self.zone_id,
-            )?;
+            let mut file = File::create(self.cluster.tfvars_path())?;
+
+            write!(file, "{}", templates.render(template, &json!({
+                "availability_zone": self.availability_zone,
+                "aws_account_id": self.aws_account_id,
+                "bastion_ssh_cidrs": self.bastion_ssh_cidrs,
+                "cidr": self.cidr,
+                "cluster": self.cluster.name(),
+                "coreos_ami": self.coreos_ami,
+                "domain": self.domain,
+                "etcd_member_certificates": self.etcd_member_certificates,
+                "etcd_volume_size": self.etcd_volume_size,
+                "iam_authenticator_config": iam_authenticator_config,
+                "iam_users": self.iam_users,
+                "instance_size": self.instance_size,
+                "key_algo": key_algorithm.name(),
+                "key_size": key_algorithm.bits(),
+                "kubernetes_version": self.kubernetes_version,
+                "masters_max_size": self.masters_max_size,
+                "masters_min_size": self.masters_min_size,
+                "node_placement": self.node_placement.as_deref().unwrap_or_default(),
+                "node_subnet_cidrs": self.node_subnet_cidrs,
+                "nodes_max_size": self.nodes_max_size,
+                "nodes_min_size": self.nodes_min_size,
+                "private_api": self.bastion,
+                "region": self.cluster.region(),
+                "scrub_credentials": self.scrub_credentials,
+                "ssh_keys": self.ssh_keys,
+                "tls_bootstrapping": self.tls_bootstrapping,
+                "wireguard": self.wireguard,
+                "zone_id": self.zone_id,
+            }))?)?;
+        });
+
+        Ok(None)
+    }
+
+    fn metadata(&self) -> Result<ClusterMetadata, KawsError> {
+        let mut metadata = ClusterMetadata {
+            cloud_provider: self.cloud_provider,
+            encryption_contexts_required: true,
+            iam_authenticator: self.iam_authenticator,
+            max_monthly_cost: self.max_monthly_cost,
+            ..ClusterMetadata::default()
+        };
+
+        for iam_user in &self.iam_users {
+            metadata.iam_users.insert(iam_user.to_string(), IamUser::default());
+        }
+
+        for group_role in &self.group_roles {
+            let (group, role) = split_key_value(group_role);
+
+            metadata.group_roles.insert(group.to_owned(), role.to_owned());
+        }
+
+        for iam_user_group in &self.iam_user_groups {
+            let (iam_user, group) = split_key_value(iam_user_group);
+
+            if !metadata.group_roles.contains_key(group) {
+                return Err(KawsError::new(Message::GroupNotBound {
+                    group: group.to_owned(),
+                }));
+            }
+
+            match metadata.iam_users.get_mut(iam_user) {
+                Some(iam_user) => iam_user.groups.push(group.to_owned()),
+                None => return Err(KawsError::new(Message::IamUserNotGiven {
+                    iam_user: iam_user.to_owned(),
+                })),
+            }
+        }
+
+        Ok(metadata)
+    }
+
+    fn create_metadata(&self, templates: &Templates, metadata: &ClusterMetadata) -> KawsResult {
+        log_wrap!("Creating metadata and RBAC files", {
+            metadata.write_to_file(&self.cluster.metadata_path())?;
+
+            let mut file = File::create(self.cluster.rbac_path())?;
+
+            write!(file, "{}", metadata.rbac_manifest(templates)?)?;
         });
 
         Ok(None)
@@ -448,6 +2758,9 @@ kaws_zone_id = \"{}\"
 
     fn create_pki_stubs(&self) -> KawsResult {
         let paths = [
+            // bootstrap token
+            &self.cluster.bootstrap_encrypted_token_path(),
+
             // etcd ca
             &self.cluster.etcd_ca_cert_path(),
             &self.cluster.etcd_encrypted_ca_key_path(),
@@ -459,6 +2772,8 @@ kaws_zone_id = \"{}\"
             // etcd clients
             &self.cluster.etcd_client_cert_path(),
             &self.cluster.etcd_encrypted_client_key_path(),
+            &self.cluster.etcd_apiserver_client_cert_path(),
+            &self.cluster.etcd_encrypted_apiserver_client_key_path(),
 
             // etcd peer ca
             &self.cluster.etcd_peer_ca_cert_path(),
@@ -468,6 +2783,14 @@ kaws_zone_id = \"{}\"
             &self.cluster.etcd_peer_cert_path(),
             &self.cluster.etcd_peer_encrypted_key_path(),
 
+            // front proxy ca
+            &self.cluster.front_proxy_ca_cert_path(),
+            &self.cluster.front_proxy_encrypted_ca_key_path(),
+
+            // front proxy client
+            &self.cluster.front_proxy_client_cert_path(),
+            &self.cluster.front_proxy_encrypted_client_key_path(),
+
             // k8s ca
             &self.cluster.k8s_ca_cert_path(),
             &self.cluster.k8s_encrypted_ca_key_path(),
@@ -479,12 +2802,46 @@ kaws_zone_id = \"{}\"
             // k8s nodes
             &self.cluster.k8s_node_cert_path(),
             &self.cluster.k8s_encrypted_node_key_path(),
+
+            // service accounts
+            &self.cluster.service_account_public_key_path(),
+            &self.cluster.service_account_encrypted_key_path(),
+
+            // ssh cas
+            &self.cluster.ssh_host_ca_public_key_path(),
+            &self.cluster.ssh_host_encrypted_ca_key_path(),
+            &self.cluster.ssh_user_ca_public_key_path(),
+            &self.cluster.ssh_user_encrypted_ca_key_path(),
         ];
 
         for path in paths.iter() {
             File::create(path)?;
         }
 
+        // k8s control plane components
+        for &(component, _) in K8S_COMPONENTS.iter() {
+            File::create(self.cluster.k8s_component_cert_path(component))?;
+            File::create(self.cluster.k8s_encrypted_component_key_path(component))?;
+        }
+
+        if self.etcd_member_certificates {
+            for &(member, _) in ETCD_MEMBERS.iter() {
+                File::create(self.cluster.etcd_member_server_cert_path(member))?;
+                File::create(self.cluster.etcd_member_encrypted_server_key_path(member))?;
+                File::create(self.cluster.etcd_member_peer_cert_path(member))?;
+                File::create(self.cluster.etcd_member_peer_encrypted_key_path(member))?;
+            }
+        }
+
         Ok(None)
     }
 }
+
+fn split_key_value(value: &str) -> (&str, &str) {
+    let mut parts = value.splitn(2, '=');
+
+    (
+        parts.next().expect("clap should have validated KEY=VALUE"),
+        parts.next().expect("clap should have validated KEY=VALUE"),
+    )
+}
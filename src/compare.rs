@@ -0,0 +1,145 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use ansi_term::Colour::{Green, Red, Yellow};
+use clap::ArgMatches;
+
+use error::{KawsError, KawsResult};
+use message::Message;
+use metadata::ClusterMetadata;
+use tfvars::Tfvars;
+
+/// Settings that identify a cluster and are expected to differ between any two of them.
+const IDENTITY_SETTINGS: [&str; 4] = [
+    "kaws_cidr",
+    "kaws_cluster",
+    "kaws_domain",
+    "kaws_zone_id",
+];
+
+pub struct Comparison<'a> {
+    all: bool,
+    first: &'a str,
+    second: &'a str,
+}
+
+impl<'a> Comparison<'a> {
+    pub fn new(matches: &'a ArgMatches) -> Self {
+        Comparison {
+            all: matches.is_present("all"),
+            first: matches.value_of("first").expect("clap should have required first"),
+            second: matches.value_of("second").expect("clap should have required second"),
+        }
+    }
+
+    pub fn compare(&self) -> KawsResult {
+        let first = settings(self.first)?;
+        let second = settings(self.second)?;
+
+        let keys: BTreeSet<&String> = first.keys().chain(second.keys()).collect();
+        let mut rows = vec![];
+        let mut divergent = 0;
+
+        for key in keys {
+            let first_value = first.get(key).map(String::as_str).unwrap_or("(unset)");
+            let second_value = second.get(key).map(String::as_str).unwrap_or("(unset)");
+            let differs = first_value != second_value &&
+                !IDENTITY_SETTINGS.contains(&key.as_str());
+
+            if differs {
+                divergent += 1;
+            }
+
+            if differs || self.all {
+                rows.push((key.as_str(), first_value, second_value, differs));
+            }
+        }
+
+        let key_width = rows.iter().map(|row| row.0.len()).chain(Some("SETTING".len())).max()
+            .unwrap_or(0);
+        let value_width = rows.iter().map(|row| row.1.len()).chain(Some(self.first.len())).max()
+            .unwrap_or(0);
+
+        if !rows.is_empty() {
+            println!(
+                "{:key_width$}  {:value_width$}  {}",
+                "SETTING",
+                self.first,
+                self.second,
+                key_width = key_width,
+                value_width = value_width,
+            );
+        }
+
+        for (key, first_value, second_value, differs) in rows {
+            let key = format!("{:width$}", key, width = key_width);
+            let first_value = format!("{:width$}", first_value, width = value_width);
+
+            if differs {
+                println!(
+                    "{}  {}  {}",
+                    Yellow.paint(key),
+                    Red.paint(first_value),
+                    Green.paint(second_value),
+                );
+            } else {
+                println!("{}  {}  {}", key, first_value, second_value);
+            }
+        }
+
+        if divergent == 0 {
+            Ok(Some(Message::ClustersMatch {
+                first: self.first.to_owned(),
+                second: self.second.to_owned(),
+            }))
+        } else {
+            Err(KawsError::new(Message::ClustersDiffer {
+                first: self.first.to_owned(),
+                second: self.second.to_owned(),
+                count: divergent,
+            }))
+        }
+    }
+}
+
+/// Every setting recorded for a cluster in its tfvars and metadata files, keyed by name.
+fn settings(cluster: &str) -> Result<BTreeMap<String, String>, KawsError> {
+    let tfvars = Tfvars::from_file(&format!("clusters/{}/terraform.tfvars", cluster)).map_err(|error| {
+        KawsError::new(Message::TfvarsUnreadable {
+            cluster: cluster.to_owned(),
+            error: error.to_string(),
+        })
+    })?;
+    let metadata = ClusterMetadata::from_file(&format!("clusters/{}/metadata.json", cluster))?;
+
+    // The encoded aws-iam-authenticator configuration is generated from the metadata, which is
+    // compared setting by setting below instead.
+    let mut settings: BTreeMap<String, String> = tfvars.iter().filter(|&(key, _)| {
+        key != "kaws_iam_authenticator_config"
+    }).map(|(key, value)| (key.to_owned(), value.to_owned())).collect();
+
+    settings.insert("metadata.iam_authenticator".to_owned(), metadata.iam_authenticator.to_string());
+
+    for (name, expiry) in &metadata.certificate_expiry {
+        settings.insert(format!("metadata.certificate_expiry.{}", name), expiry.clone());
+    }
+
+    for (group, role) in &metadata.group_roles {
+        settings.insert(format!("metadata.group_roles.{}", group), role.clone());
+    }
+
+    for (name, iam_user) in &metadata.iam_users {
+        let groups = if iam_user.groups.is_empty() {
+            "(none)".to_owned()
+        } else {
+            iam_user.groups.join(", ")
+        };
+
+        settings.insert(format!("metadata.iam_users.{}.groups", name), groups);
+    }
+
+    if let Some(max_monthly_cost) = metadata.max_monthly_cost {
+        settings.insert("metadata.max_monthly_cost".to_owned(), max_monthly_cost.to_string());
+    }
+
+    Ok(settings)
+}
@@ -0,0 +1,320 @@
+use std::collections::BTreeMap;
+
+use error::KawsError;
+use message::Message;
+use metadata::ClusterMetadata;
+
+/// A Kubernetes component whose command line kaws generates, and which flags the cluster's
+/// metadata may add to it.
+pub struct Component {
+    /// The component's name, e.g. "kube-apiserver".
+    pub name: &'static str,
+    /// The terraform.tfvars variable holding the flags from the metadata.
+    pub tfvar: &'static str,
+    /// The flags the metadata may set, each with the minor versions of Kubernetes 1.x that
+    /// accept it: from the first, up to but not including the second, if it was removed.
+    known: &'static [(&'static str, u64, Option<u64>)],
+    /// Flags kaws sets itself, which the metadata can't override without breaking the cluster.
+    managed: &'static [&'static str],
+}
+
+/// The feature gates kaws knows of, with the minor versions of Kubernetes 1.x that accept each as
+/// `COMPONENTS` lists flags. Every component takes the same gates.
+pub const FEATURE_GATES: [(&str, u64, Option<u64>); 22] = [
+    ("APIListChunking", 8, None),
+    ("Accelerators", 6, Some(11)),
+    ("AdvancedAuditing", 7, None),
+    ("AllAlpha", 5, None),
+    ("CPUManager", 8, None),
+    ("CustomPodDNS", 9, None),
+    ("CustomResourceValidation", 8, None),
+    ("DevicePlugins", 8, None),
+    ("DynamicKubeletConfig", 4, None),
+    ("ExpandPersistentVolumes", 8, None),
+    ("ExperimentalCriticalPodAnnotation", 5, None),
+    ("HugePages", 8, None),
+    ("LocalStorageCapacityIsolation", 7, None),
+    ("MountPropagation", 8, None),
+    ("PersistentLocalVolumes", 7, None),
+    ("PodPriority", 8, None),
+    ("PodShareProcessNamespace", 10, None),
+    ("RotateKubeletClientCertificate", 7, None),
+    ("RotateKubeletServerCertificate", 7, None),
+    ("TaintBasedEvictions", 6, None),
+    ("TaintNodesByCondition", 8, None),
+    ("VolumeScheduling", 9, None),
+];
+
+/// The components whose flags the metadata may set, in the order their tfvars are written.
+pub const COMPONENTS: [Component; 3] = [
+    Component {
+        name: "kube-apiserver",
+        tfvar: "kaws_apiserver_flags",
+        known: &[
+            ("audit-log-maxage", 2, None),
+            ("audit-log-maxbackup", 2, None),
+            ("audit-log-maxsize", 2, None),
+            ("audit-log-path", 2, None),
+            ("audit-policy-file", 7, None),
+            ("cors-allowed-origins", 0, None),
+            ("default-not-ready-toleration-seconds", 11, None),
+            ("default-unreachable-toleration-seconds", 11, None),
+            ("enable-swagger-ui", 3, Some(14)),
+            ("event-ttl", 0, None),
+            ("feature-gates", 3, None),
+            ("kubelet-timeout", 0, None),
+            ("max-mutating-requests-inflight", 7, None),
+            ("max-requests-inflight", 0, None),
+            ("min-request-timeout", 0, None),
+            ("profiling", 0, None),
+            ("request-timeout", 6, None),
+            ("service-node-port-range", 0, None),
+            ("target-ram-mb", 5, None),
+            ("v", 0, None),
+            ("watch-cache-sizes", 3, None),
+        ],
+        managed: &[
+            "admission-control",
+            "advertise-address",
+            "allow-privileged",
+            "anonymous-auth",
+            "authentication-token-webhook-cache-ttl",
+            "authentication-token-webhook-config-file",
+            "authorization-mode",
+            "bind-address",
+            "client-ca-file",
+            "cloud-provider",
+            "etcd-cafile",
+            "etcd-certfile",
+            "etcd-keyfile",
+            "etcd-servers",
+            "external-hostname",
+            "insecure-bind-address",
+            "proxy-client-cert-file",
+            "proxy-client-key-file",
+            "requestheader-allowed-names",
+            "requestheader-client-ca-file",
+            "requestheader-extra-headers-prefix",
+            "requestheader-group-headers",
+            "requestheader-username-headers",
+            "runtime-config",
+            "secure-port",
+            "service-account-key-file",
+            "service-cluster-ip-range",
+            "storage-backend",
+            "storage-media-type",
+            "tls-cert-file",
+            "tls-private-key-file",
+            "token-auth-file",
+        ],
+    },
+    Component {
+        name: "kube-controller-manager",
+        tfvar: "kaws_controller_manager_flags",
+        known: &[
+            ("concurrent-deployment-syncs", 2, None),
+            ("concurrent-endpoint-syncs", 0, None),
+            ("concurrent-replicaset-syncs", 2, None),
+            ("concurrent-service-syncs", 0, None),
+            ("experimental-cluster-signing-duration", 7, None),
+            ("feature-gates", 3, None),
+            ("horizontal-pod-autoscaler-downscale-delay", 6, Some(12)),
+            ("horizontal-pod-autoscaler-sync-period", 2, None),
+            ("horizontal-pod-autoscaler-upscale-delay", 6, Some(12)),
+            ("kube-api-burst", 0, None),
+            ("kube-api-qps", 0, None),
+            ("node-monitor-grace-period", 0, None),
+            ("node-monitor-period", 0, None),
+            ("pod-eviction-timeout", 0, None),
+            ("profiling", 0, None),
+            ("terminated-pod-gc-threshold", 1, None),
+            ("v", 0, None),
+        ],
+        managed: &[
+            "cloud-provider",
+            "cluster-signing-cert-file",
+            "cluster-signing-key-file",
+            "kubeconfig",
+            "leader-elect",
+            "root-ca-file",
+            "service-account-private-key-file",
+            "use-service-account-credentials",
+        ],
+    },
+    Component {
+        name: "kubelet",
+        tfvar: "kaws_kubelet_flags",
+        known: &[
+            ("eviction-hard", 3, None),
+            ("eviction-pressure-transition-period", 3, None),
+            ("eviction-soft", 3, None),
+            ("eviction-soft-grace-period", 3, None),
+            ("feature-gates", 3, None),
+            ("image-gc-high-threshold", 0, None),
+            ("image-gc-low-threshold", 0, None),
+            ("kube-reserved", 3, None),
+            ("max-pods", 0, None),
+            ("node-labels", 4, None),
+            ("pods-per-core", 4, None),
+            ("read-only-port", 0, None),
+            ("register-with-taints", 6, None),
+            ("serialize-image-pulls", 1, None),
+            ("system-reserved", 3, None),
+            ("v", 0, None),
+        ],
+        managed: &[
+            "allow-privileged",
+            "api-servers",
+            "cert-dir",
+            "cloud-provider",
+            "cluster-dns",
+            "cluster-domain",
+            "experimental-bootstrap-kubeconfig",
+            "hostname-override",
+            "kubeconfig",
+            "logtostderr",
+            "pod-manifest-path",
+            "register-schedulable",
+            "tls-cert-file",
+            "tls-private-key-file",
+        ],
+    },
+];
+
+impl Component {
+    /// The flags the cluster's metadata sets for the component, keyed by name without dashes.
+    pub fn flags<'m>(&self, metadata: &'m ClusterMetadata) -> &'m BTreeMap<String, String> {
+        match self.name {
+            "kube-apiserver" => &metadata.apiserver_flags,
+            "kube-controller-manager" => &metadata.controller_manager_flags,
+            _ => &metadata.kubelet_flags,
+        }
+    }
+
+    pub fn flags_mut<'m>(&self, metadata: &'m mut ClusterMetadata)
+    -> &'m mut BTreeMap<String, String> {
+        match self.name {
+            "kube-apiserver" => &mut metadata.apiserver_flags,
+            "kube-controller-manager" => &mut metadata.controller_manager_flags,
+            _ => &mut metadata.kubelet_flags,
+        }
+    }
+
+    /// Checks each of the metadata's flags for the component against what the given version of
+    /// Kubernetes accepts, then renders them as a raw terraform.tfvars list, e.g.
+    /// `["--feature-gates=ExpandPersistentVolumes=true"]`.
+    pub fn tfvars_value(&self, metadata: &ClusterMetadata, kubernetes_version: &str)
+    -> Result<String, KawsError> {
+        let minor = minor_version(kubernetes_version);
+        let mut rendered = vec![];
+
+        for (flag, value) in self.flags(metadata) {
+            if self.managed.contains(&flag.as_str()) {
+                return Err(KawsError::new(Message::ComponentFlagManaged {
+                    component: self.name.to_owned(),
+                    flag: flag.to_owned(),
+                }));
+            }
+
+            if !accepts(self.known, flag, minor) {
+                return Err(KawsError::new(Message::ComponentFlagUnknown {
+                    component: self.name.to_owned(),
+                    flag: flag.to_owned(),
+                    version: kubernetes_version.to_owned(),
+                }));
+            }
+
+            // The value ends up in a YAML list and a systemd command line, unquoted.
+            let unsafe_char = |c: char| c.is_whitespace() || "\"'\\#".contains(c);
+
+            if value.is_empty() || value.contains(unsafe_char) {
+                return Err(KawsError::new(Message::ComponentFlagValueInvalid {
+                    component: self.name.to_owned(),
+                    flag: flag.to_owned(),
+                    value: value.to_owned(),
+                }));
+            }
+
+            if flag == "feature-gates" {
+                self.check_feature_gates(flag, value, kubernetes_version)?;
+            }
+
+            rendered.push(format!("\"--{}={}\"", flag, value));
+        }
+
+        Ok(format!("[{}]", rendered.join(", ")))
+    }
+
+    // Private
+
+    fn check_feature_gates(&self, flag: &str, value: &str, kubernetes_version: &str)
+    -> Result<(), KawsError> {
+        let gates = parse_feature_gates(value).ok_or_else(|| {
+            KawsError::new(Message::ComponentFlagValueInvalid {
+                component: self.name.to_owned(),
+                flag: flag.to_owned(),
+                value: value.to_owned(),
+            })
+        })?;
+
+        for gate in gates.keys() {
+            if !feature_gate_exists(gate, kubernetes_version) {
+                return Err(KawsError::new(Message::FeatureGateUnknown {
+                    gate: gate.to_owned(),
+                    version: kubernetes_version.to_owned(),
+                }));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Whether the given version of Kubernetes has the feature gate `gate`.
+pub fn feature_gate_exists(gate: &str, kubernetes_version: &str) -> bool {
+    accepts(&FEATURE_GATES, gate, minor_version(kubernetes_version))
+}
+
+/// The gates a `--feature-gates` value such as "PodPriority=true,HugePages=false" sets, or none if
+/// it isn't a list of gates set to "true" or "false".
+pub fn parse_feature_gates(value: &str) -> Option<BTreeMap<String, bool>> {
+    let mut gates = BTreeMap::new();
+
+    for gate in value.split(',').filter(|gate| !gate.is_empty()) {
+        let mut parts = gate.splitn(2, '=');
+        let name = parts.next()?;
+        let enabled = parts.next()?.parse().ok()?;
+
+        gates.insert(name.to_owned(), enabled);
+    }
+
+    Some(gates)
+}
+
+/// The `--feature-gates` value that sets `gates`, the inverse of `parse_feature_gates`.
+pub fn feature_gates_value(gates: &BTreeMap<String, bool>) -> String {
+    gates.iter()
+        .map(|(gate, enabled)| format!("{}={}", gate, enabled))
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+// Whether `name` is in `known` for the minor version `minor`, or for any version if it's unknown.
+fn accepts(known: &[(&str, u64, Option<u64>)], name: &str, minor: Option<u64>) -> bool {
+    known.iter().any(|&(known_name, since, until)| {
+        known_name == name && minor.is_none_or(|minor| {
+            since <= minor && until.is_none_or(|until| minor < until)
+        })
+    })
+}
+
+// The minor version of a Kubernetes 1.x version such as "1.7.3", or none for a version kaws can't
+// parse, whose flags are only checked against every version's.
+fn minor_version(version: &str) -> Option<u64> {
+    let mut parts = version.trim_start_matches('v').split('.');
+
+    match (parts.next(), parts.next()) {
+        (Some("1"), Some(minor)) => minor.parse().ok(),
+        _ => None,
+    }
+}
@@ -0,0 +1,58 @@
+use std::collections::BTreeMap;
+
+use rusoto_core::credential::ChainProvider;
+
+use aws::pricing_products;
+use error::KawsError;
+use message::Message;
+
+/// Hours in an average month, as AWS bills them.
+const HOURS_PER_MONTH: f64 = 730.0;
+
+/// The monthly cost of running `servers`, pairs of an instance size and a number of instances, in
+/// US dollars at on-demand Linux prices in `region`.
+///
+/// Only EC2 instance hours are counted. EBS volumes, load balancers, and data transfer are left
+/// out, so the real bill is somewhat higher.
+pub fn monthly_instance_cost(
+    provider: &ChainProvider,
+    region: &str,
+    servers: &[(&str, u64)],
+) -> Result<f64, KawsError> {
+    let mut hourly_prices = BTreeMap::new();
+    let mut cost = 0.0;
+
+    for &(instance_size, count) in servers {
+        if !hourly_prices.contains_key(instance_size) {
+            hourly_prices.insert(instance_size, hourly_price(provider, region, instance_size)?);
+        }
+
+        cost += hourly_prices[instance_size] * count as f64 * HOURS_PER_MONTH;
+    }
+
+    Ok(cost)
+}
+
+// The on-demand price of a Linux instance, in US dollars per hour.
+fn hourly_price(provider: &ChainProvider, region: &str, instance_size: &str)
+-> Result<f64, KawsError> {
+    let products = pricing_products(provider, "AmazonEC2", &[
+        ("capacitystatus", "Used"),
+        ("instanceType", instance_size),
+        ("operatingSystem", "Linux"),
+        ("preInstalledSw", "NA"),
+        ("regionCode", region),
+        ("tenancy", "Shared"),
+    ])?;
+
+    // Terms and price dimensions are keyed by opaque codes; an on-demand instance has one of each.
+    products.iter()
+        .filter_map(|product| product["terms"]["OnDemand"].as_object()?.values().next().cloned())
+        .filter_map(|term| term["priceDimensions"].as_object()?.values().next().cloned())
+        .filter_map(|dimension| dimension["pricePerUnit"]["USD"].as_str()?.parse().ok())
+        .next()
+        .ok_or_else(|| KawsError::new(Message::InstancePriceUnknown {
+            instance_size: instance_size.to_owned(),
+            region: region.to_owned(),
+        }))
+}
@@ -1,14 +1,17 @@
 use std::process::{Command, Stdio};
 
 use error::{KawsError, KawsResult};
+use message::Message;
 
 pub fn ensure_dependencies() -> KawsResult {
-    ensure_cfssl().and(ensure_kubectl()).and(ensure_terraform())
+    ensure_kubectl().and(ensure_terraform())
 }
 
-fn ensure_cfssl() -> KawsResult {
-    let installed = match Command::new("cfssl")
-        .arg("version")
+/// Graphviz is only needed to render graphs as images, so it isn't checked by
+/// `ensure_dependencies`.
+pub fn ensure_graphviz() -> KawsResult {
+    let installed = match Command::new("dot")
+        .arg("-V")
         .stdout(Stdio::null())
         .stderr(Stdio::null())
         .status() {
@@ -19,11 +22,13 @@ fn ensure_cfssl() -> KawsResult {
     if installed {
         Ok(None)
     } else {
-        Err(KawsError::new("cfssl must be installed".to_string()))
+        Err(KawsError::new(Message::DependencyMissing {
+            program: "Graphviz".to_owned(),
+        }))
     }
 }
 
-fn ensure_kubectl() -> KawsResult {
+pub fn ensure_kubectl() -> KawsResult {
     let installed = match Command::new("kubectl")
         .stdout(Stdio::null())
         .stderr(Stdio::null())
@@ -35,11 +40,33 @@ fn ensure_kubectl() -> KawsResult {
     if installed {
         Ok(None)
     } else {
-        Err(KawsError::new("kubectl must be installed".to_string()))
+        Err(KawsError::new(Message::DependencyMissing {
+            program: "kubectl".to_owned(),
+        }))
+    }
+}
+
+/// ssh-keygen is only needed for SSH certificates, so it isn't checked by `ensure_dependencies`.
+pub fn ensure_ssh_keygen() -> KawsResult {
+    // ssh-keygen has no version flag and exits unsuccessfully without arguments, so only check
+    // that it can be run.
+    let installed = Command::new("ssh-keygen")
+        .arg("-?")
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .is_ok();
+
+    if installed {
+        Ok(None)
+    } else {
+        Err(KawsError::new(Message::DependencyMissing {
+            program: "ssh-keygen".to_owned(),
+        }))
     }
 }
 
-fn ensure_terraform() -> KawsResult {
+pub fn ensure_terraform() -> KawsResult {
     let installed = match Command::new("terraform")
         .arg("version")
         .stdout(Stdio::null())
@@ -52,6 +79,8 @@ fn ensure_terraform() -> KawsResult {
     if installed {
         Ok(None)
     } else {
-        Err(KawsError::new("terraform must be installed".to_string()))
+        Err(KawsError::new(Message::DependencyMissing {
+            program: "terraform".to_owned(),
+        }))
     }
 }
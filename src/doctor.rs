@@ -0,0 +1,84 @@
+use ansi_term::Colour::{Green, Red};
+use chrono::{Duration, Utc};
+use clap::ArgMatches;
+
+use aws::server_time;
+use dependencies::{ensure_kubectl, ensure_terraform};
+use error::{KawsError, KawsResult};
+use message::Message;
+use pki::DEFAULT_BACKDATE;
+use units::parse_duration;
+
+pub struct Doctor<'a> {
+    backdate: &'a str,
+    region: &'a str,
+}
+
+impl<'a> Doctor<'a> {
+    pub fn new(matches: &'a ArgMatches) -> Self {
+        Doctor {
+            backdate: matches.value_of("backdate").unwrap_or(DEFAULT_BACKDATE),
+            region: matches.value_of("region").unwrap_or("us-east-1"),
+        }
+    }
+
+    pub fn examine(&self) -> KawsResult {
+        let checks = vec![
+            ("kubectl is installed", ensure_kubectl().map(|_| None)),
+            ("terraform is installed", ensure_terraform().map(|_| None)),
+            ("clock agrees with AWS", self.check_clock_skew()),
+        ];
+
+        let mut failures = 0;
+
+        for (description, result) in checks {
+            match result {
+                Ok(None) => println!("{} {}", Green.paint("ok  "), description),
+                Ok(Some(detail)) => println!("{} {}: {}", Green.paint("ok  "), description, detail),
+                Err(error) => {
+                    failures += 1;
+
+                    println!("{} {}: {}", Red.paint("FAIL"), description, error);
+                }
+            }
+        }
+
+        if failures == 0 {
+            Ok(Some(Message::DoctorPassed))
+        } else {
+            Err(KawsError::new(Message::DoctorFailed {
+                failures,
+            }))
+        }
+    }
+
+    // Private
+
+    fn check_clock_skew(&self) -> Result<Option<String>, KawsError> {
+        let backdate = parse_duration(self.backdate).expect("clap should have validated backdate");
+        // AWS rejects signed requests whose timestamp is off by more than this.
+        let signature_limit = Duration::minutes(5);
+
+        let before = Utc::now();
+        let aws_time = server_time(&self.region.parse()?)?;
+        let after = Utc::now();
+
+        // Assume AWS read its clock halfway through the round trip.
+        let local_time = before + (after - before) / 2;
+        let skew = local_time - aws_time;
+        let seconds = skew.num_milliseconds() as f64 / 1000.0;
+
+        if skew > backdate {
+            Err(KawsError::new(Message::ClockAhead {
+                seconds,
+                backdate: self.backdate.to_owned(),
+            }))
+        } else if skew.abs() > signature_limit {
+            Err(KawsError::new(Message::ClockSkewed {
+                seconds,
+            }))
+        } else {
+            Ok(Some(format!("{:+.1}s", seconds)))
+        }
+    }
+}
@@ -0,0 +1,101 @@
+use std::fs::{create_dir_all, File};
+use std::io::Write;
+use std::path::Path;
+
+use clap::ArgMatches;
+
+use error::{KawsError, KawsResult};
+use message::Message;
+use template::Templates;
+use tfvars::Tfvars;
+
+pub struct DriftCron<'a> {
+    ci: &'a str,
+    cluster: &'a str,
+    schedule: &'a str,
+    terraform_version: &'a str,
+}
+
+impl<'a> DriftCron<'a> {
+    pub fn new(matches: &'a ArgMatches) -> Self {
+        DriftCron {
+            ci: matches.value_of("ci").unwrap_or("github"),
+            cluster: matches.value_of("cluster").expect("clap should have required cluster"),
+            schedule: matches.value_of("schedule").unwrap_or("0 6 * * *"),
+            terraform_version: matches.value_of("terraform-version").unwrap_or("0.11.15"),
+        }
+    }
+
+    pub fn generate(&self) -> KawsResult {
+        let tfvars = Tfvars::from_file(&format!("clusters/{}/terraform.tfvars", self.cluster))
+            .map_err(|error| self.tfvars_unreadable(error.to_string()))?;
+        let templates = Templates::for_repository()?;
+
+        let context = json!({
+            "account_id": self.tfvar(&tfvars, "kaws_account_id")?,
+            "cluster": self.cluster,
+            "kaws_version": env!("CARGO_PKG_VERSION"),
+            "kubernetes_version": self.tfvar(&tfvars, "kaws_version")?,
+            "region": self.tfvar(&tfvars, "kaws_region")?,
+            "schedule": self.schedule,
+            "terraform_version": self.terraform_version,
+            "zone_id": self.tfvar(&tfvars, "kaws_zone_id")?,
+        });
+
+        let (job_template, job_path) = match self.ci {
+            "gitlab" => (
+                "drift-cron-gitlab.yml",
+                format!("ci/kaws-drift-{}.gitlab-ci.yml", self.cluster),
+            ),
+            _ => (
+                "drift-cron-github.yml",
+                format!(".github/workflows/kaws-drift-{}.yml", self.cluster),
+            ),
+        };
+        let policy_path = format!("clusters/{}/drift-iam-policy.json", self.cluster);
+
+        // Render everything before writing anything so a broken override leaves no partial output.
+        let job = templates.render(job_template, &context)?;
+        let policy = templates.render("drift-iam-policy.json", &context)?;
+
+        log_wrap!("Writing CI job definition", {
+            write_file(&job_path, &job)?;
+        });
+
+        log_wrap!("Writing IAM policy", {
+            write_file(&policy_path, &policy)?;
+        });
+
+        Ok(Some(Message::DriftCronGenerated {
+            ci: self.ci.to_owned(),
+            cluster: self.cluster.to_owned(),
+            job_path,
+            policy_path,
+        }))
+    }
+
+    // Private
+
+    fn tfvar<'t>(&self, tfvars: &'t Tfvars, key: &str) -> Result<&'t str, KawsError> {
+        tfvars.get(key).ok_or_else(|| self.tfvars_unreadable(format!("{} is not set", key)))
+    }
+
+    fn tfvars_unreadable(&self, error: String) -> KawsError {
+        KawsError::new(Message::TfvarsUnreadable {
+            cluster: self.cluster.to_owned(),
+            error,
+        })
+    }
+}
+
+fn write_file(path: &str, contents: &str) -> Result<(), KawsError> {
+    if let Some(parent) = Path::new(path).parent() {
+        create_dir_all(parent)?;
+    }
+
+    let mut file = File::create(path)?;
+
+    write!(file, "{}", contents)?;
+
+    Ok(())
+}
@@ -1,110 +1,759 @@
-use std::fs::{File, remove_file};
+use std::collections::{BTreeMap, HashMap};
+use std::fs::{File, create_dir_all, remove_file};
 use std::io::{ErrorKind, Read, Write};
+use std::path::Path;
 
-use hyper::Client as HyperClient;
-use rusoto_core::{
-    ChainProvider,
-    DispatchSignedRequest,
-    ProvideAwsCredentials,
-    Region,
-    default_tls_client,
-};
+use openssl::rand::rand_bytes;
+use openssl::symm::{Cipher, decrypt_aead, encrypt_aead};
+use rusoto_core::{Region, RusotoError};
+use rusoto_core::credential::ChainProvider;
 use rusoto_kms::{
     DecryptError,
     DecryptRequest,
-    DecryptResponse,
-    EncryptError,
+    DescribeKeyRequest,
     EncryptRequest,
-    EncryptResponse,
+    GenerateDataKeyRequest,
+    GetPublicKeyRequest,
     Kms,
     KmsClient,
+    SignRequest,
 };
 use rustc_serialize::base64::{FromBase64, STANDARD, ToBase64};
 
+use audit::record_decryption;
+use aws::{
+    TracingHttpClient,
+    block_on,
+    last_request_id,
+    s3_get_object,
+    s3_is_versioned,
+    s3_list_objects,
+    s3_put_object,
+    secrets_manager_call,
+    ssm_call,
+};
 use error::{KawsError, KawsResult};
+use files::{entries, entries_if_present};
+use jobs::run_all;
+use message::Message;
+
+/// The most KMS encrypts in one request. Larger files are encrypted with a data key instead.
+const KMS_ENCRYPT_MAX_BYTES: usize = 4096;
+
+/// The start of a file encrypted with a data key, which distinguishes it from KMS's own
+/// ciphertext. It's followed by the length of the encrypted data key as two bytes, big-endian, the
+/// encrypted data key, and the AES-256-GCM nonce, tag, and ciphertext.
+const ENVELOPE_MAGIC: &[u8] = b"KAWSENV1";
+
+const ENVELOPE_NONCE_BYTES: usize = 12;
 
-pub struct Encryptor<'a, P, D> where P: ProvideAwsCredentials, D: DispatchSignedRequest {
-    client: KmsClient<P, D>,
+const ENVELOPE_TAG_BYTES: usize = 16;
+
+/// The suffix of the files kaws keeps KMS-encrypted secrets in.
+pub const ENCRYPTED_SUFFIX: &str = "-encrypted.base64";
+
+/// The directory, next to each encrypted file, with a copy of it for each of the cluster's other
+/// KMS regions, in a directory named for the region.
+pub const REGIONAL_COPIES_DIR: &str = "kms-regions";
+
+pub struct Encryptor<'a> {
+    client: KmsClient,
+    contexts_required: bool,
     decrypted_files: Vec<String>,
     kms_master_key_id: Option<&'a str>,
+    provider: ChainProvider,
+    region: Region,
+    regional_keys: Vec<RegionalKey>,
+    secret_store: Option<Box<dyn SecretStore>>,
 }
 
-impl<'a> Encryptor<'a, ChainProvider, HyperClient> {
+/// Where a cluster's encrypted files are kept in AWS besides its directory, for its servers to
+/// fetch at boot, as recorded under `secret_store` in the cluster's metadata.
+#[derive(Clone, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case", tag = "type")]
+pub enum SecretStoreConfig {
+    /// A versioned S3 bucket, with an object named CLUSTER/FILE for each file, encrypted with the
+    /// KMS key `kms_key`.
+    S3 {
+        bucket: String,
+        kms_key: String,
+    },
+    /// Secrets Manager, with a secret named kaws/CLUSTER/FILE for each file. Recorded as
+    /// "secretsmanager", as the command line and servers name it; metadata written before that
+    /// has "secrets-manager".
+    #[serde(rename = "secretsmanager", alias = "secrets-manager")]
+    SecretsManager,
+    /// SSM Parameter Store, with a SecureString parameter named /kaws/CLUSTER/FILE for each file.
+    Ssm,
+}
+
+/// A service that keeps copies of a cluster's encrypted files, each by the cluster and its name in
+/// the cluster's directory, holding the file's contents as kaws wrote them, base64-encoded.
+pub trait SecretStore: Send + Sync {
+    /// Checks that the store can keep the files, before any are written to it.
+    fn check(&self) -> Result<(), KawsError> {
+        Ok(())
+    }
+
+    /// Stores `encoded` as the file's copy, replacing any earlier one.
+    fn put(&self, cluster: &str, file: &str, encoded: &str) -> Result<(), KawsError>;
+
+    /// Stores `encoded` as the file's copy the way the store rotates secrets, if it has a way of
+    /// its own, so that servers only fetch it once the store has checked it. Otherwise the same
+    /// as `put`.
+    fn rotate(&self, cluster: &str, file: &str, encoded: &str) -> Result<(), KawsError> {
+        self.put(cluster, file, encoded)
+    }
+
+    /// The file's copy, or `None` if the store has none.
+    fn get(&self, cluster: &str, file: &str) -> Result<Option<String>, KawsError>;
+
+    /// The names of the cluster's files the store has copies of.
+    fn list(&self, cluster: &str) -> Result<Vec<String>, KawsError>;
+}
+
+/// An S3 bucket in the cluster's region, which must have versioning enabled, so that the files
+/// kaws overwrites can still be restored.
+///
+/// Objects are encrypted by S3 with a KMS key, normally the cluster's own, on top of kaws's own
+/// encryption.
+pub struct S3Store {
+    bucket: String,
+    kms_key: String,
+    provider: ChainProvider,
+    region: Region,
+}
+
+/// Secrets Manager, in the cluster's region.
+///
+/// Secrets are encrypted by Secrets Manager under the account's default key for it on top of
+/// kaws's own encryption, and tagged with the cluster and file when they're created. Each write
+/// is a new version of the secret, so Secrets Manager keeps the one it replaced as AWSPREVIOUS.
+/// Rotating a secret takes the steps a rotation function would: the new version is staged as
+/// AWSPENDING, read back, and only then made AWSCURRENT, which is the version servers fetch.
+pub struct SecretsManagerStore {
+    provider: ChainProvider,
+    region: Region,
+}
+
+/// SSM Parameter Store, in the cluster's region.
+///
+/// Parameters are SecureStrings, encrypted by SSM under the account's default key for it on top
+/// of kaws's own encryption. SSM puts them in the advanced tier only when they don't fit in the
+/// standard one, and keeps a parameter that has moved up there when a smaller value overwrites it,
+/// since it can't move back down.
+pub struct SsmParameterStore {
+    provider: ChainProvider,
+    region: Region,
+}
+
+/// Signs with a KMS asymmetric key, whose private key never leaves KMS.
+pub struct KmsSigner {
+    client: KmsClient,
+    key_id: String,
+}
+
+impl<'a> Encryptor<'a> {
     pub fn new(
         provider: ChainProvider,
         region: Region,
         kms_master_key_id: Option<&'a str>,
-    ) -> Encryptor<'a, ChainProvider, HyperClient> {
+    ) -> Encryptor<'a> {
         Encryptor {
-            client: KmsClient::new(
-                default_tls_client().expect("failed to create HTTP client with TLS"),
-                provider,
-                region,
+            client: KmsClient::new_with(
+                TracingHttpClient::new(),
+                provider.clone(),
+                region.clone(),
             ),
+            contexts_required: false,
             decrypted_files: vec![],
-            kms_master_key_id: kms_master_key_id,
+            kms_master_key_id,
+            provider,
+            region,
+            regional_keys: vec![],
+            secret_store: None,
         }
     }
 
+    /// Only decrypts files with their encryption contexts, without falling back to decrypting
+    /// them without one, as the cluster's metadata records under `encryption_contexts_required`.
+    pub fn with_contexts_required(mut self, required: bool) -> Self {
+        self.contexts_required = required;
+        self
+    }
+
+    /// Also encrypts every file with each of `keys`, KMS key ARNs by region, into its regional
+    /// copies, as the cluster's metadata records them under `kms_regions`.
+    pub fn with_regional_keys(mut self, keys: &BTreeMap<String, String>)
+    -> Result<Self, KawsError> {
+        for (region, arn) in keys {
+            self.regional_keys.push(RegionalKey {
+                arn: arn.clone(),
+                client: KmsClient::new_with(
+                    TracingHttpClient::new(),
+                    self.provider.clone(),
+                    region.parse()?,
+                ),
+                region: region.clone(),
+            });
+        }
+
+        Ok(self)
+    }
+
+    /// Also writes every file in a cluster's directory to the store `config` names, in the
+    /// encryptor's region, as the cluster's metadata records it under `secret_store`.
+    pub fn with_secret_store(mut self, config: &SecretStoreConfig) -> Self {
+        self.secret_store = Some(config.store(self.provider.clone(), self.region.clone()));
+        self
+    }
+
+    /// Decrypts the file at `source`. If KMS in the encryptor's region is unreachable, the file's
+    /// regional copies are decrypted in their own regions instead, in order, until one succeeds.
     pub fn decrypt_file(&mut self, source: &str) -> Result<Vec<u8>, KawsError> {
-        let mut src = File::open(source)?;
+        let region = self.region.name();
+        let required = self.contexts_required;
+        let plaintext = match decrypt_file_with(&self.client, region, source, source, required) {
+            Err(error) if is_unreachable(&error) => self.decrypt_regional_copy(source, error)?,
+            result => result?,
+        };
 
-        let mut encoded_data = String::new();
+        record_decryption(source, last_request_id());
 
-        src.read_to_string(&mut encoded_data)?;
+        Ok(plaintext)
+    }
 
-        let encrypted_data = encoded_data.from_base64()?;
-        let decrypted_data = self.decrypt(encrypted_data)?;
+    /// The ARN of the KMS key `key_id` names, which may be a key ID or ARN, or an alias, e.g.
+    /// "alias/kaws-production", or alias ARN, resolved to the key the alias points to now.
+    pub fn key_arn(&self, key_id: &str) -> Result<String, KawsError> {
+        let request = DescribeKeyRequest {
+            key_id: key_id.to_owned(),
+            ..Default::default()
+        };
 
-        match decrypted_data.plaintext {
-            Some(plaintext) => return Ok(plaintext),
-            None => return Err(KawsError::new("No plaintext was returned from KMS".to_owned())),
+        match block_on(self.client.describe_key(request))?.key_metadata.and_then(|key| key.arn) {
+            Some(arn) => Ok(arn),
+            None => Err(KawsError::new(Message::KmsKeyUnresolved { key_id: key_id.to_owned() })),
         }
     }
 
     pub fn encrypt_and_write_file(&mut self, data: &[u8], file_path: &str) -> KawsResult {
-        let encrypted_data = self.encrypt(data.to_owned())?;
-        let mut file = File::create(file_path)?;
+        self.encrypt_and_write_files(&[(data, file_path)])
+    }
+
+    /// Encrypts each of `files`, given as the data and the path to write it to, with the KMS
+    /// requests made concurrently, up to `--jobs` at a time, since each is a round trip to AWS.
+    /// Nothing is written unless every request succeeds.
+    ///
+    /// Each file is encrypted with an encryption context naming it and its cluster, which it must
+    /// be decrypted with too, so its ciphertext can't be passed off as another file's, and key
+    /// policies can limit who decrypts which cluster's files.
+    ///
+    /// Files too large for KMS to encrypt, such as CA bundles, are encrypted locally with
+    /// AES-256-GCM instead, under a data key KMS generates and encrypts with the cluster's key,
+    /// which is stored with each of them. Smaller files are still encrypted by KMS itself, since
+    /// servers decrypt theirs with `aws kms decrypt`, which can't decrypt the rest.
+    ///
+    /// Each file's regional copies are encrypted in the same way, under the key for their region,
+    /// with the file's own encryption context. With a secret store, each file in a cluster's
+    /// directory is written to it as well, after the files themselves.
+    pub fn encrypt_and_write_files(&mut self, files: &[(&[u8], &str)]) -> KawsResult {
+        let mut jobs = vec![];
+
+        for &(data, file_path) in files {
+            jobs.push((data, file_path.to_owned(), &self.client, self.kms_master_key(), file_path));
+        }
+
+        self.write_encrypted(files, jobs)
+    }
+
+    /// Encrypts each of `files`, given as the data and the path of the file it's decrypted from,
+    /// into the file's regional copies only, as when a region is added to a cluster.
+    pub fn encrypt_and_write_regional_copies(&mut self, files: &[(&[u8], &str)]) -> KawsResult {
+        self.write_encrypted(files, vec![])
+    }
+
+    /// A signer for the KMS asymmetric key `key_id`, in the same region as the encryptor.
+    pub fn signer(&self, key_id: &str) -> KmsSigner {
+        KmsSigner {
+            client: self.client.clone(),
+            key_id: key_id.to_owned(),
+        }
+    }
+
+    // Private
+
+    // Decrypts the regional copies of the file at `source` in turn, after KMS in the encryptor's
+    // region failed with `error`, which is returned if there are none or none can be decrypted.
+    fn decrypt_regional_copy(&self, source: &str, error: KawsError)
+    -> Result<Vec<u8>, KawsError> {
+        let mut result = Err(error);
+
+        for (region, copy) in regional_copies(source)? {
+            warn!(
+                "KMS in {} is unreachable, decrypting the copy of {} in {}",
+                self.region.name(),
+                source,
+                region,
+            );
+
+            let client = KmsClient::new_with(
+                TracingHttpClient::new(),
+                self.provider.clone(),
+                region.parse()?,
+            );
+
+            result = decrypt_file_with(&client, &region, &copy, source, self.contexts_required);
+
+            if result.is_ok() {
+                break;
+            }
+        }
+
+        result
+    }
 
-        match encrypted_data.ciphertext_blob {
-            Some(ref ciphertext_blob) => {
-                let encoded_data = ciphertext_blob.to_base64(STANDARD);
+    fn kms_master_key(&self) -> &str {
+        self.kms_master_key_id.expect("KMS key must be supplied to encrypt")
+    }
+
+    // Encrypts and writes `jobs`, each the data, the path to write it to, the client and key to
+    // encrypt it with, and the path whose encryption context to use, and the regional copies of
+    // `files`. Nothing is written unless every request succeeds. The files `jobs` names first are
+    // put in the secret store, if there is one, before anything is written to the cluster's
+    // directory, which is left alone if any put fails.
+    fn write_encrypted<'b>(
+        &'b self,
+        files: &[(&'b [u8], &'b str)],
+        mut jobs: Vec<(&'b [u8], String, &'b KmsClient, &'b str, &'b str)>,
+    ) -> KawsResult {
+        let stored = jobs.len();
 
-                file.write_all(encoded_data.as_bytes())?;
+        for &(data, file_path) in files {
+            for key in &self.regional_keys {
+                jobs.push((
+                    data,
+                    regional_copy_path(file_path, &key.region),
+                    &key.client,
+                    &key.arn,
+                    file_path,
+                ));
             }
-            None => return Err(KawsError::new("No ciphertext was returned from KMS".to_owned())),
+        }
+
+        let paths: Vec<String> = jobs.iter().map(|job| job.1.clone()).collect();
+        let results = run_all(jobs, |(data, _, client, key_id, context_path)| {
+            let context = encryption_context(context_path);
+
+            if data.len() > KMS_ENCRYPT_MAX_BYTES {
+                generate_data_key(client, key_id, context)?.seal(data)
+            } else {
+                encrypt(client, key_id, data.to_owned(), context)
+            }
+        });
+        let mut encoded_files = vec![];
+
+        for (result, file_path) in results.into_iter().zip(paths) {
+            encoded_files.push((result?.to_base64(STANDARD), file_path));
+        }
+
+        if let Some(ref store) = self.secret_store {
+            let puts: Vec<(String, String, &String, &String)> = encoded_files[..stored].iter()
+                .filter_map(|(encoded_data, file_path)| {
+                    cluster_file(file_path)
+                        .map(|(cluster, file)| (cluster, file, encoded_data, file_path))
+                })
+                .collect();
+            let mut error = None;
+            let mut failed = vec![];
+            let mut updated = vec![];
+
+            for (result, file_path) in run_all(puts, |(cluster, file, encoded_data, file_path)| {
+                (store.put(&cluster, &file, encoded_data), file_path)
+            }) {
+                match result {
+                    Ok(()) => updated.push(file_path.clone()),
+                    Err(put_error) => {
+                        failed.push(file_path.clone());
+                        error.get_or_insert(put_error);
+                    }
+                }
+            }
+
+            if let Some(error) = error {
+                return Err(KawsError::new(Message::SecretStorePutFailed {
+                    error: error.to_string(),
+                    failed,
+                    updated,
+                }));
+            }
+        }
+
+        for (encoded_data, file_path) in &encoded_files {
+            if let Some(dir) = Path::new(file_path).parent() {
+                create_dir_all(dir)?;
+            }
+
+            File::create(file_path)?.write_all(encoded_data.as_bytes())?;
         }
 
         Ok(None)
     }
+}
 
-    // Private
+impl SecretStoreConfig {
+    /// The store's name as servers know it, from the cluster's terraform.tfvars, e.g. "ssm".
+    pub fn name(&self) -> &'static str {
+        match *self {
+            SecretStoreConfig::S3 { .. } => "s3",
+            SecretStoreConfig::SecretsManager => "secretsmanager",
+            SecretStoreConfig::Ssm => "ssm",
+        }
+    }
+
+    /// The store's name for people, e.g. "SSM Parameter Store".
+    pub fn description(&self) -> &'static str {
+        match *self {
+            SecretStoreConfig::S3 { .. } => "S3",
+            SecretStoreConfig::SecretsManager => "Secrets Manager",
+            SecretStoreConfig::Ssm => "SSM Parameter Store",
+        }
+    }
+
+    /// The store, in `region`.
+    pub fn store(&self, provider: ChainProvider, region: Region) -> Box<dyn SecretStore> {
+        match *self {
+            SecretStoreConfig::S3 { ref bucket, ref kms_key } => Box::new(S3Store {
+                bucket: bucket.clone(),
+                kms_key: kms_key.clone(),
+                provider,
+                region,
+            }),
+            SecretStoreConfig::SecretsManager => Box::new(SecretsManagerStore { provider, region }),
+            SecretStoreConfig::Ssm => Box::new(SsmParameterStore { provider, region }),
+        }
+    }
+}
 
-    fn decrypt<'b>(&mut self, encrypted_data: Vec<u8>) -> Result<DecryptResponse, DecryptError> {
-        let request = DecryptRequest {
-            encryption_context: None,
-            grant_tokens: None,
-            ciphertext_blob: encrypted_data,
+impl SecretStore for S3Store {
+    fn check(&self) -> Result<(), KawsError> {
+        if s3_is_versioned(&self.provider, &self.region, &self.bucket)? {
+            Ok(())
+        } else {
+            Err(KawsError::new(Message::S3BucketNotVersioned { bucket: self.bucket.clone() }))
+        }
+    }
+
+    fn put(&self, cluster: &str, file: &str, encoded: &str) -> Result<(), KawsError> {
+        s3_put_object(
+            &self.provider,
+            &self.region,
+            &self.bucket,
+            &s3_object_key(cluster, file),
+            encoded.as_bytes(),
+            Some(&self.kms_key),
+        )
+    }
+
+    fn get(&self, cluster: &str, file: &str) -> Result<Option<String>, KawsError> {
+        let key = s3_object_key(cluster, file);
+
+        match s3_get_object(&self.provider, &self.region, &self.bucket, &key)? {
+            Some(contents) => {
+                Ok(Some(String::from_utf8(contents).map_err(|error| error.utf8_error())?))
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn list(&self, cluster: &str) -> Result<Vec<String>, KawsError> {
+        let prefix = s3_object_key(cluster, "");
+        let keys = s3_list_objects(&self.provider, &self.region, &self.bucket, &prefix)?;
+
+        Ok(keys.iter().filter_map(|key| key.strip_prefix(&prefix)).map(str::to_owned).collect())
+    }
+}
+
+impl SecretStore for SecretsManagerStore {
+    fn put(&self, cluster: &str, file: &str, encoded: &str) -> Result<(), KawsError> {
+        let name = secrets_manager_secret_name(cluster, file);
+        let response = secrets_manager_call(&self.provider, &self.region, "PutSecretValue", json!({
+            "SecretId": name,
+            "SecretString": encoded,
+        }));
+
+        match response {
+            Ok(_) => Ok(()),
+            Err(ref error)
+                if matches!(*error.message(), Message::SecretsManagerSecretNotFound { .. }) => {
+                secrets_manager_call(&self.provider, &self.region, "CreateSecret", json!({
+                    "Name": name,
+                    "Description": format!("{} of kaws cluster {}", file, cluster),
+                    "SecretString": encoded,
+                    "Tags": [
+                        { "Key": "kaws-cluster", "Value": cluster },
+                        { "Key": "kaws-file", "Value": file },
+                    ],
+                }))?;
+
+                Ok(())
+            }
+            Err(error) => Err(error),
+        }
+    }
+
+    fn rotate(&self, cluster: &str, file: &str, encoded: &str) -> Result<(), KawsError> {
+        let name = secrets_manager_secret_name(cluster, file);
+        let call = |action, body| secrets_manager_call(&self.provider, &self.region, action, body);
+        let secret = match call("DescribeSecret", json!({ "SecretId": name })) {
+            Ok(secret) => secret,
+            Err(ref error)
+                if matches!(*error.message(), Message::SecretsManagerSecretNotFound { .. }) => {
+                return self.put(cluster, file, encoded);
+            }
+            Err(error) => return Err(error),
         };
+        let current = secret["VersionIdsToStages"].as_object().into_iter().flatten()
+            .find(|(_, stages)| {
+                stages.as_array().into_iter().flatten().any(|stage| stage == "AWSCURRENT")
+            })
+            .map(|(version, _)| version.clone());
+        let mut token = [0; 16];
+
+        rand_bytes(&mut token)?;
+
+        let token: String = token.iter().map(|byte| format!("{:02x}", byte)).collect();
+        let pending = call("PutSecretValue", json!({
+            "SecretId": name,
+            "ClientRequestToken": token,
+            "SecretString": encoded,
+            "VersionStages": ["AWSPENDING"],
+        }))?;
+        let staged = call("GetSecretValue", json!({
+            "SecretId": name,
+            "VersionStage": "AWSPENDING",
+        }))?;
+
+        if staged["SecretString"].as_str() != Some(encoded) {
+            return Err(KawsError::new(Message::SecretsManagerPendingVersionInvalid { name }));
+        }
+
+        let mut promotion = json!({
+            "SecretId": name,
+            "VersionStage": "AWSCURRENT",
+            "MoveToVersionId": pending["VersionId"],
+        });
+
+        if let Some(current) = current {
+            promotion["RemoveFromVersionId"] = json!(current);
+        }
+
+        call("UpdateSecretVersionStage", promotion)?;
+        call("UpdateSecretVersionStage", json!({
+            "SecretId": name,
+            "VersionStage": "AWSPENDING",
+            "RemoveFromVersionId": pending["VersionId"],
+        }))?;
+
+        Ok(())
+    }
+
+    fn get(&self, cluster: &str, file: &str) -> Result<Option<String>, KawsError> {
+        let response = secrets_manager_call(&self.provider, &self.region, "GetSecretValue", json!({
+            "SecretId": secrets_manager_secret_name(cluster, file),
+        }));
+
+        match response {
+            Ok(body) => Ok(body["SecretString"].as_str().map(str::to_owned)),
+            Err(ref error)
+                if matches!(*error.message(), Message::SecretsManagerSecretNotFound { .. }) => {
+                Ok(None)
+            }
+            Err(error) => Err(error),
+        }
+    }
+
+    fn list(&self, cluster: &str) -> Result<Vec<String>, KawsError> {
+        let prefix = secrets_manager_secret_name(cluster, "");
+        let mut files = vec![];
+        let mut next_token: Option<String> = None;
+
+        loop {
+            let mut body = json!({
+                "Filters": [
+                    { "Key": "name", "Values": [prefix] },
+                    { "Key": "tag-key", "Values": ["kaws-cluster"] },
+                ],
+                "MaxResults": 100,
+            });
+
+            if let Some(ref next_token) = next_token {
+                body["NextToken"] = json!(next_token);
+            }
+
+            let response = secrets_manager_call(&self.provider, &self.region, "ListSecrets", body)?;
 
-        self.client.decrypt(&request)
+            for secret in response["SecretList"].as_array().into_iter().flatten() {
+                if let Some(file) = secret["Name"].as_str()
+                    .and_then(|name| name.strip_prefix(&prefix)) {
+                    files.push(file.to_owned());
+                }
+            }
+
+            match response["NextToken"].as_str() {
+                Some(token) => next_token = Some(token.to_owned()),
+                None => return Ok(files),
+            }
+        }
     }
+}
 
-    fn encrypt<'b>(&mut self, decrypted_data: Vec<u8>) -> Result<EncryptResponse, EncryptError> {
-        let request = EncryptRequest {
-            plaintext: decrypted_data,
-            encryption_context: None,
-            key_id: self.kms_master_key_id.expect("KMS key must be supplied to encrypt").to_owned(),
-            grant_tokens: None,
+impl SecretStore for SsmParameterStore {
+    fn put(&self, cluster: &str, file: &str, encoded: &str) -> Result<(), KawsError> {
+        ssm_call(&self.provider, &self.region, "PutParameter", json!({
+            "Name": ssm_parameter_name(cluster, file),
+            "Value": encoded,
+            "Type": "SecureString",
+            "Tier": "Intelligent-Tiering",
+            "Overwrite": true,
+        }))?;
+
+        Ok(())
+    }
+
+    fn get(&self, cluster: &str, file: &str) -> Result<Option<String>, KawsError> {
+        let response = ssm_call(&self.provider, &self.region, "GetParameter", json!({
+            "Name": ssm_parameter_name(cluster, file),
+            "WithDecryption": true,
+        }));
+
+        match response {
+            Ok(body) => Ok(body["Parameter"]["Value"].as_str().map(str::to_owned)),
+            Err(ref error) if matches!(*error.message(), Message::SsmParameterNotFound { .. }) => {
+                Ok(None)
+            }
+            Err(error) => Err(error),
+        }
+    }
+
+    fn list(&self, cluster: &str) -> Result<Vec<String>, KawsError> {
+        let prefix = ssm_parameter_name(cluster, "");
+        let mut files = vec![];
+        let mut next_token: Option<String> = None;
+
+        loop {
+            let mut body = json!({
+                "Path": prefix,
+                "MaxResults": 10,
+            });
+
+            if let Some(ref next_token) = next_token {
+                body["NextToken"] = json!(next_token);
+            }
+
+            let response = ssm_call(&self.provider, &self.region, "GetParametersByPath", body)?;
+
+            for parameter in response["Parameters"].as_array().into_iter().flatten() {
+                if let Some(file) = parameter["Name"].as_str()
+                    .and_then(|name| name.strip_prefix(&prefix)) {
+                    files.push(file.to_owned());
+                }
+            }
+
+            match response["NextToken"].as_str() {
+                Some(token) => next_token = Some(token.to_owned()),
+                None => return Ok(files),
+            }
+        }
+    }
+}
+
+/// An AES-256 key KMS generated, with its encryption under the cluster's KMS key.
+struct DataKey {
+    encrypted: Vec<u8>,
+    plaintext: Vec<u8>,
+}
+
+/// A KMS key in another region that files' regional copies are encrypted with.
+struct RegionalKey {
+    arn: String,
+    client: KmsClient,
+    region: String,
+}
+
+impl DataKey {
+    // Encrypts `data` as an envelope, whose header, which includes the encrypted data key, is
+    // authenticated along with the ciphertext.
+    fn seal(&self, data: &[u8]) -> Result<Vec<u8>, KawsError> {
+        let mut nonce = [0; ENVELOPE_NONCE_BYTES];
+        let mut tag = [0; ENVELOPE_TAG_BYTES];
+        let mut envelope = ENVELOPE_MAGIC.to_vec();
+
+        rand_bytes(&mut nonce)?;
+
+        envelope.extend_from_slice(&(self.encrypted.len() as u16).to_be_bytes());
+        envelope.extend_from_slice(&self.encrypted);
+
+        let ciphertext = encrypt_aead(
+            Cipher::aes_256_gcm(),
+            &self.plaintext,
+            Some(&nonce),
+            &envelope,
+            data,
+            &mut tag,
+        )?;
+
+        envelope.extend_from_slice(&nonce);
+        envelope.extend_from_slice(&tag);
+        envelope.extend_from_slice(&ciphertext);
+
+        Ok(envelope)
+    }
+}
+
+impl KmsSigner {
+    pub fn key_id(&self) -> &str {
+        &self.key_id
+    }
+
+    /// The key's public key, DER-encoded as a SubjectPublicKeyInfo.
+    pub fn public_key(&self) -> Result<Vec<u8>, KawsError> {
+        let request = GetPublicKeyRequest {
+            key_id: self.key_id.clone(),
+            ..Default::default()
         };
 
-        self.client.encrypt(&request)
+        match block_on(self.client.get_public_key(request))?.public_key {
+            Some(public_key) => Ok(public_key.to_vec()),
+            None => Err(KawsError::new(Message::KmsSigningFailed {
+                error: "KMS returned no public key".to_owned(),
+                key_id: self.key_id.clone(),
+            })),
+        }
     }
 
+    /// Signs `digest`, a hash of the message, with a KMS signing algorithm such as
+    /// "ECDSA_SHA_256", which must match the hash.
+    pub fn sign_digest(&self, digest: &[u8], algorithm: &str) -> Result<Vec<u8>, KawsError> {
+        let request = SignRequest {
+            key_id: self.key_id.clone(),
+            message: digest.to_vec().into(),
+            message_type: Some("DIGEST".to_owned()),
+            signing_algorithm: algorithm.to_owned(),
+            ..Default::default()
+        };
+
+        match block_on(self.client.sign(request))?.signature {
+            Some(signature) => Ok(signature.to_vec()),
+            None => Err(KawsError::new(Message::KmsSigningFailed {
+                error: "KMS returned no signature".to_owned(),
+                key_id: self.key_id.clone(),
+            })),
+        }
+    }
 }
 
-impl<'a, P, D> Drop for Encryptor<'a, P, D>
-where P: ProvideAwsCredentials, D: DispatchSignedRequest {
+impl<'a> Drop for Encryptor<'a> {
     fn drop(&mut self) {
         let mut failures = vec![];
 
@@ -128,3 +777,250 @@ where P: ProvideAwsCredentials, D: DispatchSignedRequest {
         }
     }
 }
+
+/// The paths of the encrypted files in the directory of the cluster `cluster`, skipping the empty
+/// placeholders `kaws cluster init` creates for PKI that hasn't been generated yet.
+pub fn encrypted_file_paths(cluster: &str) -> Result<Vec<String>, KawsError> {
+    let mut paths = vec![];
+
+    for entry in entries(&format!("clusters/{}", cluster))? {
+        if entry.name.ends_with(ENCRYPTED_SUFFIX) && !entry.is_empty()? {
+            paths.push(entry.path);
+        }
+    }
+
+    Ok(paths)
+}
+
+/// The path of the copy of the encrypted file at `path` for `region`.
+pub fn regional_copy_path(path: &str, region: &str) -> String {
+    let path = Path::new(path);
+
+    path.with_file_name(REGIONAL_COPIES_DIR)
+        .join(region)
+        .join(path.file_name().unwrap_or_default())
+        .to_string_lossy()
+        .into_owned()
+}
+
+/// The regional copies of the encrypted file at `path` that exist, by region, in order.
+pub fn regional_copies(path: &str) -> Result<Vec<(String, String)>, KawsError> {
+    let dir = Path::new(path).with_file_name(REGIONAL_COPIES_DIR);
+    let mut copies = vec![];
+
+    for entry in entries_if_present(&dir.to_string_lossy())? {
+        let copy = regional_copy_path(path, &entry.name);
+
+        if entry.is_dir()? && Path::new(&copy).exists() {
+            copies.push((entry.name, copy));
+        }
+    }
+
+    Ok(copies)
+}
+
+/// Removes the encrypted file at `path` and its regional copies.
+pub fn remove_encrypted_file(path: &str) -> Result<(), KawsError> {
+    remove_file(path)?;
+
+    for (_, copy) in regional_copies(path)? {
+        remove_file(copy)?;
+    }
+
+    Ok(())
+}
+
+// Private
+
+// Files encrypted before kaws used encryption contexts can only be decrypted without one, so if
+// KMS rejects the context, the file is decrypted again without it, unless `contexts_required`,
+// once the cluster has no such files.
+fn decrypt(
+    client: &KmsClient,
+    region: &str,
+    encrypted_data: Vec<u8>,
+    context: HashMap<String, String>,
+    contexts_required: bool,
+) -> Result<Vec<u8>, KawsError> {
+    let request = |encryption_context| DecryptRequest {
+        ciphertext_blob: encrypted_data.clone().into(),
+        encryption_context,
+        ..Default::default()
+    };
+    let response = match block_on(client.decrypt(request(Some(context)))) {
+        Err(RusotoError::Service(DecryptError::InvalidCiphertext(_))) if !contexts_required => {
+            block_on(client.decrypt(request(None)))
+        }
+        result => result,
+    };
+    let response = match response {
+        Err(ref error) if is_outage(error) => return Err(KawsError::new(Message::KmsUnreachable {
+            error: error.to_string(),
+            region: region.to_owned(),
+        })),
+        result => result?,
+    };
+
+    match response.plaintext {
+        Some(plaintext) => Ok(plaintext.to_vec()),
+        None => Err(KawsError::new(Message::KmsNoPlaintext)),
+    }
+}
+
+// Decrypts the file at `path` with `client`, for `region`, as the file at `source`, whose
+// encryption context it was encrypted with, and without one too unless `contexts_required`.
+fn decrypt_file_with(
+    client: &KmsClient,
+    region: &str,
+    path: &str,
+    source: &str,
+    contexts_required: bool,
+) -> Result<Vec<u8>, KawsError> {
+    let mut src = File::open(path)?;
+
+    let mut encoded_data = String::new();
+
+    src.read_to_string(&mut encoded_data)?;
+
+    let encrypted_data = encoded_data.from_base64()?;
+    let context = encryption_context(source);
+
+    if encrypted_data.starts_with(ENVELOPE_MAGIC) {
+        open_envelope(client, region, &encrypted_data, path, context)
+    } else {
+        decrypt(client, region, encrypted_data, context, contexts_required)
+    }
+}
+
+fn encrypt(
+    client: &KmsClient,
+    key_id: &str,
+    decrypted_data: Vec<u8>,
+    context: HashMap<String, String>,
+) -> Result<Vec<u8>, KawsError> {
+    let request = EncryptRequest {
+        encryption_context: Some(context),
+        plaintext: decrypted_data.into(),
+        key_id: key_id.to_owned(),
+        ..Default::default()
+    };
+
+    match block_on(client.encrypt(request))?.ciphertext_blob {
+        Some(ciphertext_blob) => Ok(ciphertext_blob.to_vec()),
+        None => Err(KawsError::new(Message::KmsNoCiphertext)),
+    }
+}
+
+fn generate_data_key(client: &KmsClient, key_id: &str, context: HashMap<String, String>)
+-> Result<DataKey, KawsError> {
+    let request = GenerateDataKeyRequest {
+        encryption_context: Some(context),
+        key_id: key_id.to_owned(),
+        key_spec: Some("AES_256".to_owned()),
+        ..Default::default()
+    };
+    let response = block_on(client.generate_data_key(request))?;
+
+    match (response.plaintext, response.ciphertext_blob) {
+        (Some(plaintext), Some(ciphertext_blob)) => Ok(DataKey {
+            encrypted: ciphertext_blob.to_vec(),
+            plaintext: plaintext.to_vec(),
+        }),
+        (None, _) => Err(KawsError::new(Message::KmsNoPlaintext)),
+        (_, None) => Err(KawsError::new(Message::KmsNoCiphertext)),
+    }
+}
+
+// Whether KMS failed because it couldn't be reached or couldn't serve the request, rather than
+// because it refused it, so that another region's KMS may succeed.
+fn is_outage(error: &RusotoError<DecryptError>) -> bool {
+    match *error {
+        RusotoError::HttpDispatch(_) |
+        RusotoError::Service(DecryptError::DependencyTimeout(_)) |
+        RusotoError::Service(DecryptError::KMSInternal(_)) |
+        RusotoError::Service(DecryptError::KeyUnavailable(_)) => true,
+        RusotoError::Unknown(ref response) => response.status.is_server_error(),
+        _ => false,
+    }
+}
+
+fn is_unreachable(error: &KawsError) -> bool {
+    matches!(*error.message(), Message::KmsUnreachable { .. })
+}
+
+// Decrypts a file encrypted with a data key, which KMS decrypts first.
+fn open_envelope(
+    client: &KmsClient,
+    region: &str,
+    envelope: &[u8],
+    source: &str,
+    context: HashMap<String, String>,
+) -> Result<Vec<u8>, KawsError> {
+    let invalid = || KawsError::new(Message::EncryptedFileInvalid { path: source.to_owned() });
+    let rest = &envelope[ENVELOPE_MAGIC.len()..];
+
+    if rest.len() < 2 {
+        return Err(invalid());
+    }
+
+    let key_length = ((rest[0] as usize) << 8) | rest[1] as usize;
+    let header_length = ENVELOPE_MAGIC.len() + 2 + key_length;
+
+    if envelope.len() < header_length + ENVELOPE_NONCE_BYTES + ENVELOPE_TAG_BYTES {
+        return Err(invalid());
+    }
+
+    let (header, sealed) = envelope.split_at(header_length);
+    let (nonce, sealed) = sealed.split_at(ENVELOPE_NONCE_BYTES);
+    let (tag, ciphertext) = sealed.split_at(ENVELOPE_TAG_BYTES);
+    // Envelopes were only ever sealed with an encryption context.
+    let key = decrypt(client, region, header[ENVELOPE_MAGIC.len() + 2..].to_vec(), context, true)?;
+
+    decrypt_aead(Cipher::aes_256_gcm(), &key, Some(nonce), header, ciphertext, tag)
+        .map_err(|_| invalid())
+}
+
+// The cluster whose directory holds the file at `path`, and the file's name there.
+fn cluster_file(path: &str) -> Option<(String, String)> {
+    let context = encryption_context(path);
+
+    Some((context.get("kaws-cluster")?.clone(), context.get("kaws-file")?.clone()))
+}
+
+// The key of the S3 object holding the file `file` of `cluster`, which servers fetch it by.
+fn s3_object_key(cluster: &str, file: &str) -> String {
+    format!("{}/{}", cluster, file)
+}
+
+// The name of the Secrets Manager secret holding the file `file` of `cluster`, which servers fetch
+// it by.
+fn secrets_manager_secret_name(cluster: &str, file: &str) -> String {
+    format!("kaws/{}/{}", cluster, file)
+}
+
+// The name of the SSM parameter holding the file `file` of `cluster`, which servers fetch it by.
+fn ssm_parameter_name(cluster: &str, file: &str) -> String {
+    format!("/kaws/{}/{}", cluster, file)
+}
+
+// The encryption context for the file at `path`: its name as "kaws-file" and, if it's in a
+// cluster's directory, the cluster's name as "kaws-cluster". Servers decrypt their copies of
+// cluster files with the same context.
+fn encryption_context(path: &str) -> HashMap<String, String> {
+    let path = Path::new(path);
+    let mut context = HashMap::new();
+
+    if let Some(file) = path.file_name() {
+        context.insert("kaws-file".to_owned(), file.to_string_lossy().into_owned());
+    }
+
+    if let Some(dir) = path.parent() {
+        if dir.parent().and_then(Path::file_name) == Some("clusters".as_ref()) {
+            if let Some(cluster) = dir.file_name() {
+                context.insert("kaws-cluster".to_owned(), cluster.to_string_lossy().into_owned());
+            }
+        }
+    }
+
+    context
+}
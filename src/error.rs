@@ -3,33 +3,61 @@ use std::fmt::{Debug, Display, Formatter};
 use std::fmt::Error as FmtError;
 use std::str::Utf8Error;
 
-use rusoto_core::ParseRegionError;
-use rusoto_kms::{DecryptError, EncryptError};
+use chrono::ParseError as ChronoParseError;
+use handlebars::{RenderError, TemplateError};
+use openssl::error::ErrorStack;
+use rusoto_core::{HttpDispatchError, RusotoError};
+use rusoto_core::credential::CredentialsError;
+use rusoto_core::region::ParseRegionError;
+use rusoto_kms::{
+    DecryptError,
+    DescribeKeyError,
+    EncryptError,
+    GenerateDataKeyError,
+    GetPublicKeyError,
+    SignError,
+};
 use rustc_serialize::base64::FromBase64Error;
 use serde_json::Error as SerdeJsonError;
+use xml::reader::Error as XmlReaderError;
+
+use message::Message;
 
 pub struct KawsError {
-    message: String,
+    // Boxed so that results carrying a KawsError stay small however large messages grow.
+    message: Box<Message>,
     stderr: Option<String>,
     stdout: Option<String>,
 }
 
 impl KawsError {
-    pub fn new(message: String) -> KawsError {
+    pub fn new(message: Message) -> KawsError {
         KawsError {
-            message: message,
+            message: Box::new(message),
             stderr: None,
             stdout: None,
         }
     }
 
-    pub fn with_std_streams(message: String, stdout: String, stderr: String) -> KawsError {
+    pub fn with_std_streams(message: Message, stdout: String, stderr: String) -> KawsError {
         KawsError {
-            message: message,
+            message: Box::new(message),
             stderr: Some(stderr),
             stdout: Some(stdout),
         }
     }
+
+    pub fn message(&self) -> &Message {
+        &self.message
+    }
+
+    pub fn stderr(&self) -> Option<&str> {
+        self.stderr.as_deref()
+    }
+
+    pub fn stdout(&self) -> Option<&str> {
+        self.stdout.as_deref()
+    }
 }
 
 impl Debug for KawsError {
@@ -40,7 +68,7 @@ impl Debug for KawsError {
 
 impl Display for KawsError {
     fn fmt(&self, f: &mut Formatter) -> Result<(), FmtError> {
-        if self.stdout.is_some() && self.stderr.is_some() {
+        if let (Some(stdout), Some(stderr)) = (self.stdout.as_ref(), self.stderr.as_ref()) {
             write!(f,
                 "{}
 
@@ -52,8 +80,8 @@ impl Display for KawsError {
                 stderr:
                 {}",
                 self.message,
-                self.stdout.as_ref().expect("accessing self.stdout"),
-                self.stderr.as_ref().expect("accessing self.stderr")
+                stdout,
+                stderr,
             )
         } else {
             write!(f, "{}", self.message)
@@ -61,52 +89,150 @@ impl Display for KawsError {
     }
 }
 
-impl Error for KawsError {
-    fn description(&self) -> &str {
-        &self.message
-    }
-}
+impl Error for KawsError {}
 
 impl From<::std::io::Error> for KawsError {
     fn from(error: ::std::io::Error) -> Self {
-        KawsError::new(format!("{}", error))
+        KawsError::new(Message::IoError {
+            error: format!("{}", error),
+        })
     }
 }
 
 impl From<Utf8Error> for KawsError {
     fn from(error: Utf8Error) -> Self {
-        KawsError::new(format!("{}", error))
+        KawsError::new(Message::Utf8Error {
+            error: format!("{}", error),
+        })
+    }
+}
+
+impl From<ChronoParseError> for KawsError {
+    fn from(error: ChronoParseError) -> Self {
+        KawsError::new(Message::TimeError {
+            error: format!("{}", error),
+        })
+    }
+}
+
+impl From<CredentialsError> for KawsError {
+    fn from(error: CredentialsError) -> Self {
+        KawsError::new(Message::AwsCredentialsError {
+            error: format!("{}", error),
+        })
+    }
+}
+
+impl From<HttpDispatchError> for KawsError {
+    fn from(error: HttpDispatchError) -> Self {
+        KawsError::new(Message::AwsHttpError {
+            error: format!("{}", error),
+        })
+    }
+}
+
+impl From<RusotoError<DecryptError>> for KawsError {
+    fn from(error: RusotoError<DecryptError>) -> Self {
+        KawsError::new(Message::KmsError {
+            error: format!("{}", error),
+        })
+    }
+}
+
+impl From<RusotoError<DescribeKeyError>> for KawsError {
+    fn from(error: RusotoError<DescribeKeyError>) -> Self {
+        KawsError::new(Message::KmsError {
+            error: format!("{}", error),
+        })
     }
 }
 
-impl From<DecryptError> for KawsError {
-    fn from(error: DecryptError) -> Self {
-        KawsError::new(format!("{}", error))
+impl From<RusotoError<EncryptError>> for KawsError {
+    fn from(error: RusotoError<EncryptError>) -> Self {
+        KawsError::new(Message::KmsError {
+            error: format!("{}", error),
+        })
     }
 }
 
-impl From<EncryptError> for KawsError {
-    fn from(error: EncryptError) -> Self {
-        KawsError::new(format!("{}", error))
+impl From<RusotoError<GenerateDataKeyError>> for KawsError {
+    fn from(error: RusotoError<GenerateDataKeyError>) -> Self {
+        KawsError::new(Message::KmsError {
+            error: format!("{}", error),
+        })
+    }
+}
+
+impl From<RusotoError<GetPublicKeyError>> for KawsError {
+    fn from(error: RusotoError<GetPublicKeyError>) -> Self {
+        KawsError::new(Message::KmsError {
+            error: format!("{}", error),
+        })
+    }
+}
+
+impl From<RusotoError<SignError>> for KawsError {
+    fn from(error: RusotoError<SignError>) -> Self {
+        KawsError::new(Message::KmsError {
+            error: format!("{}", error),
+        })
     }
 }
 
 impl From<FromBase64Error> for KawsError {
     fn from(error: FromBase64Error) -> Self {
-        KawsError::new(format!("{}", error))
+        KawsError::new(Message::Base64Error {
+            error: format!("{}", error),
+        })
+    }
+}
+
+impl From<RenderError> for KawsError {
+    fn from(error: RenderError) -> Self {
+        KawsError::new(Message::TemplateError {
+            error: format!("{}", error),
+        })
+    }
+}
+
+impl From<TemplateError> for KawsError {
+    fn from(error: TemplateError) -> Self {
+        KawsError::new(Message::TemplateError {
+            error: format!("{}", error),
+        })
+    }
+}
+
+impl From<ErrorStack> for KawsError {
+    fn from(error: ErrorStack) -> Self {
+        KawsError::new(Message::OpensslError {
+            error: format!("{}", error),
+        })
     }
 }
 
 impl From<ParseRegionError> for KawsError {
     fn from(error: ParseRegionError) -> Self {
-        KawsError::new(format!("{}", error))
+        KawsError::new(Message::RegionError {
+            error: format!("{}", error),
+        })
     }
 }
 
 impl From<SerdeJsonError> for KawsError {
     fn from(error: SerdeJsonError) -> Self {
-        KawsError::new(format!("{}", error))
+        KawsError::new(Message::JsonError {
+            error: format!("{}", error),
+        })
+    }
+}
+
+impl From<XmlReaderError> for KawsError {
+    fn from(error: XmlReaderError) -> Self {
+        KawsError::new(Message::XmlError {
+            error: format!("{}", error),
+        })
     }
 }
 
-pub type KawsResult = Result<Option<String>, KawsError>;
+pub type KawsResult = Result<Option<Message>, KawsError>;
@@ -0,0 +1,175 @@
+use std::collections::BTreeMap;
+
+use clap::ArgMatches;
+
+use component_flags::{
+    COMPONENTS,
+    FEATURE_GATES,
+    feature_gate_exists,
+    feature_gates_value,
+    parse_feature_gates,
+};
+use error::{KawsError, KawsResult};
+use message::Message;
+use metadata::ClusterMetadata;
+use render::print_rows;
+use tfvars::Tfvars;
+
+pub struct FeatureGates<'a> {
+    cluster: &'a str,
+    gate: Option<&'a str>,
+}
+
+impl<'a> FeatureGates<'a> {
+    pub fn new(matches: &'a ArgMatches) -> Self {
+        FeatureGates {
+            cluster: matches.value_of("cluster").expect("clap should have required cluster"),
+            gate: matches.value_of("gate"),
+        }
+    }
+
+    pub fn disable(&self) -> KawsResult {
+        self.set(false)
+    }
+
+    pub fn enable(&self) -> KawsResult {
+        self.set(true)
+    }
+
+    /// Prints each feature gate of the cluster's version of Kubernetes, and any other gate its
+    /// metadata sets, with whether the cluster's components enable or disable it. A gate that
+    /// only some components set, as edits by hand can leave it, is shown as "mixed".
+    pub fn list(&self) -> KawsResult {
+        let kubernetes_version = self.kubernetes_version()?;
+        let metadata = self.metadata()?;
+        let mut states: BTreeMap<&str, Vec<Option<bool>>> = BTreeMap::new();
+
+        for &(gate, _, _) in FEATURE_GATES.iter() {
+            if feature_gate_exists(gate, &kubernetes_version) {
+                states.insert(gate, vec![]);
+            }
+        }
+
+        let component_gates: Vec<BTreeMap<String, bool>> = COMPONENTS.iter()
+            .map(|component| component_gates(component.flags(&metadata)))
+            .collect();
+
+        for gates in &component_gates {
+            for gate in gates.keys() {
+                states.entry(gate).or_default();
+            }
+        }
+
+        let mut rows = vec![vec!["GATE".to_owned(), "STATE".to_owned()]];
+
+        for gate in states.keys() {
+            let settings: Vec<Option<bool>> = component_gates.iter()
+                .map(|gates| gates.get(*gate).cloned())
+                .collect();
+            let state = if settings.iter().any(|setting| *setting != settings[0]) {
+                "mixed"
+            } else {
+                match settings[0] {
+                    Some(true) => "enabled",
+                    Some(false) => "disabled",
+                    None => "default",
+                }
+            };
+
+            rows.push(vec![
+                if feature_gate_exists(gate, &kubernetes_version) {
+                    gate.to_string()
+                } else {
+                    format!("{} (not in Kubernetes {})", gate, kubernetes_version)
+                },
+                state.to_owned(),
+            ]);
+        }
+
+        print_rows(&rows)?;
+
+        Ok(None)
+    }
+
+    // Private
+
+    fn kubernetes_version(&self) -> Result<String, KawsError> {
+        let tfvars = Tfvars::from_file(&format!("clusters/{}/terraform.tfvars", self.cluster))?;
+
+        tfvars.get("kaws_version").map(str::to_owned).ok_or_else(|| {
+            KawsError::new(Message::TfvarsUnreadable {
+                cluster: self.cluster.to_owned(),
+                error: "kaws_version is not set".to_owned(),
+            })
+        })
+    }
+
+    fn metadata(&self) -> Result<ClusterMetadata, KawsError> {
+        ClusterMetadata::from_file(&self.metadata_path())
+    }
+
+    fn metadata_path(&self) -> String {
+        format!("clusters/{}/metadata.json", self.cluster)
+    }
+
+    // Sets the gate in the `--feature-gates` flag of every component, so that the API server,
+    // controller manager, and kubelets agree on it, and records that the masters and nodes need
+    // to be replaced for it to take effect.
+    fn set(&self, enabled: bool) -> KawsResult {
+        let gate = self.gate.expect("clap should have required gate");
+        let kubernetes_version = self.kubernetes_version()?;
+
+        if !feature_gate_exists(gate, &kubernetes_version) {
+            return Err(KawsError::new(Message::FeatureGateUnknown {
+                gate: gate.to_owned(),
+                version: kubernetes_version,
+            }));
+        }
+
+        let mut metadata = self.metadata()?;
+        let mut changed = false;
+
+        for component in COMPONENTS.iter() {
+            let flags = component.flags_mut(&mut metadata);
+            let mut gates = component_gates(flags);
+
+            if gates.insert(gate.to_owned(), enabled) != Some(enabled) {
+                flags.insert("feature-gates".to_owned(), feature_gates_value(&gates));
+                changed = true;
+            }
+        }
+
+        if !changed {
+            return Ok(Some(Message::FeatureGateUnchanged {
+                cluster: self.cluster.to_owned(),
+                enabled,
+                gate: gate.to_owned(),
+            }));
+        }
+
+        // Only the latest change to a gate matters to the servers being replaced.
+        let prefix = format!("feature gate {} ", gate);
+        let reason = format!("{}{}", prefix, if enabled { "enabled" } else { "disabled" });
+
+        metadata.rolling_update_reasons.retain(|existing| !existing.starts_with(&prefix));
+        metadata.rolling_update_reasons.push(reason);
+
+        log_wrap!("Writing metadata", {
+            metadata.write_to_file(&self.metadata_path())?;
+        });
+
+        Ok(Some(Message::FeatureGateSet {
+            cluster: self.cluster.to_owned(),
+            enabled,
+            gate: gate.to_owned(),
+        }))
+    }
+}
+
+// The gates a component's `--feature-gates` flag sets. A value that doesn't parse is treated as
+// setting none, and is replaced if a gate is set; `kaws cluster plan` rejects it otherwise.
+fn component_gates(flags: &BTreeMap<String, String>) -> BTreeMap<String, bool> {
+    flags.get("feature-gates")
+        .and_then(|value| parse_feature_gates(value))
+        .unwrap_or_default()
+}
@@ -0,0 +1,89 @@
+use std::fs::{DirEntry, Metadata, ReadDir, read_dir};
+use std::io::ErrorKind;
+
+use error::KawsError;
+
+/// A file or directory in a directory kaws lists.
+pub struct Entry {
+    /// The entry's name, without the directory.
+    pub name: String,
+    /// The entry's path: the directory as given, a slash, and the name.
+    pub path: String,
+    entry: DirEntry,
+}
+
+impl Entry {
+    /// Whether the entry is a directory, not following symlinks.
+    pub fn is_dir(&self) -> Result<bool, KawsError> {
+        Ok(self.entry.file_type()?.is_dir())
+    }
+
+    /// Whether the entry is an empty file, such as the placeholders `kaws cluster init` creates
+    /// for PKI that hasn't been generated yet.
+    pub fn is_empty(&self) -> Result<bool, KawsError> {
+        Ok(self.metadata()?.len() == 0)
+    }
+
+    pub fn metadata(&self) -> Result<Metadata, KawsError> {
+        Ok(self.entry.metadata()?)
+    }
+}
+
+/// The entries of `dir`, sorted by name.
+///
+/// Names are compared byte by byte, not by the locale's collation or in the order the file system
+/// returns them, so every platform lists the same files in the same order and output that includes
+/// them can be diffed. kaws only ever writes UTF-8 names, so an entry whose name isn't UTF-8 can't
+/// be one of its files: it's skipped with a warning rather than failing the command.
+pub fn entries(dir: &str) -> Result<Vec<Entry>, KawsError> {
+    sorted(dir, read_dir(dir)?)
+}
+
+/// Like `entries`, but with no entries if `dir` doesn't exist.
+pub fn entries_if_present(dir: &str) -> Result<Vec<Entry>, KawsError> {
+    match read_dir(dir) {
+        Ok(read) => sorted(dir, read),
+        Err(ref error) if error.kind() == ErrorKind::NotFound => Ok(vec![]),
+        Err(error) => Err(error.into()),
+    }
+}
+
+/// The names of the entries of `dir`, sorted as by `entries`.
+pub fn names(dir: &str) -> Result<Vec<String>, KawsError> {
+    Ok(entries(dir)?.into_iter().map(|entry| entry.name).collect())
+}
+
+/// The names of the files in `dir` that aren't empty, sorted as by `entries`.
+pub fn non_empty_names(dir: &str) -> Result<Vec<String>, KawsError> {
+    let mut names = vec![];
+
+    for entry in entries(dir)? {
+        if !entry.is_empty()? {
+            names.push(entry.name);
+        }
+    }
+
+    Ok(names)
+}
+
+// The entries `read` yields from `dir`, sorted as described for `entries`.
+fn sorted(dir: &str, read: ReadDir) -> Result<Vec<Entry>, KawsError> {
+    let mut entries = vec![];
+
+    for entry in read {
+        let entry = entry?;
+
+        match entry.file_name().into_string() {
+            Ok(name) => entries.push(Entry {
+                path: format!("{}/{}", dir.trim_end_matches('/'), name),
+                name,
+                entry,
+            }),
+            Err(name) => warn!("Skipping {}/{:?}, whose name isn't valid UTF-8", dir, name),
+        }
+    }
+
+    entries.sort_by(|a, b| a.name.cmp(&b.name));
+
+    Ok(entries)
+}
@@ -0,0 +1,204 @@
+use std::fs::File;
+use std::io::Write;
+
+use chrono::{DateTime, Utc};
+use clap::ArgMatches;
+use serde_json::{Value, from_reader, to_string_pretty};
+
+use cache::{self, directory_inputs};
+use error::{KawsError, KawsResult};
+use files::entries;
+use jobs::run_all;
+use paths::from_invocation;
+use pki_manifest::{ManifestEntry, PkiManifest};
+use render::Table;
+use tfvars::Tfvars;
+
+pub struct Fleet {
+    inventory_path: Option<String>,
+    json: bool,
+}
+
+/// A summary of one cluster, gathered entirely from files in the repository.
+#[derive(Deserialize, Serialize)]
+pub struct ClusterStatus {
+    pub name: String,
+    pub kubernetes_version: Option<String>,
+    pub masters: Option<u64>,
+    pub nodes: Option<u64>,
+    pub earliest_certificate_expiry: Option<DateTime<Utc>>,
+    pub last_applied: Option<DateTime<Utc>>,
+    pub errors: Vec<String>,
+    /// The cluster's current certificates, as listed in its PKI manifest.
+    #[serde(skip)]
+    pub certificates: Vec<ManifestEntry>,
+}
+
+impl Fleet {
+    pub fn new(matches: &ArgMatches) -> Self {
+        Fleet {
+            inventory_path: matches.value_of("inventory").map(from_invocation),
+            json: matches.is_present("json"),
+        }
+    }
+
+    pub fn status(&self) -> KawsResult {
+        let statuses = ClusterStatus::collect_all()?;
+
+        if let Some(ref path) = self.inventory_path {
+            let mut file = File::create(path)?;
+
+            writeln!(file, "{}", to_string_pretty(&statuses)?)?;
+        }
+
+        if self.json {
+            println!("{}", to_string_pretty(&statuses)?);
+        } else {
+            print_table(&statuses)?;
+        }
+
+        Ok(None)
+    }
+}
+
+impl ClusterStatus {
+    /// Collects the status of every cluster in the repository, in parallel, up to `--jobs` at
+    /// a time.
+    pub fn collect_all() -> Result<Vec<Self>, KawsError> {
+        Ok(run_all(cluster_names()?, ClusterStatus::collect))
+    }
+
+    /// Collects the status of the cluster `name`, from the cache if none of the files in its
+    /// directory have changed since it was last collected.
+    pub fn collect(name: String) -> Self {
+        let cache_name = format!("clusters/{}", name);
+        let inputs = directory_inputs(&format!("clusters/{}", name)).ok();
+
+        if let Some(ref inputs) = inputs {
+            if let Some((mut status, certificates)) = cache::read::<(Self, _)>(&cache_name, inputs) {
+                status.certificates = certificates;
+
+                return status;
+            }
+        }
+
+        let status = Self::collect_uncached(name);
+
+        // A cluster with errors is collected again each time, so they are reported afresh.
+        if let Some(inputs) = inputs {
+            if status.errors.is_empty() {
+                cache::write(&cache_name, inputs, (&status, &status.certificates));
+            }
+        }
+
+        status
+    }
+
+    // Private
+
+    fn collect_uncached(name: String) -> Self {
+        let mut status = ClusterStatus {
+            name,
+            kubernetes_version: None,
+            masters: None,
+            nodes: None,
+            earliest_certificate_expiry: None,
+            last_applied: None,
+            errors: vec![],
+            certificates: vec![],
+        };
+
+        match Tfvars::from_file(&format!("clusters/{}/terraform.tfvars", status.name)) {
+            Ok(tfvars) => status.kubernetes_version = tfvars.get("kaws_version").map(String::from),
+            Err(error) => status.errors.push(format!("terraform.tfvars: {}", error)),
+        }
+
+        let state_path = format!("clusters/{}/terraform.tfstate", status.name);
+
+        if let Ok(file) = File::open(&state_path) {
+            match from_reader::<_, Value>(file) {
+                Ok(state) => {
+                    status.masters = desired_capacity(&state, "aws_autoscaling_group.k8s_masters");
+                    status.nodes = desired_capacity(&state, "aws_autoscaling_group.k8s_nodes");
+                }
+                Err(error) => status.errors.push(format!("terraform.tfstate: {}", error)),
+            }
+
+            status.last_applied = file_modified(&state_path);
+        }
+
+        match PkiManifest::from_file(&status.name).and_then(|manifest| manifest.current()) {
+            Ok(certificates) => {
+                status.earliest_certificate_expiry = certificates.iter()
+                    .map(|entry| entry.not_after)
+                    .min();
+                status.certificates = certificates;
+            }
+            Err(error) => status.errors.push(format!("pki-manifest.json: {}", error)),
+        }
+
+        status
+    }
+}
+
+fn cluster_names() -> Result<Vec<String>, KawsError> {
+    let mut names = vec![];
+
+    for entry in entries("clusters")? {
+        if entry.is_dir()? {
+            names.push(entry.name);
+        }
+    }
+
+    Ok(names)
+}
+
+fn desired_capacity(state: &Value, resource: &str) -> Option<u64> {
+    state["modules"].as_array()?.iter().find_map(|module| {
+        module["resources"].get(resource)
+    })?["primary"]["attributes"]["desired_capacity"].as_str()?.parse().ok()
+}
+
+fn file_modified(path: &str) -> Option<DateTime<Utc>> {
+    File::open(path).and_then(|file| file.metadata()).and_then(|metadata| metadata.modified())
+        .ok()
+        .map(DateTime::from)
+}
+
+fn print_table(statuses: &[ClusterStatus]) -> Result<(), KawsError> {
+    let now = Utc::now();
+
+    let mut table = Table::new(&[
+        "CLUSTER",
+        "VERSION",
+        "MASTERS",
+        "NODES",
+        "EARLIEST CERT EXPIRY",
+        "LAST APPLY",
+    ]);
+
+    for status in statuses {
+        table.push(vec![
+            status.name.clone(),
+            status.kubernetes_version.clone().unwrap_or_else(|| "-".to_owned()),
+            status.masters.map(|count| count.to_string()).unwrap_or_else(|| "-".to_owned()),
+            status.nodes.map(|count| count.to_string()).unwrap_or_else(|| "-".to_owned()),
+            status.earliest_certificate_expiry.map(|expiry| {
+                format!("{} ({} days)", expiry.format("%Y-%m-%d"), (expiry - now).num_days())
+            }).unwrap_or_else(|| "-".to_owned()),
+            status.last_applied.map(|time| {
+                time.format("%Y-%m-%d %H:%M UTC").to_string()
+            }).unwrap_or_else(|| "never".to_owned()),
+        ]);
+    }
+
+    table.print()?;
+
+    for status in statuses {
+        for error in &status.errors {
+            eprintln!("warning: {}: {}", status.name, error);
+        }
+    }
+
+    Ok(())
+}
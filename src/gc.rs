@@ -0,0 +1,194 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::{self, Write};
+use std::path::Path;
+
+use chrono::Utc;
+use clap::ArgMatches;
+
+use aws::{Access, cluster_credentials_provider};
+use cluster::ETCD_MEMBERS;
+use encryption::remove_encrypted_file;
+use error::{KawsError, KawsResult};
+use files;
+use message::Message;
+use metadata::ClusterMetadata;
+use paths::from_invocation;
+use pki::{Certificate, CertificateRevocationList};
+use provider::cloud_provider;
+use render::print_rows;
+use tfvars::Tfvars;
+
+pub struct GarbageCollector<'a> {
+    aws_credentials_path: Option<String>,
+    aws_credentials_profile: Option<&'a str>,
+    cluster: &'a str,
+}
+
+impl<'a> GarbageCollector<'a> {
+    pub fn new(matches: &'a ArgMatches) -> Self {
+        GarbageCollector {
+            aws_credentials_path: matches.value_of("aws-credentials-path").map(from_invocation),
+            aws_credentials_profile: matches.value_of("aws-credentials-profile"),
+            cluster: matches.value_of("cluster").expect("clap should have required cluster"),
+        }
+    }
+
+    /// Lists the files in the cluster's directory that nothing uses any more, and removes them if
+    /// the user confirms.
+    pub fn collect(&self) -> KawsResult {
+        let garbage = self.find()?;
+
+        if garbage.is_empty() {
+            return Ok(Some(Message::GarbageNotFound {
+                cluster: self.cluster.to_owned(),
+            }));
+        }
+
+        let mut rows = vec![vec!["FILE".to_owned(), "REASON".to_owned()]];
+
+        for (path, reason) in &garbage {
+            rows.push(vec![path.clone(), reason.clone()]);
+        }
+
+        print_rows(&rows)?;
+        print!("Remove these {} files? [y/N] ", garbage.len());
+        io::stdout().flush()?;
+
+        let mut answer = String::new();
+
+        io::stdin().read_line(&mut answer)?;
+
+        if !["y", "yes"].contains(&answer.trim().to_lowercase().as_str()) {
+            return Ok(Some(Message::GarbageKept {
+                cluster: self.cluster.to_owned(),
+            }));
+        }
+
+        // Garbage that was encrypted takes its regional copies with it.
+        for path in garbage.keys() {
+            log_wrap!(format!("Removing {}", path), {
+                remove_encrypted_file(path)?;
+            });
+        }
+
+        Ok(Some(Message::GarbageCollected {
+            cluster: self.cluster.to_owned(),
+            files: garbage.len(),
+        }))
+    }
+
+    // Private
+
+    // The paths of the files that can be removed, with the reason for each.
+    fn find(&self) -> Result<BTreeMap<String, String>, KawsError> {
+        let dir = format!("clusters/{}", self.cluster);
+        let names: BTreeSet<String> = files::names(&dir)?.into_iter().collect();
+        let mut garbage = BTreeMap::new();
+
+        let mut add = |name: &str, reason: &str| {
+            if names.contains(name) {
+                garbage.insert(format!("{}/{}", dir, name), reason.to_owned());
+            }
+        };
+
+        for name in &names {
+            if name.ends_with(".srl") {
+                add(name, "serial number file left by an older version of kaws");
+            } else if name == "ca-key.pem" || name.ends_with("-ca-key.pem") {
+                add(name, "unencrypted CA key");
+            } else if let Some(stem) = name.strip_suffix("-key.pem") {
+                if names.contains(&format!("{}-key-encrypted.base64", stem)) {
+                    add(name, "unencrypted copy of an encrypted key");
+                }
+            }
+        }
+
+        // Terraform only reads the etcd certificates of the kind the cluster uses.
+        let tfvars = Tfvars::from_file(&format!("{}/terraform.tfvars", dir))?;
+        let superseded = if tfvars.get("kaws_etcd_member_certificates") == Some("true") {
+            vec!["etcd-server".to_owned(), "etcd-peer".to_owned()]
+        } else {
+            ETCD_MEMBERS.iter().flat_map(|&(member, _)| {
+                vec![format!("etcd-server-{}", member), format!("etcd-peer-{}", member)]
+            }).collect()
+        };
+
+        for stem in &superseded {
+            add(&format!("{}.pem", stem), "etcd certificate of a kind the cluster doesn't use");
+            add(
+                &format!("{}-key-encrypted.base64", stem),
+                "etcd key of a kind the cluster doesn't use",
+            );
+        }
+
+        let revoked = self.revoked_serial_numbers(&dir, &tfvars)?;
+
+        for admin in self.admins(&names) {
+            let cert = match Certificate::from_file_if_present(&format!("{}/{}.pem", dir, admin))? {
+                Some(cert) => cert,
+                None => continue,
+            };
+            let info = cert.info()?;
+
+            let reason = if revoked.contains(&info.serial_number) {
+                format!("credentials of administrator {}, whose certificate was revoked", admin)
+            } else if info.not_after < Utc::now() {
+                format!("credentials of administrator {}, whose certificate expired", admin)
+            } else {
+                continue;
+            };
+
+            for suffix in &[".pem", ".p12", "-csr.pem", "-key.pem"] {
+                add(&format!("{}{}", admin, suffix), &reason);
+            }
+        }
+
+        Ok(garbage)
+    }
+
+    // The names of the administrators with credentials in the cluster's directory, found by the
+    // requests `kaws admin create` writes. CAs waiting for an external root CA to sign them have
+    // requests too, and are left out.
+    fn admins(&self, names: &BTreeSet<String>) -> Vec<String> {
+        names.iter()
+            .filter_map(|name| name.strip_suffix("-csr.pem"))
+            .filter(|admin| !admin.ends_with("-ca"))
+            .map(String::from)
+            .collect()
+    }
+
+    // Administrators' certificates are issued by the Kubernetes CA, so only its revocation list is
+    // read, and only if it has one. A list an older version of kaws kept encrypted is decrypted.
+    fn revoked_serial_numbers(&self, dir: &str, tfvars: &Tfvars)
+    -> Result<Vec<String>, KawsError> {
+        if let Some(crl) = CertificateRevocationList::from_file_if_present(
+            &format!("{}/k8s-ca.crl", dir),
+        )? {
+            return crl.serial_numbers();
+        }
+
+        let crl_path = format!("{}/k8s-crl-encrypted.base64", dir);
+
+        if !Path::new(&crl_path).exists() {
+            return Ok(vec![]);
+        }
+
+        let metadata = ClusterMetadata::from_file(&format!("{}/metadata.json", dir))?;
+        let provider = cloud_provider(metadata.cloud_provider, cluster_credentials_provider(
+            self.cluster,
+            Access::ReadOnly,
+            self.aws_credentials_path.as_deref(),
+            self.aws_credentials_profile,
+        )?);
+        let region = tfvars.get("kaws_region").ok_or_else(|| {
+            KawsError::new(Message::TfvarsUnreadable {
+                cluster: self.cluster.to_owned(),
+                error: "kaws_region is not set".to_owned(),
+            })
+        })?;
+        let mut encryptor = provider.encryptor(region, None)?
+            .with_contexts_required(metadata.encryption_contexts_required);
+
+        CertificateRevocationList::from_encrypted_file(&mut encryptor, &crl_path)?.serial_numbers()
+    }
+}
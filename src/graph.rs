@@ -0,0 +1,339 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs::File;
+use std::io::{Write, stdout};
+use std::process::{Command, Stdio};
+
+use clap::ArgMatches;
+
+use dependencies::ensure_graphviz;
+use error::{KawsError, KawsResult};
+use files::non_empty_names;
+use message::Message;
+use module::embedded_configuration;
+use paths::from_invocation;
+use pki_verify::issuer;
+use terraform::Terraform;
+
+/// The suffixes of the files in a cluster's directory that its graph shows: certificates, public
+/// keys, revocation lists, and everything encrypted with KMS.
+const ARTIFACT_SUFFIXES: [&str; 4] = [".crl", ".pem", ".pub", "-encrypted.base64"];
+
+/// How the built-in module refers to the files in a cluster's directory.
+const CLUSTER_DIR_REFERENCE: &str = "clusters/${var.cluster}/";
+
+/// The KMS key kaws encrypts a cluster's keys and secrets with, by its address in the module.
+const KMS_KEY_ADDRESS: &str = "aws_kms_key.pki";
+
+pub struct ClusterGraph<'a> {
+    cluster: &'a str,
+    format: &'a str,
+    output: Option<String>,
+    terraform: Terraform<'a>,
+}
+
+// A file in the cluster's directory that a resource of the built-in module reads.
+struct FileReference {
+    // The address of the resource, e.g. "data.template_file.master_cloud_config".
+    address: String,
+    // Whether the resource only reads the file with some settings, e.g. with TLS bootstrapping.
+    conditional: bool,
+    // The file's name, which may have an interpolation, e.g. "etcd-peer${local.suffix}.pem".
+    name: String,
+}
+
+impl<'a> ClusterGraph<'a> {
+    pub fn new(matches: &'a ArgMatches) -> Self {
+        ClusterGraph {
+            cluster: matches.value_of("cluster").expect("clap should have required cluster"),
+            format: matches.value_of("format").unwrap_or("dot"),
+            output: matches.value_of("output").map(from_invocation),
+            terraform: Terraform::new(matches),
+        }
+    }
+
+    /// Writes Terraform's graph of the cluster's resources, with the certificates, keys, and
+    /// secrets in the cluster's directory added, to stdout or a file. The graph is in the DOT
+    /// language, or rendered as SVG with Graphviz.
+    pub fn render(&mut self) -> KawsResult {
+        if self.format == "svg" {
+            ensure_graphviz()?;
+        }
+
+        let graph = self.terraform.graph()?;
+        let artifacts = self.artifacts(&resources(&graph))?;
+
+        // The artifacts go inside the digraph, after everything Terraform put there.
+        let dot = match graph.rfind('}') {
+            Some(end) => format!("{}{}{}", &graph[..end], artifacts, &graph[end..]),
+            None => format!("digraph {{\n{}}}\n", artifacts),
+        };
+        let contents = if self.format == "svg" {
+            svg(&dot)?
+        } else {
+            dot.into_bytes()
+        };
+
+        match self.output {
+            Some(ref path) => {
+                File::create(path)?.write_all(&contents)?;
+
+                Ok(Some(Message::GraphWritten {
+                    cluster: self.cluster.to_owned(),
+                    path: path.to_owned(),
+                }))
+            }
+            None => {
+                let stdout = stdout();
+                let mut handle = stdout.lock();
+
+                handle.write_all(&contents)?;
+                handle.flush()?;
+
+                // Nothing else is printed on success, so the output can be piped to Graphviz.
+                Ok(None)
+            }
+        }
+    }
+
+    // Private
+
+    // A subgraph of the files kaws manages in the cluster's directory, and edges from the CA that
+    // issued each certificate, from each key to its certificate, from the KMS key to each file it
+    // encrypts, and from each file to the resources that read it.
+    fn artifacts(&self, resources: &BTreeMap<String, String>) -> Result<String, KawsError> {
+        let names: BTreeSet<String> = non_empty_names(&format!("clusters/{}", self.cluster))?
+            .into_iter()
+            .filter(|name| ARTIFACT_SUFFIXES.iter().any(|suffix| name.ends_with(suffix)))
+            .collect();
+        let mut dot = format!(
+            "\tsubgraph \"cluster_kaws\" {{\n\t\tlabel = {}\n",
+            quote(&format!("clusters/{}", self.cluster)),
+        );
+        let mut edges = vec![];
+
+        for name in &names {
+            let shape = if name.ends_with("-encrypted.base64") { "octagon" } else { "note" };
+
+            dot.push_str(&format!(
+                "\t\t{} [label = {}, shape = \"{}\"]\n",
+                node(name),
+                quote(name),
+                shape,
+            ));
+
+            if let Some(stem) = name.strip_suffix(".pem") {
+                let ca_cert = issuer(stem, &names).map(|ca| format!("{}-ca.pem", ca));
+
+                if let Some(ca_cert) = ca_cert.filter(|ca_cert| names.contains(ca_cert)) {
+                    edges.push((node(&ca_cert), node(name), "signs", false));
+                }
+            }
+
+            if let Some(stem) = name.strip_suffix("-key-encrypted.base64") {
+                let cert = format!("{}.pem", stem);
+
+                if names.contains(&cert) {
+                    edges.push((node(name), node(&cert), "key of", false));
+                }
+            }
+
+            if name.ends_with("-encrypted.base64") {
+                if let Some(kms_key) = resources.get(KMS_KEY_ADDRESS) {
+                    edges.push((kms_key.clone(), node(name), "encrypts", false));
+                }
+            }
+        }
+
+        dot.push_str("\t}\n");
+
+        for reference in file_references() {
+            let resource = match resources.get(&reference.address) {
+                Some(resource) => resource,
+                None => continue,
+            };
+
+            for name in &names {
+                if let Some(conditional) = reference.reads(name) {
+                    edges.push((node(name), resource.clone(), "read by", conditional));
+                }
+            }
+        }
+
+        // Outside the subgraph, so Terraform's nodes aren't drawn as part of it.
+        for (from, to, label, conditional) in edges {
+            let style = if conditional { ", style = \"dashed\"" } else { "" };
+
+            dot.push_str(&format!("\t{} -> {} [label = \"{}\"{}]\n", from, to, label, style));
+        }
+
+        Ok(dot)
+    }
+}
+
+impl FileReference {
+    // Whether the resource reads the file `name`, and if so, whether it only does with some
+    // settings. An interpolation in the reference matches nothing, or a suffix such as "-01" that
+    // the resource's own name has too, as "etcd_01_cloud_config" does.
+    fn reads(&self, name: &str) -> Option<bool> {
+        let start = match self.name.find("${") {
+            Some(start) => start,
+            None => return if self.name == name { Some(self.conditional) } else { None },
+        };
+        let end = start + self.name[start..].find('}')? + 1;
+        let middle = name.strip_prefix(&self.name[..start])?.strip_suffix(&self.name[end..])?;
+
+        if middle.is_empty() || self.address.contains(&middle.replace('-', "_")) {
+            Some(true)
+        } else {
+            None
+        }
+    }
+}
+
+// The files in a cluster's directory that the built-in module reads, with the resources that
+// read them. Blocks are recognized by their keyword at the beginning of a line, as they're
+// written throughout the module.
+fn file_references() -> Vec<FileReference> {
+    let mut references = vec![];
+
+    for (_, contents) in embedded_configuration() {
+        let mut address = None;
+
+        for line in contents.lines() {
+            if line.starts_with(char::is_alphabetic) {
+                address = block_address(line);
+            }
+
+            let address = match address {
+                Some(ref address) => address,
+                None => continue,
+            };
+            let mut rest = line;
+
+            while let Some(start) = rest.find(CLUSTER_DIR_REFERENCE) {
+                rest = &rest[start + CLUSTER_DIR_REFERENCE.len()..];
+
+                let end = rest.find('"').unwrap_or(rest.len());
+
+                references.push(FileReference {
+                    address: address.clone(),
+                    conditional: is_conditional(&line[..line.len() - rest.len()]),
+                    name: rest[..end].to_owned(),
+                });
+
+                rest = &rest[end..];
+            }
+        }
+    }
+
+    references
+}
+
+// Whether the reference at the end of `before`, the part of its line that comes before it, is in
+// a branch of a conditional expression, so it's only read with some settings. Each parenthesis or
+// interpolation it's in is checked for a condition before it. A file chosen between its own
+// contents and a secret store placeholder still reaches the servers either way, so the secret
+// store's condition doesn't count.
+fn is_conditional(before: &str) -> bool {
+    // The conditions found so far in each enclosing parenthesis or interpolation, and where the
+    // part of it being read started.
+    let mut frames: Vec<(Vec<&str>, usize)> = vec![(vec![], 0)];
+
+    for (index, character) in before.char_indices() {
+        match character {
+            '(' | '{' => frames.push((vec![], index + 1)),
+            ')' | '}' if frames.len() > 1 => {
+                frames.pop();
+            }
+            '?' | ':' => {
+                let frame = frames.last_mut().expect("there should always be a frame");
+
+                if character == '?' {
+                    frame.0.push(before[frame.1..index].trim());
+                }
+
+                frame.1 = index + 1;
+            }
+            _ => {}
+        }
+    }
+
+    frames.iter()
+        .flat_map(|frame| &frame.0)
+        .any(|condition| !condition.starts_with("var.secret_store "))
+}
+
+// The address of the resource or data source whose block starts on `line`, e.g.
+// `data "template_file" "user_data" {`.
+fn block_address(line: &str) -> Option<String> {
+    let words: Vec<&str> = line.split_whitespace().map(|word| word.trim_matches('"')).collect();
+
+    match words.as_slice() {
+        ["resource", kind, name, ..] => Some(format!("{}.{}", kind, name)),
+        ["data", kind, name, ..] => Some(format!("data.{}.{}", kind, name)),
+        _ => None,
+    }
+}
+
+// The node of each resource in Terraform's graph, by the resource's address without the modules
+// it's in, e.g. "aws_kms_key.pki" for "[root] module.kaws.aws_kms_key.pki".
+fn resources(graph: &str) -> BTreeMap<String, String> {
+    let mut resources = BTreeMap::new();
+
+    for line in graph.lines().map(str::trim) {
+        if !line.starts_with('"') || line.contains("->") {
+            continue;
+        }
+
+        let node = match line[1..].find('"') {
+            Some(end) => &line[..end + 2],
+            None => continue,
+        };
+        let label = match line.split("label = \"").nth(1).and_then(|rest| rest.split('"').next()) {
+            Some(label) => label,
+            None => continue,
+        };
+        let mut address = label;
+
+        while let Some(rest) = address.strip_prefix("module.") {
+            address = match rest.find('.') {
+                Some(end) => &rest[end + 1..],
+                None => break,
+            };
+        }
+
+        resources.entry(address.to_owned()).or_insert_with(|| node.to_owned());
+    }
+
+    resources
+}
+
+// The node of a file in the cluster's directory.
+fn node(name: &str) -> String {
+    quote(&format!("[kaws] {}", name))
+}
+
+fn quote(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+// Renders the graph with Graphviz.
+fn svg(dot: &str) -> Result<Vec<u8>, KawsError> {
+    let mut child = Command::new("dot")
+        .arg("-Tsvg")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()?;
+
+    child.stdin.take().expect("child should have a piped stdin").write_all(dot.as_bytes())?;
+
+    let output = child.wait_with_output()?;
+
+    if output.status.success() {
+        Ok(output.stdout)
+    } else {
+        Err(KawsError::new(Message::ChildProcessFailed {
+            command: "dot -Tsvg".to_owned(),
+        }))
+    }
+}
@@ -0,0 +1,198 @@
+use std::fs::File;
+use std::io::{Read, Write};
+
+use clap::ArgMatches;
+use rustc_serialize::base64::{STANDARD, ToBase64};
+
+use error::{KawsError, KawsResult};
+use message::Message;
+use metadata::{ClusterMetadata, IamUser};
+use render::print_rows;
+use template::Templates;
+use tfvars::Tfvars;
+
+pub struct Groups<'a> {
+    cluster: &'a str,
+    group: Option<&'a str>,
+    members: Vec<&'a str>,
+    role: Option<&'a str>,
+}
+
+impl<'a> Groups<'a> {
+    pub fn new(matches: &'a ArgMatches) -> Self {
+        Groups {
+            cluster: matches.value_of("cluster").expect("clap should have required cluster"),
+            group: matches.value_of("group"),
+            members: matches.values_of("member").map(|values| values.collect()).unwrap_or_default(),
+            role: matches.value_of("role"),
+        }
+    }
+
+    pub fn add(&self) -> KawsResult {
+        let group = self.group.expect("clap should have required group");
+        let mut metadata = self.metadata()?;
+
+        match self.role {
+            Some(role) => {
+                metadata.group_roles.insert(group.to_owned(), role.to_owned());
+            }
+            None => if !metadata.group_roles.contains_key(group) {
+                return Err(KawsError::new(Message::GroupNotBound {
+                    group: group.to_owned(),
+                }));
+            },
+        }
+
+        for member in &self.members {
+            let iam_user = metadata.iam_users.entry(member.to_string()).or_insert_with(IamUser::default);
+
+            if !iam_user.groups.iter().any(|existing| existing == group) {
+                iam_user.groups.push(group.to_owned());
+            }
+        }
+
+        save(self.cluster, &metadata)?;
+
+        Ok(Some(Message::GroupUpdated {
+            cluster: self.cluster.to_owned(),
+            group: group.to_owned(),
+        }))
+    }
+
+    pub fn list(&self) -> KawsResult {
+        let metadata = self.metadata()?;
+
+        let mut rows = vec![vec!["GROUP".to_owned(), "ROLE".to_owned(), "MEMBERS".to_owned()]];
+
+        for (group, role) in &metadata.group_roles {
+            let members: Vec<&str> = metadata.iam_users.iter()
+                .filter(|&(_, iam_user)| iam_user.groups.contains(group))
+                .map(|(name, _)| name.as_str())
+                .collect();
+
+            rows.push(vec![
+                group.clone(),
+                role.clone(),
+                if members.is_empty() { "-".to_owned() } else { members.join(", ") },
+            ]);
+        }
+
+        print_rows(&rows)?;
+
+        Ok(None)
+    }
+
+    pub fn remove(&self) -> KawsResult {
+        let group = self.group.expect("clap should have required group");
+        let mut metadata = self.metadata()?;
+
+        if !metadata.group_roles.contains_key(group) {
+            return Err(KawsError::new(Message::GroupNotBound {
+                group: group.to_owned(),
+            }));
+        }
+
+        // Without --member the group goes away entirely, so nobody can be left holding it.
+        let removing_group = self.members.is_empty();
+
+        for (name, iam_user) in &mut metadata.iam_users {
+            if removing_group || self.members.contains(&name.as_str()) {
+                iam_user.groups.retain(|existing| existing != group);
+            }
+        }
+
+        if removing_group {
+            metadata.group_roles.remove(group);
+        }
+
+        save(self.cluster, &metadata)?;
+
+        if removing_group {
+            Ok(Some(Message::GroupRemoved {
+                cluster: self.cluster.to_owned(),
+                group: group.to_owned(),
+            }))
+        } else {
+            Ok(Some(Message::GroupUpdated {
+                cluster: self.cluster.to_owned(),
+                group: group.to_owned(),
+            }))
+        }
+    }
+
+    // Private
+
+    fn metadata(&self) -> Result<ClusterMetadata, KawsError> {
+        ClusterMetadata::from_file(&self.metadata_path())
+    }
+
+    fn metadata_path(&self) -> String {
+        format!("clusters/{}/metadata.json", self.cluster)
+    }
+}
+
+/// Writes a cluster's metadata along with everything generated from it: the RBAC manifest and, for
+/// clusters using aws-iam-authenticator, the authenticator's configuration in terraform.tfvars.
+pub fn save(cluster: &str, metadata: &ClusterMetadata) -> KawsResult {
+    let templates = Templates::for_repository()?;
+
+    // Render everything before writing anything so a broken override leaves no partial output.
+    let rbac_manifest = metadata.rbac_manifest(&templates)?;
+    let tfvars = if metadata.iam_authenticator {
+        Some(tfvars_with_iam_authenticator_config(cluster, metadata, &templates)?)
+    } else {
+        None
+    };
+
+    log_wrap!("Writing metadata and RBAC files", {
+        metadata.write_to_file(&format!("clusters/{}/metadata.json", cluster))?;
+
+        let mut file = File::create(format!("clusters/{}/rbac.yml", cluster))?;
+
+        write!(file, "{}", rbac_manifest)?;
+    });
+
+    if let Some(tfvars) = tfvars {
+        log_wrap!("Updating aws-iam-authenticator configuration", {
+            let mut file = File::create(tfvars_path(cluster))?;
+
+            write!(file, "{}", tfvars)?;
+        });
+    }
+
+    Ok(None)
+}
+
+// The cluster's terraform.tfvars with kaws_iam_authenticator_config replaced, leaving every other
+// line as it was.
+fn tfvars_with_iam_authenticator_config(
+    cluster: &str,
+    metadata: &ClusterMetadata,
+    templates: &Templates,
+) -> Result<String, KawsError> {
+    let mut contents = String::new();
+
+    File::open(tfvars_path(cluster))?.read_to_string(&mut contents)?;
+
+    let aws_account_id = Tfvars::parse(&contents).get("kaws_account_id")
+        .map(str::to_owned)
+        .ok_or_else(|| KawsError::new(Message::TfvarsUnreadable {
+            cluster: cluster.to_owned(),
+            error: "kaws_account_id is not set".to_owned(),
+        }))?;
+    let config = metadata.iam_authenticator_config(templates, cluster, &aws_account_id)?
+        .as_bytes()
+        .to_base64(STANDARD);
+
+    Ok(contents.lines().map(|line| {
+        if line.split('=').next().map(str::trim) == Some("kaws_iam_authenticator_config") {
+            format!("kaws_iam_authenticator_config = \"{}\"\n", config)
+        } else {
+            format!("{}\n", line)
+        }
+    }).collect())
+}
+
+fn tfvars_path(cluster: &str) -> String {
+    format!("clusters/{}/terraform.tfvars", cluster)
+}
@@ -0,0 +1,346 @@
+use std::fs::File;
+use std::io::{Read, Write};
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+
+use chrono::Utc;
+use clap::ArgMatches;
+use rusoto_core::Region;
+use rusoto_core::credential::ChainProvider;
+use rustc_serialize::base64::{STANDARD, ToBase64};
+
+use aws::{Access, cluster_credentials_provider, ec2_query, xml_element_text};
+use error::{KawsError, KawsResult};
+use message::Message;
+use metadata::{BakedImage, ClusterMetadata};
+use paths::from_invocation;
+use tfvars::{Tfvars, with_values};
+
+/// How long each step of `kaws image build` may take: fetching the images, and then creating the
+/// image from the stopped instance.
+const BUILD_TIMEOUT: Duration = Duration::from_secs(30 * 60);
+
+/// How often the build instance and image are checked on.
+const POLL_INTERVAL: Duration = Duration::from_secs(15);
+
+/// Checks that instances of `instance_size` booted from `ami` will have working networking.
+///
+/// Some instance sizes, including everything built on the Nitro system, only have an ENA network
+/// interface, so an AMI without ENA support boots on them with no network at all. The other
+/// features such instance sizes depend on can't be checked: AMIs don't record whether they have
+/// NVMe drivers, and SR-IOV without ENA only affects network performance.
+pub fn ensure_ami_supports_instance_size(
+    provider: &ChainProvider,
+    region: &str,
+    ami: &str,
+    instance_size: &str,
+) -> Result<(), KawsError> {
+    let region: Region = region.parse()?;
+
+    let instance_type = ec2_query(
+        provider,
+        &region,
+        "DescribeInstanceTypes",
+        &[("InstanceType.1", instance_size)],
+    )?;
+
+    // One of "required", "supported", or "unsupported".
+    if xml_element_text(&instance_type, "enaSupport")?.as_deref() != Some("required") {
+        return Ok(());
+    }
+
+    let image = ec2_query(provider, &region, "DescribeImages", &[("ImageId.1", ami)])?;
+
+    // Only present, as "true", on AMIs with ENA support.
+    if xml_element_text(&image, "enaSupport")?.as_deref() == Some("true") {
+        Ok(())
+    } else {
+        Err(KawsError::new(Message::AmiEnaUnsupported {
+            ami: ami.to_owned(),
+            instance_size: instance_size.to_owned(),
+        }))
+    }
+}
+
+/// Builds an AMI for a cluster's masters and nodes with the Kubernetes images they run already in
+/// place, so they don't fetch them on every boot.
+pub struct Image<'a> {
+    aws_credentials_path: Option<String>,
+    aws_credentials_profile: Option<&'a str>,
+    cluster: &'a str,
+    instance_size: Option<&'a str>,
+    subnet: Option<&'a str>,
+}
+
+impl<'a> Image<'a> {
+    pub fn new(matches: &'a ArgMatches) -> Self {
+        Image {
+            aws_credentials_path: matches.value_of("aws-credentials-path").map(from_invocation),
+            aws_credentials_profile: matches.value_of("aws-credentials-profile"),
+            cluster: matches.value_of("cluster").expect("clap should have required cluster"),
+            instance_size: matches.value_of("instance-size"),
+            subnet: matches.value_of("subnet"),
+        }
+    }
+
+    pub fn build(&self) -> KawsResult {
+        let provider = cluster_credentials_provider(
+            self.cluster,
+            Access::ReadWrite,
+            self.aws_credentials_path.as_deref(),
+            self.aws_credentials_profile,
+        )?;
+        let tfvars = Tfvars::from_file(&self.tfvars_path())?;
+        let region: Region = self.tfvar(&tfvars, "kaws_region")?.parse()?;
+        let base_ami = self.tfvar(&tfvars, "kaws_coreos_ami")?;
+        let kubernetes_version = self.tfvar(&tfvars, "kaws_version")?;
+        let instance_size = match self.instance_size {
+            Some(instance_size) => instance_size,
+            None => self.tfvar(&tfvars, "kaws_instance_size")?,
+        };
+
+        let user_data = self.user_data(kubernetes_version).as_bytes().to_base64(STANDARD);
+        let instance_name = format!("kaws-image-build-{}", self.cluster);
+        let mut parameters = vec![
+            ("ImageId", base_ami),
+            ("InstanceType", instance_size),
+            ("MinCount", "1"),
+            ("MaxCount", "1"),
+            ("InstanceInitiatedShutdownBehavior", "stop"),
+            ("UserData", &user_data),
+            ("TagSpecification.1.ResourceType", "instance"),
+            ("TagSpecification.1.Tag.1.Key", "Name"),
+            ("TagSpecification.1.Tag.1.Value", &instance_name),
+        ];
+
+        if let Some(subnet) = self.subnet {
+            parameters.push(("SubnetId", subnet));
+        }
+
+        let instance_id;
+
+        log_wrap!("Launching the build instance", {
+            let instance = ec2_query(&provider, &region, "RunInstances", &parameters)?;
+
+            instance_id = xml_element_text(&instance, "instanceId")?.ok_or_else(|| {
+                self.failure("EC2 did not return the build instance's ID")
+            })?;
+        });
+
+        // The instance costs money until it is terminated, so terminate it whether or not the
+        // image was made. If it wasn't, why matters more than a failure to terminate, which is
+        // only logged.
+        let result = self.create_image(&provider, &region, &instance_id, kubernetes_version);
+
+        log_wrap!("Terminating the build instance", {
+            let terminated = ec2_query(
+                &provider,
+                &region,
+                "TerminateInstances",
+                &[("InstanceId.1", &instance_id)],
+            );
+
+            match terminated {
+                Ok(_) => {}
+                Err(error) if result.is_err() => warn!(
+                    "Failed to terminate the build instance {}: {}",
+                    instance_id,
+                    error,
+                ),
+                Err(error) => return Err(error),
+            }
+        });
+
+        let ami = result?;
+
+        self.record(&ami, base_ami, kubernetes_version)?;
+
+        Ok(Some(Message::ImageBuilt {
+            ami,
+            cluster: self.cluster.to_owned(),
+            kubernetes_version: kubernetes_version.to_owned(),
+        }))
+    }
+
+    // Private
+
+    // Waits for the build instance to power itself off once it has fetched the images, then makes
+    // an AMI from it.
+    fn create_image(
+        &self,
+        provider: &ChainProvider,
+        region: &Region,
+        instance_id: &str,
+        kubernetes_version: &str,
+    ) -> Result<String, KawsError> {
+        log_wrap!("Waiting for the build instance to fetch the images and stop", {
+            self.wait_for(BUILD_TIMEOUT, "the build instance to stop", || {
+                let instance = ec2_query(
+                    provider,
+                    region,
+                    "DescribeInstances",
+                    &[("InstanceId.1", instance_id)],
+                )?;
+
+                // The instance's state is the first element named "name" in the response.
+                match xml_element_text(&instance, "name")?.as_deref() {
+                    Some("stopped") => Ok(true),
+                    Some("shutting-down") | Some("terminated") => {
+                        Err(self.failure("the build instance was terminated"))
+                    }
+                    _ => Ok(false),
+                }
+            })?;
+        });
+
+        let name = format!(
+            "kaws-{}-kubernetes-{}-{}",
+            self.cluster,
+            kubernetes_version,
+            Utc::now().format("%Y%m%d%H%M%S"),
+        );
+        let description = format!(
+            "CoreOS with the images for Kubernetes {} for kaws cluster {}",
+            kubernetes_version,
+            self.cluster,
+        );
+        let ami;
+
+        log_wrap!("Creating the image", {
+            let image = ec2_query(provider, region, "CreateImage", &[
+                ("InstanceId", instance_id),
+                ("Name", &name),
+                ("Description", &description),
+            ])?;
+
+            ami = xml_element_text(&image, "imageId")?.ok_or_else(|| {
+                self.failure("EC2 did not return the image's ID")
+            })?;
+
+            ec2_query(provider, region, "CreateTags", &[
+                ("ResourceId.1", &ami),
+                ("Tag.1.Key", "Name"),
+                ("Tag.1.Value", &name),
+                ("Tag.2.Key", "KubernetesCluster"),
+                ("Tag.2.Value", self.cluster),
+                ("Tag.3.Key", "KubernetesVersion"),
+                ("Tag.3.Value", kubernetes_version),
+            ])?;
+        });
+
+        log_wrap!("Waiting for the image to become available", {
+            self.wait_for(BUILD_TIMEOUT, "the image to become available", || {
+                let image = ec2_query(provider, region, "DescribeImages", &[("ImageId.1", &ami)])?;
+
+                match xml_element_text(&image, "imageState")?.as_deref() {
+                    Some("available") => Ok(true),
+                    Some("failed") | Some("error") | Some("invalid") | Some("deregistered") => {
+                        Err(self.failure(&format!("image {} could not be created", ami)))
+                    }
+                    _ => Ok(false),
+                }
+            })?;
+        });
+
+        Ok(ami)
+    }
+
+    fn failure(&self, error: &str) -> KawsError {
+        KawsError::new(Message::ImageBuildFailed {
+            cluster: self.cluster.to_owned(),
+            error: error.to_owned(),
+        })
+    }
+
+    // Records the image in the cluster's metadata and switches its masters and nodes to it on the
+    // next `kaws cluster apply`.
+    fn record(&self, ami: &str, base_ami: &str, kubernetes_version: &str) -> KawsResult {
+        let metadata_path = format!("clusters/{}/metadata.json", self.cluster);
+        let mut metadata = ClusterMetadata::from_file(&metadata_path)?;
+
+        metadata.baked_image = Some(BakedImage {
+            ami: ami.to_owned(),
+            base_ami: base_ami.to_owned(),
+            built_at: Utc::now().to_rfc3339(),
+            kubernetes_version: kubernetes_version.to_owned(),
+        });
+
+        let mut contents = String::new();
+
+        File::open(self.tfvars_path())?.read_to_string(&mut contents)?;
+
+        let tfvars = with_values(&contents, &[
+            ("kaws_baked_ami", &format!("\"{}\"", ami)),
+            ("kaws_baked_version", &format!("\"{}\"", kubernetes_version)),
+        ]);
+
+        log_wrap!("Recording the image in metadata and tfvars files", {
+            metadata.write_to_file(&metadata_path)?;
+
+            let mut file = File::create(self.tfvars_path())?;
+
+            write!(file, "{}", tfvars)?;
+        });
+
+        Ok(None)
+    }
+
+    fn tfvar<'b>(&self, tfvars: &'b Tfvars, key: &str) -> Result<&'b str, KawsError> {
+        tfvars.get(key).ok_or_else(|| KawsError::new(Message::TfvarsUnreadable {
+            cluster: self.cluster.to_owned(),
+            error: format!("{} is not set", key),
+        }))
+    }
+
+    fn tfvars_path(&self) -> String {
+        format!("clusters/{}/terraform.tfvars", self.cluster)
+    }
+
+    // A cloud-config that fetches every image the kubelet and the real cloud-config service need,
+    // removes what must be unique to each server, and powers off. If fetching fails, the instance
+    // stays running and the build times out.
+    fn user_data(&self, kubernetes_version: &str) -> String {
+        format!(
+            "#cloud-config\n\
+            \n\
+            coreos:\n\
+            \x20 units:\n\
+            \x20   - name: kaws-image-build.service\n\
+            \x20     command: start\n\
+            \x20     content: |\n\
+            \x20       [Unit]\n\
+            \x20       Description=Fetch images for kaws image build\n\
+            \x20       Requires=docker.service\n\
+            \x20       After=docker.service\n\
+            \x20       [Service]\n\
+            \x20       Type=oneshot\n\
+            \x20       ExecStart=/usr/bin/rkt fetch --insecure-options=image docker://{image}\n\
+            \x20       ExecStart=/usr/bin/rkt fetch --trust-keys-from-https quay.io/coreos/awscli\n\
+            \x20       ExecStart=/usr/bin/docker pull {image}\n\
+            \x20       ExecStart=/bin/sh -c 'rm -f /etc/ssh/ssh_host_*'\n\
+            \x20       ExecStart=/usr/bin/truncate --size=0 /etc/machine-id\n\
+            \x20       ExecStart=/usr/bin/systemctl poweroff\n",
+            image = format!("gcr.io/google_containers/hyperkube:v{}", kubernetes_version),
+        )
+    }
+
+    // Calls `done` every few seconds until it returns true, failing after `timeout`.
+    fn wait_for<F>(&self, timeout: Duration, waiting_for: &str, mut done: F)
+    -> Result<(), KawsError> where F: FnMut() -> Result<bool, KawsError> {
+        let started_at = Instant::now();
+
+        while !done()? {
+            if started_at.elapsed() > timeout {
+                return Err(self.failure(&format!(
+                    "timed out after {} minutes waiting for {}",
+                    timeout.as_secs() / 60,
+                    waiting_for,
+                )));
+            }
+
+            sleep(POLL_INTERVAL);
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,79 @@
+use std::sync::Mutex;
+use std::sync::OnceLock;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+
+/// How many jobs run at once without `--jobs`. Most of them wait on AWS rather than the CPU.
+pub const DEFAULT_JOBS: usize = 16;
+
+/// Threads kaws may start for jobs beyond the ones already running them, shared by every batch,
+/// including batches started by another batch's jobs.
+static SPARE_THREADS: OnceLock<AtomicUsize> = OnceLock::new();
+
+/// Limits kaws to `jobs` jobs at once for the rest of the run, from the global `--jobs` flag.
+pub fn set_limit(jobs: usize) {
+    let _ = SPARE_THREADS.set(AtomicUsize::new(jobs.max(1) - 1));
+}
+
+/// Runs `job` on each of `items`, returning the results in the same order.
+///
+/// The calling thread runs jobs itself, and is joined by as many new threads as the `--jobs`
+/// limit leaves room for, so that a batch started by another batch's job never waits for a
+/// thread, and the whole run never has more than `--jobs` jobs running at once. With
+/// `--jobs 1`, every job runs in turn on the calling thread.
+pub fn run_all<T, R, F>(items: Vec<T>, job: F) -> Vec<R>
+where
+    T: Send,
+    R: Send,
+    F: Fn(T) -> R + Sync,
+{
+    let count = items.len();
+    let queue = Mutex::new(items.into_iter().enumerate());
+    let results = Mutex::new((0..count).map(|_| None).collect::<Vec<Option<R>>>());
+    let work = || loop {
+        let next = queue.lock().expect("job queue poisoned").next();
+
+        match next {
+            Some((index, item)) => {
+                let result = job(item);
+
+                results.lock().expect("job results poisoned")[index] = Some(result);
+            }
+            None => break,
+        }
+    };
+
+    thread::scope(|scope| {
+        for _ in 1..count {
+            if !take_spare_thread() {
+                break;
+            }
+
+            scope.spawn(|| {
+                work();
+                spare_threads().fetch_add(1, Ordering::SeqCst);
+            });
+        }
+
+        work();
+    });
+
+    results.into_inner()
+        .expect("job results poisoned")
+        .into_iter()
+        .map(|result| result.expect("every job should have run"))
+        .collect()
+}
+
+// Private
+
+fn spare_threads() -> &'static AtomicUsize {
+    SPARE_THREADS.get_or_init(|| AtomicUsize::new(DEFAULT_JOBS - 1))
+}
+
+// Claims one of the spare threads, if any are left.
+fn take_spare_thread() -> bool {
+    spare_threads()
+        .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |spare| spare.checked_sub(1))
+        .is_ok()
+}
@@ -0,0 +1,170 @@
+use std::collections::BTreeMap;
+use std::fs::{remove_dir, remove_dir_all};
+use std::io::ErrorKind;
+
+use clap::ArgMatches;
+
+use aws::{Access, cluster_credentials_provider};
+use encryption::{REGIONAL_COPIES_DIR, encrypted_file_paths};
+use error::{KawsError, KawsResult};
+use files::entries_if_present;
+use message::Message;
+use metadata::{ClusterMetadata, KmsKey};
+use paths::from_invocation;
+use provider::{CloudProvider, cloud_provider};
+use render::print_rows;
+use tfvars::Tfvars;
+
+pub struct KmsRegions<'a> {
+    aws_credentials_path: Option<String>,
+    aws_credentials_profile: Option<&'a str>,
+    cluster: &'a str,
+    kms_key: Option<&'a str>,
+    region: Option<&'a str>,
+}
+
+impl<'a> KmsRegions<'a> {
+    pub fn new(matches: &'a ArgMatches) -> Self {
+        KmsRegions {
+            aws_credentials_path: matches.value_of("aws-credentials-path").map(from_invocation),
+            aws_credentials_profile: matches.value_of("aws-credentials-profile"),
+            cluster: matches.value_of("cluster").expect("clap should have required cluster"),
+            kms_key: matches.value_of("kms-key"),
+            region: matches.value_of("region"),
+        }
+    }
+
+    /// Decrypts each of the cluster's encrypted files in its own region and encrypts a copy of it
+    /// under the given KMS key in another region, then records the key in the cluster's metadata,
+    /// so that later encryptions keep the copies current. Adding a region again replaces its
+    /// copies, e.g. to move them to another key.
+    pub fn add(&self) -> KawsResult {
+        let region = self.region.expect("clap should have required region");
+        let kms_key = self.kms_key.expect("clap should have required kms-key");
+        let cluster_region = self.cluster_region()?;
+
+        if region == cluster_region {
+            return Err(KawsError::new(Message::KmsRegionIsClusterRegion {
+                cluster: self.cluster.to_owned(),
+                region: region.to_owned(),
+            }));
+        }
+
+        let mut metadata = self.metadata()?;
+        let provider = self.provider(&metadata)?;
+        let arn = provider.encryptor(region, None)?.key_arn(kms_key)?;
+
+        let mut decryptor = provider.encryptor(&cluster_region, None)?
+            .with_contexts_required(metadata.encryption_contexts_required);
+        let mut plaintexts = vec![];
+
+        for path in encrypted_file_paths(self.cluster)? {
+            plaintexts.push((decryptor.decrypt_file(&path)?, path));
+        }
+
+        let files: Vec<(&[u8], &str)> = plaintexts.iter()
+            .map(|(plaintext, path)| (plaintext.as_slice(), path.as_str()))
+            .collect();
+        let mut keys = BTreeMap::new();
+
+        keys.insert(region.to_owned(), arn.clone());
+
+        provider.encryptor(&cluster_region, None)?
+            .with_regional_keys(&keys)?
+            .encrypt_and_write_regional_copies(&files)?;
+
+        metadata.kms_regions.insert(region.to_owned(), KmsKey {
+            arn,
+            id: kms_key.to_owned(),
+        });
+        metadata.write_to_file(&self.metadata_path())?;
+
+        Ok(Some(Message::KmsRegionAdded {
+            cluster: self.cluster.to_owned(),
+            files: files.len(),
+            region: region.to_owned(),
+        }))
+    }
+
+    /// Prints each region the cluster's encrypted files have copies in, with the KMS key they're
+    /// encrypted under.
+    pub fn list(&self) -> KawsResult {
+        let metadata = self.metadata()?;
+        let mut rows = vec![vec!["REGION".to_owned(), "KMS KEY".to_owned(), "ARN".to_owned()]];
+
+        for (region, key) in &metadata.kms_regions {
+            rows.push(vec![region.clone(), key.id.clone(), key.arn.clone()]);
+        }
+
+        print_rows(&rows)?;
+
+        Ok(None)
+    }
+
+    /// Removes the cluster's copies of its encrypted files in a region, and the region's key from
+    /// its metadata. KMS itself isn't touched, so copies in Git history can still be decrypted
+    /// for as long as the key exists.
+    pub fn remove(&self) -> KawsResult {
+        let region = self.region.expect("clap should have required region");
+        let mut metadata = self.metadata()?;
+
+        if metadata.kms_regions.remove(region).is_none() {
+            return Err(KawsError::new(Message::KmsRegionNotFound {
+                cluster: self.cluster.to_owned(),
+                region: region.to_owned(),
+                regions: metadata.kms_regions.keys().cloned().collect(),
+            }));
+        }
+
+        let dir = format!("clusters/{}/{}", self.cluster, REGIONAL_COPIES_DIR);
+
+        match remove_dir_all(format!("{}/{}", dir, region)) {
+            Err(ref error) if error.kind() == ErrorKind::NotFound => {}
+            result => result?,
+        }
+
+        if entries_if_present(&dir)?.is_empty() {
+            match remove_dir(&dir) {
+                Err(ref error) if error.kind() == ErrorKind::NotFound => {}
+                result => result?,
+            }
+        }
+
+        metadata.write_to_file(&self.metadata_path())?;
+
+        Ok(Some(Message::KmsRegionRemoved {
+            cluster: self.cluster.to_owned(),
+            region: region.to_owned(),
+        }))
+    }
+
+    // Private
+
+    fn cluster_region(&self) -> Result<String, KawsError> {
+        let tfvars = Tfvars::from_file(&format!("clusters/{}/terraform.tfvars", self.cluster))?;
+
+        tfvars.get("kaws_region").map(str::to_owned).ok_or_else(|| {
+            KawsError::new(Message::TfvarsUnreadable {
+                cluster: self.cluster.to_owned(),
+                error: "kaws_region is not set".to_owned(),
+            })
+        })
+    }
+
+    fn metadata(&self) -> Result<ClusterMetadata, KawsError> {
+        ClusterMetadata::from_file(&self.metadata_path())
+    }
+
+    fn metadata_path(&self) -> String {
+        format!("clusters/{}/metadata.json", self.cluster)
+    }
+
+    fn provider(&self, metadata: &ClusterMetadata) -> Result<Box<dyn CloudProvider>, KawsError> {
+        Ok(cloud_provider(metadata.cloud_provider, cluster_credentials_provider(
+            self.cluster,
+            Access::ReadWrite,
+            self.aws_credentials_path.as_deref(),
+            self.aws_credentials_profile,
+        )?))
+    }
+}
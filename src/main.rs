@@ -1,11 +1,13 @@
 extern crate ansi_term;
 extern crate bitstring;
+extern crate chrono;
 extern crate env_logger;
+extern crate handlebars;
 extern crate cidr;
 extern crate clap;
 #[macro_use]
 extern crate log;
-extern crate hyper;
+extern crate openssl;
 extern crate rusoto_core;
 extern crate rusoto_kms;
 extern crate rustc_serialize;
@@ -15,6 +17,8 @@ extern crate serde_derive;
 #[macro_use]
 extern crate serde_json;
 extern crate tempdir;
+extern crate tokio;
+extern crate xml;
 
 macro_rules! log_wrap {
     ($m:expr, $b:block) => {
@@ -25,64 +29,260 @@ macro_rules! log_wrap {
 }
 
 mod admin;
+mod approval;
+mod audit;
 mod aws;
+mod cache;
+mod cfssl;
+mod clean;
 mod cli;
 mod cluster;
+mod compare;
+mod component_flags;
+mod cost;
 mod dependencies;
+mod doctor;
+mod drift;
 mod encryption;
 mod error;
+mod feature_gates;
+mod files;
+mod fleet;
+mod gc;
+mod graph;
+mod groups;
+mod image;
+mod jobs;
+mod kms_regions;
+mod message;
+mod metadata;
+mod metrics;
+mod module;
+mod node;
+mod paths;
 mod pki;
+mod pki_bundle;
+mod pki_manifest;
+mod pki_profiles;
+mod pki_status;
+mod pki_verify;
+mod preset;
 mod process;
+mod provider;
+mod reconcile;
+mod render;
+mod runtime;
 mod repository;
+mod secret;
+mod secret_store;
+mod secret_sinks;
+mod settings;
+mod snapshot;
+mod spec;
+mod ssh;
+mod status;
+mod template;
 mod terraform;
+mod tfvars;
+mod tunnel;
+mod units;
+mod userdata;
 
+use std::env;
 use std::process::exit;
+use std::time::Instant;
 
 use ansi_term::Colour::{Green, Red};
+use clap::ArgMatches;
+use env_logger::LogBuilder;
+use log::LogLevelFilter;
 
 use admin::Admin;
+use approval::Approve;
+use audit::Audit;
+use clean::Clean;
 use cluster::{ExistingCluster, NewCluster};
+use compare::Comparison;
 use dependencies::ensure_dependencies;
+use doctor::Doctor;
+use drift::DriftCron;
 use error::KawsResult;
+use feature_gates::FeatureGates;
+use fleet::Fleet;
+use gc::GarbageCollector;
+use graph::ClusterGraph;
+use groups::Groups;
+use image::Image;
+use kms_regions::KmsRegions;
+use paths::{check_cluster_name, enter_repository};
+use pki_bundle::PkiBundle;
+use pki_status::PkiStatus;
+use pki_verify::PkiVerifier;
+use reconcile::Reconciler;
 use repository::Repository;
+use secret::Secret;
+use secret_store::ClusterSecretStore;
+use settings::ClusterSettings;
+use snapshot::Rollback;
+use spec::SpecReconciler;
+use status::Status;
 use terraform::Terraform;
+use tunnel::Tunnel;
 
 fn main() {
-    env_logger::init().expect("Failed to initialize logger.");
+    let app_matches = cli::app().get_matches();
+
+    init_logger(global_flag(&app_matches, "trace-aws"));
+
+    if let Some(jobs) = global_value(&app_matches, "jobs") {
+        jobs::set_limit(jobs.parse().expect("clap should have validated jobs"));
+    }
 
+    render::set_options(render::TableOptions {
+        columns: global_value(&app_matches, "columns")
+            .map(|columns| columns.split(',').map(str::to_owned).collect()),
+        pager: !global_flag(&app_matches, "no-pager"),
+        sort_by: global_value(&app_matches, "sort-by").map(str::to_owned),
+        wide: global_flag(&app_matches, "wide"),
+    });
+
+    let porcelain = global_flag(&app_matches, "porcelain");
     let mut failed = false;
 
-    match execute_cli() {
+    audit::set_command(&command(&app_matches).0.join(" "));
+
+    let started_at = Instant::now();
+    let result = match command(&app_matches).1.value_of("cluster") {
+        Some(cluster) => check_cluster_name(cluster),
+        None => Ok(()),
+    }.and_then(|()| match app_matches.subcommand_name() {
+        // A new repository is created in the current directory, not an existing one.
+        Some("init") => execute_cli(&app_matches),
+        _ => enter_repository(global_value(&app_matches, "repo"))
+            .and_then(|()| execute_cli(&app_matches)),
+    });
+
+    if let Some(url) = global_value(&app_matches, "pushgateway") {
+        let (command, matches) = command(&app_matches);
+
+        if let Err(error) = metrics::push(
+            url,
+            &command.join("-"),
+            matches.value_of("cluster"),
+            started_at.elapsed(),
+            result.is_ok(),
+        ) {
+            eprintln!("warning: failed to push metrics: {}", error);
+        }
+    }
+
+    match result {
         Ok(success) => {
             if let Some(message) = success {
-                println!("{}", Green.paint(message.to_string()));
+                if porcelain {
+                    println!("{}", json!({
+                        "status": "success",
+                        "message": message,
+                        "text": message.text(),
+                    }));
+                } else {
+                    println!("{}", Green.paint(message.to_string()));
+                }
             }
         },
         Err(error) => {
-            let error_output = format!("Error:\n{}", error);
+            if porcelain {
+                println!("{}", json!({
+                    "status": "error",
+                    "message": error.message(),
+                    "text": error.message().text(),
+                    "stdout": error.stdout(),
+                    "stderr": error.stderr(),
+                }));
+            } else {
+                let error_output = format!("Error:\n{}", error);
 
-            println!("{}", Red.paint(error_output));
+                println!("{}", Red.paint(error_output));
+            }
 
             failed = true;
         },
     }
 
+    runtime::clean_up();
+
     if failed {
         exit(1);
     }
 }
 
-fn execute_cli() -> KawsResult {
-    let app_matches = cli::app().get_matches();
+// clap only propagates global arguments down to subcommands, so a flag given after a subcommand
+// has to be looked up at the level where it was given.
+fn global_flag(matches: &ArgMatches, name: &str) -> bool {
+    matches.is_present(name) ||
+        matches.subcommand().1.is_some_and(|matches| global_flag(matches, name))
+}
+
+fn global_value<'a>(matches: &'a ArgMatches, name: &str) -> Option<&'a str> {
+    matches.value_of(name)
+        .or_else(|| matches.subcommand().1.and_then(|matches| global_value(matches, name)))
+}
+
+// The names of the subcommands that were run, e.g. ["cluster", "plan"], and the matches of the
+// last one.
+fn command<'a, 'b>(matches: &'a ArgMatches<'b>) -> (Vec<&'a str>, &'a ArgMatches<'b>) {
+    match matches.subcommand() {
+        (name, Some(subcommand_matches)) => {
+            let (mut names, matches) = command(subcommand_matches);
+
+            names.insert(0, name);
+
+            (names, matches)
+        }
+        _ => (vec![], matches),
+    }
+}
+
+fn init_logger(trace_aws: bool) {
+    let mut builder = LogBuilder::new();
 
+    builder.filter(None, LogLevelFilter::Error);
+
+    if let Ok(filters) = env::var("RUST_LOG") {
+        builder.parse(&filters);
+    }
+
+    if trace_aws {
+        builder.filter(Some("kaws::aws"), LogLevelFilter::Trace);
+    }
+
+    builder.init().expect("Failed to initialize logger.");
+}
+
+fn execute_cli(app_matches: &ArgMatches) -> KawsResult {
     match app_matches.subcommand() {
         ("admin", Some(admin_matches)) => {
             ensure_dependencies()?;
 
             match admin_matches.subcommand() {
                 ("create", Some(matches)) => Admin::new(matches).create(),
+                ("credential", Some(matches)) => Admin::new(matches).credential(),
+                ("export", Some(matches)) => Admin::new(matches).export(),
+                ("groups", Some(groups_matches)) => {
+                    match groups_matches.subcommand() {
+                        ("add", Some(matches)) => Groups::new(matches).add(),
+                        ("list", Some(matches)) => Groups::new(matches).list(),
+                        ("remove", Some(matches)) => Groups::new(matches).remove(),
+                        _ => {
+                            println!("{}", groups_matches.usage());
+
+                            Ok(None)
+                        }
+                    }
+                }
                 ("install", Some(matches)) => Admin::new(matches).install(),
                 ("sign", Some(matches)) => Admin::new(matches).sign(),
+                ("ssh-cert", Some(matches)) => Admin::new(matches).ssh_cert(),
                 _ => {
                     println!("{}", admin_matches.usage());
 
@@ -90,27 +290,86 @@ fn execute_cli() -> KawsResult {
                 }
             }
         },
+        ("audit", Some(audit_matches)) => {
+            match audit_matches.subcommand() {
+                ("anomalies", Some(matches)) => Audit::new(matches).anomalies(),
+                ("usage", Some(matches)) => Audit::new(matches).usage(),
+                _ => {
+                    println!("{}", audit_matches.usage());
+
+                    Ok(None)
+                }
+            }
+        },
+        ("clean", Some(matches)) => Clean::new(matches).clean(),
         ("cluster", Some(cluster_matches)) => {
             ensure_dependencies()?;
 
             match cluster_matches.subcommand() {
                 ("apply", Some(matches)) => Terraform::new(matches).apply(),
+                ("apply-spec", Some(matches)) => SpecReconciler::new(matches).reconcile(),
+                ("approve", Some(matches)) => Approve::new(matches).approve(),
+                ("compare", Some(matches)) => Comparison::new(matches).compare(),
                 ("destroy", Some(matches)) => Terraform::new(matches).destroy(),
-                ("init", Some(matches)) => NewCluster::new(matches).init(),
+                ("drift-cron", Some(matches)) => DriftCron::new(matches).generate(),
+                ("feature-gates", Some(feature_gates_matches)) => {
+                    match feature_gates_matches.subcommand() {
+                        ("disable", Some(matches)) => FeatureGates::new(matches).disable(),
+                        ("enable", Some(matches)) => FeatureGates::new(matches).enable(),
+                        ("list", Some(matches)) => FeatureGates::new(matches).list(),
+                        _ => {
+                            println!("{}", feature_gates_matches.usage());
+
+                            Ok(None)
+                        }
+                    }
+                }
+                ("gc", Some(matches)) => GarbageCollector::new(matches).collect(),
+                ("graph", Some(matches)) => ClusterGraph::new(matches).render(),
+                ("init", Some(matches)) => NewCluster::new(matches)?.init(),
+                ("kms-regions", Some(kms_regions_matches)) => {
+                    match kms_regions_matches.subcommand() {
+                        ("add", Some(matches)) => KmsRegions::new(matches).add(),
+                        ("list", Some(matches)) => KmsRegions::new(matches).list(),
+                        ("remove", Some(matches)) => KmsRegions::new(matches).remove(),
+                        _ => {
+                            println!("{}", kms_regions_matches.usage());
+
+                            Ok(None)
+                        }
+                    }
+                }
                 ("generate-pki", Some(generate_pki_matches)) => {
                     match generate_pki_matches.subcommand() {
                         ("all", Some(matches)) => {
                             ExistingCluster::new(matches).generate_pki_all()
                         }
+                        ("bootstrap-token", Some(matches)) => {
+                            ExistingCluster::new(matches).generate_bootstrap_token()
+                        }
                         ("etcd", Some(matches)) => {
                             ExistingCluster::new(matches).generate_etcd_pki()
                         }
                         ("etcd-peer", Some(matches)) => {
                             ExistingCluster::new(matches).generate_etcd_peer_pki()
                         }
+                        ("front-proxy", Some(matches)) => {
+                            ExistingCluster::new(matches).generate_front_proxy_pki()
+                        }
+                        ("import-ca", Some(matches)) => ExistingCluster::new(matches).import_ca(),
+                        ("ingress", Some(matches)) => {
+                            ExistingCluster::new(matches).generate_ingress_pki()
+                        }
                         ("kubernetes", Some(matches)) => {
                             ExistingCluster::new(matches).generate_kubernetes_pki()
                         }
+                        ("service-account", Some(matches)) => {
+                            ExistingCluster::new(matches).generate_service_account_pki()
+                        }
+                        ("ssh", Some(matches)) => ExistingCluster::new(matches).generate_ssh_pki(),
+                        ("userdata-signing", Some(matches)) => {
+                            ExistingCluster::new(matches).generate_userdata_signing_pki()
+                        }
                         _ => {
                             println!("{}", generate_pki_matches.usage());
 
@@ -119,8 +378,52 @@ fn execute_cli() -> KawsResult {
                     }
                 }
                 ("output", Some(matches)) => Terraform::new(matches).output(),
+                ("pki", Some(pki_matches)) => {
+                    match pki_matches.subcommand() {
+                        ("export-bundle", Some(matches)) => PkiBundle::new(matches).export(),
+                        ("import-ca", Some(matches)) => ExistingCluster::new(matches).import_ca(),
+                        ("refresh-endpoints", Some(matches)) => {
+                            ExistingCluster::new(matches).refresh_endpoints()
+                        }
+                        ("revoke", Some(matches)) => {
+                            ExistingCluster::new(matches).revoke_certificate()
+                        }
+                        ("revoke-node", Some(matches)) => {
+                            ExistingCluster::new(matches).revoke_node()
+                        }
+                        ("status", Some(matches)) => PkiStatus::new(matches).report(),
+                        ("verify", Some(matches)) => PkiVerifier::new(matches).verify(),
+                        _ => {
+                            println!("{}", pki_matches.usage());
+
+                            Ok(None)
+                        }
+                    }
+                }
                 ("plan", Some(matches)) => Terraform::new(matches).plan(),
+                ("reencrypt", Some(matches)) => ExistingCluster::new(matches).reencrypt(),
                 ("refresh", Some(matches)) => Terraform::new(matches).refresh(),
+                ("renew-pki", Some(matches)) => ExistingCluster::new(matches).renew_pki(),
+                ("rollback", Some(matches)) => Rollback::new(matches).run(),
+                ("rotate-pki", Some(matches)) => ExistingCluster::new(matches).rotate_pki(),
+                ("rotate-service-account-key", Some(matches)) => {
+                    ExistingCluster::new(matches).rotate_service_account_key()
+                }
+                ("secret-store", Some(secret_store_matches)) => {
+                    match secret_store_matches.subcommand() {
+                        ("disable", Some(matches)) => ClusterSecretStore::new(matches).disable(),
+                        ("enable", Some(matches)) => ClusterSecretStore::new(matches).enable(),
+                        ("pull", Some(matches)) => ClusterSecretStore::new(matches).pull(),
+                        ("rotate", Some(matches)) => ClusterSecretStore::new(matches).rotate(),
+                        _ => {
+                            println!("{}", secret_store_matches.usage());
+
+                            Ok(None)
+                        }
+                    }
+                }
+                ("set", Some(matches)) => ClusterSettings::new(matches).set(),
+                ("tunnel", Some(matches)) => Tunnel::new(matches).run(),
                 _ => {
                     println!("{}", cluster_matches.usage());
 
@@ -128,11 +431,50 @@ fn execute_cli() -> KawsResult {
                 }
             }
         },
+        ("doctor", Some(matches)) => Doctor::new(matches).examine(),
+        ("fleet", Some(fleet_matches)) => {
+            ensure_dependencies()?;
+
+            match fleet_matches.subcommand() {
+                ("status", Some(matches)) => Fleet::new(matches).status(),
+                _ => {
+                    println!("{}", fleet_matches.usage());
+
+                    Ok(None)
+                }
+            }
+        },
+        ("image", Some(image_matches)) => {
+            match image_matches.subcommand() {
+                ("build", Some(matches)) => Image::new(matches).build(),
+                _ => {
+                    println!("{}", image_matches.usage());
+
+                    Ok(None)
+                }
+            }
+        },
         ("init", Some(matches)) => {
             ensure_dependencies()?;
 
             Repository::new(matches).create()
         }
+        ("reconcile", Some(matches)) => {
+            ensure_dependencies()?;
+
+            Reconciler::new(matches).run()
+        }
+        ("secret", Some(secret_matches)) => {
+            match secret_matches.subcommand() {
+                ("cat", Some(matches)) => Secret::new(matches).cat(),
+                _ => {
+                    println!("{}", secret_matches.usage());
+
+                    Ok(None)
+                }
+            }
+        },
+        ("status", Some(matches)) => Status::new(matches).summarize(),
         _ => {
             println!("{}", app_matches.usage());
 
@@ -0,0 +1,1485 @@
+use std::fmt::{Display, Error as FmtError, Formatter};
+
+/// Every message kaws shows the user when a command succeeds or fails.
+///
+/// Each message has a stable code (the variant name in kebab case) and named parameters. The
+/// English text is for people; wrappers that need to act on a message, or to translate or rephrase
+/// it, should read the code and parameters from `--porcelain` output instead of parsing the text.
+/// Codes and parameter names must not change once released.
+#[derive(Debug, Serialize)]
+#[serde(tag = "code", content = "params", rename_all = "kebab-case")]
+pub enum Message {
+    // Success
+
+    AdminCreated {
+        admin: String,
+        cluster: String,
+    },
+    AdminExported {
+        admin: String,
+        cluster: String,
+        path: String,
+    },
+    AdminInstalled {
+        admin: String,
+        cluster: String,
+    },
+    AdminSigned {
+        admin: String,
+        cluster: String,
+    },
+    ApprovalRecorded {
+        approver: String,
+        operation: String,
+        path: String,
+    },
+    AuditAnomaliesNotFound {
+        cluster: String,
+    },
+    CaImported {
+        ca: String,
+        cluster: String,
+        stale: Vec<String>,
+    },
+    CertificateRevoked {
+        crl_path: String,
+        serial_number: String,
+    },
+    ClusterDestroyed {
+        cluster: String,
+    },
+    ClusterInitialized {
+        cluster: String,
+    },
+    ClusterReencrypted {
+        cluster: String,
+        files: usize,
+        kms_key: String,
+    },
+    ClusterRolledBack {
+        cluster: String,
+        timestamp: String,
+    },
+    ClusterSettingsChanged {
+        changes: usize,
+        cluster: String,
+    },
+    ClusterSettingsUnchanged {
+        cluster: String,
+    },
+    ClusterSpecApplied {
+        changes: usize,
+        cluster: String,
+    },
+    ClusterSpecMatched {
+        cluster: String,
+    },
+    ClusterSpecPlanned {
+        changes: usize,
+        cluster: String,
+    },
+    ClustersMatch {
+        first: String,
+        second: String,
+    },
+    DoctorPassed,
+    DriftCronGenerated {
+        ci: String,
+        cluster: String,
+        job_path: String,
+        policy_path: String,
+    },
+    EndpointsRefreshed {
+        applied: bool,
+        certificates: Vec<String>,
+        cluster: String,
+    },
+    EndpointsUnchanged {
+        cluster: String,
+    },
+    FeatureGateSet {
+        cluster: String,
+        enabled: bool,
+        gate: String,
+    },
+    FeatureGateUnchanged {
+        cluster: String,
+        enabled: bool,
+        gate: String,
+    },
+    GarbageCollected {
+        cluster: String,
+        files: usize,
+    },
+    GarbageKept {
+        cluster: String,
+    },
+    GarbageNotFound {
+        cluster: String,
+    },
+    GraphWritten {
+        cluster: String,
+        path: String,
+    },
+    GroupRemoved {
+        cluster: String,
+        group: String,
+    },
+    GroupUpdated {
+        cluster: String,
+        group: String,
+    },
+    ImageBuilt {
+        ami: String,
+        cluster: String,
+        kubernetes_version: String,
+    },
+    IntermediateCaRequested {
+        cluster: String,
+        csr_path: String,
+    },
+    KmsRegionAdded {
+        cluster: String,
+        files: usize,
+        region: String,
+    },
+    KmsRegionRemoved {
+        cluster: String,
+        region: String,
+    },
+    NodeRevoked {
+        cluster: String,
+        instance_id: String,
+        node: String,
+        replacement: String,
+        serial_numbers: Vec<String>,
+    },
+    PkiBundleExported {
+        cluster: String,
+        files: usize,
+        path: String,
+    },
+    PkiRenewed {
+        applied: bool,
+        cluster: String,
+        files: Vec<String>,
+    },
+    PkiRotationPhaseCompleted {
+        cluster: String,
+        phase: String,
+    },
+    PkiVerified {
+        checks: usize,
+        cluster: String,
+    },
+    RepositoryCreated {
+        name: String,
+    },
+    SecretStoreDisabled {
+        cluster: String,
+        store: String,
+    },
+    SecretStoreEnabled {
+        cluster: String,
+        files: usize,
+        store: String,
+    },
+    SecretStorePulled {
+        cluster: String,
+        files: usize,
+        store: String,
+    },
+    SecretStoreRotated {
+        cluster: String,
+        files: usize,
+        store: String,
+    },
+    ServiceAccountKeyRotationPhaseCompleted {
+        cluster: String,
+        phase: String,
+    },
+    SpecsReconciled {
+        clusters: Vec<String>,
+        waiting: Vec<String>,
+    },
+    SshCertificateSigned {
+        admin: String,
+        cert_path: String,
+        cluster: String,
+        ttl: String,
+    },
+    StatusClean,
+    StatusNeedsAttention {
+        count: usize,
+    },
+    TunnelClosed {
+        cluster: String,
+    },
+    TunnelOpened {
+        cluster: String,
+        port: String,
+    },
+    WorkingDirectoriesCleaned {
+        count: usize,
+    },
+
+    // Failure
+
+    AmiEnaUnsupported {
+        ami: String,
+        instance_size: String,
+    },
+    ApprovalKeyMismatch {
+        approver: String,
+        cluster: String,
+        key_path: String,
+    },
+    ApprovalsMissing {
+        approvals: usize,
+        cluster: String,
+        operation: String,
+        required: usize,
+    },
+    ApproverUnknown {
+        approver: String,
+        cluster: String,
+    },
+    AuditAnomaliesFound {
+        anomalies: usize,
+        cluster: String,
+    },
+    AuditKmsKeyUnknown {
+        cluster: String,
+    },
+    AwsDateMissing,
+    BudgetExceeded {
+        cluster: String,
+        estimate: f64,
+        ceiling: f64,
+    },
+    CaCertificateInvalid {
+        error: String,
+    },
+    CertificateAuthorityKeyIdMismatch {
+        authority_key_id: String,
+        ca_subject_key_id: String,
+    },
+    CertificateAuthorityKeyIdMissing,
+    CertificateAuthorityMissing {
+        path: String,
+    },
+    CertificateExpiryInvalid {
+        error: String,
+        name: String,
+        value: String,
+    },
+    CertificateNotInManifest {
+        cluster: String,
+        serial_number: String,
+    },
+    CertificateSerialNumberReused {
+        serial_number: String,
+    },
+    CertificatesExpiring {
+        cluster: String,
+        count: usize,
+        days: i64,
+    },
+    CertificateSigningRequestRejected {
+        admin: String,
+        reason: String,
+    },
+    CertificateSigningRequestSignatureInvalid,
+    CertificateSubjectInvalid {
+        error: String,
+    },
+    CfsslSigningFailed {
+        error: String,
+        url: String,
+    },
+    ChildProcessFailed {
+        command: String,
+    },
+    CloudProviderUnsupported {
+        operation: String,
+        provider: String,
+    },
+    ClockAhead {
+        seconds: f64,
+        backdate: String,
+    },
+    ClockSkewed {
+        seconds: f64,
+    },
+    ClusterDrifted {
+        cluster: String,
+    },
+    ClusterNameInvalid {
+        cluster: String,
+    },
+    ClusterSettingInvalid {
+        error: String,
+        setting: String,
+    },
+    ClusterSettingUnknown {
+        setting: String,
+    },
+    ClustersDiffer {
+        first: String,
+        second: String,
+        count: usize,
+    },
+    ComponentFlagManaged {
+        component: String,
+        flag: String,
+    },
+    ComponentFlagUnknown {
+        component: String,
+        flag: String,
+        version: String,
+    },
+    ComponentFlagValueInvalid {
+        component: String,
+        flag: String,
+        value: String,
+    },
+    DependencyMissing {
+        program: String,
+    },
+    DoctorFailed {
+        failures: usize,
+    },
+    EncryptedFileInvalid {
+        path: String,
+    },
+    FeatureGateUnknown {
+        gate: String,
+        version: String,
+    },
+    GroupNotBound {
+        group: String,
+    },
+    IamUserNotGiven {
+        iam_user: String,
+    },
+    ImageBuildFailed {
+        cluster: String,
+        error: String,
+    },
+    InstancePriceUnknown {
+        instance_size: String,
+        region: String,
+    },
+    KeySizeUnsupported {
+        algo: String,
+        size: String,
+    },
+    KmsCaKeyUnsupported {
+        key_id: String,
+        operation: String,
+    },
+    KmsKeyUnresolved {
+        key_id: String,
+    },
+    KmsNoCiphertext,
+    KmsNoPlaintext,
+    KmsRegionIsClusterRegion {
+        cluster: String,
+        region: String,
+    },
+    KmsRegionNotFound {
+        cluster: String,
+        region: String,
+        regions: Vec<String>,
+    },
+    KmsSigningFailed {
+        error: String,
+        key_id: String,
+    },
+    KmsUnreachable {
+        error: String,
+        region: String,
+    },
+    MetricsPushRejected {
+        error: String,
+        status: u16,
+        url: String,
+    },
+    NodeInstanceNotFound {
+        cluster: String,
+        instance_id: String,
+    },
+    NodeReplacementTimedOut {
+        cluster: String,
+        instance_id: String,
+        minutes: i64,
+    },
+    PassphraseIncorrect {
+        path: String,
+    },
+    PasswordEmpty,
+    PasswordMismatch,
+    Pkcs11CaKeyUnsupported {
+        operation: String,
+        uri: String,
+    },
+    Pkcs11SigningFailed {
+        error: String,
+        uri: String,
+    },
+    PkiAlreadyExists {
+        cluster: String,
+        paths: Vec<String>,
+    },
+    PkiProfileInvalid {
+        error: String,
+        path: String,
+        profile: Option<String>,
+    },
+    PkiReplacementDeclined {
+        cluster: String,
+    },
+    PkiRotationOutOfOrder {
+        cluster: String,
+        phase: String,
+        next_phase: String,
+    },
+    PkiVerificationFailed {
+        cluster: String,
+        failures: usize,
+    },
+    PresetInvalid {
+        error: String,
+        path: String,
+    },
+    PresetOptionMissing {
+        option: String,
+        preset: String,
+    },
+    PresetUnknown {
+        preset: String,
+        presets: Vec<String>,
+    },
+    RepositoryNotFound {
+        path: String,
+    },
+    S3BucketNotVersioned {
+        bucket: String,
+    },
+    S3ObjectNotFound {
+        path: String,
+    },
+    SecretNotFound {
+        cluster: String,
+        name: String,
+        names: Vec<String>,
+    },
+    SecretSinkInvalid {
+        error: String,
+        role: String,
+    },
+    SecretStoreNotEnabled {
+        cluster: String,
+    },
+    SecretStorePutFailed {
+        error: String,
+        failed: Vec<String>,
+        updated: Vec<String>,
+    },
+    SecretsManagerPendingVersionInvalid {
+        name: String,
+    },
+    SecretsManagerSecretNotFound {
+        name: String,
+    },
+    ShortLivedCertificateExpiryInvalid {
+        max_days: i64,
+        value: String,
+    },
+    SnapshotNotFound {
+        cluster: String,
+        timestamp: String,
+        timestamps: Vec<String>,
+    },
+    SpecInvalid {
+        error: String,
+        path: String,
+    },
+    SpecsNotReconciled {
+        clusters: Vec<String>,
+    },
+    SsmParameterNotFound {
+        name: String,
+    },
+    SubnetsOverlap {
+        first: String,
+        second: String,
+    },
+    TableColumnUnknown {
+        column: String,
+        columns: Vec<String>,
+    },
+    TemporaryPathNotUtf8,
+    TerraformDataDirInvalid {
+        path: String,
+    },
+    TerraformDestroyFailed {
+        cluster: String,
+    },
+    TerraformGraphFailed {
+        cluster: String,
+    },
+    TerraformInitFailed,
+    TerraformOutputFailed {
+        cluster: String,
+        output: String,
+    },
+    TerraformPlanFailed {
+        cluster: String,
+    },
+    TfvarsUnreadable {
+        cluster: String,
+        error: String,
+    },
+    TlsBootstrappingCaKeyExternal {
+        setting: String,
+    },
+    WebhookRejected {
+        status: u16,
+        url: String,
+    },
+
+    // Failure in a library kaws uses, with that library's own description of the problem
+
+    AwsCredentialsError {
+        error: String,
+    },
+    AwsHttpError {
+        error: String,
+    },
+    Base64Error {
+        error: String,
+    },
+    CloudTrailError {
+        error: String,
+    },
+    Ec2Error {
+        error: String,
+    },
+    IoError {
+        error: String,
+    },
+    JsonError {
+        error: String,
+    },
+    KmsError {
+        error: String,
+    },
+    OpensslError {
+        error: String,
+    },
+    PricingError {
+        error: String,
+    },
+    RegionError {
+        error: String,
+    },
+    S3Error {
+        error: String,
+    },
+    SecretsManagerError {
+        error: String,
+    },
+    SsmError {
+        error: String,
+    },
+    TemplateError {
+        error: String,
+    },
+    TimeError {
+        error: String,
+    },
+    Utf8Error {
+        error: String,
+    },
+    XmlError {
+        error: String,
+    },
+}
+
+impl Message {
+    /// The English text of the message.
+    pub fn text(&self) -> String {
+        match *self {
+            Message::AdminCreated { .. } => {
+                "Certificate signing request created! Commit changes to Git and ask an\n\
+                administrator to generate your client certificate.".to_owned()
+            }
+            Message::AdminExported { ref admin, ref cluster, ref path } => format!(
+                "Credentials of administrator \"{}\" for cluster \"{}\" exported to {}!\n\
+                The file contains the administrator's private key, so keep it out of Git.",
+                admin,
+                cluster,
+                path,
+            ),
+            Message::AdminInstalled { ref admin, ref cluster } => format!(
+                "Admin credentials for user \"{admin}\" installed for cluster \"{cluster}\"!\n\
+                To activate these settings as the current context, run:\n\n\
+                kubectl config use-context kaws-{cluster}\n\n\
+                If the kubectl configuration file is ever removed or changed accidentally,\n\
+                just run this command again to regenerate or reconfigure it.",
+                admin = admin,
+                cluster = cluster,
+            ),
+            Message::AdminSigned { ref admin, ref cluster } => format!(
+                "Client certificate for administrator \"{}\" created for cluster \"{}\"!\n\
+                Commit changes to Git and ask the administrator to run `kaws admin install`.",
+                admin,
+                cluster,
+            ),
+            Message::ApprovalRecorded { ref approver, ref operation, ref path } => format!(
+                "Approval of {} by \"{}\" written to {}!\n\
+                Commit it to Git so the operator running the command can use it.",
+                operation,
+                approver,
+                path,
+            ),
+            Message::AuditAnomaliesNotFound { ref cluster } => format!(
+                "No unusual decryptions of the secrets of cluster \"{}\" found.",
+                cluster,
+            ),
+            Message::CaImported { ref ca, ref cluster, ref stale } => format!(
+                "CA \"{}\" imported for cluster \"{}\"!\n{}\
+                Issue its certificates with `kaws cluster generate-pki`, using any subject but \
+                \"ca\", then commit changes to Git and run `kaws cluster apply`.",
+                ca,
+                cluster,
+                if stale.is_empty() {
+                    String::new()
+                } else {
+                    format!(
+                        "These certificates were issued by the CA it replaced, and aren't \
+                        trusted until they are issued again: {}\n",
+                        stale.join(", "),
+                    )
+                },
+            ),
+            Message::CertificateRevoked { ref crl_path, ref serial_number } => format!(
+                "Certificate {} added to the certificate revocation list at {}! The cluster's \
+                servers don't check the list, so the certificate keeps working until it expires or \
+                its CA is rotated.\nCommit changes to Git.",
+                serial_number,
+                crl_path,
+            ),
+            Message::ClusterDestroyed { ref cluster } => format!(
+                "Destroyed cluster \"{}\"! You should remove clusters/{} from Git.",
+                cluster,
+                cluster,
+            ),
+            Message::ClusterInitialized { ref cluster } => format!(
+                "Cluster \"{name}\" initialized! Commit clusters/{name} to Git.",
+                name = cluster,
+            ),
+            Message::ClusterReencrypted { ref cluster, files, ref kms_key } => format!(
+                "Encrypted {} file(s) of cluster \"{name}\" with the KMS key {}! Run `kaws cluster \
+                apply {name}` so the servers get them, and commit clusters/{name} to Git.",
+                files,
+                kms_key,
+                name = cluster,
+            ),
+            Message::ClusterRolledBack { ref cluster, ref timestamp } => format!(
+                "Restored the configuration cluster \"{name}\" was applied with at {}. Review the \
+                plan above, then run `kaws cluster apply {name}` to roll back, and commit \
+                clusters/{name} to Git.",
+                timestamp,
+                name = cluster,
+            ),
+            Message::ClusterSettingsChanged { changes, ref cluster } => format!(
+                "Changed {} settings of cluster \"{name}\". Review the plan above, then run \
+                `kaws cluster apply {name}` to carry them out, and commit clusters/{name} to Git.",
+                changes,
+                name = cluster,
+            ),
+            Message::ClusterSettingsUnchanged { ref cluster } => {
+                format!("Cluster \"{}\" already has those settings.", cluster)
+            }
+            Message::ClusterSpecApplied { changes, ref cluster } => format!(
+                "Cluster \"{}\" converged on its spec with {} changes! Commit changes to Git.",
+                cluster,
+                changes,
+            ),
+            Message::ClusterSpecMatched { ref cluster } => {
+                format!("Cluster \"{}\" already matches its spec.", cluster)
+            }
+            Message::ClusterSpecPlanned { changes, ref cluster } => format!(
+                "Cluster \"{}\" differs from its spec by {} changes. Run without --dry-run to \
+                make them.",
+                cluster,
+                changes,
+            ),
+            Message::ClustersMatch { ref first, ref second } => {
+                format!("Clusters {} and {} match.", first, second)
+            }
+            Message::DoctorPassed => "No problems found.".to_owned(),
+            Message::DriftCronGenerated { ref ci, ref job_path, ref policy_path, .. } => format!(
+                "Drift check written to {} and {}!\n\
+                Create an IAM user with only that policy, add its access key and your webhook URL \
+                to {} as described at the top of {}, and commit both files to Git.",
+                job_path,
+                policy_path,
+                if ci == "gitlab" { "GitLab" } else { "GitHub" },
+                job_path,
+            ),
+            Message::EndpointsRefreshed { applied, ref certificates, ref cluster } => format!(
+                "Reissued {} for the new endpoints of cluster \"{}\"!\n{}",
+                certificates.join(", "),
+                cluster,
+                if applied {
+                    "Commit the changes to Git."
+                } else {
+                    "Commit the changes to Git and run `kaws cluster apply` to distribute them."
+                },
+            ),
+            Message::EndpointsUnchanged { ref cluster } => format!(
+                "The endpoints of cluster \"{}\" haven't changed, so no certificates were reissued.",
+                cluster,
+            ),
+            Message::FeatureGateSet { ref cluster, enabled, ref gate } => format!(
+                "Feature gate {} {} for the API server, controller manager, and kubelets of \
+                cluster \"{}\"! Commit the changes to Git, run `kaws cluster apply {}`, and then \
+                replace the masters and nodes, which keep their flags until they are.",
+                gate,
+                if enabled { "enabled" } else { "disabled" },
+                cluster,
+                cluster,
+            ),
+            Message::FeatureGateUnchanged { ref cluster, enabled, ref gate } => format!(
+                "Feature gate {} is already {} for cluster \"{}\".",
+                gate,
+                if enabled { "enabled" } else { "disabled" },
+                cluster,
+            ),
+            Message::GarbageCollected { ref cluster, files } => format!(
+                "Removed {} file(s) from cluster \"{}\"! Commit the changes to Git.",
+                files,
+                cluster,
+            ),
+            Message::GarbageKept { ref cluster } => {
+                format!("The files of cluster \"{}\" were left as they were.", cluster)
+            }
+            Message::GarbageNotFound { ref cluster } => {
+                format!("Cluster \"{}\" has no files to clean up.", cluster)
+            }
+            Message::GraphWritten { ref cluster, ref path } => {
+                format!("Graph of cluster \"{}\" written to {}.", cluster, path)
+            }
+            Message::GroupRemoved { ref cluster, ref group } => format!(
+                "Group \"{group}\" removed from cluster \"{cluster}\"! Commit changes to Git, then run\n\
+                `kubectl delete clusterrolebinding kaws-group-{group}`. Administrators' existing \
+                client certificates keep the group until they expire or are revoked.",
+            ),
+            Message::GroupUpdated { ref cluster, ref group } => format!(
+                "Group \"{}\" updated for cluster \"{}\"! Commit changes to Git and run\n\
+                `kubectl apply -f clusters/{}/rbac.yml`. Clusters using aws-iam-authenticator also \
+                need `kaws cluster apply`.",
+                group,
+                cluster,
+                cluster,
+            ),
+            Message::ImageBuilt { ref ami, ref cluster, ref kubernetes_version } => format!(
+                "Image {} with Kubernetes {} built for cluster \"{}\"!\n\
+                Commit changes to Git and run `kaws cluster apply` to boot new masters and nodes \
+                from it.",
+                ami,
+                kubernetes_version,
+                cluster,
+            ),
+            Message::IntermediateCaRequested { ref cluster, ref csr_path } => format!(
+                "Certificate signing request for the intermediate CA written to {}!\n\
+                Have your root CA sign it, then run the same command again with \
+                --intermediate-cert and --root-cert instead of --intermediate-csr to finish \
+                generating the PKI for cluster \"{}\".",
+                csr_path,
+                cluster,
+            ),
+            Message::KmsRegionAdded { ref cluster, files, ref region } => format!(
+                "Copied {} encrypted file(s) of cluster \"{}\" under its KMS key in {}! Commit the \
+                changes to Git.",
+                files,
+                cluster,
+                region,
+            ),
+            Message::KmsRegionRemoved { ref cluster, ref region } => format!(
+                "Removed the copies of cluster \"{}\"'s encrypted files in {}! Commit the changes \
+                to Git.",
+                cluster,
+                region,
+            ),
+            Message::NodeRevoked {
+                ref cluster,
+                ref instance_id,
+                ref node,
+                ref replacement,
+                ref serial_numbers,
+            } => format!(
+                "Node {} of cluster \"{}\" was removed and its instance {} terminated, and node {} \
+                has joined in its place!\n{}\nCommit the changes to Git.",
+                node,
+                cluster,
+                instance_id,
+                replacement,
+                if serial_numbers.is_empty() {
+                    "No client certificate of the node was found to revoke.".to_owned()
+                } else {
+                    format!("Revoked certificate(s) {}.", serial_numbers.join(", "))
+                },
+            ),
+            Message::PkiBundleExported { ref cluster, files, ref path } => format!(
+                "{} public files of cluster \"{}\" exported to {}! The bundle holds no private \
+                keys, so it can be shared with auditors and other teams.",
+                files,
+                cluster,
+                path,
+            ),
+            Message::PkiRenewed { applied, ref cluster, ref files } => format!(
+                "Reissued the certificates of cluster \"{}\" from its existing CAs, changing:\n\
+                {}\n{}",
+                cluster,
+                files.iter().map(|file| format!("  {}", file)).collect::<Vec<_>>().join("\n"),
+                if applied {
+                    "Commit the changes to Git."
+                } else {
+                    "Commit the changes to Git and run `kaws cluster apply` to distribute them."
+                },
+            ),
+            Message::PkiRotationPhaseCompleted { ref cluster, ref phase } => match phase.as_str() {
+                "start" => format!(
+                    "New CAs generated for cluster \"{cluster}\" and added to its trusted CAs!\n\
+                    Commit changes to Git and run `kaws cluster apply {cluster}`, so every server \
+                    trusts the new CAs, then run the \"reissue\" phase.",
+                    cluster = cluster,
+                ),
+                "reissue" => format!(
+                    "Certificates for cluster \"{cluster}\" reissued by the new CAs!\n\
+                    Commit changes to Git and run `kaws cluster apply {cluster}`. Once every \
+                    administrator's certificate is signed again with `kaws admin sign`, run the \
+                    \"finish\" phase.",
+                    cluster = cluster,
+                ),
+                _ => format!(
+                    "Old CAs removed from the trusted CAs of cluster \"{cluster}\"!\n\
+                    Commit changes to Git and run `kaws cluster apply {cluster}` to complete the \
+                    rotation.",
+                    cluster = cluster,
+                ),
+            },
+            Message::PkiVerified { checks, ref cluster } => format!(
+                "All {} checks of the PKI of cluster \"{}\" passed!",
+                checks,
+                cluster,
+            ),
+            Message::RepositoryCreated { ref name } => {
+                format!("New repository \"{}\" created!", name)
+            }
+            Message::SecretStoreDisabled { ref cluster, ref store } => format!(
+                "Servers of cluster \"{}\" will get their encrypted files from user data again, \
+                rather than from {}! Commit the changes to Git, including the encrypted files, and \
+                run `kaws cluster apply {}`.",
+                cluster,
+                store,
+                cluster,
+            ),
+            Message::SecretStoreEnabled { ref cluster, files, ref store } => format!(
+                "Wrote {files} encrypted file(s) of cluster \"{cluster}\" to {store}! Its \
+                .gitignore now leaves them out of Git: run `git rm --cached \
+                clusters/{cluster}/*-encrypted.base64`, commit the changes, and run `kaws cluster \
+                apply {cluster}`, so servers fetch their files from {store} at boot.",
+                cluster = cluster,
+                files = files,
+                store = store,
+            ),
+            Message::SecretStorePulled { ref cluster, files, ref store } => format!(
+                "Wrote {} encrypted file(s) of cluster \"{}\" from {} to its directory!",
+                files,
+                cluster,
+                store,
+            ),
+            Message::SecretStoreRotated { ref cluster, files, ref store } => format!(
+                "Rotated {} encrypted file(s) of cluster \"{}\" in {} to the ones in its \
+                directory!",
+                files,
+                cluster,
+                store,
+            ),
+            Message::ServiceAccountKeyRotationPhaseCompleted { ref cluster, ref phase } => {
+                match phase.as_str() {
+                    "start" => format!(
+                        "New service account key generated for cluster \"{cluster}\" and trusted \
+                        alongside the current one!\n\
+                        Commit changes to Git and run `kaws cluster apply {cluster}`, so every \
+                        master trusts the new key, then run the \"reissue\" phase.",
+                        cluster = cluster,
+                    ),
+                    "reissue" => format!(
+                        "Cluster \"{cluster}\" will sign service account tokens with the new key!\n\
+                        Commit changes to Git and run `kaws cluster apply {cluster}`. Once every \
+                        token signed with the old key has been replaced, run the \"finish\" phase.",
+                        cluster = cluster,
+                    ),
+                    _ => format!(
+                        "Old service account key removed from the trusted keys of cluster \
+                        \"{cluster}\"!\n\
+                        Commit changes to Git and run `kaws cluster apply {cluster}` to complete the \
+                        rotation.",
+                        cluster = cluster,
+                    ),
+                }
+            }
+            Message::SpecsReconciled { ref clusters, ref waiting } => {
+                let mut text = if clusters.is_empty() {
+                    "No cluster specs needed applying.".to_owned()
+                } else {
+                    format!(
+                        "Specs applied to clusters {}! Commit changes to Git.",
+                        clusters.join(", "),
+                    )
+                };
+
+                if !waiting.is_empty() {
+                    text.push_str(&format!(
+                        "\nThe specs of clusters {} are waiting for approval.",
+                        waiting.join(", "),
+                    ));
+                }
+
+                text
+            }
+            Message::SshCertificateSigned { ref cert_path, ref cluster, ref ttl, .. } => format!(
+                "SSH certificate written to {cert} and valid for {ttl}!\n\
+                To connect to a server in cluster \"{cluster}\", run:\n\n\
+                ssh -o UserKnownHostsFile=clusters/{cluster}/ssh_known_hosts core@SERVER",
+                cert = cert_path,
+                cluster = cluster,
+                ttl = ttl,
+            ),
+            Message::StatusClean => "Nothing needs attention.".to_owned(),
+            Message::StatusNeedsAttention { count } => {
+                format!("{} item(s) need attention.", count)
+            }
+            Message::TunnelClosed { ref cluster } => format!(
+                "Tunnel to cluster \"{}\" closed and kubectl pointed back at its Kubernetes API.",
+                cluster,
+            ),
+            Message::TunnelOpened { ref cluster, ref port } => format!(
+                "Tunnel to cluster \"{cluster}\" open on port {port}, and kubectl pointed at it!\n\
+                When you're done, run:\n\n\
+                kaws cluster tunnel {cluster} --close",
+                cluster = cluster,
+                port = port,
+            ),
+            Message::WorkingDirectoriesCleaned { count } => {
+                format!("Removed {} Terraform working director(ies), cache(s), and scratch director(ies).", count)
+            }
+            Message::AmiEnaUnsupported { ref ami, ref instance_size } => format!(
+                "Instance size {} requires ENA (Elastic Network Adapter) support, which AMI {} \
+                does not have, so instances would boot without networking. Choose an AMI with ENA \
+                support or an instance size that doesn't require it.",
+                instance_size,
+                ami,
+            ),
+            Message::ApprovalKeyMismatch { ref approver, ref cluster, ref key_path } => format!(
+                "{} is not the key recorded for \"{}\" under approvers in \
+                clusters/{}/metadata.json, so no approval was written.",
+                key_path,
+                approver,
+                cluster,
+            ),
+            Message::ApprovalsMissing { approvals, ref cluster, ref operation, required } => format!(
+                "{operation} on cluster \"{cluster}\" needs {required} approval(s), but only \
+                {approvals} valid one(s) were found. Ask other operators to run\n\
+                `kaws cluster approve {cluster} {operation}` and commit the results.",
+                approvals = approvals,
+                cluster = cluster,
+                operation = operation,
+                required = required,
+            ),
+            Message::ApproverUnknown { ref approver, ref cluster } => format!(
+                "\"{}\" is not listed under approvers in clusters/{}/metadata.json.",
+                approver,
+                cluster,
+            ),
+            Message::AuditAnomaliesFound { anomalies, ref cluster } => format!(
+                "{} unusual decryptions of the secrets of cluster \"{}\" found. Find out who made \
+                each one, and if it wasn't expected, treat the secret as compromised.",
+                anomalies,
+                cluster,
+            ),
+            Message::AuditKmsKeyUnknown { ref cluster } => format!(
+                "The KMS key of cluster \"{}\" isn't recorded in its PKI manifest. Give it with \
+                --kms-key.",
+                cluster,
+            ),
+            Message::AwsDateMissing => "AWS did not report the current time".to_owned(),
+            Message::BudgetExceeded { ref cluster, estimate, ceiling } => format!(
+                "The EC2 instances of cluster \"{cluster}\" would cost an estimated ${estimate:.2} \
+                per month at their maximum sizes, over its budget of ${ceiling:.2}. Lower the \
+                maximum sizes in clusters/{cluster}/terraform.tfvars, raise max_monthly_cost in \
+                clusters/{cluster}/metadata.json, or pass --override-budget to go ahead anyway.",
+                cluster = cluster,
+                estimate = estimate,
+                ceiling = ceiling,
+            ),
+            Message::CaCertificateInvalid { ref error } => format!(
+                "The CA certificate can't be used because {}. Nothing was written.",
+                error,
+            ),
+            Message::CertificateAuthorityKeyIdMismatch {
+                ref authority_key_id,
+                ref ca_subject_key_id,
+            } => format!(
+                "The new certificate's authority key identifier ({}) does not match the CA's \
+                subject key identifier ({}). It was not written.",
+                authority_key_id,
+                ca_subject_key_id,
+            ),
+            Message::CertificateAuthorityKeyIdMissing => {
+                "The CA certificate has no subject key identifier, so certificates it issues can't \
+                identify it. Generate a new CA with the \"ca\" subject of `kaws cluster generate-pki`."
+                    .to_owned()
+            }
+            Message::CertificateAuthorityMissing { ref path } => format!(
+                "There is no CA certificate at {}. Generate the cluster's PKI with \
+                `kaws cluster generate-pki` first.",
+                path,
+            ),
+            Message::CertificateExpiryInvalid { ref error, ref name, ref value } => format!(
+                "The expiry \"{}\" recorded for certificate {} in the cluster's metadata or the \
+                repository's certificate-expiry.json is not a valid duration: {}.",
+                value,
+                name,
+                error,
+            ),
+            Message::CertificateNotInManifest { ref cluster, ref serial_number } => format!(
+                "No certificate with serial number {} issued by a CA with a revocation list is \
+                recorded in the PKI manifest of cluster \"{}\". Give the CA that issued it with \
+                --ca.",
+                serial_number,
+                cluster,
+            ),
+            Message::CertificateSerialNumberReused { ref serial_number } => format!(
+                "The new certificate has the same serial number ({}) as the one it replaces. \
+                It was not written.",
+                serial_number,
+            ),
+            Message::CertificatesExpiring { ref cluster, count, days } => format!(
+                "{} certificate(s) for cluster \"{}\" have expired or expire within {} day(s).",
+                count,
+                cluster,
+                days,
+            ),
+            Message::CertificateSigningRequestRejected { ref admin, ref reason } => format!(
+                "The certificate signing request for administrator \"{}\" was not signed because \
+                {}. Ask the administrator to run `kaws admin create` again.",
+                admin,
+                reason,
+            ),
+            Message::CertificateSigningRequestSignatureInvalid => {
+                "The certificate signing request's signature does not match its public key, so it \
+                was not signed. Ask the administrator to run `kaws admin create` again.".to_owned()
+            }
+            Message::CertificateSubjectInvalid { ref error } => format!(
+                "The certificate_subject in the cluster's metadata is invalid: {}",
+                error,
+            ),
+            Message::CfsslSigningFailed { ref error, ref url } => format!(
+                "Signing with the cfssl signer at {} failed: {}",
+                url,
+                error,
+            ),
+            Message::ChildProcessFailed { ref command } => {
+                format!("Execution of `{}` failed.", command)
+            }
+            Message::CloudProviderUnsupported { ref operation, ref provider } => format!(
+                "kaws can't {} for clusters on cloud provider \"{}\" yet. Only \"aws\" is \
+                supported so far; a cluster's provider is recorded as `cloud_provider` in \
+                clusters/CLUSTER/metadata.json.",
+                operation,
+                provider,
+            ),
+            Message::ClockAhead { seconds, ref backdate } => format!(
+                "This machine's clock is {:.1}s ahead of AWS, more than the {} certificate \
+                backdate. Certificates issued here will be rejected as not yet valid.",
+                seconds,
+                backdate,
+            ),
+            Message::ClockSkewed { seconds } => format!(
+                "This machine's clock is off from AWS by {:+.1}s. AWS rejects requests signed by a \
+                clock that is off by more than five minutes.",
+                seconds,
+            ),
+            Message::ClusterDrifted { ref cluster } => format!(
+                "Cluster \"{}\" has drifted from its Terraform configuration.",
+                cluster,
+            ),
+            Message::ClusterNameInvalid { ref cluster } => format!(
+                "\"{}\" isn't a cluster name. Cluster names are made of letters, digits, hyphens, \
+                and underscores, and start with a letter or digit.",
+                cluster,
+            ),
+            Message::ClusterSettingInvalid { ref error, ref setting } => {
+                format!("Invalid value for setting {}: {}", setting, error)
+            }
+            Message::ClusterSettingUnknown { ref setting } => format!(
+                "{} is not a setting. Settings are instance_size, kubernetes_version, \
+                masters_max_size, masters_min_size, nodes_max_size, nodes_min_size, and the fields \
+                of the cluster's metadata.json, with dots between the names of nested fields.",
+                setting,
+            ),
+            Message::ClustersDiffer { ref first, ref second, count } => format!(
+                "{} setting(s) differ between clusters {} and {}.",
+                count,
+                first,
+                second,
+            ),
+            Message::ComponentFlagManaged { ref component, ref flag } => format!(
+                "kaws sets {}'s --{} flag itself, so it can't be overridden in the cluster's \
+                metadata.",
+                component,
+                flag,
+            ),
+            Message::ComponentFlagUnknown { ref component, ref flag, ref version } => format!(
+                "{} flag --{} is not one kaws knows Kubernetes {} to accept. Remove it from the \
+                cluster's metadata, or check its spelling.",
+                component,
+                flag,
+                version,
+            ),
+            Message::ComponentFlagValueInvalid { ref component, ref flag, ref value } => format!(
+                "The value \"{}\" of {} flag --{} must not be empty or contain whitespace, quotes, \
+                backslashes, or \"#\".",
+                value,
+                component,
+                flag,
+            ),
+            Message::DependencyMissing { ref program } => format!("{} must be installed", program),
+            Message::DoctorFailed { failures } => format!("{} problem(s) found.", failures),
+            Message::EncryptedFileInvalid { ref path } => format!(
+                "{} is not a file kaws encrypted with a data key, or it has been modified since.",
+                path,
+            ),
+            Message::FeatureGateUnknown { ref gate, ref version } => format!(
+                "Feature gate {} is not one kaws knows Kubernetes {} to have. Run \
+                `kaws cluster feature-gates list CLUSTER` to see the gates it has.",
+                gate,
+                version,
+            ),
+            Message::GroupNotBound { ref group } => format!(
+                "Group \"{}\" is not bound to a cluster role. Bind it with --group-role or\n\
+                `kaws admin groups add --role`.",
+                group,
+            ),
+            Message::IamUserNotGiven { ref iam_user } => format!(
+                "IAM user \"{}\" must also be given with --iam-user.",
+                iam_user,
+            ),
+            Message::ImageBuildFailed { ref cluster, ref error } => {
+                format!("Failed to build an image for cluster \"{}\": {}", cluster, error)
+            }
+            Message::InstancePriceUnknown { ref instance_size, ref region } => format!(
+                "The AWS Price List has no on-demand Linux price for instance size {} in {}.",
+                instance_size,
+                region,
+            ),
+            Message::KeySizeUnsupported { ref algo, ref size } => format!(
+                "Key size {} is not supported for {} keys. Use 256 or 384 for ecdsa, and 2048, 3072, \
+                or 4096 for rsa.",
+                size,
+                algo,
+            ),
+            Message::KmsCaKeyUnsupported { ref key_id, ref operation } => format!(
+                "The CA's key is the KMS signing key {}, so kaws can't {}. Remove the CA from \
+                kms_ca_keys in the cluster's metadata.json to manage its key as an encrypted file.",
+                key_id,
+                operation,
+            ),
+            Message::KmsKeyUnresolved { ref key_id } => format!(
+                "KMS returned no ARN for the key {}.",
+                key_id,
+            ),
+            Message::KmsNoCiphertext => "No ciphertext was returned from KMS".to_owned(),
+            Message::KmsNoPlaintext => "No plaintext was returned from KMS".to_owned(),
+            Message::KmsRegionIsClusterRegion { ref cluster, ref region } => format!(
+                "{} is the region of cluster \"{}\" itself. Give another region to keep copies of \
+                its encrypted files in.",
+                region,
+                cluster,
+            ),
+            Message::KmsRegionNotFound { ref cluster, ref region, ref regions } => format!(
+                "Cluster \"{}\" has no copies of its encrypted files in {}. {}",
+                cluster,
+                region,
+                if regions.is_empty() {
+                    "It has none in any region.".to_owned()
+                } else {
+                    format!("It has them in {}.", regions.join(", "))
+                },
+            ),
+            Message::KmsSigningFailed { ref error, ref key_id } => format!(
+                "Signing with the KMS key {} failed: {}.",
+                key_id,
+                error,
+            ),
+            Message::KmsUnreachable { ref error, ref region } => format!(
+                "KMS in {} is unreachable, and no copy of the file in another region could be \
+                decrypted instead: {}",
+                region,
+                error,
+            ),
+            Message::MetricsPushRejected { ref error, status, ref url } => format!(
+                "The Pushgateway at {} rejected the metrics with status {}: {}",
+                url,
+                status,
+                error,
+            ),
+            Message::NodeInstanceNotFound { ref cluster, ref instance_id } => format!(
+                "Instance {} is not one of the nodes of cluster \"{}\".",
+                instance_id,
+                cluster,
+            ),
+            Message::NodeReplacementTimedOut { ref cluster, ref instance_id, minutes } => format!(
+                "No node replaced instance {} in cluster \"{}\" within {} minutes. Its \
+                certificate was revoked and it was terminated, so check the cluster's autoscaling \
+                group of nodes and the new instance's logs.",
+                instance_id,
+                cluster,
+                minutes,
+            ),
+            Message::PassphraseIncorrect { ref path } => {
+                format!("The passphrase for {} is incorrect.", path)
+            }
+            Message::PasswordEmpty => "The password must not be empty.".to_owned(),
+            Message::PasswordMismatch => "The passwords didn't match.".to_owned(),
+            Message::Pkcs11CaKeyUnsupported { ref operation, ref uri } => format!(
+                "The CA's key is on the PKCS #11 token at {}, so kaws can't {}. Remove the CA \
+                from pkcs11_ca_keys in the cluster's metadata.json to manage its key with KMS.",
+                uri,
+                operation,
+            ),
+            Message::Pkcs11SigningFailed { ref error, ref uri } => format!(
+                "Signing with the PKCS #11 key {} failed: {}.",
+                uri,
+                error,
+            ),
+            Message::PkiAlreadyExists { ref cluster, ref paths } => format!(
+                "Cluster \"{}\" already has PKI that this would replace: {}. Replacing a CA or key \
+                breaks every certificate or token issued with it, so use `kaws cluster rotate-pki` \
+                or `kaws cluster rotate-service-account-key` instead, or rerun with --force to \
+                replace it anyway.",
+                cluster,
+                paths.join(", "),
+            ),
+            Message::PkiProfileInvalid { ref error, ref path, ref profile } => match *profile {
+                Some(ref profile) => format!(
+                    "The \"{}\" signing profile in {} is invalid: {}",
+                    profile,
+                    path,
+                    error,
+                ),
+                None => format!("The signing profiles in {} are invalid: {}", path, error),
+            },
+            Message::PkiReplacementDeclined { ref cluster } => format!(
+                "The PKI of cluster \"{}\" was left as it was.",
+                cluster,
+            ),
+            Message::PkiRotationOutOfOrder { ref cluster, ref phase, ref next_phase } => format!(
+                "The PKI of cluster \"{}\" is not ready for the \"{}\" phase of rotation. Run the \
+                \"{}\" phase next.",
+                cluster,
+                phase,
+                next_phase,
+            ),
+            Message::PkiVerificationFailed { ref cluster, failures } => format!(
+                "{} checks of the PKI of cluster \"{}\" failed. Fix the files listed above \
+                before applying the cluster.",
+                failures,
+                cluster,
+            ),
+            Message::PresetInvalid { ref error, ref path } => {
+                format!("The preset in {} is invalid: {}", path, error)
+            }
+            Message::PresetOptionMissing { ref option, ref preset } => format!(
+                "The preset \"{}\" doesn't set --{}, so it must be given on the command line.",
+                preset,
+                option,
+            ),
+            Message::PresetUnknown { ref preset, ref presets } => format!(
+                "There is no preset named \"{}\". The presets are: {}.",
+                preset,
+                presets.join(", "),
+            ),
+            Message::RepositoryNotFound { ref path } => format!(
+                "\"{}\" is not a kaws repository. A repository has the clusters and terraform \
+                directories `kaws init` creates.",
+                path,
+            ),
+            Message::S3BucketNotVersioned { ref bucket } => format!(
+                "S3 bucket \"{}\" doesn't have versioning enabled, so overwritten files would be \
+                lost. Enable it with `aws s3api put-bucket-versioning --bucket {} \
+                --versioning-configuration Status=Enabled`.",
+                bucket,
+                bucket,
+            ),
+            Message::S3ObjectNotFound { ref path } => {
+                format!("S3 has no object at \"{}\".", path)
+            }
+            Message::SecretNotFound { ref cluster, ref name, ref names } => {
+                if names.is_empty() {
+                    format!(
+                        "Cluster \"{}\" has no secret named \"{}\", or any other secrets yet.",
+                        cluster,
+                        name,
+                    )
+                } else {
+                    format!(
+                        "Cluster \"{}\" has no secret named \"{}\". Its secrets are: {}.",
+                        cluster,
+                        name,
+                        names.join(", "),
+                    )
+                }
+            }
+            Message::SecretSinkInvalid { ref error, ref role } => format!(
+                "A secret sink for the cluster's {} in its metadata is invalid: {}.",
+                role,
+                error,
+            ),
+            Message::SecretStoreNotEnabled { ref cluster } => format!(
+                "Cluster \"{}\" doesn't keep its encrypted files in a secret store. Run `kaws \
+                cluster secret-store enable` first.",
+                cluster,
+            ),
+            Message::SecretStorePutFailed { ref error, ref failed, ref updated } => format!(
+                "Failed to write {} to the cluster's secret store: {}. Nothing was written to the \
+                cluster's directory, {}. Run the command again to write every file to both.",
+                failed.join(", "),
+                error,
+                if updated.is_empty() {
+                    "and the store has none of the new files".to_owned()
+                } else {
+                    format!(
+                        "but the store already has the new {}, which no longer match the files \
+                        there",
+                        updated.join(", "),
+                    )
+                },
+            ),
+            Message::SecretsManagerPendingVersionInvalid { ref name } => format!(
+                "Secrets Manager didn't return the version of secret {} that kaws staged as \
+                AWSPENDING, so it wasn't made AWSCURRENT, and servers still fetch the earlier \
+                version.",
+                name,
+            ),
+            Message::SecretsManagerSecretNotFound { ref name } => {
+                format!("Secrets Manager has no secret named \"{}\".", name)
+            }
+            Message::ShortLivedCertificateExpiryInvalid { max_days, ref value } => format!(
+                "The short_lived_certificates duration \"{}\" in the cluster's metadata must be a \
+                duration of at most {} days, e.g. \"3d\".",
+                value,
+                max_days,
+            ),
+            Message::SnapshotNotFound { ref cluster, ref timestamp, ref timestamps } => {
+                if timestamps.is_empty() {
+                    format!(
+                        "Cluster \"{}\" has no snapshot from {}, or any others yet. Snapshots are \
+                        taken after each successful `kaws cluster apply`.",
+                        cluster,
+                        timestamp,
+                    )
+                } else {
+                    format!(
+                        "Cluster \"{}\" has no snapshot from {}. Its snapshots are from: {}",
+                        cluster,
+                        timestamp,
+                        timestamps.join(", "),
+                    )
+                }
+            }
+            Message::SpecInvalid { ref error, ref path } => {
+                format!("The cluster spec in {} is invalid: {}", path, error)
+            }
+            Message::SpecsNotReconciled { ref clusters } => format!(
+                "The specs of clusters {} failed to apply. See the output above for details.",
+                clusters.join(", "),
+            ),
+            Message::SsmParameterNotFound { ref name } => {
+                format!("SSM Parameter Store has no parameter named \"{}\".", name)
+            }
+            Message::SubnetsOverlap { ref first, ref second } => format!(
+                "Subnets {} and {} overlap. Give the cluster's subnets distinct network ranges.",
+                first,
+                second,
+            ),
+            Message::TableColumnUnknown { ref column, ref columns } => format!(
+                "The table has no column \"{}\". Its columns are: {}.",
+                column,
+                columns.join(", "),
+            ),
+            Message::TemporaryPathNotUtf8 => "Temporary path was invalid UTF-8".to_owned(),
+            Message::TerraformDataDirInvalid { ref path } => format!(
+                "{} is not a directory, so Terraform can't keep its working files there. \
+                Run `kaws clean` to remove it.",
+                path,
+            ),
+            Message::TerraformDestroyFailed { .. } => "Failed to destroy cluster!".to_owned(),
+            Message::TerraformGraphFailed { ref cluster } => {
+                format!("Failed to graph the Terraform resources of cluster \"{}\"!", cluster)
+            }
+            Message::TerraformInitFailed => "Failed to initialize Terraform!".to_owned(),
+            Message::TerraformOutputFailed { ref cluster, ref output } => {
+                format!("Failed to read output \"{}\" of cluster \"{}\"!", output, cluster)
+            }
+            Message::TerraformPlanFailed { ref cluster } => {
+                format!("Failed to plan cluster \"{}\"!", cluster)
+            }
+            Message::TfvarsUnreadable { ref cluster, ref error } => {
+                format!("Failed to read tfvars for cluster {}: {}", cluster, error)
+            }
+            Message::TlsBootstrappingCaKeyExternal { ref setting } => format!(
+                "The cluster uses TLS bootstrapping, so the controller manager on the masters signs \
+                kubelets' certificates with the k8s CA's key, but {} in the cluster's metadata.json \
+                keeps that key where the masters can't have it. Remove the k8s CA from {}, or use \
+                a cluster created without --tls-bootstrapping.",
+                setting,
+                setting,
+            ),
+            Message::WebhookRejected { status, ref url } => {
+                format!("The webhook at {} rejected the message with status {}.", url, status)
+            }
+            Message::AwsCredentialsError { ref error } |
+            Message::AwsHttpError { ref error } |
+            Message::Base64Error { ref error } |
+            Message::CloudTrailError { ref error } |
+            Message::Ec2Error { ref error } |
+            Message::IoError { ref error } |
+            Message::JsonError { ref error } |
+            Message::KmsError { ref error } |
+            Message::OpensslError { ref error } |
+            Message::PricingError { ref error } |
+            Message::RegionError { ref error } |
+            Message::S3Error { ref error } |
+            Message::SecretsManagerError { ref error } |
+            Message::SsmError { ref error } |
+            Message::TemplateError { ref error } |
+            Message::TimeError { ref error } |
+            Message::Utf8Error { ref error } |
+            Message::XmlError { ref error } => error.clone(),
+        }
+    }
+}
+
+impl Display for Message {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), FmtError> {
+        write!(f, "{}", self.text())
+    }
+}
@@ -0,0 +1,334 @@
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{ErrorKind, Write};
+
+use chrono::Duration;
+use serde::de::DeserializeOwned;
+use serde_json::{from_reader, to_string_pretty};
+
+use cfssl::CfsslSigner;
+use encryption::SecretStoreConfig;
+use error::{KawsError, KawsResult};
+use message::Message;
+use pki::{ExternalCaKey, SHORT_LIVED_MAX_DAYS, SubjectFields, is_short_lived};
+use provider::CloudProviderName;
+use secret_sinks::SecretSink;
+use template::Templates;
+use units::parse_duration;
+
+/// The Terraform outputs naming endpoints that a server certificate must be valid for, with the
+/// name of that certificate's file without ".pem".
+pub const ENDPOINT_OUTPUTS: [(&str, &str); 1] = [("kubernetes_masters_elb_dns_name", "k8s-master")];
+
+/// The file in a kaws repository with how long certificates are valid for in every cluster that
+/// doesn't record its own duration, keyed as `ClusterMetadata::certificate_expiry` is.
+pub const REPOSITORY_CERTIFICATE_EXPIRY_PATH: &str = "certificate-expiry.json";
+
+/// Settings kaws records about a cluster beyond what Terraform needs.
+///
+/// Stored as JSON at clusters/CLUSTER/metadata.json. Clusters created before the file existed
+/// are treated as having empty metadata.
+#[derive(Default, Deserialize, Serialize)]
+pub struct ClusterMetadata {
+    /// Flags added to kube-apiserver's command line, keyed by name without the leading dashes,
+    /// e.g. "max-requests-inflight". Checked against the flags the cluster's version of Kubernetes
+    /// accepts before `kaws cluster plan` and `apply` pass them to Terraform.
+    #[serde(default)]
+    pub apiserver_flags: BTreeMap<String, String>,
+    /// DNS names and IP addresses the Kubernetes API server's certificate is valid for, beyond
+    /// the ones kaws always includes.
+    #[serde(default)]
+    pub apiserver_sans: Vec<String>,
+    /// Operators who can approve operations that use a CA's private key, keyed by name, with the
+    /// SSH public key they sign approvals with.
+    #[serde(default)]
+    pub approvers: BTreeMap<String, String>,
+    /// The AWS credentials profiles commands use for the cluster when none is given.
+    #[serde(default)]
+    pub aws_profiles: AwsProfiles,
+    /// The image last built by `kaws image build` for the cluster's masters and nodes.
+    #[serde(default)]
+    pub baked_image: Option<BakedImage>,
+    /// How long each certificate is valid for, keyed by the name of its file without ".pem", e.g.
+    /// "etcd-ca" or "k8s-node", or "admin" for administrators' client certificates.
+    #[serde(default)]
+    pub certificate_expiry: BTreeMap<String, String>,
+    /// Fields added to the subjects of the certificates kaws generates for the cluster.
+    #[serde(default)]
+    pub certificate_subject: SubjectFields,
+    /// The `cfssl serve` API that signs administrators' client certificates with the k8s CA,
+    /// instead of kaws decrypting the CA's key, so the key can stay on a hardened signing host.
+    #[serde(default)]
+    pub cfssl_signer: Option<CfsslSigner>,
+    /// The cloud the cluster runs in: "aws", the default, or "azure" or "gcp", which kaws can't
+    /// manage yet.
+    #[serde(default)]
+    pub cloud_provider: CloudProviderName,
+    /// Flags added to kube-controller-manager's command line, keyed as `apiserver_flags` is.
+    #[serde(default)]
+    pub controller_manager_flags: BTreeMap<String, String>,
+    /// Whether every one of the cluster's encrypted files has been encrypted with an encryption
+    /// context, as it has once `kaws cluster reencrypt` has run, or from the start for clusters
+    /// created since kaws used them. Until then, a file KMS won't decrypt with its context is
+    /// decrypted again without one, by kaws and by the servers, as older files must be.
+    #[serde(default)]
+    pub encryption_contexts_required: bool,
+    /// The values of the Terraform outputs in `ENDPOINT_OUTPUTS` as of the last
+    /// `kaws cluster pki refresh-endpoints`, keyed by output name. The certificates they belong in
+    /// are issued for them.
+    #[serde(default)]
+    pub endpoints: BTreeMap<String, String>,
+    /// Kubernetes groups mapped to the cluster role they are bound to.
+    #[serde(default)]
+    pub group_roles: BTreeMap<String, String>,
+    /// Whether masters run aws-iam-authenticator so administrators can use IAM credentials.
+    #[serde(default)]
+    pub iam_authenticator: bool,
+    /// IAM users who have access to the cluster, keyed by user name.
+    #[serde(default)]
+    pub iam_users: BTreeMap<String, IamUser>,
+    /// KMS asymmetric signing keys that CAs sign with instead of a key kept in the cluster's
+    /// directory, keyed by the name of the CA, e.g. "k8s". The value is a key ID, ARN, or alias.
+    #[serde(default)]
+    pub kms_ca_keys: BTreeMap<String, String>,
+    /// The KMS key kaws last encrypted the cluster's files with, as `--kms-key` named it and as
+    /// KMS resolved it. If it was named by an alias, pointing the alias at another key moves
+    /// later encryptions to that key.
+    #[serde(default)]
+    pub kms_key: Option<KmsKey>,
+    /// KMS keys in other regions that each of the cluster's encrypted files also has a copy under,
+    /// keyed by region, so the files can be decrypted when KMS in the cluster's own region is
+    /// unreachable. Added with `kaws cluster kms-regions add`.
+    #[serde(default)]
+    pub kms_regions: BTreeMap<String, KmsKey>,
+    /// Flags added to the command line of the kubelet on masters and nodes, keyed as
+    /// `apiserver_flags` is.
+    #[serde(default)]
+    pub kubelet_flags: BTreeMap<String, String>,
+    /// The most the cluster's EC2 instances may cost per month at their maximum sizes, in US
+    /// dollars, before `kaws cluster plan` and `apply` refuse to run without `--override-budget`.
+    #[serde(default)]
+    pub max_monthly_cost: Option<f64>,
+    /// PKCS #11 URIs of CA keys kept on a hardware token instead of encrypted with KMS, keyed by
+    /// the name of the CA, e.g. "k8s". The token signs whatever those CAs issue.
+    #[serde(default)]
+    pub pkcs11_ca_keys: BTreeMap<String, String>,
+    /// How many approvers must approve a command that signs with a CA's private key, such as
+    /// `kaws admin sign` or `kaws cluster rotate-pki`, before it decrypts the key. Zero, the
+    /// default, requires no approvals.
+    #[serde(default)]
+    pub required_approvals: usize,
+    /// Why the cluster's masters and nodes need to be replaced, such as a changed feature gate,
+    /// for changes that only servers launched after `kaws cluster apply` pick up. `kaws status`
+    /// lists them until they are removed, once every master and node has been replaced.
+    #[serde(default)]
+    pub rolling_update_reasons: Vec<String>,
+    /// Where servers deliver copies of their decrypted secrets once they've decrypted them at
+    /// boot, keyed by "etcd", "masters", or "nodes", for services that read them from their own
+    /// paths, systemd credentials, or environment files.
+    #[serde(default)]
+    pub secret_sinks: BTreeMap<String, Vec<SecretSink>>,
+    /// Where the cluster's encrypted files are kept in AWS for its servers to fetch at boot,
+    /// rather than passed to them in their user data. Set with `kaws cluster secret-store enable`.
+    #[serde(default)]
+    pub secret_store: Option<SecretStoreConfig>,
+    /// How long administrators' certificates are valid for when `certificate_expiry` records no
+    /// duration for them, e.g. "3d", for clusters that reissue certificates rather than relying
+    /// on revocation lists. At most `SHORT_LIVED_MAX_DAYS`, so they're issued as short-lived.
+    #[serde(default)]
+    pub short_lived_certificates: Option<String>,
+    /// The durations in the repository's `REPOSITORY_CERTIFICATE_EXPIRY_PATH`, which aren't
+    /// written back to the cluster's metadata.
+    #[serde(skip)]
+    pub repository_certificate_expiry: BTreeMap<String, String>,
+}
+
+#[derive(Default, Deserialize, Serialize)]
+pub struct AwsProfiles {
+    /// For commands that only read from AWS: `kaws cluster plan` and `kaws cluster refresh`.
+    #[serde(default)]
+    pub read_only: Option<String>,
+    /// For commands that change AWS resources or use KMS, e.g. `kaws cluster apply`,
+    /// `kaws cluster generate-pki`, and `kaws admin sign`.
+    #[serde(default)]
+    pub read_write: Option<String>,
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct BakedImage {
+    /// The ID of the built AMI.
+    pub ami: String,
+    /// The ID of the CoreOS AMI it was built from.
+    pub base_ami: String,
+    /// When it was built, in RFC 3339 format.
+    pub built_at: String,
+    /// The version of Kubernetes whose images it holds.
+    pub kubernetes_version: String,
+}
+
+#[derive(Default, Deserialize, Serialize)]
+pub struct IamUser {
+    /// Kubernetes groups the user belongs to, via their client certificate or IAM identity.
+    #[serde(default)]
+    pub groups: Vec<String>,
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct KmsKey {
+    /// The ARN of the key `id` named when kaws last encrypted with it.
+    pub arn: String,
+    /// The key's ID, ARN, alias, e.g. "alias/kaws-production", or alias ARN, as given.
+    pub id: String,
+}
+
+impl ClusterMetadata {
+    pub fn from_file(path: &str) -> Result<Self, KawsError> {
+        let mut metadata: Self = read_if_present(path)?;
+
+        metadata.repository_certificate_expiry =
+            read_if_present(REPOSITORY_CERTIFICATE_EXPIRY_PATH)?;
+
+        Ok(metadata)
+    }
+
+    pub fn write_to_file(&self, path: &str) -> KawsResult {
+        let mut file = File::create(path)?;
+
+        writeln!(file, "{}", to_string_pretty(self)?)?;
+
+        Ok(None)
+    }
+
+    /// The recorded endpoints the certificate `name` must be valid for, e.g. "k8s-master".
+    pub fn endpoint_sans<'b>(&'b self, name: &'b str) -> impl Iterator<Item = &'b str> {
+        ENDPOINT_OUTPUTS.iter()
+            .filter(move |&&(_, certificate)| certificate == name)
+            .filter_map(move |&(output, _)| self.endpoints.get(output).map(String::as_str))
+    }
+
+    /// The key of the CA named `ca`, if it's kept on a hardware token or in KMS rather than in
+    /// the cluster's directory. A CA listed under both is taken to be on the token.
+    pub fn external_ca_key(&self, ca: &str) -> Option<ExternalCaKey<'_>> {
+        if let Some(uri) = self.pkcs11_ca_keys.get(ca) {
+            return Some(ExternalCaKey::Pkcs11(uri));
+        }
+
+        self.kms_ca_keys.get(ca).map(|key_id| ExternalCaKey::Kms(key_id))
+    }
+
+    /// Fails if the k8s CA's key is kept on a hardware token or in KMS while the cluster uses TLS
+    /// bootstrapping, whose controller manager signs kubelets' certificates with that key on the
+    /// masters, where kaws can't give it to them.
+    pub fn check_tls_bootstrapping(&self, tls_bootstrapping: bool) -> Result<(), KawsError> {
+        let setting = match self.external_ca_key("k8s") {
+            Some(ExternalCaKey::Kms(_)) if tls_bootstrapping => "kms_ca_keys",
+            Some(ExternalCaKey::Pkcs11(_)) if tls_bootstrapping => "pkcs11_ca_keys",
+            _ => return Ok(()),
+        };
+
+        Err(KawsError::new(Message::TlsBootstrappingCaKeyExternal {
+            setting: setting.to_owned(),
+        }))
+    }
+
+    /// How long the certificate `name` is valid for: `requested` if given, otherwise the duration
+    /// recorded for it in the cluster's metadata, then if it's `reissuable`, one kaws can issue
+    /// again without replacing a server, and the cluster has `short_lived_certificates`, that
+    /// duration, then the one in the repository's `REPOSITORY_CERTIFICATE_EXPIRY_PATH`, otherwise
+    /// `default`.
+    pub fn certificate_expiry_for(
+        &self,
+        name: &str,
+        requested: Option<&str>,
+        default: &str,
+        reissuable: bool,
+    ) -> Result<Duration, KawsError> {
+        let recorded = requested
+            .or_else(|| self.certificate_expiry.get(name).map(String::as_str));
+
+        if let (None, Some(value)) = (recorded, self.short_lived_certificates.as_deref()) {
+            if reissuable {
+                return match parse_duration(value) {
+                    Ok(expiry) if is_short_lived(expiry) => Ok(expiry),
+                    _ => Err(KawsError::new(Message::ShortLivedCertificateExpiryInvalid {
+                        max_days: SHORT_LIVED_MAX_DAYS,
+                        value: value.to_owned(),
+                    })),
+                };
+            }
+        }
+
+        let value = recorded
+            .or_else(|| self.repository_certificate_expiry.get(name).map(String::as_str))
+            .unwrap_or(default);
+
+        parse_duration(value).map_err(|error| KawsError::new(Message::CertificateExpiryInvalid {
+            error,
+            name: name.to_owned(),
+            value: value.to_owned(),
+        }))
+    }
+
+    /// The subject fields for new certificates: those given with `--subject-fields`, which clap
+    /// has validated, and the rest from the cluster's metadata.
+    pub fn subject_fields(&self, given: Option<&str>) -> Result<SubjectFields, KawsError> {
+        self.certificate_subject.validate().map_err(|error| {
+            KawsError::new(Message::CertificateSubjectInvalid { error })
+        })?;
+
+        let given = given.map_or_else(SubjectFields::default, |value| {
+            SubjectFields::parse(value).expect("clap should have validated subject-fields")
+        });
+
+        Ok(given.or(&self.certificate_subject))
+    }
+
+    /// Checks that each group is bound to a cluster role, so that certificates are only issued for
+    /// groups `rbac.yml` knows about. Kubernetes's own "system:" groups, such as `system:masters`,
+    /// are bound by Kubernetes itself and always allowed.
+    pub fn ensure_groups_bound<S: AsRef<str>>(&self, groups: &[S]) -> Result<(), KawsError> {
+        for group in groups {
+            let group = group.as_ref();
+
+            if !group.starts_with("system:") && !self.group_roles.contains_key(group) {
+                return Err(KawsError::new(Message::GroupNotBound {
+                    group: group.to_owned(),
+                }));
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn groups_for(&self, iam_user: &str) -> Option<&Vec<String>> {
+        self.iam_users.get(iam_user).map(|iam_user| &iam_user.groups)
+    }
+
+    /// The aws-iam-authenticator server configuration, mapping each IAM user to a Kubernetes
+    /// user of the same name in the groups recorded for them.
+    pub fn iam_authenticator_config(
+        &self,
+        templates: &Templates,
+        cluster: &str,
+        aws_account_id: &str,
+    ) -> Result<String, KawsError> {
+        templates.render("iam-authenticator-config.yaml", &json!({
+            "aws_account_id": aws_account_id,
+            "cluster": cluster,
+            "iam_users": self.iam_users,
+        }))
+    }
+
+    pub fn rbac_manifest(&self, templates: &Templates) -> Result<String, KawsError> {
+        templates.render("rbac.yml", self)
+    }
+}
+
+// The JSON in the file at `path`, or the default value if there's no such file.
+fn read_if_present<T: DeserializeOwned + Default>(path: &str) -> Result<T, KawsError> {
+    match File::open(path) {
+        Ok(file) => Ok(from_reader(file)?),
+        Err(ref error) if error.kind() == ErrorKind::NotFound => Ok(T::default()),
+        Err(error) => Err(error.into()),
+    }
+}
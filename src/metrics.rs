@@ -0,0 +1,142 @@
+use std::sync::{Mutex, OnceLock};
+use std::time::Duration;
+
+use chrono::Utc;
+use rusoto_core::{DispatchSignedRequest, HttpClient, Region};
+use rusoto_core::signature::SignedRequest;
+
+use aws::block_on;
+use error::KawsError;
+use message::Message;
+
+static SAMPLES: OnceLock<Mutex<Vec<Sample>>> = OnceLock::new();
+
+struct Sample {
+    help: &'static str,
+    labels: Vec<(String, String)>,
+    name: &'static str,
+    value: f64,
+}
+
+/// Records a gauge about the current command, to be pushed when it finishes.
+///
+/// Commands record whatever is worth monitoring without knowing whether `--pushgateway` was given;
+/// when it wasn't, the samples are simply dropped.
+pub fn record(name: &'static str, help: &'static str, labels: &[(&str, &str)], value: f64) {
+    samples().lock().expect("metrics lock should not be poisoned").push(Sample {
+        help,
+        labels: labels.iter()
+            .map(|&(label, value)| (label.to_owned(), value.to_owned()))
+            .collect(),
+        name,
+        value,
+    });
+}
+
+/// Pushes the recorded metrics, along with the command's duration and outcome, to the Prometheus
+/// Pushgateway at `url`.
+///
+/// The metrics are grouped by command and cluster, and replace everything previously pushed to
+/// that group, so a certificate that has been removed stops being reported.
+pub fn push(
+    url: &str,
+    command: &str,
+    cluster: Option<&str>,
+    duration: Duration,
+    success: bool,
+) -> Result<(), KawsError> {
+    record(
+        "kaws_command_duration_seconds",
+        "How long the command took to run.",
+        &[],
+        duration.as_secs_f64(),
+    );
+    record(
+        "kaws_command_success",
+        "Whether the command succeeded (1) or failed (0).",
+        &[],
+        if success { 1.0 } else { 0.0 },
+    );
+    record(
+        "kaws_command_last_run_timestamp_seconds",
+        "When the command last finished, in seconds since the Unix epoch.",
+        &[],
+        Utc::now().timestamp() as f64,
+    );
+
+    let mut path = format!("/metrics/job/kaws/command/{}", command);
+
+    if let Some(cluster) = cluster {
+        path.push_str(&format!("/cluster/{}", cluster));
+    }
+
+    // A custom endpoint keeps its scheme, port, and any path prefix, and the request is simply
+    // never signed.
+    let region = Region::Custom {
+        name: "pushgateway".to_owned(),
+        endpoint: url.trim_end_matches('/').to_owned(),
+    };
+    let mut request = SignedRequest::new("PUT", "pushgateway", &region, &path);
+
+    request.set_content_type("text/plain; version=0.0.4".to_owned());
+    request.set_payload(Some(exposition()));
+
+    let client = HttpClient::new().expect("failed to create HTTP client with TLS");
+    let mut response = block_on(client.dispatch(request, Some(Duration::from_secs(10))))?;
+
+    if response.status.is_success() {
+        return Ok(());
+    }
+
+    let body = block_on(response.buffer())?.body;
+
+    Err(KawsError::new(Message::MetricsPushRejected {
+        error: String::from_utf8_lossy(&body).trim().to_owned(),
+        status: response.status.as_u16(),
+        url: url.to_owned(),
+    }))
+}
+
+fn samples() -> &'static Mutex<Vec<Sample>> {
+    SAMPLES.get_or_init(|| Mutex::new(vec![]))
+}
+
+// The recorded samples in Prometheus's text format, with each metric's samples kept together
+// under a single HELP and TYPE, as the format requires.
+fn exposition() -> String {
+    let samples = samples().lock().expect("metrics lock should not be poisoned");
+    let mut names: Vec<&str> = vec![];
+    let mut text = String::new();
+
+    for sample in samples.iter() {
+        if !names.contains(&sample.name) {
+            names.push(sample.name);
+        }
+    }
+
+    for name in names {
+        let mut samples = samples.iter().filter(|sample| sample.name == name).peekable();
+
+        if let Some(first) = samples.peek() {
+            text.push_str(&format!("# HELP {} {}\n# TYPE {} gauge\n", name, first.help, name));
+        }
+
+        for sample in samples {
+            let labels = sample.labels.iter()
+                .map(|(label, value)| format!("{}=\"{}\"", label, escape_label_value(value)))
+                .collect::<Vec<_>>();
+
+            if labels.is_empty() {
+                text.push_str(&format!("{} {}\n", name, sample.value));
+            } else {
+                text.push_str(&format!("{}{{{}}} {}\n", name, labels.join(","), sample.value));
+            }
+        }
+    }
+
+    text
+}
+
+fn escape_label_value(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
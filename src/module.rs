@@ -0,0 +1,89 @@
+use std::fs::{create_dir_all, remove_dir_all, symlink_metadata, File};
+use std::io::{ErrorKind, Write};
+use std::path::Path;
+use std::str::from_utf8;
+
+use error::{KawsError, KawsResult};
+use message::Message;
+
+/// The directory kaws writes its built-in copy of the Terraform module to.
+pub const EMBEDDED_MODULE_DIR: &str = ".kaws/module";
+
+/// The module source that refers to the built-in module from a repository's terraform directory.
+pub const EMBEDDED_MODULE_SOURCE: &str = "../.kaws/module";
+
+/// The files of the Terraform module this version of kaws was built with, by path in the module.
+const EMBEDDED_MODULE_FILES: [(&str, &[u8]); 26] = [
+    ("balancers.tf", include_bytes!("../terraform/balancers.tf")),
+    ("disks.tf", include_bytes!("../terraform/disks.tf")),
+    ("dns.tf", include_bytes!("../terraform/dns.tf")),
+    ("gateways.tf", include_bytes!("../terraform/gateways.tf")),
+    ("outputs.tf", include_bytes!("../terraform/outputs.tf")),
+    ("providers.tf", include_bytes!("../terraform/providers.tf")),
+    ("routing.tf", include_bytes!("../terraform/routing.tf")),
+    ("s3.tf", include_bytes!("../terraform/s3.tf")),
+    ("security.tf", include_bytes!("../terraform/security.tf")),
+    ("servers.tf", include_bytes!("../terraform/servers.tf")),
+    ("subnets.tf", include_bytes!("../terraform/subnets.tf")),
+    ("templates.tf", include_bytes!("../terraform/templates.tf")),
+    ("templates/bastion_cloud_config.yml", include_bytes!("../terraform/templates/bastion_cloud_config.yml")),
+    ("templates/deliver_secrets.sh", include_bytes!("../terraform/templates/deliver_secrets.sh")),
+    ("templates/etcd_cloud_config.yml", include_bytes!("../terraform/templates/etcd_cloud_config.yml")),
+    ("templates/kms_decrypt.sh", include_bytes!("../terraform/templates/kms_decrypt.sh")),
+    ("templates/master_cloud_config.yml", include_bytes!("../terraform/templates/master_cloud_config.yml")),
+    ("templates/node_cloud_config.yml", include_bytes!("../terraform/templates/node_cloud_config.yml")),
+    ("templates/node_drain.sh", include_bytes!("../terraform/templates/node_drain.sh")),
+    ("templates/shutdown.sh", include_bytes!("../terraform/templates/shutdown.sh")),
+    ("templates/user_data.yml", include_bytes!("../terraform/templates/user_data.yml")),
+    ("templates/user_data_signature_check.yml", include_bytes!("../terraform/templates/user_data_signature_check.yml")),
+    ("templates/verify.sh", include_bytes!("../terraform/templates/verify.sh")),
+    ("terraform.tf", include_bytes!("../terraform/terraform.tf")),
+    ("variables.tf", include_bytes!("../terraform/variables.tf")),
+    ("vpcs.tf", include_bytes!("../terraform/vpcs.tf")),
+];
+
+/// The built-in module's Terraform configuration files, by path in the module, for commands that
+/// read the module rather than run it.
+pub fn embedded_configuration() -> impl Iterator<Item = (&'static str, &'static str)> {
+    EMBEDDED_MODULE_FILES.iter()
+        .filter(|&&(path, _)| path.ends_with(".tf"))
+        .map(|&(path, contents)| {
+            (path, from_utf8(contents).expect("the built-in module should be UTF-8"))
+        })
+}
+
+/// Writes the built-in Terraform module to `EMBEDDED_MODULE_DIR`, replacing any copy written by
+/// another version of kaws, so repositories using it need no network access to fetch the module.
+pub fn write_embedded_module() -> KawsResult {
+    // Every directory on the way must be a real directory, so nothing outside the repository is
+    // removed or written.
+    for path in &[".kaws", EMBEDDED_MODULE_DIR] {
+        match symlink_metadata(path) {
+            Ok(metadata) => if !metadata.is_dir() {
+                return Err(KawsError::new(Message::TerraformDataDirInvalid {
+                    path: path.to_string(),
+                }));
+            },
+            Err(ref error) if error.kind() == ErrorKind::NotFound => {}
+            Err(error) => return Err(error.into()),
+        }
+    }
+
+    log_wrap!("Writing the built-in Terraform module", {
+        if Path::new(EMBEDDED_MODULE_DIR).exists() {
+            remove_dir_all(EMBEDDED_MODULE_DIR)?;
+        }
+
+        for &(path, contents) in EMBEDDED_MODULE_FILES.iter() {
+            let path = format!("{}/{}", EMBEDDED_MODULE_DIR, path);
+
+            if let Some(parent) = Path::new(&path).parent() {
+                create_dir_all(parent)?;
+            }
+
+            File::create(&path)?.write_all(contents)?;
+        }
+    });
+
+    Ok(None)
+}
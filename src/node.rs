@@ -0,0 +1,183 @@
+use std::collections::BTreeSet;
+use std::process::Command;
+use std::thread::sleep;
+use std::time::Duration as StdDuration;
+
+use chrono::{Duration, Utc};
+use openssl::x509::X509;
+use rusoto_core::Region;
+use rusoto_core::credential::ChainProvider;
+use rustc_serialize::base64::FromBase64;
+
+use aws::{ec2_query, xml_element_text};
+use error::{KawsError, KawsResult};
+use message::Message;
+use pki::Certificate;
+use process::execute_child_process;
+
+/// How often the cluster's nodes are listed while waiting for a replacement to join.
+const POLL_INTERVAL: StdDuration = StdDuration::from_secs(15);
+
+/// A Kubernetes node of a cluster, by the EC2 instance it runs on.
+pub struct Node<'a> {
+    cluster: &'a str,
+    instance_id: &'a str,
+    /// The node's name in Kubernetes: the instance's private IP address, which kubelets register
+    /// with.
+    pub name: String,
+    provider: &'a ChainProvider,
+    region: Region,
+}
+
+impl<'a> Node<'a> {
+    /// Looks up the node on the instance `instance_id`, which must belong to the cluster's
+    /// autoscaling group of nodes, so that no master, etcd member, or other cluster's instance is
+    /// recycled by mistake.
+    pub fn find(
+        cluster: &'a str,
+        instance_id: &'a str,
+        provider: &'a ChainProvider,
+        region: &str,
+    ) -> Result<Self, KawsError> {
+        let region: Region = region.parse()?;
+        let autoscaling_group = format!("kaws-k8s-nodes-{}", cluster);
+        let instances = ec2_query(provider, &region, "DescribeInstances", &[
+            ("InstanceId.1", instance_id),
+            ("Filter.1.Name", "tag:aws:autoscaling:groupName"),
+            ("Filter.1.Value.1", &autoscaling_group),
+        ])?;
+        let name = xml_element_text(&instances, "privateIpAddress")?
+            .filter(|name| !name.is_empty())
+            .ok_or_else(|| KawsError::new(Message::NodeInstanceNotFound {
+                cluster: cluster.to_owned(),
+                instance_id: instance_id.to_owned(),
+            }))?;
+
+        Ok(Node {
+            cluster,
+            instance_id,
+            name,
+            provider,
+            region,
+        })
+    }
+
+    /// The decimal serial numbers of the client certificates the Kubernetes certificates API
+    /// issued to the node, for clusters that use TLS bootstrapping.
+    pub fn client_certificate_serial_numbers(&self) -> Result<Vec<String>, KawsError> {
+        let output = self.kubectl_output(&[
+            "get",
+            "certificatesigningrequests",
+            "--output=jsonpath={range .items[*]}{.status.certificate}{\"\\n\"}{end}",
+        ])?;
+        let common_name = format!("CN=system:node:{}", self.name);
+        let mut serial_numbers = vec![];
+
+        for encoded in output.lines().filter(|line| !line.is_empty()) {
+            let info = Certificate::from(X509::from_pem(&encoded.from_base64()?)?).info()?;
+
+            if info.subject.split(", ").any(|field| field == common_name) {
+                serial_numbers.push(info.serial_number);
+            }
+        }
+
+        Ok(serial_numbers)
+    }
+
+    /// Stops scheduling pods on the node and removes it from the cluster, so that its pods are
+    /// recreated on other nodes.
+    pub fn remove(&self) -> KawsResult {
+        let context = self.context();
+
+        execute_child_process("kubectl", &[context.as_str(), "cordon", &self.name])?;
+        execute_child_process("kubectl", &[
+            context.as_str(),
+            "delete",
+            "node",
+            &self.name,
+            "--ignore-not-found",
+        ])
+    }
+
+    /// Terminates the node's instance, which the autoscaling group replaces with a new one.
+    pub fn terminate(&self) -> KawsResult {
+        ec2_query(self.provider, &self.region, "TerminateInstances", &[
+            ("InstanceId.1", self.instance_id),
+        ])?;
+
+        Ok(None)
+    }
+
+    /// The names of the cluster's nodes, whether they're ready or not.
+    pub fn node_names(&self) -> Result<BTreeSet<String>, KawsError> {
+        Ok(self.node_readiness()?.into_iter().map(|(name, _)| name).collect())
+    }
+
+    /// Waits for a node other than the ones in `previous`, the names of every node in the
+    /// cluster before the replacement began, to be ready, returning its name. A node that was
+    /// already there but not ready is never taken for the replacement when it recovers.
+    pub fn wait_for_replacement(&self, previous: &BTreeSet<String>, timeout: Duration)
+    -> Result<String, KawsError> {
+        let started_at = Utc::now();
+
+        loop {
+            let replacement = self.node_readiness()?
+                .into_iter()
+                .find(|&(ref name, ready)| ready && !previous.contains(name));
+
+            if let Some((name, _)) = replacement {
+                return Ok(name);
+            }
+
+            if Utc::now() - started_at > timeout {
+                return Err(KawsError::new(Message::NodeReplacementTimedOut {
+                    cluster: self.cluster.to_owned(),
+                    instance_id: self.instance_id.to_owned(),
+                    minutes: timeout.num_minutes(),
+                }));
+            }
+
+            sleep(POLL_INTERVAL);
+        }
+    }
+
+    // Private
+
+    // The kubectl context `kaws admin install` configures for the cluster.
+    fn context(&self) -> String {
+        format!("--context=kaws-{}", self.cluster)
+    }
+
+    // The name of each of the cluster's nodes, and whether it's ready.
+    fn node_readiness(&self) -> Result<Vec<(String, bool)>, KawsError> {
+        let output = self.kubectl_output(&[
+            "get",
+            "nodes",
+            "--output=jsonpath={range .items[*]}{.metadata.name} \
+            {.status.conditions[?(@.type==\"Ready\")].status}{\"\\n\"}{end}",
+        ])?;
+
+        Ok(output.lines().filter_map(|line| {
+            let mut fields = line.split_whitespace();
+
+            fields.next().map(|name| (name.to_owned(), fields.next() == Some("True")))
+        }).collect())
+    }
+
+    fn kubectl_output(&self, args: &[&str]) -> Result<String, KawsError> {
+        let context = self.context();
+        let output = Command::new("kubectl").arg(&context).args(args).output()?;
+
+        if !output.status.success() {
+            return Err(KawsError::with_std_streams(
+                Message::ChildProcessFailed {
+                    command: format!("kubectl {} {}", context, args.join(" ")),
+                },
+                String::from_utf8_lossy(&output.stdout).to_string(),
+                String::from_utf8_lossy(&output.stderr).to_string(),
+            ));
+        }
+
+        Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+    }
+}
@@ -0,0 +1,76 @@
+use std::env::{current_dir, set_current_dir};
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+use error::KawsError;
+use message::Message;
+
+/// The directory kaws was run from, if it changed to the repository's root from somewhere else.
+static INVOCATION_DIR: OnceLock<PathBuf> = OnceLock::new();
+
+/// Changes to the root of the kaws repository, so the clusters/CLUSTER and terraform paths every
+/// command uses resolve in it wherever kaws is run from, e.g. a CI job's workspace.
+///
+/// The root is `repo`, the `--repo` flag, if it's given. Otherwise it's the current directory or
+/// the nearest one above it that is a repository, the way Git finds its own. Outside of any
+/// repository the current directory is kept, for the commands that don't need one.
+pub fn enter_repository(repo: Option<&str>) -> Result<(), KawsError> {
+    let invocation_dir = current_dir()?;
+    let root = match repo {
+        Some(repo) => {
+            let root = invocation_dir.join(repo);
+
+            if !is_repository(&root) {
+                return Err(KawsError::new(Message::RepositoryNotFound {
+                    path: repo.to_owned(),
+                }));
+            }
+
+            root
+        }
+        None => match invocation_dir.ancestors().find(|dir| is_repository(dir)) {
+            Some(root) => root.to_path_buf(),
+            None => return Ok(()),
+        },
+    };
+
+    if root != invocation_dir {
+        debug!("Using the repository at {}", root.display());
+
+        set_current_dir(&root)?;
+
+        let _ = INVOCATION_DIR.set(invocation_dir);
+    }
+
+    Ok(())
+}
+
+/// A path given on the command line, such as a file to read or write, resolved against the
+/// directory kaws was run from rather than the repository's root.
+pub fn from_invocation(path: &str) -> String {
+    match INVOCATION_DIR.get() {
+        Some(dir) if Path::new(path).is_relative() => dir.join(path).to_string_lossy().into_owned(),
+        _ => path.to_owned(),
+    }
+}
+
+/// Fails unless `cluster` is a name a cluster could have. Every command checks the cluster it's
+/// given before building any path from it, since a name like "../clusters" would otherwise lead
+/// out of the directories kaws keeps for the cluster, and into ones it removes.
+pub fn check_cluster_name(cluster: &str) -> Result<(), KawsError> {
+    let valid = cluster.starts_with(|character: char| character.is_ascii_alphanumeric())
+        && cluster.chars().all(|character| {
+            character.is_ascii_alphanumeric() || character == '-' || character == '_'
+        });
+
+    if valid {
+        Ok(())
+    } else {
+        Err(KawsError::new(Message::ClusterNameInvalid { cluster: cluster.to_owned() }))
+    }
+}
+
+// Whether `dir` has the clusters and terraform directories `kaws init` creates.
+fn is_repository(dir: &Path) -> bool {
+    dir.join("clusters").is_dir() && dir.join("terraform").is_dir()
+}
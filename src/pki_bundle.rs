@@ -0,0 +1,153 @@
+use std::fs::{copy, create_dir_all};
+use std::path::Path;
+
+use clap::ArgMatches;
+
+use error::{KawsError, KawsResult};
+use files::entries;
+use message::Message;
+use paths::from_invocation;
+use pki::Certificate;
+use process::execute_child_process;
+use render::print_rows;
+use runtime::temp_dir;
+
+/// Files that are public despite not being certificates.
+const PUBLIC_FILES: [&str; 2] = ["pki-manifest.json", "pki-profiles.json"];
+
+// A file in the cluster's directory that's safe to publish.
+struct PublicFile {
+    name: String,
+    // For a file of certificates, the certificates parsed from it, to be written in its place.
+    certificates: Option<Certificate>,
+    // The fingerprint of the file's first certificate.
+    fingerprint: Option<String>,
+}
+
+pub struct PkiBundle<'a> {
+    cluster: &'a str,
+    output: Option<String>,
+}
+
+impl<'a> PkiBundle<'a> {
+    pub fn new(matches: &'a ArgMatches) -> Self {
+        PkiBundle {
+            cluster: matches.value_of("cluster").expect("clap should have required cluster"),
+            output: matches.value_of("output").map(from_invocation),
+        }
+    }
+
+    /// Copies the cluster's certificates, public keys, and PKI manifest into a directory or
+    /// gzipped tarball that can be handed to anyone, leaving out every private key.
+    pub fn export(&self) -> KawsResult {
+        let name = format!("{}-public-pki", self.cluster);
+        let path = match self.output {
+            Some(ref path) => path.to_owned(),
+            None => from_invocation(&format!("{}.tar.gz", name)),
+        };
+        let files = self.public_files()?;
+
+        if files.is_empty() {
+            return Err(KawsError::new(Message::CertificateAuthorityMissing {
+                path: format!("{}/k8s-ca.pem", self.dir()),
+            }));
+        }
+
+        let mut rows = vec![vec!["FILE".to_owned(), "SHA-256 FINGERPRINT".to_owned()]];
+
+        for file in &files {
+            rows.push(vec![
+                file.name.clone(),
+                file.fingerprint.clone().unwrap_or_else(|| "-".to_owned()),
+            ]);
+        }
+
+        print_rows(&rows)?;
+
+        if path.ends_with(".tar.gz") || path.ends_with(".tgz") {
+            let tempdir = temp_dir()?;
+
+            self.copy_files(&files, &tempdir.path().join(&name))?;
+
+            log_wrap!(format!("Writing {}", path), {
+                execute_child_process("tar", &[
+                    "-czf",
+                    &path,
+                    "-C",
+                    &tempdir.path().to_string_lossy(),
+                    &name,
+                ])?;
+            });
+        } else {
+            self.copy_files(&files, Path::new(&path))?;
+        }
+
+        Ok(Some(Message::PkiBundleExported {
+            cluster: self.cluster.to_owned(),
+            files: files.len(),
+            path,
+        }))
+    }
+
+    // Private
+
+    // The files in the cluster's directory that are safe to publish. Certificate files are never
+    // copied as they are, but written again from the certificates parsed from them, so a private
+    // key saved in the same file, or under the wrong name, can't slip through.
+    fn public_files(&self) -> Result<Vec<PublicFile>, KawsError> {
+        let mut files = vec![];
+
+        for entry in entries(&self.dir())? {
+            if entry.name.ends_with(".pem") {
+                let certificates = match Certificate::from_file_if_present(&entry.path)? {
+                    Some(file) => file.unbundle().unwrap_or_default(),
+                    None => continue,
+                };
+                let fingerprint = match certificates.first().map(Certificate::fingerprint) {
+                    Some(Ok(fingerprint)) => fingerprint,
+                    _ => continue,
+                };
+
+                let bundle = Certificate::bundle(&certificates.iter().collect::<Vec<_>>());
+
+                files.push(PublicFile {
+                    name: entry.name,
+                    certificates: Some(bundle),
+                    fingerprint: Some(fingerprint),
+                });
+            } else if (entry.name.ends_with(".pub") ||
+                PUBLIC_FILES.contains(&entry.name.as_str())) && !entry.is_empty()? {
+                files.push(PublicFile {
+                    name: entry.name,
+                    certificates: None,
+                    fingerprint: None,
+                });
+            }
+        }
+
+        Ok(files)
+    }
+
+    fn copy_files(&self, files: &[PublicFile], destination: &Path) -> Result<(), KawsError> {
+        create_dir_all(destination)?;
+
+        for file in files {
+            let path = destination.join(&file.name);
+
+            match file.certificates {
+                Some(ref certificates) => {
+                    certificates.write_to_file(&path.to_string_lossy())?;
+                }
+                None => {
+                    copy(format!("{}/{}", self.dir(), file.name), path)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn dir(&self) -> String {
+        format!("clusters/{}", self.cluster)
+    }
+}
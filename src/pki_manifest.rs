@@ -0,0 +1,217 @@
+use std::collections::BTreeSet;
+use std::fs::File;
+use std::io::{ErrorKind, Write};
+use std::path::Path;
+use std::sync::Mutex;
+
+use chrono::{DateTime, Utc};
+use serde_json::{from_reader, to_string_pretty};
+
+use error::{KawsError, KawsResult};
+use files::entries;
+use pki::{Certificate, Provenance};
+
+// Held while the manifest is read, added to, and written back, so certificates written
+// concurrently, as by `kaws cluster generate-pki all`, are all recorded.
+static RECORDING: Mutex<()> = Mutex::new(());
+
+/// Every certificate kaws has written for a cluster.
+///
+/// Stored as JSON at clusters/CLUSTER/pki-manifest.json. Entries are only ever added, so
+/// certificates that have since been replaced stay recorded, e.g. for revoking them. Clusters
+/// whose PKI predates the file are recorded from the certificates on disk the first time it is
+/// read.
+#[derive(Default, Deserialize, Serialize)]
+pub struct PkiManifest {
+    #[serde(default)]
+    pub certificates: Vec<ManifestEntry>,
+}
+
+#[derive(Clone, Deserialize, Serialize)]
+pub struct ManifestEntry {
+    /// The file the certificate was written to, e.g. "clusters/dev/k8s-master.pem".
+    pub path: String,
+    pub subject: String,
+    pub issuer: String,
+    /// In decimal, as `kaws cluster pki revoke` takes it.
+    pub serial_number: String,
+    /// The SHA-256 fingerprint of the certificate, as colon-separated hex.
+    pub fingerprint: String,
+    #[serde(default)]
+    pub subject_alt_names: Vec<String>,
+    pub not_before: DateTime<Utc>,
+    pub not_after: DateTime<Utc>,
+    /// The KMS key the certificate's private key was encrypted with, if kaws generated the key.
+    #[serde(default)]
+    pub kms_key_id: Option<String>,
+    pub recorded_at: DateTime<Utc>,
+    /// For short-lived certificates, which can't be revoked, when the certificate is due to be
+    /// reissued: two thirds of the way through its validity, leaving time to roll it out.
+    #[serde(default)]
+    pub renew_after: Option<DateTime<Utc>>,
+    /// Which run of kaws issued the certificate, from the certificate itself.
+    #[serde(default)]
+    pub provenance: Option<Provenance>,
+}
+
+impl PkiManifest {
+    pub fn from_file(cluster: &str) -> Result<Self, KawsError> {
+        Self::from_file_recording(cluster, None)
+    }
+
+    /// Adds the certificates in `cert`, which was just written to `path`, unless they are already
+    /// recorded there.
+    pub fn record(cluster: &str, path: &str, cert: &Certificate, kms_key_id: Option<&str>)
+    -> KawsResult {
+        let _recording = RECORDING.lock().unwrap_or_else(|error| error.into_inner());
+        let mut manifest = Self::from_file_recording(cluster, Some(path))?;
+
+        for cert in cert.unbundle()? {
+            let entry = ManifestEntry::new(path, &cert, kms_key_id)?;
+
+            if manifest.find(path, &entry.fingerprint).is_none() {
+                manifest.certificates.push(entry);
+            }
+        }
+
+        manifest.write_to_file(cluster)
+    }
+
+    /// The entries for the certificates still in the files they were written to, in path order
+    /// and then in the order of the certificates in each file. Certificates kaws didn't write,
+    /// e.g. ones copied into the cluster's directory by hand, are left out.
+    pub fn current(&self) -> Result<Vec<ManifestEntry>, KawsError> {
+        let paths: BTreeSet<&str> = self.certificates.iter().map(|entry| entry.path.as_str())
+            .collect();
+        let mut current = vec![];
+
+        for path in paths {
+            let bundle = match Certificate::from_file_if_present(path)? {
+                Some(bundle) => bundle,
+                None => continue,
+            };
+
+            for cert in bundle.unbundle()? {
+                if let Some(entry) = self.find(path, &cert.fingerprint()?) {
+                    current.push(entry.clone());
+                }
+            }
+        }
+
+        Ok(current)
+    }
+
+    /// The most recently recorded certificate with the serial number `serial_number`.
+    pub fn find_serial_number(&self, serial_number: &str) -> Option<&ManifestEntry> {
+        self.certificates.iter().rev().find(|entry| entry.serial_number == serial_number)
+    }
+
+    pub fn write_to_file(&self, cluster: &str) -> KawsResult {
+        let mut file = File::create(manifest_path(cluster))?;
+
+        writeln!(file, "{}", to_string_pretty(self)?)?;
+
+        Ok(None)
+    }
+
+    // Private
+
+    // Reads the manifest, or records the certificates on disk for a cluster that has none yet,
+    // apart from those at `skipped_path`, which the caller is about to record itself.
+    fn from_file_recording(cluster: &str, skipped_path: Option<&str>) -> Result<Self, KawsError> {
+        match File::open(manifest_path(cluster)) {
+            Ok(file) => Ok(from_reader(file)?),
+            Err(ref error) if error.kind() == ErrorKind::NotFound => {
+                Self::from_certificates(cluster, skipped_path)
+            }
+            Err(error) => Err(error.into()),
+        }
+    }
+
+    // Where the keys of certificates recorded this way were encrypted isn't known.
+    fn from_certificates(cluster: &str, skipped_path: Option<&str>) -> Result<Self, KawsError> {
+        let mut manifest = Self::default();
+
+        for path in certificate_paths(cluster)? {
+            if Some(path.as_str()) == skipped_path {
+                continue;
+            }
+
+            for cert in Certificate::from_file(&path)?.unbundle()? {
+                manifest.certificates.push(ManifestEntry::new(&path, &cert, None)?);
+            }
+        }
+
+        Ok(manifest)
+    }
+
+    fn find(&self, path: &str, fingerprint: &str) -> Option<&ManifestEntry> {
+        self.certificates.iter().rev().find(|entry| {
+            entry.path == path && entry.fingerprint == fingerprint
+        })
+    }
+}
+
+impl ManifestEntry {
+    pub fn new(path: &str, cert: &Certificate, kms_key_id: Option<&str>)
+    -> Result<Self, KawsError> {
+        let info = cert.info()?;
+        let renew_after = if info.no_revocation_available {
+            Some(info.not_before + (info.not_after - info.not_before) * 2 / 3)
+        } else {
+            None
+        };
+
+        Ok(ManifestEntry {
+            path: path.to_owned(),
+            subject: info.subject,
+            issuer: info.issuer,
+            serial_number: info.serial_number,
+            fingerprint: cert.fingerprint()?,
+            subject_alt_names: info.subject_alt_names,
+            not_before: info.not_before,
+            not_after: info.not_after,
+            kms_key_id: kms_key_id.map(String::from),
+            recorded_at: Utc::now(),
+            renew_after,
+            provenance: info.provenance,
+        })
+    }
+
+    /// Whether the certificate is due for renewal by `threshold`: once it's expired or expires by
+    /// then, or for a short-lived certificate, once it's due to be reissued, since one always
+    /// expires within days.
+    pub fn due_by(&self, threshold: DateTime<Utc>) -> bool {
+        match self.renew_after {
+            Some(renew_after) => renew_after < Utc::now(),
+            None => self.not_after < threshold,
+        }
+    }
+
+    /// The name of the file the certificate was written to, e.g. "k8s-master.pem".
+    pub fn file_name(&self) -> &str {
+        Path::new(&self.path).file_name().and_then(|name| name.to_str()).unwrap_or(&self.path)
+    }
+}
+
+fn manifest_path(cluster: &str) -> String {
+    format!("clusters/{}/pki-manifest.json", cluster)
+}
+
+// Paths of the non-empty public certificates for a cluster, skipping keys and CSRs.
+fn certificate_paths(cluster: &str) -> Result<Vec<String>, KawsError> {
+    let mut paths = vec![];
+
+    for entry in entries(&format!("clusters/{}", cluster))? {
+        if !entry.name.ends_with(".pem") ||
+            entry.name.ends_with("-key.pem") ||
+            entry.name.ends_with("-csr.pem") ||
+            entry.is_empty()? {
+            continue;
+        }
+
+        paths.push(entry.path);
+    }
+
+    Ok(paths)
+}
@@ -0,0 +1,179 @@
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::ErrorKind;
+
+use serde_json::from_reader;
+
+use error::KawsError;
+use message::Message;
+use pki::SigningProfile;
+use tfvars::Tfvars;
+
+/// Extended key usages for certificates only clients present.
+const CLIENT: &[&str] = &["client auth"];
+
+/// Extended key usages for certificates only servers present.
+const SERVER: &[&str] = &["server auth"];
+
+/// Extended key usages for certificates presented both by servers and as clients, such as etcd's,
+/// whose members connect to each other and to themselves with them.
+const SERVER_AND_CLIENT: &[&str] = &["server auth", "client auth"];
+
+/// How each kind of certificate kaws issues is signed, by the name its expiry is configured under.
+const CERTIFICATE_PROFILES: [CertificateProfile; 11] = [
+    CertificateProfile::new("admin", "client", CLIENT, CLIENT),
+    CertificateProfile::new("etcd-apiserver-client", "client", CLIENT, CLIENT),
+    CertificateProfile::new("etcd-client", "client", CLIENT, CLIENT),
+    CertificateProfile::new("etcd-peer", "peer", SERVER_AND_CLIENT, SERVER_AND_CLIENT),
+    CertificateProfile::new("etcd-server", "server", SERVER_AND_CLIENT, SERVER_AND_CLIENT),
+    CertificateProfile::new("front-proxy-client", "client", CLIENT, CLIENT),
+    CertificateProfile::new("ingress", "server", SERVER, SERVER),
+    CertificateProfile::new("k8s-controller-manager", "client", CLIENT, CLIENT),
+    CertificateProfile::new("k8s-master", "server", SERVER_AND_CLIENT, SERVER),
+    // Kubelets also serve their API with the nodes' certificate, unless they request their own
+    // with TLS bootstrapping, when only "client auth" is required.
+    CertificateProfile::new("k8s-node", "client", SERVER_AND_CLIENT, SERVER_AND_CLIENT),
+    CertificateProfile::new("k8s-scheduler", "client", CLIENT, CLIENT),
+];
+
+// The signing profile a kind of certificate uses, and its extended key usages.
+struct CertificateProfile {
+    certificate: &'static str,
+    profile: &'static str,
+    // The extended key usages it gets unless its profile lists some.
+    extended_key_usages: &'static [&'static str],
+    // The extended key usages the components using it need, which its profile can't leave out.
+    required_extended_key_usages: &'static [&'static str],
+}
+
+/// The signing profiles for a cluster's certificates.
+///
+/// Stored at clusters/CLUSTER/pki-profiles.json in the format of cfssl's signing configuration, so
+/// the same file can be given to cfssl with `-config`. Certificates are signed with the profile
+/// named after their kind, e.g. "etcd-server", then the "server", "client", or "peer" profile, and
+/// then the default profile, whichever the file has first. Without the file, every certificate
+/// gets the expiry kaws has always issued, and the extended key usages its kind needs.
+#[derive(Default, Deserialize)]
+pub struct PkiProfiles {
+    #[serde(default)]
+    signing: SigningConfig,
+}
+
+#[derive(Default, Deserialize)]
+struct SigningConfig {
+    default: Option<SigningProfile>,
+    #[serde(default)]
+    profiles: BTreeMap<String, SigningProfile>,
+}
+
+impl PkiProfiles {
+    pub fn from_file(cluster: &str) -> Result<Self, KawsError> {
+        let path = profiles_path(cluster);
+        let invalid = |profile: Option<&str>, error: String| {
+            KawsError::new(Message::PkiProfileInvalid {
+                error,
+                path: path.clone(),
+                profile: profile.map(String::from),
+            })
+        };
+
+        let profiles: Self = match File::open(&path) {
+            Ok(file) => from_reader(file).map_err(|error| invalid(None, error.to_string()))?,
+            Err(ref error) if error.kind() == ErrorKind::NotFound => return Ok(Self::default()),
+            Err(error) => return Err(error.into()),
+        };
+
+        if let Some(ref default) = profiles.signing.default {
+            default.validate().map_err(|error| invalid(Some("default"), error))?;
+        }
+
+        for (name, profile) in &profiles.signing.profiles {
+            profile.validate().map_err(|error| invalid(Some(name), error))?;
+        }
+
+        let tls_bootstrapping = Tfvars::from_file(&tfvars_path(cluster))?
+            .get("kaws_tls_bootstrapping") == Some("true");
+
+        // A certificate missing a usage its component needs would be rejected once it's in use.
+        for certificate in &CERTIFICATE_PROFILES {
+            let (name, profile) = match profiles.profile(certificate) {
+                Some((name, profile)) if profile.has_extended_key_usages() => (name, profile),
+                _ => continue,
+            };
+            let required = if certificate.certificate == "k8s-node" && tls_bootstrapping {
+                CLIENT
+            } else {
+                certificate.required_extended_key_usages
+            };
+
+            for &usage in required {
+                if !profile.usages.iter().any(|existing| existing == usage) {
+                    return Err(invalid(Some(name), format!(
+                        "\"{}\" certificates need the \"{}\" usage, which a profile named \
+                        \"{}\" can give them if others shouldn't have it",
+                        certificate.certificate,
+                        usage,
+                        certificate.certificate,
+                    )));
+                }
+            }
+        }
+
+        Ok(profiles)
+    }
+
+    /// The profile for the certificates whose expiry is configured as `name`, e.g. "etcd-server",
+    /// with the extended key usages they need if it lists none.
+    pub fn for_certificate(&self, name: &str) -> SigningProfile {
+        let certificate = CERTIFICATE_PROFILES.iter()
+            .find(|certificate| certificate.certificate == name)
+            .expect("every certificate should have a profile");
+        let mut profile = self.profile(certificate)
+            .map(|(_, profile)| profile.clone())
+            .unwrap_or_default();
+
+        if !profile.has_extended_key_usages() {
+            profile.usages.extend(certificate.extended_key_usages.iter().map(|&usage| {
+                usage.to_owned()
+            }));
+        }
+
+        profile
+    }
+
+    // Private
+
+    // The name and contents of the first profile in the file for `certificate`, if any.
+    fn profile(&self, certificate: &CertificateProfile) -> Option<(&str, &SigningProfile)> {
+        let profiles = &self.signing.profiles;
+
+        [certificate.certificate, certificate.profile].iter()
+            .find_map(|&name| profiles.get_key_value(name))
+            .map(|(name, profile)| (name.as_str(), profile))
+            .or_else(|| self.signing.default.as_ref().map(|profile| ("default", profile)))
+    }
+}
+
+impl CertificateProfile {
+    const fn new(
+        certificate: &'static str,
+        profile: &'static str,
+        extended_key_usages: &'static [&'static str],
+        required_extended_key_usages: &'static [&'static str],
+    ) -> Self {
+        CertificateProfile {
+            certificate,
+            profile,
+            extended_key_usages,
+            required_extended_key_usages,
+        }
+    }
+}
+
+fn profiles_path(cluster: &str) -> String {
+    format!("clusters/{}/pki-profiles.json", cluster)
+}
+
+fn tfvars_path(cluster: &str) -> String {
+    format!("clusters/{}/terraform.tfvars", cluster)
+}
@@ -0,0 +1,103 @@
+use chrono::{Duration, Utc};
+use clap::ArgMatches;
+
+use error::{KawsError, KawsResult};
+use fleet::ClusterStatus;
+use message::Message;
+use metrics::record;
+use pki_manifest::PkiManifest;
+use render::Table;
+
+pub struct PkiStatus<'a> {
+    cluster: &'a str,
+    expiry_warning_days: i64,
+}
+
+impl<'a> PkiStatus<'a> {
+    pub fn new(matches: &'a ArgMatches) -> Self {
+        PkiStatus {
+            cluster: matches.value_of("cluster").expect("clap should have required cluster"),
+            expiry_warning_days: matches
+                .value_of("expiry-warning-days")
+                .unwrap_or("30")
+                .parse()
+                .expect("clap should have validated expiry-warning-days"),
+        }
+    }
+
+    pub fn report(&self) -> KawsResult {
+        let now = Utc::now();
+        let warning_threshold = now + Duration::days(self.expiry_warning_days);
+        let mut expiring = 0;
+
+        // Serial numbers, SANs, and provenance make the table too wide to read on most terminals.
+        let mut table = Table::new(&[
+            "CERTIFICATE",
+            "SERIAL",
+            "SUBJECT",
+            "SANS",
+            "ISSUER",
+            "EXPIRES",
+            "KAWS VERSION",
+            "GENERATION",
+        ]).wide_only(&["SERIAL", "SANS", "KAWS VERSION", "GENERATION"]);
+
+        // Collecting the cluster's status caches its certificates, so this only reads the manifest
+        // and certificates again if they have changed, or to report why they couldn't be read.
+        let status = ClusterStatus::collect(self.cluster.to_owned());
+        let certificates = if status.errors.is_empty() {
+            status.certificates
+        } else {
+            PkiManifest::from_file(self.cluster)?.current()?
+        };
+
+        // A CA file holds more than one certificate while `kaws cluster rotate-pki` runs.
+        for entry in &certificates {
+            record(
+                "kaws_certificate_expiry_days",
+                "Days until the certificate expires, negative once it has.",
+                &[("certificate", entry.file_name()), ("serial_number", &entry.serial_number)],
+                (entry.not_after - now).num_seconds() as f64 / 86400.0,
+            );
+
+            if entry.due_by(warning_threshold) {
+                expiring += 1;
+            }
+
+            let days = if entry.not_after < now {
+                "expired".to_owned()
+            } else {
+                format!("{} days", (entry.not_after - now).num_days())
+            };
+
+            table.push(vec![
+                entry.file_name().to_owned(),
+                entry.serial_number.clone(),
+                entry.subject.clone(),
+                if entry.subject_alt_names.is_empty() {
+                    "-".to_owned()
+                } else {
+                    entry.subject_alt_names.join(", ")
+                },
+                entry.issuer.clone(),
+                format!("{} ({})", entry.not_after.format("%Y-%m-%d"), days),
+                entry.provenance.as_ref()
+                    .map_or("-".to_owned(), |provenance| provenance.kaws_version.clone()),
+                entry.provenance.as_ref()
+                    .map_or("-".to_owned(), |provenance| provenance.generation_id.clone()),
+            ]);
+        }
+
+        table.print()?;
+
+        if expiring == 0 {
+            Ok(None)
+        } else {
+            Err(KawsError::new(Message::CertificatesExpiring {
+                cluster: self.cluster.to_owned(),
+                count: expiring,
+                days: self.expiry_warning_days,
+            }))
+        }
+    }
+}
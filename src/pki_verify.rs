@@ -0,0 +1,367 @@
+use std::collections::BTreeSet;
+use std::net::IpAddr;
+
+use ansi_term::Colour::{Green, Red};
+use clap::ArgMatches;
+
+use aws::{Access, cluster_credentials_provider};
+use cluster::ETCD_MEMBERS;
+use encryption::Encryptor;
+use error::{KawsError, KawsResult};
+use files::non_empty_names;
+use message::Message;
+use metadata::ClusterMetadata;
+use paths::from_invocation;
+use pki::{Certificate, CertificateSigningRequest, PrivateKey, PublicKey};
+use provider::cloud_provider;
+use tfvars::Tfvars;
+
+/// The names the API server's certificate is always issued for, besides kubernetes.DOMAIN.
+const APISERVER_SANS: [&str; 6] = [
+    "DNS:kubernetes",
+    "DNS:kubernetes.default",
+    "DNS:kubernetes.default.svc",
+    "DNS:kubernetes.default.svc.cluster.local",
+    "IP:10.3.0.1",
+    "IP:127.0.0.1",
+];
+
+// The outcome of one check: what's wrong, if anything.
+type Check = Result<Option<String>, KawsError>;
+
+pub struct PkiVerifier<'a> {
+    aws_credentials_path: Option<String>,
+    aws_credentials_profile: Option<&'a str>,
+    cluster: &'a str,
+}
+
+impl<'a> PkiVerifier<'a> {
+    pub fn new(matches: &'a ArgMatches) -> Self {
+        PkiVerifier {
+            aws_credentials_path: matches.value_of("aws-credentials-path").map(from_invocation),
+            aws_credentials_profile: matches.value_of("aws-credentials-profile"),
+            cluster: matches.value_of("cluster").expect("clap should have required cluster"),
+        }
+    }
+
+    /// Checks that the cluster's PKI files agree with each other: each certificate chains to the
+    /// CA that should have issued it, each private key matches its certificate or public key, each
+    /// server certificate has the names it's used under, and each encrypted file decrypts. A CA
+    /// whose key is in KMS must have been issued for that key.
+    pub fn verify(&self) -> KawsResult {
+        let names = self.names()?;
+        let metadata = ClusterMetadata::from_file(&self.path("metadata.json"))?;
+        let tfvars = Tfvars::from_file(&self.path("terraform.tfvars"))?;
+        let mut encryptor = self.encryptor(&metadata, &tfvars);
+        let mut checks: Vec<(String, Check)> = vec![];
+
+        for name in &names {
+            let stem = match name.strip_suffix(".pem") {
+                Some(stem) => stem,
+                None => continue,
+            };
+
+            if let Some(ca) = issuer(stem, &names) {
+                checks.push((
+                    format!("{} chains to {}-ca.pem", name, ca),
+                    self.check_chain(name, ca),
+                ));
+            }
+
+            // A certificate that can't be read fails its other checks.
+            let provenance = Certificate::from_file(&self.path(name)).ok()
+                .and_then(|cert| cert.info().ok())
+                .and_then(|info| info.provenance);
+
+            if let Some(provenance) = provenance {
+                checks.push((
+                    format!(
+                        "{} was issued for this cluster, by kaws {} in generation {}",
+                        name,
+                        provenance.kaws_version,
+                        provenance.generation_id,
+                    ),
+                    Ok(self.check_provenance_cluster(&provenance.cluster)),
+                ));
+            }
+
+            if let Some(sans) = self.expected_sans(stem, &metadata, &tfvars) {
+                checks.push((format!("{} has the expected names", name), self.check_sans(name, &sans)));
+            }
+
+            // Administrators' keys are the only ones kept outside KMS. One with a passphrase can't
+            // be checked without it.
+            if names.contains(&format!("{}-key.pem", stem)) && !self.key_has_passphrase(stem) {
+                checks.push((
+                    format!("{}-key.pem matches {}", stem, name),
+                    self.check_unencrypted_key(stem),
+                ));
+            }
+        }
+
+        for name in &names {
+            let stem = match name.strip_suffix("-encrypted.base64") {
+                Some(stem) => stem,
+                None => continue,
+            };
+
+            let check = match encryptor {
+                Ok(ref mut encryptor) => match stem.strip_suffix("-key") {
+                    Some(key_stem) => self.check_encrypted_key(encryptor, name, key_stem, &names),
+                    None => encryptor.decrypt_file(&self.path(name)).map(|_| None),
+                },
+                Err(ref error) => Ok(Some(error.to_string())),
+            };
+
+            checks.push((format!("{} decrypts", name), check));
+        }
+
+        // A CA whose key is in KMS has no encrypted key to check, so its certificate is checked
+        // against the key's public key instead.
+        for (ca, key_id) in &metadata.kms_ca_keys {
+            let name = format!("{}-ca.pem", ca);
+
+            if !names.contains(&name) {
+                continue;
+            }
+
+            let check = match encryptor {
+                Ok(ref encryptor) => self.check_kms_key(encryptor, &name, key_id),
+                Err(ref error) => Ok(Some(error.to_string())),
+            };
+
+            checks.push((format!("{} matches KMS key {}", name, key_id), check));
+        }
+
+        let mut failures = 0;
+
+        for (description, check) in checks.iter() {
+            let problem = match *check {
+                Ok(ref problem) => problem.clone(),
+                Err(ref error) => Some(error.to_string()),
+            };
+
+            match problem {
+                Some(problem) => {
+                    failures += 1;
+
+                    println!("{} {}: {}", Red.paint("FAIL"), description, problem);
+                }
+                None => println!("{} {}", Green.paint("ok  "), description),
+            }
+        }
+
+        if failures > 0 {
+            return Err(KawsError::new(Message::PkiVerificationFailed {
+                cluster: self.cluster.to_owned(),
+                failures,
+            }));
+        }
+
+        Ok(Some(Message::PkiVerified {
+            checks: checks.len(),
+            cluster: self.cluster.to_owned(),
+        }))
+    }
+
+    // Private
+
+    // The names of the files in the cluster's directory, leaving out the empty placeholders
+    // `kaws cluster init` creates for PKI that hasn't been generated yet.
+    fn names(&self) -> Result<BTreeSet<String>, KawsError> {
+        Ok(non_empty_names(&format!("clusters/{}", self.cluster))?.into_iter().collect())
+    }
+
+    fn path(&self, name: &str) -> String {
+        format!("clusters/{}/{}", self.cluster, name)
+    }
+
+    // A failure to set up decryption fails each check that needs it, rather than the whole
+    // verification, so the checks that don't need KMS still run.
+    fn encryptor(&self, metadata: &ClusterMetadata, tfvars: &Tfvars)
+    -> Result<Encryptor<'static>, KawsError> {
+        let provider = cloud_provider(metadata.cloud_provider, cluster_credentials_provider(
+            self.cluster,
+            Access::ReadWrite,
+            self.aws_credentials_path.as_deref(),
+            self.aws_credentials_profile,
+        )?);
+        let region = tfvars.get("kaws_region").ok_or_else(|| {
+            KawsError::new(Message::TfvarsUnreadable {
+                cluster: self.cluster.to_owned(),
+                error: "kaws_region is not set".to_owned(),
+            })
+        })?;
+
+        Ok(provider.encryptor(region, None)?
+            .with_contexts_required(metadata.encryption_contexts_required))
+    }
+
+    // The subject alternative names the certificate in `stem`.pem must have, if it's a server
+    // certificate kaws issues.
+    fn expected_sans(&self, stem: &str, metadata: &ClusterMetadata, tfvars: &Tfvars)
+    -> Option<Vec<String>> {
+        if stem == "k8s-master" {
+            let mut sans: Vec<String> = APISERVER_SANS.iter().map(|san| san.to_string()).collect();
+
+            if let Some(domain) = tfvars.get("kaws_domain") {
+                sans.push(format!("DNS:kubernetes.{}", domain));
+            }
+
+            sans.extend(metadata.apiserver_sans.iter().map(|san| match san.parse::<IpAddr>() {
+                Ok(_) => format!("IP:{}", san),
+                Err(_) => format!("DNS:{}", san),
+            }));
+            sans.extend(metadata.endpoint_sans(stem).map(|san| format!("DNS:{}", san)));
+
+            return Some(sans);
+        }
+
+        for prefix in &["etcd-server", "etcd-peer"] {
+            if stem == *prefix {
+                return Some(ETCD_MEMBERS.iter().map(|&(_, ip)| format!("IP:{}", ip)).collect());
+            }
+
+            for &(member, ip) in ETCD_MEMBERS.iter() {
+                if stem == format!("{}-{}", prefix, member) {
+                    return Some(vec![format!("IP:{}", ip)]);
+                }
+            }
+        }
+
+        None
+    }
+
+    fn check_chain(&self, name: &str, ca: &str) -> Check {
+        let ca_path = self.path(&format!("{}-ca.pem", ca));
+        let trusted = match Certificate::from_file_if_present(&ca_path)? {
+            Some(trusted) => trusted,
+            None => return Ok(Some(format!("{} is missing", ca_path))),
+        };
+
+        Certificate::from_file(&self.path(name))?.verify_chain(&trusted)
+    }
+
+    // The CA's certificate was issued for a different KMS key than the one it's signed with.
+    fn check_kms_key(&self, encryptor: &Encryptor, name: &str, key_id: &str) -> Check {
+        let cert = Certificate::from_file(&self.path(name))?;
+
+        if cert.matches_kms_key(&encryptor.signer(key_id))? {
+            Ok(None)
+        } else {
+            Ok(Some("it wasn't issued for the KMS key, so kaws can't sign with it".to_owned()))
+        }
+    }
+
+    // A certificate kaws issued for another cluster was copied in from that cluster's directory.
+    fn check_provenance_cluster(&self, cluster: &str) -> Option<String> {
+        if cluster == self.cluster {
+            None
+        } else {
+            Some(format!("it was issued for cluster {}", cluster))
+        }
+    }
+
+    fn check_sans(&self, name: &str, expected: &[String]) -> Check {
+        let info = Certificate::from_file(&self.path(name))?.info()?;
+        let missing: Vec<&str> = expected.iter()
+            .filter(|san| !info.subject_alt_names.contains(san))
+            .map(String::as_str)
+            .collect();
+
+        if missing.is_empty() {
+            Ok(None)
+        } else {
+            Ok(Some(format!("missing {}", missing.join(", "))))
+        }
+    }
+
+    fn key_has_passphrase(&self, stem: &str) -> bool {
+        PrivateKey::from_file_unencrypted(&self.path(&format!("{}-key.pem", stem)))
+            .is_ok_and(|key| key.is_encrypted())
+    }
+
+    fn check_unencrypted_key(&self, stem: &str) -> Check {
+        let key = PrivateKey::from_file_unencrypted(&self.path(&format!("{}-key.pem", stem)))?;
+        let cert = Certificate::from_file(&self.path(&format!("{}.pem", stem)))?;
+
+        Ok(mismatch(key.matches(&cert)?, &format!("{}.pem", stem)))
+    }
+
+    // Decrypts the key and checks it against its certificate, or for the service account key,
+    // its public key. During a service account key rotation the public key file holds both keys,
+    // and the private key only has to match one of them. A CA's key is checked against its newest
+    // certificate, the first in its bundle.
+    fn check_encrypted_key(
+        &self,
+        encryptor: &mut Encryptor,
+        name: &str,
+        key_stem: &str,
+        names: &BTreeSet<String>,
+    ) -> Check {
+        let key = PrivateKey::from_file(encryptor, &self.path(name))?;
+        let cert_name = format!("{}.pem", key_stem);
+        let public_key_name = format!("{}.pub", key_stem);
+
+        // The key of a CA waiting to be signed goes with its request rather than a certificate.
+        if key_stem.ends_with("-csr") && names.contains(&cert_name) {
+            let csr = CertificateSigningRequest::from_file(&self.path(&cert_name))?;
+            let matched = csr.public_key()?.as_bytes() == key.public_key()?.as_bytes();
+
+            Ok(mismatch(matched, &cert_name))
+        } else if names.contains(&cert_name) {
+            let cert = Certificate::from_file(&self.path(&cert_name))?;
+
+            Ok(mismatch(key.matches(&cert)?, &cert_name))
+        } else if names.contains(&public_key_name) {
+            let public_key = key.public_key()?;
+            let matched = PublicKey::from_file(&self.path(&public_key_name))?.unbundle()?.iter()
+                .any(|candidate| candidate.as_bytes() == public_key.as_bytes());
+
+            Ok(mismatch(matched, &public_key_name))
+        } else {
+            Ok(None)
+        }
+    }
+}
+
+/// The certificates among `names`, the files in a cluster's directory, that `ca` should have
+/// issued, and that stop being trusted when it's replaced.
+pub fn dependents<'n>(ca: &str, names: &'n BTreeSet<String>) -> Vec<&'n str> {
+    names.iter()
+        .map(String::as_str)
+        .filter(|name| {
+            name.strip_suffix(".pem").is_some_and(|stem| issuer(stem, names) == Some(ca))
+        })
+        .collect()
+}
+
+/// The CA that should have issued the certificate in `stem`.pem, if it's one kaws issues.
+/// Administrators' certificates are recognized by the signing requests `kaws admin create` leaves
+/// beside them.
+pub fn issuer(stem: &str, names: &BTreeSet<String>) -> Option<&'static str> {
+    if stem.ends_with("-ca") || stem.ends_with("-csr") {
+        None
+    } else if stem == "etcd-client" || stem == "etcd-apiserver-client" ||
+        stem.starts_with("etcd-server") {
+        Some("etcd")
+    } else if stem.starts_with("etcd-peer") {
+        Some("etcd-peer")
+    } else if stem == "front-proxy-client" {
+        Some("front-proxy")
+    } else if stem == "k8s-master" || stem == "k8s-node" || stem == "k8s-controller-manager" ||
+        stem == "k8s-scheduler" || stem == "ingress" ||
+        names.contains(&format!("{}-csr.pem", stem)) {
+        Some("k8s")
+    } else {
+        None
+    }
+}
+
+fn mismatch(matched: bool, name: &str) -> Option<String> {
+    if matched {
+        None
+    } else {
+        Some(format!("the key doesn't match {}", name))
+    }
+}
@@ -0,0 +1,113 @@
+use std::collections::BTreeSet;
+use std::fs::File;
+use std::io::ErrorKind;
+
+use serde_json::{from_reader, from_str};
+
+use error::KawsError;
+use files::entries_if_present;
+use message::Message;
+use units::parse_volume_size;
+
+/// The presets built into kaws, by name.
+const BUILTIN_PRESETS: [(&str, &str); 3] = [
+    ("dev", include_str!("../presets/dev.json")),
+    ("ha-prod", include_str!("../presets/ha-prod.json")),
+    ("small-prod", include_str!("../presets/small-prod.json")),
+];
+
+/// The values `--node-placement` takes.
+pub const NODE_PLACEMENTS: [&str; 3] = ["cluster", "partition", "spread"];
+
+/// The directory in a kaws repository whose presets take precedence over the built-in ones.
+pub const REPOSITORY_PRESET_DIR: &str = "presets";
+
+/// Defaults for the options of `kaws cluster init`, chosen with `--preset NAME`.
+///
+/// A preset named NAME is read from NAME.json in the repository's presets directory if that file
+/// exists, and otherwise from the copy built into kaws. Options given on the command line take
+/// precedence over the preset's, and switches the preset turns on can't be turned off again.
+#[derive(Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Preset {
+    pub bastion: bool,
+    pub etcd_member_certificates: bool,
+    pub etcd_volume_size: Option<String>,
+    pub iam_authenticator: bool,
+    pub instance_size: Option<String>,
+    pub key_algo: Option<String>,
+    pub key_size: Option<u32>,
+    pub masters_max_size: Option<u32>,
+    pub masters_min_size: Option<u32>,
+    pub max_monthly_cost: Option<f64>,
+    pub node_placement: Option<String>,
+    pub nodes_max_size: Option<u32>,
+    pub nodes_min_size: Option<u32>,
+    pub scrub_credentials: bool,
+    pub tls_bootstrapping: bool,
+    pub wireguard: bool,
+}
+
+impl Preset {
+    pub fn named(name: &str) -> Result<Self, KawsError> {
+        let path = format!("{}/{}.json", REPOSITORY_PRESET_DIR, name);
+
+        let preset: Self = match File::open(&path) {
+            Ok(file) => {
+                debug!("Using preset {} from {}", name, REPOSITORY_PRESET_DIR);
+
+                from_reader(file).map_err(|error| invalid(&path, &error.to_string()))?
+            }
+            Err(ref error) if error.kind() == ErrorKind::NotFound => {
+                match BUILTIN_PRESETS.iter().find(|&&(builtin, _)| builtin == name) {
+                    Some(&(_, source)) => from_str(source)?,
+                    None => return Err(KawsError::new(Message::PresetUnknown {
+                        preset: name.to_owned(),
+                        presets: names()?,
+                    })),
+                }
+            }
+            Err(error) => return Err(error.into()),
+        };
+
+        if let Some(ref node_placement) = preset.node_placement {
+            if !NODE_PLACEMENTS.contains(&node_placement.as_str()) {
+                return Err(invalid(&path, &format!(
+                    "node_placement must be one of {}, not \"{}\"",
+                    NODE_PLACEMENTS.join(", "),
+                    node_placement,
+                )));
+            }
+        }
+
+        if let Some(ref etcd_volume_size) = preset.etcd_volume_size {
+            if let Err(error) = parse_volume_size(etcd_volume_size) {
+                return Err(invalid(&path, &format!("etcd_volume_size {}", error)));
+            }
+        }
+
+        Ok(preset)
+    }
+}
+
+fn invalid(path: &str, error: &str) -> KawsError {
+    KawsError::new(Message::PresetInvalid {
+        error: error.to_owned(),
+        path: path.to_owned(),
+    })
+}
+
+// The names of the built-in presets and those in the repository, in order.
+fn names() -> Result<Vec<String>, KawsError> {
+    let mut names: BTreeSet<String> = BUILTIN_PRESETS.iter()
+        .map(|&(name, _)| name.to_owned())
+        .collect();
+
+    for entry in entries_if_present(REPOSITORY_PRESET_DIR)? {
+        if let Some(name) = entry.name.strip_suffix(".json") {
+            names.insert(name.to_owned());
+        }
+    }
+
+    Ok(names.into_iter().collect())
+}
@@ -3,6 +3,7 @@ use std::fmt::Display;
 use std::process::Command;
 
 use error::{KawsError, KawsResult};
+use message::Message;
 
 pub fn execute_child_process<S: AsRef<OsStr> + Display>(program: S, args: &[S]) -> KawsResult {
     let mut command = Command::new(&program);
@@ -12,7 +13,9 @@ pub fn execute_child_process<S: AsRef<OsStr> + Display>(program: S, args: &[S])
     if !output.status.success() {
         return Err(
             KawsError::with_std_streams(
-                format!("Execution of `{:?}` failed.", command),
+                Message::ChildProcessFailed {
+                    command: format!("{:?}", command),
+                },
                 String::from_utf8_lossy(&output.stdout).to_string(),
                 String::from_utf8_lossy(&output.stderr).to_string(),
             )
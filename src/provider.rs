@@ -0,0 +1,158 @@
+use rusoto_core::credential::ChainProvider;
+
+use aws::{credentials, s3_put_object};
+use cost::monthly_instance_cost;
+use encryption::Encryptor;
+use error::KawsError;
+use image::ensure_ami_supports_instance_size;
+use message::Message;
+
+/// The cloud a cluster runs in, as recorded in its metadata.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CloudProviderName {
+    #[default]
+    Aws,
+    Azure,
+    Gcp,
+}
+
+/// What kaws does with a cluster's cloud directly, rather than through its Terraform module.
+///
+/// Only AWS is implemented. The others exist so a cluster can record which cloud it is meant for,
+/// and every operation on them fails with an error saying it isn't supported yet.
+pub trait CloudProvider {
+    /// The name of the template that renders the cluster's Terraform variables.
+    fn tfvars_template(&self) -> Result<&'static str, KawsError>;
+
+    /// Checks that servers of `instance_size` booted from `image` will work.
+    fn ensure_image_supports_instance_size(&self, region: &str, image: &str, instance_size: &str)
+    -> Result<(), KawsError>;
+
+    /// The monthly cost of running `servers`, pairs of an instance size and a number of instances,
+    /// in US dollars.
+    fn monthly_instance_cost(&self, region: &str, servers: &[(&str, u64)])
+    -> Result<f64, KawsError>;
+
+    /// An encryptor for secrets, using the key `key_id` to encrypt them if given.
+    fn encryptor<'a>(&self, region: &str, key_id: Option<&'a str>)
+    -> Result<Encryptor<'a>, KawsError>;
+
+    /// Stores `contents` as the object `key` in the storage bucket `bucket`.
+    fn put_object(&self, region: &str, bucket: &str, key: &str, contents: &[u8])
+    -> Result<(), KawsError>;
+
+    /// The environment variables that give Terraform the provider's credentials.
+    fn terraform_env(&self) -> Result<Vec<(&'static str, String)>, KawsError>;
+}
+
+pub struct Aws {
+    credentials_provider: ChainProvider,
+}
+
+/// A cloud kaws can record for a cluster but not yet manage.
+pub struct Unsupported {
+    name: CloudProviderName,
+}
+
+/// The provider for the cloud `name`, which uses `aws_credentials_provider` if it is AWS.
+pub fn cloud_provider(name: CloudProviderName, aws_credentials_provider: ChainProvider)
+-> Box<dyn CloudProvider> {
+    match name {
+        CloudProviderName::Aws => Box::new(Aws {
+            credentials_provider: aws_credentials_provider,
+        }),
+        name => Box::new(Unsupported { name }),
+    }
+}
+
+impl CloudProviderName {
+    /// The provider named `name`, as `kaws cluster init --cloud-provider` takes it.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "aws" => Some(CloudProviderName::Aws),
+            "azure" => Some(CloudProviderName::Azure),
+            "gcp" => Some(CloudProviderName::Gcp),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match *self {
+            CloudProviderName::Aws => "aws",
+            CloudProviderName::Azure => "azure",
+            CloudProviderName::Gcp => "gcp",
+        }
+    }
+}
+
+impl CloudProvider for Aws {
+    fn tfvars_template(&self) -> Result<&'static str, KawsError> {
+        Ok("terraform.tfvars")
+    }
+
+    fn ensure_image_supports_instance_size(&self, region: &str, image: &str, instance_size: &str)
+    -> Result<(), KawsError> {
+        ensure_ami_supports_instance_size(&self.credentials_provider, region, image, instance_size)
+    }
+
+    fn monthly_instance_cost(&self, region: &str, servers: &[(&str, u64)])
+    -> Result<f64, KawsError> {
+        monthly_instance_cost(&self.credentials_provider, region, servers)
+    }
+
+    fn encryptor<'a>(&self, region: &str, key_id: Option<&'a str>)
+    -> Result<Encryptor<'a>, KawsError> {
+        Ok(Encryptor::new(self.credentials_provider.clone(), region.parse()?, key_id))
+    }
+
+    fn put_object(&self, region: &str, bucket: &str, key: &str, contents: &[u8])
+    -> Result<(), KawsError> {
+        s3_put_object(&self.credentials_provider, &region.parse()?, bucket, key, contents, None)
+    }
+
+    fn terraform_env(&self) -> Result<Vec<(&'static str, String)>, KawsError> {
+        let credentials = credentials(&self.credentials_provider)?;
+
+        Ok(vec![
+            ("AWS_ACCESS_KEY_ID", credentials.aws_access_key_id().to_owned()),
+            ("AWS_SECRET_ACCESS_KEY", credentials.aws_secret_access_key().to_owned()),
+        ])
+    }
+}
+
+impl CloudProvider for Unsupported {
+    fn tfvars_template(&self) -> Result<&'static str, KawsError> {
+        Err(self.unsupported("generate Terraform variables"))
+    }
+
+    fn ensure_image_supports_instance_size(&self, _: &str, _: &str, _: &str)
+    -> Result<(), KawsError> {
+        Err(self.unsupported("check machine images"))
+    }
+
+    fn monthly_instance_cost(&self, _: &str, _: &[(&str, u64)]) -> Result<f64, KawsError> {
+        Err(self.unsupported("estimate costs"))
+    }
+
+    fn encryptor<'a>(&self, _: &str, _: Option<&'a str>) -> Result<Encryptor<'a>, KawsError> {
+        Err(self.unsupported("encrypt or decrypt secrets"))
+    }
+
+    fn put_object(&self, _: &str, _: &str, _: &str, _: &[u8]) -> Result<(), KawsError> {
+        Err(self.unsupported("store objects"))
+    }
+
+    fn terraform_env(&self) -> Result<Vec<(&'static str, String)>, KawsError> {
+        Err(self.unsupported("run Terraform"))
+    }
+}
+
+impl Unsupported {
+    fn unsupported(&self, operation: &str) -> KawsError {
+        KawsError::new(Message::CloudProviderUnsupported {
+            operation: operation.to_owned(),
+            provider: self.name.as_str().to_owned(),
+        })
+    }
+}
@@ -0,0 +1,321 @@
+use std::cmp::max;
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+use std::process::{Command, Output};
+use std::thread::sleep;
+use std::time::Duration;
+
+use clap::ArgMatches;
+use openssl::sha::sha256;
+use rusoto_core::{DispatchSignedRequest, HttpClient, Region};
+use rusoto_core::signature::SignedRequest;
+
+use approval::Approvals;
+use aws::block_on;
+use error::{KawsError, KawsResult};
+use files::entries;
+use message::Message;
+use metadata::ClusterMetadata;
+use paths::from_invocation;
+use process::execute_child_process;
+use units::parse_duration;
+
+/// The name each cluster's spec is stored under in its directory, without ".json", which is also
+/// the target its approvals are given for.
+const SPEC_NAME: &str = "spec";
+
+// The digest of each cluster's spec as of its last application, so unchanged specs are skipped,
+// and as of its last announcement as waiting for approval.
+#[derive(Default)]
+struct State {
+    announced: BTreeMap<String, String>,
+    applied: BTreeMap<String, String>,
+}
+
+// What happened to a spec on one pass.
+enum Outcome {
+    Applied,
+    Failed,
+    Waiting,
+}
+
+pub struct Reconciler<'a> {
+    aws_credentials_path: Option<String>,
+    aws_credentials_profile: Option<&'a str>,
+    branch: &'a str,
+    interval: Duration,
+    remote: &'a str,
+    require_approval: bool,
+    terraform_args: Option<Vec<&'a str>>,
+    watch: bool,
+    webhook_url: Option<&'a str>,
+}
+
+impl<'a> Reconciler<'a> {
+    pub fn new(matches: &'a ArgMatches) -> Self {
+        Reconciler {
+            aws_credentials_path: matches.value_of("aws-credentials-path").map(from_invocation),
+            aws_credentials_profile: matches.value_of("aws-credentials-profile"),
+            branch: matches.value_of("branch").unwrap_or("main"),
+            interval: parse_duration(matches.value_of("interval").unwrap_or("1m"))
+                .ok()
+                .and_then(|interval| interval.to_std().ok())
+                .expect("clap should have validated interval"),
+            remote: matches.value_of("remote").unwrap_or("origin"),
+            require_approval: matches.is_present("require-approval"),
+            terraform_args: matches.values_of("terraform-args").map(|values| values.collect()),
+            watch: matches.is_present("watch"),
+            webhook_url: matches.value_of("webhook-url"),
+        }
+    }
+
+    /// Applies the spec of every cluster that has one. With `--watch`, keeps pulling the branch
+    /// and applying specs as they change, committing what each application changes, and never
+    /// returns.
+    pub fn run(&self) -> KawsResult {
+        let mut state = State::default();
+
+        if !self.watch {
+            let outcomes = self.reconcile(&mut state)?;
+            let clusters = |wanted: fn(&Outcome) -> bool| -> Vec<String> {
+                outcomes.iter()
+                    .filter(|&(_, outcome)| wanted(outcome))
+                    .map(|(cluster, _)| cluster.clone())
+                    .collect()
+            };
+            let failed = clusters(|outcome| matches!(*outcome, Outcome::Failed));
+
+            if !failed.is_empty() {
+                return Err(KawsError::new(Message::SpecsNotReconciled {
+                    clusters: failed,
+                }));
+            }
+
+            return Ok(Some(Message::SpecsReconciled {
+                clusters: clusters(|outcome| matches!(*outcome, Outcome::Applied)),
+                waiting: clusters(|outcome| matches!(*outcome, Outcome::Waiting)),
+            }));
+        }
+
+        loop {
+            let result = self.pull().and_then(|_| self.reconcile(&mut state));
+
+            if let Err(error) = result {
+                self.notify(&format!("kaws reconcile failed: {}", error));
+            }
+
+            sleep(self.interval);
+        }
+    }
+
+    // Private
+
+    // Applies each spec that has changed since it was last applied, returning what happened to
+    // each, by cluster.
+    fn reconcile(&self, state: &mut State) -> Result<BTreeMap<String, Outcome>, KawsError> {
+        let mut outcomes = BTreeMap::new();
+
+        for cluster in self.clusters_with_specs()? {
+            let path = format!("clusters/{}/{}.json", cluster, SPEC_NAME);
+            let digest = digest(&path)?;
+
+            if state.applied.get(&cluster) == Some(&digest) {
+                continue;
+            }
+
+            if self.require_approval && !self.approved(&cluster, &path, &digest, state)? {
+                outcomes.insert(cluster, Outcome::Waiting);
+
+                continue;
+            }
+
+            let status = Command::new("kaws").args(self.apply_args(&path)).status()?;
+
+            // A failure isn't retried until the spec changes, so a broken spec doesn't apply
+            // half of itself over and over.
+            state.applied.insert(cluster.clone(), digest);
+
+            if status.success() {
+                self.notify(&format!("Cluster \"{}\" reconciled with its spec.", cluster));
+
+                if self.watch {
+                    self.commit(&cluster)?;
+                }
+
+                outcomes.insert(cluster, Outcome::Applied);
+            } else {
+                self.notify(&format!(
+                    "Cluster \"{}\" failed to reconcile with its spec. See the kaws reconcile \
+                    logs for details.",
+                    cluster,
+                ));
+
+                outcomes.insert(cluster, Outcome::Failed);
+            }
+        }
+
+        Ok(outcomes)
+    }
+
+    // The clusters whose directories have a spec, in order.
+    fn clusters_with_specs(&self) -> Result<Vec<String>, KawsError> {
+        let mut clusters = vec![];
+
+        for entry in entries("clusters")? {
+            if Path::new(&format!("{}/{}.json", entry.path, SPEC_NAME)).exists() {
+                clusters.push(entry.name);
+            }
+        }
+
+        Ok(clusters)
+    }
+
+    // Whether enough approvers have approved this version of the spec: at least one, or as many
+    // as the cluster requires if that's more. Until then, the plan is sent to the webhook so the
+    // approvers can see what they're approving.
+    fn approved(&self, cluster: &str, path: &str, digest: &str, state: &mut State)
+    -> Result<bool, KawsError> {
+        let metadata = ClusterMetadata::from_file(&format!("clusters/{}/metadata.json", cluster))?;
+        let approvals = Approvals::new(cluster, "apply-spec", SPEC_NAME);
+
+        match approvals.ensure_at_least(&metadata, max(metadata.required_approvals, 1)) {
+            Ok(()) => Ok(true),
+            Err(ref error) if matches!(*error.message(), Message::ApprovalsMissing { .. }) => {
+                // Approvals are checked on every pass, but each version is only announced once.
+                if state.announced.get(cluster).map(String::as_str) == Some(digest) {
+                    return Ok(false);
+                }
+
+                state.announced.insert(cluster.to_owned(), digest.to_owned());
+
+                let Output { stdout, .. } = Command::new("kaws")
+                    .args(["cluster", "apply-spec", path, "--dry-run"])
+                    .output()?;
+
+                self.notify(&format!(
+                    "Cluster \"{}\" has a new spec waiting for approval. Approve it with \
+                    `kaws cluster approve {} apply-spec {} --approver NAME`.\n\n{}",
+                    cluster,
+                    cluster,
+                    SPEC_NAME,
+                    String::from_utf8_lossy(&stdout).trim_end(),
+                ));
+
+                Ok(false)
+            }
+            Err(error) => Err(error),
+        }
+    }
+
+    fn apply_args<'b>(&'b self, path: &'b str) -> Vec<&'b str> {
+        let mut args = vec!["cluster", "apply-spec", path];
+
+        if let Some(ref path) = self.aws_credentials_path {
+            args.extend(["--aws-credentials-path", path]);
+        }
+
+        if let Some(profile) = self.aws_credentials_profile {
+            args.extend(["--aws-credentials-profile", profile]);
+        }
+
+        // Nobody is there to answer Terraform's prompt.
+        args.extend(["--", "-auto-approve"]);
+
+        if let Some(ref terraform_args) = self.terraform_args {
+            args.extend(terraform_args);
+        }
+
+        args
+    }
+
+    // Brings the checkout up to date with the branch, keeping any commits that haven't been
+    // pushed yet on top.
+    fn pull(&self) -> Result<(), KawsError> {
+        log_wrap!(format!("Pulling {} from {}", self.branch, self.remote), {
+            execute_child_process("git", &["pull", "--rebase", self.remote, self.branch])?;
+        });
+
+        Ok(())
+    }
+
+    // Commits and pushes the files applying a spec changed, such as terraform.tfvars and the
+    // Terraform state, so the repository keeps describing the cluster.
+    fn commit(&self, cluster: &str) -> Result<(), KawsError> {
+        let dir = format!("clusters/{}", cluster);
+        let status = Command::new("git").args(["status", "--porcelain", &dir]).output()?;
+
+        if status.stdout.is_empty() {
+            return Ok(());
+        }
+
+        log_wrap!(format!("Committing changes to {}", dir), {
+            execute_child_process("git", &["add", "-A", &dir])?;
+            execute_child_process("git", &[
+                "commit",
+                "-m",
+                &format!("Reconcile cluster {} with its spec", cluster),
+            ])?;
+            execute_child_process("git", &[
+                "push",
+                self.remote,
+                &format!("HEAD:{}", self.branch),
+            ])?;
+        });
+
+        Ok(())
+    }
+
+    // Prints a message and posts it to the webhook, if there is one. A webhook that can't be
+    // reached is only warned about, so it can't stop clusters from being reconciled.
+    fn notify(&self, text: &str) {
+        println!("{}", text);
+
+        if let Some(url) = self.webhook_url {
+            if let Err(error) = post(url, text) {
+                eprintln!("warning: failed to post to webhook: {}", error);
+            }
+        }
+    }
+}
+
+fn digest(path: &str) -> Result<String, KawsError> {
+    let mut contents = vec![];
+
+    File::open(path)?.read_to_end(&mut contents)?;
+
+    Ok(sha256(&contents).iter().map(|byte| format!("{:02x}", byte)).collect())
+}
+
+// Posts JSON with a single `text` field, which Slack and most chat services accept for incoming
+// webhooks, as the drift check's job does.
+fn post(url: &str, text: &str) -> Result<(), KawsError> {
+    let host_start = url.find("://").map(|index| index + 3).unwrap_or(0);
+    let (endpoint, path) = match url[host_start..].find('/') {
+        Some(index) => url.split_at(host_start + index),
+        None => (url, "/"),
+    };
+
+    // As with the Pushgateway, a custom endpoint is never signed.
+    let region = Region::Custom {
+        name: "webhook".to_owned(),
+        endpoint: endpoint.to_owned(),
+    };
+    let mut request = SignedRequest::new("POST", "webhook", &region, path);
+
+    request.set_content_type("application/json".to_owned());
+    request.set_payload(Some(json!({ "text": text }).to_string()));
+
+    let client = HttpClient::new().expect("failed to create HTTP client with TLS");
+    let response = block_on(client.dispatch(request, Some(Duration::from_secs(10))))?;
+
+    if response.status.is_success() {
+        Ok(())
+    } else {
+        Err(KawsError::new(Message::WebhookRejected {
+            status: response.status.as_u16(),
+            url: url.to_owned(),
+        }))
+    }
+}
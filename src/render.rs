@@ -0,0 +1,232 @@
+use std::cmp::Ordering;
+use std::env::var;
+use std::io::{ErrorKind, IsTerminal, Write, stdout};
+use std::process::{Command, Stdio};
+use std::sync::OnceLock;
+use std::sync::atomic::{self, AtomicBool};
+
+use error::KawsError;
+use message::Message;
+
+/// The pager tables are shown through when neither `KAWS_PAGER` nor `PAGER` is set.
+const DEFAULT_PAGER: &str = "less";
+
+/// The options `less` runs with unless `LESS` is set, as Git sets them: quit if the output fits on
+/// one screen, keep colors, and leave the output on the screen after quitting.
+const DEFAULT_LESS: &str = "FRX";
+
+/// How every table is printed for the rest of the run, from the global flags.
+static OPTIONS: OnceLock<TableOptions> = OnceLock::new();
+
+/// Whether a table has been printed yet in this run. `--columns` and `--sort-by` are checked
+/// against the first table a command prints; the tables it prints after that, such as the
+/// principals `kaws audit usage --cloudtrail` lists below its decryptions, have columns of their
+/// own, and skip the names they don't have.
+static PRINTED: AtomicBool = AtomicBool::new(false);
+
+/// How tables are printed, from the global `--columns`, `--sort-by`, `--wide`, and `--no-pager`
+/// flags.
+#[derive(Default)]
+pub struct TableOptions {
+    /// The columns to print, in order, as given to `--columns`, or all of them.
+    pub columns: Option<Vec<String>>,
+    /// Whether to show tables through a pager when stdout is a terminal.
+    pub pager: bool,
+    /// The column to sort rows by, as given to `--sort-by`, or the order they were added in.
+    pub sort_by: Option<String>,
+    /// Whether to print the columns that are only printed with `--wide`.
+    pub wide: bool,
+}
+
+/// A table of cells, printed with each column as wide as its widest cell.
+pub struct Table {
+    columns: Vec<Column>,
+    rows: Vec<Vec<String>>,
+}
+
+struct Column {
+    name: String,
+    wide: bool,
+}
+
+/// Sets how tables are printed for the rest of the run.
+pub fn set_options(options: TableOptions) {
+    let _ = OPTIONS.set(options);
+}
+
+/// Prints `rows`, the first of which names the columns, as a table.
+pub fn print_rows(rows: &[Vec<String>]) -> Result<(), KawsError> {
+    let mut table = Table::new(&rows[0].iter().map(String::as_str).collect::<Vec<_>>());
+
+    for row in &rows[1..] {
+        table.push(row.clone());
+    }
+
+    table.print()
+}
+
+impl Table {
+    /// A table with no rows and the columns `names`, e.g. "EARLIEST CERT EXPIRY", which
+    /// `--columns` and `--sort-by` name in lowercase with dashes, e.g. "earliest-cert-expiry".
+    pub fn new(names: &[&str]) -> Self {
+        Table {
+            columns: names.iter().map(|name| Column {
+                name: name.to_string(),
+                wide: false,
+            }).collect(),
+            rows: vec![],
+        }
+    }
+
+    /// Leaves the columns `names` out unless `--wide` is given or `--columns` names them.
+    pub fn wide_only(mut self, names: &[&str]) -> Self {
+        for column in &mut self.columns {
+            if names.contains(&column.name.as_str()) {
+                column.wide = true;
+            }
+        }
+
+        self
+    }
+
+    /// Adds a row, with a cell for each column in order.
+    pub fn push(&mut self, row: Vec<String>) {
+        self.rows.push(row);
+    }
+
+    /// Prints the table to stdout with the run's options, through the pager if stdout is a
+    /// terminal.
+    pub fn print(&self) -> Result<(), KawsError> {
+        let default_options = TableOptions::default();
+        let options = OPTIONS.get().unwrap_or(&default_options);
+        let secondary = PRINTED.swap(true, atomic::Ordering::Relaxed);
+        let selected = self.selected_columns(options, secondary)?;
+        let mut rows: Vec<&Vec<String>> = self.rows.iter().collect();
+
+        if let Some(ref name) = options.sort_by {
+            match self.column_index(name) {
+                Ok(index) => rows.sort_by(|a, b| compare_cells(&a[index], &b[index])),
+                Err(_) if secondary => {}
+                Err(error) => return Err(error),
+            }
+        }
+
+        let header: Vec<String> = self.columns.iter().map(|column| column.name.clone()).collect();
+        let cells: Vec<Vec<&str>> = Some(&header).into_iter().chain(rows)
+            .map(|row| selected.iter().map(|&index| row[index].as_str()).collect())
+            .collect();
+        let widths: Vec<usize> = (0..selected.len()).map(|column| {
+            cells.iter().map(|row| row[column].len()).max().unwrap_or(0)
+        }).collect();
+        let mut output = String::new();
+
+        for row in &cells {
+            let padded: Vec<String> = row.iter().zip(&widths).map(|(cell, width)| {
+                format!("{:width$}", cell, width = width)
+            }).collect();
+
+            output.push_str(padded.join("  ").trim_end());
+            output.push('\n');
+        }
+
+        if options.pager && stdout().is_terminal() {
+            page(&output)
+        } else {
+            print!("{}", output);
+
+            Ok(())
+        }
+    }
+
+    // Private
+
+    // The index of the column `name` names, as `--columns` and `--sort-by` take it.
+    fn column_index(&self, name: &str) -> Result<usize, KawsError> {
+        let name = flag_name(name);
+
+        self.columns.iter().position(|column| flag_name(&column.name) == name).ok_or_else(|| {
+            KawsError::new(Message::TableColumnUnknown {
+                column: name,
+                columns: self.columns.iter().map(|column| flag_name(&column.name)).collect(),
+            })
+        })
+    }
+
+    // The indexes of the columns to print, in the order to print them. A `secondary` table
+    // prints the columns `--columns` names that it has, or its usual ones if it has none of them.
+    fn selected_columns(&self, options: &TableOptions, secondary: bool)
+    -> Result<Vec<usize>, KawsError> {
+        let default = || (0..self.columns.len())
+            .filter(|&index| options.wide || !self.columns[index].wide)
+            .collect();
+
+        match options.columns {
+            Some(ref names) if secondary => {
+                let selected: Vec<usize> = names.iter()
+                    .filter_map(|name| self.column_index(name).ok())
+                    .collect();
+
+                Ok(if selected.is_empty() { default() } else { selected })
+            }
+            Some(ref names) => names.iter().map(|name| self.column_index(name)).collect(),
+            None => Ok(default()),
+        }
+    }
+}
+
+// Cells that are both numbers, such as counts, are compared as numbers, and any others as text,
+// which sorts the dates kaws prints, e.g. "2024-01-31", by time.
+fn compare_cells(a: &str, b: &str) -> Ordering {
+    match (a.parse::<f64>(), b.parse::<f64>()) {
+        (Ok(a), Ok(b)) => a.partial_cmp(&b).unwrap_or(Ordering::Equal),
+        _ => a.cmp(b),
+    }
+}
+
+// A column's name as flags take it: lowercase, with dashes between words.
+fn flag_name(name: &str) -> String {
+    name.trim().to_lowercase().replace([' ', '_'], "-")
+}
+
+// Writes `output` to the standard input of the pager `KAWS_PAGER` or `PAGER` names, run by the
+// shell so it can have arguments. An empty pager or "cat" prints the output directly, as does a
+// pager that can't be started. Quitting the pager early isn't an error.
+fn page(output: &str) -> Result<(), KawsError> {
+    let pager = var("KAWS_PAGER").or_else(|_| var("PAGER"))
+        .unwrap_or_else(|_| DEFAULT_PAGER.to_owned());
+
+    if pager.trim().is_empty() || pager.trim() == "cat" {
+        print!("{}", output);
+
+        return Ok(());
+    }
+
+    let mut command = Command::new("sh");
+
+    command.arg("-c").arg(&pager).stdin(Stdio::piped());
+
+    if var("LESS").is_err() {
+        command.env("LESS", DEFAULT_LESS);
+    }
+
+    let mut child = match command.spawn() {
+        Ok(child) => child,
+        Err(error) => {
+            warn!("Couldn't start the pager {:?}, printing directly: {}", pager, error);
+            print!("{}", output);
+
+            return Ok(());
+        }
+    };
+
+    if let Some(mut stdin) = child.stdin.take() {
+        match stdin.write_all(output.as_bytes()) {
+            Err(ref error) if error.kind() == ErrorKind::BrokenPipe => {}
+            result => result?,
+        }
+    }
+
+    child.wait()?;
+
+    Ok(())
+}
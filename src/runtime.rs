@@ -0,0 +1,112 @@
+use std::env;
+use std::fs::{DirBuilder, remove_dir_all};
+use std::io::ErrorKind;
+use std::os::unix::fs::DirBuilderExt;
+use std::path::PathBuf;
+use std::process::{self, Command, Stdio};
+use std::sync::Mutex;
+
+use tempdir::TempDir;
+
+use error::KawsError;
+use files::entries_if_present;
+
+/// The directory this invocation of kaws keeps its scratch files in, made the first time it's
+/// needed and removed by `clean_up`. Jobs running concurrently may each need it first.
+static INVOCATION_DIR: Mutex<Option<PathBuf>> = Mutex::new(None);
+
+/// The directory kaws keeps files in that only matter while it runs, private to the user:
+/// $XDG_RUNTIME_DIR/kaws, or without it, $XDG_CACHE_HOME/kaws/run or ~/.cache/kaws/run, rather than
+/// the system's shared temporary directory. It's made, readable only by the user, if it's missing.
+///
+/// It holds a directory for each invocation, so that several invocations on one machine never
+/// share scratch files, and the control sockets of open tunnels, which outlive the invocation that
+/// opened them.
+pub fn runtime_dir() -> Result<PathBuf, KawsError> {
+    let dir = match env::var_os("XDG_RUNTIME_DIR").filter(|dir| !dir.is_empty()) {
+        Some(dir) => PathBuf::from(dir).join("kaws"),
+        None => {
+            let cache_home = match env::var_os("XDG_CACHE_HOME").filter(|dir| !dir.is_empty()) {
+                Some(dir) => PathBuf::from(dir),
+                None => match env::var_os("HOME") {
+                    Some(home) => PathBuf::from(home).join(".cache"),
+                    None => env::temp_dir(),
+                },
+            };
+
+            cache_home.join("kaws").join("run")
+        }
+    };
+
+    DirBuilder::new().recursive(true).mode(0o700).create(&dir)?;
+
+    Ok(dir)
+}
+
+/// A new temporary directory within this invocation's own, removed when it's dropped.
+pub fn temp_dir() -> Result<TempDir, KawsError> {
+    Ok(TempDir::new_in(invocation_dir()?, "kaws")?)
+}
+
+/// Removes this invocation's directory, if it made one. Called as kaws exits, since the directory
+/// is never dropped.
+pub fn clean_up() {
+    let dir = INVOCATION_DIR.lock().unwrap_or_else(|error| error.into_inner()).take();
+
+    if let Some(dir) = dir {
+        if let Err(error) = remove_dir_all(&dir) {
+            if error.kind() != ErrorKind::NotFound {
+                debug!("Not removing {}: {}", dir.display(), error);
+            }
+        }
+    }
+}
+
+/// The paths of the directories in the runtime directory left behind by invocations that are no
+/// longer running, e.g. because they were killed before they could clean up.
+pub fn stale_invocation_dirs() -> Result<Vec<String>, KawsError> {
+    let runtime_dir = runtime_dir()?;
+    let mut paths = vec![];
+
+    for entry in entries_if_present(&runtime_dir.to_string_lossy())? {
+        if !entry.is_dir()? {
+            continue;
+        }
+
+        if let Some(pid) = entry.name.split('.').next().and_then(|pid| pid.parse::<u32>().ok()) {
+            if pid != process::id() && !is_running(pid) {
+                paths.push(entry.path);
+            }
+        }
+    }
+
+    Ok(paths)
+}
+
+// This invocation's directory, in the runtime directory, named after the process ID and a random
+// suffix.
+fn invocation_dir() -> Result<PathBuf, KawsError> {
+    let mut invocation_dir = INVOCATION_DIR.lock().unwrap_or_else(|error| error.into_inner());
+
+    if let Some(ref dir) = *invocation_dir {
+        return Ok(dir.clone());
+    }
+
+    let dir = TempDir::new_in(runtime_dir()?, &process::id().to_string())?.into_path();
+
+    *invocation_dir = Some(dir.clone());
+
+    Ok(dir)
+}
+
+// Whether a process with the ID `pid` is running, as far as `kill -0` can tell.
+fn is_running(pid: u32) -> bool {
+    Command::new("kill")
+        .arg("-0")
+        .arg(pid.to_string())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .map(|status| status.success())
+        .unwrap_or(true)
+}
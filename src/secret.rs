@@ -0,0 +1,127 @@
+use std::io::{Write, stdout};
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+use clap::ArgMatches;
+
+use approval::Approvals;
+use aws::{Access, cluster_credentials_provider};
+use error::{KawsError, KawsResult};
+use files::non_empty_names;
+use message::Message;
+use metadata::ClusterMetadata;
+use paths::from_invocation;
+use provider::cloud_provider;
+use tfvars::Tfvars;
+
+/// The suffix of the files kaws keeps KMS-encrypted secrets in.
+const ENCRYPTED_SUFFIX: &str = "-encrypted.base64";
+
+pub struct Secret<'a> {
+    aws_credentials_path: Option<String>,
+    aws_credentials_profile: Option<&'a str>,
+    cluster: &'a str,
+    exec: Option<&'a str>,
+    name: &'a str,
+}
+
+impl<'a> Secret<'a> {
+    pub fn new(matches: &'a ArgMatches) -> Self {
+        Secret {
+            aws_credentials_path: matches.value_of("aws-credentials-path").map(from_invocation),
+            aws_credentials_profile: matches.value_of("aws-credentials-profile"),
+            cluster: matches.value_of("cluster").expect("clap should have required cluster"),
+            exec: matches.value_of("exec"),
+            name: matches.value_of("name").expect("clap should have required name"),
+        }
+    }
+
+    /// Decrypts one of the cluster's secrets and writes it to stdout, or with `--exec`, to the
+    /// standard input of a shell command. The plaintext is never written to disk. A CA's key can
+    /// sign anything, so it's only decrypted with the approvals signing with it would need.
+    pub fn cat(&self) -> KawsResult {
+        let path = format!("clusters/{}/{}{}", self.cluster, self.name, ENCRYPTED_SUFFIX);
+
+        if !Path::new(&path).metadata().map(|metadata| metadata.len() > 0).unwrap_or(false) {
+            return Err(KawsError::new(Message::SecretNotFound {
+                cluster: self.cluster.to_owned(),
+                name: self.name.to_owned(),
+                names: self.names()?,
+            }));
+        }
+
+        let metadata = ClusterMetadata::from_file(&format!("clusters/{}/metadata.json", self.cluster))?;
+        let tfvars = Tfvars::from_file(&format!("clusters/{}/terraform.tfvars", self.cluster))?;
+        let approvals = Approvals::new(self.cluster, "secret-cat", self.name);
+        let ca_key = self.name.ends_with("-ca-key");
+
+        if ca_key {
+            approvals.ensure_sufficient(&metadata)?;
+        }
+
+        let provider = cloud_provider(metadata.cloud_provider, cluster_credentials_provider(
+            self.cluster,
+            Access::ReadWrite,
+            self.aws_credentials_path.as_deref(),
+            self.aws_credentials_profile,
+        )?);
+        let region = tfvars.get("kaws_region").ok_or_else(|| {
+            KawsError::new(Message::TfvarsUnreadable {
+                cluster: self.cluster.to_owned(),
+                error: "kaws_region is not set".to_owned(),
+            })
+        })?;
+        let plaintext = provider.encryptor(region, None)?
+            .with_contexts_required(metadata.encryption_contexts_required)
+            .decrypt_file(&path)?;
+
+        match self.exec {
+            Some(exec) => self.exec(exec, &plaintext)?,
+            None => {
+                let stdout = stdout();
+                let mut handle = stdout.lock();
+
+                handle.write_all(&plaintext)?;
+                handle.flush()?;
+            }
+        }
+
+        if ca_key {
+            approvals.clear()?;
+        }
+
+        // Nothing else is printed on success, so the output is only the secret.
+        Ok(None)
+    }
+
+    // Private
+
+    // Runs the command with `sh -c`, with the secret on its standard input through a pipe, so
+    // commands that want the secret in a file can read it from /dev/stdin.
+    fn exec(&self, exec: &str, plaintext: &[u8]) -> Result<(), KawsError> {
+        let mut child = Command::new("sh").args(["-c", exec]).stdin(Stdio::piped()).spawn()?;
+
+        {
+            let mut stdin = child.stdin.take().expect("child should have a piped stdin");
+
+            // The command may exit without reading all of its input, which isn't an error.
+            let _ = stdin.write_all(plaintext);
+        }
+
+        if !child.wait()?.success() {
+            return Err(KawsError::new(Message::ChildProcessFailed {
+                command: exec.to_owned(),
+            }));
+        }
+
+        Ok(())
+    }
+
+    // The names of the secrets the cluster has, for suggesting one when the name given is wrong.
+    fn names(&self) -> Result<Vec<String>, KawsError> {
+        Ok(non_empty_names(&format!("clusters/{}", self.cluster))?.iter()
+            .filter_map(|name| name.strip_suffix(ENCRYPTED_SUFFIX))
+            .map(str::to_owned)
+            .collect())
+    }
+}
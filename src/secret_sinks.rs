@@ -0,0 +1,143 @@
+use error::KawsError;
+use message::Message;
+use metadata::ClusterMetadata;
+
+/// The kinds of server whose decrypted secrets can be delivered to sinks, by the name the
+/// cluster's metadata lists their sinks under, with the terraform.tfvars variable holding them.
+pub const SINK_ROLES: [(&str, &str); 3] = [
+    ("etcd", "kaws_etcd_secret_sinks"),
+    ("masters", "kaws_master_secret_sinks"),
+    ("nodes", "kaws_node_secret_sinks"),
+];
+
+/// Somewhere a server puts a copy of one of its decrypted secrets, once it has decrypted them at
+/// boot, for services that can't read them where kaws writes them.
+#[derive(Clone, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase", tag = "type")]
+pub enum SecretSink {
+    /// A file with its own path, permissions, and owner.
+    File {
+        /// The decrypted secret, e.g. "/etc/etcd2/ssl/etcd-client-key.pem".
+        source: String,
+        path: String,
+        /// Octal permissions, defaults to "0400".
+        #[serde(default)]
+        mode: Option<String>,
+        /// "USER" or "USER:GROUP", defaults to "root:root".
+        #[serde(default)]
+        owner: Option<String>,
+    },
+    /// A credential systemd units load with `LoadCredential=NAME`, which systemd finds in
+    /// /run/credstore.
+    Credential {
+        source: String,
+        name: String,
+    },
+    /// A variable in an environment file for units to read with `EnvironmentFile=`. Only for
+    /// secrets that are a single line, such as the bootstrap token.
+    Environment {
+        source: String,
+        path: String,
+        variable: String,
+    },
+}
+
+impl SecretSink {
+    // The sink as a line of the manifest the servers read, its type and fields separated by
+    // spaces, after checking that no field could break the line or the script reading it.
+    fn manifest_line(&self) -> Result<String, String> {
+        let fields = match *self {
+            SecretSink::File { ref source, ref path, ref mode, ref owner } => {
+                let mode = mode.as_deref().unwrap_or("0400");
+                let owner = owner.as_deref().unwrap_or("root:root");
+
+                let octal = mode.chars().all(|c| ('0'..='7').contains(&c));
+
+                if !(3..=4).contains(&mode.len()) || !octal {
+                    return Err(format!("mode \"{}\" is not octal permissions like \"0440\"", mode));
+                }
+
+                if owner.split(':').count() > 2 || !owner.split(':').all(is_name) {
+                    return Err(format!("owner \"{}\" is not USER or USER:GROUP", owner));
+                }
+
+                check_path(source)?;
+                check_path(path)?;
+
+                vec!["file", source, path, mode, owner]
+            }
+            SecretSink::Credential { ref source, ref name } => {
+                check_path(source)?;
+
+                if !is_name(name) {
+                    return Err(format!("credential name \"{}\" is not a valid file name", name));
+                }
+
+                vec!["credential", source, name]
+            }
+            SecretSink::Environment { ref source, ref path, ref variable } => {
+                check_path(source)?;
+                check_path(path)?;
+
+                let mut chars = variable.chars();
+                let valid = chars.next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+                    && chars.all(|c| c.is_ascii_alphanumeric() || c == '_');
+
+                if !valid {
+                    return Err(format!("\"{}\" is not an environment variable name", variable));
+                }
+
+                vec!["environment", source, path, variable]
+            }
+        };
+
+        Ok(fields.join(" "))
+    }
+}
+
+/// The sinks listed for the servers named `role` in the cluster's metadata, as the Terraform list
+/// their tfvars variable holds.
+pub fn tfvars_value(metadata: &ClusterMetadata, role: &str) -> Result<String, KawsError> {
+    let mut lines = vec![];
+
+    if let Some(unknown) = metadata.secret_sinks.keys()
+        .find(|name| !SINK_ROLES.iter().any(|&(known, _)| known == name.as_str()))
+    {
+        return Err(KawsError::new(Message::SecretSinkInvalid {
+            error: "servers are \"etcd\", \"masters\", or \"nodes\"".to_owned(),
+            role: unknown.to_owned(),
+        }));
+    }
+
+    for sink in metadata.secret_sinks.get(role).into_iter().flatten() {
+        let line = sink.manifest_line().map_err(|error| {
+            KawsError::new(Message::SecretSinkInvalid {
+                error,
+                role: role.to_owned(),
+            })
+        })?;
+
+        lines.push(format!("\"{}\"", line));
+    }
+
+    Ok(format!("[{}]", lines.join(", ")))
+}
+
+// Checks that a path on the servers is absolute and, like the values of component flags, has
+// nothing that would need quoting.
+fn check_path(path: &str) -> Result<(), String> {
+    let unsafe_char = |c: char| c.is_whitespace() || "\"'\\#$`".contains(c);
+
+    if !path.starts_with('/') || path.contains(unsafe_char) {
+        return Err(format!("\"{}\" is not an absolute path without spaces or quotes", path));
+    }
+
+    Ok(())
+}
+
+// Whether `name` is a user, group, or file name made of letters, digits, '.', '_', and '-'.
+fn is_name(name: &str) -> bool {
+    !name.is_empty()
+        && !name.starts_with('.')
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || "._-".contains(c))
+}
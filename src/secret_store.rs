@@ -0,0 +1,274 @@
+use std::fs::File;
+use std::io::{ErrorKind, Read, Write};
+use std::path::Path;
+
+use clap::ArgMatches;
+use rusoto_core::Region;
+
+use aws::{Access, cluster_credentials_provider};
+use encryption::{ENCRYPTED_SUFFIX, SecretStore, SecretStoreConfig, encrypted_file_paths};
+use error::{KawsError, KawsResult};
+use jobs::run_all;
+use message::Message;
+use metadata::ClusterMetadata;
+use paths::from_invocation;
+use tfvars::{Tfvars, with_values};
+
+/// The line of the cluster's .gitignore that keeps its encrypted files out of Git while they're
+/// kept in a secret store. Their regional copies are still committed.
+const GITIGNORE_LINE: &str = "/*-encrypted.base64";
+
+pub struct ClusterSecretStore<'a> {
+    aws_credentials_path: Option<String>,
+    aws_credentials_profile: Option<&'a str>,
+    bucket: Option<&'a str>,
+    cluster: &'a str,
+    kms_key: Option<&'a str>,
+    store: Option<&'a str>,
+}
+
+impl<'a> ClusterSecretStore<'a> {
+    pub fn new(matches: &'a ArgMatches) -> Self {
+        ClusterSecretStore {
+            aws_credentials_path: matches.value_of("aws-credentials-path").map(from_invocation),
+            aws_credentials_profile: matches.value_of("aws-credentials-profile"),
+            bucket: matches.value_of("bucket"),
+            cluster: matches.value_of("cluster").expect("clap should have required cluster"),
+            kms_key: matches.value_of("kms-key"),
+            store: matches.value_of("store"),
+        }
+    }
+
+    /// Writes each of the cluster's encrypted files to the store, then records it in the cluster's
+    /// metadata and terraform.tfvars, so that later encryptions write to it as well and servers
+    /// fetch their files from it, and leaves the files out of Git. Enabling a store again writes
+    /// every file to it again. Nothing is written unless the store can keep the files, e.g. an S3
+    /// bucket without versioning can't.
+    pub fn enable(&self) -> KawsResult {
+        let config = match self.store.expect("clap should have required store") {
+            "s3" => SecretStoreConfig::S3 {
+                bucket: self.bucket.expect("clap should have required bucket").to_owned(),
+                kms_key: self.kms_key.expect("clap should have required kms-key").to_owned(),
+            },
+            "secretsmanager" => SecretStoreConfig::SecretsManager,
+            "ssm" => SecretStoreConfig::Ssm,
+            _ => unreachable!("clap should have validated store"),
+        };
+        let mut metadata = self.metadata()?;
+        let store = self.store(&config)?;
+        let files = self.encrypted_files()?;
+
+        store.check()?;
+
+        for result in run_all(files.iter().collect(), |(file, encoded)| {
+            store.put(self.cluster, file, encoded)
+        }) {
+            result?;
+        }
+
+        metadata.secret_store = Some(config.clone());
+        metadata.write_to_file(&self.metadata_path())?;
+        self.write_tfvars(Some(&config))?;
+        self.set_gitignored(true)?;
+
+        Ok(Some(Message::SecretStoreEnabled {
+            cluster: self.cluster.to_owned(),
+            files: files.len(),
+            store: config.description().to_owned(),
+        }))
+    }
+
+    /// Writes each of the cluster's files the store has a copy of to the cluster's directory, as
+    /// after cloning the repository, which doesn't have them.
+    pub fn pull(&self) -> KawsResult {
+        let config = self.config()?;
+        let files = self.pull_files(&config)?;
+
+        Ok(Some(Message::SecretStorePulled {
+            cluster: self.cluster.to_owned(),
+            files,
+            store: config.description().to_owned(),
+        }))
+    }
+
+    /// Writes each of the cluster's encrypted files to its store again, through the store's own
+    /// rotation if it has one, e.g. to bring the store back in line with the cluster's directory.
+    /// Secrets Manager stages each file as AWSPENDING and checks it before making it AWSCURRENT,
+    /// so servers never fetch a version it couldn't read back; the other stores just keep the
+    /// version each file replaces.
+    pub fn rotate(&self) -> KawsResult {
+        let config = self.config()?;
+        let store = self.store(&config)?;
+        let files = self.encrypted_files()?;
+
+        for result in run_all(files.iter().collect(), |(file, encoded)| {
+            store.rotate(self.cluster, file, encoded)
+        }) {
+            result?;
+        }
+
+        Ok(Some(Message::SecretStoreRotated {
+            cluster: self.cluster.to_owned(),
+            files: files.len(),
+            store: config.description().to_owned(),
+        }))
+    }
+
+    /// Pulls the cluster's files from the store, then removes the store from the cluster's
+    /// metadata and terraform.tfvars, so servers get their files in their user data again and
+    /// the files are committed to Git. The store's copies are left alone.
+    pub fn disable(&self) -> KawsResult {
+        let config = self.config()?;
+        let mut metadata = self.metadata()?;
+
+        self.pull_files(&config)?;
+
+        metadata.secret_store = None;
+        metadata.write_to_file(&self.metadata_path())?;
+        self.write_tfvars(None)?;
+        self.set_gitignored(false)?;
+
+        Ok(Some(Message::SecretStoreDisabled {
+            cluster: self.cluster.to_owned(),
+            store: config.description().to_owned(),
+        }))
+    }
+
+    // Private
+
+    fn config(&self) -> Result<SecretStoreConfig, KawsError> {
+        self.metadata()?.secret_store.ok_or_else(|| {
+            KawsError::new(Message::SecretStoreNotEnabled {
+                cluster: self.cluster.to_owned(),
+            })
+        })
+    }
+
+    // The name and contents of each of the cluster's encrypted files in its directory.
+    fn encrypted_files(&self) -> Result<Vec<(String, String)>, KawsError> {
+        let mut files = vec![];
+
+        for path in encrypted_file_paths(self.cluster)? {
+            let mut encoded = String::new();
+
+            File::open(&path)?.read_to_string(&mut encoded)?;
+            files.push((file_name(&path), encoded));
+        }
+
+        Ok(files)
+    }
+
+    fn metadata(&self) -> Result<ClusterMetadata, KawsError> {
+        ClusterMetadata::from_file(&self.metadata_path())
+    }
+
+    fn metadata_path(&self) -> String {
+        format!("clusters/{}/metadata.json", self.cluster)
+    }
+
+    // Writes the store's copies of the cluster's encrypted files to its directory, returning how
+    // many there were.
+    fn pull_files(&self, config: &SecretStoreConfig) -> Result<usize, KawsError> {
+        let store = self.store(config)?;
+        let files: Vec<String> = store.list(self.cluster)?.into_iter()
+            .filter(|file| file.ends_with(ENCRYPTED_SUFFIX) && !file.contains('/'))
+            .collect();
+        let mut count = 0;
+
+        for (result, file) in run_all(files.iter().collect(), |file| {
+            store.get(self.cluster, file)
+        }).into_iter().zip(&files) {
+            if let Some(encoded) = result? {
+                File::create(format!("clusters/{}/{}", self.cluster, file))?
+                    .write_all(encoded.as_bytes())?;
+
+                count += 1;
+            }
+        }
+
+        Ok(count)
+    }
+
+    fn region(&self) -> Result<Region, KawsError> {
+        let tfvars = Tfvars::from_file(&self.tfvars_path())?;
+        let region = tfvars.get("kaws_region").ok_or_else(|| {
+            KawsError::new(Message::TfvarsUnreadable {
+                cluster: self.cluster.to_owned(),
+                error: "kaws_region is not set".to_owned(),
+            })
+        })?;
+
+        Ok(region.parse()?)
+    }
+
+    // Adds the line leaving the encrypted files out of Git to the cluster's .gitignore, or
+    // removes it.
+    fn set_gitignored(&self, ignored: bool) -> KawsResult {
+        let path = format!("clusters/{}/.gitignore", self.cluster);
+        let mut contents = String::new();
+
+        match File::open(&path) {
+            Ok(mut file) => { file.read_to_string(&mut contents)?; }
+            Err(ref error) if error.kind() == ErrorKind::NotFound => {}
+            Err(error) => return Err(error.into()),
+        }
+
+        let mut lines: Vec<&str> = contents.lines().filter(|&line| line != GITIGNORE_LINE).collect();
+
+        if ignored {
+            lines.push(GITIGNORE_LINE);
+        }
+
+        let contents: String = lines.iter().map(|line| format!("{}\n", line)).collect();
+
+        File::create(&path)?.write_all(contents.as_bytes())?;
+
+        Ok(None)
+    }
+
+    fn store(&self, config: &SecretStoreConfig) -> Result<Box<dyn SecretStore>, KawsError> {
+        let provider = cluster_credentials_provider(
+            self.cluster,
+            Access::ReadWrite,
+            self.aws_credentials_path.as_deref(),
+            self.aws_credentials_profile,
+        )?;
+
+        Ok(config.store(provider, self.region()?))
+    }
+
+    fn tfvars_path(&self) -> String {
+        format!("clusters/{}/terraform.tfvars", self.cluster)
+    }
+
+    // Writes the store's name, and its bucket if it has one, to the cluster's terraform.tfvars,
+    // where servers find them, or empties them without a store.
+    fn write_tfvars(&self, config: Option<&SecretStoreConfig>) -> KawsResult {
+        let path = self.tfvars_path();
+        let mut contents = String::new();
+
+        File::open(&path)?.read_to_string(&mut contents)?;
+
+        let (store, bucket) = match config {
+            Some(SecretStoreConfig::S3 { bucket, .. }) => ("s3", bucket.as_str()),
+            Some(config) => (config.name(), ""),
+            None => ("", ""),
+        };
+        let store = format!("\"{}\"", store);
+        let bucket = format!("\"{}\"", bucket);
+
+        File::create(&path)?.write_all(
+            with_values(
+                &contents,
+                &[("kaws_secret_store", &store), ("kaws_secret_store_bucket", &bucket)],
+            ).as_bytes(),
+        )?;
+
+        Ok(None)
+    }
+}
+
+// The name of the file at `path`.
+fn file_name(path: &str) -> String {
+    Path::new(path).file_name().unwrap_or_default().to_string_lossy().into_owned()
+}
@@ -0,0 +1,352 @@
+use std::fs::File;
+use std::io::{Read, Write};
+
+use clap::ArgMatches;
+use serde_json::{Map, Value, from_str, from_value, to_value};
+
+use error::{KawsError, KawsResult};
+use groups::save;
+use message::Message;
+use metadata::ClusterMetadata;
+use pki::{SHORT_LIVED_MAX_DAYS, is_short_lived};
+use render::print_rows;
+use terraform::Terraform;
+use tfvars::{Tfvars, with_values};
+use units::parse_duration;
+
+/// The terraform.tfvars variables `kaws cluster set` changes, with the name each is set by. Every
+/// other setting is a path into the cluster's metadata.
+const TFVARS_SETTINGS: [(&str, &str); 6] = [
+    ("instance_size", "kaws_instance_size"),
+    ("kubernetes_version", "kaws_version"),
+    ("masters_max_size", "kaws_masters_max_size"),
+    ("masters_min_size", "kaws_masters_min_size"),
+    ("nodes_max_size", "kaws_nodes_max_size"),
+    ("nodes_min_size", "kaws_nodes_min_size"),
+];
+
+pub struct ClusterSettings<'a> {
+    cluster: &'a str,
+    settings: Vec<&'a str>,
+    terraform: Terraform<'a>,
+}
+
+// A setting whose value changes.
+struct Change {
+    setting: String,
+    current: String,
+    new: String,
+}
+
+impl<'a> ClusterSettings<'a> {
+    pub fn new(matches: &'a ArgMatches) -> Self {
+        ClusterSettings {
+            cluster: matches.value_of("cluster").expect("clap should have required cluster"),
+            settings: matches.values_of("settings")
+                .expect("clap should have required settings")
+                .collect(),
+            terraform: Terraform::new(matches),
+        }
+    }
+
+    /// Changes each setting given as KEY=VALUE in terraform.tfvars or the cluster's metadata,
+    /// once all of them have been checked, then displays the plan that would apply them.
+    pub fn set(&mut self) -> KawsResult {
+        let tfvars_path = format!("clusters/{}/terraform.tfvars", self.cluster);
+        let metadata_path = format!("clusters/{}/metadata.json", self.cluster);
+        let mut contents = String::new();
+
+        File::open(&tfvars_path)?.read_to_string(&mut contents)?;
+
+        let tfvars = Tfvars::parse(&contents);
+        let current_metadata = to_value(ClusterMetadata::from_file(&metadata_path)?)?;
+        let mut metadata = current_metadata.clone();
+        let mut tfvars_values: Vec<(&str, String)> = vec![];
+        let mut metadata_settings: Vec<&str> = vec![];
+
+        for setting in &self.settings {
+            let mut parts = setting.splitn(2, '=');
+            let key = parts.next().expect("clap should have validated KEY=VALUE");
+            let value = parts.next().expect("clap should have validated KEY=VALUE");
+
+            match TFVARS_SETTINGS.iter().find(|&&(name, _)| name == key) {
+                Some(&(_, tfvar)) => {
+                    let value = tfvar_value(key, value)?;
+
+                    tfvars_values.retain(|&(existing, _)| existing != tfvar);
+                    tfvars_values.push((tfvar, value));
+                }
+                None => {
+                    set_metadata(&mut metadata, key, value)?;
+
+                    if !metadata_settings.contains(&key) {
+                        metadata_settings.push(key);
+                    }
+                }
+            }
+        }
+
+        check_pool_sizes(&tfvars, &tfvars_values)?;
+
+        let metadata: ClusterMetadata = from_value(metadata)?;
+
+        check_metadata(&metadata, &tfvars)?;
+
+        let new_metadata = to_value(&metadata)?;
+        let mut changes = vec![];
+
+        for &(tfvar, ref value) in &tfvars_values {
+            let current = tfvars.get_raw(tfvar).unwrap_or("(unset)");
+
+            if current != value {
+                changes.push(Change {
+                    setting: tfvar.to_owned(),
+                    current: current.to_owned(),
+                    new: value.to_owned(),
+                });
+            }
+        }
+
+        for key in &metadata_settings {
+            let current = describe(lookup(&current_metadata, key));
+            let new = describe(lookup(&new_metadata, key));
+
+            if current != new {
+                changes.push(Change {
+                    setting: key.to_string(),
+                    current,
+                    new,
+                });
+            }
+        }
+
+        if changes.is_empty() {
+            return Ok(Some(Message::ClusterSettingsUnchanged {
+                cluster: self.cluster.to_owned(),
+            }));
+        }
+
+        let mut rows = vec![vec!["SETTING".to_owned(), "CURRENT".to_owned(), "NEW".to_owned()]];
+
+        for change in &changes {
+            rows.push(vec![change.setting.clone(), change.current.clone(), change.new.clone()]);
+        }
+
+        print_rows(&rows)?;
+
+        if !tfvars_values.is_empty() {
+            let values: Vec<(&str, &str)> = tfvars_values.iter()
+                .map(|&(key, ref value)| (key, value.as_str()))
+                .collect();
+
+            log_wrap!("Updating terraform.tfvars", {
+                File::create(&tfvars_path)?.write_all(with_values(&contents, &values).as_bytes())?;
+            });
+        }
+
+        // The RBAC manifest and the authenticator's configuration follow the metadata.
+        if new_metadata != current_metadata {
+            save(self.cluster, &metadata)?;
+        }
+
+        self.terraform.plan()?;
+
+        Ok(Some(Message::ClusterSettingsChanged {
+            changes: changes.len(),
+            cluster: self.cluster.to_owned(),
+        }))
+    }
+}
+
+// Checks what reading the metadata doesn't: that administrators' groups are bound to cluster
+// roles, that the certificate settings hold durations and subject fields, and that the k8s CA's
+// key is where a cluster using TLS bootstrapping needs it.
+fn check_metadata(metadata: &ClusterMetadata, tfvars: &Tfvars) -> Result<(), KawsError> {
+    for iam_user in metadata.iam_users.values() {
+        metadata.ensure_groups_bound(&iam_user.groups)?;
+    }
+
+    metadata.subject_fields(None)?;
+
+    for (name, value) in &metadata.certificate_expiry {
+        if let Err(error) = parse_duration(value) {
+            return Err(setting_invalid(&format!("certificate_expiry.{}", name), error));
+        }
+    }
+
+    if let Some(ref value) = metadata.short_lived_certificates {
+        if !parse_duration(value).is_ok_and(is_short_lived) {
+            return Err(KawsError::new(Message::ShortLivedCertificateExpiryInvalid {
+                max_days: SHORT_LIVED_MAX_DAYS,
+                value: value.to_owned(),
+            }));
+        }
+    }
+
+    metadata.check_tls_bootstrapping(tfvars.get("kaws_tls_bootstrapping") == Some("true"))?;
+
+    Ok(())
+}
+
+// Checks that neither pool's minimum size would be greater than its maximum.
+fn check_pool_sizes(tfvars: &Tfvars, values: &[(&str, String)]) -> Result<(), KawsError> {
+    let size = |tfvar: &str| -> u64 {
+        values.iter()
+            .find(|&&(key, _)| key == tfvar)
+            .map(|(_, value)| value.trim_matches('"'))
+            .or_else(|| tfvars.get(tfvar))
+            .and_then(|value| value.parse().ok())
+            .unwrap_or(0)
+    };
+
+    for pool in ["masters", "nodes"] {
+        let min_size = size(&format!("kaws_{}_min_size", pool));
+        let max_size = size(&format!("kaws_{}_max_size", pool));
+
+        if min_size > max_size {
+            return Err(setting_invalid(
+                &format!("{}_min_size", pool),
+                format!("{} is greater than {}_max_size, {}", min_size, pool, max_size),
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+// A setting's value as JSON, or "(unset)" if the metadata has none.
+fn describe(value: Option<&Value>) -> String {
+    match value {
+        Some(&Value::Null) | None => "(unset)".to_owned(),
+        Some(value) => value.to_string(),
+    }
+}
+
+// The value at a dotted path such as "certificate_expiry.k8s-node".
+fn lookup<'v>(metadata: &'v Value, key: &str) -> Option<&'v Value> {
+    key.split('.').try_fold(metadata, |value, name| value.get(name))
+}
+
+// Sets the value at the dotted path `key` in the metadata, read as JSON, or as a string if it
+// isn't JSON or the metadata needs a string there. "null" removes it.
+fn set_metadata(metadata: &mut Value, key: &str, value: &str) -> Result<(), KawsError> {
+    let path: Vec<&str> = key.split('.').collect();
+    let unknown = || KawsError::new(Message::ClusterSettingUnknown {
+        setting: key.to_owned(),
+    });
+
+    if metadata.get(path[0]).is_none() {
+        return Err(unknown());
+    }
+
+    let parsed = from_str(value).unwrap_or_else(|_| Value::String(value.to_owned()));
+    let mut attempts = vec![parsed];
+
+    if !attempts[0].is_string() && !attempts[0].is_null() {
+        attempts.push(Value::String(value.to_owned()));
+    }
+
+    let mut error = String::new();
+
+    for attempt in attempts {
+        let removing = attempt.is_null();
+        let mut candidate = metadata.clone();
+
+        set_path(&mut candidate, &path, attempt)
+            .map_err(|error| setting_invalid(key, error))?;
+
+        match from_value::<ClusterMetadata>(candidate) {
+            Ok(parsed) => {
+                let parsed = to_value(&parsed)?;
+
+                // Reading the metadata drops fields it doesn't have, so a path that didn't
+                // survive wasn't a setting.
+                if !removing && lookup(&parsed, key).is_none() {
+                    return Err(unknown());
+                }
+
+                *metadata = parsed;
+
+                return Ok(());
+            }
+            Err(parse_error) => error = parse_error.to_string(),
+        }
+    }
+
+    Err(setting_invalid(key, error))
+}
+
+// Sets or, for null, removes the value at `path`, adding objects on the way to it as needed.
+fn set_path(root: &mut Value, path: &[&str], value: Value) -> Result<(), String> {
+    let (last, parents) = path.split_last().expect("a setting should have a name");
+
+    if value.is_null() {
+        let parent = parents.iter().try_fold(root, |value, name| value.get_mut(*name));
+
+        if let Some(&mut Value::Object(ref mut map)) = parent {
+            map.remove(*last);
+        }
+
+        return Ok(());
+    }
+
+    let mut current = root;
+
+    for (depth, name) in parents.iter().enumerate() {
+        if current.is_null() {
+            *current = Value::Object(Map::new());
+        }
+
+        current = match *current {
+            Value::Object(ref mut map) => map.entry(name.to_string()).or_insert(Value::Null),
+            _ => return Err(format!("{} has no fields", path[..depth].join("."))),
+        };
+    }
+
+    if current.is_null() {
+        *current = Value::Object(Map::new());
+    }
+
+    match *current {
+        Value::Object(ref mut map) => {
+            map.insert(last.to_string(), value);
+
+            Ok(())
+        }
+        _ => Err(format!("{} has no fields", parents.join("."))),
+    }
+}
+
+fn setting_invalid(setting: &str, error: String) -> KawsError {
+    KawsError::new(Message::ClusterSettingInvalid {
+        error,
+        setting: setting.to_owned(),
+    })
+}
+
+// The raw terraform.tfvars value for one of `TFVARS_SETTINGS`, after checking it.
+fn tfvar_value(key: &str, value: &str) -> Result<String, KawsError> {
+    let valid = match key {
+        "instance_size" => {
+            !value.is_empty() && value.chars().all(|c| c.is_ascii_alphanumeric() || c == '.')
+        }
+        "kubernetes_version" => {
+            value.split('.').count() == 3 && value.split('.').all(|part| {
+                !part.is_empty() && part.chars().all(|c| c.is_ascii_digit())
+            })
+        }
+        _ => value.parse::<u32>().is_ok(),
+    };
+
+    if !valid {
+        let expected = match key {
+            "instance_size" => "an EC2 instance type, e.g. \"m3.medium\"",
+            "kubernetes_version" => "a version of Kubernetes, e.g. \"1.7.0\"",
+            _ => "a whole number",
+        };
+
+        return Err(setting_invalid(key, format!("\"{}\" is not {}", value, expected)));
+    }
+
+    Ok(format!("\"{}\"", value))
+}
@@ -0,0 +1,235 @@
+use std::collections::BTreeMap;
+use std::fs::{File, create_dir_all, remove_file};
+use std::io::{ErrorKind, Read, Write};
+
+use chrono::{DateTime, Utc};
+use clap::ArgMatches;
+use openssl::sha::sha256;
+use serde_json::{Value, from_reader, to_string_pretty};
+
+use error::{KawsError, KawsResult};
+use files::entries_if_present;
+use message::Message;
+use render::print_rows;
+use terraform::Terraform;
+use tfvars::Tfvars;
+
+/// How many snapshots are kept for each cluster. Older ones are removed as new ones are taken.
+const SNAPSHOTS_KEPT: usize = 10;
+
+/// The format of a snapshot's timestamp, which is also its file name, e.g. "20171016T143000Z".
+const TIMESTAMP_FORMAT: &str = "%Y%m%dT%H%M%SZ";
+
+/// The configuration a cluster was successfully applied with.
+///
+/// Stored as JSON at clusters/CLUSTER/snapshots/TIMESTAMP.json, one file per apply, so that
+/// `kaws cluster rollback` can restore it after a bad change.
+#[derive(Deserialize, Serialize)]
+pub struct Snapshot {
+    pub taken_at: DateTime<Utc>,
+    /// The contents of terraform.tfvars.
+    pub tfvars: String,
+    /// The contents of metadata.json.
+    pub metadata: String,
+    /// The SHA-256 digest of each server's rendered cloud-config, keyed by the name of the
+    /// template that rendered it, e.g. "master_cloud_config".
+    #[serde(default)]
+    pub userdata_digests: BTreeMap<String, String>,
+}
+
+pub struct Rollback<'a> {
+    cluster: &'a str,
+    terraform: Terraform<'a>,
+    to: Option<&'a str>,
+}
+
+impl Snapshot {
+    /// Takes a snapshot of the cluster's configuration, after it was applied successfully. Nothing
+    /// is written if it's the same as the latest snapshot.
+    pub fn record(cluster: &str) -> Result<(), KawsError> {
+        let snapshot = Snapshot {
+            taken_at: Utc::now(),
+            tfvars: read_file(&format!("clusters/{}/terraform.tfvars", cluster))?,
+            metadata: read_file(&format!("clusters/{}/metadata.json", cluster))?,
+            userdata_digests: userdata_digests(cluster)?,
+        };
+        let mut timestamps = timestamps(cluster)?;
+
+        if let Some(latest) = timestamps.last() {
+            if Snapshot::from_file(cluster, latest)?.same_configuration(&snapshot) {
+                return Ok(());
+            }
+        }
+
+        let timestamp = snapshot.taken_at.format(TIMESTAMP_FORMAT).to_string();
+
+        log_wrap!(format!("Recording snapshot {} of the cluster's configuration", timestamp), {
+            create_dir_all(snapshots_dir(cluster))?;
+            File::create(snapshot_path(cluster, &timestamp))?
+                .write_all(to_string_pretty(&snapshot)?.as_bytes())?;
+        });
+
+        timestamps.push(timestamp);
+
+        let excess = timestamps.len().saturating_sub(SNAPSHOTS_KEPT);
+
+        for timestamp in &timestamps[..excess] {
+            remove_file(snapshot_path(cluster, timestamp))?;
+        }
+
+        Ok(())
+    }
+
+    pub fn from_file(cluster: &str, timestamp: &str) -> Result<Self, KawsError> {
+        Ok(from_reader(File::open(snapshot_path(cluster, timestamp))?)?)
+    }
+
+    // Private
+
+    fn same_configuration(&self, other: &Snapshot) -> bool {
+        self.tfvars == other.tfvars
+            && self.metadata == other.metadata
+            && self.userdata_digests == other.userdata_digests
+    }
+}
+
+impl<'a> Rollback<'a> {
+    pub fn new(matches: &'a ArgMatches) -> Self {
+        Rollback {
+            cluster: matches.value_of("cluster").expect("clap should have required cluster"),
+            terraform: Terraform::new(matches),
+            to: matches.value_of("to"),
+        }
+    }
+
+    /// Restores the configuration in the snapshot given with `--to` and displays the plan that
+    /// would revert the cluster to it, or lists the cluster's snapshots without `--to`.
+    pub fn run(&mut self) -> KawsResult {
+        match self.to {
+            Some(timestamp) => self.restore(timestamp),
+            None => self.list(),
+        }
+    }
+
+    // Private
+
+    // Prints each snapshot with whether its configuration is the one in the cluster's directory,
+    // and whether its servers' cloud-configs are the ones in the Terraform state, i.e. running.
+    fn list(&self) -> KawsResult {
+        let tfvars = read_file(&format!("clusters/{}/terraform.tfvars", self.cluster))?;
+        let metadata = read_file(&format!("clusters/{}/metadata.json", self.cluster))?;
+        let running = userdata_digests(self.cluster)?;
+        let mut rows = vec![vec![
+            "SNAPSHOT".to_owned(),
+            "KUBERNETES".to_owned(),
+            "CONFIGURATION".to_owned(),
+            "USERDATA".to_owned(),
+        ]];
+
+        for timestamp in timestamps(self.cluster)?.into_iter().rev() {
+            let snapshot = Snapshot::from_file(self.cluster, &timestamp)?;
+            let version = Tfvars::parse(&snapshot.tfvars).get("kaws_version")
+                .unwrap_or("-")
+                .to_owned();
+            let configuration = if snapshot.tfvars == tfvars && snapshot.metadata == metadata {
+                "current"
+            } else {
+                "differs"
+            };
+            let userdata = if snapshot.userdata_digests.is_empty() || running.is_empty() {
+                "-"
+            } else if snapshot.userdata_digests == running {
+                "running"
+            } else {
+                "differs"
+            };
+
+            rows.push(vec![timestamp, version, configuration.to_owned(), userdata.to_owned()]);
+        }
+
+        print_rows(&rows)?;
+
+        Ok(None)
+    }
+
+    fn restore(&mut self, timestamp: &str) -> KawsResult {
+        let timestamps = timestamps(self.cluster)?;
+
+        if !timestamps.iter().any(|existing| existing == timestamp) {
+            return Err(KawsError::new(Message::SnapshotNotFound {
+                cluster: self.cluster.to_owned(),
+                timestamp: timestamp.to_owned(),
+                timestamps,
+            }));
+        }
+
+        let snapshot = Snapshot::from_file(self.cluster, timestamp)?;
+
+        log_wrap!("Restoring terraform.tfvars and metadata.json", {
+            File::create(format!("clusters/{}/terraform.tfvars", self.cluster))?
+                .write_all(snapshot.tfvars.as_bytes())?;
+            File::create(format!("clusters/{}/metadata.json", self.cluster))?
+                .write_all(snapshot.metadata.as_bytes())?;
+        });
+
+        self.terraform.plan()?;
+
+        Ok(Some(Message::ClusterRolledBack {
+            cluster: self.cluster.to_owned(),
+            timestamp: timestamp.to_owned(),
+        }))
+    }
+}
+
+fn read_file(path: &str) -> Result<String, KawsError> {
+    let mut contents = String::new();
+
+    File::open(path)?.read_to_string(&mut contents)?;
+
+    Ok(contents)
+}
+
+fn snapshot_path(cluster: &str, timestamp: &str) -> String {
+    format!("{}/{}.json", snapshots_dir(cluster), timestamp)
+}
+
+fn snapshots_dir(cluster: &str) -> String {
+    format!("clusters/{}/snapshots", cluster)
+}
+
+// The timestamps of the cluster's snapshots, oldest first, which their names sort by.
+fn timestamps(cluster: &str) -> Result<Vec<String>, KawsError> {
+    Ok(entries_if_present(&snapshots_dir(cluster))?.into_iter()
+        .filter_map(|entry| entry.name.strip_suffix(".json").map(str::to_owned))
+        .collect())
+}
+
+// The digests of the cloud-configs rendered for the cluster's servers, as recorded in its
+// Terraform state, or none if it has no state yet.
+fn userdata_digests(cluster: &str) -> Result<BTreeMap<String, String>, KawsError> {
+    let state: Value = match File::open(format!("clusters/{}/terraform.tfstate", cluster)) {
+        Ok(file) => from_reader(file)?,
+        Err(ref error) if error.kind() == ErrorKind::NotFound => return Ok(BTreeMap::new()),
+        Err(error) => return Err(error.into()),
+    };
+    let mut digests = BTreeMap::new();
+
+    for module in state["modules"].as_array().into_iter().flatten() {
+        for (address, resource) in module["resources"].as_object().into_iter().flatten() {
+            let name = match address.strip_prefix("data.template_file.") {
+                Some(name) => name,
+                None => continue,
+            };
+
+            if let Some(rendered) = resource["primary"]["attributes"]["rendered"].as_str() {
+                let digest = sha256(rendered.as_bytes()).iter()
+                    .map(|byte| format!("{:02x}", byte))
+                    .collect();
+
+                digests.insert(name.to_owned(), digest);
+            }
+        }
+    }
+
+    Ok(digests)
+}
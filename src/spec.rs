@@ -0,0 +1,395 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs::File;
+use std::io::{Read, Write};
+use std::path::Path;
+use std::process::Command;
+
+use clap::ArgMatches;
+use serde_json::from_reader;
+
+use error::{KawsError, KawsResult};
+use groups::save;
+use message::Message;
+use metadata::{ClusterMetadata, IamUser};
+use paths::from_invocation;
+use process::execute_child_process;
+use render::print_rows;
+use tfvars::{Tfvars, with_values};
+
+/// The desired state of a cluster, read by `kaws cluster apply-spec`.
+///
+/// Every setting is optional, and settings left out are left as they are. `groups` and `admins`
+/// are authoritative when given: groups and administrators the spec doesn't list are removed.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ClusterSpec {
+    cluster: String,
+    /// Administrators, keyed by IAM user name, with the Kubernetes groups each belongs to.
+    admins: Option<BTreeMap<String, Vec<String>>>,
+    /// Kubernetes groups mapped to the cluster role they are bound to.
+    groups: Option<BTreeMap<String, String>>,
+    instance_size: Option<String>,
+    kubernetes_version: Option<String>,
+    masters: Option<PoolSize>,
+    nodes: Option<PoolSize>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PoolSize {
+    max_size: u32,
+    min_size: u32,
+}
+
+// A setting whose current value differs from the spec's.
+struct Change {
+    setting: String,
+    current: String,
+    desired: String,
+}
+
+pub struct SpecReconciler<'a> {
+    aws_credentials_path: Option<String>,
+    aws_credentials_profile: Option<&'a str>,
+    dry_run: bool,
+    override_budget: bool,
+    skip_ami_check: bool,
+    spec_path: String,
+    terraform_args: Option<Vec<&'a str>>,
+}
+
+impl<'a> SpecReconciler<'a> {
+    pub fn new(matches: &'a ArgMatches) -> Self {
+        SpecReconciler {
+            aws_credentials_path: matches.value_of("aws-credentials-path").map(from_invocation),
+            aws_credentials_profile: matches.value_of("aws-credentials-profile"),
+            dry_run: matches.is_present("dry-run"),
+            override_budget: matches.is_present("override-budget"),
+            skip_ami_check: matches.is_present("skip-ami-check"),
+            spec_path: from_invocation(
+                matches.value_of("spec").expect("clap should have required spec"),
+            ),
+            terraform_args: matches.values_of("terraform-args").map(|values| values.collect()),
+        }
+    }
+
+    /// Shows how the cluster differs from the spec, then converges it: updates terraform.tfvars
+    /// and the metadata, applies the Terraform plan, signs pending requests from the spec's
+    /// administrators, and applies the RBAC manifest.
+    pub fn reconcile(&self) -> KawsResult {
+        let spec = self.spec()?;
+        let cluster = spec.cluster.as_str();
+        let tfvars_path = format!("clusters/{}/terraform.tfvars", cluster);
+        let metadata_path = format!("clusters/{}/metadata.json", cluster);
+
+        let mut contents = String::new();
+
+        File::open(&tfvars_path)?.read_to_string(&mut contents)?;
+
+        let tfvars = Tfvars::parse(&contents);
+        let metadata = ClusterMetadata::from_file(&metadata_path)?;
+        let mut desired_metadata = ClusterMetadata::from_file(&metadata_path)?;
+
+        let tfvars_changes = self.tfvars_changes(&spec, &tfvars);
+        let metadata_changes = self.metadata_changes(&spec, &mut desired_metadata)?;
+        let pending_admins = self.pending_admins(&spec);
+
+        if tfvars_changes.is_empty() && metadata_changes.is_empty() && pending_admins.is_empty() {
+            return Ok(Some(Message::ClusterSpecMatched {
+                cluster: cluster.to_owned(),
+            }));
+        }
+
+        let mut rows = vec![
+            vec!["SETTING".to_owned(), "CURRENT".to_owned(), "DESIRED".to_owned()],
+        ];
+
+        for change in tfvars_changes.iter().chain(&metadata_changes) {
+            rows.push(vec![change.setting.clone(), change.current.clone(), change.desired.clone()]);
+        }
+
+        for admin in &pending_admins {
+            rows.push(vec![
+                format!("certificate for {}", admin),
+                "requested".to_owned(),
+                "signed".to_owned(),
+            ]);
+        }
+
+        print_rows(&rows)?;
+
+        let changes = rows.len() - 1;
+
+        if self.dry_run {
+            return Ok(Some(Message::ClusterSpecPlanned {
+                cluster: cluster.to_owned(),
+                changes,
+            }));
+        }
+
+        if !tfvars_changes.is_empty() {
+            let values: Vec<(&str, &str)> = tfvars_changes.iter()
+                .map(|change| (change.setting.as_str(), change.desired.as_str()))
+                .collect();
+
+            log_wrap!("Updating terraform.tfvars", {
+                File::create(&tfvars_path)?.write_all(with_values(&contents, &values).as_bytes())?;
+            });
+        }
+
+        if !metadata_changes.is_empty() {
+            save(cluster, &desired_metadata)?;
+        }
+
+        // The authenticator's configuration lives in terraform.tfvars, so it changes with the
+        // metadata.
+        if !tfvars_changes.is_empty() || (!metadata_changes.is_empty() && metadata.iam_authenticator) {
+            self.kaws(&self.apply_args(cluster))?;
+        }
+
+        for admin in &pending_admins {
+            self.kaws(&["admin", "sign", cluster, admin])?;
+        }
+
+        if !metadata_changes.is_empty() {
+            let context = format!("--context=kaws-{}", cluster);
+
+            log_wrap!("Applying RBAC manifest", {
+                execute_child_process("kubectl", &[
+                    context.as_str(),
+                    "apply",
+                    "-f",
+                    &format!("clusters/{}/rbac.yml", cluster),
+                ])?;
+
+                for group in metadata.group_roles.keys() {
+                    if !desired_metadata.group_roles.contains_key(group) {
+                        execute_child_process("kubectl", &[
+                            context.as_str(),
+                            "delete",
+                            "clusterrolebinding",
+                            &format!("kaws-group-{}", group),
+                        ])?;
+                    }
+                }
+            });
+        }
+
+        Ok(Some(Message::ClusterSpecApplied {
+            cluster: cluster.to_owned(),
+            changes,
+        }))
+    }
+
+    // Private
+
+    fn spec(&self) -> Result<ClusterSpec, KawsError> {
+        let invalid = |error: String| {
+            KawsError::new(Message::SpecInvalid {
+                error,
+                path: self.spec_path.to_owned(),
+            })
+        };
+
+        let spec: ClusterSpec = from_reader(File::open(&self.spec_path)?)
+            .map_err(|error| invalid(error.to_string()))?;
+
+        // `kaws reconcile` finds specs by the directory they're in, so they must agree.
+        let directory = Path::new(&self.spec_path).parent()
+            .filter(|parent| parent.parent() == Some(Path::new("clusters")))
+            .and_then(Path::file_name);
+
+        if directory.is_some_and(|directory| directory != spec.cluster.as_str()) {
+            return Err(invalid(format!(
+                "the spec is in the directory of another cluster than \"{}\"",
+                spec.cluster,
+            )));
+        }
+
+        if !Path::new(&format!("clusters/{}/terraform.tfvars", spec.cluster)).exists() {
+            return Err(invalid(format!("cluster \"{}\" has not been initialized", spec.cluster)));
+        }
+
+        for (pool, size) in [("masters", &spec.masters), ("nodes", &spec.nodes)] {
+            if let Some(ref size) = *size {
+                if size.min_size > size.max_size {
+                    return Err(invalid(format!("{}.min_size is greater than max_size", pool)));
+                }
+            }
+        }
+
+        Ok(spec)
+    }
+
+    // The terraform.tfvars settings the spec changes, with raw values as written in the file.
+    fn tfvars_changes(&self, spec: &ClusterSpec, tfvars: &Tfvars) -> Vec<Change> {
+        let quote = |value: &str| format!("\"{}\"", value);
+        let mut desired = vec![];
+
+        if let Some(ref instance_size) = spec.instance_size {
+            desired.push(("kaws_instance_size", quote(instance_size)));
+        }
+
+        if let Some(ref kubernetes_version) = spec.kubernetes_version {
+            desired.push(("kaws_version", quote(kubernetes_version)));
+        }
+
+        if let Some(ref masters) = spec.masters {
+            desired.push(("kaws_masters_max_size", quote(&masters.max_size.to_string())));
+            desired.push(("kaws_masters_min_size", quote(&masters.min_size.to_string())));
+        }
+
+        if let Some(ref nodes) = spec.nodes {
+            desired.push(("kaws_nodes_max_size", quote(&nodes.max_size.to_string())));
+            desired.push(("kaws_nodes_min_size", quote(&nodes.min_size.to_string())));
+        }
+
+        let mut changes: Vec<Change> = desired.into_iter().filter_map(|(key, value)| {
+            let current = tfvars.get_raw(key).unwrap_or("(unset)");
+
+            if current == value {
+                None
+            } else {
+                Some(Change {
+                    setting: key.to_owned(),
+                    current: current.to_owned(),
+                    desired: value,
+                })
+            }
+        }).collect();
+
+        // Only these IAM users can use the cluster's KMS key, so the list follows the admins.
+        if let Some(ref admins) = spec.admins {
+            let current = tfvars.get_raw("kaws_iam_users").unwrap_or("[]");
+            let current_users: BTreeSet<&str> = current.trim_matches(&['[', ']'][..])
+                .split(',')
+                .map(|user| user.trim().trim_matches('"'))
+                .filter(|user| !user.is_empty())
+                .collect();
+
+            if !current_users.iter().copied().eq(admins.keys().map(String::as_str)) {
+                let users: Vec<String> = admins.keys().map(|user| quote(user)).collect();
+
+                changes.push(Change {
+                    setting: "kaws_iam_users".to_owned(),
+                    current: current.to_owned(),
+                    desired: format!("[{}]", users.join(", ")),
+                });
+            }
+        }
+
+        changes
+    }
+
+    // Updates `metadata` to match the spec's groups and admins, returning what changed.
+    fn metadata_changes(&self, spec: &ClusterSpec, metadata: &mut ClusterMetadata)
+    -> Result<Vec<Change>, KawsError> {
+        let mut changes = vec![];
+
+        if let Some(ref groups) = spec.groups {
+            let names: BTreeSet<&String> = metadata.group_roles.keys().chain(groups.keys()).collect();
+
+            for name in names {
+                let current = metadata.group_roles.get(name);
+                let desired = groups.get(name);
+
+                if current != desired {
+                    changes.push(Change {
+                        setting: format!("group {}", name),
+                        current: current.cloned().unwrap_or_else(|| "(none)".to_owned()),
+                        desired: desired.cloned().unwrap_or_else(|| "(none)".to_owned()),
+                    });
+                }
+            }
+
+            metadata.group_roles = groups.clone();
+        }
+
+        if let Some(ref admins) = spec.admins {
+            let names: BTreeSet<&String> = metadata.iam_users.keys().chain(admins.keys()).collect();
+            let describe = |groups: Option<&Vec<String>>| match groups {
+                Some(groups) if groups.is_empty() => "(no groups)".to_owned(),
+                Some(groups) => groups.join(", "),
+                None => "(none)".to_owned(),
+            };
+
+            for name in names {
+                let current = metadata.groups_for(name);
+                let desired = admins.get(name);
+
+                if current != desired {
+                    changes.push(Change {
+                        setting: format!("admin {}", name),
+                        current: describe(current),
+                        desired: describe(desired),
+                    });
+                }
+            }
+
+            metadata.iam_users = admins.iter().map(|(name, groups)| {
+                (name.clone(), IamUser { groups: groups.clone() })
+            }).collect();
+        }
+
+        for groups in metadata.iam_users.values() {
+            metadata.ensure_groups_bound(&groups.groups)?;
+        }
+
+        Ok(changes)
+    }
+
+    // The spec's admins who have requested a client certificate that hasn't been signed yet.
+    fn pending_admins(&self, spec: &ClusterSpec) -> Vec<String> {
+        let admins = match spec.admins {
+            Some(ref admins) => admins,
+            None => return vec![],
+        };
+
+        admins.keys().filter(|admin| {
+            let cert_path = format!("clusters/{}/{}.pem", spec.cluster, admin);
+
+            Path::new(&format!("clusters/{}/{}-csr.pem", spec.cluster, admin)).exists() &&
+                !Path::new(&cert_path).metadata().is_ok_and(|metadata| metadata.len() > 0)
+        }).cloned().collect()
+    }
+
+    fn apply_args<'b>(&'b self, cluster: &'b str) -> Vec<&'b str> {
+        let mut args = vec!["cluster", "apply", cluster];
+
+        if let Some(ref path) = self.aws_credentials_path {
+            args.extend(["--aws-credentials-path", path]);
+        }
+
+        if let Some(profile) = self.aws_credentials_profile {
+            args.extend(["--aws-credentials-profile", profile]);
+        }
+
+        if self.override_budget {
+            args.push("--override-budget");
+        }
+
+        if self.skip_ami_check {
+            args.push("--skip-ami-check");
+        }
+
+        if let Some(ref terraform_args) = self.terraform_args {
+            args.push("--");
+            args.extend(terraform_args);
+        }
+
+        args
+    }
+
+    // Runs another kaws command, which may prompt the user, as a step of the reconciliation.
+    fn kaws(&self, args: &[&str]) -> KawsResult {
+        let status = Command::new("kaws").args(args).status()?;
+
+        if !status.success() {
+            return Err(KawsError::new(Message::ChildProcessFailed {
+                command: format!("kaws {}", args.join(" ")),
+            }));
+        }
+
+        Ok(None)
+    }
+}
@@ -0,0 +1,122 @@
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Write};
+use std::os::unix::fs::OpenOptionsExt;
+
+use chrono::Duration;
+use tempdir::TempDir;
+
+use encryption::Encryptor;
+use error::{KawsError, KawsResult};
+use message::Message;
+use process::execute_child_process;
+use runtime::temp_dir;
+
+/// An SSH certificate authority, whose private key is only ever written to disk encrypted by KMS,
+/// or unencrypted to a temporary directory while `ssh-keygen` uses it.
+pub struct SshCertificateAuthority {
+    key: Vec<u8>,
+    public_key: String,
+}
+
+impl SshCertificateAuthority {
+    pub fn from_files(
+        encryptor: &mut Encryptor,
+        public_key_path: &str,
+        key_path: &str,
+    ) -> Result<Self, KawsError> {
+        let mut public_key = String::new();
+        File::open(public_key_path)?.read_to_string(&mut public_key)?;
+
+        Ok(SshCertificateAuthority {
+            key: encryptor.decrypt_file(key_path)?,
+            public_key,
+        })
+    }
+
+    pub fn generate(comment: &str) -> Result<Self, KawsError> {
+        let tempdir = temp_dir()?;
+        let key_path = temporary_path(&tempdir, "ca")?;
+
+        execute_child_process("ssh-keygen", &[
+            "-q",
+            "-t",
+            "ed25519",
+            "-N",
+            "",
+            "-C",
+            comment,
+            "-f",
+            key_path.as_str(),
+        ])?;
+
+        let mut key = vec![];
+        File::open(&key_path)?.read_to_end(&mut key)?;
+
+        let mut public_key = String::new();
+        File::open(format!("{}.pub", key_path))?.read_to_string(&mut public_key)?;
+
+        Ok(SshCertificateAuthority {
+            key,
+            public_key,
+        })
+    }
+
+    /// The CA's public key, in the one-line format of authorized_keys files.
+    pub fn public_key(&self) -> &str {
+        self.public_key.trim_end()
+    }
+
+    /// Signs the public key at `public_key_path` as a user certificate, returning the path
+    /// `ssh-keygen` wrote the certificate to.
+    pub fn sign_user_key(
+        &self,
+        public_key_path: &str,
+        identity: &str,
+        principals: &[&str],
+        backdate: Duration,
+        ttl: Duration,
+    ) -> Result<String, KawsError> {
+        let tempdir = temp_dir()?;
+        let key_path = temporary_path(&tempdir, "ca")?;
+
+        // ssh-keygen refuses to use a private key that other users can read.
+        OpenOptions::new().write(true).create_new(true).mode(0o600).open(&key_path)?
+            .write_all(&self.key)?;
+
+        execute_child_process("ssh-keygen", &[
+            "-q",
+            "-s",
+            key_path.as_str(),
+            "-I",
+            identity,
+            "-n",
+            principals.join(",").as_str(),
+            "-V",
+            format!("-{}s:+{}s", backdate.num_seconds(), ttl.num_seconds()).as_str(),
+            public_key_path,
+        ])?;
+
+        Ok(format!("{}-cert.pub", public_key_path.trim_end_matches(".pub")))
+    }
+
+    pub fn write_to_files(
+        &self,
+        encryptor: &mut Encryptor,
+        public_key_path: &str,
+        key_path: &str,
+    ) -> KawsResult {
+        let mut public_key_file = File::create(public_key_path)?;
+        writeln!(public_key_file, "{}", self.public_key())?;
+
+        encryptor.encrypt_and_write_file(&self.key, key_path)?;
+
+        Ok(None)
+    }
+}
+
+fn temporary_path(tempdir: &TempDir, name: &str) -> Result<String, KawsError> {
+    match tempdir.path().join(name).to_str() {
+        Some(value) => Ok(value.to_owned()),
+        None => Err(KawsError::new(Message::TemporaryPathNotUtf8)),
+    }
+}
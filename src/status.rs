@@ -0,0 +1,233 @@
+use std::fs::metadata;
+use std::path::Path;
+use std::process::Command;
+
+use ansi_term::Colour::Yellow;
+use chrono::{Duration, Utc};
+use clap::ArgMatches;
+
+use cache::{self, file_inputs, program_path};
+use error::{KawsError, KawsResult};
+use files::entries;
+use fleet::ClusterStatus;
+use message::Message;
+use metadata::ClusterMetadata;
+
+pub struct Status {
+    expiry_warning_days: i64,
+}
+
+impl Status {
+    pub fn new(matches: &ArgMatches) -> Self {
+        Status {
+            expiry_warning_days: matches
+                .value_of("expiry-warning-days")
+                .unwrap_or("30")
+                .parse()
+                .expect("clap should have validated expiry-warning-days"),
+        }
+    }
+
+    pub fn summarize(&self) -> KawsResult {
+        let statuses = ClusterStatus::collect_all()?;
+        let mut attention = vec![];
+
+        println!("Clusters:");
+
+        if statuses.is_empty() {
+            println!("  (none)");
+        }
+
+        for status in &statuses {
+            println!(
+                "  {}: Kubernetes {}, last applied {}",
+                status.name,
+                status.kubernetes_version.as_deref().unwrap_or("unknown"),
+                status.last_applied.map(|time| {
+                    time.format("%Y-%m-%d %H:%M UTC").to_string()
+                }).unwrap_or_else(|| "never".to_owned()),
+            );
+
+            attention.extend(self.pki_attention(status)?);
+        }
+
+        println!("\nDependencies:");
+
+        for program in &["kubectl", "terraform"] {
+            match dependency_version(program) {
+                Some(version) => println!("  {}: {}", program, version),
+                None => {
+                    println!("  {}: not installed", program);
+
+                    attention.push(format!("{} is not installed", program));
+                }
+            }
+        }
+
+        match uncommitted_files() {
+            Some(files) => if !files.is_empty() {
+                attention.push(format!(
+                    "{} generated file(s) have uncommitted changes:\n      {}",
+                    files.len(),
+                    files.join("\n      "),
+                ));
+            },
+            None => attention.push("Could not read Git status for the repository".to_owned()),
+        }
+
+        println!("\nNeeds attention:");
+
+        if attention.is_empty() {
+            println!("  (nothing)");
+        }
+
+        for item in &attention {
+            println!("  {} {}", Yellow.paint("*"), item);
+        }
+
+        if attention.is_empty() {
+            Ok(Some(Message::StatusClean))
+        } else {
+            Ok(Some(Message::StatusNeedsAttention {
+                count: attention.len(),
+            }))
+        }
+    }
+
+    // Private
+
+    fn pki_attention(&self, status: &ClusterStatus) -> Result<Vec<String>, KawsError> {
+        let now = Utc::now();
+        let warning_threshold = now + Duration::days(self.expiry_warning_days);
+        let mut attention = vec![];
+
+        for certificate in &status.certificates {
+            if certificate.not_after < now {
+                attention.push(format!(
+                    "{}: certificate {} has expired",
+                    status.name,
+                    certificate.path,
+                ));
+            } else if certificate.renew_after.is_some() && certificate.due_by(warning_threshold) {
+                attention.push(format!(
+                    "{}: short-lived certificate {} is due to be reissued, and expires in {} \
+                    hour(s)",
+                    status.name,
+                    certificate.path,
+                    (certificate.not_after - now).num_hours(),
+                ));
+            } else if certificate.due_by(warning_threshold) {
+                attention.push(format!(
+                    "{}: certificate {} expires in {} day(s)",
+                    status.name,
+                    certificate.path,
+                    (certificate.not_after - now).num_days(),
+                ));
+            }
+        }
+
+        for admin in unsigned_csrs(&status.name)? {
+            attention.push(format!(
+                "{}: certificate signing request for {} is waiting for `kaws admin sign {} {}`",
+                status.name,
+                admin,
+                status.name,
+                admin,
+            ));
+        }
+
+        let metadata = ClusterMetadata::from_file(
+            &format!("clusters/{}/metadata.json", status.name),
+        )?;
+
+        if !metadata.rolling_update_reasons.is_empty() {
+            attention.push(format!(
+                "{}: masters and nodes need to be replaced after `kaws cluster apply`: {}",
+                status.name,
+                metadata.rolling_update_reasons.join(", "),
+            ));
+        }
+
+        for error in &status.errors {
+            attention.push(format!("{}: {}", status.name, error));
+        }
+
+        Ok(attention)
+    }
+}
+
+/// Administrators whose CSR has no certificate, or is newer than their certificate.
+fn unsigned_csrs(cluster: &str) -> Result<Vec<String>, KawsError> {
+    let mut admins = vec![];
+
+    for entry in entries(&format!("clusters/{}", cluster))? {
+        let admin = match entry.name.strip_suffix("-csr.pem") {
+            Some(admin) => admin,
+            None => continue,
+        };
+        let csr_path = Path::new(&entry.path);
+        let cert_path = csr_path.with_file_name(format!("{}.pem", admin));
+
+        if !is_signed(csr_path, &cert_path) {
+            admins.push(admin.to_owned());
+        }
+    }
+
+    Ok(admins)
+}
+
+fn is_signed(csr_path: &Path, cert_path: &Path) -> bool {
+    let cert_metadata = match metadata(cert_path) {
+        Ok(cert_metadata) if cert_metadata.len() > 0 => cert_metadata,
+        _ => return false,
+    };
+
+    match (metadata(csr_path).and_then(|m| m.modified()), cert_metadata.modified()) {
+        (Ok(csr_modified), Ok(cert_modified)) => cert_modified >= csr_modified,
+        _ => true,
+    }
+}
+
+/// The first line a dependency prints about its version, or `None` if it isn't installed. The
+/// version is cached until the program's executable changes.
+fn dependency_version(program: &str) -> Option<String> {
+    let path = program_path(program)?;
+    let cache_name = format!("dependencies/{}", program);
+    let inputs = file_inputs(&[&path]).ok()?;
+
+    if let Some(version) = cache::read(&cache_name, &inputs) {
+        return Some(version);
+    }
+
+    let args: &[&str] = match program {
+        "kubectl" => &["version", "--client"],
+        _ => &["version"],
+    };
+
+    let output = Command::new(&path).args(args).output().ok()?;
+
+    if !output.status.success() {
+        return None;
+    }
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let line = stdout.lines().map(str::trim).find(|line| !line.is_empty()).unwrap_or("unknown");
+
+    cache::write(&cache_name, inputs, line);
+
+    Some(line.to_owned())
+}
+
+/// Files kaws generates that differ from what is committed, or `None` if Git couldn't tell.
+fn uncommitted_files() -> Option<Vec<String>> {
+    let output = Command::new("git")
+        .args(["status", "--porcelain", "--", "clusters", "terraform", "templates"])
+        .output()
+        .ok()?;
+
+    if !output.status.success() {
+        return None;
+    }
+
+    Some(String::from_utf8_lossy(&output.stdout).lines().map(|line| line.trim().to_owned()).collect())
+}
@@ -0,0 +1,94 @@
+use std::fs::{File, copy, create_dir_all};
+use std::io::{ErrorKind, Read};
+use std::path::Path;
+
+use handlebars::{Handlebars, no_escape};
+use serde::Serialize;
+
+use error::KawsError;
+
+/// The templates built into kaws, by name.
+const BUILTIN_TEMPLATES: [(&str, &str); 9] = [
+    ("cluster-gitignore", include_str!("../templates/cluster-gitignore.hbs")),
+    ("drift-cron-github.yml", include_str!("../templates/drift-cron-github.yml.hbs")),
+    ("drift-cron-gitlab.yml", include_str!("../templates/drift-cron-gitlab.yml.hbs")),
+    ("drift-iam-policy.json", include_str!("../templates/drift-iam-policy.json.hbs")),
+    ("gitignore", include_str!("../templates/gitignore.hbs")),
+    ("iam-authenticator-config.yaml", include_str!("../templates/iam-authenticator-config.yaml.hbs")),
+    ("kaws.tf", include_str!("../templates/kaws.tf.hbs")),
+    ("rbac.yml", include_str!("../templates/rbac.yml.hbs")),
+    ("terraform.tfvars", include_str!("../templates/terraform.tfvars.hbs")),
+];
+
+/// The directory in a kaws repository whose templates take precedence over the built-in ones.
+pub const REPOSITORY_TEMPLATE_DIR: &str = "templates";
+
+/// Handlebars templates for every file kaws generates.
+///
+/// A template named NAME is read from NAME.hbs in the override directory if that file exists, and
+/// otherwise from the copy built into kaws.
+pub struct Templates {
+    registry: Handlebars<'static>,
+}
+
+impl Templates {
+    pub fn new(override_dir: Option<&str>) -> Result<Self, KawsError> {
+        let mut registry = Handlebars::new();
+
+        registry.register_escape_fn(no_escape);
+        registry.set_strict_mode(true);
+
+        for &(name, builtin) in BUILTIN_TEMPLATES.iter() {
+            let source = match override_dir {
+                Some(dir) => read_override(&format!("{}/{}.hbs", dir, name))?,
+                None => None,
+            };
+
+            if source.is_some() {
+                debug!("Using template {} from {}", name, override_dir.unwrap_or_default());
+            }
+
+            registry.register_template_string(name, source.as_deref().unwrap_or(builtin))?;
+        }
+
+        Ok(Templates {
+            registry,
+        })
+    }
+
+    /// Templates for commands run inside a kaws repository.
+    pub fn for_repository() -> Result<Self, KawsError> {
+        Self::new(Some(REPOSITORY_TEMPLATE_DIR))
+    }
+
+    /// Copies every override template in `from` into `to`.
+    pub fn copy_overrides(from: &str, to: &str) -> Result<(), KawsError> {
+        for &(name, _) in BUILTIN_TEMPLATES.iter() {
+            let source = format!("{}/{}.hbs", from, name);
+
+            if Path::new(&source).is_file() {
+                create_dir_all(to)?;
+                copy(&source, format!("{}/{}.hbs", to, name))?;
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn render<T: Serialize>(&self, name: &str, data: &T) -> Result<String, KawsError> {
+        Ok(self.registry.render(name, data)?)
+    }
+}
+
+fn read_override(path: &str) -> Result<Option<String>, KawsError> {
+    let mut file = match File::open(path) {
+        Ok(file) => file,
+        Err(ref error) if error.kind() == ErrorKind::NotFound => return Ok(None),
+        Err(error) => return Err(error.into()),
+    };
+
+    let mut source = String::new();
+    file.read_to_string(&mut source)?;
+
+    Ok(Some(source))
+}
@@ -1,193 +1,386 @@
+use std::fs::{File, create_dir_all, symlink_metadata};
+use std::io::{BufRead, BufReader, ErrorKind, Read, Write};
 use std::process::{Command, Stdio};
 
 use clap::ArgMatches;
-use rusoto_core::{ChainProvider, ProvideAwsCredentials};
 
-use aws::credentials_provider;
+use aws::{Access, cluster_credentials_provider};
+use cache::{self, file_inputs};
+use component_flags::COMPONENTS;
 use error::{KawsError, KawsResult};
+use message::Message;
+use metadata::ClusterMetadata;
+use metrics::record;
+use module::write_embedded_module;
+use paths::from_invocation;
+use provider::{CloudProvider, cloud_provider};
+use secret_sinks::{self, SINK_ROLES};
+use snapshot::Snapshot;
+use tfvars::{Tfvars, with_values};
+use userdata::sign_userdata;
+
+/// The directory holding each cluster's Terraform working directory.
+pub const DATA_DIR_ROOT: &str = ".kaws/terraform";
+
+/// The .terraform directory older versions of kaws shared between all clusters.
+pub const LEGACY_DATA_DIR: &str = ".terraform";
+
+/// The directory Terraform keeps a cluster's downloaded modules and providers in, so that nothing
+/// `terraform init` writes for one cluster is seen by another.
+pub fn data_dir(cluster: &str) -> String {
+    format!("{}/{}", DATA_DIR_ROOT, cluster)
+}
 
 pub struct Terraform<'a> {
-    aws_credentials_provider: ChainProvider,
+    aws_credentials_path: Option<String>,
+    aws_credentials_profile: Option<&'a str>,
     cluster: &'a str,
     output: Option<&'a str>,
+    override_budget: bool,
+    skip_ami_check: bool,
     terraform_args: Option<Vec<&'a str>>,
 }
 
 impl<'a> Terraform<'a> {
     pub fn new(matches: &'a ArgMatches) -> Terraform<'a> {
         Terraform {
-            aws_credentials_provider: credentials_provider(
-                matches.value_of("aws-credentials-path"),
-                matches.value_of("aws-credentials-profile"),
-            ),
+            aws_credentials_path: matches.value_of("aws-credentials-path").map(from_invocation),
+            aws_credentials_profile: matches.value_of("aws-credentials-profile"),
             cluster: matches.value_of("cluster").expect("clap should have required cluster"),
             output: matches.value_of("output"),
+            override_budget: matches.is_present("override-budget"),
+            skip_ami_check: matches.is_present("skip-ami-check"),
             terraform_args: matches.values_of("terraform-args").map(|values| values.collect()),
         }
     }
 
     pub fn apply(&mut self) -> KawsResult {
+        self.sync_metadata_tfvars()?;
+
+        let provider = self.cloud_provider(Access::ReadWrite)?;
+
+        if !self.skip_ami_check {
+            self.check_ami(&*provider)?;
+        }
+
+        if !self.override_budget {
+            self.check_budget(&*provider)?;
+        }
+
+        let terraform_env = provider.terraform_env()?;
+
         self.init()?;
 
-        let mut command = Command::new("terraform");
+        let mut command = self.command();
 
-        command.args(&[
+        command.args([
             "apply",
             "-backup=-",
             &format!("-state=clusters/{}/terraform.tfstate", self.cluster),
             &format!("-var-file=clusters/{}/terraform.tfvars", self.cluster),
         ]);
 
-        if self.terraform_args.is_some() {
-            command.args(self.terraform_args.as_ref().unwrap());
+        if let Some(ref terraform_args) = self.terraform_args {
+            command.args(terraform_args);
         }
 
-        command.arg("terraform").env(
-            "AWS_ACCESS_KEY_ID",
-            self.aws_credentials_provider.credentials().expect(
-                "Failed to get AWS credentials"
-            ).aws_access_key_id(),
-        ).env(
-            "AWS_SECRET_ACCESS_KEY",
-            self.aws_credentials_provider.credentials().expect(
-                "Failed to get AWS credentials"
-            ).aws_secret_access_key(),
-        );
+        command.arg("terraform").envs(terraform_env);
 
-        command.status()?;
+        // A configuration that applied cleanly is one `kaws cluster rollback` can return to. The
+        // cloud-configs it uploaded are signed first, since servers won't boot until they are.
+        if command.status()?.success() {
+            let region = self.tfvar(&self.tfvars()?, "kaws_region")?.to_owned();
+
+            sign_userdata(self.cluster, &*provider, &region)?;
+            Snapshot::record(self.cluster)?;
+        }
 
         Ok(None)
     }
 
     pub fn destroy(&mut self) -> KawsResult {
+        let terraform_env = self.cloud_provider(Access::ReadWrite)?.terraform_env()?;
+
         self.init()?;
 
-        let mut command = Command::new("terraform");
+        let mut command = self.command();
 
-        command.args(&[
+        command.args([
             "destroy",
             "-backup=-",
             &format!("-state=clusters/{}/terraform.tfstate", self.cluster),
             &format!("-var-file=clusters/{}/terraform.tfvars", self.cluster),
         ]);
 
-        if self.terraform_args.is_some() {
-            command.args(self.terraform_args.as_ref().unwrap());
+        if let Some(ref terraform_args) = self.terraform_args {
+            command.args(terraform_args);
         }
 
-        command.arg("terraform").env(
-            "AWS_ACCESS_KEY_ID",
-            self.aws_credentials_provider.credentials().expect(
-                "Failed to get AWS credentials"
-            ).aws_access_key_id(),
-        ).env(
-            "AWS_SECRET_ACCESS_KEY",
-            self.aws_credentials_provider.credentials().expect(
-                "Failed to get AWS credentials"
-            ).aws_secret_access_key(),
-        );
+        command.arg("terraform").envs(terraform_env);
 
         let exit_status = command.status()?;
 
         if exit_status.success() {
-            Ok(Some(format!(
-                "Destroyed cluster \"{}\"! You should remove clusters/{} from Git.",
-                self.cluster,
-                self.cluster,
-            )))
+            Ok(Some(Message::ClusterDestroyed {
+                cluster: self.cluster.to_owned(),
+            }))
         } else {
-            Err(KawsError::new(format!("Failed to destroy cluster!")))
+            Err(KawsError::new(Message::TerraformDestroyFailed {
+                cluster: self.cluster.to_owned(),
+            }))
+        }
+    }
+
+    /// Terraform's graph of the resources in the cluster's configuration, in the DOT language.
+    pub fn graph(&mut self) -> Result<String, KawsError> {
+        self.init()?;
+
+        let output = self.command()
+            .args(["graph", "-module-depth=-1", "terraform"])
+            .stderr(Stdio::inherit())
+            .output()?;
+
+        if output.status.success() {
+            Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+        } else {
+            Err(KawsError::new(Message::TerraformGraphFailed {
+                cluster: self.cluster.to_owned(),
+            }))
         }
     }
 
     pub fn output(&mut self) -> KawsResult {
+        let state_path = format!("clusters/{}/terraform.tfstate", self.cluster);
+
+        // A single output, which other commands look up, is cached until the state changes.
+        let cached = match self.output {
+            Some(output) => Some((
+                format!("outputs/{}/{}", self.cluster, output),
+                file_inputs(&[&state_path])?,
+            )),
+            None => None,
+        };
+
+        if let Some((ref cache_name, ref inputs)) = cached {
+            if let Some(value) = cache::read::<String>(cache_name, inputs) {
+                print!("{}", value);
+
+                return Ok(None);
+            }
+        }
+
         self.init()?;
 
-        let mut command = Command::new("terraform");
+        let mut command = self.command();
 
-        command.args(&[
+        command.args([
             "output",
             "-module=kaws",
-            &format!("-state=clusters/{}/terraform.tfstate", self.cluster),
+            &format!("-state={}", state_path),
         ]);
 
         if let Some(output) = self.output {
             command.arg(output);
         }
 
-        command.status()?;
+        match cached {
+            Some((cache_name, inputs)) => {
+                let output = command.stderr(Stdio::inherit()).output()?;
+                let value = String::from_utf8_lossy(&output.stdout);
+
+                print!("{}", value);
+
+                // Commands looking the output up tell a failure apart from an empty value.
+                if !output.status.success() {
+                    return Err(KawsError::new(Message::TerraformOutputFailed {
+                        cluster: self.cluster.to_owned(),
+                        output: self.output.unwrap_or_default().to_owned(),
+                    }));
+                }
+
+                if !inputs.is_empty() {
+                    cache::write(&cache_name, inputs, &value);
+                }
+            }
+            None => {
+                command.status()?;
+            }
+        }
 
         Ok(None)
     }
 
     pub fn plan(&mut self) -> KawsResult {
+        self.sync_metadata_tfvars()?;
+
+        let provider = self.cloud_provider(Access::ReadOnly)?;
+
+        if !self.override_budget {
+            self.check_budget(&*provider)?;
+        }
+
+        let terraform_env = provider.terraform_env()?;
+
         self.init()?;
 
-        let mut command = Command::new("terraform");
+        let mut command = self.command();
 
-        command.args(&[
+        command.args([
             "plan",
             "-module-depth=-1",
             &format!("-state=clusters/{}/terraform.tfstate", self.cluster),
             &format!("-var-file=clusters/{}/terraform.tfvars", self.cluster),
         ]);
 
-        if self.terraform_args.is_some() {
-            command.args(self.terraform_args.as_ref().unwrap());
+        if let Some(ref terraform_args) = self.terraform_args {
+            command.args(terraform_args);
         }
 
-        command.arg("terraform").env(
-            "AWS_ACCESS_KEY_ID",
-            self.aws_credentials_provider.credentials().expect(
-                "Failed to get AWS credentials"
-            ).aws_access_key_id(),
-        ).env(
-            "AWS_SECRET_ACCESS_KEY",
-            self.aws_credentials_provider.credentials().expect(
-                "Failed to get AWS credentials"
-            ).aws_secret_access_key(),
-        );
+        command.arg("terraform").envs(terraform_env);
 
-        command.status()?;
+        // The output is passed through line by line so the summary of changes can be recorded.
+        let mut child = command.stdout(Stdio::piped()).spawn()?;
+        let stdout = child.stdout.take().expect("stdout should have been piped");
 
-        Ok(None)
+        for line in BufReader::new(stdout).lines() {
+            let line = line?;
+
+            println!("{}", line);
+
+            if let Some(counts) = plan_summary(&line) {
+                for (action, count) in ["add", "change", "destroy"].iter().zip(counts.iter()) {
+                    record(
+                        "kaws_plan_resources",
+                        "Resources the last plan would add, change, or destroy.",
+                        &[("action", action)],
+                        *count as f64,
+                    );
+                }
+            }
+        }
+
+        // With -detailed-exitcode, Terraform exits with 2 when the plan has changes.
+        match child.wait()?.code() {
+            Some(0) => Ok(None),
+            Some(2) => Err(KawsError::new(Message::ClusterDrifted {
+                cluster: self.cluster.to_owned(),
+            })),
+            _ => Err(KawsError::new(Message::TerraformPlanFailed {
+                cluster: self.cluster.to_owned(),
+            })),
+        }
     }
 
     pub fn refresh(&mut self) -> KawsResult {
+        let terraform_env = self.cloud_provider(Access::ReadOnly)?.terraform_env()?;
+
         self.init()?;
 
-        let mut command = Command::new("terraform");
+        let mut command = self.command();
 
-        command.args(&[
+        command.args([
             "refresh",
             "-backup=-",
             &format!("-state=clusters/{}/terraform.tfstate", self.cluster),
             &format!("-var-file=clusters/{}/terraform.tfvars", self.cluster),
         ]);
 
-        if self.terraform_args.is_some() {
-            command.args(self.terraform_args.as_ref().unwrap());
+        if let Some(ref terraform_args) = self.terraform_args {
+            command.args(terraform_args);
         }
 
-        command.arg("terraform").env(
-            "AWS_ACCESS_KEY_ID",
-            self.aws_credentials_provider.credentials().expect(
-                "Failed to get AWS credentials"
-            ).aws_access_key_id(),
-        ).env(
-            "AWS_SECRET_ACCESS_KEY",
-            self.aws_credentials_provider.credentials().expect(
-                "Failed to get AWS credentials"
-            ).aws_secret_access_key(),
-        );
+        command.arg("terraform").envs(terraform_env);
 
         command.status()?;
 
         Ok(None)
     }
 
+    // Private
+
+    // The cloud provider recorded in the cluster's metadata. Plan and refresh only need to read
+    // from AWS, so they can use the cluster's read-only credentials.
+    fn cloud_provider(&self, access: Access) -> Result<Box<dyn CloudProvider>, KawsError> {
+        let metadata = ClusterMetadata::from_file(
+            &format!("clusters/{}/metadata.json", self.cluster),
+        )?;
+
+        Ok(cloud_provider(metadata.cloud_provider, cluster_credentials_provider(
+            self.cluster,
+            access,
+            self.aws_credentials_path.as_deref(),
+            self.aws_credentials_profile,
+        )?))
+    }
+
+    // The AMI or instance size may have been changed in the tfvars since `kaws cluster init`.
+    fn check_ami(&self, provider: &dyn CloudProvider) -> KawsResult {
+        let tfvars = self.tfvars()?;
+
+        log_wrap!("Checking that the AMI supports the instance size", {
+            provider.ensure_image_supports_instance_size(
+                self.tfvar(&tfvars, "kaws_region")?,
+                self.tfvar(&tfvars, "kaws_coreos_ami")?,
+                self.tfvar(&tfvars, "kaws_instance_size")?,
+            )?;
+        });
+
+        Ok(None)
+    }
+
+    // The cost is estimated at the autoscaling groups' maximum sizes, which is what a mistaken
+    // scale-up changes.
+    fn check_budget(&self, provider: &dyn CloudProvider) -> KawsResult {
+        let metadata = ClusterMetadata::from_file(
+            &format!("clusters/{}/metadata.json", self.cluster),
+        )?;
+
+        let ceiling = match metadata.max_monthly_cost {
+            Some(ceiling) => ceiling,
+            None => return Ok(None),
+        };
+
+        let tfvars = self.tfvars()?;
+        let instance_size = self.tfvar(&tfvars, "kaws_instance_size")?;
+        let estimate;
+
+        log_wrap!("Estimating the cluster's monthly cost", {
+            estimate = provider.monthly_instance_cost(
+                self.tfvar(&tfvars, "kaws_region")?,
+                &[
+                    // The etcd and bastion servers are fixed by the Terraform module.
+                    (instance_size, 3),
+                    ("t2.micro", 1),
+                    (instance_size, self.tfvar_count(&tfvars, "kaws_masters_max_size")?),
+                    (instance_size, self.tfvar_count(&tfvars, "kaws_nodes_max_size")?),
+                ],
+            )?;
+        });
+
+        if estimate > ceiling {
+            Err(KawsError::new(Message::BudgetExceeded {
+                cluster: self.cluster.to_owned(),
+                estimate,
+                ceiling,
+            }))
+        } else {
+            Ok(None)
+        }
+    }
+
+    fn command(&self) -> Command {
+        let mut command = Command::new("terraform");
+
+        command.env("TF_DATA_DIR", data_dir(self.cluster));
+
+        command
+    }
+
     fn init(&self) -> KawsResult {
-        let exit_status = Command::new("terraform").args(&[
+        self.prepare_data_dir()?;
+        write_embedded_module()?;
+
+        let exit_status = self.command().args([
             "init",
             "terraform",
         ]).stdout(Stdio::null()).status()?;
@@ -195,7 +388,143 @@ impl<'a> Terraform<'a> {
         if exit_status.success() {
             Ok(None)
         } else {
-            Err(KawsError::new("Failed to initialize Terraform!".to_string()))
+            Err(KawsError::new(Message::TerraformInitFailed))
+        }
+    }
+
+    // Creates the cluster's working directory if needed. Every directory on the way must be a real
+    // directory, since a symlink could point Terraform at another cluster's working files.
+    fn prepare_data_dir(&self) -> KawsResult {
+        let data_dir = data_dir(self.cluster);
+
+        for path in &[".kaws", DATA_DIR_ROOT, &data_dir] {
+            match symlink_metadata(path) {
+                Ok(metadata) => if !metadata.is_dir() {
+                    return Err(KawsError::new(Message::TerraformDataDirInvalid {
+                        path: path.to_string(),
+                    }));
+                },
+                Err(ref error) if error.kind() == ErrorKind::NotFound => {}
+                Err(error) => return Err(error.into()),
+            }
+        }
+
+        create_dir_all(&data_dir)?;
+
+        Ok(None)
+    }
+
+    // Checks the Kubernetes components' flags and the servers' secret sinks in the cluster's
+    // metadata, the flags against its version of Kubernetes, that the k8s CA's key can be given
+    // to the masters if they sign kubelets' certificates, and writes them to terraform.tfvars,
+    // where the built-in module reads them, along with whether the servers may decrypt files
+    // without their encryption contexts. The file is left alone when they're already there,
+    // including when there are none.
+    fn sync_metadata_tfvars(&self) -> KawsResult {
+        let metadata = ClusterMetadata::from_file(
+            &format!("clusters/{}/metadata.json", self.cluster),
+        )?;
+        let tfvars_path = format!("clusters/{}/terraform.tfvars", self.cluster);
+        let mut contents = String::new();
+
+        File::open(&tfvars_path)?.read_to_string(&mut contents)?;
+
+        let tfvars = Tfvars::parse(&contents);
+
+        metadata.check_tls_bootstrapping(tfvars.get("kaws_tls_bootstrapping") == Some("true"))?;
+
+        let kubernetes_version = self.tfvar(&tfvars, "kaws_version")?;
+        let mut values = vec![];
+
+        for component in &COMPONENTS {
+            let value = component.tfvars_value(&metadata, kubernetes_version)?;
+
+            if tfvars.get_raw(component.tfvar).unwrap_or("[]") != value {
+                values.push((component.tfvar, value));
+            }
+        }
+
+        for &(role, tfvar) in &SINK_ROLES {
+            let value = secret_sinks::tfvars_value(&metadata, role)?;
+
+            if tfvars.get_raw(tfvar).unwrap_or("[]") != value {
+                values.push((tfvar, value));
+            }
+        }
+
+        if metadata.encryption_contexts_required &&
+            tfvars.get("kaws_encryption_contexts_required") != Some("true") {
+            values.push(("kaws_encryption_contexts_required", "\"true\"".to_owned()));
+        }
+
+        if !values.is_empty() {
+            let values: Vec<(&str, &str)> = values.iter()
+                .map(|&(key, ref value)| (key, value.as_str()))
+                .collect();
+
+            log_wrap!("Updating terraform.tfvars from the cluster's metadata", {
+                File::create(&tfvars_path)?.write_all(with_values(&contents, &values).as_bytes())?;
+            });
+        }
+
+        Ok(None)
+    }
+
+    fn tfvars(&self) -> Result<Tfvars, KawsError> {
+        Tfvars::from_file(&format!("clusters/{}/terraform.tfvars", self.cluster))
+            .map_err(|error| self.tfvars_unreadable(error.to_string()))
+    }
+
+    fn tfvar<'t>(&self, tfvars: &'t Tfvars, key: &str) -> Result<&'t str, KawsError> {
+        tfvars.get(key).ok_or_else(|| self.tfvars_unreadable(format!("{} is not set", key)))
+    }
+
+    fn tfvar_count(&self, tfvars: &Tfvars, key: &str) -> Result<u64, KawsError> {
+        self.tfvar(tfvars, key)?.parse().map_err(|_| {
+            self.tfvars_unreadable(format!("{} is not a whole number", key))
+        })
+    }
+
+    fn tfvars_unreadable(&self, error: String) -> KawsError {
+        KawsError::new(Message::TfvarsUnreadable {
+            cluster: self.cluster.to_owned(),
+            error,
+        })
+    }
+}
+
+// The numbers of resources Terraform's summary of a plan says it would add, change, and destroy,
+// e.g. "Plan: 1 to add, 2 to change, 0 to destroy.", or none if `line` isn't the summary.
+fn plan_summary(line: &str) -> Option<[u64; 3]> {
+    let line = strip_colors(line);
+    let line = line.trim();
+
+    if line.starts_with("No changes.") {
+        return Some([0, 0, 0]);
+    }
+
+    let mut counts = line.strip_prefix("Plan: ")?.split(", ")
+        .map(|count| count.split(' ').next().and_then(|count| count.parse().ok()));
+
+    Some([counts.next()??, counts.next()??, counts.next()??])
+}
+
+// Removes the ANSI escape sequences Terraform colors its output with.
+fn strip_colors(line: &str) -> String {
+    let mut stripped = String::new();
+    let mut chars = line.chars();
+
+    while let Some(c) = chars.next() {
+        if c == '\u{1b}' {
+            for c in chars.by_ref() {
+                if c.is_ascii_alphabetic() {
+                    break;
+                }
+            }
+        } else {
+            stripped.push(c);
         }
     }
+
+    stripped
 }
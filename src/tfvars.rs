@@ -0,0 +1,79 @@
+use std::fs::File;
+use std::io::Read;
+
+use error::KawsError;
+
+/// A minimal reader for the terraform.tfvars files kaws generates.
+///
+/// Only the `key = value` lines written by `kaws cluster init` are understood. Comments and blank
+/// lines are skipped, and values are kept as written, including quotes and brackets.
+pub struct Tfvars {
+    entries: Vec<(String, String)>,
+}
+
+impl Tfvars {
+    pub fn from_file(path: &str) -> Result<Self, KawsError> {
+        let mut file = File::open(path)?;
+        let mut contents = String::new();
+        file.read_to_string(&mut contents)?;
+
+        Ok(Self::parse(&contents))
+    }
+
+    pub fn parse(contents: &str) -> Self {
+        let entries = contents.lines().filter_map(|line| {
+            let line = line.trim();
+
+            if line.is_empty() || line.starts_with('#') {
+                return None;
+            }
+
+            let mut parts = line.splitn(2, '=');
+
+            match (parts.next(), parts.next()) {
+                (Some(key), Some(value)) => Some((key.trim().to_owned(), value.trim().to_owned())),
+                _ => None,
+            }
+        }).collect();
+
+        Tfvars {
+            entries,
+        }
+    }
+
+    /// Every key and raw value, in the order they appear in the file.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.entries.iter().map(|(key, value)| (key.as_str(), value.as_str()))
+    }
+
+    /// The raw value for `key`, as written in the file.
+    pub fn get_raw(&self, key: &str) -> Option<&str> {
+        self.entries.iter().find(|&(k, _)| k == key).map(|(_, value)| value.as_str())
+    }
+
+    /// The value for `key` with surrounding quotes removed.
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.get_raw(key).map(|value| value.trim_matches('"'))
+    }
+}
+
+/// The contents of a terraform.tfvars file with each of `values` set, replacing the key's line if
+/// it has one and appending it otherwise. Values are raw, so strings must include their quotes.
+pub fn with_values(contents: &str, values: &[(&str, &str)]) -> String {
+    let mut lines: Vec<String> = contents.lines().map(str::to_owned).collect();
+
+    for &(key, value) in values {
+        let line = format!("{} = {}", key, value);
+
+        let existing = lines.iter().position(|existing| {
+            existing.split('=').next().map(str::trim) == Some(key)
+        });
+
+        match existing {
+            Some(index) => lines[index] = line,
+            None => lines.push(line),
+        }
+    }
+
+    lines.iter().map(|line| format!("{}\n", line)).collect()
+}
@@ -0,0 +1,146 @@
+use std::path::Path;
+use std::process::Command;
+
+use clap::ArgMatches;
+
+use error::{KawsError, KawsResult};
+use message::Message;
+use process::execute_child_process;
+use runtime::runtime_dir;
+use tfvars::Tfvars;
+
+pub struct Tunnel<'a> {
+    close: bool,
+    cluster: &'a str,
+    port: &'a str,
+}
+
+impl<'a> Tunnel<'a> {
+    pub fn new(matches: &'a ArgMatches) -> Self {
+        Tunnel {
+            close: matches.is_present("close"),
+            cluster: matches.value_of("cluster").expect("clap should have required cluster"),
+            port: matches.value_of("port").unwrap_or("6443"),
+        }
+    }
+
+    pub fn run(&self) -> KawsResult {
+        let domain = self.domain()?;
+
+        if self.close {
+            self.close(&domain)
+        } else {
+            self.open(&domain)
+        }
+    }
+
+    // Private
+
+    fn open(&self, domain: &str) -> KawsResult {
+        let mut command = Command::new("ssh");
+
+        // Runs in the background once the forward is listening, with a control socket that
+        // `--close` uses to find it again.
+        command.args([
+            "-f",
+            "-N",
+            "-M",
+            "-S",
+            &self.control_socket()?,
+            "-o",
+            "ExitOnForwardFailure=yes",
+            "-L",
+            &format!("127.0.0.1:{}:kubernetes.{}:443", self.port, domain),
+        ]);
+        command.args(self.known_hosts_args());
+        command.arg(format!("core@bastion.{}", domain));
+
+        if !command.status()?.success() {
+            return Err(KawsError::new(Message::ChildProcessFailed {
+                command: format!("{:?}", command),
+            }));
+        }
+
+        log_wrap!("Pointing kubectl at the tunnel", {
+            // The server certificate is still checked against the API's own name.
+            execute_child_process("kubectl", &[
+                "config",
+                "set-cluster",
+                &format!("kaws-{}", self.cluster),
+                &format!("--server=https://127.0.0.1:{}", self.port),
+                &format!("--tls-server-name=kubernetes.{}", domain),
+            ])?;
+        });
+
+        Ok(Some(Message::TunnelOpened {
+            cluster: self.cluster.to_owned(),
+            port: self.port.to_owned(),
+        }))
+    }
+
+    fn close(&self, domain: &str) -> KawsResult {
+        log_wrap!("Pointing kubectl back at the Kubernetes API", {
+            execute_child_process("kubectl", &[
+                "config",
+                "set-cluster",
+                &format!("kaws-{}", self.cluster),
+                &format!("--server=https://kubernetes.{}", domain),
+            ])?;
+
+            execute_child_process("kubectl", &[
+                "config",
+                "unset",
+                &format!("clusters.kaws-{}.tls-server-name", self.cluster),
+            ])?;
+        });
+
+        let control_socket = self.control_socket()?;
+
+        if Path::new(&control_socket).exists() {
+            execute_child_process("ssh", &[
+                "-S",
+                &control_socket,
+                "-O",
+                "exit",
+                &format!("core@bastion.{}", domain),
+            ])?;
+        }
+
+        Ok(Some(Message::TunnelClosed {
+            cluster: self.cluster.to_owned(),
+        }))
+    }
+
+    // In the runtime directory rather than an invocation's own, since `--close` is another
+    // invocation.
+    fn control_socket(&self) -> Result<String, KawsError> {
+        Ok(runtime_dir()?.join(format!("tunnel-{}.sock", self.cluster)).to_string_lossy()
+            .into_owned())
+    }
+
+    fn domain(&self) -> Result<String, KawsError> {
+        let tfvars = Tfvars::from_file(&format!("clusters/{}/terraform.tfvars", self.cluster))
+            .map_err(|error| KawsError::new(Message::TfvarsUnreadable {
+                cluster: self.cluster.to_owned(),
+                error: error.to_string(),
+            }))?;
+
+        match tfvars.get("kaws_domain") {
+            Some(domain) => Ok(domain.to_owned()),
+            None => Err(KawsError::new(Message::TfvarsUnreadable {
+                cluster: self.cluster.to_owned(),
+                error: "kaws_domain is not set".to_owned(),
+            })),
+        }
+    }
+
+    fn known_hosts_args(&self) -> Vec<String> {
+        let known_hosts_path = format!("clusters/{}/ssh_known_hosts", self.cluster);
+
+        if Path::new(&known_hosts_path).exists() {
+            vec!["-o".to_owned(), format!("UserKnownHostsFile={}", known_hosts_path)]
+        } else {
+            vec![]
+        }
+    }
+}
@@ -0,0 +1,156 @@
+use std::convert::TryFrom;
+
+use chrono::Duration;
+
+/// Bytes in a gibibyte, the unit EBS volume sizes are given in.
+pub const GIBIBYTE: u64 = 1 << 30;
+
+/// The largest gp2 EBS volume, in gibibytes.
+pub const MAX_VOLUME_GIBIBYTES: u64 = 16384;
+
+/// Parses a duration made of whole numbers of units, e.g. "90d", "8h", or "1h30m". The units are
+/// "w" (7 days), "d" (24 hours), "h", "m", "s", and "ms". Go-style durations without fractions,
+/// which kaws has always accepted, are a subset.
+///
+/// Input that could be read more than one way is rejected rather than guessed at: numbers without
+/// a unit, fractions, and months and years, whose lengths vary.
+pub fn parse_duration(value: &str) -> Result<Duration, String> {
+    if value.is_empty() {
+        return Err("A duration can't be empty, e.g. \"90d\" or \"8h\"".to_owned());
+    }
+
+    let mut total = Duration::zero();
+
+    for (amount, unit) in quantities(value, "1h30m\" for 1.5h")? {
+        let amount = i64::try_from(amount).map_err(|_| too_large(value))?;
+
+        total = total.checked_add(&match unit {
+            "w" => Duration::try_weeks(amount),
+            "d" => Duration::try_days(amount),
+            "h" => Duration::try_hours(amount),
+            "m" => Duration::try_minutes(amount),
+            "s" => Duration::try_seconds(amount),
+            "ms" => Duration::try_milliseconds(amount),
+            "mo" | "M" | "y" | "Y" => return Err(format!(
+                "\"{}\" is in months or years, which vary in length; give it in days instead, \
+                e.g. \"365d\"",
+                value,
+            )),
+            "" => return Err(format!(
+                "\"{}\" has no unit; add one, e.g. \"{}s\" or \"{}d\"",
+                value,
+                amount,
+                amount,
+            )),
+            _ => return Err(format!(
+                "\"{}\" isn't a unit of time; use w, d, h, m, s, or ms, e.g. \"90d\" or \"8h\"",
+                unit,
+            )),
+        }.ok_or_else(|| too_large(value))?).ok_or_else(|| too_large(value))?;
+    }
+
+    Ok(total)
+}
+
+/// Parses a size in bytes with a binary unit, e.g. "200Gi" or "512Mi". The units are "Ki", "Mi",
+/// "Gi", and "Ti", powers of 1024, as Kubernetes uses them.
+///
+/// Decimal units like "G" and "GB" are rejected, since they're often used to mean binary ones, as
+/// are numbers without a unit.
+pub fn parse_size(value: &str) -> Result<u64, String> {
+    if value.is_empty() {
+        return Err("A size can't be empty, e.g. \"200Gi\"".to_owned());
+    }
+
+    let (amount, unit) = match quantities(value, "1536Mi\" for 1.5Gi")?[..] {
+        [quantity] => quantity,
+        _ => return Err(format!(
+            "\"{}\" should be one whole number followed by a unit, e.g. \"200Gi\"",
+            value,
+        )),
+    };
+    let multiplier: u64 = match unit {
+        "Ki" => 1 << 10,
+        "Mi" => 1 << 20,
+        "Gi" => GIBIBYTE,
+        "Ti" => 1 << 40,
+        "k" | "K" | "KB" | "M" | "MB" | "G" | "GB" | "T" | "TB" => return Err(format!(
+            "\"{}\" could mean powers of 1000 or of 1024; use {}i for powers of 1024, e.g. \
+            \"{}{}i\"",
+            value,
+            &unit.to_uppercase()[..1],
+            amount,
+            &unit.to_uppercase()[..1],
+        )),
+        "" => return Err(format!("\"{}\" has no unit; add one, e.g. \"{}Gi\"", value, amount)),
+        _ => return Err(format!(
+            "\"{}\" isn't a unit of size; use Ki, Mi, Gi, or Ti, e.g. \"200Gi\"",
+            unit,
+        )),
+    };
+
+    amount.checked_mul(multiplier).ok_or_else(|| too_large(value))
+}
+
+/// Parses the size of an EBS volume, as accepted by `parse_size`, into whole gibibytes between 1
+/// and `MAX_VOLUME_GIBIBYTES`.
+pub fn parse_volume_size(value: &str) -> Result<u64, String> {
+    let bytes = parse_size(value)?;
+
+    if bytes % GIBIBYTE != 0 {
+        return Err(format!(
+            "\"{}\" isn't a whole number of gibibytes, which EBS volumes are sized in, e.g. \
+            \"20Gi\"",
+            value,
+        ));
+    }
+
+    match bytes / GIBIBYTE {
+        gibibytes @ 1..=MAX_VOLUME_GIBIBYTES => Ok(gibibytes),
+        _ => Err(format!(
+            "\"{}\" isn't between 1Gi and {}Gi, the sizes of EBS volumes",
+            value,
+            MAX_VOLUME_GIBIBYTES,
+        )),
+    }
+}
+
+// Private
+
+// Splits `value` into whole numbers and the units after them, e.g. "1h30m" into (1, "h") and
+// (30, "m"). The last unit may be empty. `example`, the end of a quoted value and what it stands
+// for, shows what to give instead of a fraction.
+fn quantities<'a>(value: &'a str, example: &str) -> Result<Vec<(u64, &'a str)>, String> {
+    let mut quantities = vec![];
+    let mut rest = value;
+
+    while !rest.is_empty() {
+        let digits = rest.find(|c: char| !c.is_ascii_digit()).unwrap_or(rest.len());
+
+        if digits == 0 {
+            return Err(format!("\"{}\" should be whole numbers, each followed by a unit", value));
+        }
+
+        let amount = rest[..digits].parse().map_err(|_| too_large(value))?;
+        let unit_length = rest[digits..].find(|c: char| c.is_ascii_digit() || c == '.')
+            .unwrap_or(rest.len() - digits);
+        let unit = &rest[digits..digits + unit_length];
+
+        if rest[digits + unit_length..].starts_with('.') {
+            return Err(format!(
+                "\"{}\" has a fraction; use smaller units instead, e.g. \"{}",
+                value,
+                example,
+            ));
+        }
+
+        quantities.push((amount, unit));
+        rest = &rest[digits + unit_length..];
+    }
+
+    Ok(quantities)
+}
+
+fn too_large(value: &str) -> String {
+    format!("\"{}\" is too large", value)
+}
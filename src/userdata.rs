@@ -0,0 +1,102 @@
+use std::fs::File;
+use std::io::ErrorKind;
+use std::path::Path;
+
+use openssl::hash::MessageDigest;
+use openssl::pkey::PKey;
+use openssl::sha::sha256;
+use openssl::sign::Signer;
+use serde_json::{Value, from_reader};
+
+use error::KawsError;
+use metadata::ClusterMetadata;
+use pki::PrivateKey;
+use provider::CloudProvider;
+
+/// The prefix of the objects in a cluster's bucket that hold its cloud-configs' signatures.
+///
+/// Each signature is named after the cloud-config and the SHA-256 digest of the revision it signs,
+/// e.g. "signatures/master_cloud_config.yml/DIGEST", so that a server booting an older revision,
+/// as masters and nodes do until their launch configuration is replaced, still finds its own.
+const SIGNATURES_PREFIX: &str = "signatures";
+
+/// The KMS-encrypted private key the cluster's cloud-configs are signed with.
+pub fn userdata_signing_encrypted_key_path(cluster: &str) -> String {
+    format!("clusters/{}/userdata-signing-key-encrypted.base64", cluster)
+}
+
+/// Signs each cloud-config in the cluster's Terraform state with its userdata signing key, and
+/// uploads the signatures to the bucket the cloud-configs are in, where servers fetch them to
+/// verify what they downloaded before running it.
+///
+/// Nothing is signed for a cluster without a key, i.e. one whose servers don't verify.
+pub fn sign_userdata(cluster: &str, provider: &dyn CloudProvider, region: &str)
+-> Result<(), KawsError> {
+    let key_path = userdata_signing_encrypted_key_path(cluster);
+
+    if !Path::new(&key_path).exists() {
+        return Ok(());
+    }
+
+    let objects = cloud_configs(cluster)?;
+
+    if objects.is_empty() {
+        return Ok(());
+    }
+
+    log_wrap!("Signing the servers' cloud-configs", {
+        let metadata = ClusterMetadata::from_file(&format!("clusters/{}/metadata.json", cluster))?;
+        let mut encryptor = provider.encryptor(region, None)?
+            .with_contexts_required(metadata.encryption_contexts_required);
+        let key = PrivateKey::from_file(&mut encryptor, &key_path)?;
+        let key = PKey::private_key_from_pem(key.as_bytes())?;
+
+        for (bucket, name, content) in objects {
+            let mut signer = Signer::new(MessageDigest::sha256(), &key)?;
+
+            signer.update(content.as_bytes())?;
+
+            let digest: String = sha256(content.as_bytes()).iter()
+                .map(|byte| format!("{:02x}", byte))
+                .collect();
+            let signature_name = format!("{}/{}/{}", SIGNATURES_PREFIX, name, digest);
+
+            debug!("Uploading the signature of {} to s3://{}/{}", name, bucket, signature_name);
+
+            provider.put_object(region, &bucket, &signature_name, &signer.sign_to_vec()?)?;
+        }
+    });
+
+    Ok(())
+}
+
+// The bucket, name, and contents of each cloud-config object in the cluster's Terraform state,
+// or none if it has no state yet.
+fn cloud_configs(cluster: &str) -> Result<Vec<(String, String, String)>, KawsError> {
+    let state: Value = match File::open(format!("clusters/{}/terraform.tfstate", cluster)) {
+        Ok(file) => from_reader(file)?,
+        Err(ref error) if error.kind() == ErrorKind::NotFound => return Ok(vec![]),
+        Err(error) => return Err(error.into()),
+    };
+    let mut objects = vec![];
+
+    for module in state["modules"].as_array().into_iter().flatten() {
+        for (address, resource) in module["resources"].as_object().into_iter().flatten() {
+            if !address.starts_with("aws_s3_bucket_object.") {
+                continue;
+            }
+
+            let attributes = &resource["primary"]["attributes"];
+
+            if let (Some(bucket), Some(name), Some(content)) = (
+                attributes["bucket"].as_str(),
+                attributes["key"].as_str(),
+                attributes["content"].as_str(),
+            ) {
+                objects.push((bucket.to_owned(), name.to_owned(), content.to_owned()));
+            }
+        }
+    }
+
+    Ok(objects)
+}
@@ -1,6 +1,7 @@
 resource "aws_elb" "k8s_masters" {
   connection_draining = true
   idle_timeout = 3600
+  internal = "${var.private_api}"
   name = "kaws-k8s-masters-${var.cluster}"
   security_groups = ["${aws_security_group.balancers.id}"]
   subnets = ["${aws_subnet.balancers.id}"]
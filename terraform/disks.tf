@@ -2,7 +2,7 @@ resource "aws_ebs_volume" "etcd_01" {
   availability_zone = "${var.availability_zone}"
   encrypted = true
   kms_key_id = "${aws_kms_key.etcd.arn}"
-  size = "10"
+  size = "${var.etcd_volume_size}"
   type = "gp2"
 
   tags {
@@ -15,7 +15,7 @@ resource "aws_ebs_volume" "etcd_02" {
   availability_zone = "${var.availability_zone}"
   encrypted = true
   kms_key_id = "${aws_kms_key.etcd.arn}"
-  size = "10"
+  size = "${var.etcd_volume_size}"
   type = "gp2"
 
   tags {
@@ -28,7 +28,7 @@ resource "aws_ebs_volume" "etcd_03" {
   availability_zone = "${var.availability_zone}"
   encrypted = true
   kms_key_id = "${aws_kms_key.etcd.arn}"
-  size = "10"
+  size = "${var.etcd_volume_size}"
   type = "gp2"
 
   tags {
@@ -6,6 +6,14 @@ output "internet_gateway_id" {
   value = "${aws_internet_gateway.outgoing.id}"
 }
 
+output "kubernetes_masters_elb_dns_name" {
+  value = "${aws_elb.k8s_masters.dns_name}"
+}
+
+output "kubernetes_node_subnet_ids" {
+  value = ["${aws_subnet.k8s_nodes.*.id}"]
+}
+
 output "kubernetes_nodes_elb_dns_name" {
   value = "${aws_elb.k8s_nodes.dns_name}"
 }
@@ -38,6 +46,10 @@ output "ssh_bastion_security_group_id" {
   value = "${aws_security_group.bastion.id}"
 }
 
+output "ssh_known_hosts" {
+  value = "@cert-authority * ${trimspace(file("clusters/${var.cluster}/ssh-host-ca.pub"))}"
+}
+
 output "region" {
   value = "${var.region}"
 }
@@ -13,6 +13,13 @@ resource "aws_route_table_association" "k8s" {
   subnet_id = "${aws_subnet.k8s.id}"
 }
 
+resource "aws_route_table_association" "k8s_nodes" {
+  count = "${length(var.node_subnet_cidrs)}"
+
+  route_table_id = "${aws_route_table.k8s.id}"
+  subnet_id = "${element(aws_subnet.k8s_nodes.*.id, count.index)}"
+}
+
 resource "aws_route" "k8s_outgoing_traffic" {
   route_table_id = "${aws_route_table.k8s.id}"
   destination_cidr_block = "0.0.0.0/0"
@@ -1,3 +1,14 @@
+/*
+Each server may fetch the encrypted files it gets in its user data from an S3 bucket, Secrets
+Manager, or SSM Parameter Store, where `kaws cluster secret-store enable` keeps them, and no others.
+Secrets Manager appends six random characters to each secret's name in its ARN.
+*/
+locals {
+  s3_object_arn = "arn:aws:s3:::${var.secret_store_bucket}/${var.cluster}"
+  secrets_manager_secret_arn = "arn:aws:secretsmanager:${var.region}:${var.account_id}:secret:kaws/${var.cluster}"
+  ssm_parameter_arn = "arn:aws:ssm:${var.region}:${var.account_id}:parameter/kaws/${var.cluster}"
+}
+
 data "aws_iam_policy_document" "assume_role_policy" {
   statement {
     actions = ["sts:AssumeRole"]
@@ -14,6 +25,28 @@ data "aws_iam_policy_document" "bastion" {
     actions = ["s3:GetObject"]
     resources = [
       "arn:aws:s3:::${aws_s3_bucket.cloud_config.id}/${aws_s3_bucket_object.bastion_cloud_config.id}",
+      "arn:aws:s3:::${aws_s3_bucket.cloud_config.id}/signatures/${aws_s3_bucket_object.bastion_cloud_config.id}/*",
+    ]
+  }
+
+  statement {
+    actions = ["s3:GetObject"]
+    resources = [
+      "${local.s3_object_arn}/ssh-host-ca-key-encrypted.base64",
+    ]
+  }
+
+  statement {
+    actions = ["secretsmanager:GetSecretValue"]
+    resources = [
+      "${local.secrets_manager_secret_arn}/ssh-host-ca-key-encrypted.base64-??????",
+    ]
+  }
+
+  statement {
+    actions = ["ssm:GetParameter"]
+    resources = [
+      "${local.ssm_parameter_arn}/ssh-host-ca-key-encrypted.base64",
     ]
   }
 }
@@ -23,17 +56,96 @@ data "aws_iam_policy_document" "etcd" {
     actions = ["s3:GetObject"]
     resources = [
       "arn:aws:s3:::${aws_s3_bucket.cloud_config.id}/${aws_s3_bucket_object.etcd_01_cloud_config.id}",
+      "arn:aws:s3:::${aws_s3_bucket.cloud_config.id}/signatures/${aws_s3_bucket_object.etcd_01_cloud_config.id}/*",
       "arn:aws:s3:::${aws_s3_bucket.cloud_config.id}/${aws_s3_bucket_object.etcd_02_cloud_config.id}",
+      "arn:aws:s3:::${aws_s3_bucket.cloud_config.id}/signatures/${aws_s3_bucket_object.etcd_02_cloud_config.id}/*",
       "arn:aws:s3:::${aws_s3_bucket.cloud_config.id}/${aws_s3_bucket_object.etcd_03_cloud_config.id}",
+      "arn:aws:s3:::${aws_s3_bucket.cloud_config.id}/signatures/${aws_s3_bucket_object.etcd_03_cloud_config.id}/*",
+    ]
+  }
+
+  statement {
+    actions = ["s3:GetObject"]
+    resources = [
+      "${local.s3_object_arn}/etcd-peer*-key-encrypted.base64",
+      "${local.s3_object_arn}/etcd-server*-key-encrypted.base64",
+      "${local.s3_object_arn}/ssh-host-ca-key-encrypted.base64",
+    ]
+  }
+
+  statement {
+    actions = ["secretsmanager:GetSecretValue"]
+    resources = [
+      "${local.secrets_manager_secret_arn}/etcd-peer*-key-encrypted.base64-??????",
+      "${local.secrets_manager_secret_arn}/etcd-server*-key-encrypted.base64-??????",
+      "${local.secrets_manager_secret_arn}/ssh-host-ca-key-encrypted.base64-??????",
+    ]
+  }
+
+  statement {
+    actions = ["ssm:GetParameter"]
+    resources = [
+      "${local.ssm_parameter_arn}/etcd-peer*-key-encrypted.base64",
+      "${local.ssm_parameter_arn}/etcd-server*-key-encrypted.base64",
+      "${local.ssm_parameter_arn}/ssh-host-ca-key-encrypted.base64",
     ]
   }
 }
 
 data "aws_iam_policy_document" "k8s_master" {
   statement {
-    actions = ["s3:GetObject"]
+    actions = ["s3:GetObject", "s3:GetObjectVersion"]
     resources = [
       "arn:aws:s3:::${aws_s3_bucket.cloud_config.id}/${aws_s3_bucket_object.master_cloud_config.id}",
+      "arn:aws:s3:::${aws_s3_bucket.cloud_config.id}/signatures/${aws_s3_bucket_object.master_cloud_config.id}/*",
+    ]
+  }
+
+  statement {
+    actions = ["s3:GetObject"]
+    resources = [
+      "${local.s3_object_arn}/bootstrap-token-encrypted.base64",
+      "${local.s3_object_arn}/etcd-apiserver-client-key-encrypted.base64",
+      "${local.s3_object_arn}/etcd-client-key-encrypted.base64",
+      "${local.s3_object_arn}/front-proxy-client-key-encrypted.base64",
+      "${local.s3_object_arn}/k8s-ca-key-encrypted.base64",
+      "${local.s3_object_arn}/k8s-controller-manager-key-encrypted.base64",
+      "${local.s3_object_arn}/k8s-master-key-encrypted.base64",
+      "${local.s3_object_arn}/k8s-scheduler-key-encrypted.base64",
+      "${local.s3_object_arn}/service-account-key-encrypted.base64",
+      "${local.s3_object_arn}/ssh-host-ca-key-encrypted.base64",
+    ]
+  }
+
+  statement {
+    actions = ["secretsmanager:GetSecretValue"]
+    resources = [
+      "${local.secrets_manager_secret_arn}/bootstrap-token-encrypted.base64-??????",
+      "${local.secrets_manager_secret_arn}/etcd-apiserver-client-key-encrypted.base64-??????",
+      "${local.secrets_manager_secret_arn}/etcd-client-key-encrypted.base64-??????",
+      "${local.secrets_manager_secret_arn}/front-proxy-client-key-encrypted.base64-??????",
+      "${local.secrets_manager_secret_arn}/k8s-ca-key-encrypted.base64-??????",
+      "${local.secrets_manager_secret_arn}/k8s-controller-manager-key-encrypted.base64-??????",
+      "${local.secrets_manager_secret_arn}/k8s-master-key-encrypted.base64-??????",
+      "${local.secrets_manager_secret_arn}/k8s-scheduler-key-encrypted.base64-??????",
+      "${local.secrets_manager_secret_arn}/service-account-key-encrypted.base64-??????",
+      "${local.secrets_manager_secret_arn}/ssh-host-ca-key-encrypted.base64-??????",
+    ]
+  }
+
+  statement {
+    actions = ["ssm:GetParameter"]
+    resources = [
+      "${local.ssm_parameter_arn}/bootstrap-token-encrypted.base64",
+      "${local.ssm_parameter_arn}/etcd-apiserver-client-key-encrypted.base64",
+      "${local.ssm_parameter_arn}/etcd-client-key-encrypted.base64",
+      "${local.ssm_parameter_arn}/front-proxy-client-key-encrypted.base64",
+      "${local.ssm_parameter_arn}/k8s-ca-key-encrypted.base64",
+      "${local.ssm_parameter_arn}/k8s-controller-manager-key-encrypted.base64",
+      "${local.ssm_parameter_arn}/k8s-master-key-encrypted.base64",
+      "${local.ssm_parameter_arn}/k8s-scheduler-key-encrypted.base64",
+      "${local.ssm_parameter_arn}/service-account-key-encrypted.base64",
+      "${local.ssm_parameter_arn}/ssh-host-ca-key-encrypted.base64",
     ]
   }
 
@@ -63,9 +175,47 @@ data "aws_iam_policy_document" "k8s_master" {
 
 data "aws_iam_policy_document" "k8s_node" {
   statement {
-    actions = ["s3:GetObject"]
+    actions = ["s3:GetObject", "s3:GetObjectVersion"]
     resources = [
       "arn:aws:s3:::${aws_s3_bucket.cloud_config.id}/${aws_s3_bucket_object.node_cloud_config.id}",
+      "arn:aws:s3:::${aws_s3_bucket.cloud_config.id}/signatures/${aws_s3_bucket_object.node_cloud_config.id}/*",
+    ]
+  }
+
+  statement {
+    actions = ["s3:GetObject"]
+    resources = [
+      "${local.s3_object_arn}/bootstrap-token-encrypted.base64",
+      "${local.s3_object_arn}/etcd-client-key-encrypted.base64",
+      "${local.s3_object_arn}/k8s-node-key-encrypted.base64",
+      "${local.s3_object_arn}/ssh-host-ca-key-encrypted.base64",
+    ]
+  }
+
+  statement {
+    actions = ["secretsmanager:GetSecretValue"]
+    resources = [
+      "${local.secrets_manager_secret_arn}/bootstrap-token-encrypted.base64-??????",
+      "${local.secrets_manager_secret_arn}/etcd-client-key-encrypted.base64-??????",
+      "${local.secrets_manager_secret_arn}/k8s-node-key-encrypted.base64-??????",
+      "${local.secrets_manager_secret_arn}/ssh-host-ca-key-encrypted.base64-??????",
+    ]
+  }
+
+  statement {
+    actions = ["ssm:GetParameter"]
+    resources = [
+      "${local.ssm_parameter_arn}/bootstrap-token-encrypted.base64",
+      "${local.ssm_parameter_arn}/etcd-client-key-encrypted.base64",
+      "${local.ssm_parameter_arn}/k8s-node-key-encrypted.base64",
+      "${local.ssm_parameter_arn}/ssh-host-ca-key-encrypted.base64",
+    ]
+  }
+
+  statement {
+    actions = ["autoscaling:CompleteLifecycleAction"]
+    resources = [
+      "arn:aws:autoscaling:${var.region}:${var.account_id}:autoScalingGroup:*:autoScalingGroupName/kaws-k8s-nodes-${var.cluster}",
     ]
   }
 
@@ -178,6 +328,65 @@ data "aws_iam_policy_document" "kms_key" {
     sid = "Allow use of the key"
   }
 
+  # The bastion is the only server reachable from the internet, so it may only decrypt the SSH host
+  # CA key it signs its own host key with, and the objects S3 decrypts for it. The cloud-config
+  # bucket is named rather than referred to, since it's encrypted with this key.
+  statement {
+    actions = [
+      "kms:Decrypt",
+    ]
+
+    condition {
+      test = "StringEquals"
+      values = ["${var.cluster}"]
+      variable = "kms:EncryptionContext:kaws-cluster"
+    }
+
+    condition {
+      test = "StringEquals"
+      values = ["ssh-host-ca-key-encrypted.base64"]
+      variable = "kms:EncryptionContext:kaws-file"
+    }
+
+    principals {
+      type = "AWS"
+      identifiers = ["${aws_iam_role.bastion.arn}"]
+    }
+
+    resources = [
+      "*",
+    ]
+
+    sid = "Allow the bastion to decrypt the SSH host CA key"
+  }
+
+  statement {
+    actions = [
+      "kms:Decrypt",
+    ]
+
+    condition {
+      test = "StringLike"
+      values = [
+        "arn:aws:s3:::kaws-${var.account_id}-${var.cluster}/bastion_cloud_config.yml",
+        "arn:aws:s3:::kaws-${var.account_id}-${var.cluster}/signatures/bastion_cloud_config.yml/*",
+        "${local.s3_object_arn}/ssh-host-ca-key-encrypted.base64",
+      ]
+      variable = "kms:EncryptionContext:aws:s3:arn"
+    }
+
+    principals {
+      type = "AWS"
+      identifiers = ["${aws_iam_role.bastion.arn}"]
+    }
+
+    resources = [
+      "*",
+    ]
+
+    sid = "Allow the bastion to read its objects from S3"
+  }
+
   statement {
     actions = [
       "kms:CreateGrant",
@@ -272,7 +481,7 @@ resource "aws_kms_key" "pki" {
   policy = "${data.aws_iam_policy_document.kms_key.json}"
 
   provisioner "local-exec" {
-    command = "kaws cluster generate-pki all ${var.cluster} --domain ${var.domain} --kms-key ${aws_kms_key.pki.key_id} --region ${var.region}"
+    command = "kaws cluster generate-pki all ${var.cluster} --domain ${var.domain} --key-algo ${var.key_algo} --key-size ${var.key_size} --kms-key ${aws_kms_key.pki.key_id} --region ${var.region}"
   }
 }
 
@@ -331,7 +540,7 @@ resource "aws_security_group" "bastion" {
     from_port = 22
     to_port = 22
     protocol = "tcp"
-    cidr_blocks = ["0.0.0.0/0"]
+    cidr_blocks = ["${var.bastion_ssh_cidrs}"]
   }
 
   egress {
@@ -4,7 +4,7 @@ resource "aws_instance" "bastion" {
   iam_instance_profile = "${aws_iam_instance_profile.bastion.name}"
   instance_type = "t2.micro"
   subnet_id = "${aws_subnet.k8s.id}"
-  user_data = "${replace("${data.template_file.user_data.rendered}", "__FILE__", "bastion_cloud_config.yml")}"
+  user_data = "${replace("${local.unpinned_user_data}", "__FILE__", "bastion_cloud_config.yml")}"
   vpc_security_group_ids = ["${aws_security_group.bastion.id}"]
 
   tags {
@@ -21,7 +21,7 @@ resource "aws_instance" "etcd_01" {
   instance_type = "${var.instance_size}"
   private_ip = "10.0.1.4"
   subnet_id = "${aws_subnet.etcd.id}"
-  user_data = "${replace("${data.template_file.user_data.rendered}", "__FILE__", "etcd_01_cloud_config.yml")}"
+  user_data = "${replace("${local.unpinned_user_data}", "__FILE__", "etcd_01_cloud_config.yml")}"
   vpc_security_group_ids = ["${aws_security_group.etcd.id}"]
 
   tags {
@@ -38,7 +38,7 @@ resource "aws_instance" "etcd_02" {
   instance_type = "${var.instance_size}"
   private_ip = "10.0.1.5"
   subnet_id = "${aws_subnet.etcd.id}"
-  user_data = "${replace("${data.template_file.user_data.rendered}", "__FILE__", "etcd_02_cloud_config.yml")}"
+  user_data = "${replace("${local.unpinned_user_data}", "__FILE__", "etcd_02_cloud_config.yml")}"
   vpc_security_group_ids = ["${aws_security_group.etcd.id}"]
 
   tags {
@@ -55,7 +55,7 @@ resource "aws_instance" "etcd_03" {
   instance_type = "${var.instance_size}"
   private_ip = "10.0.1.6"
   subnet_id = "${aws_subnet.etcd.id}"
-  user_data = "${replace("${data.template_file.user_data.rendered}", "__FILE__", "etcd_03_cloud_config.yml")}"
+  user_data = "${replace("${local.unpinned_user_data}", "__FILE__", "etcd_03_cloud_config.yml")}"
   vpc_security_group_ids = ["${aws_security_group.etcd.id}"]
 
   tags {
@@ -67,11 +67,11 @@ resource "aws_instance" "etcd_03" {
 resource "aws_launch_configuration" "k8s_masters" {
   associate_public_ip_address = true
   iam_instance_profile = "${aws_iam_instance_profile.k8s_master.name}"
-  image_id = "${var.coreos_ami}"
+  image_id = "${local.kubernetes_ami}"
   instance_type = "${var.instance_size}"
   name_prefix = "kaws-k8s-masters-${var.cluster}-"
   security_groups = ["${aws_security_group.kubernetes.id}"]
-  user_data = "${replace("${data.template_file.user_data.rendered}", "__FILE__", "master_cloud_config.yml")}"
+  user_data = "${local.master_user_data}"
 
   lifecycle {
     create_before_destroy = true
@@ -113,11 +113,11 @@ resource "aws_autoscaling_group" "k8s_masters" {
 resource "aws_launch_configuration" "k8s_nodes" {
   associate_public_ip_address = true
   iam_instance_profile = "${aws_iam_instance_profile.k8s_node.name}"
-  image_id = "${var.coreos_ami}"
+  image_id = "${local.kubernetes_ami}"
   instance_type = "${var.instance_size}"
   name_prefix = "kaws-k8s-nodes-${var.cluster}-"
   security_groups = ["${aws_security_group.kubernetes.id}"]
-  user_data = "${replace("${data.template_file.user_data.rendered}", "__FILE__", "node_cloud_config.yml")}"
+  user_data = "${local.node_user_data}"
 
   lifecycle {
     create_before_destroy = true
@@ -129,6 +129,13 @@ resource "aws_launch_configuration" "k8s_nodes" {
   }
 }
 
+resource "aws_placement_group" "k8s_nodes" {
+  count = "${var.node_placement == "" ? 0 : 1}"
+
+  name = "kaws-k8s-nodes-${var.cluster}"
+  strategy = "${var.node_placement}"
+}
+
 resource "aws_autoscaling_group" "k8s_nodes" {
   health_check_grace_period = 300
   health_check_type = "ELB"
@@ -137,7 +144,10 @@ resource "aws_autoscaling_group" "k8s_nodes" {
   max_size = "${var.nodes_max_size}"
   min_size = "${var.nodes_min_size}"
   name = "kaws-k8s-nodes-${var.cluster}"
-  vpc_zone_identifier = ["${aws_subnet.k8s.id}"]
+  placement_group = "${join("", aws_placement_group.k8s_nodes.*.id)}"
+
+  # Conditionals can't choose between lists, so the chosen subnet IDs are joined and split again.
+  vpc_zone_identifier = ["${split(",", length(var.node_subnet_cidrs) > 0 ? join(",", aws_subnet.k8s_nodes.*.id) : aws_subnet.k8s.id)}"]
 
   lifecycle {
     create_before_destroy = true
@@ -155,3 +165,15 @@ resource "aws_autoscaling_group" "k8s_nodes" {
     propagate_at_launch = true
   }
 }
+
+/*
+Nodes being scaled in wait for kaws-node-drain.service to drain them and complete the lifecycle
+action, or for the hook to time out, before their instances are terminated.
+*/
+resource "aws_autoscaling_lifecycle_hook" "k8s_nodes_drain" {
+  autoscaling_group_name = "${aws_autoscaling_group.k8s_nodes.name}"
+  default_result = "CONTINUE"
+  heartbeat_timeout = 300
+  lifecycle_transition = "autoscaling:EC2_INSTANCE_TERMINATING"
+  name = "kaws-k8s-nodes-drain-${var.cluster}"
+}
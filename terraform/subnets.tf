@@ -30,3 +30,16 @@ resource "aws_subnet" "k8s" {
     KubernetesCluster = "${var.cluster}"
   }
 }
+
+resource "aws_subnet" "k8s_nodes" {
+  count = "${length(var.node_subnet_cidrs)}"
+
+  availability_zone = "${var.availability_zone}"
+  cidr_block = "${element(var.node_subnet_cidrs, count.index)}"
+  vpc_id = "${aws_vpc.kubernetes.id}"
+
+  tags {
+    Name = "kaws-k8s-nodes-${count.index + 1}-${var.cluster}"
+    KubernetesCluster = "${var.cluster}"
+  }
+}
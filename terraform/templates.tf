@@ -6,12 +6,199 @@ turn forcing all the servers to be recreated on each `terraform apply`. It's unc
 happens.
 */
 
+/*
+Flannel's WireGuard backend needs a newer flannel than Container Linux ships. Each server's flanneld
+generates its own WireGuard key and publishes the public key in its subnet lease in etcd.
+*/
+/*
+An image built by `kaws image build` already has the kubelet's image in rkt's store, so masters and
+nodes booted from it never fetch the image. The image is only used while its Kubernetes version
+matches the cluster's, so changing the version falls back to fetching on boot until it is rebuilt.
+*/
+locals {
+  kubelet_pull_policy = "${var.baked_ami != "" && var.baked_version == var.version ? "--pull-policy=never" : ""}"
+  kubernetes_ami = "${var.baked_ami != "" && var.baked_version == var.version ? var.baked_ami : var.coreos_ami}"
+}
+
+/*
+With TLS bootstrapping, nodes get the bootstrap token instead of the nodes' shared certificate and
+key, and each kubelet requests its own client certificate, which the controller manager signs with
+the Kubernetes CA. Files a server doesn't need are written outside the directories the servers
+decrypt and read them from.
+*/
+locals {
+  node_credentials_ssl_dir = "${var.tls_bootstrapping == "true" ? "/etc/kubernetes/disabled-ssl" : "/etc/kubernetes/ssl"}"
+  tls_bootstrapping_ssl_dir = "${var.tls_bootstrapping == "true" ? "/etc/kubernetes/ssl" : "/etc/kubernetes/disabled-ssl"}"
+}
+
+/*
+With per-member certificates, each etcd server gets the server and peer certificates issued for it
+alone, named after the suffix of its server name.
+*/
+locals {
+  etcd_01_certificate_suffix = "${var.etcd_member_certificates == "true" ? "-01" : ""}"
+  etcd_02_certificate_suffix = "${var.etcd_member_certificates == "true" ? "-02" : ""}"
+  etcd_03_certificate_suffix = "${var.etcd_member_certificates == "true" ? "-03" : ""}"
+}
+
+locals {
+  flannel_image_environment = "${var.wireguard == "true" ? "Environment=FLANNEL_IMAGE_TAG=v0.15.1" : "# WireGuard is disabled, so use the default flannel image"}"
+  flannel_network_config = "${var.wireguard == "true" ? "{\\\"Network\\\":\\\"10.2.0.0/16\\\",\\\"Backend\\\":{\\\"Type\\\":\\\"wireguard\\\"}}" : "{\\\"Network\\\":\\\"10.2.0.0/16\\\"}"}"
+}
+
+/*
+Flags from the cluster's metadata, which kaws checks against the Kubernetes version before
+passing them, go after the ones set here. The API server's and controller manager's are items of
+the command lists in their manifests.
+*/
+locals {
+  apiserver_flags = "${join("", formatlist("\n              - %s", var.apiserver_flags))}"
+  controller_manager_flags = "${join("", formatlist("\n              - %s", var.controller_manager_flags))}"
+  kubelet_flags = "${join(" ", var.kubelet_flags)}"
+}
+
+/*
+With a userdata signing key, each server also checks the signature kaws uploads after applying the
+cluster before running its cloud-config. Without one, the check is left out entirely, so that the
+servers' user data, and so the servers, don't change.
+*/
+data template_file "user_data_signature_check" {
+  template = "${file("${path.module}/templates/user_data_signature_check.yml")}"
+
+  vars {
+    bucket = "${aws_s3_bucket.cloud_config.id}"
+    public_key = "${var.userdata_signing_public_key}"
+    region = "${var.region}"
+  }
+}
+
 data template_file "user_data" {
   template = "${file("${path.module}/templates/user_data.yml")}"
 
   vars {
-    s3_uri = "s3://${aws_s3_bucket.cloud_config.id}"
+    bucket = "${aws_s3_bucket.cloud_config.id}"
+    region = "${var.region}"
+    signature_check = "${var.userdata_signing_public_key == "" ? "" : data.template_file.user_data_signature_check.rendered}"
+  }
+}
+
+locals {
+  # Masters and nodes boot the exact revision of their cloud-config that their launch
+  # configuration was created with, and check it against its digest before running it.
+  master_user_data = "${replace(replace(replace(data.template_file.user_data.rendered, "__FILE__", "master_cloud_config.yml"), "__VERSION_ARGS__", "--version-id ${aws_s3_bucket_object.master_cloud_config.version_id}"), "__SHA256__", sha256(data.template_file.master_cloud_config.rendered))}"
+  node_user_data = "${replace(replace(replace(data.template_file.user_data.rendered, "__FILE__", "node_cloud_config.yml"), "__VERSION_ARGS__", "--version-id ${aws_s3_bucket_object.node_cloud_config.version_id}"), "__SHA256__", sha256(data.template_file.node_cloud_config.rendered))}"
+
+  # Pinning the bastion and etcd servers the same way would make Terraform replace them on every
+  # change to their cloud-config, so they keep fetching the latest revision.
+  unpinned_user_data = "${replace(replace(data.template_file.user_data.rendered, "__VERSION_ARGS__", ""), "__SHA256__", "")}"
+}
+
+/*
+Every server gets /opt/kaws/kms-decrypt, which decrypts its copy of a file in the cluster's
+directory with the encryption context kaws encrypted it with, looking up the file's name in
+/etc/kaws/encrypted-files. Unless `encryption_contexts_required` is set, a file it won't decrypt
+with one is decrypted without, as files encrypted before kaws used encryption contexts must be.
+*/
+data "template_file" "kms_decrypt" {
+  template = "${file("${path.module}/templates/kms_decrypt.sh")}"
+
+  vars {
+    cluster = "${var.cluster}"
+    encryption_contexts_required = "${var.encryption_contexts_required}"
+    region = "${var.region}"
+    secret_store = "${var.secret_store}"
+    secret_store_bucket = "${var.secret_store_bucket}"
+  }
+}
+
+/*
+With a secret store, servers get a placeholder in place of each encrypted file, which
+/opt/kaws/kms-decrypt replaces with the file it fetches from the store before decrypting it.
+*/
+locals {
+  secret_store_placeholder = "${base64encode("KAWSSTORE")}"
+}
+
+/*
+Masters, nodes, and etcd servers get /opt/kaws/verify, which checks without writing anything that
+the server could decrypt its files and reach the services it needs, e.g. as a startup probe.
+*/
+data "template_file" "verify" {
+  template = "${file("${path.module}/templates/verify.sh")}"
+
+  vars {
+    cluster = "${var.cluster}"
+    encryption_contexts_required = "${var.encryption_contexts_required}"
     region = "${var.region}"
+    secret_store = "${var.secret_store}"
+    secret_store_bucket = "${var.secret_store_bucket}"
+  }
+}
+
+/*
+They also get /opt/kaws/deliver-secrets, which /opt/kaws/decrypt-pki runs last to copy what it
+decrypted to the sinks listed for the server's role, if any, in /etc/kaws/secret-sinks.
+*/
+locals {
+  deliver_secrets_script = "${base64encode(file("${path.module}/templates/deliver_secrets.sh"))}"
+  etcd_secret_sinks = "${base64encode(join("", formatlist("%s\n", var.etcd_secret_sinks)))}"
+  master_secret_sinks = "${base64encode(join("", formatlist("%s\n", var.master_secret_sinks)))}"
+  node_secret_sinks = "${base64encode(join("", formatlist("%s\n", var.node_secret_sinks)))}"
+}
+
+/*
+Masters, nodes, and etcd servers run /opt/kaws/shutdown when kaws-shutdown.service stops as they
+shut down. Unless the server is rebooting, a master or node deletes its Node object through the
+Kubernetes API, and with `scrub_credentials`, the server removes what /opt/kaws/decrypt-pki
+decrypted. Masters reach their own API server, and nodes the masters' with the kubelet's client
+certificate.
+*/
+data "template_file" "etcd_shutdown" {
+  template = "${file("${path.module}/templates/shutdown.sh")}"
+
+  vars {
+    api_client_args = ""
+    api_server = ""
+    scrub_credentials = "${var.scrub_credentials}"
+  }
+}
+
+data "template_file" "master_shutdown" {
+  template = "${file("${path.module}/templates/shutdown.sh")}"
+
+  vars {
+    api_client_args = ""
+    api_server = "http://127.0.0.1:8080"
+    scrub_credentials = "${var.scrub_credentials}"
+  }
+}
+
+data "template_file" "node_shutdown" {
+  template = "${file("${path.module}/templates/shutdown.sh")}"
+
+  vars {
+    api_client_args = "${var.tls_bootstrapping == "true" ? "--cacert /etc/kubernetes/ssl/ca.pem --cert /etc/kubernetes/kubelet/pki/kubelet-client.crt --key /etc/kubernetes/kubelet/pki/kubelet-client.key" : "--cacert /etc/kubernetes/ssl/ca.pem --cert /etc/kubernetes/ssl/node.pem --key /etc/kubernetes/ssl/node-key.pem"}"
+    api_server = "https://kubernetes.${var.domain}"
+    scrub_credentials = "${var.scrub_credentials}"
+  }
+}
+
+/*
+Nodes also get /opt/kaws/drain-on-scale-in, which drains the node when its autoscaling group scales
+it in. The group's name is built rather than referenced, since the group depends on the nodes'
+cloud-config through their launch configuration.
+*/
+data "template_file" "node_drain" {
+  template = "${file("${path.module}/templates/node_drain.sh")}"
+
+  vars {
+    auto_scaling_group = "kaws-k8s-nodes-${var.cluster}"
+    drain_timeout = 240
+    kubeconfig = "${var.tls_bootstrapping == "true" ? "/etc/kubernetes/kubelet/kubeconfig.yml" : "/etc/kubernetes/node-kubeconfig.yml"}"
+    lifecycle_hook = "kaws-k8s-nodes-drain-${var.cluster}"
+    master_ip = "kubernetes.${var.domain}"
+    region = "${var.region}"
+    version = "${var.version}"
   }
 }
 
@@ -19,7 +206,12 @@ data "template_file" "bastion_cloud_config" {
   template = "${file("${path.module}/templates/bastion_cloud_config.yml")}"
 
   vars {
+    kms_decrypt_script = "${base64encode(data.template_file.kms_decrypt.rendered)}"
+    kms_key_id = "${aws_kms_key.pki.key_id}"
+    region = "${var.region}"
+    ssh_host_ca_key = "${var.secret_store == "" ? file("clusters/${var.cluster}/ssh-host-ca-key-encrypted.base64") : local.secret_store_placeholder}"
     ssh_public_keys = "${join(", ", var.ssh_keys)}"
+    ssh_user_ca_public_key = "${base64encode(file("clusters/${var.cluster}/ssh-user-ca.pub"))}"
   }
 }
 
@@ -27,16 +219,24 @@ data "template_file" "etcd_01_cloud_config" {
   template = "${file("${path.module}/templates/etcd_cloud_config.yml")}"
 
   vars {
+    certificate_suffix = "${local.etcd_01_certificate_suffix}"
+    deliver_secrets_script = "${local.deliver_secrets_script}"
     etcd_ca_cert = "${base64encode(file("clusters/${var.cluster}/etcd-ca.pem"))}",
     etcd_peer_ca_cert = "${base64encode(file("clusters/${var.cluster}/etcd-peer-ca.pem"))}",
-    etcd_peer_cert = "${base64encode(file("clusters/${var.cluster}/etcd-peer.pem"))}",
-    etcd_peer_key = "${file("clusters/${var.cluster}/etcd-peer-key-encrypted.base64")}",
-    etcd_server_cert = "${base64encode(file("clusters/${var.cluster}/etcd-server.pem"))}",
-    etcd_server_key = "${file("clusters/${var.cluster}/etcd-server-key-encrypted.base64")}",
+    etcd_peer_cert = "${base64encode(file("clusters/${var.cluster}/etcd-peer${local.etcd_01_certificate_suffix}.pem"))}",
+    etcd_peer_key = "${var.secret_store == "" ? file("clusters/${var.cluster}/etcd-peer${local.etcd_01_certificate_suffix}-key-encrypted.base64") : local.secret_store_placeholder}",
+    etcd_server_cert = "${base64encode(file("clusters/${var.cluster}/etcd-server${local.etcd_01_certificate_suffix}.pem"))}",
+    etcd_server_key = "${var.secret_store == "" ? file("clusters/${var.cluster}/etcd-server${local.etcd_01_certificate_suffix}-key-encrypted.base64") : local.secret_store_placeholder}",
+    kms_decrypt_script = "${base64encode(data.template_file.kms_decrypt.rendered)}"
     kms_key_id = "${aws_kms_key.pki.key_id}"
     name = "etcd_01"
     region = "${var.region}"
+    secret_sinks = "${local.etcd_secret_sinks}"
+    shutdown_script = "${base64encode(data.template_file.etcd_shutdown.rendered)}"
+    ssh_host_ca_key = "${var.secret_store == "" ? file("clusters/${var.cluster}/ssh-host-ca-key-encrypted.base64") : local.secret_store_placeholder}"
     ssh_public_keys = "${join(", ", var.ssh_keys)}"
+    ssh_user_ca_public_key = "${base64encode(file("clusters/${var.cluster}/ssh-user-ca.pub"))}"
+    verify_script = "${base64encode(data.template_file.verify.rendered)}"
   }
 }
 
@@ -44,16 +244,24 @@ data "template_file" "etcd_02_cloud_config" {
   template = "${file("${path.module}/templates/etcd_cloud_config.yml")}"
 
   vars {
+    certificate_suffix = "${local.etcd_02_certificate_suffix}"
+    deliver_secrets_script = "${local.deliver_secrets_script}"
     etcd_ca_cert = "${base64encode(file("clusters/${var.cluster}/etcd-ca.pem"))}",
     etcd_peer_ca_cert = "${base64encode(file("clusters/${var.cluster}/etcd-peer-ca.pem"))}",
-    etcd_peer_cert = "${base64encode(file("clusters/${var.cluster}/etcd-peer.pem"))}",
-    etcd_peer_key = "${file("clusters/${var.cluster}/etcd-peer-key-encrypted.base64")}",
-    etcd_server_cert = "${base64encode(file("clusters/${var.cluster}/etcd-server.pem"))}",
-    etcd_server_key = "${file("clusters/${var.cluster}/etcd-server-key-encrypted.base64")}",
+    etcd_peer_cert = "${base64encode(file("clusters/${var.cluster}/etcd-peer${local.etcd_02_certificate_suffix}.pem"))}",
+    etcd_peer_key = "${var.secret_store == "" ? file("clusters/${var.cluster}/etcd-peer${local.etcd_02_certificate_suffix}-key-encrypted.base64") : local.secret_store_placeholder}",
+    etcd_server_cert = "${base64encode(file("clusters/${var.cluster}/etcd-server${local.etcd_02_certificate_suffix}.pem"))}",
+    etcd_server_key = "${var.secret_store == "" ? file("clusters/${var.cluster}/etcd-server${local.etcd_02_certificate_suffix}-key-encrypted.base64") : local.secret_store_placeholder}",
+    kms_decrypt_script = "${base64encode(data.template_file.kms_decrypt.rendered)}"
     kms_key_id = "${aws_kms_key.pki.key_id}"
     name = "etcd_02"
     region = "${var.region}"
+    secret_sinks = "${local.etcd_secret_sinks}"
+    shutdown_script = "${base64encode(data.template_file.etcd_shutdown.rendered)}"
+    ssh_host_ca_key = "${var.secret_store == "" ? file("clusters/${var.cluster}/ssh-host-ca-key-encrypted.base64") : local.secret_store_placeholder}"
     ssh_public_keys = "${join(", ", var.ssh_keys)}"
+    ssh_user_ca_public_key = "${base64encode(file("clusters/${var.cluster}/ssh-user-ca.pub"))}"
+    verify_script = "${base64encode(data.template_file.verify.rendered)}"
   }
 }
 
@@ -61,16 +269,24 @@ data "template_file" "etcd_03_cloud_config" {
   template = "${file("${path.module}/templates/etcd_cloud_config.yml")}"
 
   vars {
+    certificate_suffix = "${local.etcd_03_certificate_suffix}"
+    deliver_secrets_script = "${local.deliver_secrets_script}"
     etcd_ca_cert = "${base64encode(file("clusters/${var.cluster}/etcd-ca.pem"))}",
     etcd_peer_ca_cert = "${base64encode(file("clusters/${var.cluster}/etcd-peer-ca.pem"))}",
-    etcd_peer_cert = "${base64encode(file("clusters/${var.cluster}/etcd-peer.pem"))}",
-    etcd_peer_key = "${file("clusters/${var.cluster}/etcd-peer-key-encrypted.base64")}",
-    etcd_server_cert = "${base64encode(file("clusters/${var.cluster}/etcd-server.pem"))}",
-    etcd_server_key = "${file("clusters/${var.cluster}/etcd-server-key-encrypted.base64")}",
+    etcd_peer_cert = "${base64encode(file("clusters/${var.cluster}/etcd-peer${local.etcd_03_certificate_suffix}.pem"))}",
+    etcd_peer_key = "${var.secret_store == "" ? file("clusters/${var.cluster}/etcd-peer${local.etcd_03_certificate_suffix}-key-encrypted.base64") : local.secret_store_placeholder}",
+    etcd_server_cert = "${base64encode(file("clusters/${var.cluster}/etcd-server${local.etcd_03_certificate_suffix}.pem"))}",
+    etcd_server_key = "${var.secret_store == "" ? file("clusters/${var.cluster}/etcd-server${local.etcd_03_certificate_suffix}-key-encrypted.base64") : local.secret_store_placeholder}",
+    kms_decrypt_script = "${base64encode(data.template_file.kms_decrypt.rendered)}"
     kms_key_id = "${aws_kms_key.pki.key_id}"
     name = "etcd_03"
     region = "${var.region}"
+    secret_sinks = "${local.etcd_secret_sinks}"
+    shutdown_script = "${base64encode(data.template_file.etcd_shutdown.rendered)}"
+    ssh_host_ca_key = "${var.secret_store == "" ? file("clusters/${var.cluster}/ssh-host-ca-key-encrypted.base64") : local.secret_store_placeholder}"
     ssh_public_keys = "${join(", ", var.ssh_keys)}"
+    ssh_user_ca_public_key = "${base64encode(file("clusters/${var.cluster}/ssh-user-ca.pub"))}"
+    verify_script = "${base64encode(data.template_file.verify.rendered)}"
   }
 }
 
@@ -78,17 +294,51 @@ data "template_file" "master_cloud_config" {
   template = "${file("${path.module}/templates/master_cloud_config.yml")}"
 
   vars {
+    apiserver_flags = "${local.apiserver_flags}"
+    bootstrap_token = "${var.tls_bootstrapping == "true" ? (var.secret_store == "" ? file("clusters/${var.cluster}/bootstrap-token-encrypted.base64") : local.secret_store_placeholder) : ""}"
     cluster = "${var.cluster}"
+    cluster_signing_key_flag = "${var.tls_bootstrapping == "true" ? "--cluster-signing-key-file=/etc/kubernetes/ssl/ca-key.pem" : "--cluster-signing-key-file=/etc/kubernetes/ca/ca.key"}"
+    controller_manager_flags = "${local.controller_manager_flags}"
+    deliver_secrets_script = "${local.deliver_secrets_script}"
     domain = "${var.domain}"
+    etcd_apiserver_client_cert = "${base64encode(file("clusters/${var.cluster}/etcd-apiserver-client.pem"))}",
+    etcd_apiserver_client_key = "${var.secret_store == "" ? file("clusters/${var.cluster}/etcd-apiserver-client-key-encrypted.base64") : local.secret_store_placeholder}",
     etcd_ca_cert = "${base64encode(file("clusters/${var.cluster}/etcd-ca.pem"))}",
     etcd_client_cert = "${base64encode(file("clusters/${var.cluster}/etcd-client.pem"))}",
-    etcd_client_key = "${file("clusters/${var.cluster}/etcd-client-key-encrypted.base64")}",
+    etcd_client_key = "${var.secret_store == "" ? file("clusters/${var.cluster}/etcd-client-key-encrypted.base64") : local.secret_store_placeholder}",
+    front_proxy_ca_cert = "${base64encode(file("clusters/${var.cluster}/front-proxy-ca.pem"))}",
+    front_proxy_client_cert = "${base64encode(file("clusters/${var.cluster}/front-proxy-client.pem"))}",
+    front_proxy_client_key = "${var.secret_store == "" ? file("clusters/${var.cluster}/front-proxy-client-key-encrypted.base64") : local.secret_store_placeholder}",
     k8s_ca_cert = "${base64encode(file("clusters/${var.cluster}/k8s-ca.pem"))}",
     k8s_master_cert = "${base64encode(file("clusters/${var.cluster}/k8s-master.pem"))}",
-    k8s_master_key = "${file("clusters/${var.cluster}/k8s-master-key-encrypted.base64")}",
+    k8s_ca_key = "${var.tls_bootstrapping == "true" ? (var.secret_store == "" ? file("clusters/${var.cluster}/k8s-ca-key-encrypted.base64") : local.secret_store_placeholder) : ""}",
+    k8s_controller_manager_cert = "${base64encode(file("clusters/${var.cluster}/k8s-controller-manager.pem"))}",
+    k8s_controller_manager_key = "${var.secret_store == "" ? file("clusters/${var.cluster}/k8s-controller-manager-key-encrypted.base64") : local.secret_store_placeholder}",
+    k8s_master_key = "${var.secret_store == "" ? file("clusters/${var.cluster}/k8s-master-key-encrypted.base64") : local.secret_store_placeholder}",
+    k8s_scheduler_cert = "${base64encode(file("clusters/${var.cluster}/k8s-scheduler.pem"))}",
+    k8s_scheduler_key = "${var.secret_store == "" ? file("clusters/${var.cluster}/k8s-scheduler-key-encrypted.base64") : local.secret_store_placeholder}",
+    kubelet_flags = "${local.kubelet_flags}"
+    kubelet_pull_policy = "${local.kubelet_pull_policy}"
+    flannel_image_environment = "${local.flannel_image_environment}"
+    flannel_network_config = "${local.flannel_network_config}"
+    iam_authenticator_config = "${var.iam_authenticator_config}"
+    iam_authenticator_image = "${var.iam_authenticator_image}"
+    iam_authenticator_manifest_dir = "${var.iam_authenticator_config == "" ? "/etc/kubernetes/disabled-manifests" : "/etc/kubernetes/manifests"}"
+    iam_authenticator_webhook_flag = "${var.iam_authenticator_config == "" ? "--authentication-token-webhook-cache-ttl=2m0s" : "--authentication-token-webhook-config-file=/etc/kubernetes/aws-iam-authenticator/kubeconfig.yaml"}"
+    kms_decrypt_script = "${base64encode(data.template_file.kms_decrypt.rendered)}"
     kms_key_id = "${aws_kms_key.pki.key_id}"
     region = "${var.region}"
+    secret_sinks = "${local.master_secret_sinks}"
+    service_account_key = "${var.secret_store == "" ? file("clusters/${var.cluster}/service-account-key-encrypted.base64") : local.secret_store_placeholder}"
+    service_account_public_keys = "${base64encode(file("clusters/${var.cluster}/service-account.pub"))}"
+    shutdown_script = "${base64encode(data.template_file.master_shutdown.rendered)}"
+    ssh_host_ca_key = "${var.secret_store == "" ? file("clusters/${var.cluster}/ssh-host-ca-key-encrypted.base64") : local.secret_store_placeholder}"
     ssh_public_keys = "${join(", ", var.ssh_keys)}"
+    ssh_user_ca_public_key = "${base64encode(file("clusters/${var.cluster}/ssh-user-ca.pub"))}"
+    tls_bootstrapping_manifest_dir = "${var.tls_bootstrapping == "true" ? "/etc/kubernetes/manifests" : "/etc/kubernetes/disabled-manifests"}"
+    tls_bootstrapping_ssl_dir = "${local.tls_bootstrapping_ssl_dir}"
+    token_auth_flag = "${var.tls_bootstrapping == "true" ? "--token-auth-file=/etc/kubernetes/ssl/known-tokens.csv" : "--anonymous-auth=false"}"
+    verify_script = "${base64encode(data.template_file.verify.rendered)}"
     version = "${var.version}"
   }
 }
@@ -97,17 +347,33 @@ data "template_file" "node_cloud_config" {
   template = "${file("${path.module}/templates/node_cloud_config.yml")}"
 
   vars {
+    bootstrap_token = "${var.tls_bootstrapping == "true" ? (var.secret_store == "" ? file("clusters/${var.cluster}/bootstrap-token-encrypted.base64") : local.secret_store_placeholder) : ""}"
     cluster = "${var.cluster}"
+    deliver_secrets_script = "${local.deliver_secrets_script}"
+    drain_script = "${base64encode(data.template_file.node_drain.rendered)}"
     etcd_ca_cert = "${base64encode(file("clusters/${var.cluster}/etcd-ca.pem"))}",
     etcd_client_cert = "${base64encode(file("clusters/${var.cluster}/etcd-client.pem"))}",
-    etcd_client_key = "${file("clusters/${var.cluster}/etcd-client-key-encrypted.base64")}",
+    etcd_client_key = "${var.secret_store == "" ? file("clusters/${var.cluster}/etcd-client-key-encrypted.base64") : local.secret_store_placeholder}",
+    flannel_image_environment = "${local.flannel_image_environment}"
     k8s_ca_cert = "${base64encode(file("clusters/${var.cluster}/k8s-ca.pem"))}",
-    k8s_node_cert = "${base64encode(file("clusters/${var.cluster}/k8s-node.pem"))}",
-    k8s_node_key = "${file("clusters/${var.cluster}/k8s-node-key-encrypted.base64")}",
+    k8s_node_cert = "${var.tls_bootstrapping == "true" ? "" : base64encode(file("clusters/${var.cluster}/k8s-node.pem"))}",
+    k8s_node_key = "${var.tls_bootstrapping == "true" ? "" : (var.secret_store == "" ? file("clusters/${var.cluster}/k8s-node-key-encrypted.base64") : local.secret_store_placeholder)}",
+    kms_decrypt_script = "${base64encode(data.template_file.kms_decrypt.rendered)}"
     kms_key_id = "${aws_kms_key.pki.key_id}"
+    kubelet_credential_flags = "${var.tls_bootstrapping == "true" ? "--cert-dir=/etc/kubernetes/kubelet/pki --experimental-bootstrap-kubeconfig=/etc/kubernetes/bootstrap-kubeconfig.yml" : "--tls-cert-file=/etc/kubernetes/ssl/node.pem --tls-private-key-file=/etc/kubernetes/ssl/node-key.pem"}"
+    kubelet_flags = "${local.kubelet_flags}"
+    kubelet_kubeconfig = "${var.tls_bootstrapping == "true" ? "/etc/kubernetes/kubelet/kubeconfig.yml" : "/etc/kubernetes/node-kubeconfig.yml"}"
+    kubelet_pull_policy = "${local.kubelet_pull_policy}"
     master_ip = "kubernetes.${var.domain}"
+    node_credentials_ssl_dir = "${local.node_credentials_ssl_dir}"
     region = "${var.region}"
+    secret_sinks = "${local.node_secret_sinks}"
+    shutdown_script = "${base64encode(data.template_file.node_shutdown.rendered)}"
+    ssh_host_ca_key = "${var.secret_store == "" ? file("clusters/${var.cluster}/ssh-host-ca-key-encrypted.base64") : local.secret_store_placeholder}"
     ssh_public_keys = "${join(", ", var.ssh_keys)}"
+    ssh_user_ca_public_key = "${base64encode(file("clusters/${var.cluster}/ssh-user-ca.pub"))}"
+    tls_bootstrapping_ssl_dir = "${local.tls_bootstrapping_ssl_dir}"
+    verify_script = "${base64encode(data.template_file.verify.rendered)}"
     version = "${var.version}"
   }
 }
@@ -0,0 +1,35 @@
+#!/bin/bash -e
+# Delivers copies of the secrets /opt/kaws/decrypt-pki decrypted to the sinks kaws lists in
+# /etc/kaws/secret-sinks from `secret_sinks` in the cluster's metadata, one per line:
+#
+#   file SOURCE PATH MODE OWNER
+#   credential SOURCE NAME
+#   environment SOURCE PATH VARIABLE
+#
+# Credentials go in /run/credstore, where systemd finds them for units with `LoadCredential=NAME`.
+umask 077
+
+while read -r type source target option owner; do
+  case "$type" in
+    "")
+      ;;
+    file)
+      install -D -m "$option" "$source" "$target"
+      chown "$owner" "$target"
+      ;;
+    credential)
+      install -D -m 0400 "$source" "/run/credstore/$target"
+      ;;
+    environment)
+      # Other variables in the file, including ones other sinks set, are kept.
+      mkdir -p "$(dirname "$target")"
+      touch "$target"
+      { grep -v "^$option=" "$target" || true; echo "$option=$(head -n 1 "$source")"; } > "$target.new"
+      mv "$target.new" "$target"
+      ;;
+    *)
+      echo "Unknown secret sink type \"$type\" in /etc/kaws/secret-sinks" >&2
+      exit 1
+      ;;
+  esac
+done < /etc/kaws/secret-sinks
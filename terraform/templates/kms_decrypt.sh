@@ -0,0 +1,88 @@
+#!/bin/bash -e
+# Decrypts a file kaws encrypted with KMS, writing the plaintext to another file with the caller's
+# umask:
+#
+#   /opt/kaws/kms-decrypt ENCRYPTED PLAINTEXT
+#
+# kaws encrypts each file with an encryption context naming the cluster and the file in the
+# cluster's directory, which /etc/kaws/encrypted-files lists after the path of the server's copy,
+# and KMS only decrypts it with the same context. Until the cluster's metadata records that every
+# file has one, files encrypted before kaws used encryption contexts are decrypted without one.
+#
+# With a secret store, the server's copy is a placeholder until the file is fetched from the store
+# by the same name, after which it's decrypted as if it had been there all along.
+encrypted="$1"
+plaintext="$2"
+file="$(awk -v path="$encrypted" '$1 == path { print $2 }' /etc/kaws/encrypted-files)"
+
+decrypt() {
+  /usr/bin/rkt run \
+    --net=host \
+    --volume=dns,kind=host,source=/etc/resolv.conf,readOnly=true \
+    --mount=volume=dns,target=/etc/resolv.conf \
+    --volume=encrypted,kind=host,source="$encrypted",readOnly=true \
+    --mount=volume=encrypted,target=/tmp/encrypted.binary \
+    --volume=plaintext,kind=host,source="$(dirname "$plaintext")" \
+    --mount=volume=plaintext,target=/tmp/plaintext \
+    --trust-keys-from-https \
+    quay.io/coreos/awscli \
+    --exec=/bin/bash \
+    -- \
+    -c "set -o pipefail && umask $(umask) && aws --region ${region} kms decrypt --ciphertext-blob fileb:///tmp/encrypted.binary $* --output text --query Plaintext | base64 -d > /tmp/plaintext/$(basename "$plaintext")"
+}
+
+fetch() {
+  case "${secret_store}" in
+    ssm)
+      /usr/bin/rkt run \
+        --net=host \
+        --volume=dns,kind=host,source=/etc/resolv.conf,readOnly=true \
+        --mount=volume=dns,target=/etc/resolv.conf \
+        --volume=encrypted,kind=host,source="$(dirname "$encrypted")" \
+        --mount=volume=encrypted,target=/tmp/encrypted \
+        --trust-keys-from-https \
+        quay.io/coreos/awscli \
+        --exec=/bin/bash \
+        -- \
+        -c "set -o pipefail && aws --region ${region} ssm get-parameter --name /kaws/${cluster}/$file --with-decryption --output text --query Parameter.Value | base64 -d > /tmp/encrypted/$(basename "$encrypted").fetched"
+      ;;
+    s3)
+      /usr/bin/rkt run \
+        --net=host \
+        --volume=dns,kind=host,source=/etc/resolv.conf,readOnly=true \
+        --mount=volume=dns,target=/etc/resolv.conf \
+        --volume=encrypted,kind=host,source="$(dirname "$encrypted")" \
+        --mount=volume=encrypted,target=/tmp/encrypted \
+        --trust-keys-from-https \
+        quay.io/coreos/awscli \
+        --exec=/bin/bash \
+        -- \
+        -c "set -o pipefail && aws --region ${region} s3 cp s3://${secret_store_bucket}/${cluster}/$file - | base64 -d > /tmp/encrypted/$(basename "$encrypted").fetched"
+      ;;
+    secretsmanager)
+      /usr/bin/rkt run \
+        --net=host \
+        --volume=dns,kind=host,source=/etc/resolv.conf,readOnly=true \
+        --mount=volume=dns,target=/etc/resolv.conf \
+        --volume=encrypted,kind=host,source="$(dirname "$encrypted")" \
+        --mount=volume=encrypted,target=/tmp/encrypted \
+        --trust-keys-from-https \
+        quay.io/coreos/awscli \
+        --exec=/bin/bash \
+        -- \
+        -c "set -o pipefail && aws --region ${region} secretsmanager get-secret-value --secret-id kaws/${cluster}/$file --output text --query SecretString | base64 -d > /tmp/encrypted/$(basename "$encrypted").fetched"
+      ;;
+  esac
+
+  mv "$encrypted.fetched" "$encrypted"
+}
+
+if [ "$(cat "$encrypted")" = "KAWSSTORE" ]; then
+  fetch
+fi
+
+if [ "${encryption_contexts_required}" = "true" ]; then
+  decrypt --encryption-context "kaws-cluster=${cluster},kaws-file=$file"
+else
+  decrypt --encryption-context "kaws-cluster=${cluster},kaws-file=$file" || decrypt
+fi
@@ -0,0 +1,43 @@
+#!/bin/bash
+# Waits for the node's autoscaling group to scale its instance in, which the group's termination
+# lifecycle hook holds in Terminating:Wait, then drains the node through the Kubernetes API and
+# completes the lifecycle action, so the instance is terminated as soon as its pods have moved
+# rather than when the hook times out. Runs as kaws-node-drain.service.
+metadata=http://169.254.169.254/latest/meta-data
+
+until [ "$(curl -sf $metadata/autoscaling/target-lifecycle-state)" = "Terminated" ]; do
+  sleep 5
+done
+
+source /etc/environment
+instance_id="$(curl -sf $metadata/instance-id)"
+
+echo "Instance $instance_id is being scaled in, draining node $COREOS_PRIVATE_IPV4"
+
+# The pods get most of the hook's timeout to stop, and the node is terminated even if they don't.
+/usr/bin/rkt run \
+  --net=host \
+  --volume=dns,kind=host,source=/etc/resolv.conf,readOnly=true \
+  --mount=volume=dns,target=/etc/resolv.conf \
+  --volume=kubernetes,kind=host,source=/etc/kubernetes,readOnly=true \
+  --mount=volume=kubernetes,target=/etc/kubernetes \
+  --insecure-options=image \
+  docker://gcr.io/google_containers/hyperkube:v${version} \
+  --exec=/hyperkube \
+  -- \
+  kubectl --kubeconfig=${kubeconfig} --server=https://${master_ip} \
+  drain "$COREOS_PRIVATE_IPV4" --ignore-daemonsets --delete-local-data --force --timeout=${drain_timeout}s \
+  || echo "Failed to drain node $COREOS_PRIVATE_IPV4, terminating it anyway" >&2
+
+/usr/bin/rkt run \
+  --net=host \
+  --volume=dns,kind=host,source=/etc/resolv.conf,readOnly=true \
+  --mount=volume=dns,target=/etc/resolv.conf \
+  --trust-keys-from-https \
+  quay.io/coreos/awscli \
+  -- \
+  aws --region ${region} autoscaling complete-lifecycle-action \
+  --auto-scaling-group-name ${auto_scaling_group} \
+  --lifecycle-hook-name ${lifecycle_hook} \
+  --instance-id "$instance_id" \
+  --lifecycle-action-result CONTINUE
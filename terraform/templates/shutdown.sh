@@ -0,0 +1,43 @@
+#!/bin/bash
+# Runs when kaws-shutdown.service stops as the server shuts down, e.g. when its instance is
+# terminated. Unless the server is only rebooting, a master or node deletes its Node object so the
+# cluster stops scheduling to it, and if the cluster scrubs credentials, the secrets
+# /opt/kaws/decrypt-pki decrypted at boot, and their copies in secret sinks, are removed. Failures
+# are logged rather than holding up the shutdown.
+source /etc/environment
+shopt -s nullglob
+
+if systemctl list-jobs | grep -Eq "reboot.target +start"; then
+  echo "Rebooting, so the server keeps its registration and credentials"
+  exit 0
+fi
+
+# etcd members keep their names and data across replacement, so etcd servers aren't removed.
+if [ -n "${api_server}" ]; then
+  curl -sS --max-time 10 ${api_client_args} -X DELETE \
+    "${api_server}/api/v1/nodes/$COREOS_PRIVATE_IPV4" >/dev/null \
+    && echo "Deleted node $COREOS_PRIVATE_IPV4" \
+    || echo "Failed to delete node $COREOS_PRIVATE_IPV4" >&2
+fi
+
+if [ "${scrub_credentials}" != "true" ]; then
+  exit 0
+fi
+
+scrub() {
+  [ -e "$1" ] && shred -u "$1" && echo "Scrubbed $1"
+}
+
+for file in /etc/etcd2/ssl/*-encrypted.binary /etc/kubernetes/ssl/*-encrypted.binary; do
+  scrub "$${file/-encrypted.binary/.pem}"
+done
+
+scrub /etc/kubernetes/ssl/known-tokens.csv
+
+while read -r type source target option owner; do
+  case "$type" in
+    file) scrub "$target" ;;
+    credential) scrub "/run/credstore/$target" ;;
+    environment) [ -f "$target" ] && sed -i "/^$option=/d" "$target" && echo "Scrubbed $option from $target" ;;
+  esac
+done < /etc/kaws/secret-sinks
@@ -0,0 +1,121 @@
+#!/bin/bash
+# Checks what this server needs to boot without writing anything: that each of its encrypted files
+# is there and KMS decrypts it with the server's IAM role, or for a placeholder, that the secret
+# store has it, and that etcd and S3 are reachable. Prints a JSON report and exits with status 1 if
+# any check failed, so it can be used as a health or startup probe.
+shopt -s nullglob
+
+failed=0
+checks=()
+
+# Runs a check, a command whose output is discarded, and records whether it succeeded along with
+# the last line of its errors.
+check() {
+  local name="$1" target="$2" error
+  shift 2
+
+  if error="$("$@" 2>&1 >/dev/null)"; then
+    checks+=("{\"check\":\"$name\",\"target\":\"$target\",\"ok\":true}")
+  else
+    failed=1
+    error="$(echo "$error" | tail -n 1 | tr -d '"\\')"
+    checks+=("{\"check\":\"$name\",\"target\":\"$target\",\"ok\":false,\"error\":\"$error\"}")
+  fi
+}
+
+# Asks KMS to decrypt a file, printing only the ID of the key it was encrypted with. Like
+# /opt/kaws/kms-decrypt, it tries the file's encryption context first, then none, unless the
+# cluster requires encryption contexts.
+kms_decrypt() {
+  local file="$(awk -v path="$1" '$1 == path { print $2 }' /etc/kaws/encrypted-files)"
+
+  if [ "${encryption_contexts_required}" = "true" ]; then
+    kms_decrypt_with_context "$1" --encryption-context "kaws-cluster=${cluster},kaws-file=$file"
+  else
+    kms_decrypt_with_context "$1" --encryption-context "kaws-cluster=${cluster},kaws-file=$file" \
+      || kms_decrypt_with_context "$1"
+  fi
+}
+
+kms_decrypt_with_context() {
+  local encrypted="$1"
+  shift
+
+  /usr/bin/rkt run \
+    --net=host \
+    --volume=dns,kind=host,source=/etc/resolv.conf,readOnly=true \
+    --mount=volume=dns,target=/etc/resolv.conf \
+    --volume=file,kind=host,source="$encrypted",readOnly=true \
+    --mount=volume=file,target=/tmp/encrypted.binary \
+    --trust-keys-from-https \
+    quay.io/coreos/awscli \
+    -- \
+    aws --region ${region} kms decrypt --ciphertext-blob fileb:///tmp/encrypted.binary "$@" \
+    --output text --query KeyId
+}
+
+# Asks the secret store for a file a server still has a placeholder for, printing only its name.
+secret_store_get() {
+  local file="$(awk -v path="$1" '$1 == path { print $2 }' /etc/kaws/encrypted-files)"
+
+  case "${secret_store}" in
+    ssm)
+      /usr/bin/rkt run \
+        --net=host \
+        --volume=dns,kind=host,source=/etc/resolv.conf,readOnly=true \
+        --mount=volume=dns,target=/etc/resolv.conf \
+        --trust-keys-from-https \
+        quay.io/coreos/awscli \
+        -- \
+        aws --region ${region} ssm get-parameter --name "/kaws/${cluster}/$file" \
+        --output text --query Parameter.Name
+      ;;
+    s3)
+      /usr/bin/rkt run \
+        --net=host \
+        --volume=dns,kind=host,source=/etc/resolv.conf,readOnly=true \
+        --mount=volume=dns,target=/etc/resolv.conf \
+        --trust-keys-from-https \
+        quay.io/coreos/awscli \
+        -- \
+        aws --region ${region} s3api head-object --bucket "${secret_store_bucket}" \
+        --key "${cluster}/$file" --output text --query ETag
+      ;;
+    secretsmanager)
+      /usr/bin/rkt run \
+        --net=host \
+        --volume=dns,kind=host,source=/etc/resolv.conf,readOnly=true \
+        --mount=volume=dns,target=/etc/resolv.conf \
+        --trust-keys-from-https \
+        quay.io/coreos/awscli \
+        -- \
+        aws --region ${region} secretsmanager get-secret-value --secret-id "kaws/${cluster}/$file" \
+        --output text --query Name
+      ;;
+  esac
+}
+
+# Fails unless a file is there and isn't empty.
+present() {
+  [ -s "$1" ] || { echo "missing or empty" >&2; return 1; }
+}
+
+for file in /etc/etcd2/ssl/*.binary /etc/kubernetes/ssl/*.binary /etc/ssh/kaws-host-ca-key-encrypted.binary; do
+  check encrypted-file "$file" present "$file"
+
+  if [ "$(cat "$file" 2>/dev/null)" = "KAWSSTORE" ]; then
+    check secret-store "$file" secret_store_get "$file"
+  else
+    check kms-decrypt "$file" kms_decrypt "$file"
+  fi
+done
+
+for endpoint in 10.0.1.4 10.0.1.5 10.0.1.6; do
+  check etcd "$endpoint:2379" timeout 5 bash -c "</dev/tcp/$endpoint/2379"
+done
+
+check s3 "s3.${region}.amazonaws.com" curl -sS -o /dev/null --max-time 5 "https://s3.${region}.amazonaws.com"
+
+echo "{\"ok\":$([ $failed -eq 0 ] && echo true || echo false),\"checks\":[$(IFS=,; echo "$${checks[*]}")]}"
+
+exit $failed
@@ -2,10 +2,32 @@ variable "account_id" {
   description = "Numerical account ID of the AWS account to use, e.g. `12345678`"
 }
 
+variable "apiserver_flags" {
+  description = "Flags added to kube-apiserver's command line, e.g. `--max-requests-inflight=800`"
+  type = "list"
+  default = []
+}
+
 variable "availability_zone" {
   description = "Availability Zone for etcd instances and EBS volumes, e.g. `us-east-1a`"
 }
 
+variable "baked_ami" {
+  description = "The AMI ID of the image built by `kaws image build` for Kubernetes masters and nodes, or empty to use `coreos_ami`"
+  default = ""
+}
+
+variable "baked_version" {
+  description = "Version of Kubernetes whose images are in `baked_ami`, which is only used while it matches `version`"
+  default = ""
+}
+
+variable "bastion_ssh_cidrs" {
+  description = "IPv4 network ranges allowed to SSH to the bastion server"
+  type = "list"
+  default = ["0.0.0.0/0"]
+}
+
 variable "cidr" {
   description = "IPv4 network range of the subnet where Kubernetes nodes will run, e.g. `10.0.2.0/24`"
 }
@@ -14,6 +36,12 @@ variable "cluster" {
   description = "The target cluster's name, e.g. `production`"
 }
 
+variable "controller_manager_flags" {
+  description = "Flags added to kube-controller-manager's command line"
+  type = "list"
+  default = []
+}
+
 variable "coreos_ami" {
   description = "The AMI ID for the CoreOS image to use for servers, e.g. `ami-1234abcd`"
 }
@@ -22,6 +50,37 @@ variable "domain" {
   description = "The domain name for the cluster, e.g. `example.com`"
 }
 
+variable "encryption_contexts_required" {
+  description = "Whether servers only decrypt their encrypted files with the files' encryption contexts, once every file has one"
+  default = "false"
+}
+
+variable "etcd_member_certificates" {
+  description = "Whether each etcd member has server and peer certificates of its own instead of sharing them"
+  default = "false"
+}
+
+variable "etcd_secret_sinks" {
+  description = "Where etcd servers deliver copies of their decrypted secrets, one sink per item"
+  type = "list"
+  default = []
+}
+
+variable "etcd_volume_size" {
+  description = "The size of each etcd member's EBS volume in GiB"
+  default = "10"
+}
+
+variable "iam_authenticator_config" {
+  description = "Base64-encoded aws-iam-authenticator server configuration, or empty to use only client certificate authentication"
+  default = ""
+}
+
+variable "iam_authenticator_image" {
+  description = "Container image for the aws-iam-authenticator server run on Kubernetes masters"
+  default = "602401143452.dkr.ecr.us-west-2.amazonaws.com/amazon/aws-iam-authenticator:v0.5.9"
+}
+
 variable "iam_users" {
   description = "A list of IAM user names who will have access to cluster PKI secrets"
   type = "list"
@@ -31,6 +90,28 @@ variable "instance_size" {
   description = "The EC2 instance size, e.g. `m3.medium`"
 }
 
+variable "key_algo" {
+  description = "Algorithm of the private keys generated for the cluster's PKI, `rsa` or `ecdsa`"
+  default = "rsa"
+}
+
+variable "key_size" {
+  description = "Size in bits of the private keys generated for the cluster's PKI, e.g. `2048` for rsa or `256` for ecdsa"
+  default = "2048"
+}
+
+variable "kubelet_flags" {
+  description = "Flags added to the command line of the kubelet on masters and nodes"
+  type = "list"
+  default = []
+}
+
+variable "master_secret_sinks" {
+  description = "Where masters deliver copies of their decrypted secrets, one sink per item"
+  type = "list"
+  default = []
+}
+
 variable "masters_max_size" {
   description = "The maximum number of EC2 instances the Kubernetes masters may autoscale to"
 }
@@ -39,6 +120,23 @@ variable "masters_min_size" {
   description = "The minimum number of EC2 instances the Kubernetes masters may autoscale to"
 }
 
+variable "node_placement" {
+  description = "Strategy of the placement group Kubernetes nodes are launched in, `cluster`, `partition`, or `spread`, or empty for none"
+  default = ""
+}
+
+variable "node_secret_sinks" {
+  description = "Where nodes deliver copies of their decrypted secrets, one sink per item"
+  type = "list"
+  default = []
+}
+
+variable "node_subnet_cidrs" {
+  description = "IPv4 network ranges of the subnets Kubernetes nodes run in instead of the `cidr` subnet"
+  type = "list"
+  default = []
+}
+
 variable "nodes_max_size" {
   description = "The maximum number of EC2 instances the Kubernetes nodes may autoscale to"
 }
@@ -47,6 +145,11 @@ variable "nodes_min_size" {
   description = "The minimum number of EC2 instances the Kubernetes nodes may autoscale to"
 }
 
+variable "private_api" {
+  description = "Whether the Kubernetes API is reachable only from inside the VPC, e.g. through the bastion server"
+  default = "false"
+}
+
 variable "propagating_vgws" {
   description = "A list of virtual gateways that should propagate routes to the route table"
   type = "list"
@@ -56,15 +159,45 @@ variable "region" {
   description = "The AWS Region where the cluster will live, e.g. `us-east-1`"
 }
 
+variable "scrub_credentials" {
+  description = "Whether servers remove the credentials they decrypted at boot when they shut down for good"
+  default = "false"
+}
+
+variable "secret_store" {
+  description = "Where servers fetch their encrypted files at boot: \"s3\" for an S3 bucket, \"secretsmanager\" for Secrets Manager, \"ssm\" for SSM Parameter Store, or empty to get them in their user data"
+  default = ""
+}
+
+variable "secret_store_bucket" {
+  description = "The S3 bucket servers fetch their encrypted files from at boot, if secret_store is \"s3\""
+  default = ""
+}
+
 variable "ssh_keys" {
   description = "SSH public keys to add to ~/.ssh/authorized_keys on each server"
   type = "list"
 }
 
+variable "tls_bootstrapping" {
+  description = "Whether kubelets request their own client certificates with a bootstrap token instead of sharing the nodes' certificate"
+  default = "false"
+}
+
+variable "userdata_signing_public_key" {
+  description = "The base64-encoded public key each server verifies the signature of its cloud-config with before running it, written by `kaws cluster generate-pki userdata-signing`, or empty to skip verifying"
+  default = ""
+}
+
 variable "version" {
   description = "Version of Kubernetes to use, e.g. `1.0.0`"
 }
 
+variable "wireguard" {
+  description = "Whether pod network traffic between servers is encrypted with WireGuard"
+  default = "false"
+}
+
 variable "zone_id" {
   description = "Zone ID of the Route 53 hosted zone, e.g. `Z111111QQQQQQQ`"
 }